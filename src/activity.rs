@@ -0,0 +1,194 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{ClientId, Currency, TxType};
+
+// Per mille, so p99.9 fits alongside the usual ones.
+const PERCENTILES: [(u32, &str); 9] = [
+    (10, "p1"),
+    (50, "p5"),
+    (250, "p25"),
+    (500, "p50"),
+    (750, "p75"),
+    (900, "p90"),
+    (950, "p95"),
+    (990, "p99"),
+    (999, "p99.9"),
+];
+
+#[derive(Serialize)]
+struct ActivityRow {
+    distribution: &'static str,
+    bucket: String,
+    value: String,
+}
+
+// Power of two bucket of a client's transaction count: 1, 2-3, 4-7, ...
+fn bucket_of(count: u64) -> u32 {
+    count.ilog2()
+}
+
+fn bucket_name(bucket: u32) -> String {
+    let (low, high) = (1u64 << bucket, (1u64 << bucket) * 2 - 1);
+    if low == high {
+        low.to_string()
+    } else {
+        format!("{}-{}", low, high)
+    }
+}
+
+// How the input's transactions spread over clients and amounts, for designing sharding keys and
+// checking that generated workloads look like production. Balances are never computed.
+#[derive(Default)]
+pub struct Activity {
+    rows: u64,
+    per_client: HashMap<ClientId, u64>,
+    // Counted per distinct amount rather than kept per row, so memory follows the distinct
+    // amounts. Only deposits and withdrawals carry amounts of their own.
+    amounts: BTreeMap<Currency, u64>,
+    amount_rows: u64,
+}
+
+impl Activity {
+    pub fn observe(&mut self, cid: ClientId, tx_type: TxType, amount: Currency) {
+        self.rows += 1;
+        *self.per_client.entry(cid).or_default() += 1;
+        if matches!(tx_type, TxType::Deposit | TxType::Withdrawal) {
+            *self.amounts.entry(amount).or_default() += 1;
+            self.amount_rows += 1;
+        }
+    }
+
+    // Nearest rank, so every percentile is an amount that actually occurred.
+    fn percentiles(&self) -> Vec<(&'static str, Currency)> {
+        let mut amounts = self.amounts.iter();
+        let (mut seen, mut current) = (0, None);
+        PERCENTILES
+            .iter()
+            .filter_map(|(per_mille, name)| {
+                let rank = (self.amount_rows * *per_mille as u64).div_ceil(1000).max(1);
+                while seen < rank {
+                    let (amount, count) = amounts.next()?;
+                    seen += count;
+                    current = Some(*amount);
+                }
+                current.map(|amount| (*name, amount))
+            })
+            .collect()
+    }
+
+    pub fn summary(&self) -> String {
+        let busiest = self
+            .per_client
+            .iter()
+            .max_by_key(|(cid, count)| (**count, std::cmp::Reverse(**cid)));
+        match busiest {
+            Some((cid, count)) => format!(
+                "Counted {} transactions across {} clients, the busiest is client {} with {}.",
+                self.rows,
+                self.per_client.len(),
+                cid.0,
+                count
+            ),
+            None => "Counted no transactions.".to_string(),
+        }
+    }
+
+    // One long form csv: clients per power of two bucket of transaction counts, then the amount
+    // percentiles with the smallest and largest amounts.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        let mut buckets = BTreeMap::<u32, u64>::new();
+        for count in self.per_client.values() {
+            *buckets.entry(bucket_of(*count)).or_default() += 1;
+        }
+        for (bucket, clients) in buckets {
+            writer.serialize(ActivityRow {
+                distribution: "transactions_per_client",
+                bucket: bucket_name(bucket),
+                value: clients.to_string(),
+            })?;
+        }
+
+        let extremes = [
+            ("min", self.amounts.keys().next()),
+            ("max", self.amounts.keys().next_back()),
+        ];
+        let amounts = self.percentiles().into_iter().chain(
+            extremes
+                .into_iter()
+                .filter_map(|(name, amount)| amount.map(|amount| (name, *amount))),
+        );
+        for (bucket, amount) in amounts {
+            writer.serialize(ActivityRow {
+                distribution: "amount",
+                bucket: bucket.to_string(),
+                value: format!("{:.4}", amount),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_powers_of_two() {
+        assert_eq!(bucket_name(bucket_of(1)), "1");
+        assert_eq!(bucket_name(bucket_of(3)), "2-3");
+        assert_eq!(bucket_name(bucket_of(4)), "4-7");
+        assert_eq!(bucket_name(bucket_of(1000)), "512-1023");
+    }
+
+    #[test]
+    fn histogram_and_percentiles() {
+        let mut activity = Activity::default();
+        // Client 1 makes 100 deposits of 1 to 100, client 2 one dispute, client 3
+        // five withdrawals of 1000.
+        for amount in 1..=100 {
+            activity.observe(ClientId(1), TxType::Deposit, Currency::from_num(amount));
+        }
+        activity.observe(ClientId(2), TxType::Dispute, Currency::from_num(0));
+        for _ in 0..5 {
+            activity.observe(ClientId(3), TxType::Withdrawal, Currency::from_num(1000));
+        }
+        assert_eq!(
+            activity.summary(),
+            "Counted 106 transactions across 3 clients, the busiest is client 1 with 100."
+        );
+
+        let mut out = vec![];
+        activity.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "distribution,bucket,value\n\
+             transactions_per_client,1,1\n\
+             transactions_per_client,4-7,1\n\
+             transactions_per_client,64-127,1\n\
+             amount,p1,2.0000\n\
+             amount,p5,6.0000\n\
+             amount,p25,27.0000\n\
+             amount,p50,53.0000\n\
+             amount,p75,79.0000\n\
+             amount,p90,95.0000\n\
+             amount,p95,100.0000\n\
+             amount,p99,1000.0000\n\
+             amount,p99.9,1000.0000\n\
+             amount,min,1.0000\n\
+             amount,max,1000.0000\n"
+        );
+    }
+
+    #[test]
+    fn empty_input() {
+        let activity = Activity::default();
+        assert_eq!(activity.summary(), "Counted no transactions.");
+        let mut out = vec![];
+        activity.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "");
+    }
+}
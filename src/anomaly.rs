@@ -0,0 +1,194 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{ClientId, Currency, TxId, TxType};
+
+pub const DEFAULT_THRESHOLD: f64 = 4.0;
+// Weight of the newest amount in the moving average, roughly the last 20 amounts matter.
+const ALPHA: f64 = 0.1;
+// Amounts seen before a client's pattern is trusted enough to score against.
+const WARMUP: u32 = 5;
+// A client that always moves the same amount has no spread, so the deviation is floored at a
+// fraction of the mean, otherwise any other amount would score infinitely far out.
+const MIN_RELATIVE_DEVIATION: f64 = 0.05;
+
+// Exponentially weighted mean and variance, constant memory per client however long the history.
+#[derive(Default, Debug, Clone, Copy)]
+struct Ewma {
+    count: u32,
+    mean: f64,
+    variance: f64,
+}
+
+impl Ewma {
+    fn update(&mut self, value: f64) {
+        if self.count == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            let increment = ALPHA * diff;
+            self.mean += increment;
+            self.variance = (1.0 - ALPHA) * (self.variance + diff * increment);
+        }
+        self.count += 1;
+    }
+
+    fn deviation(&self) -> f64 {
+        self.variance
+            .sqrt()
+            .max(self.mean.abs() * MIN_RELATIVE_DEVIATION)
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Anomaly {
+    client: ClientId,
+    tx: TxId,
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    amount: Currency,
+    mean: String,
+    deviation: String,
+    zscore: String,
+}
+
+// First pass fraud screen: flags deposits and withdrawals whose amount is more than threshold
+// deviations away from the client's recent amounts of the same type. Streaming, one pass.
+pub struct AnomalyDetector {
+    threshold: f64,
+    stats: HashMap<(ClientId, TxType), Ewma>,
+    anomalies: Vec<Anomaly>,
+}
+
+impl AnomalyDetector {
+    pub fn new(threshold: f64) -> Self {
+        AnomalyDetector {
+            threshold,
+            stats: HashMap::new(),
+            anomalies: vec![],
+        }
+    }
+
+    // Every row counts, rejected ones included, an attempt far out of pattern is still worth a look.
+    pub fn observe(&mut self, cid: ClientId, tid: TxId, tx_type: TxType, amount: Currency) {
+        if !matches!(tx_type, TxType::Deposit | TxType::Withdrawal) {
+            return;
+        }
+        let value = amount.to_num::<f64>();
+        let stats = self.stats.entry((cid, tx_type)).or_default();
+        if stats.count >= WARMUP {
+            let deviation = stats.deviation();
+            let zscore = if deviation > 0.0 {
+                (value - stats.mean) / deviation
+            } else {
+                0.0
+            };
+            if zscore.abs() > self.threshold {
+                self.anomalies.push(Anomaly {
+                    client: cid,
+                    tx: tid,
+                    tx_type,
+                    amount,
+                    mean: format!("{:.4}", stats.mean),
+                    deviation: format!("{:.4}", deviation),
+                    zscore: format!("{:.2}", zscore),
+                });
+            }
+        }
+        stats.update(value);
+    }
+
+    pub fn len(&self) -> usize {
+        self.anomalies.len()
+    }
+
+    // Rows in input order.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        writer.write_record([
+            "client",
+            "tx",
+            "type",
+            "amount",
+            "mean",
+            "deviation",
+            "zscore",
+        ])?;
+        for anomaly in &self.anomalies {
+            writer.serialize(anomaly)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observe_all(detector: &mut AnomalyDetector, cid: u16, tx_type: TxType, amounts: &[f64]) {
+        for (i, amount) in amounts.iter().enumerate() {
+            detector.observe(
+                ClientId(cid),
+                TxId(i as u32),
+                tx_type,
+                Currency::from_num(*amount),
+            );
+        }
+    }
+
+    #[test]
+    fn flags_out_of_pattern_amounts() {
+        let mut detector = AnomalyDetector::new(DEFAULT_THRESHOLD);
+        observe_all(
+            &mut detector,
+            1,
+            TxType::Withdrawal,
+            &[20.0, 25.0, 18.0, 22.0, 21.0, 19.0, 5000.0, 23.0],
+        );
+        assert_eq!(detector.len(), 1);
+        assert_eq!(detector.anomalies[0].tx, TxId(6));
+        assert_eq!(detector.anomalies[0].mean, "20.3343");
+
+        // Deposits and other clients have their own pattern.
+        observe_all(&mut detector, 1, TxType::Deposit, &[5000.0; 8]);
+        observe_all(&mut detector, 2, TxType::Withdrawal, &[5000.0; 8]);
+        assert_eq!(detector.len(), 1);
+    }
+
+    #[test]
+    fn constant_amounts_use_deviation_floor() {
+        let mut detector = AnomalyDetector::new(DEFAULT_THRESHOLD);
+        observe_all(
+            &mut detector,
+            1,
+            TxType::Deposit,
+            &[100.0, 100.0, 100.0, 100.0, 100.0, 110.0, 150.0],
+        );
+        assert_eq!(detector.len(), 1);
+        assert_eq!(detector.anomalies[0].tx, TxId(6));
+    }
+
+    #[test]
+    fn warmup_and_report() {
+        let mut detector = AnomalyDetector::new(DEFAULT_THRESHOLD);
+        observe_all(
+            &mut detector,
+            1,
+            TxType::Deposit,
+            &[1.0, 1000.0, 1.0, 1000000.0],
+        );
+        assert_eq!(detector.len(), 0);
+
+        let mut out = vec![];
+        detector.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,tx,type,amount,mean,deviation,zscore\n"
+        );
+    }
+}
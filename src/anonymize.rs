@@ -0,0 +1,190 @@
+use crate::sample::SplitMix64;
+use crate::schema::RowReader;
+use std::error::Error;
+use std::io::{Read, Write};
+use txcli_model::{ClientId, Currency, TxId, TxType};
+
+// Keeps the draws deciding different things about the same row independent.
+const SAMPLE_SALT: u64 = 0x5A3C_0001;
+const PERTURB_SALT: u64 = 0x5A3C_0002;
+const FEISTEL_ROUNDS: u64 = 4;
+
+// Turns an input into shareable test data, reproducible from the seed and meaningless without it.
+// Clients are renamed through a seeded shuffle of every possible id, tids through a seeded
+// bijection, so a dispute still points at its transaction. Everything else about a row is
+// decided per original client and tid, so subsampling keeps or drops a transaction together with
+// its disputes, resolves and chargebacks.
+pub struct Anonymizer {
+    seed: u64,
+    clients: Vec<u16>,
+    // Fraction an amount may move either way.
+    perturb: f64,
+    // A draw at or below it keeps the transaction, None keeps everything.
+    threshold: Option<u64>,
+}
+
+impl Anonymizer {
+    // perturb is a percentage below 100, sample a fraction of transactions to keep.
+    pub fn new(seed: u64, perturb: f64, sample: Option<f64>) -> Self {
+        // Fisher-Yates over the whole id space, so the mapping needs no pass over the input.
+        let mut rng = SplitMix64(seed);
+        let mut clients: Vec<u16> = (0..=u16::MAX).collect();
+        for i in (1..clients.len()).rev() {
+            clients.swap(i, rng.next() as usize % (i + 1));
+        }
+        Anonymizer {
+            seed,
+            clients,
+            perturb: perturb / 100.0,
+            threshold: sample
+                .filter(|rate| *rate < 1.0)
+                .map(|rate| (rate * u64::MAX as f64) as u64),
+        }
+    }
+
+    fn draw(&self, cid: ClientId, tid: TxId, salt: u64) -> u64 {
+        SplitMix64(self.seed ^ salt ^ ((cid.0 as u64) << 32 | tid.0 as u64)).next()
+    }
+
+    pub fn client(&self, cid: ClientId) -> ClientId {
+        ClientId(self.clients[cid.0 as usize])
+    }
+
+    // A Feistel network over the two halves of the id is a bijection whatever its round function.
+    pub fn tid(&self, tid: TxId) -> TxId {
+        let (mut left, mut right) = ((tid.0 >> 16) as u16, tid.0 as u16);
+        for round in 0..FEISTEL_ROUNDS {
+            let mixed = SplitMix64(self.seed ^ (round << 32) ^ right as u64).next() as u16;
+            (left, right) = (right, left ^ mixed);
+        }
+        TxId((left as u32) << 16 | right as u32)
+    }
+
+    pub fn keeps(&self, cid: ClientId, tid: TxId) -> bool {
+        self.threshold
+            .is_none_or(|threshold| self.draw(cid, tid, SAMPLE_SALT) <= threshold)
+    }
+
+    pub fn amount(&self, cid: ClientId, tid: TxId, amount: Currency) -> Currency {
+        // Uniform in [-1, 1].
+        let unit = self.draw(cid, tid, PERTURB_SALT) as f64 / u64::MAX as f64 * 2.0 - 1.0;
+        amount.saturating_mul(Currency::from_num(1.0 + unit * self.perturb))
+    }
+
+    // Copies the rows that are kept under the input's own header. Returns the rows read and kept.
+    pub fn run<R: Read, W: Write>(
+        &self,
+        reader: &mut csv::Reader<R>,
+        mut rows: RowReader,
+        writer: W,
+    ) -> Result<(u64, u64), Box<dyn Error>> {
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(writer);
+        writer.write_record(reader.headers()?)?;
+        let (mut read, mut kept) = (0, 0);
+        while rows.read(reader)? {
+            let tx = rows.parse()?;
+            read += 1;
+            if !self.keeps(tx.cid, tx.tid) {
+                continue;
+            }
+            let amount = match tx.tx_type {
+                TxType::Deposit | TxType::Withdrawal | TxType::Transfer => {
+                    self.amount(tx.cid, tx.tid, tx.amount)
+                }
+                _ => tx.amount,
+            };
+            writer.write_record(&rows.replaced(self.client(tx.cid), self.tid(tx.tid), amount))?;
+            kept += 1;
+        }
+        writer.flush()?;
+        Ok((read, kept))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+    use std::collections::HashSet;
+
+    fn anonymize(anonymizer: &Anonymizer, input: &str) -> String {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let rows = Schema::V1.reader(reader.headers().unwrap()).unwrap();
+        let mut out = vec![];
+        anonymizer.run(&mut reader, rows, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn pseudonyms_are_bijections() {
+        let anonymizer = Anonymizer::new(7, 0.0, None);
+        let clients: HashSet<_> = (0..=u16::MAX)
+            .map(|cid| anonymizer.client(ClientId(cid)))
+            .collect();
+        assert_eq!(clients.len(), 1 << 16);
+        let tids: HashSet<_> = (0..100_000).map(|tid| anonymizer.tid(TxId(tid))).collect();
+        assert_eq!(tids.len(), 100_000);
+        assert_ne!(anonymizer.tid(TxId(1)), TxId(1));
+        let other = Anonymizer::new(8, 0.0, None);
+        assert_ne!(
+            (1..10)
+                .map(|cid| anonymizer.client(ClientId(cid)))
+                .collect::<Vec<_>>(),
+            (1..10)
+                .map(|cid| other.client(ClientId(cid)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn perturbs_within_bounds() {
+        let anonymizer = Anonymizer::new(7, 10.0, None);
+        let amounts: Vec<_> = (0..1000)
+            .map(|tid| anonymizer.amount(ClientId(1), TxId(tid), Currency::from_num(100)))
+            .collect();
+        assert!(amounts.iter().all(|amount| *amount >= 90 && *amount <= 110));
+        assert!(amounts.iter().any(|amount| *amount != 100));
+    }
+
+    #[test]
+    fn keeps_disputes_with_their_transactions() {
+        let anonymizer = Anonymizer::new(7, 5.0, Some(0.5));
+        let mut input = "type,client,tx,amount,account\n".to_string();
+        for tid in 1..=200 {
+            input.push_str(&format!("deposit,3,{},10.0,savings\n", tid));
+            input.push_str(&format!("dispute,3,{},,savings\n", tid));
+        }
+        let out = anonymize(&anonymizer, &input);
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "type,client,tx,amount,account");
+        let kept = (lines.len() - 1) / 2;
+        assert!((50..150).contains(&kept), "{}", kept);
+        let client = anonymizer.client(ClientId(3)).0.to_string();
+        for pair in lines[1..].chunks(2) {
+            let deposit: Vec<_> = pair[0].split(',').collect();
+            let dispute: Vec<_> = pair[1].split(',').collect();
+            assert_eq!((deposit[0], dispute[0]), ("deposit", "dispute"));
+            assert_eq!((deposit[1], dispute[1]), (client.as_str(), client.as_str()));
+            assert_eq!(deposit[2], dispute[2]);
+            assert_eq!((deposit[4], dispute[3]), ("savings", ""));
+        }
+        // The same seed gives the same file.
+        assert_eq!(anonymize(&anonymizer, &input), out);
+    }
+
+    #[test]
+    fn redacts_memos() {
+        let anonymizer = Anonymizer::new(7, 0.0, None);
+        let input = "type,client,tx,amount,account,to_account,category,memo\n\
+                     note,3,1,,,,,called from +44 20 7946 0000\n\
+                     deposit,3,2,1.0\n";
+        let out = anonymize(&anonymizer, input);
+        let lines: Vec<_> = out.lines().collect();
+        assert!(lines[1].ends_with(",redacted"), "{}", lines[1]);
+        assert!(!out.contains("7946"));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::i18n::{text, Msg};
+use crate::{AppState, BasicError};
+use std::error::Error;
+use std::fs;
+use txcli_model::{parse_amount, ClientId, Currency};
+
+// Where two match at the same place the longer wins, so <= isn't taken for <.
+const OPS: [(&str, Op); 6] = [
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Field {
+    Available,
+    Held,
+    Total,
+    Locked,
+}
+
+// The left hand side, a number read off the final state.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Quantity {
+    // client(7).available, summed across the client's accounts.
+    Client(ClientId, Field),
+    // sum(available) across all clients.
+    Sum(Field),
+    ClientCount,
+    LockedCount,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Value {
+    Amount(Currency),
+    Count(u64),
+    Flag(bool),
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Amount(amount) => write!(f, "{:.4}", amount),
+            Value::Count(count) => write!(f, "{}", count),
+            Value::Flag(flag) => write!(f, "{}", flag),
+        }
+    }
+}
+
+// One expectation about the state at the end of the run, e.g. client(7).available == 100.0 or
+// locked_count <= 3. Each side of the operator is one term, there is no arithmetic.
+#[derive(Debug, PartialEq)]
+pub struct Assertion {
+    text: String,
+    quantity: Quantity,
+    op: Op,
+    expected: Value,
+}
+
+fn field(name: &str) -> Option<Field> {
+    match name {
+        "available" => Some(Field::Available),
+        "held" => Some(Field::Held),
+        "total" => Some(Field::Total),
+        "locked" => Some(Field::Locked),
+        _ => None,
+    }
+}
+
+fn quantity(lhs: &str) -> Option<Quantity> {
+    if let Some(rest) = lhs.strip_prefix("client(") {
+        let (cid, name) = rest.split_once(").")?;
+        return Some(Quantity::Client(
+            ClientId(cid.trim().parse().ok()?),
+            field(name.trim())?,
+        ));
+    }
+    if let Some(name) = lhs
+        .strip_prefix("sum(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        return field(name.trim())
+            .filter(|field| *field != Field::Locked)
+            .map(Quantity::Sum);
+    }
+    match lhs {
+        "client_count" => Some(Quantity::ClientCount),
+        "locked_count" => Some(Quantity::LockedCount),
+        _ => None,
+    }
+}
+
+impl std::str::FromStr for Assertion {
+    type Err = Box<dyn Error>;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            |reason: &str| BasicError::new(format!("Invalid assertion {}: {}", text, reason));
+        let (index, symbol, op) = OPS
+            .iter()
+            .filter_map(|(symbol, op)| text.find(symbol).map(|index| (index, *symbol, *op)))
+            .min_by_key(|(index, symbol, _)| (*index, std::cmp::Reverse(symbol.len())))
+            .ok_or_else(|| invalid("expected one of == != < <= > >="))?;
+        let (lhs, rhs) = (text[..index].trim(), text[index + symbol.len()..].trim());
+        let quantity = quantity(lhs).ok_or_else(|| {
+            invalid(&format!(
+                "unknown quantity {}, expected client(<id>).available|held|total|locked, sum(available|held|total), client_count or locked_count",
+                lhs
+            ))
+        })?;
+        let expected = match quantity {
+            Quantity::Client(_, Field::Locked) => {
+                if !matches!(op, Op::Eq | Op::Ne) {
+                    return Err(invalid("locked can only be compared with == or !="));
+                }
+                Value::Flag(rhs.parse().map_err(|_| invalid("expected true or false"))?)
+            }
+            Quantity::Client(..) | Quantity::Sum(_) => {
+                Value::Amount(parse_amount(rhs).map_err(|err| invalid(&err.to_string()))?)
+            }
+            Quantity::ClientCount | Quantity::LockedCount => {
+                Value::Count(rhs.parse().map_err(|_| invalid("expected a count"))?)
+            }
+        };
+        Ok(Assertion {
+            text: text.trim().to_string(),
+            quantity,
+            op,
+            expected,
+        })
+    }
+}
+
+impl Assertion {
+    // What the state says, None for a client that doesn't exist.
+    fn actual(&self, state: &AppState) -> Option<Value> {
+        let amount = |field: Field, client: &crate::ClientState| match field {
+            Field::Available => client.available(),
+            Field::Held => client.held(),
+            _ => client.available() + client.held(),
+        };
+        Some(match self.quantity {
+            Quantity::Client(cid, Field::Locked) => Value::Flag(state.clients.get(&cid)?.locked),
+            Quantity::Client(cid, field) => Value::Amount(amount(field, state.clients.get(&cid)?)),
+            Quantity::Sum(field) => Value::Amount(
+                state
+                    .clients
+                    .values()
+                    .map(|client| amount(field, client))
+                    .sum(),
+            ),
+            Quantity::ClientCount => Value::Count(state.clients.len() as u64),
+            Quantity::LockedCount => Value::Count(
+                state
+                    .clients
+                    .values()
+                    .filter(|client| client.locked)
+                    .count() as u64,
+            ),
+        })
+    }
+
+    // None when it holds, otherwise why not.
+    pub fn check(&self, state: &AppState) -> Option<String> {
+        let actual = match self.actual(state) {
+            Some(actual) => actual,
+            None => return Some(text(Msg::AssertionNoClient, &[&self.text])),
+        };
+        let holds = match self.op {
+            Op::Eq => actual == self.expected,
+            Op::Ne => actual != self.expected,
+            _ => {
+                let ordering = match (actual, self.expected) {
+                    (Value::Amount(actual), Value::Amount(expected)) => actual.cmp(&expected),
+                    (Value::Count(actual), Value::Count(expected)) => actual.cmp(&expected),
+                    _ => unreachable!("only flags mix types and they only take == and !="),
+                };
+                match self.op {
+                    Op::Lt => ordering.is_lt(),
+                    Op::Le => ordering.is_le(),
+                    Op::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                }
+            }
+        };
+        (!holds).then(|| text(Msg::AssertionFailed, &[&self.text, &actual]))
+    }
+}
+
+// The --assert expressions followed by the lines of the --assertions file, where blank lines and
+// lines starting with # are skipped. Parsed before the run so a typo doesn't waste a long one.
+pub fn load(
+    expressions: &[String],
+    file: Option<&String>,
+) -> Result<Vec<Assertion>, Box<dyn Error>> {
+    let contents = match file {
+        Some(path) => fs::read_to_string(path)?,
+        None => String::new(),
+    };
+    let lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    expressions
+        .iter()
+        .map(String::as_str)
+        .chain(lines)
+        .map(str::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_transaction;
+    use txcli_model::{TxRecord, TxType};
+
+    fn state() -> AppState {
+        let mut state = AppState::default();
+        for tx in [
+            TxRecord::new(TxType::Deposit, 7, 1, Currency::from_num(100)),
+            TxRecord::new(TxType::Deposit, 8, 2, Currency::from_num(20)),
+            TxRecord::new(TxType::Dispute, 8, 2, Currency::from_num(0)),
+            TxRecord::new(TxType::ChargeBack, 8, 2, Currency::from_num(0)),
+            TxRecord::new(TxType::Deposit, 9, 3, Currency::from_num(5)),
+            TxRecord::new(TxType::Dispute, 9, 3, Currency::from_num(0)),
+        ] {
+            execute_transaction(&mut state, tx).unwrap();
+        }
+        state
+    }
+
+    fn check(text: &str) -> Option<String> {
+        text.parse::<Assertion>().unwrap().check(&state())
+    }
+
+    #[test]
+    fn holding_assertions() {
+        for text in [
+            "client(7).available == 100.0",
+            "client(7).total>=99.5",
+            "client(9).held == 5",
+            "client(8).locked == true",
+            "client(7).locked != true",
+            "locked_count <= 3",
+            "client_count == 3",
+            "sum(available) == 100",
+            "sum(total) > 100",
+        ] {
+            assert_eq!(check(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn failing_assertions() {
+        assert_eq!(
+            check("client(7).available == 99"),
+            Some("client(7).available == 99 failed, it is 100.0000".to_string())
+        );
+        assert_eq!(
+            check("locked_count < 1"),
+            Some("locked_count < 1 failed, it is 1".to_string())
+        );
+        assert_eq!(
+            check("client(42).held == 0"),
+            Some("client(42).held == 0 failed, there is no such client".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_syntax() {
+        for text in [
+            "client(7).available",
+            "client(7).balance == 1",
+            "client(x).held == 1",
+            "client(7).locked < true",
+            "client(7).locked == yes",
+            "sum(locked) == 1",
+            "locked_count == 1.5",
+            "clients == 3",
+        ] {
+            assert!(text.parse::<Assertion>().is_err(), "{}", text);
+        }
+        let err = "client(7).held = 1".parse::<Assertion>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid assertion client(7).held = 1: expected one of == != < <= > >="
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use crate::BasicError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use txcli_model::{deserialize_optional_amount, Currency, TxRecord, TxType, CURRENCY_FORMAT};
+
+// Assets file format, entries override the built in assets with the same code:
+//
+// [[asset]]
+// code = "BTC"
+// decimals = 8
+// dust = "0.00000546"     # amounts strictly below this are dust, defaults to no threshold
+// dust_policy = "sweep"   # ignore (default) rejects dust rows, sweep collects them per asset
+#[derive(Deserialize)]
+struct AssetsFile {
+    #[serde(default, rename = "asset")]
+    assets: Vec<RawAsset>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAsset {
+    code: String,
+    decimals: u32,
+    #[serde(default, deserialize_with = "deserialize_optional_amount")]
+    dust: Option<Currency>,
+    #[serde(default)]
+    dust_policy: DustPolicy,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DustPolicy {
+    // The row is rejected like any other invalid transaction.
+    #[default]
+    Ignore,
+    // The row is not applied to the client, its amount is added to the asset's swept total instead.
+    Sweep,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Asset {
+    pub decimals: u32,
+    pub dust: Option<Currency>,
+    pub dust_policy: DustPolicy,
+}
+
+// Rows without a currency column, or with a code the registry doesn't know, are treated as fiat.
+pub const FIAT_DECIMALS: u32 = 4;
+
+#[derive(Debug, Clone)]
+pub struct AssetRegistry {
+    assets: HashMap<String, Asset>,
+}
+
+impl Default for AssetRegistry {
+    fn default() -> Self {
+        let asset = |decimals, dust: Option<&str>| Asset {
+            decimals,
+            dust: dust.map(|dust| txcli_model::parse_amount(dust).unwrap()),
+            dust_policy: DustPolicy::Ignore,
+        };
+        AssetRegistry {
+            assets: [
+                // 546 satoshi is the standard output dust limit.
+                ("BTC", asset(8, Some("0.00000546"))),
+                ("ETH", asset(18, None)),
+            ]
+            .into_iter()
+            .map(|(code, asset)| (code.to_string(), asset))
+            .collect(),
+        }
+    }
+}
+
+impl AssetRegistry {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        AssetRegistry::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let file: AssetsFile = toml::from_str(input)?;
+        let mut registry = AssetRegistry::default();
+        for raw in file.assets {
+            if raw.code.is_empty() {
+                return Err(BasicError::new("Asset code cannot be empty."));
+            }
+            if raw.dust.is_some_and(|dust| dust < 0) {
+                return Err(BasicError::new(format!(
+                    "Asset {} has a negative dust threshold.",
+                    raw.code
+                )));
+            }
+            registry.assets.insert(
+                raw.code,
+                Asset {
+                    decimals: raw.decimals,
+                    dust: raw.dust,
+                    dust_policy: raw.dust_policy,
+                },
+            );
+        }
+        Ok(registry)
+    }
+
+    pub fn codes(&self) -> Vec<&str> {
+        self.assets.keys().map(String::as_str).collect()
+    }
+
+    pub fn get(&self, code: &str) -> Option<&Asset> {
+        self.assets.get(code)
+    }
+
+    pub fn decimals(&self, code: Option<&str>) -> u32 {
+        code.and_then(|code| self.get(code))
+            .map_or(FIAT_DECIMALS, |asset| asset.decimals)
+    }
+
+    // Assets whose smallest unit is finer than the currency format resolves, so amounts get rounded.
+    pub fn too_precise(&self) -> Vec<(&str, u32)> {
+        let resolvable = resolvable_decimals();
+        let mut codes: Vec<_> = self
+            .assets
+            .iter()
+            .filter(|(_, asset)| asset.decimals > resolvable)
+            .map(|(code, asset)| (code.as_str(), asset.decimals))
+            .collect();
+        codes.sort_unstable();
+        codes
+    }
+
+    // The dust policy to apply when this row moves less than its asset's dust threshold.
+    pub fn dust(&self, tx: &TxRecord) -> Option<DustPolicy> {
+        if !matches!(tx.tx_type, TxType::Deposit | TxType::Withdrawal) {
+            return None;
+        }
+        let asset = self.get(tx.currency?)?;
+        asset
+            .dust
+            .filter(|dust| tx.amount < *dust)
+            .map(|_| asset.dust_policy)
+    }
+}
+
+// Decimal places the currency format tells apart, e.g. 4 for I50F14 as 2^-14 is about 0.000061.
+pub fn resolvable_decimals() -> u32 {
+    let mut decimals = 0;
+    while 10u128.pow(decimals + 1) <= 1u128 << Currency::FRAC_NBITS {
+        decimals += 1;
+    }
+    decimals
+}
+
+pub fn precision_warning(code: &str, decimals: u32) -> String {
+    format!(
+        "Asset {} has {} decimals but the {} currency format only resolves {}, amounts will be rounded. Build with the currency-i64f64 feature for more precision.",
+        code,
+        decimals,
+        CURRENCY_FORMAT,
+        resolvable_decimals()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSETS: &str = r#"
+        [[asset]]
+        code = "BTC"
+        decimals = 8
+        dust = "0.001"
+        dust_policy = "sweep"
+
+        [[asset]]
+        code = "DOGE"
+        decimals = 8
+        dust = "1"
+    "#;
+
+    #[test]
+    fn builtin_assets() {
+        let registry = AssetRegistry::default();
+        assert_eq!(registry.decimals(Some("BTC")), 8);
+        assert_eq!(registry.decimals(Some("ETH")), 18);
+        assert_eq!(registry.decimals(Some("USD")), FIAT_DECIMALS);
+        assert_eq!(registry.decimals(None), FIAT_DECIMALS);
+    }
+
+    #[test]
+    fn file_overrides_builtins() {
+        let registry = AssetRegistry::parse(ASSETS).unwrap();
+        let btc = registry.get("BTC").unwrap();
+        assert_eq!(btc.dust, Some(Currency::from_num(0.001)));
+        assert_eq!(btc.dust_policy, DustPolicy::Sweep);
+        assert_eq!(
+            registry.get("DOGE").unwrap().dust_policy,
+            DustPolicy::Ignore
+        );
+        assert_eq!(registry.decimals(Some("ETH")), 18);
+    }
+
+    #[test]
+    fn dust_rows() {
+        let registry = AssetRegistry::parse(ASSETS).unwrap();
+        let deposit = |amount: f64, currency| TxRecord {
+            currency,
+            ..TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(amount))
+        };
+        assert_eq!(
+            registry.dust(&deposit(0.5, Some("DOGE"))),
+            Some(DustPolicy::Ignore)
+        );
+        assert_eq!(registry.dust(&deposit(1.0, Some("DOGE"))), None);
+        assert_eq!(
+            registry.dust(&deposit(0.0005, Some("BTC"))),
+            Some(DustPolicy::Sweep)
+        );
+        assert_eq!(registry.dust(&deposit(0.5, Some("ETH"))), None);
+        assert_eq!(registry.dust(&deposit(0.5, None)), None);
+        let dispute = TxRecord {
+            currency: Some("DOGE"),
+            ..TxRecord::new(TxType::Dispute, 1, 1, Currency::default())
+        };
+        assert_eq!(registry.dust(&dispute), None);
+    }
+
+    #[test]
+    fn reject_bad_assets() {
+        assert!(AssetRegistry::parse("[[asset]]\ncode = \"X\"\n").is_err());
+        assert!(
+            AssetRegistry::parse("[[asset]]\ncode = \"X\"\ndecimals = 2\nunknown = 1\n").is_err()
+        );
+        assert!(
+            AssetRegistry::parse("[[asset]]\ncode = \"X\"\ndecimals = 2\ndust = \"-1\"\n").is_err()
+        );
+        assert!(AssetRegistry::parse(
+            "[[asset]]\ncode = \"X\"\ndecimals = 2\ndust_policy = \"burn\"\n"
+        )
+        .is_err());
+    }
+
+    #[cfg(not(any(feature = "currency-i64f64", feature = "currency-i34f30")))]
+    #[test]
+    fn eth_is_too_precise_for_default_format() {
+        assert_eq!(resolvable_decimals(), 4);
+        let registry = AssetRegistry::default();
+        assert_eq!(registry.too_precise(), vec![("BTC", 8), ("ETH", 18)]);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::engine::Engine;
+use crate::rejection::RejectionReason;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use txcli_model::{
+    precision4_serialize_currency, precision4_serialize_optional_currency, ClientId, Currency,
+    TxId, TxRecord, TxType,
+};
+
+#[derive(Serialize)]
+struct Entry {
+    // The client's own count of rows so far, from 1.
+    seq: u64,
+    // Blank for rows that aren't lines of the input, e.g. cases from the disputes file.
+    line: Option<u64>,
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    tx: TxId,
+    account: String,
+    // Blank for rows referencing another transaction and admin rows, which carry no amount.
+    #[serde(serialize_with = "precision4_serialize_optional_currency")]
+    amount: Option<Currency>,
+    status: &'static str,
+    reason: Option<RejectionReason>,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    available: Currency,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    held: Currency,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    total: Currency,
+    locked: bool,
+}
+
+// Every row of each client in the order it was applied or rejected, with why it was rejected
+// and the client's balances right after it, so an account's state can be traced back step by
+// step. The order is kept here rather than in ClientState, whose history is keyed by tid and
+// forgets what --retain-history prunes. Rows of joint owners go in the canonical client's trail.
+#[derive(Default)]
+pub struct AuditTrail {
+    entries: BTreeMap<ClientId, Vec<Entry>>,
+}
+
+impl AuditTrail {
+    // Called once per row, after the engine applied or rejected it, or instead of the engine for
+    // rows rejected before it.
+    pub fn record(
+        &mut self,
+        engine: &Engine,
+        line: Option<u64>,
+        tx: &TxRecord,
+        result: Result<(), RejectionReason>,
+    ) {
+        let cid = engine.state().canonical(tx.cid);
+        let balance = engine.balance(cid);
+        let entries = self.entries.entry(cid).or_default();
+        let moves_funds = !matches!(
+            tx.tx_type,
+            TxType::Dispute
+                | TxType::Resolve
+                | TxType::ChargeBack
+                | TxType::Void
+                | TxType::ArchiveClient
+                | TxType::ReactivateClient
+        );
+        entries.push(Entry {
+            seq: entries.len() as u64 + 1,
+            line,
+            tx_type: tx.tx_type,
+            tx: tx.tid,
+            account: tx.account.to_string(),
+            amount: moves_funds.then_some(tx.amount),
+            status: if result.is_ok() {
+                "applied"
+            } else {
+                "rejected"
+            },
+            reason: result.err(),
+            available: balance.as_ref().map_or(Currency::ZERO, |b| b.available),
+            held: balance.as_ref().map_or(Currency::ZERO, |b| b.held),
+            total: balance.as_ref().map_or(Currency::ZERO, |b| b.total),
+            locked: balance.is_some_and(|b| b.locked),
+        });
+    }
+
+    // Writes dir/client-<id>.csv for every client with a row, returns how many.
+    pub fn write(&self, dir: &str) -> Result<usize, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        for (cid, entries) in &self.entries {
+            let mut writer =
+                csv::Writer::from_path(Path::new(dir).join(format!("client-{}.csv", cid.0)))?;
+            for entry in entries {
+                writer.serialize(entry)?;
+            }
+            writer.flush()?;
+        }
+        Ok(self.entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use std::collections::HashMap;
+
+    #[test]
+    fn ordered_trail_per_client() {
+        let mut engine = EngineBuilder::new()
+            .joint_owners(HashMap::from([(ClientId(3), ClientId(1))]))
+            .build();
+        let mut trail = AuditTrail::default();
+        for (line, tx) in [
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(10)),
+            TxRecord::new(TxType::Withdrawal, 2, 2, Currency::from_num(1)),
+            TxRecord::new(TxType::Withdrawal, 3, 3, Currency::from_num(4)),
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::from_num(0)),
+            TxRecord::new(TxType::Resolve, 1, 9, Currency::from_num(0)),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let result = engine.try_apply(*tx);
+            trail.record(&engine, Some(line as u64 + 2), tx, result);
+        }
+        let dir = std::env::temp_dir().join(format!("txcli-audit-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        assert_eq!(trail.write(dir).unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(Path::new(dir).join("client-1.csv")).unwrap(),
+            "seq,line,type,tx,account,amount,status,reason,available,held,total,locked\n\
+            1,2,deposit,1,default,10.0000,applied,,10.0000,0.0000,10.0000,false\n\
+            2,4,withdrawal,3,default,4.0000,applied,,6.0000,0.0000,6.0000,false\n\
+            3,5,dispute,1,default,,applied,,-4.0000,10.0000,6.0000,false\n\
+            4,6,resolve,9,default,,rejected,unknown_tx,-4.0000,10.0000,6.0000,false\n"
+        );
+        let other = fs::read_to_string(Path::new(dir).join("client-2.csv")).unwrap();
+        assert!(other.ends_with(
+            "1,3,withdrawal,2,default,1.0000,rejected,insufficient_funds,0.0000,0.0000,0.0000,false\n"
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,344 @@
+use crate::soak::parse_duration;
+use crate::BasicError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::time::Duration;
+use txcli_model::{AccountId, ClientId, Currency, Timestamp, TxId, TxRecord};
+
+// Settlement delays file format, for --settlement-delays:
+//
+// [[delay]]
+// counterparty = "visa"
+// rows = 500              # available to the rows after the next 500 of the input
+//
+// [[delay]]
+// counterparty = "amex"
+// after = "2d"            # available from the first row stamped 2 days after the deposit, s, m, h or d
+//
+// Deposits from a listed counterparty are applied as usual but wait in the pending bucket of
+// their account until the delay is up, pending funds count in the total and can't be withdrawn.
+// Deposits from anyone else are available at once.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DelaysFile {
+    #[serde(default, rename = "delay")]
+    delays: Vec<RawDelay>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDelay {
+    counterparty: String,
+    rows: Option<u64>,
+    after: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Delay {
+    // Later rows of the input, applied or not.
+    Rows(u64),
+    // Time by the rows' timestamps.
+    After(Duration),
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct SettlementDelays {
+    delays: HashMap<String, Delay>,
+}
+
+impl SettlementDelays {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        SettlementDelays::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let file: DelaysFile = toml::from_str(input)?;
+        let mut delays = HashMap::new();
+        for raw in file.delays {
+            let delay = match (raw.rows, raw.after) {
+                (Some(rows), None) if rows > 0 => Delay::Rows(rows),
+                (None, Some(after)) => match parse_duration(&after) {
+                    Some(after) => Delay::After(after),
+                    None => {
+                        return Err(BasicError::new(format!(
+                            "Invalid delay after = {:?} for counterparty {}, expected a duration like 90s, 30m, 24h or 2d.",
+                            after, raw.counterparty
+                        )))
+                    }
+                },
+                _ => {
+                    return Err(BasicError::new(format!(
+                        "The delay for counterparty {} needs either rows of at least 1 or after.",
+                        raw.counterparty
+                    )))
+                }
+            };
+            if delays.insert(raw.counterparty.clone(), delay).is_some() {
+                return Err(BasicError::new(format!(
+                    "Counterparty {} has more than one delay.",
+                    raw.counterparty
+                )));
+            }
+        }
+        Ok(SettlementDelays { delays })
+    }
+
+    // The delay of a deposit, None for one available at once.
+    pub fn of(&self, tx: &TxRecord) -> Option<Delay> {
+        self.delays.get(tx.counterparty?).copied()
+    }
+}
+
+// A deposit waiting in the pending bucket of its account.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Hold {
+    // The canonical client.
+    pub cid: ClientId,
+    pub tid: TxId,
+    pub currency: Option<String>,
+    pub account: AccountId,
+    pub amount: Currency,
+    due: Due,
+}
+
+// When a hold is released. Saved in snapshots with the rows still to go, so a restored run
+// counts on from its own first row.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Due {
+    Row(u64),
+    Time(Timestamp),
+    // A timed hold on a deposit without a timestamp, read before any row had one. The delay
+    // counts from the first row that does.
+    Unstamped(Duration),
+}
+
+// Deposits pending by when they become available, the order they were held in breaking ties.
+#[derive(Debug, Default)]
+pub struct Holds {
+    // Rows read so far, and the latest timestamp among them.
+    seen: u64,
+    now: Option<Timestamp>,
+    next: u64,
+    holds: BTreeMap<u64, Hold>,
+    by_row: BTreeSet<(u64, u64)>,
+    by_time: BTreeSet<(Timestamp, u64)>,
+    unstamped: Vec<u64>,
+    ids: HashMap<(ClientId, TxId), u64>,
+}
+
+impl Holds {
+    // Called as each row is read, before it is applied. Returns the holds due by then.
+    pub fn advance(&mut self, timestamp: Option<Timestamp>) -> Vec<Hold> {
+        self.seen += 1;
+        if let Some(timestamp) = timestamp {
+            self.now = Some(self.now.map_or(timestamp, |now| now.max(timestamp)));
+        }
+        if self.holds.is_empty() {
+            return vec![];
+        }
+        if let Some(now) = self.now {
+            for key in std::mem::take(&mut self.unstamped) {
+                if let Some(hold) = self.holds.get_mut(&key) {
+                    if let Due::Unstamped(after) = hold.due {
+                        hold.due = Due::Time(Timestamp(now.0 + after.as_secs()));
+                        self.by_time
+                            .insert((Timestamp(now.0 + after.as_secs()), key));
+                    }
+                }
+            }
+        }
+        let mut due: Vec<u64> = vec![];
+        while let Some(&(row, key)) = self.by_row.first().filter(|(row, _)| *row <= self.seen) {
+            self.by_row.remove(&(row, key));
+            due.push(key);
+        }
+        while let Some(&(time, key)) = self
+            .by_time
+            .first()
+            .filter(|(time, _)| self.now.is_some_and(|now| *time <= now))
+        {
+            self.by_time.remove(&(time, key));
+            due.push(key);
+        }
+        // Released in the order they were held.
+        due.sort_unstable();
+        due.into_iter().filter_map(|key| self.remove(key)).collect()
+    }
+
+    // Holds an applied deposit of the canonical client cid, read as the latest row.
+    pub fn hold(&mut self, cid: ClientId, tx: &TxRecord, delay: Delay) {
+        let due = match (delay, tx.timestamp.or(self.now)) {
+            (Delay::Rows(rows), _) => Due::Row(self.seen + rows + 1),
+            (Delay::After(after), Some(from)) => Due::Time(Timestamp(from.0 + after.as_secs())),
+            (Delay::After(after), None) => Due::Unstamped(after),
+        };
+        self.insert(Hold {
+            cid,
+            tid: tx.tid,
+            currency: tx.currency.map(str::to_string),
+            account: AccountId(tx.account.to_string()),
+            amount: tx.amount,
+            due,
+        });
+    }
+
+    fn insert(&mut self, hold: Hold) {
+        let key = self.next;
+        self.next += 1;
+        match hold.due {
+            Due::Row(row) => {
+                self.by_row.insert((row, key));
+            }
+            Due::Time(time) => {
+                self.by_time.insert((time, key));
+            }
+            Due::Unstamped(_) => self.unstamped.push(key),
+        }
+        self.ids.insert((hold.cid, hold.tid), key);
+        self.holds.insert(key, hold);
+    }
+
+    fn remove(&mut self, key: u64) -> Option<Hold> {
+        let hold = self.holds.remove(&key)?;
+        self.ids.remove(&(hold.cid, hold.tid));
+        Some(hold)
+    }
+
+    // Takes the hold on tid out early, e.g. once the deposit is disputed or voided.
+    pub fn take(&mut self, cid: ClientId, tid: TxId) -> Option<Hold> {
+        let key = *self.ids.get(&(cid, tid))?;
+        let hold = self.remove(key)?;
+        match hold.due {
+            Due::Row(row) => {
+                self.by_row.remove(&(row, key));
+            }
+            Due::Time(time) => {
+                self.by_time.remove(&(time, key));
+            }
+            Due::Unstamped(_) => self.unstamped.retain(|unstamped| *unstamped != key),
+        }
+        Some(hold)
+    }
+
+    // For a snapshot, rows due counted from the next row read.
+    pub fn saved(&self) -> Vec<Hold> {
+        self.holds
+            .values()
+            .map(|hold| Hold {
+                due: match hold.due {
+                    Due::Row(row) => Due::Row(row - self.seen),
+                    due => due,
+                },
+                ..hold.clone()
+            })
+            .collect()
+    }
+
+    // Picks up the holds of a snapshot, or of another engine's clients.
+    pub fn restore(&mut self, holds: Vec<Hold>) {
+        for hold in holds {
+            self.insert(Hold {
+                due: match hold.due {
+                    Due::Row(row) => Due::Row(row + self.seen),
+                    due => due,
+                },
+                ..hold
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::TxType;
+
+    fn deposit(tid: u32, timestamp: Option<u64>) -> TxRecord<'static> {
+        let mut tx = TxRecord::new(TxType::Deposit, 1, tid, Currency::from_num(tid));
+        tx.timestamp = timestamp.map(Timestamp);
+        tx
+    }
+
+    fn released(holds: &mut Holds, timestamp: Option<u64>) -> Vec<u32> {
+        holds
+            .advance(timestamp.map(Timestamp))
+            .iter()
+            .map(|hold| hold.tid.0)
+            .collect()
+    }
+
+    #[test]
+    fn released_by_rows_and_time() {
+        let mut holds = Holds::default();
+        released(&mut holds, Some(1000));
+        holds.hold(ClientId(1), &deposit(1, Some(1000)), Delay::Rows(2));
+        released(&mut holds, None);
+        holds.hold(
+            ClientId(1),
+            &deposit(2, None),
+            Delay::After(Duration::from_secs(60)),
+        );
+        released(&mut holds, None);
+        holds.hold(ClientId(1), &deposit(3, None), Delay::Rows(5));
+        // Two rows after the first deposit.
+        assert_eq!(released(&mut holds, Some(1059)), vec![1]);
+        assert_eq!(released(&mut holds, Some(1060)), vec![2]);
+        assert!(holds.take(ClientId(1), TxId(3)).is_some());
+        assert!(holds.take(ClientId(1), TxId(3)).is_none());
+        assert!(holds.saved().is_empty());
+
+        // Timed holds before any timestamp wait for the first one.
+        let mut holds = Holds::default();
+        released(&mut holds, None);
+        holds.hold(
+            ClientId(1),
+            &deposit(4, None),
+            Delay::After(Duration::from_secs(10)),
+        );
+        assert!(released(&mut holds, Some(500)).is_empty());
+        assert_eq!(released(&mut holds, Some(510)), vec![4]);
+    }
+
+    #[test]
+    fn snapshots_count_rows_from_the_restore() {
+        let mut holds = Holds::default();
+        for _ in 0..10 {
+            released(&mut holds, None);
+        }
+        holds.hold(ClientId(1), &deposit(1, None), Delay::Rows(3));
+        released(&mut holds, None);
+        let mut restored = Holds::default();
+        restored.restore(holds.saved());
+        assert!(released(&mut restored, None).is_empty());
+        assert!(released(&mut restored, None).is_empty());
+        assert_eq!(released(&mut restored, None), vec![1]);
+    }
+
+    #[test]
+    fn delays_file() {
+        let delays = SettlementDelays::parse(
+            "[[delay]]\ncounterparty = \"visa\"\nrows = 3\n\n[[delay]]\ncounterparty = \"amex\"\nafter = \"2d\"\n",
+        )
+        .unwrap();
+        let mut tx = deposit(1, None);
+        tx.counterparty = Some("amex");
+        assert_eq!(
+            delays.of(&tx),
+            Some(Delay::After(Duration::from_secs(172800)))
+        );
+        tx.counterparty = Some("mc");
+        assert_eq!(delays.of(&tx), None);
+        for invalid in [
+            "[[delay]]\ncounterparty = \"visa\"\n",
+            "[[delay]]\ncounterparty = \"visa\"\nrows = 0\n",
+            "[[delay]]\ncounterparty = \"visa\"\nrows = 1\nafter = \"1h\"\n",
+            "[[delay]]\ncounterparty = \"visa\"\nafter = \"soon\"\n",
+            "[[delay]]\ncounterparty = \"visa\"\nrows = 1\n[[delay]]\ncounterparty = \"visa\"\nrows = 2\n",
+        ] {
+            assert!(SettlementDelays::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{AppState, Balance, BasicError};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Read;
+use txcli_model::{
+    deserialize_amount, deserialize_optional_amount, ClientId, Currency, DEFAULT_ACCOUNT,
+};
+
+// Matches the output format so a previous run's output can be fed straight back in.
+// total is optional and only used as a sanity check.
+#[derive(Deserialize, Debug)]
+pub struct BalanceRow {
+    pub client: ClientId,
+    // Blank for the unnamed asset.
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub available: Currency,
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    pub held: Currency,
+    #[serde(default, deserialize_with = "deserialize_optional_amount")]
+    total: Option<Currency>,
+    #[serde(default)]
+    pub locked: bool,
+}
+
+pub fn read_balances<R: Read>(input: R) -> Result<Vec<BalanceRow>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(true)
+        .from_reader(input);
+
+    let mut seen = HashSet::new();
+    let mut balances = vec![];
+    for row in reader.deserialize::<BalanceRow>() {
+        let balance = row?;
+        if let Some(total) = balance.total {
+            if total != balance.available + balance.held {
+                return Err(BasicError::new(format!(
+                    "Balance for client[{}] has total {} which does not equal available + held.",
+                    balance.client.0, total
+                )));
+            }
+        }
+        if !seen.insert((balance.client, balance.currency.clone())) {
+            return Err(BasicError::new(format!(
+                "Balances list client[{}] {} more than once.",
+                balance.client.0,
+                balance.currency.as_deref().unwrap_or("without a currency")
+            )));
+        }
+        balances.push(balance);
+    }
+
+    Ok(balances)
+}
+
+// Opening balances always land in the default account of their asset.
+// Opening held funds are not backed by any disputed transaction in this run, so they can never be
+// resolved or charged back here. They are carried through untouched until a later run knows the disputes.
+pub fn seed<R: Read>(app_state: &mut AppState, input: R) -> Result<(), Box<dyn Error>> {
+    for balance in read_balances(input)? {
+        if app_state.joint_owners.contains_key(&balance.client) {
+            return Err(BasicError::new(format!(
+                "Opening balances list client[{}], which is a joint owner. Opening balances belong to the canonical owner.",
+                balance.client.0
+            )));
+        }
+        let client = app_state.clients.entry(balance.client).or_default();
+        *client.balance(balance.currency.as_deref(), DEFAULT_ACCOUNT) = Balance {
+            available: balance.available,
+            held: balance.held,
+            ..Default::default()
+        };
+        client.locked |= balance.locked;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_transaction;
+    use txcli_model::{TxRecord, TxType};
+
+    #[test]
+    fn seed_from_output_format() {
+        let mut app_state = AppState::default();
+        let input =
+            "client,available,held,total,locked\n1,1.5000,0.5000,2.0000,false\n2,3.0,0,3.0,true\n";
+        seed(&mut app_state, input.as_bytes()).unwrap();
+        assert_eq!(app_state.clients.len(), 2);
+        let client = &app_state.clients[&ClientId(1)];
+        assert_eq!(client.available(), Currency::from_num(1.5));
+        assert_eq!(client.held(), Currency::from_num(0.5));
+        assert!(!client.locked);
+        assert!(app_state.clients[&ClientId(2)].locked);
+    }
+
+    #[test]
+    fn seed_without_optional_columns() {
+        let mut app_state = AppState::default();
+        seed(&mut app_state, "client,available\n4, 10.25\n".as_bytes()).unwrap();
+        let client = &app_state.clients[&ClientId(4)];
+        assert_eq!(client.available(), Currency::from_num(10.25));
+        assert_eq!(client.held(), Currency::from_num(0));
+        assert!(!client.locked);
+    }
+
+    #[test]
+    fn transactions_apply_on_top_of_opening_balance() {
+        let mut app_state = AppState::default();
+        seed(&mut app_state, "client,available\n1,5.0\n".as_bytes()).unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Withdrawal, 1, 1, Currency::from_num(2.0)),
+        )
+        .unwrap();
+        assert_eq!(
+            app_state.clients[&ClientId(1)].available(),
+            Currency::from_num(3.0)
+        );
+    }
+
+    #[test]
+    fn seed_each_currency() {
+        let mut app_state = AppState::default();
+        let input = "client,currency,available\n1,BTC,2\n1,USD,100\n1,,3\n";
+        seed(&mut app_state, input.as_bytes()).unwrap();
+        let client = &app_state.clients[&ClientId(1)];
+        assert_eq!(client.asset_names().count(), 3);
+        assert_eq!(client.available(), Currency::from_num(105));
+
+        let input = "client,currency,available\n1,BTC,2\n1,BTC,100\n";
+        assert!(seed(&mut AppState::default(), input.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn reject_bad_total() {
+        let mut app_state = AppState::default();
+        let input = "client,available,held,total\n1,1.0,1.0,3.0\n";
+        assert!(seed(&mut app_state, input.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn reject_duplicate_client() {
+        let mut app_state = AppState::default();
+        let input = "client,available\n1,1.0\n1,2.0\n";
+        assert!(seed(&mut app_state, input.as_bytes()).is_err());
+    }
+}
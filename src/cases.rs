@@ -0,0 +1,210 @@
+use crate::ordering::case_first;
+use crate::BasicError;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+use std::str::FromStr;
+use txcli_model::{ClientId, Currency, Timestamp, TxId, TxRecord, TxType};
+
+// One row of a case management export.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Case {
+    pub tx_type: TxType,
+    // None when the export has no client column, the caller finds the client by tid.
+    pub cid: Option<ClientId>,
+    pub tid: TxId,
+    pub timestamp: Timestamp,
+    pub line: u64,
+}
+
+impl Case {
+    // The row to apply, its client named by the case or else found in owners.
+    pub fn record(&self, owners: &HashMap<TxId, ClientId>) -> Option<TxRecord<'static>> {
+        let cid = self.cid.or_else(|| owners.get(&self.tid).copied())?;
+        Some(TxRecord {
+            timestamp: Some(self.timestamp),
+            ..TxRecord::new(self.tx_type, cid.0, self.tid.0, Currency::from_num(0))
+        })
+    }
+}
+
+// Disputes, resolves and chargebacks exported from case management, keyed by tid and ordered by
+// timestamp: type,tx,timestamp with an optional client column, found by header name. They are
+// merged into the transaction stream as it is read, each released just before the first row
+// stamped later than it, so a case stamped the same second as a transaction comes after it.
+pub struct Cases<R> {
+    reader: csv::Reader<R>,
+    tx_type: usize,
+    tx: usize,
+    timestamp: usize,
+    client: Option<usize>,
+    // The next case not released yet.
+    next: Option<Case>,
+}
+
+impl<R: Read> Cases<R> {
+    pub fn new(reader: R) -> Result<Self, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(reader);
+        let headers = reader.headers()?;
+        let position = |name: &str| headers.iter().position(|header| header == name);
+        let required = |name: &str| {
+            position(name).ok_or_else(|| {
+                BasicError::new(format!("The disputes file is missing the {} column.", name))
+            })
+        };
+        let mut cases = Cases {
+            tx_type: required("type")?,
+            tx: required("tx")?,
+            timestamp: required("timestamp")?,
+            client: position("client"),
+            reader,
+            next: None,
+        };
+        cases.next = cases.read()?;
+        Ok(cases)
+    }
+
+    // Whether cases name their client, otherwise the caller has to know who owns each tid.
+    pub fn has_clients(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn read(&mut self) -> Result<Option<Case>, Box<dyn Error>> {
+        let mut record = csv::StringRecord::new();
+        if !self.reader.read_record(&mut record)? {
+            return Ok(None);
+        }
+        let line = record.position().map_or(0, |position| position.line());
+        let field = |column: usize, name: &str| {
+            record
+                .get(column)
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| {
+                    BasicError::new(format!("disputes file line {}: missing {}", line, name))
+                })
+        };
+        fn parsed<T: FromStr>(value: &str, name: &str, line: u64) -> Result<T, Box<dyn Error>> {
+            Ok(value.parse().map_err(|_| {
+                BasicError::new(format!(
+                    "disputes file line {}: invalid {} {}",
+                    line, name, value
+                ))
+            })?)
+        }
+        let tx_type = parsed(field(self.tx_type, "type")?, "type", line)?;
+        if !matches!(
+            tx_type,
+            TxType::Dispute | TxType::Resolve | TxType::ChargeBack
+        ) {
+            return Err(BasicError::new(format!(
+                "disputes file line {}: {} is not a dispute, resolve or chargeback",
+                line, tx_type
+            )));
+        }
+        Ok(Some(Case {
+            tx_type,
+            cid: self
+                .client
+                .map(|column| parsed(field(column, "client")?, "client", line).map(ClientId))
+                .transpose()?,
+            tid: TxId(parsed(field(self.tx, "tx")?, "tx", line)?),
+            timestamp: parsed(field(self.timestamp, "timestamp")?, "timestamp", line)?,
+            line,
+        }))
+    }
+
+    // The cases stamped before timestamp in file order, or all that are left for None.
+    pub fn before(&mut self, timestamp: Option<Timestamp>) -> Result<Vec<Case>, Box<dyn Error>> {
+        let mut due = vec![];
+        while let Some(case) = self.next {
+            if timestamp.is_some_and(|timestamp| !case_first(case.timestamp, timestamp)) {
+                break;
+            }
+            self.next = self.read()?;
+            if let Some(next) = self.next.filter(|next| next.timestamp < case.timestamp) {
+                return Err(BasicError::new(format!(
+                    "disputes file line {}: timestamp goes back from the previous case, the file must be ordered by timestamp",
+                    next.line
+                )));
+            }
+            due.push(case);
+        }
+        Ok(due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cases(input: &str) -> Result<Cases<&[u8]>, Box<dyn Error>> {
+        Cases::new(input.as_bytes())
+    }
+
+    #[test]
+    fn released_before_later_rows() {
+        let mut cases = cases(
+            "case_id,timestamp,tx,type,client\n\
+             A1,100,7,dispute,3\n\
+             A1,100,8,dispute,3\n\
+             A1,200,7,chargeback,3\n",
+        )
+        .unwrap();
+        assert!(cases.has_clients());
+        assert!(cases.before(Some(Timestamp(50))).unwrap().is_empty());
+        // Not yet, a case stamped the same second comes after the transaction.
+        assert!(cases.before(Some(Timestamp(100))).unwrap().is_empty());
+        let due = cases.before(Some(Timestamp(150))).unwrap();
+        assert_eq!(due.len(), 2);
+        assert_eq!(
+            due[0],
+            Case {
+                tx_type: TxType::Dispute,
+                cid: Some(ClientId(3)),
+                tid: TxId(7),
+                timestamp: Timestamp(100),
+                line: 2,
+            }
+        );
+        assert_eq!(due[1].tid, TxId(8));
+        let rest = cases.before(None).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].tx_type, TxType::ChargeBack);
+        assert!(cases.before(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn client_found_by_tid() {
+        let mut cases = cases("type,tx,timestamp\ndispute,7,100\ndispute,9,100\n").unwrap();
+        let due = cases.before(None).unwrap();
+        let owners = HashMap::from([(TxId(7), ClientId(3))]);
+        let record = due[0].record(&owners).unwrap();
+        assert_eq!(record.cid, ClientId(3));
+        assert_eq!(record.timestamp, Some(Timestamp(100)));
+        assert_eq!(due[1].record(&owners), None);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let err = cases("type,tx\ndispute,1\n").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "The disputes file is missing the timestamp column."
+        );
+        let err = cases("type,tx,timestamp\ndeposit,1,5\n").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "disputes file line 2: deposit is not a dispute, resolve or chargeback"
+        );
+        let mut unordered = cases("type,tx,timestamp\ndispute,1,5\nresolve,1,4\n").unwrap();
+        assert!(!unordered.has_clients());
+        let err = unordered.before(None).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("disputes file line 3: timestamp goes back"));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::BasicError;
+use regex::Regex;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use txcli_model::{deserialize_optional_amount, Currency, TxRecord, TxType};
+
+// Rules file format, first matching rule wins:
+//
+// [[rule]]
+// category = "groceries"
+// tag = "(?i)^(tesco|safeway)"  # regex against the row's category column
+// counterparty = "(?i)tesco"     # regex against the schema v2 counterparty column
+// account = "^checking$"        # regex against the account name
+// type = "withdrawal"
+// min_amount = "0.0"            # amounts are strings to stay in fixed point
+// max_amount = "500.0"
+//
+// Every condition is optional, a rule with none of them is a catch all.
+#[derive(Deserialize)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RawRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    category: String,
+    tag: Option<String>,
+    counterparty: Option<String>,
+    account: Option<String>,
+    #[serde(rename = "type")]
+    tx_type: Option<TxType>,
+    #[serde(default, deserialize_with = "deserialize_optional_amount")]
+    min_amount: Option<Currency>,
+    #[serde(default, deserialize_with = "deserialize_optional_amount")]
+    max_amount: Option<Currency>,
+}
+
+struct Rule {
+    category: String,
+    tag: Option<Regex>,
+    counterparty: Option<Regex>,
+    account: Option<Regex>,
+    tx_type: Option<TxType>,
+    min_amount: Option<Currency>,
+    max_amount: Option<Currency>,
+}
+
+impl Rule {
+    fn matches(&self, tx: &TxRecord) -> bool {
+        // A pattern never matches a missing value, otherwise "^$" style patterns get surprising.
+        let optional_matches = |pattern: &Option<Regex>, value: Option<&str>| match (pattern, value)
+        {
+            (Some(pattern), Some(value)) => pattern.is_match(value),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        optional_matches(&self.tag, tx.category)
+            && optional_matches(&self.counterparty, tx.counterparty)
+            && self
+                .account
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(tx.account))
+            && self.tx_type.is_none_or(|tx_type| tx_type == tx.tx_type)
+            && self.min_amount.is_none_or(|min| tx.amount >= min)
+            && self.max_amount.is_none_or(|max| tx.amount <= max)
+    }
+}
+
+pub struct Categorizer {
+    rules: Vec<Rule>,
+}
+
+impl Categorizer {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Categorizer::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let file: RulesFile = toml::from_str(input)?;
+        let compile = |pattern: Option<String>| -> Result<Option<Regex>, Box<dyn Error>> {
+            match pattern {
+                Some(pattern) => Ok(Some(Regex::new(&pattern).map_err(|err| {
+                    BasicError::new(format!("Invalid categorize pattern {}: {}", pattern, err))
+                })?)),
+                None => Ok(None),
+            }
+        };
+
+        let mut rules = vec![];
+        for raw in file.rules {
+            rules.push(Rule {
+                category: raw.category,
+                tag: compile(raw.tag)?,
+                counterparty: compile(raw.counterparty)?,
+                account: compile(raw.account)?,
+                tx_type: raw.tx_type,
+                min_amount: raw.min_amount,
+                max_amount: raw.max_amount,
+            });
+        }
+        Ok(Categorizer { rules })
+    }
+
+    // Replaces the row's category with the first matching rule's. Rows matching no rule keep their own tag.
+    pub fn apply<'a>(&'a self, tx: &mut TxRecord<'a>) {
+        if let Some(rule) = self.rules.iter().find(|rule| rule.matches(tx)) {
+            tx.category = Some(&rule.category);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+        [[rule]]
+        category = "groceries"
+        tag = "(?i)^(tesco|safeway)"
+
+        [[rule]]
+        category = "salary"
+        type = "deposit"
+        account = "^payroll$"
+
+        [[rule]]
+        category = "utilities"
+        counterparty = "(?i)power co"
+
+        [[rule]]
+        category = "large"
+        min_amount = "1000.0"
+    "#;
+
+    fn categorize(tx: TxRecord) -> Option<String> {
+        let categorizer = Categorizer::parse(RULES).unwrap();
+        let mut tx: TxRecord = tx;
+        categorizer.apply(&mut tx);
+        tx.category.map(String::from)
+    }
+
+    fn tagged<'a>(mut tx: TxRecord<'a>, tag: &'a str) -> TxRecord<'a> {
+        tx.category = Some(tag);
+        tx
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let tx = TxRecord::new(TxType::Withdrawal, 1, 1, Currency::from_num(5000));
+        assert_eq!(
+            categorize(tagged(tx, "TESCO store 42")).as_deref(),
+            Some("groceries")
+        );
+        let tx = TxRecord::new(TxType::Withdrawal, 1, 1, Currency::from_num(5000));
+        assert_eq!(
+            categorize(tagged(tx, "car dealer")).as_deref(),
+            Some("large")
+        );
+    }
+
+    #[test]
+    fn untagged_rows_match_on_other_fields() {
+        let tx =
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(10)).with_account("payroll");
+        assert_eq!(categorize(tx).as_deref(), Some("salary"));
+        let tx =
+            TxRecord::new(TxType::Withdrawal, 1, 1, Currency::from_num(10)).with_account("payroll");
+        assert_eq!(categorize(tx), None);
+    }
+
+    #[test]
+    fn match_on_counterparty() {
+        let mut tx = TxRecord::new(TxType::Withdrawal, 1, 1, Currency::from_num(10));
+        tx.counterparty = Some("City Power Co");
+        assert_eq!(categorize(tx).as_deref(), Some("utilities"));
+    }
+
+    #[test]
+    fn unmatched_rows_keep_their_tag() {
+        let tx = TxRecord::new(TxType::Withdrawal, 1, 1, Currency::from_num(10));
+        assert_eq!(categorize(tagged(tx, "rent")).as_deref(), Some("rent"));
+    }
+
+    #[test]
+    fn reject_bad_rules() {
+        assert!(Categorizer::parse("[[rule]]\ncategory = \"x\"\ntag = \"(\"\n").is_err());
+        assert!(Categorizer::parse("[[rule]]\ncategory = \"x\"\nunknown = 1\n").is_err());
+        assert!(Categorizer::parse("[[rule]]\ntag = \"x\"\n").is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use txcli_model::{ClientOutputState, Timestamp, TxId, TxType};
+
+// Debezium style envelope so the stream can be fed into the same tooling that consumes database CDC.
+// A wall clock timestamp is deliberately left out so reruns over the same input produce identical streams,
+// seq orders the events instead. The transaction's own timestamp is included when the input has one.
+#[derive(Serialize)]
+struct Envelope<'a> {
+    op: &'static str,
+    before: Option<&'a ClientOutputState>,
+    after: &'a ClientOutputState,
+    source: Source,
+}
+
+#[derive(Serialize)]
+struct Source {
+    seq: u64,
+    tid: TxId,
+    tx_type: TxType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<Timestamp>,
+}
+
+pub struct CdcWriter<W: Write> {
+    writer: W,
+    seq: u64,
+}
+
+impl CdcWriter<BufWriter<File>> {
+    pub fn create(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(CdcWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> CdcWriter<W> {
+    pub fn new(writer: W) -> Self {
+        CdcWriter { writer, seq: 0 }
+    }
+
+    // Writes one json line per balance change, transactions that leave the client untouched are skipped.
+    pub fn record(
+        &mut self,
+        tid: TxId,
+        tx_type: TxType,
+        timestamp: Option<Timestamp>,
+        before: Option<&ClientOutputState>,
+        after: &ClientOutputState,
+    ) -> Result<(), Box<dyn Error>> {
+        if before == Some(after) {
+            return Ok(());
+        }
+
+        self.seq += 1;
+        let envelope = Envelope {
+            op: if before.is_some() { "u" } else { "c" },
+            before,
+            after,
+            source: Source {
+                seq: self.seq,
+                tid,
+                tx_type,
+                timestamp,
+            },
+        };
+        serde_json::to_writer(&mut self.writer, &envelope)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::{ClientId, Currency};
+
+    fn state(available: f64, held: f64) -> ClientOutputState {
+        ClientOutputState {
+            cid: ClientId(1),
+            currency: None,
+            account: None,
+            available: Currency::from_num(available),
+            held: Currency::from_num(held),
+            total: Currency::from_num(available + held),
+            locked: false,
+            owners: None,
+            penalties: None,
+            chargeback_fees: None,
+            written_off: None,
+            dispute_count: None,
+            pending: None,
+        }
+    }
+
+    #[test]
+    fn create_then_update() {
+        let mut cdc = CdcWriter::new(vec![]);
+        cdc.record(TxId(1), TxType::Deposit, None, None, &state(1.0, 0.0))
+            .unwrap();
+        cdc.record(
+            TxId(1),
+            TxType::Dispute,
+            Some(Timestamp(1660052700)),
+            Some(&state(1.0, 0.0)),
+            &state(0.0, 1.0),
+        )
+        .unwrap();
+        let out = String::from_utf8(cdc.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r#"{"op":"c","before":null,"after":{"cid":1,"available":"1.0000","held":"0.0000","total":"1.0000","locked":false},"source":{"seq":1,"tid":1,"tx_type":"deposit"}}"#
+        );
+        assert!(lines[1].starts_with(r#"{"op":"u","before":{"cid":1,"available":"1.0000""#));
+        assert!(lines[1].ends_with(
+            r#""source":{"seq":2,"tid":1,"tx_type":"dispute","timestamp":1660052700}}"#
+        ));
+    }
+
+    #[test]
+    fn unchanged_state_is_skipped() {
+        let mut cdc = CdcWriter::new(vec![]);
+        cdc.record(
+            TxId(2),
+            TxType::Withdrawal,
+            None,
+            Some(&state(1.0, 0.0)),
+            &state(1.0, 0.0),
+        )
+        .unwrap();
+        assert!(cdc.finish().unwrap().is_empty());
+    }
+}
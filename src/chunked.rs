@@ -0,0 +1,339 @@
+use crate::options::Options;
+use crate::schema::RowReader;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Cursor, Read, SeekFrom};
+use std::sync::mpsc;
+use txcli_model::Tx;
+
+// The csv crate's own default is 8 KiB, a few more syscalls saved for little memory.
+pub const DEFAULT_READ_BUFFER: usize = 64 * 1024;
+// Enough rows to be worth a task, around a megabyte of typical input.
+pub const DEFAULT_BATCH_SIZE: usize = 32 * 1024;
+
+// Sizes of the parsing pipeline, for trading throughput against memory on given hardware.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Tuning {
+    pub threads: usize,
+    // Bytes buffered reading the input.
+    pub read_buffer: usize,
+    // Rows per chunk handed to the pool.
+    pub batch_size: usize,
+    // Chunks read ahead of the engine, parsed or waiting to be.
+    pub channel_capacity: usize,
+}
+
+impl Tuning {
+    // Defaults keep every thread busy with one chunk while another waits for it.
+    pub fn of(options: &Options, threads: usize) -> Self {
+        Tuning {
+            threads,
+            read_buffer: options.read_buffer.unwrap_or(DEFAULT_READ_BUFFER),
+            batch_size: options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+            channel_capacity: options.channel_capacity.unwrap_or(2 * threads),
+        }
+    }
+}
+
+// The rows of one chunk in input order, each with the input row as read for the retry file, or
+// why it failed to parse. Parsing carries on past a failed row, whether processing skips it or
+// stops there is up to the caller, just like reading sequentially.
+pub struct Chunk {
+    pub rows: Vec<Result<(Tx, csv::StringRecord), String>>,
+}
+
+// Hands back items finished in any order by the index they were numbered with when read,
+// holding each one until every earlier one is out. Everything after it is applied on one thread,
+// so every client's rows reach the engine in input order however the pool interleaves the work.
+pub struct Sequencer<T> {
+    next: u64,
+    ready: BTreeMap<u64, T>,
+}
+
+impl<T> Default for Sequencer<T> {
+    fn default() -> Self {
+        Sequencer {
+            next: 0,
+            ready: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> Sequencer<T> {
+    pub fn insert(&mut self, index: u64, item: T) {
+        debug_assert!(index >= self.next, "item {} was already handed back", index);
+        self.ready.insert(index, item);
+    }
+
+    // The next item in order, None while it is still being worked on.
+    pub fn pop(&mut self) -> Option<T> {
+        let item = self.ready.remove(&self.next)?;
+        self.next += 1;
+        Some(item)
+    }
+
+    // Items handed back so far.
+    pub fn handed_back(&self) -> u64 {
+        self.next
+    }
+}
+
+// Reads the input in chunks of whole lines and parses them on a rayon pool, handing the chunks
+// back in input order to be applied sequentially. Only channel_capacity chunks are read ahead of
+// the caller, so a slow applier holds back reading instead of the whole file landing in memory.
+// Chunks are split at newlines, so quoted fields can't span lines.
+pub struct ChunkedRows<R> {
+    input: BufReader<R>,
+    rows: RowReader,
+    pool: rayon::ThreadPool,
+    batch_size: usize,
+    in_flight: u64,
+    // Position of the next chunk to read, so errors and line() point into the whole input.
+    position: csv::Position,
+    eof: bool,
+    sender: mpsc::Sender<(u64, Chunk)>,
+    receiver: mpsc::Receiver<(u64, Chunk)>,
+    // Chunks read so far, parsed ones are handed back through sequencer.
+    read: u64,
+    sequencer: Sequencer<Chunk>,
+}
+
+impl<R: Read> ChunkedRows<R> {
+    // input starts at position, the first row after the header, and rows was set up for it.
+    pub fn new(
+        input: R,
+        position: csv::Position,
+        rows: RowReader,
+        tuning: Tuning,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (sender, receiver) = mpsc::channel();
+        Ok(ChunkedRows {
+            input: BufReader::with_capacity(tuning.read_buffer, input),
+            rows,
+            pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(tuning.threads)
+                .build()?,
+            batch_size: tuning.batch_size,
+            in_flight: tuning.channel_capacity as u64,
+            position,
+            eof: false,
+            sender,
+            receiver,
+            read: 0,
+            sequencer: Sequencer::default(),
+        })
+    }
+
+    pub fn next(&mut self) -> Option<Result<Chunk, Box<dyn Error>>> {
+        while !self.eof && self.read - self.sequencer.handed_back() < self.in_flight {
+            if let Err(err) = self.spawn() {
+                return Some(Err(err));
+            }
+        }
+        if self.sequencer.handed_back() == self.read {
+            return None;
+        }
+        loop {
+            if let Some(chunk) = self.sequencer.pop() {
+                return Some(Ok(chunk));
+            }
+            // Never disconnected since self holds a sender, and a panicking task aborts.
+            let (index, chunk) = self.receiver.recv().ok()?;
+            self.sequencer.insert(index, chunk);
+        }
+    }
+
+    fn spawn(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut bytes = vec![];
+        let mut lines = 0;
+        while lines < self.batch_size as u64 && self.input.read_until(b'\n', &mut bytes)? > 0 {
+            lines += 1;
+        }
+        if bytes.is_empty() {
+            self.eof = true;
+            return Ok(());
+        }
+
+        let position = self.position.clone();
+        self.position.set_byte(position.byte() + bytes.len() as u64);
+        self.position.set_line(position.line() + lines);
+        self.position.set_record(position.record() + lines);
+
+        let (index, rows, sender) = (self.read, self.rows.clone(), self.sender.clone());
+        self.read += 1;
+        self.pool.spawn(move || {
+            // The receiver only goes away with self, when nobody wants the chunk anymore.
+            let _ = sender.send((index, parse(rows, bytes, position)));
+        });
+        Ok(())
+    }
+}
+
+fn parse(mut rows: RowReader, bytes: Vec<u8>, position: csv::Position) -> Chunk {
+    let mut chunk = Chunk { rows: vec![] };
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(Cursor::new(bytes));
+    // Seeks nowhere, only numbers the rows from where the chunk sits in the input.
+    if let Err(err) = reader.seek_raw(SeekFrom::Start(0), position) {
+        chunk.rows.push(Err(err.to_string()));
+        return chunk;
+    }
+    loop {
+        let tx = match rows.read(&mut reader) {
+            Ok(true) => rows.parse().map(|tx| tx.to_tx()),
+            Ok(false) => break,
+            Err(err) => Err(err),
+        };
+        chunk.rows.push(
+            tx.map(|tx| (tx, rows.record().clone()))
+                .map_err(|err| err.to_string()),
+        );
+    }
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+    use std::io::Seek;
+    use txcli_model::{Currency, TxId, TxType};
+
+    const INPUT: &str = "type,client,tx,amount\n";
+
+    // Tiny chunks and buffers so rows land in chunks of their own.
+    fn chunked(input: &str, threads: usize) -> (Vec<Tx>, Vec<String>) {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let rows = Schema::V1.reader(reader.headers().unwrap()).unwrap();
+        let position = reader.position().clone();
+        let mut file = Cursor::new(input.as_bytes());
+        file.seek(SeekFrom::Start(position.byte())).unwrap();
+        let tuning = Tuning {
+            threads,
+            read_buffer: 16,
+            batch_size: 3,
+            channel_capacity: 2,
+        };
+        let mut chunks = ChunkedRows::new(file, position, rows, tuning).unwrap();
+        let (mut txs, mut errors) = (vec![], vec![]);
+        while let Some(chunk) = chunks.next() {
+            for row in chunk.unwrap().rows {
+                match row {
+                    Ok((tx, _)) => txs.push(tx),
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+        (txs, errors)
+    }
+
+    #[test]
+    fn keeps_input_order() {
+        let mut input = INPUT.to_string();
+        for tid in 1..=500 {
+            input.push_str(&format!("deposit,{},{},{}.5\n", tid % 7, tid, tid));
+        }
+        let (txs, errors) = chunked(&input, 4);
+        assert!(errors.is_empty());
+        assert_eq!(txs.len(), 500);
+        for (tx, tid) in txs.iter().zip(1..) {
+            assert_eq!(tx.tid, TxId(tid));
+            assert_eq!(tx.amount, Currency::from_num(tid) + Currency::from_num(0.5));
+        }
+    }
+
+    // Every order 6 chunks can finish parsing in, each client's rows must still come out in input
+    // order. Rows are (client, tid) with tids increasing through the input.
+    #[test]
+    fn per_client_order_under_any_interleaving() {
+        fn permutations(items: Vec<u64>) -> Vec<Vec<u64>> {
+            if items.len() <= 1 {
+                return vec![items];
+            }
+            let mut all = vec![];
+            for (i, first) in items.iter().enumerate() {
+                let mut rest = items.clone();
+                rest.remove(i);
+                for mut tail in permutations(rest) {
+                    tail.insert(0, *first);
+                    all.push(tail);
+                }
+            }
+            all
+        }
+        let chunks: Vec<Vec<(u16, u32)>> = (0..6u32)
+            .map(|chunk| {
+                (0..4)
+                    .map(|row| ((chunk * 4 + row) as u16 % 3, chunk * 4 + row))
+                    .collect()
+            })
+            .collect();
+        let orders = permutations((0..6).collect());
+        assert_eq!(orders.len(), 720);
+        for order in orders {
+            let mut sequencer = Sequencer::default();
+            let mut applied = vec![];
+            for index in order {
+                sequencer.insert(index, chunks[index as usize].clone());
+                while let Some(chunk) = sequencer.pop() {
+                    applied.extend(chunk);
+                }
+            }
+            assert_eq!(sequencer.handed_back(), 6);
+            for client in 0..3 {
+                let tids: Vec<u32> = applied
+                    .iter()
+                    .filter(|(cid, _)| *cid == client)
+                    .map(|(_, tid)| *tid)
+                    .collect();
+                assert_eq!(tids.len(), 8);
+                assert!(tids.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", tids);
+            }
+        }
+    }
+
+    #[test]
+    fn carries_on_past_bad_rows() {
+        let mut input = INPUT.to_string();
+        for tid in 1..=40 {
+            input.push_str(&format!("deposit,1,{},1.0\n", tid));
+        }
+        input.push_str("refund,1,41,1.0\ndeposit,1,42,1.0\ndeposit,1\n");
+        let (txs, errors) = chunked(&input, 3);
+        assert_eq!(txs.len(), 41);
+        assert_eq!(txs[40].tid, TxId(42));
+        assert_eq!(
+            errors,
+            vec!["line 42: invalid type refund", "line 44: missing tx"]
+        );
+    }
+
+    #[test]
+    fn default_tuning() {
+        let options = Options::default();
+        let tuning = Tuning::of(&options, 4);
+        assert_eq!(tuning.channel_capacity, 8);
+        assert_eq!(tuning.batch_size, DEFAULT_BATCH_SIZE);
+        let options = Options {
+            channel_capacity: Some(1),
+            ..Options::default()
+        };
+        assert_eq!(Tuning::of(&options, 4).channel_capacity, 1);
+    }
+
+    #[test]
+    fn last_line_without_newline() {
+        let (txs, errors) = chunked("type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,1", 2);
+        assert!(errors.is_empty());
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[1].tx_type, TxType::Dispute);
+        assert_eq!(chunked(INPUT, 2).0, vec![]);
+    }
+}
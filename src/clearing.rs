@@ -0,0 +1,126 @@
+use crate::schema::Schema;
+use crate::BasicError;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+use txcli_model::{ClientId, Currency, TxId, TxRecord, TxType};
+
+// One row of the authorizations file.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Authorization {
+    pub tx_type: TxType,
+    pub cid: ClientId,
+    pub tid: TxId,
+    pub amount: Currency,
+    pub line: u64,
+}
+
+// Deposits and withdrawals authorized ahead of the clearing file, keyed by tid. A clearing only
+// goes through when it clears an authorization of the same tid, client and type, each
+// authorization at most once. The clearing's amount is the one applied, what was authorized can
+// differ from what settles. Disputes, resolves, chargebacks and voids aren't authorized, they
+// reference cleared transactions and go through as usual.
+pub struct Authorizations {
+    pending: HashMap<TxId, Authorization>,
+}
+
+impl Authorizations {
+    pub fn load<R: Read>(input: R) -> Result<Self, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(input);
+        let mut rows = Schema::detect(reader.headers()?).reader(reader.headers()?)?;
+        let mut pending = HashMap::new();
+        while rows.read(&mut reader)? {
+            let line = rows.line();
+            let tx = rows.parse()?;
+            if !matches!(tx.tx_type, TxType::Deposit | TxType::Withdrawal) {
+                return Err(BasicError::new(format!(
+                    "line {} of the authorizations file: only deposits and withdrawals are authorized, not {}.",
+                    line, tx.tx_type
+                )));
+            }
+            let authorization = Authorization {
+                tx_type: tx.tx_type,
+                cid: tx.cid,
+                tid: tx.tid,
+                amount: tx.amount,
+                line,
+            };
+            if let Some(earlier) = pending.insert(tx.tid, authorization) {
+                return Err(BasicError::new(format!(
+                    "line {} of the authorizations file: tid[{}] was already authorized on line {}.",
+                    line, tx.tid.0, earlier.line
+                )));
+            }
+        }
+        Ok(Authorizations { pending })
+    }
+
+    // Whether tx may be applied, consuming the authorization it clears.
+    pub fn clear(&mut self, tx: &TxRecord) -> bool {
+        if !matches!(tx.tx_type, TxType::Deposit | TxType::Withdrawal) {
+            return true;
+        }
+        match self.pending.get(&tx.tid) {
+            Some(authorization)
+                if authorization.cid == tx.cid && authorization.tx_type == tx.tx_type =>
+            {
+                self.pending.remove(&tx.tid);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Authorizations nothing cleared, in the order of the authorizations file.
+    pub fn unmatched(self) -> Vec<Authorization> {
+        let mut unmatched: Vec<_> = self.pending.into_values().collect();
+        unmatched.sort_unstable_by_key(|authorization| authorization.line);
+        unmatched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clearings_match_authorizations_once() {
+        let mut authorizations = Authorizations::load(
+            "type,client,tx,amount\n\
+            deposit,1,1,10.0\n\
+            withdrawal,1,2,4.0\n\
+            deposit,2,3,5.0\n\
+            deposit,3,4,1.0\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let tx = |tx_type, cid, tid, amount: f64| {
+            TxRecord::new(tx_type, cid, tid, Currency::from_num(amount))
+        };
+        // Cleared for another amount than authorized.
+        assert!(authorizations.clear(&tx(TxType::Deposit, 1, 1, 9.5)));
+        assert!(!authorizations.clear(&tx(TxType::Deposit, 1, 1, 9.5)));
+        assert!(authorizations.clear(&tx(TxType::Withdrawal, 1, 2, 4.0)));
+        // Another client, another type, or no authorization at all.
+        assert!(!authorizations.clear(&tx(TxType::Deposit, 1, 3, 5.0)));
+        assert!(!authorizations.clear(&tx(TxType::Withdrawal, 3, 4, 1.0)));
+        assert!(!authorizations.clear(&tx(TxType::Deposit, 1, 9, 1.0)));
+        assert!(authorizations.clear(&tx(TxType::Dispute, 1, 1, 0.0)));
+        let unmatched: Vec<_> = authorizations
+            .unmatched()
+            .iter()
+            .map(|authorization| (authorization.tid, authorization.line))
+            .collect();
+        assert_eq!(unmatched, [(TxId(3), 4), (TxId(4), 5)]);
+
+        assert!(Authorizations::load("type,client,tx,amount\ndispute,1,1,\n".as_bytes()).is_err());
+        assert!(Authorizations::load(
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,1,1.0\n".as_bytes()
+        )
+        .is_err());
+    }
+}
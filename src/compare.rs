@@ -0,0 +1,181 @@
+use crate::BasicError;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::io::Write;
+use std::process;
+
+// Stands in for a client, column or value one of the two outputs doesn't have.
+const MISSING: &str = "missing";
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Difference {
+    client: String,
+    field: String,
+    old: String,
+    new: String,
+}
+
+// One version's balances, rows keyed by client, and account when written per account.
+struct Balances {
+    headers: Vec<String>,
+    rows: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Balances {
+    fn parse(output: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(output);
+        let headers: Vec<String> = reader.headers()?.iter().map(String::from).collect();
+        if !headers.iter().any(|header| header == "client") {
+            return Err(BasicError::new("Output has no client column."));
+        }
+        let mut rows = BTreeMap::new();
+        for record in reader.records() {
+            let row: BTreeMap<String, String> = headers
+                .iter()
+                .cloned()
+                .zip(record?.iter().map(String::from))
+                .collect();
+            let key = match row.get("account") {
+                Some(account) => format!("{}/{}", row["client"], account),
+                None => row["client"].clone(),
+            };
+            rows.insert(key, row);
+        }
+        Ok(Balances { headers, rows })
+    }
+}
+
+// Runs one txcli binary over the input, returning the balances it writes to stdout. What it
+// prints to stderr is only shown when it fails.
+pub fn run(binary: &str, input: &str, args: &[String]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let output = process::Command::new(binary)
+        .args(args)
+        .arg(input)
+        .output()
+        .map_err(|err| BasicError::new(format!("Could not run {}: {}", binary, err)))?;
+    if !output.status.success() {
+        return Err(BasicError::new(format!(
+            "{} failed with {}: {}",
+            binary,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+// Field by field differences between the balances from the old and the new version, matched by
+// client rather than by line since neither orders its rows. Columns only one version writes are
+// reported once with client "all", not on every row.
+pub fn compare(old: &[u8], new: &[u8]) -> Result<Vec<Difference>, Box<dyn Error>> {
+    let (old, new) = (Balances::parse(old)?, Balances::parse(new)?);
+    let mut differences = vec![];
+    let difference = |client: &str, field: &str, old: Option<&String>, new: Option<&String>| {
+        let value = |value: Option<&String>| value.map_or(MISSING.to_string(), String::clone);
+        Difference {
+            client: client.to_string(),
+            field: field.to_string(),
+            old: value(old),
+            new: value(new),
+        }
+    };
+    let present = "present".to_string();
+    let columns: BTreeSet<&String> = old.headers.iter().chain(&new.headers).collect();
+    for column in columns {
+        let (in_old, in_new) = (old.headers.contains(column), new.headers.contains(column));
+        if in_old != in_new {
+            differences.push(difference(
+                "all",
+                column,
+                in_old.then_some(&present),
+                in_new.then_some(&present),
+            ));
+        }
+    }
+    let shared: Vec<&String> = old
+        .headers
+        .iter()
+        .filter(|header| new.headers.contains(header))
+        .collect();
+    let clients: BTreeSet<&String> = old.rows.keys().chain(new.rows.keys()).collect();
+    for client in clients {
+        match (old.rows.get(client), new.rows.get(client)) {
+            (Some(old), Some(new)) => {
+                for field in &shared {
+                    if old.get(*field) != new.get(*field) {
+                        differences.push(difference(
+                            client,
+                            field,
+                            old.get(*field),
+                            new.get(*field),
+                        ));
+                    }
+                }
+            }
+            (old, new) => differences.push(difference(
+                client,
+                "client",
+                old.map(|_| &present),
+                new.map(|_| &present),
+            )),
+        }
+    }
+    Ok(differences)
+}
+
+pub fn write<W: Write>(differences: &[Difference], writer: W) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for difference in differences {
+        writer.serialize(difference)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_outputs_in_any_order() {
+        let old = b"client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n2,0.0000,0.0000,0.0000,true\n";
+        let new = b"client,available,held,total,locked\n2,0.0000,0.0000,0.0000,true\n1,1.0000,0.0000,1.0000,false\n";
+        assert!(compare(old, new).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_each_difference() {
+        let old = b"client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n2,5.0000,0.0000,5.0000,false\n";
+        let new = b"client,available,held,total,locked,penalties\n1,0.5000,0.0000,0.5000,false,0.0000\n3,1.0000,0.0000,1.0000,false,0.0000\n";
+        let mut out = vec![];
+        write(&compare(old, new).unwrap(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,field,old,new\n\
+             all,penalties,missing,present\n\
+             1,available,1.0000,0.5000\n\
+             1,total,1.0000,0.5000\n\
+             2,client,present,missing\n\
+             3,client,missing,present\n"
+        );
+    }
+
+    #[test]
+    fn runs_binaries() {
+        let dir = std::env::temp_dir().join(format!("txcli-compare-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        std::fs::write(&input, "client\n1\n").unwrap();
+        let input = input.to_str().unwrap();
+        assert_eq!(run("cat", input, &[]).unwrap(), b"client\n1\n");
+        let err = run("false", input, &[]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("false failed with exit status: 1"));
+        assert!(run("./no-such-txcli", input, &[]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use crate::gating::Gating;
+use crate::options::{Command, Options};
+use crate::policy;
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Write;
+
+// txcli config effective <the command line of a run> prints every option that run would apply,
+// one per line, with where its value came from:
+//
+// overdraft_rate = "0.02"        # command line, over policy file policy.toml
+// max_amount = "50"              # policy file policy.toml
+// locked_policy unset            # default, reject-all
+// strict = false                 # default
+//
+// The layers, lowest first, are the defaults, the --policy file and the command line. txcli reads
+// no settings from the environment, and txcli.toml beside the executable only gates what may run,
+// which is checked here as the run would. The run itself is never started.
+
+#[derive(Debug, PartialEq)]
+enum Source {
+    Default,
+    PolicyFile,
+    CommandLine { over_file: bool },
+}
+
+// Flags whose option is named differently.
+fn field_of(flag: &str) -> String {
+    match flag {
+        "--txs" => "split_txs".to_string(),
+        "--into" => "split_into".to_string(),
+        "--by" => "client_mod".to_string(),
+        "-o" => "out".to_string(),
+        // serve <addr> is --serve <addr>.
+        "serve" => "serve".to_string(),
+        _ => flag.trim_start_matches('-').replace('-', "_"),
+    }
+}
+
+// The options args set, flags by their option and positional arguments as what they were read as.
+fn given(args: &[String], options: &Options) -> HashSet<String> {
+    let mut fields: HashSet<String> = args
+        .iter()
+        .filter(|arg| arg.starts_with('-') || *arg == "serve")
+        .map(|arg| field_of(arg))
+        .collect();
+    if args
+        .first()
+        .is_some_and(|arg| Command::named(arg).is_some())
+    {
+        fields.insert("command".to_string());
+    }
+    if options.policy_action.is_some() {
+        fields.insert("policy_action".to_string());
+    }
+    if options.ctl.is_some() {
+        fields.insert("ctl".to_string());
+    }
+    if !options.input.is_empty() {
+        fields.insert("input".to_string());
+    }
+    if !options.more_inputs.is_empty() {
+        fields.insert("more_inputs".to_string());
+    }
+    fields
+}
+
+pub fn effective<W: Write>(args: &[String], mut writer: W) -> Result<(), Box<dyn Error>> {
+    let argv: Vec<String> = std::iter::once("txcli".to_string())
+        .chain(args.iter().cloned())
+        .collect();
+    Gating::load()?.check(&argv)?;
+    let options = Options::parse(argv)?;
+    let command_line = given(args, &options);
+    let file: HashSet<String> = match &options.policy {
+        Some(path) => policy::flags(path)?
+            .iter()
+            .filter(|arg| arg.starts_with("--"))
+            .map(|flag| field_of(flag))
+            .collect(),
+        None => HashSet::new(),
+    };
+    // What each policy key does left unset, as policy explain says it.
+    let unset: Vec<(String, &str)> = policy::settings(&options)
+        .into_iter()
+        .map(|(key, _, unset)| (field_of(key), unset))
+        .collect();
+
+    let serde_json::Value::Object(values) = serde_json::to_value(&options)? else {
+        unreachable!("options serialize to an object");
+    };
+    for (field, value) in values {
+        let source = if command_line.contains(&field) {
+            Source::CommandLine {
+                over_file: file.contains(&field),
+            }
+        } else if file.contains(&field) {
+            Source::PolicyFile
+        } else {
+            Source::Default
+        };
+        let setting = match value {
+            serde_json::Value::Null => format!("{} unset", field),
+            value => format!("{} = {}", field, value),
+        };
+        let source = match source {
+            Source::CommandLine { over_file: false } => "command line".to_string(),
+            Source::CommandLine { over_file: true } => format!(
+                "command line, over policy file {}",
+                options.policy.as_deref().unwrap_or_default()
+            ),
+            Source::PolicyFile => format!(
+                "policy file {}",
+                options.policy.as_deref().unwrap_or_default()
+            ),
+            Source::Default => match unset.iter().find(|(key, _)| *key == field) {
+                Some((_, unset)) if setting.ends_with(" unset") => format!("default, {}", unset),
+                _ => "default".to_string(),
+            },
+        };
+        writeln!(writer, "{:<30} # {}", setting, source)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn annotates_each_layer() {
+        let dir = std::env::temp_dir().join(format!("txcli-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let policy = dir.join("policy.toml");
+        fs::write(&policy, "overdraft-rate = 0.01\nmax-amount = \"50\"\n").unwrap();
+        let policy = policy.to_str().unwrap();
+        let args: Vec<String> = [
+            "in.csv",
+            "--policy",
+            policy,
+            "--overdraft-rate",
+            "0.02",
+            "--strict",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        let mut out = vec![];
+        effective(&args, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let line = |field: &str| {
+            out.lines()
+                .find(|line| line.starts_with(&format!("{} ", field)))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            line("overdraft_rate"),
+            format!(
+                "{:<30} # command line, over policy file {}",
+                "overdraft_rate = \"0.02\"", policy
+            )
+        );
+        assert!(line("max_amount").ends_with(&format!("# policy file {}", policy)));
+        assert!(line("input").ends_with("# command line"));
+        assert!(line("strict").starts_with("strict = true "));
+        assert!(line("command").ends_with("# default"));
+        assert!(line("locked_policy").ends_with("# default, reject-all"));
+        assert!(line("seed").starts_with("seed unset "));
+
+        // The command line is validated as the run would.
+        assert!(effective(&["--strict".to_string()], vec![]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+use crate::engine::FeePayer;
+use crate::{AppState, BasicError};
+use std::error::Error;
+use txcli_model::{ClientId, Currency, TxRecord, TxType};
+
+fn clients_total(state: &AppState) -> Currency {
+    state
+        .clients
+        .values()
+        .map(|client| client.available() + client.held() + client.pending())
+        .sum()
+}
+
+// What an applied row moves between the clients and the outside world, worked out from the row
+// alone so an engine bug can't hide in it. Positive flows to the world. Chargebacks and voids
+// act on the referenced transaction, so this is called before the row is applied.
+pub fn world_flow(state: &AppState, cid: ClientId, tx: &TxRecord) -> Currency {
+    let client = state.clients.get(&cid);
+    let shortfall = client.map_or(Currency::from_num(0), |client| client.shortfall(tx.tid));
+    match tx.tx_type {
+        TxType::Deposit => -tx.amount,
+        TxType::Withdrawal => tx.amount,
+        TxType::Transfer => Currency::from_num(0),
+        // A disputed deposit's funds are already the client's, a disputed withdrawal's are held
+        // for the client until it is resolved, when they go back, or charged back.
+        TxType::Dispute => match client.and_then(|client| client.history.get(&tx.tid)) {
+            Some(disputed) if disputed.tx_type == TxType::Withdrawal => -disputed.amount,
+            _ => Currency::from_num(0),
+        },
+        // Only what the dispute held moves, less its shortfall under DisputePolicy::PartialHold.
+        TxType::Resolve => match client.and_then(|client| client.disputed.get(&tx.tid)) {
+            Some(disputed) if disputed.tx_type == TxType::Withdrawal => disputed.amount - shortfall,
+            _ => Currency::from_num(0),
+        },
+        // Held funds of a deposit go back to the network, with the fee when the client pays it.
+        TxType::ChargeBack => {
+            let fee = match state.chargeback_fee {
+                Some(fee) if fee.payer == FeePayer::Client => fee.amount,
+                _ => Currency::from_num(0),
+            };
+            match client.and_then(|client| client.disputed.get(&tx.tid)) {
+                Some(disputed) if disputed.tx_type == TxType::Deposit => {
+                    disputed.amount - shortfall + fee
+                }
+                _ => fee,
+            }
+        }
+        TxType::Void => match client.and_then(|client| client.history.get(&tx.tid)) {
+            Some(voided) if voided.tx_type == TxType::Deposit => voided.amount,
+            Some(voided) => -voided.amount,
+            None => Currency::from_num(0),
+        },
+        // Only clients without funds are archived, notes move nothing.
+        TxType::ArchiveClient | TxType::ReactivateClient | TxType::Note => Currency::from_num(0),
+        // Only its handler knows, which is why --conservation-check refuses --tx-types.
+        TxType::Custom(_) => Currency::from_num(0),
+    }
+}
+
+// Tracks an external world account so the clients' totals plus the world is constant, zero by
+// construction, after every row. Opening balances come out of the world when this is created.
+#[derive(Clone)]
+pub struct Conservation {
+    world: Currency,
+}
+
+impl Conservation {
+    pub fn new(state: &AppState) -> Self {
+        Conservation {
+            world: -clients_total(state),
+        }
+    }
+
+    // flow is world_flow() of the row when it was applied, penalties what the engine charged
+    // at a cutoff during the row. tx is None for the cutoff closing the run. Sums every client,
+    // so it costs a pass over the state per row.
+    pub fn check(
+        &mut self,
+        state: &AppState,
+        tx: Option<&TxRecord>,
+        flow: Option<Currency>,
+        penalties: Currency,
+    ) -> Result<(), Box<dyn Error>> {
+        self.world += flow.unwrap_or_default() + penalties;
+        let clients = clients_total(state);
+        if clients + self.world != 0 {
+            let at = match tx {
+                Some(tx) => format!("{} tid[{}] of client[{}]", tx.tx_type, tx.tid.0, tx.cid.0),
+                None => "the closing cutoff".to_string(),
+            };
+            return Err(BasicError::new(format!(
+                "Conservation check failed at {}: clients total {:.4} and world {:.4} sum to {:.4} instead of 0.",
+                at,
+                clients,
+                self.world,
+                clients + self.world
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn world(&self) -> Currency {
+        self.world
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::DisputePolicy;
+    use crate::{execute_transaction, penalty};
+
+    fn run(
+        state: &mut AppState,
+        conservation: &mut Conservation,
+        tx: TxRecord,
+    ) -> Result<(), Box<dyn Error>> {
+        let flow = world_flow(state, tx.cid, &tx);
+        let applied = execute_transaction(state, tx).is_ok();
+        conservation.check(
+            state,
+            Some(&tx),
+            applied.then_some(flow),
+            Currency::from_num(0),
+        )
+    }
+
+    #[test]
+    fn conserved_through_lifecycle() {
+        let mut state = AppState::default();
+        let mut conservation = Conservation::new(&state);
+        let tx =
+            |tx_type, tid, amount: u32| TxRecord::new(tx_type, 1, tid, Currency::from_num(amount));
+        for row in [
+            tx(TxType::Deposit, 1, 10),
+            tx(TxType::Deposit, 2, 4),
+            tx(TxType::Withdrawal, 3, 3),
+            tx(TxType::Withdrawal, 4, 30),
+            tx(TxType::Void, 3, 0),
+            tx(TxType::Void, 2, 0),
+            tx(TxType::Dispute, 1, 0),
+            tx(TxType::Resolve, 1, 0),
+            tx(TxType::Dispute, 1, 0),
+            tx(TxType::ChargeBack, 1, 0),
+            tx(TxType::ChargeBack, 9, 0),
+        ] {
+            run(&mut state, &mut conservation, row).unwrap();
+        }
+        assert_eq!(conservation.world(), Currency::from_num(0));
+    }
+
+    #[test]
+    fn conserved_through_withdrawal_disputes() {
+        let mut state = AppState::default();
+        let mut conservation = Conservation::new(&state);
+        let tx =
+            |tx_type, tid, amount: u32| TxRecord::new(tx_type, 1, tid, Currency::from_num(amount));
+        for row in [
+            tx(TxType::Deposit, 1, 10),
+            tx(TxType::Withdrawal, 2, 4),
+            tx(TxType::Dispute, 2, 0),
+            tx(TxType::Resolve, 2, 0),
+            tx(TxType::Dispute, 2, 0),
+            tx(TxType::ChargeBack, 2, 0),
+        ] {
+            run(&mut state, &mut conservation, row).unwrap();
+        }
+        // The withdrawal came back from the world.
+        assert_eq!(conservation.world(), Currency::from_num(-10));
+    }
+
+    // A deposit mostly withdrawn before its dispute, which each policy holds differently.
+    #[test]
+    fn conserved_under_every_dispute_policy() {
+        for policy in [
+            DisputePolicy::AllowNegative,
+            DisputePolicy::Reject,
+            DisputePolicy::PartialHold,
+        ] {
+            let mut state = AppState {
+                dispute_policy: policy,
+                ..AppState::default()
+            };
+            let mut conservation = Conservation::new(&state);
+            let tx = |tx_type, tid, amount: u32| {
+                TxRecord::new(tx_type, 1, tid, Currency::from_num(amount))
+            };
+            for row in [
+                tx(TxType::Deposit, 1, 10),
+                tx(TxType::Withdrawal, 2, 8),
+                tx(TxType::Dispute, 1, 0),
+                tx(TxType::Resolve, 1, 0),
+                tx(TxType::Dispute, 2, 0),
+                tx(TxType::Resolve, 2, 0),
+                tx(TxType::Dispute, 1, 0),
+                tx(TxType::ChargeBack, 1, 0),
+                tx(TxType::Deposit, 3, 5),
+                tx(TxType::Dispute, 2, 0),
+                tx(TxType::ChargeBack, 2, 0),
+            ] {
+                run(&mut state, &mut conservation, row)
+                    .unwrap_or_else(|err| panic!("{:?}: {}", policy, err));
+            }
+        }
+    }
+
+    #[test]
+    fn penalties_leave_to_the_world() {
+        let mut state = AppState::default();
+        let mut conservation = Conservation::new(&state);
+        let tx =
+            |tx_type, tid, amount: u32| TxRecord::new(tx_type, 1, tid, Currency::from_num(amount));
+        run(&mut state, &mut conservation, tx(TxType::Deposit, 1, 10)).unwrap();
+        run(&mut state, &mut conservation, tx(TxType::Withdrawal, 2, 10)).unwrap();
+        let dispute = tx(TxType::Dispute, 1, 0);
+        run(&mut state, &mut conservation, dispute).unwrap();
+        let charged = penalty::assess(&mut state, Currency::from_num(0.5));
+        assert_eq!(charged, Currency::from_num(5));
+        conservation.check(&state, None, None, charged).unwrap();
+        assert_eq!(conservation.world(), Currency::from_num(5));
+    }
+
+    #[test]
+    fn reports_created_money() {
+        let mut state = AppState::default();
+        let mut conservation = Conservation::new(&state);
+        let deposit = TxRecord::new(TxType::Deposit, 3, 7, Currency::from_num(5));
+        run(&mut state, &mut conservation, deposit).unwrap();
+        // Money out of nowhere, as a buggy engine might do.
+        state
+            .clients
+            .get_mut(&ClientId(3))
+            .unwrap()
+            .account("default")
+            .held += Currency::from_num(1);
+        let err = conservation
+            .check(&state, Some(&deposit), None, Currency::from_num(0))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Conservation check failed at deposit tid[7] of client[3]: clients total 6.0000 and world -5.0000 sum to 1.0000 instead of 0."
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use crate::BasicError;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+
+#[cfg(all(unix, feature = "server"))]
+pub use listening::listen;
+
+// txcli ctl operates a --serve, --stdin or pipe process started with --control <socket> over that
+// unix socket, one request per connection answered with one line:
+//
+// pause              takes the engine and keeps it, every row waits until resume, queries too
+// resume             hands the engine back
+// status             {"state":"paused","stats":{...}}, stats as --serve's stats request gives them
+// snapshot <path>    writes the state there as --snapshot-out would, paused or not
+//
+// A paused process keeps its state in memory and leaves its input unread, so whatever feeds it
+// backs up instead of losing rows and downstream maintenance doesn't cost a restart. The row in
+// flight when pause comes is applied first.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Request {
+    Pause,
+    Resume,
+    Status,
+    Snapshot(String),
+}
+
+impl Request {
+    // The words after ctl, or a request line split at its first space.
+    pub fn parse(action: &str, argument: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        match (action, argument) {
+            ("pause", None) => Ok(Request::Pause),
+            ("resume", None) => Ok(Request::Resume),
+            ("status", None) => Ok(Request::Status),
+            ("snapshot", Some(path)) if !path.is_empty() => Ok(Request::Snapshot(path.to_string())),
+            ("snapshot", _) => Err(BasicError::new("snapshot requires a path to write to.")),
+            _ => Err(BasicError::new(format!(
+                "Unknown control request {}, expected pause, resume, status or snapshot <path>.",
+                action
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Request::Pause => write!(f, "pause"),
+            Request::Resume => write!(f, "resume"),
+            Request::Status => write!(f, "status"),
+            Request::Snapshot(path) => write!(f, "snapshot {}", path),
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "server"))]
+mod listening {
+    use super::Request;
+    use crate::engine::Engine;
+    use crate::server::Shared;
+    use crate::BasicError;
+    use std::error::Error;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::sync::{Mutex, MutexGuard};
+    use std::thread;
+
+    // Removes the socket once the process is done with it.
+    pub struct Listening {
+        path: String,
+    }
+
+    impl Drop for Listening {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn lock(engine: &Mutex<Engine>) -> MutexGuard<'_, Engine> {
+        engine
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Pausing is holding the engine's lock, which every row and query takes, until resume.
+    fn answer<'a>(
+        engine: &'a Mutex<Engine>,
+        held: &mut Option<MutexGuard<'a, Engine>>,
+        request: Request,
+    ) -> Result<String, Box<dyn Error>> {
+        let paused = held.is_some();
+        match request {
+            Request::Pause => {
+                if !paused {
+                    *held = Some(lock(engine));
+                }
+                Ok("paused".to_string())
+            }
+            Request::Resume => {
+                *held = None;
+                Ok("resumed".to_string())
+            }
+            Request::Status => {
+                let stats = match held {
+                    Some(engine) => engine.report().stats,
+                    None => lock(engine).report().stats,
+                };
+                Ok(serde_json::json!({
+                    "state": if paused { "paused" } else { "running" },
+                    "stats": stats,
+                })
+                .to_string())
+            }
+            Request::Snapshot(path) => {
+                let snapshot = match held {
+                    Some(engine) => engine.snapshot(),
+                    None => lock(engine).snapshot(),
+                };
+                snapshot.write(&path)?;
+                Ok(format!("written {}", path))
+            }
+        }
+    }
+
+    fn session<'a>(
+        engine: &'a Mutex<Engine>,
+        held: &mut Option<MutexGuard<'a, Engine>>,
+        stream: &UnixStream,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        let line = line.trim();
+        let (action, argument) = match line.split_once(' ') {
+            Some((action, argument)) => (action, Some(argument)),
+            None => (line, None),
+        };
+        answer(engine, held, Request::parse(action, argument)?)
+    }
+
+    // Serves requests on their own thread until the process ends. A socket left behind by a
+    // process that is gone is replaced, one still answering is refused.
+    pub fn listen(path: &str, engine: Shared) -> Result<Listening, Box<dyn Error>> {
+        if Path::new(path).exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(BasicError::new(format!(
+                    "--control {} is in use by another process.",
+                    path
+                )));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        thread::spawn(move || {
+            let mut held = None;
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let reply = match session(&engine, &mut held, &stream) {
+                    Ok(reply) => reply,
+                    Err(err) => format!("error {}", err),
+                };
+                // The requester may be gone already, which changes nothing here.
+                let _ = writeln!(stream, "{}", reply);
+            }
+        });
+        Ok(Listening {
+            path: path.to_string(),
+        })
+    }
+}
+
+#[cfg(all(not(unix), feature = "server"))]
+pub fn listen(_path: &str, _engine: crate::server::Shared) -> Result<(), Box<dyn Error>> {
+    Err(BasicError::new("--control is only supported on unix."))
+}
+
+// Sends request to the process listening on path and returns its reply.
+#[cfg(unix)]
+pub fn send(path: &str, request: &Request) -> Result<String, Box<dyn Error>> {
+    use std::io::{Read, Write};
+    use std::net::Shutdown;
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path)
+        .map_err(|err| BasicError::new(format!("Could not reach {}: {}", path, err)))?;
+    writeln!(stream, "{}", request)?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    match reply.strip_prefix("error ") {
+        Some(err) => Err(BasicError::new(err.trim())),
+        None => Ok(reply),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn send(_path: &str, _request: &Request) -> Result<String, Box<dyn Error>> {
+    Err(BasicError::new("txcli ctl is only supported on unix."))
+}
+
+#[cfg(all(test, unix, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use txcli_model::{Currency, TxRecord, TxType};
+
+    #[test]
+    fn pause_status_snapshot_resume() {
+        let dir = std::env::temp_dir().join(format!("txcli-control-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("ctl.sock");
+        let socket = socket.to_str().unwrap();
+        let engine: crate::server::Shared = Arc::new(Mutex::new(EngineBuilder::new().build()));
+        let listening = listen(socket, Arc::clone(&engine)).unwrap();
+        // The socket answers, so a second process can't take it over.
+        assert!(listen(socket, Arc::clone(&engine)).is_err());
+
+        engine
+            .lock()
+            .unwrap()
+            .apply(TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(2)));
+        assert_eq!(send(socket, &Request::Pause).unwrap(), "paused\n");
+        // Rows wait for resume.
+        let feeder = {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || {
+                engine.lock().unwrap().apply(TxRecord::new(
+                    TxType::Deposit,
+                    1,
+                    2,
+                    Currency::from_num(3),
+                ));
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        let status: serde_json::Value =
+            serde_json::from_str(&send(socket, &Request::Status).unwrap()).unwrap();
+        assert_eq!(status["state"], "paused");
+        assert_eq!(status["stats"]["applied"], 1);
+
+        let snapshot = dir.join("state.json");
+        let snapshot = snapshot.to_str().unwrap().to_string();
+        send(socket, &Request::Snapshot(snapshot.clone())).unwrap();
+        let mut restored = EngineBuilder::new().build();
+        restored.restore(crate::snapshot::Snapshot::read(&snapshot).unwrap());
+        assert_eq!(
+            restored
+                .balance(txcli_model::ClientId(1))
+                .unwrap()
+                .available,
+            Currency::from_num(2)
+        );
+
+        assert_eq!(send(socket, &Request::Resume).unwrap(), "resumed\n");
+        feeder.join().unwrap();
+        let status: serde_json::Value =
+            serde_json::from_str(&send(socket, &Request::Status).unwrap()).unwrap();
+        assert_eq!(status["state"], "running");
+        assert_eq!(status["stats"]["applied"], 2);
+
+        drop(listening);
+        assert!(!std::path::Path::new(socket).exists());
+        assert!(send(socket, &Request::Status).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn requests() {
+        assert_eq!(Request::parse("pause", None).unwrap(), Request::Pause);
+        assert_eq!(
+            Request::parse("snapshot", Some("s.json"))
+                .unwrap()
+                .to_string(),
+            "snapshot s.json"
+        );
+        assert!(Request::parse("snapshot", None).is_err());
+        assert!(Request::parse("status", Some("now")).is_err());
+        assert!(Request::parse("stop", None).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::chunked::{ChunkedRows, Tuning};
+use crate::determinism::state_hash;
+use crate::engine::{Engine, EngineBuilder};
+use crate::partition::Partition;
+use crate::sample::SplitMix64;
+use crate::schema::Schema;
+use crate::shard::Shards;
+use crate::BasicError;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{Cursor, Write};
+use txcli_model::{ClientId, Currency, Tx};
+
+pub const DEFAULT_REPLICAS: usize = 3;
+// Enough rows that every shard and chunk of every replica gets a share of each row type.
+const ROWS: u32 = 20_000;
+const CLIENTS: u64 = 60;
+
+// How one replica applies the workload. Replica 0 is always the plain sequential run the others
+// have to agree with.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    Sequential,
+    // --threads, see shard::Shards.
+    Sharded { shards: usize },
+    // --parse-threads with --batch-size, see chunked::ChunkedRows.
+    Chunked { threads: usize, batch_size: usize },
+    // split into shard inputs each run on its own, as on several machines, and the clients
+    // gathered after.
+    Partitioned { shards: usize },
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Sequential => write!(f, "sequential"),
+            Mode::Sharded { shards } => write!(f, "{} shards", shards),
+            Mode::Chunked {
+                threads,
+                batch_size,
+            } => write!(f, "{} parse threads, batches of {}", threads, batch_size),
+            Mode::Partitioned { shards } => write!(f, "{} partitions", shards),
+        }
+    }
+}
+
+// Every mode in turn after the sequential one, with counts and sizes drawn from the seed, so
+// different seeds cover different ways of cutting the same rows.
+fn modes(replicas: usize, rng: &mut SplitMix64) -> Vec<Mode> {
+    let mut modes = vec![Mode::Sequential];
+    for replica in 1..replicas {
+        let count = 2 + (rng.next() % 7) as usize;
+        modes.push(match replica % 3 {
+            1 => Mode::Sharded { shards: count },
+            2 => Mode::Chunked {
+                threads: count,
+                batch_size: 1 + (rng.next() % 2000) as usize,
+            },
+            _ => Mode::Partitioned { shards: count },
+        });
+    }
+    modes
+}
+
+// Every row type over CLIENTS clients, amounts of up to 6 decimals, and disputes, resolves,
+// chargebacks and voids of random earlier ids, so plenty of rows are rejected on the way.
+fn workload(rng: &mut SplitMix64) -> String {
+    let mut csv = String::from("type,client,tx,amount\n");
+    for tid in 1..=ROWS {
+        let cid = rng.next() % CLIENTS;
+        let earlier = 1 + rng.next() % tid as u64;
+        let micros = rng.next() % 100_000_000;
+        let amount = format!("{}.{:06}", micros / 1_000_000, micros % 1_000_000);
+        let row = match rng.next() % 10 {
+            0..=3 => format!("deposit,{},{},{}", cid, tid, amount),
+            4..=5 => format!("withdrawal,{},{},{}", cid, tid, amount),
+            6 => format!("dispute,{},{},", cid, earlier),
+            7 => format!("resolve,{},{},", cid, earlier),
+            8 => format!("chargeback,{},{},", cid, earlier),
+            _ => format!("void,{},{},", cid, earlier),
+        };
+        csv += &row;
+        csv.push('\n');
+    }
+    csv
+}
+
+fn replay(
+    mode: Mode,
+    builder: &EngineBuilder,
+    csv: &str,
+    txs: &[Tx],
+) -> Result<Engine, Box<dyn Error>> {
+    let engine = match mode {
+        Mode::Sequential => {
+            let mut engine = builder.clone().build();
+            for tx in txs {
+                engine.apply(tx.as_record());
+            }
+            engine.finish();
+            engine
+        }
+        Mode::Sharded { shards } => {
+            let mut sharded = Shards::new(builder, shards);
+            for tx in txs {
+                sharded.push(tx.clone())?;
+            }
+            sharded.finish()?
+        }
+        Mode::Chunked {
+            threads,
+            batch_size,
+        } => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .flexible(true)
+                .from_reader(csv.as_bytes());
+            let rows = Schema::detect(reader.headers()?).reader(reader.headers()?)?;
+            let position = reader.position().clone();
+            let input = Cursor::new(&csv.as_bytes()[position.byte() as usize..]);
+            let tuning = Tuning {
+                threads,
+                read_buffer: 4096,
+                batch_size,
+                channel_capacity: 2 * threads,
+            };
+            let mut chunks = ChunkedRows::new(input, position, rows, tuning)?;
+            let mut engine = builder.clone().build();
+            while let Some(chunk) = chunks.next() {
+                for row in chunk?.rows {
+                    let (tx, _) = row.map_err(BasicError::new)?;
+                    engine.apply(tx.as_record());
+                }
+            }
+            engine.finish();
+            engine
+        }
+        Mode::Partitioned { shards } => {
+            let mut engines: Vec<Engine> = (0..shards).map(|_| builder.clone().build()).collect();
+            let partition = Partition::new(shards, engines[0].config().joint_owners.clone());
+            for tx in txs {
+                engines[partition.shard(tx.cid)].apply(tx.as_record());
+            }
+            let mut engines = engines.into_iter().map(|mut engine| {
+                engine.finish();
+                engine
+            });
+            let mut engine = engines.next().expect("at least one partition");
+            for other in engines {
+                engine.absorb(other);
+            }
+            engine
+        }
+    };
+    Ok(engine)
+}
+
+// Feeds the workload drawn from seed to replicas engines, one line per replica with the state
+// hash it ends on, and fails naming the first replica that disagrees with the sequential one.
+// Engines run with joint owners and an overdraft rate, so the modes have to route co-owned rows
+// and close the run the same way too.
+pub fn run<W: Write>(replicas: usize, seed: u64, mut writer: W) -> Result<(), Box<dyn Error>> {
+    let mut rng = SplitMix64(seed);
+    let csv = workload(&mut rng);
+    let modes = modes(replicas, &mut rng);
+    let builder = EngineBuilder::new()
+        .joint_owners(HashMap::from([
+            (ClientId(11), ClientId(3)),
+            (ClientId(29), ClientId(3)),
+            (ClientId(42), ClientId(17)),
+        ]))
+        .overdraft_rate(Currency::from_num(0.01));
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let mut rows = Schema::detect(reader.headers()?).reader(reader.headers()?)?;
+    let mut txs = vec![];
+    while let Some(row) = rows.next(&mut reader) {
+        txs.push(row?.to_tx());
+    }
+
+    let mut expected: Option<String> = None;
+    for (replica, mode) in modes.into_iter().enumerate() {
+        let hash = state_hash(&replay(mode, &builder, &csv, &txs)?);
+        writeln!(writer, "replica {} {} {}", replica, mode, hash)?;
+        match &expected {
+            None => expected = Some(hash),
+            Some(expected) if *expected != hash => {
+                return Err(BasicError::new(format!(
+                    "Replica {} ({}) ends on state hash {}, the sequential replica on {}, with --seed {}.",
+                    replica, mode, hash, expected, seed
+                )))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_mode_converges() {
+        let mut out = vec![];
+        run(4, 9, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("replica 0 sequential "));
+        assert!(lines[3].contains(" partitions "));
+        let hash = |line: &str| line.rsplit(' ').next().unwrap().to_string();
+        assert!(lines.iter().all(|line| hash(line) == hash(lines[0])));
+    }
+
+    #[test]
+    fn modes_follow_the_seed() {
+        let modes = |seed| modes(7, &mut SplitMix64(seed));
+        assert_eq!(modes(1), modes(1));
+        assert_ne!(modes(1), modes(2));
+        assert_eq!(modes(1)[0], Mode::Sequential);
+        assert!(matches!(modes(1)[1], Mode::Sharded { .. }));
+        assert!(matches!(modes(1)[5], Mode::Chunked { .. }));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::BasicError;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Debug;
+use std::fs;
+use std::sync::Arc;
+use txcli_model::{deserialize_amount, ClientOutputState, Currency, TxRecord};
+
+// What a custom row does to the account it names, added to its balances as is.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Effect {
+    pub available: Currency,
+    pub held: Currency,
+    // Locks the whole client, never unlocks it.
+    pub lock: bool,
+}
+
+// Applies a transaction type registered with EngineBuilder::custom_type. The handler decides and
+// the engine moves the funds, so a handler can't leave the state half updated. Custom rows never
+// enter the dispute lifecycle: they can't be disputed or voided, and their tid isn't checked
+// against earlier rows.
+pub trait CustomTxHandler: Debug + Send + Sync {
+    // client is the row's client as it stands, for a new client all zero. None refuses the row.
+    fn effect(&self, tx: &TxRecord, client: &ClientOutputState) -> Option<Effect>;
+}
+
+// Types file format, one table per type:
+//
+// [cashback]
+// available = "1"    # multiples of the row's amount added to available and held, default 0
+// held = "0"
+// lock = false       # whether the row locks the client
+// overdraft = false  # whether the row may take available below zero, refused otherwise
+//
+// e.g. a fee is available = "-1", a hold placed by a card network available = "-1", held = "1".
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Linear {
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    available: Currency,
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    held: Currency,
+    #[serde(default)]
+    lock: bool,
+    #[serde(default)]
+    overdraft: bool,
+}
+
+impl CustomTxHandler for Linear {
+    fn effect(&self, tx: &TxRecord, client: &ClientOutputState) -> Option<Effect> {
+        let effect = Effect {
+            available: self.available * tx.amount,
+            held: self.held * tx.amount,
+            lock: self.lock,
+        };
+        // Against the client's available across accounts, a custom row can't see per account
+        // balances.
+        if !self.overdraft && effect.available < 0 && client.available + effect.available < 0 {
+            return None;
+        }
+        Some(effect)
+    }
+}
+
+// Handlers by type name, in name order.
+pub type Handlers = Vec<(String, Arc<dyn CustomTxHandler>)>;
+
+pub fn load(path: &str) -> Result<Handlers, Box<dyn Error>> {
+    parse(&fs::read_to_string(path)?)
+}
+
+fn parse(contents: &str) -> Result<Handlers, Box<dyn Error>> {
+    let types: BTreeMap<String, Linear> = toml::from_str(contents)
+        .map_err(|err| BasicError::new(format!("Invalid transaction types file: {}", err)))?;
+    Ok(types
+        .into_iter()
+        .map(|(name, linear)| (name, Arc::new(linear) as Arc<dyn CustomTxHandler>))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use txcli_model::{register_custom_type, ClientId, TxType};
+
+    #[test]
+    fn linear_effects() {
+        let types = parse(
+            "[fee]\navailable = \"-1\"\n\n[card_hold]\navailable = \"-1\"\nheld = \"1\"\noverdraft = true\n",
+        )
+        .unwrap();
+        let names: Vec<&str> = types.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["card_hold", "fee"]);
+        let client = |available: u32| ClientOutputState {
+            cid: ClientId(1),
+            currency: None,
+            account: None,
+            available: Currency::from_num(available),
+            held: Currency::from_num(0),
+            total: Currency::from_num(available),
+            locked: false,
+            owners: None,
+            penalties: None,
+            chargeback_fees: None,
+            written_off: None,
+            dispute_count: None,
+            pending: None,
+        };
+        let tx = TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(5));
+        let (hold, fee) = (&types[0].1, &types[1].1);
+        assert_eq!(fee.effect(&tx, &client(4)), None);
+        assert_eq!(
+            fee.effect(&tx, &client(5)).unwrap().available,
+            Currency::from_num(-5)
+        );
+        let effect = hold.effect(&tx, &client(0)).unwrap();
+        assert_eq!(
+            (effect.available, effect.held),
+            (Currency::from_num(-5), Currency::from_num(5))
+        );
+        assert!(parse("[fee]\nrate = \"1\"\n").is_err());
+    }
+
+    #[test]
+    fn engine_applies_custom_rows() {
+        let (_, bonus) = parse("[signup_bonus]\navailable = \"1\"\nlock = true\n")
+            .unwrap()
+            .remove(0);
+        let mut engine = EngineBuilder::new()
+            .custom_type("signup_bonus", bonus)
+            .unwrap()
+            .build();
+        let bonus = TxRecord::new(
+            register_custom_type("signup_bonus").unwrap(),
+            3,
+            1,
+            Currency::from_num(20),
+        );
+        assert!(engine.apply(bonus));
+        // Not recorded, so there is nothing to dispute.
+        assert!(!engine.apply(TxRecord::new(TxType::Dispute, 3, 1, Currency::from_num(0))));
+        let unhandled = TxRecord::new(
+            register_custom_type("no_handler").unwrap(),
+            3,
+            2,
+            Currency::from_num(1),
+        );
+        assert!(!engine.apply(unhandled));
+        let report = engine.report();
+        assert_eq!(report.clients[0].available, Currency::from_num(20));
+        assert!(report.clients[0].locked);
+        assert_eq!(report.rejects["no_handler"], 1);
+    }
+}
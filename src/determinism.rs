@@ -0,0 +1,128 @@
+use crate::engine::{Engine, EngineBuilder};
+use crate::schema::Schema;
+use crate::BasicError;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt::Write;
+use txcli_model::CURRENCY_FORMAT;
+
+// Deposits, withdrawals, transfers, disputes, resolves, voids and a few chargebacks across 40
+// clients and their accounts, with amounts of up to 9 decimals so most of them are rounded, and
+// a few rows every engine has to reject. Built into the binary, the check needs nothing else.
+const REFERENCE: &str = include_str!("../tests/reference.csv");
+
+// The reference workload's state hash per target architecture and currency format. The engine
+// only does integer arithmetic on the fixed point bits, so the hash is the same everywhere and
+// only the format changes it. Each architecture still has its own entry, so a new platform is
+// added deliberately and a mismatch names the platform that broke the promise. Only hashes
+// generated on the architecture itself go in, x86_64 so far. CI runs the check on aarch64 as
+// well, which fails until the hash it prints there is added here.
+const EXPECTED: [(&str, &str, &str); 3] = [
+    (
+        "x86_64",
+        "I50F14",
+        "45dd03e113483dacdbc6d6f16bcf64fadf04b9adda8e0c2217bbb41ae0503bc7",
+    ),
+    (
+        "x86_64",
+        "I64F64",
+        "359374764cb0646a4800ca93007db7d9e222e4d69ea8f7f2109061a13090ac03",
+    ),
+    (
+        "x86_64",
+        "I34F30",
+        "6ff4afcc689bc7bd2868841a1d4ed0337672e07be66876c33a186dd2b1d07f7c",
+    ),
+];
+
+// Over the exact bits of every client row, the printed balances round to 4 decimals and could
+// hide a difference in the last bits. Runs with several currencies add each row's, so two
+// assets' balances can't swap unnoticed, without changing the hash of inputs naming one or none.
+pub fn state_hash(engine: &Engine) -> String {
+    let mut rows = String::new();
+    for row in engine.output_states() {
+        let account = row.account.as_ref().map_or("", |account| &account.0);
+        let _ = write!(
+            rows,
+            "{},{},{},{},{},{}",
+            row.cid.0,
+            account,
+            row.available.to_bits(),
+            row.held.to_bits(),
+            row.total.to_bits(),
+            row.locked
+        );
+        if let Some(currency) = &row.currency {
+            let _ = write!(rows, ",{}", currency);
+        }
+        rows.push('\n');
+    }
+    Sha256::digest(rows.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Applies the reference workload on a default engine, the way plain processing would.
+pub fn reference_hash() -> Result<String, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(REFERENCE.as_bytes());
+    let mut rows = Schema::detect(reader.headers()?).reader(reader.headers()?)?;
+    let mut engine = EngineBuilder::new().build();
+    while let Some(row) = rows.next(&mut reader) {
+        engine.apply(row?);
+    }
+    engine.finish();
+    Ok(state_hash(&engine))
+}
+
+// Exits with an error unless this build hashes the reference workload to the committed hash.
+pub fn check() -> Result<(), Box<dyn Error>> {
+    let hash = reference_hash()?;
+    let arch = std::env::consts::ARCH;
+    let expected = EXPECTED
+        .iter()
+        .find(|(target, format, _)| *target == arch && *format == CURRENCY_FORMAT)
+        .map(|(_, _, expected)| *expected);
+    match expected {
+        Some(expected) if expected == hash => {
+            println!("{} {} {}", arch, CURRENCY_FORMAT, hash);
+            Ok(())
+        }
+        Some(expected) => Err(BasicError::new(format!(
+            "The reference workload hashes to {} on {} {}, expected {}.",
+            hash, arch, CURRENCY_FORMAT, expected
+        ))),
+        None => Err(BasicError::new(format!(
+            "No expected hash for {} {}, the reference workload hashes to {}.",
+            arch, CURRENCY_FORMAT, hash
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::{Currency, TxRecord, TxType};
+
+    fn hash(deposits: &[&'static str]) -> String {
+        let mut engine = EngineBuilder::new().build();
+        for (tid, currency) in (1..).zip(deposits) {
+            engine.apply(TxRecord {
+                currency: Some(currency),
+                ..TxRecord::new(TxType::Deposit, 1, tid, Currency::from_num(tid))
+            });
+        }
+        engine.finish();
+        state_hash(&engine)
+    }
+
+    #[test]
+    fn hashes_each_rows_currency() {
+        assert_eq!(hash(&["BTC", "USD"]), hash(&["BTC", "USD"]));
+        assert_ne!(hash(&["BTC", "USD"]), hash(&["BTC", "EUR"]));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::i18n::Msg;
+use crate::rejection::RejectionReason;
+use serde::{Deserialize, Serialize};
+use txcli_model::TxType;
+
+// Where one transaction id stands for one client. Derived from the client's state rather than
+// stored per transaction, see ClientState::dispute_state.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    // Never applied for this client.
+    Unknown,
+    // An applied deposit or withdrawal that was never disputed.
+    Settled,
+    // An applied transfer between the client's own accounts, which can't be disputed.
+    Internal,
+    // An applied note, kept for its memo, nothing can reference it.
+    Noted,
+    Disputed,
+    // Disputed then resolved, it can be disputed again but no longer voided.
+    Resolved,
+    ChargedBack,
+    Voided,
+    // Pruned under --retain-history last-<n>, it is past the dispute window.
+    Expired,
+}
+
+// Every accepted transition, anything not listed is rejected. Rows moving funds can still be
+// rejected by the engine for lack of funds, leaving the transaction Unknown.
+#[rustfmt::skip]
+pub const TRANSITIONS: [(DisputeState, TxType, DisputeState); 9] = [
+    (DisputeState::Unknown,  TxType::Deposit,    DisputeState::Settled),
+    (DisputeState::Unknown,  TxType::Withdrawal, DisputeState::Settled),
+    (DisputeState::Unknown,  TxType::Transfer,   DisputeState::Internal),
+    (DisputeState::Unknown,  TxType::Note,       DisputeState::Noted),
+    (DisputeState::Settled,  TxType::Dispute,    DisputeState::Disputed),
+    (DisputeState::Settled,  TxType::Void,       DisputeState::Voided),
+    (DisputeState::Disputed, TxType::Resolve,    DisputeState::Resolved),
+    (DisputeState::Disputed, TxType::ChargeBack, DisputeState::ChargedBack),
+    (DisputeState::Resolved, TxType::Dispute,    DisputeState::Disputed),
+];
+
+pub fn next(state: DisputeState, tx_type: TxType) -> Option<DisputeState> {
+    TRANSITIONS
+        .iter()
+        .find(|(from, on, _)| *from == state && *on == tx_type)
+        .map(|(_, _, to)| *to)
+}
+
+// Why a row without a transition is rejected.
+pub fn rejection(state: DisputeState, tx_type: TxType) -> RejectionReason {
+    let new_id = matches!(
+        tx_type,
+        TxType::Deposit | TxType::Withdrawal | TxType::Transfer | TxType::Note
+    );
+    match state {
+        DisputeState::Unknown => RejectionReason::UnknownTx,
+        _ if new_id => RejectionReason::ReusedId,
+        DisputeState::Internal => RejectionReason::InternalTransfer,
+        DisputeState::Noted => RejectionReason::NoteReferenced,
+        DisputeState::Disputed if tx_type == TxType::Dispute => RejectionReason::AlreadyDisputed,
+        DisputeState::Disputed | DisputeState::Resolved if tx_type == TxType::Void => {
+            RejectionReason::VoidDisputed
+        }
+        DisputeState::Settled | DisputeState::Resolved => RejectionReason::NotDisputed,
+        DisputeState::ChargedBack => RejectionReason::ChargedBack,
+        DisputeState::Voided => RejectionReason::Voided,
+        DisputeState::Expired => RejectionReason::Expired,
+        DisputeState::Disputed => RejectionReason::UnderDispute,
+    }
+}
+
+// The words for a rejection by the table, completing i18n::Msg::Rejected.
+pub fn clause(reason: RejectionReason) -> Msg {
+    match reason {
+        RejectionReason::UnknownTx => Msg::UnknownTx,
+        RejectionReason::ReusedId => Msg::ReusedId,
+        RejectionReason::InternalTransfer => Msg::InternalTransfer,
+        RejectionReason::NoteReferenced => Msg::NoteReferenced,
+        RejectionReason::AlreadyDisputed => Msg::AlreadyDisputed,
+        RejectionReason::VoidDisputed => Msg::VoidDisputed,
+        RejectionReason::NotDisputed => Msg::NotDisputed,
+        RejectionReason::ChargedBack => Msg::ChargedBack,
+        RejectionReason::Voided => Msg::Voided,
+        RejectionReason::Expired => Msg::Expired,
+        RejectionReason::UnderDispute => Msg::UnderDispute,
+        other => unreachable!("{} is not a rejection by the dispute table", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::text;
+    use crate::{execute_transaction, AppState};
+    use txcli_model::{ClientId, Currency, TxId, TxRecord};
+
+    const STATES: [DisputeState; 9] = [
+        DisputeState::Unknown,
+        DisputeState::Settled,
+        DisputeState::Internal,
+        DisputeState::Noted,
+        DisputeState::Disputed,
+        DisputeState::Resolved,
+        DisputeState::ChargedBack,
+        DisputeState::Voided,
+        DisputeState::Expired,
+    ];
+    const TX_TYPES: [TxType; 8] = [
+        TxType::Deposit,
+        TxType::Withdrawal,
+        TxType::Transfer,
+        TxType::Note,
+        TxType::Dispute,
+        TxType::Resolve,
+        TxType::ChargeBack,
+        TxType::Void,
+    ];
+    const TID: u32 = 1;
+
+    fn row(tx_type: TxType) -> TxRecord<'static> {
+        let tx = TxRecord::new(tx_type, 1, TID, Currency::from_num(1));
+        match tx_type {
+            TxType::Transfer => tx.with_to_account("savings"),
+            TxType::Note => TxRecord {
+                amount: Currency::ZERO,
+                memo: Some("called about a missing deposit"),
+                ..tx
+            },
+            _ => tx,
+        }
+    }
+
+    // A client with plenty of funds under another id, and TID brought into the given state. The
+    // chargeback locks the client, allowed through so the table is checked rather than the lock.
+    fn client_in(state: DisputeState) -> AppState {
+        let mut app_state = AppState {
+            locked_policy: crate::engine::LockedPolicy::Allow,
+            ..AppState::default()
+        };
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 100, Currency::from_num(100)),
+        )
+        .unwrap();
+        let path: &[TxType] = match state {
+            DisputeState::Unknown => &[],
+            DisputeState::Settled => &[TxType::Deposit],
+            DisputeState::Internal => &[TxType::Transfer],
+            DisputeState::Noted => &[TxType::Note],
+            DisputeState::Disputed => &[TxType::Deposit, TxType::Dispute],
+            DisputeState::Resolved => &[TxType::Deposit, TxType::Dispute, TxType::Resolve],
+            DisputeState::ChargedBack => &[TxType::Deposit, TxType::Dispute, TxType::ChargeBack],
+            DisputeState::Voided => &[TxType::Deposit, TxType::Void],
+            DisputeState::Expired => &[TxType::Deposit],
+        };
+        for tx_type in path {
+            execute_transaction(&mut app_state, row(*tx_type)).unwrap();
+        }
+        if state == DisputeState::Expired {
+            let client = app_state.clients.get_mut(&ClientId(1)).unwrap();
+            crate::retention::expire(client, TxId(TID));
+        }
+        app_state
+    }
+
+    fn state_of(app_state: &AppState) -> DisputeState {
+        app_state.clients[&ClientId(1)].dispute_state(TxId(TID))
+    }
+
+    #[test]
+    fn setup_reaches_every_state() {
+        for state in STATES {
+            assert_eq!(state_of(&client_in(state)), state);
+        }
+    }
+
+    // Every transaction type against every prior state: the engine accepts exactly the table's
+    // transitions and lands in the listed state, anything else is rejected and changes nothing.
+    #[test]
+    fn engine_follows_the_table() {
+        for state in STATES {
+            for tx_type in TX_TYPES {
+                let mut app_state = client_in(state);
+                let before = app_state.output_state(ClientId(1));
+                let applied = execute_transaction(&mut app_state, row(tx_type));
+                match next(state, tx_type) {
+                    Some(to) => {
+                        assert!(applied.is_ok(), "{:?} x {} should apply", state, tx_type);
+                        assert_eq!(state_of(&app_state), to, "{:?} x {}", state, tx_type);
+                    }
+                    None => {
+                        assert_eq!(
+                            applied,
+                            Err(rejection(state, tx_type)),
+                            "{:?} x {}",
+                            state,
+                            tx_type
+                        );
+                        assert_eq!(state_of(&app_state), state, "{:?} x {}", state, tx_type);
+                        assert_eq!(app_state.output_state(ClientId(1)), before);
+                        assert!(!text(clause(rejection(state, tx_type)), &[]).is_empty());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn table_is_a_function() {
+        for (index, (from, on, _)) in TRANSITIONS.iter().enumerate() {
+            assert!(!TRANSITIONS[index + 1..]
+                .iter()
+                .any(|(other_from, other_on, _)| other_from == from && other_on == on));
+        }
+        for state in [
+            DisputeState::Noted,
+            DisputeState::ChargedBack,
+            DisputeState::Voided,
+            DisputeState::Expired,
+        ] {
+            assert!(TX_TYPES
+                .iter()
+                .all(|tx_type| next(state, *tx_type).is_none()));
+        }
+    }
+
+    #[test]
+    fn short_of_funds_stays_unknown() {
+        let mut app_state = AppState::default();
+        let withdrawal = TxRecord::new(TxType::Withdrawal, 1, TID, Currency::from_num(1));
+        assert_eq!(
+            execute_transaction(&mut app_state, withdrawal),
+            Err(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(state_of(&app_state), DisputeState::Unknown);
+        assert_eq!(
+            execute_transaction(&mut app_state, row(TxType::Dispute)),
+            Err(RejectionReason::UnknownTx)
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use crate::engine::Engine;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+// Interim balance reports during a long run, so downstream consumers can start on approximate
+// data before it finishes. Each is the balances as of the rows applied so far, before the end of
+// run cutoff, merges and splits, written under a temporary name and renamed into place so a
+// consumer watching the directory never picks up half a file.
+pub struct Emitter {
+    every: u64,
+    dir: PathBuf,
+    rows: u64,
+    written: u64,
+}
+
+impl Emitter {
+    pub fn new(every: u64, dir: &str) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        Ok(Emitter {
+            every,
+            dir: PathBuf::from(dir),
+            rows: 0,
+            written: 0,
+        })
+    }
+
+    // Called once per row that reached the engine.
+    pub fn record(&mut self, engine: &Engine) -> Result<(), Box<dyn Error>> {
+        self.rows += 1;
+        if !self.rows.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        // Zero padded so the reports sort by name in the order they were written.
+        let path = self.dir.join(format!("balances-{:012}.csv", self.rows));
+        let partial = path.with_extension("csv.partial");
+        fs::write(&partial, engine.report().balances_csv()?)?;
+        fs::rename(&partial, &path)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use txcli_model::{Currency, TxRecord, TxType};
+
+    #[test]
+    fn writes_every_n_rows() {
+        let dir = std::env::temp_dir().join(format!("txcli-emit-{}", std::process::id()));
+        let mut emitter = Emitter::new(2, dir.to_str().unwrap()).unwrap();
+        let mut engine = EngineBuilder::new().build();
+        for tid in 1..=5 {
+            engine.apply(TxRecord::new(
+                TxType::Deposit,
+                1,
+                tid,
+                Currency::from_num(1),
+            ));
+            emitter.record(&engine).unwrap();
+        }
+        assert_eq!(emitter.written(), 2);
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["balances-000000000002.csv", "balances-000000000004.csv"]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("balances-000000000004.csv")).unwrap(),
+            "client,available,held,total,locked\n1,4.0000,0.0000,4.0000,false\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1510 @@
+use crate::asset::{AssetRegistry, DustPolicy};
+use crate::availability::{Hold, Holds, SettlementDelays};
+use crate::custom::CustomTxHandler;
+use crate::i18n::{text, Msg};
+use crate::latency::{Latency, Stopwatch};
+use crate::metrics::Metrics;
+use crate::rejection::RejectionReason;
+use crate::results::{Report, Stats, Totals};
+use crate::retention::{self, Retention};
+use crate::snapshot::Snapshot;
+use crate::spill::{self, HistoryBackend, Spill};
+use crate::validation::{self, TidScope};
+use crate::warnings::{Kind, Warning};
+use crate::{
+    check_lock, dispute_state, execute_transaction, penalty, AppState, BasicError, ClientState,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use txcli_model::{
+    register_custom_type, ClientId, ClientOutputState, Currency, CustomType, Tx, TxId, TxRecord,
+    TxType,
+};
+
+// Keeps the first failure of the spill file. History is incomplete from then on, so every later
+// row is rejected and history_error ends the run.
+fn lose(history_lost: &mut Option<String>, result: Result<(), Box<dyn Error>>) {
+    if let (Err(err), None) = (result, &history_lost) {
+        eprintln!("{}", err);
+        *history_lost = Some(err.to_string());
+    }
+}
+
+// What happens to a dispute that would take a client over the cap on open disputes.
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    // Rejected like any other invalid transaction.
+    #[default]
+    Reject,
+    // Held back and opened in order as the client's open disputes are resolved or charged back.
+    Queue,
+}
+
+impl FromStr for Overflow {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Overflow::Reject),
+            "queue" => Ok(Overflow::Queue),
+            _ => Err(BasicError::new(format!(
+                "Unknown dispute overflow {}, expected reject or queue.",
+                s
+            ))),
+        }
+    }
+}
+
+// What a locked client can still do. Resolves and chargebacks of its open disputes always go
+// through, they settle what was disputed before the lock, and so do notes, which move nothing.
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockedPolicy {
+    // Deposits, withdrawals, transfers, disputes, voids and custom rows are all rejected.
+    #[default]
+    RejectAll,
+    // Only withdrawals are rejected, funds can still come in.
+    RejectWithdrawals,
+    // A lock only marks the client, nothing is rejected for it.
+    Allow,
+}
+
+impl LockedPolicy {
+    pub fn rejects(self, tx_type: TxType) -> bool {
+        match self {
+            LockedPolicy::RejectAll => {
+                !matches!(tx_type, TxType::Resolve | TxType::ChargeBack | TxType::Note)
+            }
+            LockedPolicy::RejectWithdrawals => tx_type == TxType::Withdrawal,
+            LockedPolicy::Allow => false,
+        }
+    }
+}
+
+impl FromStr for LockedPolicy {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject-all" => Ok(LockedPolicy::RejectAll),
+            "reject-withdrawals" => Ok(LockedPolicy::RejectWithdrawals),
+            "allow" => Ok(LockedPolicy::Allow),
+            _ => Err(BasicError::new(format!(
+                "Unknown locked policy {}, expected reject-all, reject-withdrawals or allow.",
+                s
+            ))),
+        }
+    }
+}
+
+// What a dispute of a deposit does when the deposit's account no longer has it available, e.g.
+// it was withdrawn since.
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisputePolicy {
+    // The whole amount is held and available goes negative, the client is in debt.
+    #[default]
+    AllowNegative,
+    // The dispute is rejected with insufficient funds.
+    Reject,
+    // Only what is available is held, the rest is kept as the dispute's shortfall.
+    PartialHold,
+}
+
+impl FromStr for DisputePolicy {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow-negative" => Ok(DisputePolicy::AllowNegative),
+            "reject" => Ok(DisputePolicy::Reject),
+            "partial-hold" => Ok(DisputePolicy::PartialHold),
+            _ => Err(BasicError::new(format!(
+                "Unknown dispute policy {}, expected allow-negative, reject or partial-hold.",
+                s
+            ))),
+        }
+    }
+}
+
+// Who pays the fee assessed on each chargeback.
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FeePayer {
+    // Taken from available on the charged back transaction's account, which may go negative.
+    #[default]
+    Client,
+    // Netted off the settlement figure of the charged back transaction's counterparty.
+    Counterparty,
+}
+
+impl FromStr for FeePayer {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(FeePayer::Client),
+            "counterparty" => Ok(FeePayer::Counterparty),
+            _ => Err(BasicError::new(format!(
+                "Unknown chargeback fee payer {}, expected client or counterparty.",
+                s
+            ))),
+        }
+    }
+}
+
+// A flat fee on every chargeback applied, what the network charges for handling it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ChargebackFee {
+    pub amount: Currency,
+    pub payer: FeePayer,
+}
+
+// Limits how many disputes a client can have open at once, a malformed feed once opened tens of
+// thousands on one client and blew up its held balance.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DisputeCap {
+    pub max: usize,
+    pub overflow: Overflow,
+}
+
+// Locks a client once its chargebacks cross either threshold, rather than on the first one,
+// mirroring the production risk system so simulations lock the same clients.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct RiskLock {
+    pub chargebacks: Option<u64>,
+    pub amount: Option<Currency>,
+}
+
+impl RiskLock {
+    fn breached(&self, chargebacks: u64, amount: Currency) -> bool {
+        self.chargebacks.is_some_and(|max| chargebacks >= max)
+            || self.amount.is_some_and(|max| amount >= max)
+    }
+}
+
+// Every policy knob the engine understands, set through EngineBuilder.
+#[derive(Debug, Default, Clone)]
+pub struct EngineConfig {
+    // Joint owners mapped to the canonical client holding the shared state.
+    pub joint_owners: HashMap<ClientId, ClientId>,
+    // Overdraft penalty rate charged against negative available balances at each cutoff.
+    pub overdraft_rate: Option<Currency>,
+    // Cutoff after every N transactions, the end of the run is always a cutoff as well.
+    pub cutoff_every: Option<u64>,
+    // Per asset precision and dust thresholds, rows below their asset's dust are never applied.
+    pub assets: Option<AssetRegistry>,
+    pub dispute_cap: Option<DisputeCap>,
+    pub risk_lock: Option<RiskLock>,
+    pub custom_types: HashMap<CustomType, Arc<dyn CustomTxHandler>>,
+    // Prunes history as rows are applied, see retention::Retention.
+    pub retention: Option<Retention>,
+    pub locked_policy: LockedPolicy,
+    pub dispute_policy: DisputePolicy,
+    pub chargeback_fee: Option<ChargebackFee>,
+    // Prints what chargebacks wrote off per client, see writeoff::WriteoffReport.
+    pub track_writeoffs: bool,
+    // Deposits from the listed counterparties wait as pending, see availability::Holds.
+    pub settlement_delays: Option<SettlementDelays>,
+    // The balances' columns in order, instead of those the run produces, see --columns.
+    pub columns: Option<Vec<&'static str>>,
+    pub tid_scope: TidScope,
+    // Transactions kept in history in memory, the rest spill to disk, see spill::Spill.
+    pub history_cache: Option<usize>,
+    // Archived clients are printed with the active ones.
+    pub include_archived: bool,
+    // Rows taking longer are warned about with where the time went, see latency::Latency.
+    pub latency_budget: Option<Duration>,
+}
+
+// EngineBuilder::new().overdraft_rate(rate).cutoff_every(1000).build()
+// Every engine flag has its call here. --strict has none: the engine is handed rows already
+// parsed, so stopping at a malformed one is up to whatever reads them.
+#[derive(Debug, Default, Clone)]
+pub struct EngineBuilder {
+    config: EngineConfig,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        EngineBuilder::default()
+    }
+
+    pub fn joint_owners(mut self, joint_owners: HashMap<ClientId, ClientId>) -> Self {
+        self.config.joint_owners = joint_owners;
+        self
+    }
+
+    pub fn overdraft_rate(mut self, rate: Currency) -> Self {
+        self.config.overdraft_rate = Some(rate);
+        self
+    }
+
+    pub fn cutoff_every(mut self, transactions: u64) -> Self {
+        self.config.cutoff_every = Some(transactions);
+        self
+    }
+
+    pub fn assets(mut self, assets: AssetRegistry) -> Self {
+        self.config.assets = Some(assets);
+        self
+    }
+
+    pub fn dispute_cap(mut self, max: usize, overflow: Overflow) -> Self {
+        self.config.dispute_cap = Some(DisputeCap { max, overflow });
+        self
+    }
+
+    // Registers name as a transaction type, parsed from then on, and applies its rows with handler.
+    pub fn custom_type(
+        mut self,
+        name: &str,
+        handler: Arc<dyn CustomTxHandler>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if let TxType::Custom(custom) = register_custom_type(name)? {
+            self.config.custom_types.insert(custom, handler);
+        }
+        Ok(self)
+    }
+
+    pub fn locked_policy(mut self, policy: LockedPolicy) -> Self {
+        self.config.locked_policy = policy;
+        self
+    }
+
+    pub fn dispute_policy(mut self, policy: DisputePolicy) -> Self {
+        self.config.dispute_policy = policy;
+        self
+    }
+
+    pub fn chargeback_fee(mut self, amount: Currency, payer: FeePayer) -> Self {
+        self.config.chargeback_fee = Some(ChargebackFee { amount, payer });
+        self
+    }
+
+    pub fn track_writeoffs(mut self, track: bool) -> Self {
+        self.config.track_writeoffs = track;
+        self
+    }
+
+    pub fn settlement_delays(mut self, delays: SettlementDelays) -> Self {
+        self.config.settlement_delays = Some(delays);
+        self
+    }
+
+    pub fn columns(mut self, columns: Vec<&'static str>) -> Self {
+        self.config.columns = Some(columns);
+        self
+    }
+
+    pub fn tid_scope(mut self, scope: TidScope) -> Self {
+        self.config.tid_scope = scope;
+        self
+    }
+
+    pub fn retention(mut self, retention: Retention) -> Self {
+        self.config.retention = Some(retention);
+        self
+    }
+
+    pub fn history_cache(mut self, transactions: usize) -> Self {
+        self.config.history_cache = Some(transactions);
+        self
+    }
+
+    // Disk keeps a cache set through history_cache, spill::DEFAULT_CACHE transactions otherwise.
+    pub fn history_backend(mut self, backend: HistoryBackend) -> Self {
+        self.config.history_cache = match backend {
+            HistoryBackend::Memory => None,
+            HistoryBackend::Disk => Some(self.config.history_cache.unwrap_or(spill::DEFAULT_CACHE)),
+        };
+        self
+    }
+
+    pub fn include_archived(mut self) -> Self {
+        self.config.include_archived = true;
+        self
+    }
+
+    pub fn latency_budget(mut self, budget: Duration) -> Self {
+        self.config.latency_budget = Some(budget);
+        self
+    }
+
+    pub fn risk_lock(mut self, risk_lock: RiskLock) -> Self {
+        self.config.risk_lock = Some(risk_lock);
+        self
+    }
+
+    pub fn build(self) -> Engine {
+        let state = AppState {
+            joint_owners: self.config.joint_owners.clone(),
+            locked_policy: self.config.locked_policy,
+            dispute_policy: self.config.dispute_policy,
+            chargeback_fee: self.config.chargeback_fee,
+            ..Default::default()
+        };
+        Engine {
+            spill: self.config.history_cache.map(Spill::new),
+            history_lost: None,
+            latency: self.config.latency_budget.map(Latency::new),
+            metrics: Metrics::default(),
+            config: self.config,
+            state,
+            processed: 0,
+            rejects: BTreeMap::new(),
+            penalties_assessed: Currency::from_num(0),
+            chargeback_fees: Currency::from_num(0),
+            swept: BTreeMap::new(),
+            queued: HashMap::new(),
+            charged_back: HashMap::new(),
+            recent: HashMap::new(),
+            tids: HashSet::new(),
+            holds: Holds::default(),
+        }
+    }
+}
+
+pub struct Engine {
+    config: EngineConfig,
+    state: AppState,
+    processed: u64,
+    // Rows not applied, per transaction type.
+    rejects: BTreeMap<String, u64>,
+    penalties_assessed: Currency,
+    // Chargeback fees assessed, whoever paid them.
+    chargeback_fees: Currency,
+    // Dust collected under the sweep policy, per asset code.
+    swept: BTreeMap<String, Currency>,
+    // Disputes over the cap waiting for a slot, per canonical client in arrival order.
+    queued: HashMap<ClientId, VecDeque<Tx>>,
+    // Chargebacks and the amount charged back so far per canonical client, only under RiskLock.
+    charged_back: HashMap<ClientId, (u64, Currency)>,
+    // The dispute window per canonical client, oldest first, only under Retention::LastN.
+    recent: HashMap<ClientId, VecDeque<TxId>>,
+    // Every deposit, withdrawal and transfer id applied, only under TidScope::Global.
+    tids: HashSet<TxId>,
+    // Deposits pending settlement, only with settlement delays.
+    holds: Holds,
+    // Transactions evicted from history, only with a history cache.
+    spill: Option<Spill>,
+    // The first failure of the spill file, see lose.
+    history_lost: Option<String>,
+    // Only with a latency budget.
+    latency: Option<Latency>,
+    metrics: Metrics,
+}
+
+impl Engine {
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    // For rows rejected before they reach the engine, e.g. over --max-amount.
+    pub(crate) fn metrics_mut(&mut self) -> &mut Metrics {
+        &mut self.metrics
+    }
+
+    pub(crate) fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    pub(crate) fn state_mut(&mut self) -> &mut AppState {
+        &mut self.state
+    }
+
+    // Returns whether the transaction was applied, see try_apply for why it wasn't.
+    pub fn apply(&mut self, tx: TxRecord) -> bool {
+        self.try_apply(tx).is_ok()
+    }
+
+    // Applies tx or returns why it was rejected, see execute_transaction.
+    pub fn try_apply(&mut self, tx: TxRecord) -> Result<(), RejectionReason> {
+        let mut stopwatch = self.latency.is_some().then(Stopwatch::start);
+        if self.config.settlement_delays.is_some() {
+            self.settle(&tx);
+        }
+        // Any row may reference tx.tid, if only to be rejected for reusing it.
+        if let Some(spill) = &mut self.spill {
+            let cid = self.state.canonical(tx.cid);
+            lose(
+                &mut self.history_lost,
+                spill.load(&mut self.state, cid, tx.tid),
+            );
+        }
+        if let Some(stopwatch) = &mut stopwatch {
+            stopwatch.looked_up();
+        }
+        // What the chargeback takes back and whether the client was locked, before it applies.
+        let chargeback = self
+            .config
+            .risk_lock
+            .filter(|_| tx.tx_type == TxType::ChargeBack)
+            .and_then(|_| {
+                let cid = self.state.canonical(tx.cid);
+                let client = self.state.clients.get(&cid)?;
+                let disputed = client.disputed.get(&tx.tid)?;
+                Some((
+                    cid,
+                    disputed.amount - client.shortfall(tx.tid),
+                    client.locked,
+                ))
+            });
+        let dust = self
+            .config
+            .assets
+            .as_ref()
+            .and_then(|assets| assets.dust(&tx));
+        let tids = (self.config.tid_scope == TidScope::Global).then_some(&self.tids);
+        let checked = match self.history_lost {
+            Some(_) => Err(RejectionReason::HistoryUnavailable),
+            None => validation::check(&tx, tids),
+        }
+        .and_then(|()| self.check_archived(&tx))
+        .and_then(|()| match dust {
+            Some(DustPolicy::Ignore) => {
+                Warning::new(Kind::Dust, text(Msg::Dust, &[&tx.tid.0]))
+                    .tx(tx.tx_type, tx.tid)
+                    .client(tx.cid)
+                    .emit();
+                Err(RejectionReason::Dust)
+            }
+            Some(DustPolicy::Sweep) => {
+                // dust() only matches rows with a currency.
+                *self
+                    .swept
+                    .entry(tx.currency.unwrap_or_default().to_string())
+                    .or_default() += tx.amount;
+                Err(RejectionReason::Dust)
+            }
+            None => Ok(()),
+        });
+        if let Some(stopwatch) = &mut stopwatch {
+            stopwatch.checked();
+        }
+        let applied = checked.and_then(|()| match (tx.tx_type, self.config.dispute_cap) {
+            (TxType::Custom(custom), _) => self.apply_custom(custom, tx),
+            (TxType::ArchiveClient | TxType::ReactivateClient, _) => self.apply_admin(tx),
+            (_, Some(cap)) => self.apply_capped(cap, tx),
+            (_, None) => execute_transaction(&mut self.state, tx),
+        });
+        if let (Ok(()), TxType::ChargeBack, Some(fee)) =
+            (applied, tx.tx_type, self.config.chargeback_fee)
+        {
+            self.chargeback_fees += fee.amount;
+        }
+        if let (Ok(()), TxType::Deposit, Some(delay)) = (
+            applied,
+            tx.tx_type,
+            self.config
+                .settlement_delays
+                .as_ref()
+                .and_then(|delays| delays.of(&tx)),
+        ) {
+            let cid = self.state.canonical(tx.cid);
+            if let Some(client) = self.state.clients.get_mut(&cid) {
+                let balance = client.balance(tx.currency, tx.account);
+                balance.available -= tx.amount;
+                balance.pending += tx.amount;
+                self.holds.hold(cid, &tx, delay);
+            }
+        }
+        if let (Ok(()), TidScope::Global) = (applied, self.config.tid_scope) {
+            validation::record(&tx, &mut self.tids);
+        }
+        if let (Ok(()), Some((cid, amount, was_locked)), Some(risk_lock)) =
+            (applied, chargeback, self.config.risk_lock)
+        {
+            self.risk_check(risk_lock, tx, cid, amount, was_locked);
+        }
+        if let (Ok(()), Some(retention)) = (applied, self.config.retention) {
+            let cid = self.state.canonical(tx.cid);
+            // The transaction about to leave the window has to be in history to expire.
+            if let (Retention::LastN(n), Some(spill)) = (retention, &mut self.spill) {
+                if let Some(oldest) = self.recent.get(&cid).filter(|window| window.len() >= n) {
+                    lose(
+                        &mut self.history_lost,
+                        spill.load(&mut self.state, cid, oldest[0]),
+                    );
+                }
+            }
+            if let Some(client) = self.state.clients.get_mut(&cid) {
+                retention::retain(retention, client, self.recent.entry(cid).or_default(), &tx);
+            }
+        }
+        if let (Ok(()), Some(spill)) = (applied, &mut self.spill) {
+            if matches!(
+                tx.tx_type,
+                TxType::Deposit
+                    | TxType::Withdrawal
+                    | TxType::Transfer
+                    | TxType::Resolve
+                    | TxType::ChargeBack
+            ) {
+                let cid = self.state.canonical(tx.cid);
+                lose(
+                    &mut self.history_lost,
+                    spill.touch(&mut self.state, cid, tx.tid),
+                );
+            }
+        }
+        self.processed += 1;
+        self.metrics.record(&tx, applied);
+        if applied.is_err() {
+            *self.rejects.entry(tx.tx_type.to_string()).or_default() += 1;
+        }
+        if let (Some(rate), Some(every)) = (self.config.overdraft_rate, self.config.cutoff_every) {
+            if self.processed.is_multiple_of(every) {
+                self.penalties_assessed += penalty::assess(&mut self.state, rate);
+            }
+        }
+        if let (Some(latency), Some(stopwatch)) = (&mut self.latency, stopwatch) {
+            latency.record(&tx, stopwatch.stop());
+        }
+        applied
+    }
+
+    // Releases the holds due by the time tx is read. A dispute or void of a pending deposit
+    // releases it first, so it finds the funds where those of a settled deposit would be.
+    fn settle(&mut self, tx: &TxRecord) {
+        let mut due = self.holds.advance(tx.timestamp);
+        if matches!(tx.tx_type, TxType::Dispute | TxType::Void) {
+            let cid = self.state.canonical(tx.cid);
+            due.extend(self.holds.take(cid, tx.tid));
+        }
+        for hold in due {
+            self.release(hold);
+        }
+    }
+
+    fn release(&mut self, hold: Hold) {
+        if let Some(client) = self.state.clients.get_mut(&hold.cid) {
+            let balance = client.balance(hold.currency.as_deref(), &hold.account.0);
+            balance.pending -= hold.amount;
+            balance.available += hold.amount;
+        }
+    }
+
+    // Rows for an archived client are rejected until it is reactivated, admin rows decide for
+    // themselves.
+    fn check_archived(&self, tx: &TxRecord) -> Result<(), RejectionReason> {
+        let cid = self.state.canonical(tx.cid);
+        if matches!(tx.tx_type, TxType::ArchiveClient | TxType::ReactivateClient)
+            || !self.state.archived.contains_key(&cid)
+        {
+            return Ok(());
+        }
+        self.reject_admin(tx, Msg::ClientArchived, RejectionReason::Archived)
+    }
+
+    fn reject_admin(
+        &self,
+        tx: &TxRecord,
+        msg: Msg,
+        reason: RejectionReason,
+    ) -> Result<(), RejectionReason> {
+        let cid = self.state.canonical(tx.cid);
+        Warning::new(Kind::Archival, text(msg, &[&tx.tx_type, &tx.tid.0, &cid.0]))
+            .tx(tx.tx_type, tx.tid)
+            .client(tx.cid)
+            .emit();
+        Err(reason)
+    }
+
+    // Moves a dormant client to the archived clients, or an archived one back. Neither touches
+    // its state, a reactivated client carries on with its history, lifecycle and lock.
+    fn apply_admin(&mut self, tx: TxRecord) -> Result<(), RejectionReason> {
+        let cid = self.state.canonical(tx.cid);
+        let (msg, reason) = match tx.tx_type {
+            TxType::ArchiveClient => match self.state.clients.get(&cid) {
+                None if self.state.archived.contains_key(&cid) => {
+                    (Msg::ClientArchived, RejectionReason::Archived)
+                }
+                None => (Msg::UnknownClient, RejectionReason::UnknownClient),
+                // A queued dispute would open on a client no longer there.
+                Some(client)
+                    if !client.dormant()
+                        || self.queued.get(&cid).is_some_and(|q| !q.is_empty()) =>
+                {
+                    (Msg::NotDormant, RejectionReason::NotDormant)
+                }
+                Some(_) => {
+                    let client = self.state.clients.remove(&cid).unwrap_or_default();
+                    self.state.archived.insert(cid, client);
+                    return Ok(());
+                }
+            },
+            _ => match self.state.archived.remove(&cid) {
+                Some(client) => {
+                    self.state.clients.insert(cid, client);
+                    return Ok(());
+                }
+                None => (Msg::NotArchived, RejectionReason::NotArchived),
+            },
+        };
+        self.reject_admin(&tx, msg, reason)
+    }
+
+    fn apply_custom(&mut self, custom: CustomType, tx: TxRecord) -> Result<(), RejectionReason> {
+        check_lock(&self.state, &tx)?;
+        let cid = self.state.canonical(tx.cid);
+        let effect = match self.config.custom_types.get(&custom) {
+            Some(handler) => {
+                let client = self.state.clients.entry(cid).or_default();
+                handler
+                    .effect(&tx, &client.output(cid))
+                    .ok_or((Msg::CustomRefused, RejectionReason::CustomRefused))
+            }
+            None => Err((Msg::NoHandler, RejectionReason::NoHandler)),
+        };
+        let effect = match effect {
+            Ok(effect) => effect,
+            Err((msg, reason)) => {
+                Warning::new(Kind::Rejected, text(msg, &[&tx.tx_type, &tx.tid.0]))
+                    .tx(tx.tx_type, tx.tid)
+                    .client(tx.cid)
+                    .emit();
+                return Err(reason);
+            }
+        };
+        let client = self.state.clients.entry(cid).or_default();
+        let account = client.balance(tx.currency, tx.account);
+        account.available += effect.available;
+        account.held += effect.held;
+        client.locked |= effect.lock;
+        Ok(())
+    }
+
+    // A queued dispute counts as applied, the row was accepted and is opened once there is room.
+    fn apply_capped(&mut self, cap: DisputeCap, tx: TxRecord) -> Result<(), RejectionReason> {
+        let cid = self.state.canonical(tx.cid);
+        let queued = self.queued.entry(cid).or_default();
+        let position = queued.iter().position(|queued| queued.tid == tx.tid);
+        let reject = |reason: RejectionReason, words: &str| {
+            Warning::new(
+                Kind::DisputeCap,
+                text(Msg::DisputeCap, &[&tx.tx_type, &tx.tid.0, &words]),
+            )
+            .tx(tx.tx_type, tx.tid)
+            .client(tx.cid)
+            .emit();
+            Err(reason)
+        };
+        match (tx.tx_type, position) {
+            (TxType::Dispute, Some(_)) => reject(
+                RejectionReason::DisputeQueued,
+                &text(Msg::DisputeQueued, &[]),
+            ),
+            // The dispute never opened, so resolving it only has to take it out of the queue.
+            (TxType::Resolve, Some(position)) => {
+                queued.remove(position);
+                Ok(())
+            }
+            (TxType::ChargeBack, Some(_)) => reject(
+                RejectionReason::DisputeQueued,
+                &text(Msg::DisputeStillQueued, &[]),
+            ),
+            (TxType::Dispute, None) => {
+                let policy = self.state.locked_policy;
+                let (open, allowed) = self.state.clients.get(&cid).map_or((0, false), |client| {
+                    let state = client.dispute_state(tx.tid);
+                    (
+                        client.disputed.len(),
+                        dispute_state::next(state, TxType::Dispute).is_some()
+                            && !(client.locked && policy.rejects(TxType::Dispute)),
+                    )
+                });
+                // Disputes that would be rejected anyway are left to say why.
+                if open < cap.max || !allowed {
+                    return execute_transaction(&mut self.state, tx);
+                }
+                match cap.overflow {
+                    Overflow::Reject => reject(
+                        RejectionReason::TooManyDisputes,
+                        &text(Msg::TooManyDisputes, &[&cid.0, &open]),
+                    ),
+                    Overflow::Queue => {
+                        queued.push_back(tx.to_tx());
+                        Ok(())
+                    }
+                }
+            }
+            _ => {
+                let applied = execute_transaction(&mut self.state, tx);
+                if applied.is_ok() && matches!(tx.tx_type, TxType::Resolve | TxType::ChargeBack) {
+                    self.open_queued(cap, cid);
+                }
+                applied
+            }
+        }
+    }
+
+    // Opens queued disputes while the client has room, ones that are no longer valid, e.g. their
+    // transaction was voided meanwhile, are rejected as usual.
+    fn open_queued(&mut self, cap: DisputeCap, cid: ClientId) {
+        let Some(queued) = self.queued.get_mut(&cid) else {
+            return;
+        };
+        while self
+            .state
+            .clients
+            .get(&cid)
+            .is_some_and(|client| client.disputed.len() < cap.max)
+        {
+            let Some(dispute) = queued.pop_front() else {
+                break;
+            };
+            if let Some(spill) = &mut self.spill {
+                lose(
+                    &mut self.history_lost,
+                    spill.load(&mut self.state, cid, dispute.tid),
+                );
+            }
+            let _ = execute_transaction(&mut self.state, dispute.as_record());
+        }
+    }
+
+    // A chargeback always locks in execute_transaction, here the lock is undone unless the
+    // client was already locked or this chargeback takes it over a threshold.
+    fn risk_check(
+        &mut self,
+        risk_lock: RiskLock,
+        tx: TxRecord,
+        cid: ClientId,
+        amount: Currency,
+        was_locked: bool,
+    ) {
+        let totals = self.charged_back.entry(cid).or_default();
+        totals.0 += 1;
+        totals.1 += amount;
+        let (chargebacks, charged_back) = *totals;
+        let breached = risk_lock.breached(chargebacks, charged_back);
+        if let Some(client) = self.state.clients.get_mut(&cid) {
+            client.locked = was_locked || breached;
+        }
+        if breached && !was_locked {
+            Warning::new(
+                Kind::AutoLock,
+                text(
+                    Msg::AutoLock,
+                    &[
+                        &cid.0,
+                        &tx.tid.0,
+                        &chargebacks,
+                        &format!("{:.4}", charged_back),
+                    ],
+                ),
+            )
+            .tx(tx.tx_type, tx.tid)
+            .client(cid)
+            .emit();
+        }
+    }
+
+    // Takes over the clients of an engine with the same config that saw none of this engine's
+    // clients, see shard::Shards.
+    pub(crate) fn absorb(&mut self, other: Engine) {
+        self.state.clients.extend(other.state.clients);
+        self.state.archived.extend(other.state.archived);
+        self.processed += other.processed;
+        for (tx_type, count) in other.rejects {
+            *self.rejects.entry(tx_type).or_default() += count;
+        }
+        self.penalties_assessed += other.penalties_assessed;
+        self.chargeback_fees += other.chargeback_fees;
+        for (code, amount) in other.swept {
+            *self.swept.entry(code).or_default() += amount;
+        }
+        self.queued.extend(other.queued);
+        self.charged_back.extend(other.charged_back);
+        self.recent.extend(other.recent);
+        self.tids.extend(other.tids);
+        self.holds.restore(other.holds.saved());
+    }
+
+    // Puts every transaction spilled to disk back in history, before what reads all of it, i.e.
+    // snapshots and merges.
+    pub(crate) fn load_history(&mut self) -> Result<(), Box<dyn Error>> {
+        self.history_error()?;
+        if let Some(spill) = &mut self.spill {
+            spill.load_all(&mut self.state)?;
+        }
+        Ok(())
+    }
+
+    // Fails once the spill file did, whatever the engine decided since was decided without the
+    // whole history.
+    pub fn history_error(&self) -> Result<(), Box<dyn Error>> {
+        match &self.history_lost {
+            Some(err) => Err(BasicError::new(err.clone())),
+            None => Ok(()),
+        }
+    }
+
+    // Transactions on disk and the bytes written for them, only with a history cache.
+    pub(crate) fn spilled(&self) -> Option<(usize, u64)> {
+        self.spill.as_ref().map(Spill::usage)
+    }
+
+    // Everything a later run carries on from, see snapshot.rs.
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        let mut snapshot =
+            Snapshot::new(&self.state, &self.queued, &self.charged_back, &self.recent);
+        snapshot.holds = self.holds.saved();
+        snapshot
+    }
+
+    // Picks up where the snapshot's run left off, before any row is applied.
+    pub(crate) fn restore(&mut self, mut snapshot: Snapshot) {
+        snapshot.restore_clients(&mut self.state);
+        self.queued = snapshot
+            .queued
+            .into_iter()
+            .map(|(cid, queue)| (cid, queue.into()))
+            .collect();
+        self.charged_back = snapshot.charged_back.into_iter().collect();
+        self.recent = snapshot
+            .recent
+            .into_iter()
+            .map(|(cid, window)| (cid, window.into()))
+            .collect();
+        self.holds.restore(std::mem::take(&mut snapshot.holds));
+        // The ids the snapshot's run applied, as far as its history still knows them.
+        if self.config.tid_scope == TidScope::Global {
+            self.tids = self
+                .state
+                .clients
+                .values()
+                .flat_map(|client| {
+                    let applied = client.history.values().filter(|tx| {
+                        matches!(
+                            tx.tx_type,
+                            TxType::Deposit | TxType::Withdrawal | TxType::Transfer | TxType::Note
+                        )
+                    });
+                    // Voided ones are gone from history.
+                    applied
+                        .map(|tx| tx.tid)
+                        .chain(client.lifecycle.keys().copied())
+                })
+                .collect();
+        }
+        if let Some(spill) = &mut self.spill {
+            let restored: Vec<_> = self
+                .state
+                .clients
+                .iter()
+                .flat_map(|(cid, client)| {
+                    // Notes stay in memory like the run's own, see notes.
+                    client
+                        .history
+                        .iter()
+                        .filter(|(_, tx)| tx.tx_type != TxType::Note)
+                        .map(|(tid, _)| (*cid, *tid))
+                })
+                .collect();
+            for (cid, tid) in restored {
+                lose(
+                    &mut self.history_lost,
+                    spill.touch(&mut self.state, cid, tid),
+                );
+            }
+        }
+    }
+
+    // Clients archive_client moved out and no row reactivated.
+    pub fn archived_clients(&self) -> usize {
+        self.state.archived.len()
+    }
+
+    // Disputes still waiting for a slot.
+    pub fn queued_disputes(&self) -> usize {
+        self.queued.values().map(VecDeque::len).sum()
+    }
+
+    // Closes the run. The end of the run is always a cutoff, unless the last transaction already closed a period.
+    pub fn finish(&mut self) {
+        if let Some(rate) = self.config.overdraft_rate {
+            if self
+                .config
+                .cutoff_every
+                .is_none_or(|every| !self.processed.is_multiple_of(every))
+            {
+                self.penalties_assessed += penalty::assess(&mut self.state, rate);
+            }
+        }
+    }
+
+    pub fn penalties_assessed(&self) -> Currency {
+        self.penalties_assessed
+    }
+
+    // The state as it stands, typically after finish.
+    pub fn report(&self) -> Report {
+        let clients = self.output_states();
+        let mut totals = Totals {
+            penalties_assessed: self.penalties_assessed,
+            chargeback_fees: self.chargeback_fees,
+            written_off: self.printed().map(|(_, client)| client.written_off()).sum(),
+            pending: self.printed().map(|(_, client)| client.pending()).sum(),
+            shortfall: self
+                .printed()
+                .flat_map(|(_, client)| client.shortfalls.values())
+                .sum(),
+            swept: self.swept.clone(),
+            ..Totals::default()
+        };
+        for client in &clients {
+            totals.available += client.available;
+            totals.held += client.held;
+            totals.total += client.total;
+        }
+        let rejected: u64 = self.rejects.values().sum();
+        Report {
+            columns: self.output_columns(),
+            totals,
+            rejects: self.rejects.clone(),
+            stats: Stats {
+                processed: self.processed,
+                applied: self.processed - rejected,
+                clients: self.state.clients.len() as u64,
+                locked: self
+                    .state
+                    .clients
+                    .values()
+                    .filter(|client| client.locked)
+                    .count() as u64,
+                queued_disputes: self.queued_disputes() as u64,
+                slow_rows: self.latency.as_ref().map_or(0, |latency| latency.slow),
+                slowest_row_us: self
+                    .latency
+                    .as_ref()
+                    .map_or(0, |latency| latency.slowest.as_micros() as u64),
+            },
+            clients,
+        }
+    }
+
+    // The clients printed, archived ones only with include_archived.
+    fn printed(&self) -> impl Iterator<Item = (&ClientId, &ClientState)> {
+        let archived = self.config.include_archived.then_some(&self.state.archived);
+        self.state
+            .clients
+            .iter()
+            .chain(archived.into_iter().flatten())
+    }
+
+    // Files that never mention an account keep the original one row per client output.
+    fn per_account(&self) -> bool {
+        self.printed()
+            .any(|(_, client)| client.has_named_accounts())
+    }
+
+    // Files naming one currency, or none, keep one row per client and account.
+    fn per_asset(&self) -> bool {
+        let mut assets = self.printed().flat_map(|(_, client)| client.asset_names());
+        let first = assets.next();
+        assets.any(|asset| Some(asset) != first)
+    }
+
+    // The client's balances across its accounts, joint owners give their canonical client's.
+    pub fn balance(&self, cid: ClientId) -> Option<ClientOutputState> {
+        self.state.output_state(self.state.canonical(cid))
+    }
+
+    // The client's notes in tid order, joint owners give their canonical client's. Notes never
+    // spill to disk, so history has them all.
+    pub fn notes(&self, cid: ClientId) -> Option<Vec<&Tx>> {
+        let client = self.state.clients.get(&self.state.canonical(cid))?;
+        let mut notes: Vec<&Tx> = client
+            .history
+            .values()
+            .filter(|tx| tx.tx_type == TxType::Note)
+            .collect();
+        notes.sort_unstable_by_key(|tx| tx.tid);
+        Some(notes)
+    }
+
+    fn fees_charged_to_clients(&self) -> bool {
+        self.config
+            .chargeback_fee
+            .is_some_and(|fee| fee.payer == FeePayer::Client)
+    }
+
+    pub fn output_columns(&self) -> Vec<&'static str> {
+        if let Some(columns) = &self.config.columns {
+            return columns.clone();
+        }
+        let mut columns = vec!["client"];
+        if self.per_asset() {
+            columns.push("currency");
+        }
+        if self.per_account() {
+            columns.push("account");
+        }
+        columns.extend(["available", "held", "total", "locked"]);
+        if !self.config.joint_owners.is_empty() {
+            columns.push("owners");
+        }
+        if self.config.overdraft_rate.is_some() {
+            columns.push("penalties");
+        }
+        if self.fees_charged_to_clients() {
+            columns.push("chargeback_fees");
+        }
+        if self.config.track_writeoffs {
+            columns.push("written_off");
+        }
+        if self.config.settlement_delays.is_some() {
+            columns.push("pending");
+        }
+        columns
+    }
+
+    // Rows matching output_columns, optional columns are filled for every row so the csv stays rectangular.
+    pub fn output_states(&self) -> Vec<ClientOutputState> {
+        let per_asset = self.per_asset();
+        let per_account = self.per_account();
+        let columns = self.output_columns();
+        let has = |column| columns.contains(&column);
+        let mut rows: Vec<_> = self
+            .printed()
+            .flat_map(|(cid, user)| {
+                let mut rows = if per_asset || per_account {
+                    user.split_output(*cid, per_asset, per_account)
+                } else {
+                    vec![ClientOutputState {
+                        penalties: Some(user.penalties()),
+                        chargeback_fees: Some(user.chargeback_fees()),
+                        written_off: Some(user.written_off()),
+                        pending: Some(user.pending()),
+                        ..user.output(*cid)
+                    }]
+                };
+                for row in &mut rows {
+                    if has("owners") {
+                        row.owners = Some(self.state.owners(*cid));
+                    }
+                    if !has("penalties") {
+                        row.penalties = None;
+                    }
+                    if !has("chargeback_fees") {
+                        row.chargeback_fees = None;
+                    }
+                    if !has("written_off") {
+                        row.written_off = None;
+                    }
+                    if !has("pending") {
+                        row.pending = None;
+                    }
+                    if has("dispute_count") {
+                        row.dispute_count = Some(user.disputed.len() as u64);
+                    }
+                }
+                rows
+            })
+            .collect();
+        // Stable, split_output already orders a client's rows by currency then account.
+        rows.sort_by_key(|row| row.cid);
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::TxType;
+
+    #[test]
+    fn default_engine_matches_plain_execution() {
+        let mut engine = EngineBuilder::new().build();
+        assert!(engine.apply(TxRecord::new(
+            TxType::Deposit,
+            1,
+            1,
+            Currency::from_num(1.0)
+        )));
+        assert!(!engine.apply(TxRecord::new(
+            TxType::Withdrawal,
+            1,
+            2,
+            Currency::from_num(2.0)
+        )));
+        engine.finish();
+        assert_eq!(
+            engine.output_columns(),
+            vec!["client", "available", "held", "total", "locked"]
+        );
+        let rows = engine.output_states();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].available, Currency::from_num(1.0));
+        assert_eq!(rows[0].penalties, None);
+    }
+
+    #[test]
+    fn dispute_cap() {
+        let deposit = |tid| TxRecord::new(TxType::Deposit, 1, tid, Currency::from_num(10));
+        let row = |tx_type, tid| TxRecord::new(tx_type, 1, tid, Currency::from_num(0));
+        let held = |engine: &Engine| engine.output_states()[0].held;
+
+        let mut engine = EngineBuilder::new()
+            .dispute_cap(2, Overflow::Reject)
+            .build();
+        for tid in 1..=3 {
+            engine.apply(deposit(tid));
+        }
+        assert!(engine.apply(row(TxType::Dispute, 1)));
+        assert!(engine.apply(row(TxType::Dispute, 2)));
+        assert!(!engine.apply(row(TxType::Dispute, 3)));
+        assert_eq!(held(&engine), Currency::from_num(20));
+        // Rejected for the usual reason, not the cap.
+        assert!(!engine.apply(row(TxType::Dispute, 9)));
+
+        // Allowed while locked, so the chargeback below frees its slot for another dispute.
+        let mut engine = EngineBuilder::new()
+            .dispute_cap(1, Overflow::Queue)
+            .locked_policy(LockedPolicy::Allow)
+            .build();
+        for tid in 1..=4 {
+            engine.apply(deposit(tid));
+        }
+        assert!(engine.apply(row(TxType::Dispute, 1)));
+        assert!(engine.apply(row(TxType::Dispute, 2)));
+        assert!(engine.apply(row(TxType::Dispute, 3)));
+        assert!(!engine.apply(row(TxType::Dispute, 3)));
+        assert_eq!(
+            (held(&engine), engine.queued_disputes()),
+            (Currency::from_num(10), 2)
+        );
+        // A resolve for a queued dispute only drops it from the queue.
+        assert!(engine.apply(row(TxType::Resolve, 3)));
+        assert!(!engine.apply(row(TxType::ChargeBack, 2)));
+        assert_eq!(engine.queued_disputes(), 1);
+        // Resolving the open one lets the next queued dispute in.
+        assert!(engine.apply(row(TxType::Resolve, 1)));
+        assert_eq!(
+            (held(&engine), engine.queued_disputes()),
+            (Currency::from_num(10), 0)
+        );
+        assert!(engine.apply(row(TxType::ChargeBack, 2)));
+        assert!(engine.apply(row(TxType::Dispute, 4)));
+        assert_eq!(held(&engine), Currency::from_num(10));
+    }
+
+    #[test]
+    fn risk_lock() {
+        let mut engine = EngineBuilder::new()
+            .risk_lock(RiskLock {
+                chargebacks: Some(3),
+                amount: Some(Currency::from_num(25)),
+            })
+            .build();
+        let locked = |engine: &Engine, cid: u16| engine.state().clients[&ClientId(cid)].locked;
+        let charge_back = |engine: &mut Engine, cid, tid, amount: u32| {
+            engine.apply(TxRecord::new(
+                TxType::Deposit,
+                cid,
+                tid,
+                Currency::from_num(amount),
+            ));
+            engine.apply(TxRecord::new(
+                TxType::Dispute,
+                cid,
+                tid,
+                Currency::from_num(0),
+            ));
+            assert!(engine.apply(TxRecord::new(
+                TxType::ChargeBack,
+                cid,
+                tid,
+                Currency::from_num(0)
+            )));
+        };
+        // Client 1 crosses on count, client 2 on amount.
+        charge_back(&mut engine, 1, 1, 1);
+        charge_back(&mut engine, 1, 2, 1);
+        assert!(!locked(&engine, 1));
+        charge_back(&mut engine, 1, 3, 1);
+        assert!(locked(&engine, 1));
+        charge_back(&mut engine, 2, 4, 20);
+        assert!(!locked(&engine, 2));
+        charge_back(&mut engine, 2, 5, 5);
+        assert!(locked(&engine, 2));
+        // Without the rule every chargeback locks.
+        let mut engine = EngineBuilder::new().build();
+        charge_back(&mut engine, 1, 1, 1);
+        assert!(locked(&engine, 1));
+    }
+
+    #[test]
+    fn locked_policies() {
+        let tx =
+            |tx_type, tid, amount: u32| TxRecord::new(tx_type, 1, tid, Currency::from_num(amount));
+        // Client 1 locked by a chargeback, with a second deposit still disputed.
+        let locked = |policy| {
+            let mut engine = EngineBuilder::new().locked_policy(policy).build();
+            for tx in [
+                tx(TxType::Deposit, 1, 10),
+                tx(TxType::Deposit, 2, 10),
+                tx(TxType::Dispute, 2, 0),
+                tx(TxType::Dispute, 1, 0),
+                tx(TxType::ChargeBack, 1, 0),
+            ] {
+                engine.try_apply(tx).unwrap();
+            }
+            engine
+        };
+        let results = |policy| {
+            let mut engine = locked(policy);
+            [
+                engine.try_apply(tx(TxType::Deposit, 3, 5)),
+                engine.try_apply(tx(TxType::Withdrawal, 4, 1)),
+                engine.try_apply(tx(TxType::Dispute, 3, 0)),
+            ]
+        };
+        let rejected = Err(RejectionReason::Locked);
+        assert_eq!(
+            results(LockedPolicy::RejectAll),
+            [rejected, rejected, rejected]
+        );
+        assert_eq!(
+            results(LockedPolicy::RejectWithdrawals),
+            [Ok(()), rejected, Ok(())]
+        );
+        assert_eq!(results(LockedPolicy::Allow), [Ok(()), Ok(()), Ok(())]);
+
+        // Disputes already open still settle, whatever the policy.
+        let mut engine = locked(LockedPolicy::RejectAll);
+        assert_eq!(engine.try_apply(tx(TxType::Resolve, 2, 0)), Ok(()));
+        assert_eq!(
+            engine.state().clients[&ClientId(1)].available(),
+            Currency::from_num(10)
+        );
+    }
+
+    #[test]
+    fn archival() {
+        let tx = |tx_type, cid, tid, amount: u32| {
+            TxRecord::new(tx_type, cid, tid, Currency::from_num(amount))
+        };
+        let mut engine = EngineBuilder::new().build();
+        for row in [
+            tx(TxType::Deposit, 1, 1, 10),
+            tx(TxType::Deposit, 2, 2, 5),
+            tx(TxType::Withdrawal, 2, 3, 5),
+        ] {
+            engine.try_apply(row).unwrap();
+        }
+        assert_eq!(
+            engine.try_apply(tx(TxType::ArchiveClient, 1, 4, 0)),
+            Err(RejectionReason::NotDormant)
+        );
+        assert_eq!(
+            engine.try_apply(tx(TxType::ArchiveClient, 9, 5, 0)),
+            Err(RejectionReason::UnknownClient)
+        );
+        assert_eq!(engine.try_apply(tx(TxType::ArchiveClient, 2, 6, 0)), Ok(()));
+        assert_eq!(engine.archived_clients(), 1);
+        assert_eq!(engine.output_states().len(), 1);
+        // Nothing reaches an archived client until it is reactivated.
+        assert_eq!(
+            engine.try_apply(tx(TxType::Deposit, 2, 7, 1)),
+            Err(RejectionReason::Archived)
+        );
+        assert_eq!(
+            engine.try_apply(tx(TxType::ReactivateClient, 1, 8, 0)),
+            Err(RejectionReason::NotArchived)
+        );
+        assert_eq!(
+            engine.try_apply(tx(TxType::ReactivateClient, 2, 9, 0)),
+            Ok(())
+        );
+        // Its history came back with it.
+        assert_eq!(
+            engine.try_apply(tx(TxType::Deposit, 2, 2, 1)),
+            Err(RejectionReason::ReusedId)
+        );
+        assert_eq!(engine.try_apply(tx(TxType::Deposit, 2, 7, 1)), Ok(()));
+
+        let mut engine = EngineBuilder::new().include_archived().build();
+        engine.try_apply(tx(TxType::Deposit, 1, 1, 1)).unwrap();
+        engine.try_apply(tx(TxType::Withdrawal, 1, 2, 1)).unwrap();
+        engine
+            .try_apply(tx(TxType::ArchiveClient, 1, 3, 0))
+            .unwrap();
+        assert_eq!(engine.output_states().len(), 1);
+    }
+
+    #[test]
+    fn tid_scopes() {
+        let deposit = |cid, tid| TxRecord::new(TxType::Deposit, cid, tid, Currency::from_num(1));
+        let results = |scope| {
+            let mut engine = EngineBuilder::new().tid_scope(scope).build();
+            let results = [
+                engine.try_apply(deposit(1, 1)),
+                engine.try_apply(deposit(1, 1)),
+                engine.try_apply(deposit(2, 1)),
+                engine.try_apply(TxRecord::new(TxType::Deposit, 2, 2, Currency::from_num(-1))),
+                engine.try_apply(deposit(2, 2)),
+            ];
+            (engine, results)
+        };
+        let (_, client) = results(TidScope::Client);
+        let reused = Err(RejectionReason::ReusedId);
+        let negative = Err(RejectionReason::InvalidAmount);
+        assert_eq!(client, [Ok(()), reused, Ok(()), negative, Ok(())]);
+        let (engine, global) = results(TidScope::Global);
+        assert_eq!(global, [Ok(()), reused, reused, negative, Ok(())]);
+
+        // The ids carry over a snapshot.
+        let mut restored = EngineBuilder::new().tid_scope(TidScope::Global).build();
+        restored.restore(engine.snapshot());
+        assert_eq!(restored.try_apply(deposit(3, 2)), reused);
+        assert_eq!(restored.try_apply(deposit(3, 3)), Ok(()));
+    }
+
+    #[test]
+    fn penalties_at_cutoffs() {
+        let mut engine = EngineBuilder::new()
+            .overdraft_rate(Currency::from_num(0.5))
+            .cutoff_every(3)
+            .build();
+        engine.apply(TxRecord::new(
+            TxType::Deposit,
+            1,
+            1,
+            Currency::from_num(10.0),
+        ));
+        engine.apply(TxRecord::new(
+            TxType::Withdrawal,
+            1,
+            2,
+            Currency::from_num(10.0),
+        ));
+        engine.apply(TxRecord::new(TxType::Dispute, 1, 1, Currency::default()));
+        // First cutoff after 3 transactions charged 0.5 * 10.
+        assert_eq!(engine.penalties_assessed(), Currency::from_num(5.0));
+        engine.finish();
+        // The last transaction closed a period, so finishing doesn't charge again.
+        assert_eq!(engine.penalties_assessed(), Currency::from_num(5.0));
+
+        engine.apply(TxRecord::new(
+            TxType::Deposit,
+            2,
+            3,
+            Currency::from_num(1.0),
+        ));
+        engine.finish();
+        assert_eq!(engine.penalties_assessed(), Currency::from_num(12.5));
+        assert_eq!(engine.output_columns().last(), Some(&"penalties"));
+    }
+
+    #[test]
+    fn dust_is_ignored_or_swept() {
+        let assets = AssetRegistry::parse(
+            "[[asset]]\ncode = \"BTC\"\ndecimals = 8\ndust = \"0.01\"\ndust_policy = \"sweep\"\n\n[[asset]]\ncode = \"DOGE\"\ndecimals = 8\ndust = \"1\"\n",
+        )
+        .unwrap();
+        let mut engine = EngineBuilder::new().assets(assets).build();
+        let deposit = |tid, amount: f64, currency| TxRecord {
+            currency: Some(currency),
+            ..TxRecord::new(TxType::Deposit, 1, tid, Currency::from_num(amount))
+        };
+        assert!(engine.apply(deposit(1, 1.0, "BTC")));
+        assert!(!engine.apply(deposit(2, 0.005, "BTC")));
+        assert!(!engine.apply(deposit(3, 0.005, "BTC")));
+        assert!(!engine.apply(deposit(4, 0.5, "DOGE")));
+        assert!(engine.apply(deposit(5, 0.5, "USD")));
+        assert_eq!(
+            engine.state().output_state(ClientId(1)).unwrap().available,
+            Currency::from_num(1.5)
+        );
+        let swept = engine.report().totals.swept;
+        assert_eq!(swept.len(), 1);
+        assert_eq!(swept["BTC"], Currency::from_num(0.01));
+    }
+
+    #[test]
+    fn balances_per_asset() {
+        let mut engine = EngineBuilder::new().build();
+        let row = |tx_type, cid, tid, amount: u32, currency| TxRecord {
+            currency,
+            ..TxRecord::new(tx_type, cid, tid, Currency::from_num(amount))
+        };
+        engine.apply(row(TxType::Deposit, 1, 1, 10, Some("USD")));
+        let columns = engine.output_columns();
+        assert_eq!(columns, ["client", "available", "held", "total", "locked"]);
+        engine.apply(row(TxType::Deposit, 1, 2, 1, Some("BTC")));
+        engine.apply(row(TxType::Deposit, 2, 3, 5, None));
+        assert_eq!(
+            engine.try_apply(row(TxType::Withdrawal, 1, 4, 2, Some("BTC"))),
+            Err(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(
+            engine.try_apply(row(TxType::Dispute, 1, 1, 0, Some("BTC"))),
+            Err(RejectionReason::AssetMismatch)
+        );
+        assert_eq!(
+            engine.try_apply(row(TxType::Dispute, 1, 1, 0, None)),
+            Ok(())
+        );
+        assert_eq!(
+            String::from_utf8(engine.report().balances_csv().unwrap()).unwrap(),
+            "client,currency,available,held,total,locked\n\
+             1,BTC,1.0000,0.0000,1.0000,false\n\
+             1,USD,0.0000,10.0000,10.0000,false\n\
+             2,,5.0000,0.0000,5.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn joint_owner_columns() {
+        let engine = EngineBuilder::new()
+            .joint_owners([(ClientId(2), ClientId(1))].into())
+            .build();
+        assert_eq!(engine.state().canonical(ClientId(2)), ClientId(1));
+        assert_eq!(engine.output_columns().last(), Some(&"owners"));
+    }
+
+    #[test]
+    fn settlement_delays_hold_deposits() {
+        let delays =
+            SettlementDelays::parse("[[delay]]\ncounterparty = \"visa\"\nrows = 2\n").unwrap();
+        let mut engine = EngineBuilder::new().settlement_delays(delays).build();
+        let row = |tx_type, tid, amount: u32, counterparty| TxRecord {
+            counterparty,
+            ..TxRecord::new(tx_type, 1, tid, Currency::from_num(amount))
+        };
+        assert!(engine.apply(row(TxType::Deposit, 1, 10, Some("visa"))));
+        let client = engine.balance(ClientId(1)).unwrap();
+        assert_eq!(
+            (client.available, client.total),
+            (Currency::from_num(0), Currency::from_num(10))
+        );
+        assert_eq!(
+            engine.try_apply(row(TxType::Withdrawal, 2, 5, None)),
+            Err(RejectionReason::InsufficientFunds)
+        );
+        assert!(engine.apply(row(TxType::Deposit, 3, 3, Some("amex"))));
+        // Two rows on, the deposit settles before the withdrawal applies.
+        assert!(engine.apply(row(TxType::Withdrawal, 4, 5, None)));
+        // A dispute of a pending deposit settles it and holds it like any other.
+        assert!(engine.apply(row(TxType::Deposit, 5, 4, Some("visa"))));
+        assert!(engine.apply(row(TxType::Dispute, 5, 0, None)));
+        assert!(engine.apply(row(TxType::Deposit, 6, 1, Some("visa"))));
+        assert_eq!(engine.report().totals.pending, Currency::from_num(1));
+        assert_eq!(
+            String::from_utf8(engine.report().balances_csv().unwrap()).unwrap(),
+            "client,available,held,total,locked,pending\n1,8.0000,4.0000,13.0000,false,1.0000\n"
+        );
+    }
+}
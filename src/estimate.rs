@@ -0,0 +1,271 @@
+use crate::engine::EngineBuilder;
+use crate::memory::MemoryUsage;
+use crate::schema::Schema;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::time::{Duration, Instant};
+use txcli_model::{ClientId, TxType};
+
+// How much of the input estimate reads: this many bytes from each of this many evenly spaced
+// places. Inputs no bigger than that are read whole and their counts are exact.
+pub const SAMPLE_PLACES: u64 = 16;
+pub const SAMPLE_BYTES: u64 = 64 * 1024;
+
+// The sampled rows as a csv of their own under the input's header, with what they cover.
+pub struct Sample {
+    csv: Vec<u8>,
+    rows: u64,
+    // Bytes of the input the sampled rows took, header excluded.
+    bytes: u64,
+    // Bytes of the input after the header.
+    input_bytes: u64,
+    places: u64,
+}
+
+impl Sample {
+    // Reads whole lines from places evenly spaced places of bytes each. A place starting inside a
+    // line skips to the next, and a line running past the place's end is left out, so the rows
+    // are never cut. Quoted fields with line breaks in them can still be split.
+    pub fn read(path: &str, places: u64, bytes: u64) -> Result<Self, Box<dyn Error>> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut csv = vec![];
+        let header = file.read_until(b'\n', &mut csv)? as u64;
+        let input_bytes = file.get_ref().metadata()?.len() - header;
+        if input_bytes <= places * bytes {
+            let read = file.read_to_end(&mut csv)? as u64;
+            let rows = csv[header as usize..]
+                .split(|b| *b == b'\n')
+                .filter(|line| !line.is_empty())
+                .count() as u64;
+            return Ok(Sample {
+                csv,
+                rows,
+                bytes: read,
+                input_bytes,
+                places: 1,
+            });
+        }
+
+        let mut sample = Sample {
+            csv,
+            rows: 0,
+            bytes: 0,
+            input_bytes,
+            places,
+        };
+        let mut chunk = vec![];
+        for place in 0..places {
+            let offset = header + place * input_bytes / places;
+            file.seek(SeekFrom::Start(offset))?;
+            chunk.clear();
+            (&mut file).take(bytes).read_to_end(&mut chunk)?;
+            let start = match place {
+                0 => 0,
+                _ => chunk
+                    .iter()
+                    .position(|b| *b == b'\n')
+                    .map_or(chunk.len(), |i| i + 1),
+            };
+            let end = chunk.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+            if start >= end {
+                continue;
+            }
+            let lines = &chunk[start..end];
+            sample.rows += lines
+                .split(|b| *b == b'\n')
+                .filter(|line| !line.is_empty())
+                .count() as u64;
+            sample.bytes += lines.len() as u64;
+            sample.csv.extend_from_slice(lines);
+        }
+        Ok(sample)
+    }
+
+    pub fn exact(&self) -> bool {
+        self.bytes == self.input_bytes
+    }
+}
+
+#[derive(Debug)]
+pub struct Estimate {
+    pub rows: u64,
+    pub clients: u64,
+    // Share of the rows that are disputes.
+    pub dispute_density: f64,
+    pub memory_bytes: u64,
+    pub runtime: Duration,
+    // Rows a second the sample was parsed and applied at.
+    pub rows_per_sec: f64,
+    pub exact: bool,
+    sampled_rows: u64,
+    places: u64,
+    sampled_bytes: u64,
+    input_bytes: u64,
+}
+
+// Distinct values in a population of total from counts of each value seen in a sample of
+// sampled, by the guaranteed error estimator (Charikar et al.): values seen twice or more are
+// assumed to be all there is of them, values seen once stand for sqrt(total / sampled) each.
+fn distinct(counts: &HashMap<ClientId, u64>, sampled: u64, total: u64) -> u64 {
+    let once = counts.values().filter(|count| **count == 1).count() as f64;
+    let more = counts.values().filter(|count| **count > 1).count() as f64;
+    let scale = (total as f64 / sampled.max(1) as f64).sqrt();
+    (more + once * scale).round() as u64
+}
+
+impl Estimate {
+    // Parses and applies the sample on a fresh engine, the calibration pass the runtime is
+    // extrapolated from. The sampled rows come from all over the input, so most disputes in it
+    // miss their transaction and the state is smaller than a real run's at the same row: runtime
+    // and memory are a lower bound for inputs heavy on disputes, and larger inputs run slower
+    // once the state outgrows the caches.
+    pub fn of(sample: &Sample, schema: Option<Schema>) -> Result<Self, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(sample.csv.as_slice());
+        let schema = match schema {
+            Some(schema) => schema,
+            None => Schema::detect(reader.headers()?),
+        };
+        let mut rows = schema.reader(reader.headers()?)?;
+        let mut engine = EngineBuilder::new().build();
+        let mut counts: HashMap<ClientId, u64> = HashMap::new();
+        let mut disputes = 0;
+        let started = Instant::now();
+        while let Some(row) = rows.next(&mut reader) {
+            let Ok(tx) = row else { continue };
+            *counts.entry(tx.cid).or_default() += 1;
+            if tx.tx_type == TxType::Dispute {
+                disputes += 1;
+            }
+            engine.apply(tx);
+        }
+        engine.finish();
+        let elapsed = started.elapsed();
+
+        let sampled = sample.rows.max(1);
+        let total = match sample.exact() {
+            true => sample.rows,
+            false => (sample.rows as f64 * sample.input_bytes as f64 / sample.bytes.max(1) as f64)
+                .round() as u64,
+        };
+        let clients = match sample.exact() {
+            true => counts.len() as u64,
+            false => distinct(&counts, sampled, total)
+                .min(total)
+                .min(u16::MAX as u64 + 1),
+        };
+        let usage = MemoryUsage::of(engine.state());
+        let per_row = (usage.history + usage.disputed) as f64 / sampled as f64;
+        let per_client = (usage.clients + usage.accounts) as f64 / counts.len().max(1) as f64;
+        let rows_per_sec = sample.rows as f64 / elapsed.as_secs_f64().max(1e-9);
+        Ok(Estimate {
+            rows: total,
+            clients,
+            dispute_density: disputes as f64 / sampled as f64,
+            memory_bytes: (per_row * total as f64 + per_client * clients as f64) as u64,
+            runtime: Duration::from_secs_f64(total as f64 / rows_per_sec),
+            rows_per_sec,
+            exact: sample.exact(),
+            sampled_rows: sample.rows,
+            places: sample.places,
+            sampled_bytes: sample.bytes,
+            input_bytes: sample.input_bytes,
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        let about = if self.exact { "" } else { "~" };
+        match self.exact {
+            true => writeln!(
+                writer,
+                "sample: the whole input, {} rows in {} bytes",
+                self.sampled_rows, self.input_bytes
+            )?,
+            false => writeln!(
+                writer,
+                "sample: {} rows from {} places, {} of {} bytes",
+                self.sampled_rows, self.places, self.sampled_bytes, self.input_bytes
+            )?,
+        }
+        writeln!(writer, "rows: {}{}", about, self.rows)?;
+        writeln!(writer, "distinct clients: {}{}", about, self.clients)?;
+        writeln!(
+            writer,
+            "dispute density: {:.2}% of rows",
+            self.dispute_density * 100.0
+        )?;
+        writeln!(
+            writer,
+            "memory: ~{} bytes of engine state",
+            self.memory_bytes
+        )?;
+        writeln!(
+            writer,
+            "runtime: ~{:.1}s on this machine, calibrated at {:.0} rows/s",
+            self.runtime.as_secs_f64(),
+            self.rows_per_sec
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn input(name: &str, rows: u32, clients: u32) -> String {
+        let path =
+            std::env::temp_dir().join(format!("txcli-estimate-{}-{}", std::process::id(), name));
+        let mut csv = String::from("type,client,tx,amount\n");
+        for tid in 1..=rows {
+            match tid % 10 {
+                0 => csv += &format!("dispute,{},{},\n", (tid - 1) % clients + 1, tid - 1),
+                _ => csv += &format!("deposit,{},{},1.5\n", tid % clients + 1, tid),
+            }
+        }
+        fs::write(&path, csv).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn small_inputs_are_counted_exactly() {
+        let path = input("small", 100, 7);
+        let sample = Sample::read(&path, SAMPLE_PLACES, SAMPLE_BYTES).unwrap();
+        let estimate = Estimate::of(&sample, None).unwrap();
+        assert!(estimate.exact);
+        assert_eq!(estimate.rows, 100);
+        assert_eq!(estimate.clients, 7);
+        assert!((estimate.dispute_density - 0.1).abs() < 1e-9);
+        assert!(estimate.memory_bytes > 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn large_inputs_are_extrapolated() {
+        let path = input("large", 20_000, 50);
+        let sample = Sample::read(&path, 8, 1024).unwrap();
+        assert!(!sample.exact());
+        assert!(sample.rows < 20_000);
+        // Every sampled line is whole.
+        let text = String::from_utf8(sample.csv.clone()).unwrap();
+        assert!(text
+            .lines()
+            .skip(1)
+            .all(|line| line.split(',').count() == 4));
+
+        let estimate = Estimate::of(&sample, None).unwrap();
+        assert!((18_000..22_000).contains(&estimate.rows), "{:?}", estimate);
+        assert!((40..=60).contains(&estimate.clients), "{:?}", estimate);
+        assert!((estimate.dispute_density - 0.1).abs() < 0.02);
+        let mut out = vec![];
+        estimate.write(&mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("rows: ~"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,396 @@
+use crate::BasicError;
+use serde::Serialize;
+use std::error::Error;
+use std::io::Write;
+use std::str::FromStr;
+use txcli_model::{
+    precision4_serialize_currency, precision4_serialize_optional_currency, ClientId,
+    ClientOutputState, Currency, TxId, TxRecord, TxType,
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Subject {
+    // One transaction id: the transaction and every row referencing it.
+    Tx(TxId),
+    // Every row of one client, for working out how it got to its balances.
+    Client(ClientId),
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(BasicError::new(format!(
+                "Unknown format {}, expected text or json.",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Default, Clone, Copy)]
+struct Balances {
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    available: Currency,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    held: Currency,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    total: Currency,
+    locked: bool,
+}
+
+impl Balances {
+    // A client that doesn't exist yet has nothing, not even a lock.
+    fn of(state: Option<&ClientOutputState>) -> Self {
+        state.map_or(Balances::default(), |state| Balances {
+            available: state.available,
+            held: state.held,
+            total: state.total,
+            locked: state.locked,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Event {
+    line: u64,
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    client: ClientId,
+    tx: TxId,
+    account: String,
+    // Only rows that move funds carry an amount, disputes and the rest act on the original's.
+    #[serde(
+        serialize_with = "precision4_serialize_optional_currency",
+        skip_serializing_if = "Option::is_none"
+    )]
+    amount: Option<Currency>,
+    // Only notes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memo: Option<String>,
+    applied: bool,
+    before: Balances,
+    after: Balances,
+}
+
+#[derive(Serialize)]
+struct Explanation<'a> {
+    events: &'a [Event],
+    status: String,
+}
+
+// Collects the rows concerning one subject while the input is processed, applied or not,
+// with the client's balances around each of them.
+pub struct Explainer {
+    subject: Subject,
+    events: Vec<Event>,
+}
+
+impl Explainer {
+    pub fn new(subject: Subject) -> Self {
+        Explainer {
+            subject,
+            events: vec![],
+        }
+    }
+
+    pub fn wants(&self, tx: &TxRecord) -> bool {
+        match self.subject {
+            Subject::Tx(tid) => tx.tid == tid,
+            Subject::Client(cid) => tx.cid == cid,
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        line: u64,
+        tx: &TxRecord,
+        applied: bool,
+        before: Option<&ClientOutputState>,
+        after: Option<&ClientOutputState>,
+    ) {
+        let moves_funds = matches!(
+            tx.tx_type,
+            TxType::Deposit | TxType::Withdrawal | TxType::Transfer
+        );
+        self.events.push(Event {
+            line,
+            tx_type: tx.tx_type,
+            client: tx.cid,
+            tx: tx.tid,
+            account: tx.account.to_string(),
+            amount: moves_funds.then_some(tx.amount),
+            memo: tx.memo.map(String::from),
+            applied,
+            before: Balances::of(before),
+            after: Balances::of(after),
+        });
+    }
+
+    fn status(&self) -> String {
+        let mut applied = self.events.iter().filter(|event| event.applied);
+        match self.subject {
+            // Where the transaction ended up.
+            Subject::Tx(_) => applied
+                .fold("never applied", |status, event| match event.tx_type {
+                    TxType::Dispute => "disputed",
+                    TxType::Resolve => "resolved",
+                    TxType::ChargeBack => "charged back",
+                    TxType::Void => "voided",
+                    _ if status == "never applied" => "applied",
+                    _ => status,
+                })
+                .to_string(),
+            // Why the client is locked, if it is.
+            Subject::Client(_) => {
+                match applied.find(|event| event.after.locked && !event.before.locked) {
+                    Some(event) => format!(
+                        "locked by the chargeback of tx {} on line {}",
+                        event.tx.0, event.line
+                    ),
+                    None => "not locked".to_string(),
+                }
+            }
+        }
+    }
+
+    fn write_event<W: Write>(&self, writer: &mut W, event: &Event) -> Result<(), Box<dyn Error>> {
+        write!(writer, "line {}: {}", event.line, event.tx_type)?;
+        match self.subject {
+            Subject::Tx(_) => write!(writer, " by client {}", event.client.0)?,
+            Subject::Client(_) => write!(writer, " of tx {}", event.tx.0)?,
+        }
+        write!(writer, " on account {}", event.account)?;
+        if let Some(amount) = event.amount {
+            write!(writer, " of {:.4}", amount)?;
+        }
+        if let Some(memo) = &event.memo {
+            write!(writer, " {:?}", memo)?;
+        }
+        if !event.applied {
+            writeln!(writer, ", rejected")?;
+            return Ok(());
+        }
+        // Notes move nothing.
+        if event.tx_type == TxType::Note {
+            writeln!(writer, ", applied")?;
+            return Ok(());
+        }
+        let (before, after) = (event.before, event.after);
+        write!(
+            writer,
+            ", applied. available {:.4} -> {:.4}, held {:.4} -> {:.4}, total {:.4} -> {:.4}",
+            before.available, after.available, before.held, after.held, before.total, after.total
+        )?;
+        if after.locked && !before.locked {
+            write!(writer, ", client locked")?;
+        }
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W, format: Format) -> Result<(), Box<dyn Error>> {
+        if self.events.is_empty() {
+            return Err(BasicError::new(match self.subject {
+                Subject::Tx(tid) => format!("Transaction {} does not appear in the input.", tid.0),
+                Subject::Client(cid) => format!("Client {} does not appear in the input.", cid.0),
+            }));
+        }
+        match format {
+            Format::Json => {
+                serde_json::to_writer_pretty(
+                    &mut writer,
+                    &Explanation {
+                        events: &self.events,
+                        status: self.status(),
+                    },
+                )?;
+                writeln!(writer)?;
+            }
+            Format::Text => {
+                match self.subject {
+                    Subject::Tx(tid) => writeln!(writer, "tx {}", tid.0)?,
+                    Subject::Client(cid) => writeln!(writer, "client {}", cid.0)?,
+                }
+                for event in &self.events {
+                    self.write_event(&mut writer, event)?;
+                }
+                writeln!(writer, "status: {}", self.status())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(available: f64, held: f64, locked: bool) -> Option<ClientOutputState> {
+        Some(ClientOutputState {
+            cid: ClientId(1),
+            currency: None,
+            account: None,
+            available: Currency::from_num(available),
+            held: Currency::from_num(held),
+            total: Currency::from_num(available + held),
+            locked,
+            owners: None,
+            penalties: None,
+            chargeback_fees: None,
+            written_off: None,
+            dispute_count: None,
+            pending: None,
+        })
+    }
+
+    fn chargeback_lifecycle(subject: Subject) -> Explainer {
+        let deposit =
+            |cid, tid, amount| TxRecord::new(TxType::Deposit, cid, tid, Currency::from_num(amount));
+        let refer = |tx_type, tid| TxRecord::new(tx_type, 1, tid, Currency::default());
+        let rows = [
+            (2, deposit(1, 5, 10), true, None, state(10.0, 0.0, false)),
+            (
+                3,
+                deposit(1, 6, 1),
+                true,
+                state(10.0, 0.0, false),
+                state(11.0, 0.0, false),
+            ),
+            (
+                4,
+                refer(TxType::Resolve, 5),
+                false,
+                state(11.0, 0.0, false),
+                state(11.0, 0.0, false),
+            ),
+            (
+                5,
+                refer(TxType::Dispute, 5),
+                true,
+                state(11.0, 0.0, false),
+                state(1.0, 10.0, false),
+            ),
+            (6, deposit(2, 7, 3), true, None, state(3.0, 0.0, false)),
+            (
+                7,
+                TxRecord {
+                    memo: Some("says the card was stolen"),
+                    ..refer(TxType::Note, 8)
+                },
+                true,
+                state(1.0, 10.0, false),
+                state(1.0, 10.0, false),
+            ),
+            (
+                8,
+                refer(TxType::ChargeBack, 5),
+                true,
+                state(1.0, 10.0, false),
+                state(1.0, 0.0, true),
+            ),
+        ];
+        let mut explainer = Explainer::new(subject);
+        for (line, tx, applied, before, after) in rows {
+            if explainer.wants(&tx) {
+                explainer.record(line, &tx, applied, before.as_ref(), after.as_ref());
+            }
+        }
+        explainer
+    }
+
+    #[test]
+    fn tx_lifecycle() {
+        let mut out = vec![];
+        chargeback_lifecycle(Subject::Tx(TxId(5)))
+            .write(&mut out, Format::Text)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "tx 5\n\
+             line 2: deposit by client 1 on account default of 10.0000, applied. available 0.0000 -> 10.0000, held 0.0000 -> 0.0000, total 0.0000 -> 10.0000\n\
+             line 4: resolve by client 1 on account default, rejected\n\
+             line 5: dispute by client 1 on account default, applied. available 11.0000 -> 1.0000, held 0.0000 -> 10.0000, total 11.0000 -> 11.0000\n\
+             line 8: chargeback by client 1 on account default, applied. available 1.0000 -> 1.0000, held 10.0000 -> 0.0000, total 11.0000 -> 1.0000, client locked\n\
+             status: charged back\n"
+        );
+    }
+
+    #[test]
+    fn client_timeline() {
+        let mut out = vec![];
+        chargeback_lifecycle(Subject::Client(ClientId(1)))
+            .write(&mut out, Format::Text)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "client 1");
+        assert!(
+            lines[2].starts_with("line 3: deposit of tx 6 on account default of 1.0000, applied.")
+        );
+        assert_eq!(
+            lines[3],
+            "line 4: resolve of tx 5 on account default, rejected"
+        );
+        assert_eq!(
+            lines[5],
+            "line 7: note of tx 8 on account default \"says the card was stolen\", applied"
+        );
+        assert_eq!(
+            lines[7],
+            "status: locked by the chargeback of tx 5 on line 8"
+        );
+    }
+
+    #[test]
+    fn json_output() {
+        let mut out = vec![];
+        chargeback_lifecycle(Subject::Client(ClientId(1)))
+            .write(&mut out, Format::Json)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["status"], "locked by the chargeback of tx 5 on line 8");
+        let events = json["events"].as_array().unwrap();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0]["type"], "deposit");
+        assert_eq!(events[0]["amount"], "10.0000");
+        assert_eq!(events[0]["after"]["available"], "10.0000");
+        assert_eq!(events[2]["applied"], false);
+        assert!(events[2].get("amount").is_none());
+        assert_eq!(events[4]["memo"], "says the card was stolen");
+        assert!(events[0].get("memo").is_none());
+        assert_eq!(events[5]["after"]["locked"], true);
+    }
+
+    #[test]
+    fn never_applied_and_missing() {
+        let mut explainer = Explainer::new(Subject::Tx(TxId(3)));
+        assert!(explainer.write(vec![], Format::Text).is_err());
+        explainer.record(
+            9,
+            &TxRecord::new(TxType::Dispute, 2, 3, Currency::default()),
+            false,
+            None,
+            None,
+        );
+        let mut out = vec![];
+        explainer.write(&mut out, Format::Text).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("rejected\nstatus: never applied\n"));
+        assert!("xml".parse::<Format>().is_err());
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+    }
+}
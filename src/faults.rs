@@ -0,0 +1,169 @@
+// Fault injection for the negative tests in tests/faults.rs, only built with the faults feature:
+// cargo test --features faults --test faults
+//
+// TXCLI_FAULTS lists the faults of a run, comma separated, each with the byte it strikes at:
+//
+// read-error@4096          reading the input fails once 4096 bytes of it were read
+// disk-full@100            writing --output or --snapshot-out fails as out of space at byte 100
+// snapshot-interrupt@50    the process dies once 50 bytes of the snapshot reached its file
+//
+// Inputs are counted across the run, written files each on their own.
+use crate::BasicError;
+use std::error::Error;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+const VARIABLE: &str = "TXCLI_FAULTS";
+
+#[derive(Debug, Default, PartialEq)]
+struct Faults {
+    read_error: Option<u64>,
+    disk_full: Option<u64>,
+    snapshot_interrupt: Option<u64>,
+}
+
+static FAULTS: OnceLock<Faults> = OnceLock::new();
+static READ: AtomicU64 = AtomicU64::new(0);
+
+fn parse(list: &str) -> Result<Faults, Box<dyn Error>> {
+    let mut faults = Faults::default();
+    for fault in list
+        .split(',')
+        .map(str::trim)
+        .filter(|fault| !fault.is_empty())
+    {
+        let invalid = || {
+            BasicError::new(format!(
+                "Invalid fault {} in {}, expected read-error, disk-full or snapshot-interrupt @<bytes>.",
+                fault, VARIABLE
+            ))
+        };
+        let (name, at) = fault.split_once('@').ok_or_else(invalid)?;
+        let at = Some(at.parse().map_err(|_| invalid())?);
+        match name {
+            "read-error" => faults.read_error = at,
+            "disk-full" => faults.disk_full = at,
+            "snapshot-interrupt" => faults.snapshot_interrupt = at,
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(faults)
+}
+
+// Reads TXCLI_FAULTS, before the run touches any file.
+pub fn init() -> Result<(), Box<dyn Error>> {
+    let faults = match std::env::var(VARIABLE) {
+        Ok(list) => parse(&list)?,
+        Err(_) => Faults::default(),
+    };
+    let _ = FAULTS.set(faults);
+    Ok(())
+}
+
+fn faults() -> &'static Faults {
+    FAULTS.get_or_init(Faults::default)
+}
+
+// Passes a read of the input on up to the read-error byte, and fails every read after it. The
+// bytes read past it are dropped, the error comes before them.
+pub fn input(read: io::Result<usize>) -> io::Result<usize> {
+    let read = read? as u64;
+    let Some(at) = faults().read_error else {
+        return Ok(read as usize);
+    };
+    let before = READ.fetch_add(read, Ordering::Relaxed).min(at);
+    if before == at {
+        return Err(io::Error::other(format!(
+            "injected read error at byte {}",
+            at
+        )));
+    }
+    Ok(read.min(at - before) as usize)
+}
+
+// A file txcli writes, failing as the faults say.
+pub struct Sink<W> {
+    inner: W,
+    written: u64,
+    full: Option<u64>,
+    // Only for snapshots.
+    interrupt: Option<u64>,
+}
+
+pub fn output<W: Write>(inner: W) -> Sink<W> {
+    Sink {
+        inner,
+        written: 0,
+        full: faults().disk_full,
+        interrupt: None,
+    }
+}
+
+pub fn snapshot<W: Write>(inner: W) -> Sink<W> {
+    Sink {
+        interrupt: faults().snapshot_interrupt,
+        ..output(inner)
+    }
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let limit = self.full.into_iter().chain(self.interrupt).min();
+        let room = limit.map_or(buf.len() as u64, |limit| {
+            limit.saturating_sub(self.written).min(buf.len() as u64)
+        });
+        if room == 0 && !buf.is_empty() {
+            if self.interrupt == limit {
+                // As if killed, nothing after this point runs, not even destructors.
+                let _ = self.inner.flush();
+                std::process::abort();
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                "injected disk full",
+            ));
+        }
+        let written = self.inner.write(&buf[..room as usize])?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fault_list() {
+        assert_eq!(
+            parse("read-error@10, snapshot-interrupt@3").unwrap(),
+            Faults {
+                read_error: Some(10),
+                disk_full: None,
+                snapshot_interrupt: Some(3),
+            }
+        );
+        assert_eq!(parse("").unwrap(), Faults::default());
+        for invalid in ["read-error", "disk-full@many", "power-cut@1"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn disk_full_at_the_byte() {
+        let mut sink = Sink {
+            inner: vec![],
+            written: 0,
+            full: Some(5),
+            interrupt: Some(9),
+        };
+        let err = sink.write_all(b"0123456").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(sink.inner, b"01234");
+    }
+}
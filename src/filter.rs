@@ -0,0 +1,124 @@
+use crate::{AppState, BasicError};
+use serde::Serialize;
+use std::error::Error;
+use std::str::FromStr;
+use txcli_model::ClientOutputState;
+
+// Which rows --output-filter keeps in the balances, the problem accounts an operational run
+// cares about.
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFilter {
+    Locked,
+    // Something of the row's account is under dispute, whatever the amount held.
+    HasOpenDisputes,
+    // Available below zero, after overdraft penalties or a dispute of spent funds.
+    Negative,
+}
+
+impl FromStr for OutputFilter {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "locked" => Ok(OutputFilter::Locked),
+            "has-open-disputes" => Ok(OutputFilter::HasOpenDisputes),
+            "negative" => Ok(OutputFilter::Negative),
+            _ => Err(BasicError::new(format!(
+                "Unknown --output-filter {}, expected locked, has-open-disputes or negative.",
+                s
+            ))),
+        }
+    }
+}
+
+impl OutputFilter {
+    fn matches(&self, state: &AppState, row: &ClientOutputState) -> bool {
+        match self {
+            OutputFilter::Locked => row.locked,
+            OutputFilter::HasOpenDisputes => state.clients.get(&row.cid).is_some_and(|client| {
+                client.disputed.values().any(|disputed| {
+                    row.account
+                        .as_ref()
+                        .is_none_or(|account| *account == disputed.account)
+                })
+            }),
+            OutputFilter::Negative => row.available < 0,
+        }
+    }
+}
+
+// Whether a balance row is printed, rows matching any of the filters are, every row without one.
+pub fn keeps(filters: &[OutputFilter], state: &AppState, row: &ClientOutputState) -> bool {
+    filters.is_empty() || filters.iter().any(|filter| filter.matches(state, row))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineBuilder, LockedPolicy};
+    use txcli_model::{Currency, TxRecord, TxType};
+
+    #[test]
+    fn problem_accounts_only() {
+        let mut engine = EngineBuilder::new()
+            .locked_policy(LockedPolicy::Allow)
+            .build();
+        let tx = |tx_type, cid, tid, amount: u32| {
+            TxRecord::new(tx_type, cid, tid, Currency::from_num(amount))
+        };
+        for tx in [
+            // Healthy.
+            tx(TxType::Deposit, 1, 1, 10),
+            // Disputed after spending, so negative as well.
+            tx(TxType::Deposit, 2, 2, 10),
+            tx(TxType::Withdrawal, 2, 3, 8),
+            tx(TxType::Dispute, 2, 2, 0),
+            // Charged back, locked.
+            tx(TxType::Deposit, 3, 4, 10),
+            tx(TxType::Dispute, 3, 4, 0),
+            tx(TxType::ChargeBack, 3, 4, 0),
+            // Disputed in savings only.
+            TxRecord::new(TxType::Deposit, 4, 5, Currency::from_num(1)).with_account("savings"),
+            tx(TxType::Deposit, 4, 6, 1),
+            tx(TxType::Dispute, 4, 5, 0),
+        ] {
+            engine.apply(tx);
+        }
+        let report = engine.report();
+        let kept = |filters: &[OutputFilter]| -> Vec<(u16, Option<String>)> {
+            report
+                .clients
+                .iter()
+                .filter(|row| keeps(filters, engine.state(), row))
+                .map(|row| {
+                    (
+                        row.cid.0,
+                        row.account.as_ref().map(|account| account.0.clone()),
+                    )
+                })
+                .collect()
+        };
+        let clients = |filters: &[OutputFilter]| {
+            let mut clients: Vec<u16> = kept(filters).into_iter().map(|(cid, _)| cid).collect();
+            clients.sort_unstable();
+            clients.dedup();
+            clients
+        };
+        assert_eq!(clients(&[]), [1, 2, 3, 4]);
+        assert_eq!(clients(&[OutputFilter::Locked]), [3]);
+        assert_eq!(clients(&[OutputFilter::HasOpenDisputes]), [2, 4]);
+        assert_eq!(clients(&[OutputFilter::Negative]), [2]);
+        assert_eq!(
+            clients(&[OutputFilter::Locked, OutputFilter::Negative]),
+            [2, 3]
+        );
+        assert_eq!(
+            kept(&[OutputFilter::HasOpenDisputes])
+                .into_iter()
+                .filter(|(cid, _)| *cid == 4)
+                .collect::<Vec<_>>(),
+            [(4, Some("savings".to_string()))]
+        );
+    }
+}
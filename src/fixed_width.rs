@@ -0,0 +1,408 @@
+use crate::source::COLUMNS;
+use crate::BasicError;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Read};
+
+// Layout file format for --input-format fixed-width, where every record has its fields at fixed
+// byte positions:
+//
+// encoding = "ebcdic"      # code page 037, ascii (the default) reads bytes as they are
+// record_length = 80       # records back to back without line breaks, one per line if left out
+//
+// [[field]]
+// column = "type"          # the csv column it fills, named as in a v2 header
+// offset = 0               # bytes from the start of the record
+// length = 10
+//
+// [[field]]
+// column = "amount"
+// offset = 27
+// length = 11
+// kind = "decimal"         # text (the default), integer or decimal
+// scale = 2                # decimal places implied after the last digit, 0 if left out
+//
+// Fields are trimmed of spaces. Integers lose their leading zeros. Decimals take a sign before or
+// after the digits or overpunched on the last one ({ and A-I positive, } and J-R negative), and
+// keep a decimal point as written instead of the implied one. A blank field is an empty column.
+// Type codes other than txcli's own can be renamed with --fixups.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutFile {
+    #[serde(default)]
+    encoding: Encoding,
+    record_length: Option<usize>,
+    #[serde(default, rename = "field")]
+    fields: Vec<RawField>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    #[default]
+    Ascii,
+    Ebcdic,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    #[default]
+    Text,
+    Integer,
+    Decimal,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawField {
+    column: String,
+    offset: usize,
+    length: usize,
+    #[serde(default)]
+    kind: Kind,
+    #[serde(default)]
+    scale: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    // Index into source::COLUMNS.
+    column: usize,
+    offset: usize,
+    length: usize,
+    kind: Kind,
+    scale: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Layout {
+    encoding: Encoding,
+    record_length: Option<usize>,
+    fields: Vec<Field>,
+}
+
+// Code page 037 for the characters transaction files hold, anything else is refused rather than
+// guessed at.
+fn ebcdic(byte: u8) -> Option<char> {
+    let from = |first: u8, letters: &str| letters.chars().nth((byte - first) as usize);
+    match byte {
+        0x40 => Some(' '),
+        0x4B => Some('.'),
+        0x4E => Some('+'),
+        0x60 => Some('-'),
+        0x61 => Some('/'),
+        0x6B => Some(','),
+        0x6D => Some('_'),
+        0x7A => Some(':'),
+        0xC0 => Some('{'),
+        0xD0 => Some('}'),
+        0x81..=0x89 => from(0x81, "abcdefghi"),
+        0x91..=0x99 => from(0x91, "jklmnopqr"),
+        0xA2..=0xA9 => from(0xA2, "stuvwxyz"),
+        0xC1..=0xC9 => from(0xC1, "ABCDEFGHI"),
+        0xD1..=0xD9 => from(0xD1, "JKLMNOPQR"),
+        0xE2..=0xE9 => from(0xE2, "STUVWXYZ"),
+        0xF0..=0xF9 => from(0xF0, "0123456789"),
+        _ => None,
+    }
+}
+
+// The digit and sign an overpunched last character stands for.
+fn overpunch(c: char) -> Option<(char, bool)> {
+    match c {
+        '{' => Some(('0', false)),
+        '}' => Some(('0', true)),
+        'A'..='I' => Some(((b'1' + (c as u8 - b'A')) as char, false)),
+        'J'..='R' => Some(((b'1' + (c as u8 - b'J')) as char, true)),
+        _ => None,
+    }
+}
+
+fn decimal(value: &str, scale: usize) -> Result<String, String> {
+    let invalid = || format!("invalid decimal {:?}", value);
+    let (mut negative, mut digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest.to_string()),
+        None => (false, value.strip_prefix('+').unwrap_or(value).to_string()),
+    };
+    if let Some(rest) = digits.strip_suffix('-') {
+        (negative, digits) = (true, rest.to_string());
+    } else if let Some(rest) = digits.strip_suffix('+') {
+        digits = rest.to_string();
+    } else if let Some((digit, minus)) = digits.chars().last().and_then(overpunch) {
+        digits.pop();
+        digits.push(digit);
+        negative |= minus;
+    }
+    let points = digits.chars().filter(|c| *c == '.').count();
+    if digits.is_empty() || points > 1 || !digits.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return Err(invalid());
+    }
+    let sign = if negative { "-" } else { "" };
+    if points == 1 || scale == 0 {
+        return Ok(format!("{}{}", sign, digits));
+    }
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (units, places) = digits.split_at(digits.len() - scale);
+    Ok(format!("{}{}.{}", sign, units, places))
+}
+
+impl Layout {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Layout::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let file: LayoutFile = toml::from_str(input)?;
+        let mut fields: Vec<Field> = vec![];
+        for raw in file.fields {
+            let Some(column) = COLUMNS.iter().position(|column| *column == raw.column) else {
+                return Err(BasicError::new(format!(
+                    "Unknown layout column {}, expected one of {}.",
+                    raw.column,
+                    COLUMNS.join(", ")
+                )));
+            };
+            if fields.iter().any(|field| field.column == column) {
+                return Err(BasicError::new(format!(
+                    "Layout column {} is given twice.",
+                    raw.column
+                )));
+            }
+            if raw.length == 0 {
+                return Err(BasicError::new(format!(
+                    "Layout column {} has length 0.",
+                    raw.column
+                )));
+            }
+            if file
+                .record_length
+                .is_some_and(|length| raw.offset + raw.length > length)
+            {
+                return Err(BasicError::new(format!(
+                    "Layout column {} runs past the end of the record.",
+                    raw.column
+                )));
+            }
+            fields.push(Field {
+                column,
+                offset: raw.offset,
+                length: raw.length,
+                kind: raw.kind,
+                scale: raw.scale,
+            });
+        }
+        if let Some(missing) = ["type", "client", "tx"]
+            .into_iter()
+            .find(|name| !fields.iter().any(|field| COLUMNS[field.column] == *name))
+        {
+            return Err(BasicError::new(format!(
+                "The layout has no {} column, every record needs one.",
+                missing
+            )));
+        }
+        if file.encoding == Encoding::Ebcdic && file.record_length.is_none() {
+            return Err(BasicError::new(
+                "An ebcdic layout needs a record_length, its records have no line breaks to split at.",
+            ));
+        }
+        Ok(Layout {
+            encoding: file.encoding,
+            record_length: file.record_length,
+            fields,
+        })
+    }
+
+    // The records of reader with the byte each starts at: record_length bytes at a time, or lines
+    // without their line break, blank ones skipped.
+    pub fn records<R: BufRead>(
+        &self,
+        mut reader: R,
+    ) -> impl Iterator<Item = io::Result<(u64, Vec<u8>)>> {
+        let record_length = self.record_length;
+        let mut position = 0;
+        std::iter::from_fn(move || loop {
+            let start = position;
+            let mut record = vec![];
+            let read = match record_length {
+                Some(length) => reader.by_ref().take(length as u64).read_to_end(&mut record),
+                None => reader.read_until(b'\n', &mut record),
+            };
+            let read = match read {
+                Ok(read) => read,
+                Err(err) => return Some(Err(err)),
+            };
+            position += read as u64;
+            if read == 0 {
+                return None;
+            }
+            if let Some(length) = record_length.filter(|length| read < *length) {
+                return Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "the last record, at byte {}, is {} bytes short",
+                        start,
+                        length - read
+                    ),
+                )));
+            }
+            if record_length.is_none() {
+                while record
+                    .last()
+                    .is_some_and(|byte| *byte == b'\n' || *byte == b'\r')
+                {
+                    record.pop();
+                }
+                if record.iter().all(|byte| *byte == b' ') {
+                    continue;
+                }
+            }
+            return Some(Ok((start, record)));
+        })
+    }
+
+    // One record as the row of source::COLUMNS it fills. Errors name the field and where it is.
+    pub fn row(&self, record: &[u8]) -> Result<csv::StringRecord, String> {
+        let mut row = vec![String::new(); COLUMNS.len()];
+        for field in &self.fields {
+            let name = COLUMNS[field.column];
+            let at = || {
+                format!(
+                    "{} at bytes {}-{}",
+                    name,
+                    field.offset,
+                    field.offset + field.length - 1
+                )
+            };
+            let Some(bytes) = record.get(field.offset..field.offset + field.length) else {
+                return Err(format!(
+                    "{}: the record is only {} bytes",
+                    at(),
+                    record.len()
+                ));
+            };
+            let text: String = match self.encoding {
+                Encoding::Ascii => match std::str::from_utf8(bytes) {
+                    Ok(text) => text.to_string(),
+                    Err(_) => return Err(format!("{}: not ascii", at())),
+                },
+                Encoding::Ebcdic => {
+                    let mut text = String::new();
+                    for byte in bytes {
+                        match ebcdic(*byte) {
+                            Some(c) => text.push(c),
+                            None => {
+                                return Err(format!("{}: unknown ebcdic byte {:#04x}", at(), byte))
+                            }
+                        }
+                    }
+                    text
+                }
+            };
+            let value = text.trim_matches(' ');
+            row[field.column] = match field.kind {
+                _ if value.is_empty() => String::new(),
+                Kind::Text => value.to_string(),
+                Kind::Integer if value.chars().all(|c| c.is_ascii_digit()) => {
+                    let value = value.trim_start_matches('0');
+                    if value.is_empty() { "0" } else { value }.to_string()
+                }
+                Kind::Integer => return Err(format!("{}: invalid integer {:?}", at(), value)),
+                Kind::Decimal => {
+                    decimal(value, field.scale).map_err(|err| format!("{}: {}", at(), err))?
+                }
+            };
+        }
+        Ok(row.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = "
+        record_length = 24
+
+        [[field]]
+        column = \"type\"
+        offset = 0
+        length = 10
+
+        [[field]]
+        column = \"client\"
+        offset = 10
+        length = 3
+        kind = \"integer\"
+
+        [[field]]
+        column = \"tx\"
+        offset = 13
+        length = 4
+        kind = \"integer\"
+
+        [[field]]
+        column = \"amount\"
+        offset = 17
+        length = 7
+        kind = \"decimal\"
+        scale = 2
+    ";
+
+    #[test]
+    fn records_become_rows() {
+        let layout = Layout::parse(LAYOUT).unwrap();
+        let input = "deposit   0070001000025Ewithdrawal0070002000001}dispute   0070001       ";
+        let rows: Vec<csv::StringRecord> = layout
+            .records(input.as_bytes())
+            .map(|record| layout.row(&record.unwrap().1).unwrap())
+            .collect();
+        let columns = |row: &csv::StringRecord| -> Vec<String> {
+            (0..4).map(|column| row[column].to_string()).collect()
+        };
+        assert_eq!(columns(&rows[0]), ["deposit", "7", "1", "00002.55"]);
+        assert_eq!(columns(&rows[1]), ["withdrawal", "7", "2", "-00000.10"]);
+        assert_eq!(columns(&rows[2]), ["dispute", "7", "1", ""]);
+        assert_eq!(rows[0].len(), COLUMNS.len());
+
+        let short: Vec<_> = layout.records("deposit   0070001".as_bytes()).collect();
+        assert!(short[0].is_err());
+        let error = layout.row(b"deposit   0x70001000025E").unwrap_err();
+        assert_eq!(error, "client at bytes 10-12: invalid integer \"0x7\"");
+    }
+
+    #[test]
+    fn ebcdic_and_decimals() {
+        let layout =
+            Layout::parse(&LAYOUT.replace("record_length", "encoding = \"ebcdic\"\nrecord_length"))
+                .unwrap();
+        let ascii = "deposit   0070001+012.50";
+        let record: Vec<u8> = ascii
+            .bytes()
+            .map(|byte| {
+                (0..=255u8)
+                    .find(|code| ebcdic(*code) == Some(byte as char))
+                    .unwrap()
+            })
+            .collect();
+        let row = layout.row(&record).unwrap();
+        assert_eq!((&row[0], &row[3]), ("deposit", "012.50"));
+        assert!(layout.row(&[0xFF; 24]).is_err());
+
+        assert_eq!(decimal("12345", 2).unwrap(), "123.45");
+        assert_eq!(decimal("5", 4).unwrap(), "0.0005");
+        assert_eq!(decimal("1234-", 0).unwrap(), "-1234");
+        assert_eq!(decimal("-1.5", 2).unwrap(), "-1.5");
+        assert!(decimal("1.2.3", 0).is_err());
+        assert!(decimal("12a4", 0).is_err());
+
+        assert!(Layout::parse("[[field]]\ncolumn = \"type\"\noffset = 0\nlength = 1").is_err());
+        assert!(Layout::parse(&LAYOUT.replace("\"amount\"", "\"amt\"")).is_err());
+        assert!(Layout::parse(&format!(
+            "encoding = \"ebcdic\"\n{}",
+            LAYOUT.replace("record_length = 24", "")
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use crate::BasicError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Fixups file format, for known quirks of upstream feeds fixed before the rows are parsed:
+//
+// [rename_types]                # the type column as written -> the type txcli knows
+// charge_back = "chargeback"
+//
+// [[scale]]
+// factor = "0.01"               # a power of ten, cents -> units, the digits are shifted exactly
+// types = ["deposit", "withdrawal"]  # optional, after renaming, every row with an amount if left out
+//
+// [[drop]]
+// from = 1000                   # tx ids, inclusive
+// to = 1999
+// client = 7                    # optional, every client if left out
+//
+// Drops are decided on the row as written, then types are renamed, then amounts scaled.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FixupsFile {
+    #[serde(default)]
+    rename_types: HashMap<String, String>,
+    #[serde(default)]
+    scale: Vec<RawScale>,
+    #[serde(default)]
+    drop: Vec<Drop>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawScale {
+    factor: String,
+    types: Option<Vec<String>>,
+}
+
+struct Scale {
+    // Places the decimal point moves, right when positive.
+    shift: i32,
+    types: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Drop {
+    from: u32,
+    to: u32,
+    client: Option<u16>,
+}
+
+impl Drop {
+    fn matches(&self, cid: Option<u16>, tid: Option<u32>) -> bool {
+        tid.is_some_and(|tid| (self.from..=self.to).contains(&tid))
+            && self.client.is_none_or(|client| cid == Some(client))
+    }
+}
+
+// Where the columns fixups look at are, see schema::RowReader.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FixupColumns {
+    pub tx_type: Option<usize>,
+    pub client: Option<usize>,
+    pub tx: Option<usize>,
+    pub amount: Option<usize>,
+}
+
+// The rows --fixups rewrote and dropped, shared by the clones parsing chunks in parallel.
+#[derive(Default)]
+pub struct Fixups {
+    rename_types: HashMap<String, String>,
+    scale: Vec<Scale>,
+    drop: Vec<Drop>,
+    rewritten: AtomicU64,
+    dropped: AtomicU64,
+}
+
+// "100" is 2, "0.01" is -2, anything but a power of ten is None.
+fn power_of_ten(factor: &str) -> Option<i32> {
+    match factor.split_once('.') {
+        None => {
+            let zeros = factor.strip_prefix('1')?;
+            zeros
+                .chars()
+                .all(|c| c == '0')
+                .then_some(zeros.len() as i32)
+        }
+        Some(("0", fraction)) => {
+            let zeros = fraction.strip_suffix('1')?;
+            zeros
+                .chars()
+                .all(|c| c == '0')
+                .then_some(-(fraction.len() as i32))
+        }
+        Some(_) => None,
+    }
+}
+
+// Moves the decimal point of a decimal number written like an amount, None if it isn't one and
+// the row is left for the parser to reject.
+fn shift_decimal(amount: &str, shift: i32) -> Option<String> {
+    let (sign, digits) = match amount.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", amount.strip_prefix('+').unwrap_or(amount)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let all: String = whole.chars().chain(fraction.chars()).collect();
+    let point = whole.len() as i64 + shift as i64;
+    let (whole, fraction) = if point <= 0 {
+        (String::new(), "0".repeat(-point as usize) + &all)
+    } else if point as usize >= all.len() {
+        (
+            all.clone() + &"0".repeat(point as usize - all.len()),
+            String::new(),
+        )
+    } else {
+        let (whole, fraction) = all.split_at(point as usize);
+        (whole.to_string(), fraction.to_string())
+    };
+    let whole = whole.trim_start_matches('0');
+    let fraction = fraction.trim_end_matches('0');
+    let whole = if whole.is_empty() { "0" } else { whole };
+    Some(match fraction {
+        "" => format!("{}{}", sign, whole),
+        fraction => format!("{}{}.{}", sign, whole, fraction),
+    })
+}
+
+impl Fixups {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Fixups::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let file: FixupsFile = toml::from_str(input)?;
+        let mut scale = vec![];
+        for raw in file.scale {
+            let shift = power_of_ten(&raw.factor).ok_or_else(|| {
+                BasicError::new(format!(
+                    "Fixup scale factor {} is not a power of ten, e.g. 100 or 0.01.",
+                    raw.factor
+                ))
+            })?;
+            scale.push(Scale {
+                shift,
+                types: raw.types,
+            });
+        }
+        if let Some(drop) = file.drop.iter().find(|drop| drop.from > drop.to) {
+            return Err(BasicError::new(format!(
+                "Fixup drop range {}..{} is empty, from must not be above to.",
+                drop.from, drop.to
+            )));
+        }
+        Ok(Fixups {
+            rename_types: file.rename_types,
+            scale,
+            drop: file.drop,
+            ..Fixups::default()
+        })
+    }
+
+    // Rewrites record in place, returns false for a row to drop.
+    pub fn fix(&self, record: &mut csv::StringRecord, columns: FixupColumns) -> bool {
+        let field = |column: Option<usize>| column.and_then(|column| record.get(column));
+        let cid = field(columns.client).and_then(|cid| cid.parse().ok());
+        let tid = field(columns.tx).and_then(|tid| tid.parse().ok());
+        if self.drop.iter().any(|drop| drop.matches(cid, tid)) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let written = field(columns.tx_type).unwrap_or_default();
+        let tx_type = self
+            .rename_types
+            .get(written)
+            .map_or(written, String::as_str);
+        let original = field(columns.amount).filter(|amount| !amount.is_empty());
+        let mut amount = original.map(String::from);
+        for scale in &self.scale {
+            let applies = scale
+                .types
+                .as_ref()
+                .is_none_or(|types| types.iter().any(|name| name == tx_type));
+            if let (true, Some(value)) = (applies, &amount) {
+                if let Some(shifted) = shift_decimal(value, scale.shift) {
+                    amount = Some(shifted);
+                }
+            }
+        }
+        let changed = tx_type != written || amount.as_deref() != original;
+        if changed {
+            let fixed: csv::StringRecord = record
+                .iter()
+                .enumerate()
+                .map(|(column, value)| match Some(column) {
+                    index if index == columns.tx_type => tx_type,
+                    index if index == columns.amount => amount.as_deref().unwrap_or(value),
+                    _ => value,
+                })
+                .collect();
+            let position = record.position().cloned();
+            *record = fixed;
+            record.set_position(position);
+            self.rewritten.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+
+    // Rows rewritten and dropped so far.
+    pub fn counts(&self) -> (u64, u64) {
+        (
+            self.rewritten.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLUMNS: FixupColumns = FixupColumns {
+        tx_type: Some(0),
+        client: Some(1),
+        tx: Some(2),
+        amount: Some(3),
+    };
+
+    fn fixed(fixups: &Fixups, row: &[&str]) -> Option<Vec<String>> {
+        let mut record = csv::StringRecord::from(row.to_vec());
+        fixups
+            .fix(&mut record, COLUMNS)
+            .then(|| record.iter().map(String::from).collect())
+    }
+
+    #[test]
+    fn rename_scale_and_drop() {
+        let fixups = Fixups::parse(
+            "[rename_types]\ncharge_back = \"chargeback\"\nrefund = \"withdrawal\"\n\n\
+             [[scale]]\nfactor = \"0.01\"\ntypes = [\"deposit\", \"withdrawal\"]\n\n\
+             [[drop]]\nfrom = 100\nto = 199\n\n[[drop]]\nfrom = 7\nto = 7\nclient = 2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            fixed(&fixups, &["deposit", "1", "1", "1234"]).unwrap(),
+            ["deposit", "1", "1", "12.34"]
+        );
+        assert_eq!(
+            fixed(&fixups, &["refund", "1", "2", "-5"]).unwrap(),
+            ["withdrawal", "1", "2", "-0.05"]
+        );
+        assert_eq!(
+            fixed(&fixups, &["charge_back", "1", "1", ""]).unwrap(),
+            ["chargeback", "1", "1", ""]
+        );
+        assert_eq!(
+            fixed(&fixups, &["dispute", "1", "1"]).unwrap(),
+            ["dispute", "1", "1"]
+        );
+        assert_eq!(fixed(&fixups, &["deposit", "1", "150", "1"]), None);
+        assert_eq!(fixed(&fixups, &["deposit", "2", "7", "1"]), None);
+        assert!(fixed(&fixups, &["deposit", "3", "7", "1"]).is_some());
+        assert_eq!(fixups.counts(), (4, 2));
+    }
+
+    #[test]
+    fn decimal_shifts() {
+        assert_eq!(power_of_ten("100"), Some(2));
+        assert_eq!(power_of_ten("1"), Some(0));
+        assert_eq!(power_of_ten("0.001"), Some(-3));
+        assert_eq!(power_of_ten("0.02"), None);
+        assert_eq!(power_of_ten("25"), None);
+        assert_eq!(shift_decimal("1234", -2).as_deref(), Some("12.34"));
+        assert_eq!(shift_decimal("5", -3).as_deref(), Some("0.005"));
+        assert_eq!(shift_decimal("1.5", 2).as_deref(), Some("150"));
+        assert_eq!(shift_decimal("+0.10", 1).as_deref(), Some("1"));
+        assert_eq!(shift_decimal("abc", 2), None);
+        assert!(Fixups::parse("[[scale]]\nfactor = \"0.5\"\n").is_err());
+        assert!(Fixups::parse("[[drop]]\nfrom = 5\nto = 1\n").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::options::{self, Command};
+use crate::BasicError;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Installation config, read from beside the executable so whoever deploys the binary decides
+// what it may do, and nothing on the command line or in the environment can point elsewhere:
+//
+// [gating]
+// disabled_commands = ["merge-clients", "split-client"]
+// disabled_flags = ["--redis", "--retry-out"]
+pub const CONFIG_FILE: &str = "txcli.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    gating: RawGating,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RawGating {
+    #[serde(default)]
+    disabled_commands: Vec<String>,
+    #[serde(default)]
+    disabled_flags: Vec<String>,
+}
+
+// Subcommands and flags this installation refuses to run.
+#[derive(Debug, Default, PartialEq)]
+pub struct Gating {
+    // The config it was read from, for pointing at it when something is refused.
+    source: Option<PathBuf>,
+    commands: Vec<String>,
+    flags: Vec<String>,
+}
+
+impl Gating {
+    // No config beside the executable gates nothing. One that doesn't parse fails the run rather
+    // than letting everything through.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let exe = std::env::current_exe()?;
+        let path = exe.with_file_name(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Gating::default());
+        }
+        let mut gating = Gating::parse(&fs::read_to_string(&path)?)
+            .map_err(|err| BasicError::new(format!("{}: {}", path.display(), err)))?;
+        gating.source = Some(path);
+        Ok(gating)
+    }
+
+    // Unknown names are errors, a typo would otherwise quietly leave the command enabled.
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let file: ConfigFile = toml::from_str(input)?;
+        let mut gating = Gating::default();
+        for name in file.gating.disabled_commands {
+            if Command::named(&name).is_none() {
+                return Err(BasicError::new(format!(
+                    "Unknown command {} to disable.",
+                    name
+                )));
+            }
+            gating.commands.push(name);
+        }
+        for flag in file.gating.disabled_flags {
+            if !options::known_flag(&flag) {
+                return Err(BasicError::new(format!(
+                    "Unknown flag {} to disable.",
+                    flag
+                )));
+            }
+            gating.flags.push(flag);
+        }
+        Ok(gating)
+    }
+
+    // Checked against the raw arguments, before they are parsed into options.
+    pub fn check(&self, args: &[String]) -> Result<(), Box<dyn Error>> {
+        let refused = args.iter().skip(1).enumerate().find_map(|(index, arg)| {
+            let command = index == 0 && self.commands.contains(arg);
+            (command || self.flags.contains(arg)).then_some(arg)
+        });
+        match refused {
+            Some(arg) => Err(BasicError::new(format!(
+                "{} is disabled on this installation by {}.",
+                arg,
+                self.source
+                    .as_deref()
+                    .unwrap_or(Path::new(CONFIG_FILE))
+                    .display()
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("txcli")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn refuses_disabled_commands_and_flags() {
+        let gating = Gating::parse(
+            "[gating]\ndisabled_commands = [\"merge-clients\"]\ndisabled_flags = [\"--redis\"]\n",
+        )
+        .unwrap();
+        assert!(gating.check(&args(&["in.csv"])).is_ok());
+        assert!(gating.check(&args(&["split-client", "in.csv"])).is_ok());
+        let err = gating
+            .check(&args(&["merge-clients", "--map", "m.csv", "in.csv"]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "merge-clients is disabled on this installation by txcli.toml."
+        );
+        assert!(gating
+            .check(&args(&["in.csv", "--redis", "localhost"]))
+            .is_err());
+        // Only the first argument names a command, a file called merge-clients is fine.
+        assert!(gating.check(&args(&["in.csv", "merge-clients"])).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert_eq!(Gating::parse("").unwrap(), Gating::default());
+        let err = Gating::parse("[gating]\ndisabled_commands = [\"merge\"]\n").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown command merge to disable.");
+        let err = Gating::parse("[gating]\ndisabled_flags = [\"--redsi\"]\n").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown flag --redsi to disable.");
+        assert!(Gating::parse("[gating]\ndisabled_flags = [\"redis\"]\n").is_err());
+        assert!(Gating::parse("[gatng]\ndisabled_flags = [\"--redis\"]\n").is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::AppState;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{precision4_serialize_optional_currency, ClientId, Currency, TxId, TxType};
+
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+struct Step {
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    applied: bool,
+}
+
+#[derive(Serialize)]
+struct TxNode<'a> {
+    tx: TxId,
+    // None when the referenced transaction was never stored, e.g. a dispute of an unknown tid.
+    #[serde(rename = "type")]
+    tx_type: Option<TxType>,
+    #[serde(serialize_with = "precision4_serialize_optional_currency")]
+    amount: Option<Currency>,
+    steps: &'a [Step],
+}
+
+#[derive(Serialize)]
+struct ClientNode<'a> {
+    client: ClientId,
+    transactions: Vec<TxNode<'a>>,
+}
+
+// Links each disputed transaction to the disputes, resolves and chargebacks referencing it, in
+// input order. Only the dispute lifecycle is kept while processing, the transactions themselves
+// are looked up in the final state when writing.
+#[derive(Default)]
+pub struct DisputeGraph {
+    steps: BTreeMap<(ClientId, TxId), Vec<Step>>,
+}
+
+impl DisputeGraph {
+    // cid is the canonical client, which is where the referenced transaction lives.
+    pub fn record(&mut self, cid: ClientId, tid: TxId, tx_type: TxType, applied: bool) {
+        if matches!(
+            tx_type,
+            TxType::Dispute | TxType::Resolve | TxType::ChargeBack
+        ) {
+            self.steps
+                .entry((cid, tid))
+                .or_default()
+                .push(Step { tx_type, applied });
+        }
+    }
+
+    fn clients<'a>(&'a self, state: &AppState) -> Vec<ClientNode<'a>> {
+        let mut clients: Vec<ClientNode> = vec![];
+        for ((cid, tid), steps) in &self.steps {
+            let tx = state
+                .clients
+                .get(cid)
+                .and_then(|client| client.history.get(tid).or_else(|| client.disputed.get(tid)));
+            let node = TxNode {
+                tx: *tid,
+                tx_type: tx.map(|tx| tx.tx_type),
+                amount: tx.map(|tx| tx.amount),
+                steps,
+            };
+            match clients.last_mut() {
+                Some(client) if client.client == *cid => client.transactions.push(node),
+                _ => clients.push(ClientNode {
+                    client: *cid,
+                    transactions: vec![node],
+                }),
+            }
+        }
+        clients
+    }
+
+    pub fn write_json<W: Write>(
+        &self,
+        state: &AppState,
+        mut writer: W,
+    ) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer_pretty(&mut writer, &self.clients(state))?;
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    // One cluster per client. Each transaction is a box chained to its lifecycle steps, rejected
+    // steps are dashed so they stand out without breaking the chain.
+    pub fn write_dot<W: Write>(
+        &self,
+        state: &AppState,
+        mut writer: W,
+    ) -> Result<(), Box<dyn Error>> {
+        writeln!(writer, "digraph disputes {{")?;
+        writeln!(writer, "  rankdir=LR;")?;
+        for client in self.clients(state) {
+            let cid = client.client.0;
+            writeln!(writer, "  subgraph cluster_client_{} {{", cid)?;
+            writeln!(writer, "    label=\"client {}\";", cid)?;
+            for tx in &client.transactions {
+                let node = format!("c{}_t{}", cid, tx.tx.0);
+                match (tx.tx_type, tx.amount) {
+                    (Some(tx_type), Some(amount)) => writeln!(
+                        writer,
+                        "    {} [shape=box, label=\"{} {}\\n{:.4}\"];",
+                        node, tx_type, tx.tx.0, amount
+                    )?,
+                    _ => writeln!(
+                        writer,
+                        "    {} [shape=box, style=dashed, label=\"unknown {}\"];",
+                        node, tx.tx.0
+                    )?,
+                }
+                let mut previous = node.clone();
+                for (index, step) in tx.steps.iter().enumerate() {
+                    let step_node = format!("{}_s{}", node, index);
+                    let color = match step.tx_type {
+                        TxType::Dispute => "orange",
+                        TxType::Resolve => "green",
+                        _ => "red",
+                    };
+                    let style = if step.applied { "solid" } else { "dashed" };
+                    let label = if step.applied {
+                        step.tx_type.to_string()
+                    } else {
+                        format!("{}\\nrejected", step.tx_type)
+                    };
+                    writeln!(
+                        writer,
+                        "    {} [color={}, style={}, label=\"{}\"];",
+                        step_node, color, style, label
+                    )?;
+                    writeln!(
+                        writer,
+                        "    {} -> {} [style={}];",
+                        previous, step_node, style
+                    )?;
+                    previous = step_node;
+                }
+            }
+            writeln!(writer, "  }}")?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_transaction;
+    use txcli_model::TxRecord;
+
+    fn run(rows: &[(TxType, u16, u32, u32)]) -> (AppState, DisputeGraph) {
+        let mut state = AppState::default();
+        let mut graph = DisputeGraph::default();
+        for (tx_type, cid, tid, amount) in rows {
+            let tx = TxRecord::new(*tx_type, *cid, *tid, Currency::from_num(*amount));
+            let applied = execute_transaction(&mut state, tx).is_ok();
+            graph.record(tx.cid, tx.tid, tx.tx_type, applied);
+        }
+        (state, graph)
+    }
+
+    #[test]
+    fn dot_chains_lifecycle() {
+        let (state, graph) = run(&[
+            (TxType::Deposit, 1, 1, 10),
+            (TxType::Deposit, 1, 2, 5),
+            (TxType::Dispute, 1, 1, 0),
+            (TxType::Resolve, 1, 1, 0),
+            (TxType::Dispute, 1, 1, 0),
+            (TxType::ChargeBack, 1, 1, 0),
+            (TxType::Resolve, 2, 9, 0),
+        ]);
+        let mut out = vec![];
+        graph.write_dot(&state, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "digraph disputes {\n  rankdir=LR;\n  subgraph cluster_client_1 {\n    label=\"client 1\";\n    \
+             c1_t1 [shape=box, label=\"deposit 1\\n10.0000\"];\n    \
+             c1_t1_s0 [color=orange, style=solid, label=\"dispute\"];\n    c1_t1 -> c1_t1_s0 [style=solid];\n    \
+             c1_t1_s1 [color=green, style=solid, label=\"resolve\"];\n    c1_t1_s0 -> c1_t1_s1 [style=solid];\n    \
+             c1_t1_s2 [color=orange, style=solid, label=\"dispute\"];\n    c1_t1_s1 -> c1_t1_s2 [style=solid];\n    \
+             c1_t1_s3 [color=red, style=solid, label=\"chargeback\"];\n    c1_t1_s2 -> c1_t1_s3 [style=solid];\n  }\n  \
+             subgraph cluster_client_2 {\n    label=\"client 2\";\n    \
+             c2_t9 [shape=box, style=dashed, label=\"unknown 9\"];\n    \
+             c2_t9_s0 [color=green, style=dashed, label=\"resolve\\nrejected\"];\n    c2_t9 -> c2_t9_s0 [style=dashed];\n  }\n}\n"
+        );
+    }
+
+    #[test]
+    fn json_groups_by_client() {
+        let (state, graph) = run(&[
+            (TxType::Deposit, 1, 1, 10),
+            (TxType::Deposit, 1, 2, 5),
+            (TxType::Dispute, 1, 2, 0),
+            (TxType::Dispute, 1, 1, 0),
+        ]);
+        let mut out = vec![];
+        graph.write_json(&state, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let transactions = json[0]["transactions"].as_array().unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0]["tx"], 1);
+        assert_eq!(transactions[0]["type"], "deposit");
+        assert_eq!(transactions[1]["amount"], "5.0000");
+        assert_eq!(transactions[1]["steps"][0]["type"], "dispute");
+        assert_eq!(transactions[1]["steps"][0]["applied"], true);
+    }
+}
@@ -0,0 +1,646 @@
+use crate::BasicError;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+// The language messages are written in, set once at startup from --lang. A global for the same
+// reason as the warnings channel, messages come from deep inside the engine.
+static LANG: Mutex<Lang> = Mutex::new(Lang::En);
+
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    Fr,
+    De,
+}
+
+impl FromStr for Lang {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Lang::En),
+            "fr" => Ok(Lang::Fr),
+            "de" => Ok(Lang::De),
+            _ => Err(BasicError::new(format!(
+                "Unknown language {}, expected en, fr or de.",
+                s
+            ))),
+        }
+    }
+}
+
+pub fn set_lang(lang: Lang) {
+    *LANG.lock().unwrap() = lang;
+}
+
+// Every message a person reads on stderr during a run. Anything a program reads, csv columns,
+// warning kinds and the like, stays English whatever the language.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Msg {
+    Rejected,
+    UnknownTx,
+    ReusedId,
+    InternalTransfer,
+    AlreadyDisputed,
+    VoidDisputed,
+    NotDisputed,
+    ChargedBack,
+    Voided,
+    UnderDispute,
+    AssetMismatch,
+    InsufficientWithdrawal,
+    InsufficientTransfer,
+    InvalidTransfer,
+    Dust,
+    DisputeCap,
+    DisputeQueued,
+    DisputeStillQueued,
+    TooManyDisputes,
+    AutoLock,
+    MaxAmount,
+    UnmatchedCase,
+    UnmatchedClearing,
+    UnmatchedAuthorization,
+    ParseError,
+    SamplingSeed,
+    ConservationHeld,
+    SplitMissing,
+    DisputesQueued,
+    PenaltiesAssessed,
+    DustSwept,
+    InterimReports,
+    RetryWritten,
+    AnomaliesFlagged,
+    AssertionFailed,
+    AssertionNoClient,
+    Assertion,
+    BreaksFound,
+    AssertionsFailed,
+    NoHandler,
+    CustomRefused,
+    Expired,
+    // Only --serve prints these.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    Serving,
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    ConnectionFailed,
+    StatementsWritten,
+    ClientLocked,
+    InvalidAmount,
+    UnexpectedAmount,
+    FileRejected,
+    ClientArchived,
+    NotArchived,
+    NotDormant,
+    UnknownClient,
+    ArchivedClients,
+    AuditWritten,
+    RowsRead,
+    StoppedAtParseError,
+    PrecisionLoss,
+    SlowRow,
+    Progress,
+    FixupsApplied,
+    InsufficientDispute,
+    PartialHold,
+    DisputeShortfall,
+    ClientInTwoFiles,
+    ChargebackFees,
+    NextRun,
+    RunFinished,
+    RunFailed,
+    NotifyFailed,
+    WrittenOff,
+    Pending,
+    InputUnreadable,
+    NoteReferenced,
+    MissingMemo,
+}
+
+#[cfg(test)]
+const ALL: [Msg; 75] = [
+    Msg::Rejected,
+    Msg::UnknownTx,
+    Msg::ReusedId,
+    Msg::InternalTransfer,
+    Msg::AlreadyDisputed,
+    Msg::VoidDisputed,
+    Msg::NotDisputed,
+    Msg::ChargedBack,
+    Msg::Voided,
+    Msg::UnderDispute,
+    Msg::AssetMismatch,
+    Msg::InsufficientWithdrawal,
+    Msg::InsufficientTransfer,
+    Msg::InvalidTransfer,
+    Msg::Dust,
+    Msg::DisputeCap,
+    Msg::DisputeQueued,
+    Msg::DisputeStillQueued,
+    Msg::TooManyDisputes,
+    Msg::AutoLock,
+    Msg::MaxAmount,
+    Msg::UnmatchedCase,
+    Msg::UnmatchedClearing,
+    Msg::UnmatchedAuthorization,
+    Msg::ParseError,
+    Msg::SamplingSeed,
+    Msg::ConservationHeld,
+    Msg::SplitMissing,
+    Msg::DisputesQueued,
+    Msg::PenaltiesAssessed,
+    Msg::DustSwept,
+    Msg::InterimReports,
+    Msg::RetryWritten,
+    Msg::AnomaliesFlagged,
+    Msg::AssertionFailed,
+    Msg::AssertionNoClient,
+    Msg::Assertion,
+    Msg::BreaksFound,
+    Msg::AssertionsFailed,
+    Msg::NoHandler,
+    Msg::CustomRefused,
+    Msg::Expired,
+    Msg::Serving,
+    Msg::ConnectionFailed,
+    Msg::StatementsWritten,
+    Msg::ClientLocked,
+    Msg::InvalidAmount,
+    Msg::UnexpectedAmount,
+    Msg::FileRejected,
+    Msg::ClientArchived,
+    Msg::NotArchived,
+    Msg::NotDormant,
+    Msg::UnknownClient,
+    Msg::ArchivedClients,
+    Msg::AuditWritten,
+    Msg::RowsRead,
+    Msg::StoppedAtParseError,
+    Msg::PrecisionLoss,
+    Msg::SlowRow,
+    Msg::Progress,
+    Msg::FixupsApplied,
+    Msg::InsufficientDispute,
+    Msg::PartialHold,
+    Msg::DisputeShortfall,
+    Msg::ClientInTwoFiles,
+    Msg::ChargebackFees,
+    Msg::NextRun,
+    Msg::RunFinished,
+    Msg::RunFailed,
+    Msg::NotifyFailed,
+    Msg::WrittenOff,
+    Msg::Pending,
+    Msg::InputUnreadable,
+    Msg::NoteReferenced,
+    Msg::MissingMemo,
+];
+
+impl Msg {
+    // en, fr and de side by side so a new message can't miss a language. {n} is the nth argument,
+    // languages are free to reorder them.
+    fn templates(self) -> [&'static str; 3] {
+        match self {
+            Msg::Rejected => [
+                "Rejected {0} tid[{1}], it {2}. Ignoring.",
+                "{0} tid[{1}] rejeté : {2}. Ignoré.",
+                "{0} tid[{1}] abgelehnt: {2}. Ignoriert.",
+            ],
+            Msg::UnknownTx => [
+                "references an unknown transaction",
+                "référence une transaction inconnue",
+                "verweist auf eine unbekannte Transaktion",
+            ],
+            Msg::ReusedId => [
+                "reuses the id of an earlier transaction",
+                "réutilise l'identifiant d'une transaction antérieure",
+                "verwendet die ID einer früheren Transaktion erneut",
+            ],
+            Msg::InternalTransfer => [
+                "references a transfer, transfers between a client's own accounts cannot be disputed",
+                "référence un virement, les virements entre les comptes d'un même client ne peuvent pas être contestés",
+                "verweist auf eine Umbuchung, Umbuchungen zwischen eigenen Konten eines Kunden können nicht angefochten werden",
+            ],
+            Msg::NoteReferenced => [
+                "references a note, notes hold no funds and cannot be disputed or voided",
+                "référence une note, les notes ne portent aucun fonds et ne peuvent être ni contestées ni annulées",
+                "verweist auf eine Notiz, Notizen halten kein Guthaben und können weder angefochten noch storniert werden",
+            ],
+            Msg::AlreadyDisputed => [
+                "is already under dispute",
+                "la transaction est déjà contestée",
+                "die Transaktion wird bereits angefochten",
+            ],
+            Msg::VoidDisputed => [
+                "was disputed, only undisputed transactions can be voided",
+                "la transaction a été contestée, seules les transactions non contestées peuvent être annulées",
+                "die Transaktion wurde angefochten, nur nicht angefochtene Transaktionen können storniert werden",
+            ],
+            Msg::NotDisputed => [
+                "is not under dispute",
+                "la transaction n'est pas contestée",
+                "die Transaktion wird nicht angefochten",
+            ],
+            Msg::ChargedBack => [
+                "was already charged back",
+                "la transaction a déjà été rétrofacturée",
+                "die Transaktion wurde bereits zurückgebucht",
+            ],
+            Msg::Voided => [
+                "was voided",
+                "la transaction a été annulée",
+                "die Transaktion wurde storniert",
+            ],
+            Msg::Expired => [
+                "is older than the history --retain-history keeps",
+                "la transaction est plus ancienne que l'historique conservé par --retain-history",
+                "die Transaktion ist älter als der mit --retain-history aufbewahrte Verlauf",
+            ],
+            Msg::UnderDispute => [
+                "is under dispute",
+                "la transaction est contestée",
+                "die Transaktion wird angefochten",
+            ],
+            Msg::AssetMismatch => [
+                "names another currency than the transaction it references",
+                "la devise diffère de celle de la transaction référencée",
+                "die Währung weicht von der der referenzierten Transaktion ab",
+            ],
+            Msg::InsufficientWithdrawal => [
+                "Insuffient funds to withdraw tid[{0}]. Ignoring.",
+                "Fonds insuffisants pour le retrait tid[{0}]. Ignoré.",
+                "Unzureichendes Guthaben für die Abhebung tid[{0}]. Ignoriert.",
+            ],
+            Msg::InsufficientTransfer => [
+                "Insuffient funds to transfer tid[{0}]. Ignoring.",
+                "Fonds insuffisants pour le virement tid[{0}]. Ignoré.",
+                "Unzureichendes Guthaben für die Umbuchung tid[{0}]. Ignoriert.",
+            ],
+            Msg::InvalidTransfer => [
+                "Transfer tid[{0}] needs a to_account different from its account. Ignoring.",
+                "Le virement tid[{0}] nécessite un to_account différent de son account. Ignoré.",
+                "Die Umbuchung tid[{0}] braucht ein to_account, das sich von ihrem account unterscheidet. Ignoriert.",
+            ],
+            Msg::Dust => [
+                "Amount of tid[{0}] is below the dust threshold. Ignoring.",
+                "Le montant de tid[{0}] est sous le seuil de poussière. Ignoré.",
+                "Der Betrag von tid[{0}] liegt unter der Staubgrenze. Ignoriert.",
+            ],
+            Msg::DisputeCap => [
+                "Rejected {0} tid[{1}], {2}. Ignoring.",
+                "{0} tid[{1}] rejeté : {2}. Ignoré.",
+                "{0} tid[{1}] abgelehnt: {2}. Ignoriert.",
+            ],
+            Msg::DisputeQueued => [
+                "its dispute is already queued",
+                "sa contestation est déjà en attente",
+                "ihre Anfechtung steht bereits in der Warteschlange",
+            ],
+            Msg::DisputeStillQueued => [
+                "its dispute is still queued behind --max-open-disputes",
+                "sa contestation attend encore derrière --max-open-disputes",
+                "ihre Anfechtung wartet noch hinter --max-open-disputes",
+            ],
+            Msg::TooManyDisputes => [
+                "client {0} already has {1} open disputes",
+                "le client {0} a déjà {1} contestations ouvertes",
+                "Kunde {0} hat bereits {1} offene Anfechtungen",
+            ],
+            Msg::AutoLock => [
+                "Locked client {0} at chargeback tid[{1}], {2} chargebacks totalling {3} crossed the risk threshold.",
+                "Client {0} bloqué à la rétrofacturation tid[{1}], {2} rétrofacturations totalisant {3} ont franchi le seuil de risque.",
+                "Kunde {0} bei Rückbuchung tid[{1}] gesperrt, {2} Rückbuchungen über insgesamt {3} haben die Risikoschwelle überschritten.",
+            ],
+            Msg::MaxAmount => [
+                "line {0}: amount {1} of {2} tid[{3}] exceeds --max-amount {4}. Ignoring.",
+                "ligne {0} : le montant {1} de {2} tid[{3}] dépasse --max-amount {4}. Ignoré.",
+                "Zeile {0}: Betrag {1} von {2} tid[{3}] überschreitet --max-amount {4}. Ignoriert.",
+            ],
+            Msg::UnmatchedCase => [
+                "Case for tid[{0}] on line {1} of the disputes file matches no transaction. Ignoring.",
+                "Le dossier de tid[{0}] à la ligne {1} du fichier de litiges ne correspond à aucune transaction. Ignoré.",
+                "Der Fall zu tid[{0}] in Zeile {1} der Anfechtungsdatei passt zu keiner Transaktion. Ignoriert.",
+            ],
+            Msg::UnmatchedClearing => [
+                "line {0}: {1} tid[{2}] of client {3} clears no authorization. Ignoring.",
+                "ligne {0} : {1} tid[{2}] du client {3} ne compense aucune autorisation. Ignoré.",
+                "Zeile {0}: {1} tid[{2}] von Kunde {3} verrechnet keine Autorisierung. Ignoriert.",
+            ],
+            Msg::UnmatchedAuthorization => [
+                "Authorized {0} tid[{1}] of client {2} for {3} on line {4} of the authorizations file was never cleared.",
+                "{0} tid[{1}] du client {2} autorisé pour {3} à la ligne {4} du fichier d'autorisations n'a jamais été compensé.",
+                "Autorisierte {0} tid[{1}] von Kunde {2} über {3} in Zeile {4} der Autorisierungsdatei wurde nie verrechnet.",
+            ],
+            Msg::ParseError => [
+                "Failed to deserialize row, skipping [{0}]",
+                "Ligne illisible, elle est ignorée [{0}]",
+                "Zeile nicht lesbar, sie wird übersprungen [{0}]",
+            ],
+            Msg::SamplingSeed => [
+                "Sampling with seed {0}.",
+                "Échantillonnage avec la graine {0}.",
+                "Stichprobe mit Seed {0}.",
+            ],
+            Msg::ConservationHeld => [
+                "Conservation held, the world account stands at {0}.",
+                "La conservation est respectée, le compte monde s'élève à {0}.",
+                "Die Erhaltung gilt, das Weltkonto steht bei {0}.",
+            ],
+            Msg::SplitMissing => [
+                "{0} listed transactions were not found for the split client, first tid[{1}].",
+                "{0} transactions listées sont introuvables pour le client scindé, la première est tid[{1}].",
+                "{0} aufgeführte Transaktionen wurden für den aufgeteilten Kunden nicht gefunden, die erste ist tid[{1}].",
+            ],
+            Msg::DisputesQueued => [
+                "{0} disputes are still queued behind --max-open-disputes and were never opened.",
+                "{0} contestations attendent encore derrière --max-open-disputes et n'ont jamais été ouvertes.",
+                "{0} Anfechtungen warten noch hinter --max-open-disputes und wurden nie eröffnet.",
+            ],
+            Msg::PenaltiesAssessed => [
+                "Assessed overdraft penalties totalling {0}.",
+                "Pénalités de découvert appliquées pour un total de {0}.",
+                "Überziehungsgebühren über insgesamt {0} berechnet.",
+            ],
+            Msg::DustSwept => [
+                "Swept dust totalling {0} {1}.",
+                "Poussière balayée pour un total de {0} {1}.",
+                "Staub über insgesamt {0} {1} eingesammelt.",
+            ],
+            Msg::InterimReports => [
+                "Wrote {0} interim balance reports to {1}.",
+                "{0} rapports de soldes intermédiaires écrits dans {1}.",
+                "{0} Zwischenberichte der Salden nach {1} geschrieben.",
+            ],
+            Msg::RetryWritten => [
+                "Wrote {0} rejected transactions for retry.",
+                "{0} transactions rejetées écrites pour une nouvelle tentative.",
+                "{0} abgelehnte Transaktionen zur Wiederholung geschrieben.",
+            ],
+            Msg::AnomaliesFlagged => [
+                "Flagged {0} anomalous transactions.",
+                "{0} transactions anormales signalées.",
+                "{0} auffällige Transaktionen markiert.",
+            ],
+            Msg::AssertionFailed => [
+                "{0} failed, it is {1}",
+                "{0} a échoué, la valeur est {1}",
+                "{0} ist fehlgeschlagen, der Wert ist {1}",
+            ],
+            Msg::AssertionNoClient => [
+                "{0} failed, there is no such client",
+                "{0} a échoué, ce client n'existe pas",
+                "{0} ist fehlgeschlagen, diesen Kunden gibt es nicht",
+            ],
+            Msg::Assertion => ["Assertion {0}.", "Assertion {0}.", "Zusicherung {0}."],
+            Msg::BreaksFound => [
+                "Closing balances do not reconcile, {0} breaks found.",
+                "Les soldes de clôture ne concordent pas, {0} écarts trouvés.",
+                "Die Schlusssalden stimmen nicht überein, {0} Abweichungen gefunden.",
+            ],
+            Msg::AssertionsFailed => [
+                "{0} of {1} assertions failed.",
+                "{0} assertions sur {1} ont échoué.",
+                "{0} von {1} Zusicherungen sind fehlgeschlagen.",
+            ],
+            Msg::ClientLocked => [
+                "Rejected {0} tid[{1}], client {2} is locked. Ignoring.",
+                "{0} tid[{1}] rejeté : le client {2} est bloqué. Ignoré.",
+                "{0} tid[{1}] abgelehnt: Kunde {2} ist gesperrt. Ignoriert.",
+            ],
+            Msg::InvalidAmount => [
+                "Rejected {0} tid[{1}], its amount must be above zero. Ignoring.",
+                "{0} tid[{1}] rejeté : son montant doit être supérieur à zéro. Ignoré.",
+                "{0} tid[{1}] abgelehnt: der Betrag muss größer als null sein. Ignoriert.",
+            ],
+            Msg::FileRejected => [
+                "{0} has {1} invalid rows, none of its rows were applied.",
+                "{0} contient {1} lignes invalides, aucune de ses lignes n'a été appliquée.",
+                "{0} enthält {1} ungültige Zeilen, keine ihrer Zeilen wurde angewendet.",
+            ],
+            Msg::UnexpectedAmount => [
+                "Rejected {0} tid[{1}], it references another transaction and takes no amount. Ignoring.",
+                "{0} tid[{1}] rejeté : il référence une autre transaction et ne prend pas de montant. Ignoré.",
+                "{0} tid[{1}] abgelehnt: sie verweist auf eine andere Transaktion und hat keinen Betrag. Ignoriert.",
+            ],
+            Msg::MissingMemo => [
+                "Rejected {0} tid[{1}], a note needs a memo. Ignoring.",
+                "{0} tid[{1}] rejeté : une note a besoin d'un mémo. Ignoré.",
+                "{0} tid[{1}] abgelehnt: eine Notiz braucht ein Memo. Ignoriert.",
+            ],
+            Msg::NoHandler => [
+                "Rejected {0} tid[{1}], no handler is registered for its type. Ignoring.",
+                "{0} tid[{1}] rejeté : aucun gestionnaire n'est enregistré pour ce type. Ignoré.",
+                "{0} tid[{1}] abgelehnt: für diesen Typ ist kein Handler registriert. Ignoriert.",
+            ],
+            Msg::Serving => [
+                "Serving on {0}.",
+                "En service sur {0}.",
+                "Bereit auf {0}.",
+            ],
+            Msg::ConnectionFailed => [
+                "Connection from {0} failed: {1}",
+                "La connexion depuis {0} a échoué : {1}",
+                "Die Verbindung von {0} ist fehlgeschlagen: {1}",
+            ],
+            Msg::StatementsWritten => [
+                "Wrote {0} client statements to {1}.",
+                "{0} relevés clients écrits dans {1}.",
+                "{0} Kundenauszüge nach {1} geschrieben.",
+            ],
+            Msg::ClientArchived => [
+                "Rejected {0} tid[{1}], client {2} is archived. Ignoring.",
+                "{0} tid[{1}] rejeté : le client {2} est archivé. Ignoré.",
+                "{0} tid[{1}] abgelehnt: Kunde {2} ist archiviert. Ignoriert.",
+            ],
+            Msg::NotArchived => [
+                "Rejected {0} tid[{1}], client {2} isn't archived. Ignoring.",
+                "{0} tid[{1}] rejeté : le client {2} n'est pas archivé. Ignoré.",
+                "{0} tid[{1}] abgelehnt: Kunde {2} ist nicht archiviert. Ignoriert.",
+            ],
+            Msg::NotDormant => [
+                "Rejected {0} tid[{1}], client {2} still has funds or open disputes. Ignoring.",
+                "{0} tid[{1}] rejeté : le client {2} a encore des fonds ou des contestations ouvertes. Ignoré.",
+                "{0} tid[{1}] abgelehnt: Kunde {2} hat noch Guthaben oder offene Anfechtungen. Ignoriert.",
+            ],
+            Msg::UnknownClient => [
+                "Rejected {0} tid[{1}], client {2} doesn't exist. Ignoring.",
+                "{0} tid[{1}] rejeté : le client {2} n'existe pas. Ignoré.",
+                "{0} tid[{1}] abgelehnt: Kunde {2} existiert nicht. Ignoriert.",
+            ],
+            Msg::ArchivedClients => [
+                "{0} archived clients were left out of the balances, --include-archived prints them.",
+                "{0} clients archivés ont été omis des soldes, --include-archived les affiche.",
+                "{0} archivierte Kunden wurden bei den Salden ausgelassen, --include-archived gibt sie aus.",
+            ],
+            Msg::AuditWritten => [
+                "Wrote {0} client audit trails to {1}.",
+                "{0} pistes d'audit clients écrites dans {1}.",
+                "{0} Kunden-Prüfpfade nach {1} geschrieben.",
+            ],
+            Msg::RowsRead => [
+                "Read {0} rows: {1} applied, {2} rejected, {3} skipped as malformed.",
+                "{0} lignes lues : {1} appliquées, {2} rejetées, {3} ignorées car mal formées.",
+                "{0} Zeilen gelesen: {1} angewendet, {2} abgelehnt, {3} als fehlerhaft übersprungen.",
+            ],
+            Msg::StoppedAtParseError => [
+                "Stopped at the first malformed row of {0} under --strict, nothing was written.",
+                "Arrêt à la première ligne mal formée de {0} avec --strict, rien n'a été écrit.",
+                "Bei der ersten fehlerhaften Zeile von {0} mit --strict angehalten, nichts wurde geschrieben.",
+            ],
+            Msg::PrecisionLoss => [
+                "{0} of {1} amounts were rounded to fit {2}, losing {3} in all and at most {4} on one.",
+                "{0} montants sur {1} ont été arrondis pour tenir en {2}, perdant {3} au total et au plus {4} sur un seul.",
+                "{0} von {1} Beträgen wurden für {2} gerundet, insgesamt {3} und höchstens {4} bei einem verloren.",
+            ],
+            Msg::SlowRow => [
+                "{0} tid[{1}] took {2}µs, over the {3}µs budget: {4}µs store lookup, {5}µs policy, {6}µs apply.",
+                "{0} tid[{1}] a pris {2}µs, au-delà du budget de {3}µs : {4}µs de recherche, {5}µs de politique, {6}µs d'application.",
+                "{0} tid[{1}] dauerte {2}µs, über dem Budget von {3}µs: {4}µs Suche, {5}µs Richtlinie, {6}µs Anwendung.",
+            ],
+            Msg::Progress => [
+                "{0} rows so far: {1} applied, {2} rejected, {3} clients, {4} rows/s.",
+                "{0} lignes jusqu'ici : {1} appliquées, {2} rejetées, {3} clients, {4} lignes/s.",
+                "Bisher {0} Zeilen: {1} angewendet, {2} abgelehnt, {3} Kunden, {4} Zeilen/s.",
+            ],
+            Msg::FixupsApplied => [
+                "--fixups rewrote {0} rows and dropped {1}.",
+                "--fixups a réécrit {0} lignes et en a écarté {1}.",
+                "--fixups hat {0} Zeilen umgeschrieben und {1} verworfen.",
+            ],
+            Msg::InsufficientDispute => [
+                "Insuffient funds to hold disputed tid[{0}]. Ignoring.",
+                "Fonds insuffisants pour bloquer la transaction contestée tid[{0}]. Ignoré.",
+                "Unzureichendes Guthaben, um die angefochtene tid[{0}] zurückzuhalten. Ignoriert.",
+            ],
+            Msg::PartialHold => [
+                "Dispute of tid[{0}] held {1} of {2}, {3} short.",
+                "La contestation de tid[{0}] a bloqué {1} sur {2}, il manque {3}.",
+                "Anfechtung von tid[{0}] hat {1} von {2} zurückgehalten, {3} fehlen.",
+            ],
+            Msg::DisputeShortfall => [
+                "Disputes held {0} less than they disputed under --dispute-policy partial-hold.",
+                "Les contestations ont bloqué {0} de moins que contesté avec --dispute-policy partial-hold.",
+                "Anfechtungen haben mit --dispute-policy partial-hold {0} weniger zurückgehalten als angefochten.",
+            ],
+            Msg::ClientInTwoFiles => [
+                "client[{0}] has rows in both {1} and {2}, --disjoint-files needs each client in one file only. Nothing was written.",
+                "client[{0}] a des lignes dans {1} et dans {2}, --disjoint-files exige chaque client dans un seul fichier. Rien n'a été écrit.",
+                "client[{0}] hat Zeilen in {1} und in {2}, --disjoint-files verlangt jeden Kunden in nur einer Datei. Nichts wurde geschrieben.",
+            ],
+            Msg::ChargebackFees => [
+                "Assessed chargeback fees totalling {0}.",
+                "Frais de rétrofacturation appliqués pour un total de {0}.",
+                "Rückbuchungsgebühren über insgesamt {0} berechnet.",
+            ],
+            Msg::NextRun => [
+                "Next run at {0}.",
+                "Prochaine exécution à {0}.",
+                "Nächster Lauf um {0}.",
+            ],
+            Msg::RunFinished => [
+                "Run at {0} finished in {1}s.",
+                "Exécution de {0} terminée en {1}s.",
+                "Lauf um {0} nach {1}s beendet.",
+            ],
+            Msg::RunFailed => [
+                "Run at {0} failed: {1}",
+                "Échec de l'exécution de {0} : {1}",
+                "Lauf um {0} fehlgeschlagen: {1}",
+            ],
+            Msg::NotifyFailed => [
+                "Failure notification {0} did not run: {1}",
+                "La notification d'échec {0} n'a pas abouti : {1}",
+                "Fehlerbenachrichtigung {0} lief nicht: {1}",
+            ],
+            Msg::WrittenOff => [
+                "Chargebacks wrote off {0} of held funds.",
+                "Les rétrofacturations ont passé en perte {0} de fonds bloqués.",
+                "Rückbuchungen haben {0} an gesperrten Mitteln abgeschrieben.",
+            ],
+            Msg::Pending => [
+                "Deposits still pending settlement: {0}.",
+                "Dépôts toujours en attente de règlement : {0}.",
+                "Noch nicht abgewickelte Einzahlungen: {0}.",
+            ],
+            Msg::InputUnreadable => [
+                "Reading {0} failed partway, nothing was written.",
+                "La lecture de {0} a échoué en cours de route, rien n'a été écrit.",
+                "Das Lesen von {0} ist unterwegs fehlgeschlagen, nichts wurde geschrieben.",
+            ],
+            Msg::CustomRefused => [
+                "Rejected {0} tid[{1}], its handler refused it. Ignoring.",
+                "{0} tid[{1}] rejeté : son gestionnaire l'a refusé. Ignoré.",
+                "{0} tid[{1}] abgelehnt: sein Handler hat ihn abgelehnt. Ignoriert.",
+            ],
+        }
+    }
+}
+
+// One pass over the template, so an argument that happens to contain {1} is left alone.
+fn render(lang: Lang, msg: Msg, args: &[&dyn Display]) -> String {
+    let template = msg.templates()[lang as usize];
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let end = start + rest[start..].find('}').expect("unclosed placeholder");
+        let index: usize = rest[start + 1..end].parse().expect("numbered placeholder");
+        out.push_str(&args[index].to_string());
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// The message in the language set by --lang.
+pub fn text(msg: Msg, args: &[&dyn Display]) -> String {
+    let lang = *LANG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    render(lang, msg, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn placeholders(template: &str) -> BTreeSet<&str> {
+        template
+            .split('{')
+            .skip(1)
+            .map(|rest| &rest[..rest.find('}').unwrap()])
+            .collect()
+    }
+
+    #[test]
+    fn every_language_takes_the_same_arguments() {
+        for msg in ALL {
+            let [en, fr, de] = msg.templates();
+            assert_eq!(placeholders(en), placeholders(fr), "{:?}", msg);
+            assert_eq!(placeholders(en), placeholders(de), "{:?}", msg);
+        }
+    }
+
+    #[test]
+    fn renders_in_each_language() {
+        let args: [&dyn Display; 3] = [&"dispute", &7, &"{1}"];
+        assert_eq!(
+            render(Lang::En, Msg::Rejected, &args),
+            "Rejected dispute tid[7], it {1}. Ignoring."
+        );
+        assert_eq!(
+            render(Lang::Fr, Msg::AssertionsFailed, &[&2, &5]),
+            "2 assertions sur 5 ont échoué."
+        );
+        assert_eq!(
+            render(Lang::De, Msg::RetryWritten, &[&3]),
+            "3 abgelehnte Transaktionen zur Wiederholung geschrieben."
+        );
+        assert_eq!("fr".parse::<Lang>().unwrap(), Lang::Fr);
+        assert!("es".parse::<Lang>().is_err());
+    }
+}
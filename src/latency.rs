@@ -0,0 +1,122 @@
+use crate::i18n::{text, Msg};
+use crate::warnings::{Kind, Warning};
+use std::time::{Duration, Instant};
+use txcli_model::TxRecord;
+
+// Where one row's time went in Engine::try_apply: bringing the referenced transaction back from
+// the history store, the checks deciding whether the row may apply at all (validation, archival,
+// dust), and applying it, dispute table and lock included, with the bookkeeping after.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Breakdown {
+    pub lookup: Duration,
+    pub policy: Duration,
+    pub apply: Duration,
+}
+
+impl Breakdown {
+    pub fn total(&self) -> Duration {
+        self.lookup + self.policy + self.apply
+    }
+}
+
+// Marks taken as try_apply passes each stage.
+pub struct Stopwatch {
+    started: Instant,
+    looked_up: Instant,
+    checked: Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Stopwatch {
+            started: now,
+            looked_up: now,
+            checked: now,
+        }
+    }
+
+    pub fn looked_up(&mut self) {
+        self.looked_up = Instant::now();
+    }
+
+    pub fn checked(&mut self) {
+        self.checked = Instant::now();
+    }
+
+    pub fn stop(self) -> Breakdown {
+        Breakdown {
+            lookup: self.looked_up - self.started,
+            policy: self.checked - self.looked_up,
+            apply: self.checked.elapsed(),
+        }
+    }
+}
+
+// Rows timed against --latency-budget, each one over it warned about with its breakdown, so a
+// slow plugin or history backend shows up on the row it slowed down rather than in an average.
+#[derive(Debug, Default)]
+pub struct Latency {
+    budget: Duration,
+    pub slow: u64,
+    pub slowest: Duration,
+}
+
+impl Latency {
+    pub fn new(budget: Duration) -> Self {
+        Latency {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    pub fn record(&mut self, tx: &TxRecord, breakdown: Breakdown) {
+        let total = breakdown.total();
+        self.slowest = self.slowest.max(total);
+        if total <= self.budget {
+            return;
+        }
+        self.slow += 1;
+        Warning::new(
+            Kind::Slow,
+            text(
+                Msg::SlowRow,
+                &[
+                    &tx.tx_type,
+                    &tx.tid.0,
+                    &total.as_micros(),
+                    &self.budget.as_micros(),
+                    &breakdown.lookup.as_micros(),
+                    &breakdown.policy.as_micros(),
+                    &breakdown.apply.as_micros(),
+                ],
+            ),
+        )
+        .tx(tx.tx_type, tx.tid)
+        .client(tx.cid)
+        .emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::{Currency, TxType};
+
+    #[test]
+    fn counts_rows_over_budget() {
+        let mut latency = Latency::new(Duration::from_micros(100));
+        let tx = TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1));
+        let row = |lookup, policy, apply| Breakdown {
+            lookup: Duration::from_micros(lookup),
+            policy: Duration::from_micros(policy),
+            apply: Duration::from_micros(apply),
+        };
+        latency.record(&tx, row(10, 20, 30));
+        latency.record(&tx, row(40, 30, 30));
+        assert_eq!(latency.slow, 0);
+        latency.record(&tx, row(90, 5, 50));
+        assert_eq!(latency.slow, 1);
+        assert_eq!(latency.slowest, Duration::from_micros(145));
+    }
+}
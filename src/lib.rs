@@ -0,0 +1,2555 @@
+mod activity;
+mod anomaly;
+mod anonymize;
+mod assertions;
+mod asset;
+mod audit;
+mod availability;
+mod bootstrap;
+mod cases;
+mod categorize;
+mod cdc;
+mod chunked;
+mod clearing;
+mod compare;
+mod config;
+mod conservation;
+mod control;
+mod converge;
+mod custom;
+mod determinism;
+mod dispute_state;
+mod emit;
+mod engine;
+mod estimate;
+mod explain;
+#[cfg(feature = "faults")]
+mod faults;
+mod filter;
+mod fixed_width;
+mod fixups;
+mod gating;
+mod graph;
+mod i18n;
+mod latency;
+mod manifest;
+mod memory;
+mod merge;
+mod metadata;
+mod metrics;
+mod options;
+mod ordering;
+mod partition;
+mod penalty;
+#[cfg(feature = "server")]
+mod pipe;
+mod pipeline;
+mod policy;
+mod precision;
+mod priority;
+mod profile;
+mod reconcile;
+mod redis;
+mod rejection;
+mod report;
+mod results;
+mod retention;
+mod retry;
+mod rollforward;
+mod sample;
+mod schedule;
+mod schema;
+mod screen;
+#[cfg(feature = "server")]
+mod server;
+mod settlement;
+mod shard;
+mod shrink;
+mod snapshot;
+mod soak;
+mod source;
+mod sources;
+mod spill;
+mod split;
+mod statements;
+mod tui;
+mod upsert;
+mod validation;
+#[cfg(test)]
+mod verify;
+mod warnings;
+mod writeoff;
+
+// The embedding API: build a TransactionEngine, apply rows to it and read the balances back.
+// Everything else in the crate is the txcli command line, run, built on top of it. These names
+// are what downstream crates depend on, tests/public_api.rs uses every one of them, so removing
+// or changing one fails the build rather than someone else's.
+pub use asset::AssetRegistry;
+pub use availability::SettlementDelays;
+pub use custom::{CustomTxHandler, Effect};
+pub use engine::{
+    ChargebackFee, DisputePolicy, Engine as TransactionEngine, EngineBuilder, FeePayer,
+    LockedPolicy, Overflow, RiskLock,
+};
+pub use metrics::Metrics;
+// Reading inputs in any format as csv, and logging rejections like --errors.
+pub use rejection::{RejectionLog, RejectionReason};
+pub use results::{Report, Stats, Totals};
+pub use retention::Retention;
+pub use source::{InputFormat, TxSource};
+pub use spill::HistoryBackend;
+pub use txcli_model::{ClientId, ClientOutputState, Currency, Tx, TxId, TxRecord, TxType};
+pub use validation::TidScope;
+
+use dispute_state::DisputeState;
+use fixups::Fixups;
+use i18n::{text, Msg};
+use options::{Command, Options};
+use ordering::OrderingPolicy;
+use schema::Schema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::sync::Arc;
+use txcli_model::{parse_amount, AccountId, Timestamp};
+use warnings::{Kind, Warning};
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Clone, Copy)]
+struct Balance {
+    available: Currency,
+    held: Currency,
+    // Total overdraft penalties charged against this account, see penalty::assess.
+    penalties: Currency,
+    // Total chargeback fees taken from this account, only when clients pay them.
+    #[serde(default)]
+    chargeback_fees: Currency,
+    // Held funds chargebacks took for good, see writeoff::WriteoffReport.
+    #[serde(default)]
+    written_off: Currency,
+    // Deposits waiting out their counterparty's settlement delay, see availability::Holds.
+    #[serde(default)]
+    pending: Currency,
+}
+
+// Locking applies to the client as a whole, a chargeback on any account freezes all of them.
+#[derive(Default, Debug, PartialEq, Clone)]
+struct ClientState {
+    accounts: BTreeMap<AccountId, Balance>,
+    // Balances of rows naming a currency, per asset. Rows without one use accounts, so files
+    // without a currency column keep a single asset.
+    assets: BTreeMap<String, BTreeMap<AccountId, Balance>>,
+    locked: bool,
+    history: HashMap<TxId, Tx>,
+    disputed: HashMap<TxId, Tx>,
+    // Transactions past Settled, see dispute_state. Disputed ones also sit in disputed, voided
+    // ones are gone from history.
+    lifecycle: HashMap<TxId, DisputeState>,
+    // What disputes of deposits couldn't hold under DisputePolicy::PartialHold, per disputed
+    // transaction. Resolves drop theirs, charged back ones stay as what the chargeback missed.
+    shortfalls: HashMap<TxId, Currency>,
+}
+
+impl ClientState {
+    fn dispute_state(&self, tid: TxId) -> DisputeState {
+        if let Some(state) = self.lifecycle.get(&tid) {
+            return *state;
+        }
+        match self.history.get(&tid) {
+            Some(tx) if tx.tx_type == TxType::Transfer => DisputeState::Internal,
+            Some(tx) if tx.tx_type == TxType::Note => DisputeState::Noted,
+            Some(_) => DisputeState::Settled,
+            None => DisputeState::Unknown,
+        }
+    }
+
+    // What the dispute of tid couldn't hold, see DisputePolicy::PartialHold.
+    fn shortfall(&self, tid: TxId) -> Currency {
+        self.shortfalls.get(&tid).copied().unwrap_or_default()
+    }
+
+    fn account(&mut self, account: &str) -> &mut Balance {
+        self.balance(None, account)
+    }
+
+    // The account's balance in currency, the unnamed asset for None.
+    fn balance(&mut self, currency: Option<&str>, account: &str) -> &mut Balance {
+        let accounts = match currency {
+            None => &mut self.accounts,
+            Some(currency) => self.assets.entry(currency.to_string()).or_default(),
+        };
+        if !accounts.contains_key(account) {
+            accounts.insert(AccountId(account.to_string()), Balance::default());
+        }
+        accounts.get_mut(account).unwrap()
+    }
+
+    // Like balance, without creating it.
+    fn balance_of(&self, currency: Option<&str>, account: &str) -> Option<&Balance> {
+        match currency {
+            None => self.accounts.get(account),
+            Some(currency) => self.assets.get(currency)?.get(account),
+        }
+    }
+
+    // Every balance with its asset, the unnamed one first.
+    fn balances(&self) -> impl Iterator<Item = (Option<&str>, &AccountId, &Balance)> {
+        let unnamed = self
+            .accounts
+            .iter()
+            .map(|(account, balance)| (None, account, balance));
+        let named = self.assets.iter().flat_map(|(asset, accounts)| {
+            accounts
+                .iter()
+                .map(move |(account, balance)| (Some(asset.as_str()), account, balance))
+        });
+        unnamed.chain(named)
+    }
+
+    fn balances_mut(&mut self) -> impl Iterator<Item = &mut Balance> {
+        self.accounts.values_mut().chain(
+            self.assets
+                .values_mut()
+                .flat_map(|accounts| accounts.values_mut()),
+        )
+    }
+
+    // The assets the client holds balances in, None for the unnamed one.
+    fn asset_names(&self) -> impl Iterator<Item = Option<&str>> {
+        let unnamed = (!self.accounts.is_empty()).then_some(None);
+        unnamed
+            .into_iter()
+            .chain(self.assets.keys().map(|asset| Some(asset.as_str())))
+    }
+
+    // Summed across assets, only meaningful for clients holding one.
+    fn available(&self) -> Currency {
+        self.balances()
+            .map(|(_, _, balance)| balance.available)
+            .sum()
+    }
+
+    fn held(&self) -> Currency {
+        self.balances().map(|(_, _, balance)| balance.held).sum()
+    }
+
+    fn penalties(&self) -> Currency {
+        self.balances()
+            .map(|(_, _, balance)| balance.penalties)
+            .sum()
+    }
+
+    fn chargeback_fees(&self) -> Currency {
+        self.balances()
+            .map(|(_, _, balance)| balance.chargeback_fees)
+            .sum()
+    }
+
+    fn written_off(&self) -> Currency {
+        self.balances()
+            .map(|(_, _, balance)| balance.written_off)
+            .sum()
+    }
+
+    fn pending(&self) -> Currency {
+        self.balances().map(|(_, _, balance)| balance.pending).sum()
+    }
+
+    // No funds anywhere and nothing disputed, what archive_client asks of a client.
+    fn dormant(&self) -> bool {
+        self.disputed.is_empty()
+            && self.balances().all(|(_, _, balance)| {
+                balance.available == 0 && balance.held == 0 && balance.pending == 0
+            })
+    }
+
+    fn has_named_accounts(&self) -> bool {
+        self.balances()
+            .any(|(_, account, _)| *account != AccountId::default())
+    }
+
+    // Pending funds are the client's, counted in the total though not available.
+    fn output(&self, cid: ClientId) -> ClientOutputState {
+        let (available, held) = (self.available(), self.held());
+        ClientOutputState {
+            cid,
+            currency: None,
+            account: None,
+            available,
+            held,
+            total: available + held + self.pending(),
+            locked: self.locked,
+            owners: None,
+            penalties: None,
+            chargeback_fees: None,
+            written_off: None,
+            dispute_count: None,
+            pending: None,
+        }
+    }
+
+    // One row per asset like output, summed over the accounts, rows for the unnamed asset have no
+    // currency. The cdc stream and the sample follow each asset through these.
+    fn asset_outputs(&self, cid: ClientId) -> Vec<ClientOutputState> {
+        if self.asset_names().next().is_none() {
+            return vec![self.output(cid)];
+        }
+        self.split_output(cid, true, false)
+            .into_iter()
+            .map(|row| ClientOutputState {
+                currency: row.currency.filter(|currency| !currency.is_empty()),
+                penalties: None,
+                chargeback_fees: None,
+                written_off: None,
+                pending: None,
+                ..row
+            })
+            .collect()
+    }
+
+    // One row per asset and account, either summed over when not split by. Split by asset, rows
+    // without a currency get a blank one. Penalties, chargeback fees, writeoffs and pending funds
+    // are always filled, the engine drops them when they aren't charged or tracked.
+    fn split_output(
+        &self,
+        cid: ClientId,
+        per_asset: bool,
+        per_account: bool,
+    ) -> Vec<ClientOutputState> {
+        let mut rows: BTreeMap<(Option<&str>, Option<&AccountId>), Balance> = BTreeMap::new();
+        for (asset, account, balance) in self.balances() {
+            let key = (
+                asset.filter(|_| per_asset),
+                Some(account).filter(|_| per_account),
+            );
+            let row = rows.entry(key).or_default();
+            row.available += balance.available;
+            row.held += balance.held;
+            row.penalties += balance.penalties;
+            row.chargeback_fees += balance.chargeback_fees;
+            row.written_off += balance.written_off;
+            row.pending += balance.pending;
+        }
+        rows.into_iter()
+            .map(|((asset, account), balance)| ClientOutputState {
+                cid,
+                currency: per_asset.then(|| asset.unwrap_or_default().to_string()),
+                account: account.cloned(),
+                available: balance.available,
+                held: balance.held,
+                total: balance.available + balance.held + balance.pending,
+                locked: self.locked,
+                owners: None,
+                penalties: Some(balance.penalties),
+                chargeback_fees: Some(balance.chargeback_fees),
+                written_off: Some(balance.written_off),
+                dispute_count: None,
+                pending: Some(balance.pending),
+            })
+            .collect()
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Clone)]
+struct AppState {
+    clients: HashMap<ClientId, ClientState>,
+    // Joint owners act on the canonical client's state, see metadata::ClientMetadata.
+    joint_owners: HashMap<ClientId, ClientId>,
+    locked_policy: LockedPolicy,
+    dispute_policy: DisputePolicy,
+    chargeback_fee: Option<ChargebackFee>,
+    // Dormant clients moved out by archive_client, left out of the balances until
+    // reactivate_client brings them back.
+    archived: HashMap<ClientId, ClientState>,
+}
+
+impl AppState {
+    fn canonical(&self, cid: ClientId) -> ClientId {
+        self.joint_owners.get(&cid).copied().unwrap_or(cid)
+    }
+
+    fn owners(&self, cid: ClientId) -> String {
+        let mut owners: Vec<u16> = self
+            .joint_owners
+            .iter()
+            .filter(|(_, owner)| **owner == cid)
+            .map(|(joint, _)| joint.0)
+            .collect();
+        owners.sort_unstable();
+        std::iter::once(cid.0)
+            .chain(owners)
+            .map(|owner| owner.to_string())
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    // The client whether active or archived.
+    fn client_mut(&mut self, cid: ClientId) -> Option<&mut ClientState> {
+        match self.clients.get_mut(&cid) {
+            Some(client) => Some(client),
+            None => self.archived.get_mut(&cid),
+        }
+    }
+
+    fn output_state(&self, cid: ClientId) -> Option<ClientOutputState> {
+        self.clients.get(&cid).map(|client| client.output(cid))
+    }
+
+    fn asset_states(&self, cid: ClientId) -> Vec<ClientOutputState> {
+        self.clients
+            .get(&cid)
+            .map_or_else(Vec::new, |client| client.asset_outputs(cid))
+    }
+}
+
+#[derive(Debug)]
+struct BasicError {
+    desc: String,
+}
+
+impl BasicError {
+    fn new(desc: impl Into<String>) -> Box<Self> {
+        Box::new(BasicError { desc: desc.into() })
+    }
+}
+
+impl Display for BasicError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.desc)
+    }
+}
+
+impl Error for BasicError {
+    fn description(&self) -> &str {
+        &self.desc
+    }
+
+    fn cause(&self) -> Option<&dyn Error> {
+        None
+    }
+}
+
+// Rejects tx when its client is locked and the locked policy doesn't let its type through.
+fn check_lock(app_state: &AppState, tx: &TxRecord) -> Result<(), RejectionReason> {
+    let cid = app_state.canonical(tx.cid);
+    let locked = app_state
+        .clients
+        .get(&cid)
+        .is_some_and(|client| client.locked);
+    if !locked || !app_state.locked_policy.rejects(tx.tx_type) {
+        return Ok(());
+    }
+    Warning::new(
+        Kind::Locked,
+        text(Msg::ClientLocked, &[&tx.tx_type, &tx.tid.0, &cid.0]),
+    )
+    .tx(tx.tx_type, tx.tid)
+    .client(tx.cid)
+    .emit();
+    Err(RejectionReason::Locked)
+}
+
+// Returns why the transaction was rejected, if it was. Rejected transactions are also reported to stderr and leave
+// balances untouched.
+// Which rows are allowed against which transaction is decided by dispute_state::TRANSITIONS,
+// the arms below only move the funds.
+fn execute_transaction(app_state: &mut AppState, tx: TxRecord) -> Result<(), RejectionReason> {
+    check_lock(app_state, &tx)?;
+    let cid = app_state.canonical(tx.cid);
+    let client_entry = app_state.clients.entry(cid).or_default();
+
+    let state = client_entry.dispute_state(tx.tid);
+    let Some(next) = dispute_state::next(state, tx.tx_type) else {
+        let reason = dispute_state::rejection(state, tx.tx_type);
+        Warning::new(
+            Kind::Rejected,
+            text(
+                Msg::Rejected,
+                &[
+                    &tx.tx_type,
+                    &tx.tid.0,
+                    &text(dispute_state::clause(reason), &[]),
+                ],
+            ),
+        )
+        .tx(tx.tx_type, tx.tid)
+        .client(tx.cid)
+        .emit();
+        return Err(reason);
+    };
+
+    // A reference row naming a currency has to name the referenced transaction's, one without
+    // follows the transaction into whatever asset it moved.
+    let referenced = client_entry
+        .history
+        .get(&tx.tid)
+        .or_else(|| client_entry.disputed.get(&tx.tid));
+    if let (Some(currency), Some(referenced)) = (tx.currency, referenced) {
+        if referenced.currency.as_deref() != Some(currency) {
+            Warning::new(
+                Kind::Rejected,
+                text(
+                    Msg::Rejected,
+                    &[&tx.tx_type, &tx.tid.0, &text(Msg::AssetMismatch, &[])],
+                ),
+            )
+            .tx(tx.tx_type, tx.tid)
+            .client(tx.cid)
+            .emit();
+            return Err(RejectionReason::AssetMismatch);
+        }
+    }
+
+    // The table only lets reference rows through for transactions it knows where to find.
+    let applied = match &tx.tx_type {
+        TxType::Deposit => {
+            client_entry.balance(tx.currency, tx.account).available += tx.amount;
+            Ok(())
+        }
+        TxType::Withdrawal => {
+            let account = client_entry.balance(tx.currency, tx.account);
+            if account.available >= tx.amount {
+                account.available -= tx.amount;
+                Ok(())
+            } else {
+                Warning::new(
+                    Kind::InsufficientFunds,
+                    text(Msg::InsufficientWithdrawal, &[&tx.tid.0]),
+                )
+                .tx(tx.tx_type, tx.tid)
+                .client(tx.cid)
+                .emit();
+                Err(RejectionReason::InsufficientFunds)
+            }
+        }
+        TxType::Transfer => match tx.to_account {
+            Some(to_account) if to_account != tx.account => {
+                let from = client_entry.balance(tx.currency, tx.account);
+                if from.available >= tx.amount {
+                    from.available -= tx.amount;
+                    client_entry.balance(tx.currency, to_account).available += tx.amount;
+                    Ok(())
+                } else {
+                    Warning::new(
+                        Kind::InsufficientFunds,
+                        text(Msg::InsufficientTransfer, &[&tx.tid.0]),
+                    )
+                    .tx(tx.tx_type, tx.tid)
+                    .client(tx.cid)
+                    .emit();
+                    Err(RejectionReason::InsufficientFunds)
+                }
+            }
+            _ => {
+                Warning::new(
+                    Kind::InvalidTransfer,
+                    text(Msg::InvalidTransfer, &[&tx.tid.0]),
+                )
+                .tx(tx.tx_type, tx.tid)
+                .client(tx.cid)
+                .emit();
+                Err(RejectionReason::InvalidTransfer)
+            }
+        },
+        // A disputed deposit no longer available is decided by the dispute policy, by default
+        // the user is allowed to enter debt.
+        // A disputed withdrawal is held on the client's behalf, nothing leaves available, and
+        // charging it back is what returns the funds to the client.
+        TxType::Dispute => {
+            let previous_tx = &client_entry.history[&tx.tid];
+            let available = client_entry
+                .balance_of(previous_tx.currency.as_deref(), &previous_tx.account.0)
+                .map_or(Currency::ZERO, |account| account.available);
+            let short = match previous_tx.tx_type {
+                TxType::Deposit => {
+                    (previous_tx.amount - available.max(Currency::ZERO)).max(Currency::ZERO)
+                }
+                _ => Currency::ZERO,
+            };
+            match app_state.dispute_policy {
+                DisputePolicy::Reject if short > 0 => {
+                    Warning::new(
+                        Kind::InsufficientFunds,
+                        text(Msg::InsufficientDispute, &[&tx.tid.0]),
+                    )
+                    .tx(tx.tx_type, tx.tid)
+                    .client(tx.cid)
+                    .emit();
+                    return Err(RejectionReason::InsufficientFunds);
+                }
+                DisputePolicy::PartialHold if short > 0 => {
+                    let amount = previous_tx.amount;
+                    Warning::new(
+                        Kind::PartialHold,
+                        text(
+                            Msg::PartialHold,
+                            &[
+                                &tx.tid.0,
+                                &format!("{:.4}", amount - short),
+                                &format!("{:.4}", amount),
+                                &format!("{:.4}", short),
+                            ],
+                        ),
+                    )
+                    .tx(tx.tx_type, tx.tid)
+                    .client(tx.cid)
+                    .emit();
+                    client_entry.shortfalls.insert(tx.tid, short);
+                }
+                _ => {}
+            }
+            let previous_tx = client_entry.history.remove(&tx.tid).unwrap();
+            let hold = previous_tx.amount - client_entry.shortfall(tx.tid);
+            let account =
+                client_entry.balance(previous_tx.currency.as_deref(), &previous_tx.account.0);
+            account.held += hold;
+            if previous_tx.tx_type == TxType::Deposit {
+                account.available -= hold;
+            }
+            client_entry.disputed.insert(tx.tid, previous_tx);
+            Ok(())
+        }
+        // Only what the dispute held is released.
+        TxType::Resolve => {
+            let previous_tx = client_entry.disputed.remove(&tx.tid).unwrap();
+            let held =
+                previous_tx.amount - client_entry.shortfalls.remove(&tx.tid).unwrap_or_default();
+            let account =
+                client_entry.balance(previous_tx.currency.as_deref(), &previous_tx.account.0);
+            account.held -= held;
+            if previous_tx.tx_type == TxType::Deposit {
+                account.available += held;
+            }
+            client_entry.history.insert(tx.tid, previous_tx);
+            Ok(())
+        }
+        TxType::ChargeBack => {
+            let previous_tx = client_entry.disputed.remove(&tx.tid).unwrap();
+            let held = previous_tx.amount - client_entry.shortfall(tx.tid);
+            let account =
+                client_entry.balance(previous_tx.currency.as_deref(), &previous_tx.account.0);
+            account.held -= held;
+            // A charged back withdrawal comes back to the client, anything else is a loss.
+            if previous_tx.tx_type == TxType::Withdrawal {
+                account.available += previous_tx.amount;
+            } else {
+                account.written_off += held;
+            }
+            if let Some(ChargebackFee {
+                amount,
+                payer: FeePayer::Client,
+            }) = app_state.chargeback_fee
+            {
+                account.available -= amount;
+                account.chargeback_fees += amount;
+            }
+            client_entry.history.insert(tx.tid, previous_tx);
+            client_entry.locked = true;
+            Ok(())
+        }
+        // Same as disputes, a voided deposit may leave the account in debt.
+        TxType::Void => {
+            let previous_tx = client_entry.history.remove(&tx.tid).unwrap();
+            let account =
+                client_entry.balance(previous_tx.currency.as_deref(), &previous_tx.account.0);
+            match previous_tx.tx_type {
+                TxType::Deposit => account.available -= previous_tx.amount,
+                _ => account.available += previous_tx.amount,
+            }
+            Ok(())
+        }
+        // Only kept in history, for its memo.
+        TxType::Note => Ok(()),
+        TxType::Custom(_) => unreachable!("custom types have no transitions, see Engine::apply"),
+        TxType::ArchiveClient | TxType::ReactivateClient => {
+            unreachable!("admin rows have no transitions, see Engine::apply_admin")
+        }
+    };
+
+    if applied.is_ok() {
+        match next {
+            // Only rows that move funds can be referenced later and only notes are read back, so
+            // they are the only ones worth an owned copy.
+            DisputeState::Settled | DisputeState::Internal | DisputeState::Noted => {
+                client_entry.history.insert(tx.tid, tx.to_tx());
+            }
+            _ => {
+                client_entry.lifecycle.insert(tx.tid, next);
+            }
+        }
+    }
+    applied
+}
+
+// A file of the run's results, through the fault layer in builds with the faults feature.
+fn create(path: &str) -> std::io::Result<impl Write> {
+    let file = File::create(path)?;
+    #[cfg(feature = "faults")]
+    let file = faults::output(file);
+    Ok(file)
+}
+
+// Returns what was written so the run manifest can hash it.
+fn print_balances(
+    options: &Options,
+    report: &results::Report,
+    keep: impl Fn(&ClientOutputState) -> bool,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let out = report.balances_where(options.output_format.unwrap_or_default(), keep)?;
+    match &options.output {
+        Some(path) => create(path)?.write_all(&out)?,
+        None => std::io::stdout().write_all(&out)?,
+    }
+    Ok(out)
+}
+
+// The end of run lines on stderr, shared by processing and sharded processing.
+fn summarize(engine: &engine::Engine, report: &results::Report) {
+    if !engine.config().include_archived && engine.archived_clients() > 0 {
+        eprintln!(
+            "{}",
+            text(Msg::ArchivedClients, &[&engine.archived_clients()])
+        );
+    }
+    if report.stats.queued_disputes > 0 {
+        eprintln!(
+            "{}",
+            text(Msg::DisputesQueued, &[&report.stats.queued_disputes])
+        );
+    }
+    if engine.config().overdraft_rate.is_some() {
+        eprintln!(
+            "{}",
+            text(
+                Msg::PenaltiesAssessed,
+                &[&format!("{:.4}", report.totals.penalties_assessed)]
+            )
+        );
+    }
+    if engine.config().chargeback_fee.is_some() {
+        eprintln!(
+            "{}",
+            text(
+                Msg::ChargebackFees,
+                &[&format!("{:.4}", report.totals.chargeback_fees)]
+            )
+        );
+    }
+    if engine.config().track_writeoffs {
+        eprintln!(
+            "{}",
+            text(
+                Msg::WrittenOff,
+                &[&format!("{:.4}", report.totals.written_off)]
+            )
+        );
+    }
+    if engine.config().settlement_delays.is_some() {
+        eprintln!(
+            "{}",
+            text(Msg::Pending, &[&format!("{:.4}", report.totals.pending)])
+        );
+    }
+    if engine.config().dispute_policy == DisputePolicy::PartialHold {
+        eprintln!(
+            "{}",
+            text(
+                Msg::DisputeShortfall,
+                &[&format!("{:.4}", report.totals.shortfall)]
+            )
+        );
+    }
+    if let Some(assets) = &engine.config().assets {
+        for (code, swept) in &report.totals.swept {
+            let decimals = assets.decimals(Some(code)) as usize;
+            eprintln!(
+                "{}",
+                text(Msg::DustSwept, &[&format!("{:.*}", decimals, swept), code])
+            );
+        }
+    }
+}
+
+fn run_screen<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = schema.reader(reader.headers()?)?;
+    let mut screen = screen::Screen::default();
+    while let Some(row) = rows.next(reader) {
+        let tx = row?;
+        screen.observe(tx.tx_type, tx.amount);
+    }
+    eprintln!("{}", screen.summary());
+    screen.write(std::io::stdout())
+}
+
+// Exits with an error when the versions disagree, so an upgrade check can gate a rollout.
+fn run_compare(options: &Options) -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = match options.schema {
+        Some(Schema::V1) => vec!["--schema".to_string(), "v1".to_string()],
+        Some(Schema::V2) => vec!["--schema".to_string(), "v2".to_string()],
+        None => vec![],
+    };
+    let (old, new) = (
+        options.old.as_deref().unwrap_or_default(),
+        options.new.as_deref().unwrap_or_default(),
+    );
+    let differences = compare::compare(
+        &compare::run(old, &options.input, &args)?,
+        &compare::run(new, &options.input, &args)?,
+    )?;
+    compare::write(&differences, std::io::stdout())?;
+    if !differences.is_empty() {
+        return Err(BasicError::new(format!(
+            "{} and {} disagree on {} fields.",
+            old,
+            new,
+            differences.len()
+        )));
+    }
+    eprintln!("{} and {} agree.", old, new);
+    Ok(())
+}
+
+// Exits with an error when settings conflict, so a policy change can be checked before it ships.
+fn run_policy(options: &Options) -> Result<(), Box<dyn Error>> {
+    let conflicts = policy::lint(options)?;
+    if options.policy_action == Some(policy::PolicyAction::Explain) {
+        print!("{}", policy::explain(options));
+    }
+    // They are what lint reports, explain reports the policy.
+    for conflict in &conflicts {
+        match options.policy_action {
+            Some(policy::PolicyAction::Lint) => println!("{}", conflict),
+            _ => eprintln!("{}", conflict),
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(BasicError::new(format!(
+            "The policy has {} conflicts.",
+            conflicts.len()
+        )));
+    }
+    if options.policy_action == Some(policy::PolicyAction::Lint) {
+        eprintln!("The policy is valid.");
+    }
+    Ok(())
+}
+
+fn run_activity<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = schema.reader(reader.headers()?)?;
+    let mut activity = activity::Activity::default();
+    while let Some(row) = rows.next(reader) {
+        let tx = row?;
+        activity.observe(tx.cid, tx.tx_type, tx.amount);
+    }
+    eprintln!("{}", activity.summary());
+    activity.write(std::io::stdout())
+}
+
+fn run_statements<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let metadata = match &options.clients {
+        Some(path) => Some(metadata::load_columns(File::open(path)?)?),
+        None => None,
+    };
+    let mut engine = engine_builder(options)?.build();
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = schema.reader(reader.headers()?)?;
+    let mut statements = statements::Statements::default();
+    while let Some(row) = rows.next(reader) {
+        let tx = row?;
+        let applied = engine.apply(tx);
+        statements.record(&engine, &tx, applied);
+    }
+    engine.finish();
+    let dir = options.out_dir.as_deref().unwrap_or_default();
+    let written = statements.write(dir, &engine, metadata.as_ref())?;
+    eprintln!("{}", text(Msg::StatementsWritten, &[&written, &dir]));
+    Ok(())
+}
+
+fn run_audit<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let mut engine = engine_builder(options)?.build();
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = schema.reader(reader.headers()?)?;
+    let mut audit = audit::AuditTrail::default();
+    while rows.read(reader)? {
+        let line = rows.line();
+        let tx = rows.parse()?;
+        let result = engine.try_apply(tx);
+        audit.record(&engine, Some(line), &tx, result);
+    }
+    engine.finish();
+    let dir = options.out_dir.as_deref().unwrap_or_default();
+    let written = audit.write(dir)?;
+    eprintln!("{}", text(Msg::AuditWritten, &[&written, &dir]));
+    Ok(())
+}
+
+// The input's rows with --fixups applied as they are read.
+fn fixed_rows(
+    options: &Options,
+    rows: schema::RowReader,
+) -> Result<schema::RowReader, Box<dyn Error>> {
+    Ok(match &options.fixups {
+        Some(path) => rows.with_fixups(Arc::new(Fixups::load(path)?)),
+        None => rows,
+    })
+}
+
+fn report_fixups(fixups: Option<&Fixups>) {
+    if let Some((rewritten, dropped)) = fixups.map(Fixups::counts) {
+        eprintln!("{}", text(Msg::FixupsApplied, &[&rewritten, &dropped]));
+    }
+}
+
+// Plain processing with rows applied on a pool of engines, see shard::Shards.
+fn run_sharded<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let mut shards = shard::Shards::new(&engine_builder(options)?, options.threads.unwrap_or(1));
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = fixed_rows(options, schema.reader(reader.headers()?)?)?;
+    // Reordered before sharding, so each shard sees its rows in the order processing applies them.
+    let mut pending = (rows.has_priority()
+        && options.ordering_policy != Some(OrderingPolicy::Input))
+    .then(priority::PendingQueue::default);
+    let mut parse_errors = 0;
+    while let Some(row) = rows.next(reader) {
+        let tx = match row {
+            Ok(tx) => tx,
+            Err(err) => {
+                Warning::new(Kind::ParseError, text(Msg::ParseError, &[&err]))
+                    .line(rows.line())
+                    .emit();
+                parse_errors += 1;
+                if schema::unreadable(&*err) {
+                    return Err(BasicError::new(text(
+                        Msg::InputUnreadable,
+                        &[&options.input],
+                    )));
+                }
+                if options.strict {
+                    break;
+                }
+                continue;
+            }
+        };
+        match &mut pending {
+            Some(pending) => {
+                for tx in pending.push(tx.timestamp, tx.priority, tx.to_tx()) {
+                    shards.push(tx)?;
+                }
+            }
+            None => shards.push(tx.to_tx())?,
+        }
+    }
+    if let Some(pending) = &mut pending {
+        for tx in pending.drain() {
+            shards.push(tx)?;
+        }
+    }
+    let engine = shards.finish()?;
+    if options.strict && parse_errors > 0 {
+        return Err(BasicError::new(text(
+            Msg::StoppedAtParseError,
+            &[&options.input],
+        )));
+    }
+    finish_gathered(options, &engine, parse_errors, rows.fixups())
+}
+
+// One input of --disjoint-files applied on an engine of its own, with the clients its rows name.
+struct FileRun {
+    engine: engine::Engine,
+    parse_errors: u64,
+    clients: std::collections::HashSet<ClientId>,
+}
+
+fn apply_file(
+    options: &Options,
+    builder: EngineBuilder,
+    path: &str,
+    fixups: Option<Arc<Fixups>>,
+) -> Result<FileRun, Box<dyn Error>> {
+    let file = source::TxSource::for_run(path, options)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(true)
+        .flexible(true)
+        .buffer_capacity(options.read_buffer.unwrap_or(chunked::DEFAULT_READ_BUFFER))
+        .from_reader(file);
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = schema.reader(reader.headers()?)?;
+    if let Some(fixups) = fixups {
+        rows = rows.with_fixups(fixups);
+    }
+    let mut engine = builder.build();
+    let mut pending = (rows.has_priority()
+        && options.ordering_policy != Some(OrderingPolicy::Input))
+    .then(priority::PendingQueue::default);
+    let mut parse_errors = 0;
+    let mut clients = std::collections::HashSet::new();
+    while let Some(row) = rows.next(&mut reader) {
+        let tx = match row {
+            Ok(tx) => tx,
+            Err(err) => {
+                Warning::new(Kind::ParseError, text(Msg::ParseError, &[&err]))
+                    .line(rows.line())
+                    .emit();
+                parse_errors += 1;
+                if schema::unreadable(&*err) {
+                    return Err(BasicError::new(text(Msg::InputUnreadable, &[&path])));
+                }
+                if options.strict {
+                    break;
+                }
+                continue;
+            }
+        };
+        clients.insert(engine.state().canonical(tx.cid));
+        match &mut pending {
+            Some(pending) => {
+                for tx in pending.push(tx.timestamp, tx.priority, tx.to_tx()) {
+                    engine.apply(tx.as_record());
+                }
+            }
+            None => {
+                engine.apply(tx);
+            }
+        }
+    }
+    if let Some(pending) = &mut pending {
+        for tx in pending.drain() {
+            engine.apply(tx.as_record());
+        }
+    }
+    engine.history_error()?;
+    engine.finish();
+    Ok(FileRun {
+        engine,
+        parse_errors,
+        clients,
+    })
+}
+
+// Plain processing of several inputs at once, each file on an engine and thread of its own, for
+// inputs known to share no client. The clients are gathered into one engine at the end, after
+// checking no client was named by two files, since its rows would have been split between two
+// states that can't be put back together.
+fn run_disjoint(options: &Options) -> Result<(), Box<dyn Error>> {
+    let builder = engine_builder(options)?;
+    let fixups = match &options.fixups {
+        Some(path) => Some(Arc::new(Fixups::load(path)?)),
+        None => None,
+    };
+    let paths: Vec<&str> = std::iter::once(&options.input)
+        .chain(&options.more_inputs)
+        .map(String::as_str)
+        .collect();
+    let runs: Vec<Result<FileRun, String>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                let (builder, fixups) = (builder.clone(), fixups.clone());
+                scope.spawn(move || {
+                    apply_file(options, builder, path, fixups).map_err(|err| err.to_string())
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .zip(&paths)
+            .map(|(worker, path)| {
+                worker
+                    .join()
+                    .unwrap_or_else(|_| Err(format!("Applying {} panicked.", path)))
+            })
+            .collect()
+    });
+
+    let mut owners: HashMap<ClientId, &str> = HashMap::new();
+    let mut gathered: Option<engine::Engine> = None;
+    let mut parse_errors = 0;
+    for (path, run) in paths.iter().copied().zip(runs) {
+        let run = run.map_err(|err| BasicError::new(format!("{}: {}", path, err)))?;
+        if options.strict && run.parse_errors > 0 {
+            return Err(BasicError::new(text(Msg::StoppedAtParseError, &[&path])));
+        }
+        let mut clients: Vec<ClientId> = run.clients.into_iter().collect();
+        clients.sort_unstable();
+        for cid in clients {
+            if let Some(other) = owners.insert(cid, path) {
+                return Err(BasicError::new(text(
+                    Msg::ClientInTwoFiles,
+                    &[&cid.0, &other, &path],
+                )));
+            }
+        }
+        parse_errors += run.parse_errors;
+        match &mut gathered {
+            Some(engine) => engine.absorb(run.engine),
+            None => gathered = Some(run.engine),
+        }
+    }
+    let engine = gathered.expect("at least one input");
+    finish_gathered(options, &engine, parse_errors, fixups.as_deref())
+}
+
+// The end of a run applied on several engines gathered into one, see run_sharded and
+// run_disjoint.
+fn finish_gathered(
+    options: &Options,
+    engine: &engine::Engine,
+    parse_errors: u64,
+    fixups: Option<&Fixups>,
+) -> Result<(), Box<dyn Error>> {
+    let report = engine.report();
+    let stats = &report.stats;
+    eprintln!(
+        "{}",
+        text(
+            Msg::RowsRead,
+            &[
+                &(stats.processed + parse_errors),
+                &stats.applied,
+                &(stats.processed - stats.applied),
+                &parse_errors
+            ]
+        )
+    );
+    report_fixups(fixups);
+    summarize(engine, &report);
+    print_balances(options, &report, |client| {
+        filter::keeps(&options.output_filter, engine.state(), client)
+    })?;
+    Ok(())
+}
+
+fn run_anonymize<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let rows = schema.reader(reader.headers()?)?;
+    let anonymizer = anonymize::Anonymizer::new(
+        options.seed.unwrap_or_default(),
+        options.perturb.unwrap_or_default(),
+        options.sample,
+    );
+    // Both are required for anonymize.
+    let out = options.out.as_deref().unwrap_or_default();
+    let (read, kept) = anonymizer.run(reader, rows, std::io::BufWriter::new(File::create(out)?))?;
+    eprintln!("Wrote {} of {} rows to {}.", kept, read, out);
+    Ok(())
+}
+
+fn run_shrink<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let rows = schema.reader(reader.headers()?)?;
+    let mut shrinker = shrink::Shrinker::read(
+        reader,
+        rows,
+        engine_builder(options)?,
+        options.conservation_check,
+    )?;
+    if shrinker.malformed > 0 {
+        eprintln!("Left out {} malformed rows.", shrinker.malformed);
+    }
+    let Some(target) = shrinker.target(options.preserve_client, options.preserve_failure) else {
+        return Err(BasicError::new(match options.preserve_client {
+            Some(cid) => format!(
+                "No row of client {} fails, there is nothing to preserve.",
+                cid.0
+            ),
+            None => "No row fails, there is nothing to preserve.".to_string(),
+        }));
+    };
+    // The first replay's warnings are all there is to them.
+    warnings::mute();
+    match &target {
+        shrink::Target::Failure(index, failure) => {
+            eprintln!("Preserving line {} {}.", shrinker.line(*index), failure)
+        }
+        shrink::Target::Balance(cid, _) => eprintln!("Preserving client {}'s balance.", cid.0),
+    }
+    let keep = shrinker.shrink(&target);
+    // Required for shrink.
+    let out = options.out.as_deref().unwrap_or_default();
+    shrinker.write(&keep, std::io::BufWriter::new(File::create(out)?))?;
+    eprintln!(
+        "Shrunk {} rows to {} in {} replays, written to {}.",
+        shrinker.len(),
+        keep.len(),
+        shrinker.replays(),
+        out
+    );
+    Ok(())
+}
+
+fn run_split<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let joint_owners = match &options.clients {
+        Some(path) => metadata::load(File::open(path)?)?.joint_owners,
+        None => HashMap::new(),
+    };
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let rows = schema.reader(reader.headers()?)?;
+    // Both are required for split.
+    let partition = partition::Partition::new(options.client_mod.unwrap_or(1), joint_owners);
+    let dir = options.out.as_deref().unwrap_or_default();
+    let counts = partition.run(reader, rows, std::path::Path::new(dir))?;
+    eprintln!(
+        "Wrote {} rows to {} shards in {}.",
+        counts.iter().sum::<u64>(),
+        counts.len(),
+        dir
+    );
+    Ok(())
+}
+
+fn run_profile<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    use profile::Stage;
+
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = schema.reader(reader.headers()?)?;
+    let mut engine = EngineBuilder::new().build();
+    let mut profile = profile::Profile::default();
+    while profile.time(Stage::Read, 1, || rows.read(reader))? {
+        let tx = profile.time(Stage::Parse, 1, || rows.parse())?;
+        profile.time(Stage::Apply, 1, || engine.apply(tx));
+    }
+    engine.finish();
+
+    let report = engine.report();
+    profile.time(Stage::Serialize, report.clients.len() as u64, || {
+        report.balances_csv()
+    })?;
+
+    profile.write_summary(std::io::stdout())?;
+    if let Some(path) = &options.profile_out {
+        profile.write_trace(std::io::BufWriter::new(File::create(path)?))?;
+    }
+    Ok(())
+}
+
+fn run_explain<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+) -> Result<(), Box<dyn Error>> {
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = schema.reader(reader.headers()?)?;
+    let mut engine = EngineBuilder::new().build();
+    let subject = match (options.tx, options.client) {
+        (Some(tid), _) => explain::Subject::Tx(tid),
+        (None, cid) => explain::Subject::Client(cid.unwrap_or_default()),
+    };
+    let mut explainer = explain::Explainer::new(subject);
+    // Same order as processing, see priority::PendingQueue.
+    let mut pending = (rows.has_priority()
+        && options.ordering_policy != Some(OrderingPolicy::Input))
+    .then(priority::PendingQueue::default);
+    let mut apply = |line: u64, tx: TxRecord| {
+        if !explainer.wants(&tx) {
+            engine.apply(tx);
+            return;
+        }
+        let cid = engine.state().canonical(tx.cid);
+        let before = engine.state().output_state(cid);
+        let applied = engine.apply(tx);
+        let after = engine.state().output_state(cid);
+        explainer.record(line, &tx, applied, before.as_ref(), after.as_ref());
+    };
+    while rows.read(reader)? {
+        let line = rows.line();
+        let tx = rows.parse()?;
+        match &mut pending {
+            Some(pending) => {
+                for (line, tx) in pending.push(tx.timestamp, tx.priority, (line, tx.to_tx())) {
+                    apply(line, tx.as_record());
+                }
+            }
+            None => apply(line, tx),
+        }
+    }
+    if let Some(pending) = &mut pending {
+        for (line, tx) in pending.drain() {
+            apply(line, tx.as_record());
+        }
+    }
+    engine.finish();
+    explainer.write(std::io::stdout(), options.format.unwrap_or_default())
+}
+
+// The engine the policy flags ask for, shared by processing and serving.
+fn engine_builder(options: &Options) -> Result<EngineBuilder, Box<dyn Error>> {
+    let mut builder = EngineBuilder::new();
+    if let Some(path) = &options.clients {
+        builder = builder.joint_owners(metadata::load(File::open(path)?)?.joint_owners);
+    }
+    if let Some(rate) = options.overdraft_rate {
+        builder = builder.overdraft_rate(rate);
+    }
+    if let Some(every) = options.cutoff_every {
+        builder = builder.cutoff_every(every);
+    }
+    if options.lock_after_chargebacks.is_some() || options.lock_after_chargeback_amount.is_some() {
+        builder = builder.risk_lock(engine::RiskLock {
+            chargebacks: options.lock_after_chargebacks,
+            amount: options.lock_after_chargeback_amount,
+        });
+    }
+    if let Some(max) = options.max_open_disputes {
+        builder = builder.dispute_cap(max, options.dispute_overflow.unwrap_or_default());
+    }
+    if let Some(path) = &options.assets {
+        let assets = AssetRegistry::load(path)?;
+        for (code, decimals) in assets.too_precise() {
+            eprintln!("{}", asset::precision_warning(code, decimals));
+        }
+        builder = builder.assets(assets);
+    }
+    if let Some(retention) = options.retain_history {
+        builder = builder.retention(retention);
+    }
+    if options.include_archived {
+        builder = builder.include_archived();
+    }
+    if let Some(transactions) = options.history_cache {
+        builder = builder.history_cache(transactions);
+    }
+    if let Some(backend) = options.history_backend {
+        builder = builder.history_backend(backend);
+    }
+    if let Some(budget) = options.latency_budget {
+        builder = builder.latency_budget(budget);
+    }
+    if let Some(policy) = options.locked_policy {
+        builder = builder.locked_policy(policy);
+    }
+    if let Some(policy) = options.dispute_policy {
+        builder = builder.dispute_policy(policy);
+    }
+    if let Some(fee) = options.chargeback_fee {
+        builder = builder.chargeback_fee(fee, options.chargeback_fee_payer.unwrap_or_default());
+    }
+    if options.writeoffs.is_some() {
+        builder = builder.track_writeoffs(true);
+    }
+    if let Some(path) = &options.settlement_delays {
+        builder = builder.settlement_delays(SettlementDelays::load(path)?);
+    }
+    if let Some(columns) = &options.columns {
+        builder = builder.columns(columns.clone());
+    }
+    if let Some(scope) = options.unique_tids {
+        builder = builder.tid_scope(scope);
+    }
+    // Registers the names before the first row is parsed.
+    if let Some(path) = &options.tx_types {
+        for (name, handler) in custom::load(path)? {
+            builder = builder.custom_type(&name, handler)?;
+        }
+    }
+    Ok(builder)
+}
+
+// Keeps one engine in memory and feeds it requests from a socket or stdin, see server.rs.
+#[cfg(feature = "server")]
+fn run_serve(options: &Options) -> Result<(), Box<dyn Error>> {
+    let engine: server::Shared = Arc::new(std::sync::Mutex::new(engine_builder(options)?.build()));
+    let _control = match &options.control {
+        Some(path) => Some(control::listen(path, Arc::clone(&engine))?),
+        None => None,
+    };
+    match &options.serve {
+        Some(addr) => {
+            let listener = std::net::TcpListener::bind(addr)?;
+            eprintln!("{}", text(Msg::Serving, &[&listener.local_addr()?]));
+            server::listen(engine, listener)
+        }
+        None => server::serve(&engine, std::io::stdin().lock(), std::io::stdout().lock()),
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn run_serve(_: &Options) -> Result<(), Box<dyn Error>> {
+    Err(BasicError::new(
+        "--serve and --stdin need txcli built with the server feature.",
+    ))
+}
+
+// Applies length prefixed frames from stdin until it closes, see pipe.rs.
+#[cfg(feature = "server")]
+fn run_pipe(options: &Options) -> Result<(), Box<dyn Error>> {
+    let engine: server::Shared = Arc::new(std::sync::Mutex::new(engine_builder(options)?.build()));
+    let _control = match &options.control {
+        Some(path) => Some(control::listen(path, Arc::clone(&engine))?),
+        None => None,
+    };
+    pipe::run(
+        &engine,
+        std::io::stdin().lock(),
+        std::io::stdout().lock(),
+        options.state_every.unwrap_or(pipe::DEFAULT_STATE_EVERY),
+    )
+}
+
+#[cfg(not(feature = "server"))]
+fn run_pipe(_: &Options) -> Result<(), Box<dyn Error>> {
+    Err(BasicError::new(
+        "txcli pipe needs txcli built with the server feature.",
+    ))
+}
+
+// The txcli command line, args as std::env::args gives them, program name first.
+pub fn run(args: Vec<String>) -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "faults")]
+    faults::init()?;
+    gating::Gating::load()?.check(&args)?;
+    let options = Options::parse(args)?;
+    if options.help {
+        println!("{}", options.usage());
+        return Ok(());
+    }
+    i18n::set_lang(options.lang.unwrap_or_default());
+    let started = std::time::Instant::now();
+    let started_at = Timestamp(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+    );
+    if let (Command::Soak, Some(duration), Some(tps)) =
+        (options.command, options.duration, options.tps)
+    {
+        return soak::Soak::new(options.seed.unwrap_or_default()).run(
+            duration,
+            tps,
+            std::io::stdout(),
+        );
+    }
+
+    if options.command == Command::DeterminismCheck {
+        return determinism::check();
+    }
+    if options.command == Command::ConvergeTest {
+        // The workload's rejections are expected, the hashes are what the run reports.
+        warnings::mute();
+        return converge::run(
+            options.replicas.unwrap_or(converge::DEFAULT_REPLICAS),
+            options.seed.unwrap_or_default(),
+            std::io::stdout(),
+        );
+    }
+    if options.command == Command::Tui {
+        return tui::run(&options);
+    }
+    if options.command == Command::CompareVersions {
+        return run_compare(&options);
+    }
+    if options.command == Command::Policy {
+        return run_policy(&options);
+    }
+    if options.command == Command::Config {
+        return config::effective(&options.config_args, std::io::stdout());
+    }
+    if let (Command::Schedule, Some(cron), Some(job)) =
+        (options.command, &options.cron, &options.job)
+    {
+        return schedule::run(cron, &schedule::Job::load(job)?, options.read_only);
+    }
+    if let (Command::Ctl, Some(request), Some(path)) =
+        (options.command, &options.ctl, &options.control)
+    {
+        print!("{}", control::send(path, request)?);
+        return Ok(());
+    }
+    if options.command == Command::Pipe {
+        return run_pipe(&options);
+    }
+    if options.serve.is_some() || options.stdin {
+        return run_serve(&options);
+    }
+
+    if options.disjoint_files {
+        return run_disjoint(&options);
+    }
+    if options.command == Command::Estimate {
+        return run_estimate(&options);
+    }
+
+    let file = source::TxSource::for_run(&options.input, &options)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(true)
+        .flexible(true)
+        .buffer_capacity(options.read_buffer.unwrap_or(chunked::DEFAULT_READ_BUFFER))
+        .from_reader(file);
+
+    match options.command {
+        Command::Screen => return run_screen(&options, &mut reader),
+        Command::Activity => return run_activity(&options, &mut reader),
+        Command::Anonymize => return run_anonymize(&options, &mut reader),
+        Command::Shrink => return run_shrink(&options, &mut reader),
+        Command::Split => return run_split(&options, &mut reader),
+        Command::Profile => return run_profile(&options, &mut reader),
+        Command::Explain => return run_explain(&options, &mut reader),
+        Command::Statements => return run_statements(&options, &mut reader),
+        Command::Audit => return run_audit(&options, &mut reader),
+        _ => {}
+    }
+    if options.threads.is_some() {
+        return run_sharded(&options, &mut reader);
+    }
+    run_process(&options, &mut reader, started, started_at)
+}
+
+fn run_estimate(options: &Options) -> Result<(), Box<dyn Error>> {
+    let sample = estimate::Sample::read(
+        &options.input,
+        estimate::SAMPLE_PLACES,
+        estimate::SAMPLE_BYTES,
+    )?;
+    estimate::Estimate::of(&sample, options.schema)?.write(std::io::stdout())
+}
+
+// Reads the rows into the pipeline, returning the parse errors and whether the input stopped
+// being readable partway.
+fn read_rows<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+    rows: &mut schema::RowReader,
+    engine: &mut engine::Engine,
+    pipeline: &mut pipeline::Pipeline,
+) -> Result<(u64, bool), Box<dyn Error>> {
+    let mut parse_errors = 0;
+    let mut unreadable = false;
+    match options.parse_threads {
+        Some(threads) => {
+            // Picks up right after the header the reader already went through.
+            let position = reader.position().clone();
+            let mut input = File::open(&options.input)?;
+            input.seek(SeekFrom::Start(position.byte()))?;
+            let tuning = chunked::Tuning::of(options, threads);
+            let mut chunks = chunked::ChunkedRows::new(input, position, rows.clone(), tuning)?;
+            'chunks: while let Some(chunk) = chunks.next() {
+                for row in &chunk?.rows {
+                    match row {
+                        Ok((tx, row)) => pipeline.feed(engine, tx.as_record(), row)?,
+                        Err(err) => {
+                            Warning::new(Kind::ParseError, text(Msg::ParseError, &[err])).emit();
+                            parse_errors += 1;
+                            if options.strict {
+                                break 'chunks;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        // read() and parse() rather than next(), so the raw row stays available for the retry file.
+        None => loop {
+            let row = match rows.read(reader) {
+                Ok(true) => rows.parse(),
+                Ok(false) => break,
+                Err(err) => Err(err),
+            };
+            match row {
+                Ok(tx) => pipeline.feed(engine, tx, rows.record())?,
+                Err(err) => {
+                    Warning::new(Kind::ParseError, text(Msg::ParseError, &[&err]))
+                        .line(rows.line())
+                        .emit();
+                    parse_errors += 1;
+                    unreadable = schema::unreadable(&*err);
+                    if options.strict || unreadable {
+                        break;
+                    }
+                }
+            }
+        },
+    }
+    Ok((parse_errors, unreadable))
+}
+
+// process, bootstrap, merge-clients and split-client on one engine: the rows go through the
+// pipeline, then the balances and everything the flags ask for are written.
+fn run_process<R: std::io::Read>(
+    options: &Options,
+    reader: &mut csv::Reader<R>,
+    started: std::time::Instant,
+    started_at: Timestamp,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(fd) = options.warnings_fd {
+        warnings::open_fd(fd)?;
+    }
+    if let Some(path) = &options.warnings_out {
+        warnings::open_file(path)?;
+    }
+
+    let mut engine = engine_builder(options)?.build();
+    if let Some(path) = &options.snapshot_in {
+        engine.restore(snapshot::Snapshot::read(path)?);
+    }
+    if let Some(path) = &options.balances {
+        bootstrap::seed(engine.state_mut(), File::open(path)?)?;
+    }
+
+    let merges = match &options.map {
+        Some(path) => merge::read_map(File::open(path)?)?,
+        None => vec![],
+    };
+
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => Schema::detect(reader.headers()?),
+    };
+    let mut rows = fixed_rows(options, schema.reader(reader.headers()?)?)?;
+    let mut pipeline = pipeline::Pipeline::new(options, &engine, reader.headers()?, &rows)?;
+    let (parse_errors, unreadable) =
+        read_rows(options, reader, &mut rows, &mut engine, &mut pipeline)?;
+    pipeline.drain(&mut engine)?;
+    pipeline.counts.parse_errors = parse_errors;
+    eprintln!(
+        "{}",
+        text(
+            Msg::RowsRead,
+            &[
+                &(pipeline.counts.rows + parse_errors),
+                &pipeline.counts.applied,
+                &pipeline.counts.rejected,
+                &parse_errors
+            ]
+        )
+    );
+    report_fixups(rows.fixups());
+    if options.stats {
+        let summary = metrics::Summary {
+            parse_errors,
+            ..engine.metrics().summary()
+        };
+        eprintln!("{}", serde_json::to_string(&summary)?);
+    }
+    engine.history_error()?;
+    // Everything the run keeps is written from here on, so a rejected file leaves no trace but
+    // its rejections.
+    let invalid = pipeline.invalid;
+    if (options.strict && parse_errors > 0)
+        || (options.atomic_per_file && parse_errors + invalid > 0)
+        || unreadable
+    {
+        if let Some(errors) = pipeline.errors {
+            errors.finish()?;
+        }
+        // Balances of half the input would pass for the whole of it.
+        if unreadable {
+            return Err(BasicError::new(text(
+                Msg::InputUnreadable,
+                &[&options.input],
+            )));
+        }
+        if options.strict && parse_errors > 0 {
+            return Err(BasicError::new(text(
+                Msg::StoppedAtParseError,
+                &[&options.input],
+            )));
+        }
+        return Err(BasicError::new(text(
+            Msg::FileRejected,
+            &[&options.input, &(parse_errors + invalid)],
+        )));
+    }
+    let penalties = engine.penalties_assessed();
+    engine.finish();
+    if let Some(conservation) = &mut pipeline.conservation {
+        let charged = engine.penalties_assessed() - penalties;
+        conservation.check(engine.state(), None, None, charged)?;
+        eprintln!(
+            "{}",
+            text(
+                Msg::ConservationHeld,
+                &[&format!("{:.4}", conservation.world())]
+            )
+        );
+    }
+    // Before any merge, which moves the disputed transactions away from the clients they were recorded under.
+    if let (Some(path), Some(graph)) = (&options.graph_out, &pipeline.graph) {
+        let writer = std::io::BufWriter::new(File::create(path)?);
+        if path.ends_with(".json") {
+            graph.write_json(engine.state(), writer)?;
+        } else {
+            graph.write_dot(engine.state(), writer)?;
+        }
+    }
+    if let Some(split) = &pipeline.split {
+        let missing = split.missing();
+        if !missing.is_empty() {
+            eprintln!(
+                "{}",
+                text(Msg::SplitMissing, &[&missing.len(), &missing[0].0])
+            );
+        }
+    }
+    if !merges.is_empty() || options.snapshot_out.is_some() {
+        engine.load_history()?;
+    }
+    merge::merge(engine.state_mut(), &merges)?;
+    write_outputs(options, &engine, pipeline, started, started_at)
+}
+
+// The balances and every file, report and check of a run that got through its input.
+fn write_outputs(
+    options: &Options,
+    engine: &engine::Engine,
+    mut pipeline: pipeline::Pipeline,
+    started: std::time::Instant,
+    started_at: Timestamp,
+) -> Result<(), Box<dyn Error>> {
+    let report = engine.report();
+    summarize(engine, &report);
+    if let Some(cdc) = pipeline.cdc.take() {
+        cdc.finish()?;
+    }
+    if let Some(sampler) = pipeline.sampler.take() {
+        sampler.finish()?;
+    }
+    if let (Some(emitter), Some(dir)) = (&pipeline.emitter, &options.emit_dir) {
+        eprintln!("{}", text(Msg::InterimReports, &[&emitter.written(), dir]));
+    }
+    if let Some(retry) = pipeline.retry.take() {
+        eprintln!("{}", text(Msg::RetryWritten, &[&retry.len()]));
+        retry.finish()?;
+    }
+    if let Some(errors) = pipeline.errors.take() {
+        errors.finish()?;
+    }
+
+    // Only what's printed is filtered, reconciliation, assertions and publishing see every client.
+    let balances = print_balances(options, &report, |client| {
+        filter::keeps(&options.output_filter, engine.state(), client)
+    })?;
+
+    let anomalies = pipeline.anomalies.take();
+    let assertions = std::mem::take(&mut pipeline.assertions);
+    let mut counts = std::mem::take(&mut pipeline.counts);
+    pipeline.write_reports(engine)?;
+    if options.mem_stats {
+        for line in memory::report(engine.state()) {
+            eprintln!("Memory {}", line);
+        }
+        if let Some((txs, bytes)) = engine.spilled() {
+            eprintln!(
+                "Memory spilled history: {} transactions, {} bytes on disk",
+                txs, bytes
+            );
+        }
+    }
+    if let (Some(path), Some(anomalies)) = (&options.anomalies, &anomalies) {
+        anomalies.write(File::create(path)?)?;
+        eprintln!("{}", text(Msg::AnomaliesFlagged, &[&anomalies.len()]));
+    }
+
+    if let Some(url) = &options.redis {
+        redis::publish(url, &report.clients)?;
+    }
+    if let Some(path) = &options.upsert {
+        let run_date = &started_at.to_string()[..10];
+        upsert::upsert(path, &report, run_date, &manifest::run_id(options)?)?;
+    }
+    if let Some(path) = &options.snapshot_out {
+        engine.snapshot().write(path)?;
+    }
+
+    let breaks = match &options.closing_balances {
+        Some(path) => {
+            let expected = bootstrap::read_balances(File::open(path)?)?;
+            let tolerance = match options.tolerance {
+                Some(tolerance) => tolerance,
+                None => parse_amount(reconcile::DEFAULT_TOLERANCE)?,
+            };
+            Some(reconcile::reconcile(engine.state(), &expected, tolerance))
+        }
+        None => None,
+    };
+
+    let failed: Vec<String> = assertions
+        .iter()
+        .filter_map(|assertion| assertion.check(engine.state()))
+        .collect();
+    for failure in &failed {
+        eprintln!("{}", text(Msg::Assertion, &[failure]));
+    }
+
+    // Written even when the balances don't reconcile, that run is the one worth a record.
+    if let Some(path) = &options.manifest {
+        counts.clients = engine.state().clients.len() as u64;
+        manifest::Manifest::new(
+            options,
+            &counts,
+            started_at,
+            started.elapsed(),
+            &balances,
+            breaks.as_ref().map(|breaks| breaks.len() as u64),
+        )?
+        .write(std::io::BufWriter::new(File::create(path)?))?;
+    }
+
+    if let Some(breaks) = breaks.filter(|breaks| !breaks.is_empty()) {
+        reconcile::write_report(&breaks, std::io::stderr())?;
+        return Err(BasicError::new(text(Msg::BreaksFound, &[&breaks.len()])));
+    }
+    if !failed.is_empty() {
+        return Err(BasicError::new(text(
+            Msg::AssertionsFailed,
+            &[&failed.len(), &assertions.len()],
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NOTE: Could do more tests for scenarios including more users, and for more complicated
+    // transaction chains but this should be good enough to show a pattern
+
+    #[test]
+    fn basic_deposit() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        assert_eq!(app_state.clients.len(), 1);
+        assert_eq!(
+            app_state
+                .clients
+                .entry(ClientId(1))
+                .or_default()
+                .available(),
+            Currency::from_num(1.0)
+        );
+    }
+
+    #[test]
+    fn basic_deposit_multi_user() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 2, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        assert_eq!(app_state.clients.len(), 2);
+        assert_eq!(
+            app_state
+                .clients
+                .entry(ClientId(1))
+                .or_default()
+                .available(),
+            Currency::from_num(1.0)
+        );
+        assert_eq!(
+            app_state
+                .clients
+                .entry(ClientId(2))
+                .or_default()
+                .available(),
+            Currency::from_num(1.0)
+        );
+    }
+
+    #[test]
+    fn basic_withdrawal() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Withdrawal, 1, 2, Currency::from_num(0.5)),
+        )
+        .unwrap();
+        assert_eq!(app_state.clients.len(), 1);
+        assert_eq!(
+            app_state
+                .clients
+                .entry(ClientId(1))
+                .or_default()
+                .available(),
+            Currency::from_num(0.5)
+        );
+    }
+
+    #[test]
+    fn dispute_happy_path() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        assert_eq!(app_state.clients.len(), 1);
+        let client_state = app_state.clients.entry(ClientId(1)).or_default();
+        assert_eq!(client_state.available(), Currency::from_num(0.0));
+        assert_eq!(client_state.held(), Currency::from_num(1.0));
+        assert!(!client_state.locked);
+    }
+
+    #[test]
+    fn dispute_policies() {
+        // A deposit of 10 with 6 of it withdrawn, then disputed.
+        let disputed = |dispute_policy| {
+            let mut app_state = AppState {
+                dispute_policy,
+                ..Default::default()
+            };
+            for tx in [
+                TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(10)),
+                TxRecord::new(TxType::Withdrawal, 1, 2, Currency::from_num(6)),
+            ] {
+                execute_transaction(&mut app_state, tx).unwrap();
+            }
+            let dispute = TxRecord::new(TxType::Dispute, 1, 1, Currency::default());
+            let result = execute_transaction(&mut app_state, dispute);
+            (result, app_state)
+        };
+        let balances = |app_state: &AppState| {
+            let client = &app_state.clients[&ClientId(1)];
+            (client.available(), client.held(), client.shortfall(TxId(1)))
+        };
+
+        let (result, app_state) = disputed(DisputePolicy::AllowNegative);
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            balances(&app_state),
+            (
+                Currency::from_num(-6),
+                Currency::from_num(10),
+                Currency::ZERO
+            )
+        );
+
+        let (result, app_state) = disputed(DisputePolicy::Reject);
+        assert_eq!(result, Err(RejectionReason::InsufficientFunds));
+        assert_eq!(
+            balances(&app_state),
+            (Currency::from_num(4), Currency::ZERO, Currency::ZERO)
+        );
+        assert_eq!(
+            app_state.clients[&ClientId(1)].dispute_state(TxId(1)),
+            DisputeState::Settled
+        );
+
+        let (result, mut app_state) = disputed(DisputePolicy::PartialHold);
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            balances(&app_state),
+            (Currency::ZERO, Currency::from_num(4), Currency::from_num(6))
+        );
+        let mut resolved = app_state.clone();
+        execute_transaction(
+            &mut resolved,
+            TxRecord::new(TxType::Resolve, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        assert_eq!(
+            balances(&resolved),
+            (Currency::from_num(4), Currency::ZERO, Currency::ZERO)
+        );
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::ChargeBack, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        assert_eq!(
+            balances(&app_state),
+            (Currency::ZERO, Currency::ZERO, Currency::from_num(6))
+        );
+    }
+
+    #[test]
+    fn chargeback_fees() {
+        let charged_back = |payer| {
+            let mut app_state = AppState {
+                chargeback_fee: Some(ChargebackFee {
+                    amount: Currency::from_num(2),
+                    payer,
+                }),
+                ..Default::default()
+            };
+            for tx in [
+                TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(10)),
+                TxRecord::new(TxType::Deposit, 1, 2, Currency::from_num(1)),
+                TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+                TxRecord::new(TxType::ChargeBack, 1, 1, Currency::default()),
+            ] {
+                execute_transaction(&mut app_state, tx).unwrap();
+            }
+            app_state.clients.remove(&ClientId(1)).unwrap()
+        };
+
+        // Taken from available, into debt if need be.
+        let client = charged_back(FeePayer::Client);
+        assert_eq!(client.available(), Currency::from_num(-1));
+        assert_eq!(client.chargeback_fees(), Currency::from_num(2));
+        assert!(client.locked);
+
+        // Left to the counterparty, see settlement::Settles.
+        let client = charged_back(FeePayer::Counterparty);
+        assert_eq!(client.available(), Currency::from_num(1));
+        assert_eq!(client.chargeback_fees(), Currency::ZERO);
+    }
+
+    #[test]
+    fn dispute_txid_doesnt_exist() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 0, Currency::default()),
+        )
+        .unwrap_err();
+        assert_eq!(app_state.clients.len(), 1);
+        let client_state = app_state.clients.entry(ClientId(1)).or_default();
+        assert_eq!(client_state.available(), Currency::from_num(1.0));
+        assert_eq!(client_state.held(), Currency::from_num(0.0));
+        assert!(!client_state.locked);
+    }
+
+    #[test]
+    fn resolve_happy_path() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Resolve, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        assert_eq!(app_state.clients.len(), 1);
+        let client_state = app_state.clients.entry(ClientId(1)).or_default();
+        assert_eq!(client_state.available(), Currency::from_num(1.0));
+        assert_eq!(client_state.held(), Currency::from_num(0.0));
+        assert!(!client_state.locked);
+    }
+
+    #[test]
+    fn resolve_txid_doesnt_exist() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Resolve, 1, 0, Currency::default()),
+        )
+        .unwrap_err();
+        assert_eq!(app_state.clients.len(), 1);
+        let client_state = app_state.clients.entry(ClientId(1)).or_default();
+        assert_eq!(client_state.available(), Currency::from_num(0.0));
+        assert_eq!(client_state.held(), Currency::from_num(1.0));
+        assert!(!client_state.locked);
+    }
+
+    #[test]
+    fn chargeback_happy_path() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::ChargeBack, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        assert_eq!(app_state.clients.len(), 1);
+        let client_state = app_state.clients.entry(ClientId(1)).or_default();
+        assert_eq!(client_state.available(), Currency::from_num(0.0));
+        assert_eq!(client_state.held(), Currency::from_num(0.0));
+        assert!(client_state.locked);
+    }
+
+    #[test]
+    fn withdrawal_disputes() {
+        let balances = |app_state: &AppState| {
+            let client = &app_state.clients[&ClientId(1)];
+            (client.available(), client.held(), client.locked)
+        };
+        let currency = |amount: f64| Currency::from_num(amount);
+        let mut app_state = AppState::default();
+        for tx in [
+            TxRecord::new(TxType::Deposit, 1, 1, currency(10.0)),
+            TxRecord::new(TxType::Withdrawal, 1, 2, currency(4.0)),
+            TxRecord::new(TxType::Dispute, 1, 2, Currency::default()),
+        ] {
+            execute_transaction(&mut app_state, tx).unwrap();
+        }
+        // Held for the client, available doesn't move.
+        assert_eq!(balances(&app_state), (currency(6.0), currency(4.0), false));
+        let mut resolved = app_state.clone();
+        execute_transaction(
+            &mut resolved,
+            TxRecord::new(TxType::Resolve, 1, 2, Currency::default()),
+        )
+        .unwrap();
+        // The withdrawal stands.
+        assert_eq!(balances(&resolved), (currency(6.0), currency(0.0), false));
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::ChargeBack, 1, 2, Currency::default()),
+        )
+        .unwrap();
+        // The withdrawal is reversed, its funds back with the client.
+        assert_eq!(balances(&app_state), (currency(10.0), currency(0.0), true));
+    }
+
+    #[test]
+    fn atomic_file_rejected_whole() {
+        let dir = std::env::temp_dir().join(format!("txcli-atomic-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        // The withdrawal is only short of funds, the negative deposit is what fails the file.
+        std::fs::write(
+            path("in.csv"),
+            "type,client,tx,amount\ndeposit,1,1,5.0\nwithdrawal,1,2,9.0\ndeposit,2,3,-1.0\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            ["txcli", &path("in.csv"), "--errors", &path("errors.csv")]
+                .iter()
+                .chain(extra)
+                .map(|arg| arg.to_string())
+                .collect()
+        };
+        let err = run(args(&[
+            "--atomic-per-file",
+            "--snapshot-out",
+            &path("state.json"),
+        ]))
+        .unwrap_err();
+        assert!(err.to_string().contains("has 1 invalid rows"));
+        assert!(!dir.join("state.json").exists());
+        assert_eq!(
+            std::fs::read_to_string(path("errors.csv")).unwrap(),
+            "line,client,tx,type,reason\n3,1,2,withdrawal,insufficient_funds\n4,2,3,deposit,invalid_amount\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_rows_skipped() {
+        let dir = std::env::temp_dir().join(format!("txcli-malformed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        std::fs::write(
+            path("in.csv"),
+            "type,client,tx,amount\ndeposit,1,1,5.0\nrefund,1,2,1.0\ndeposit,1\ndeposit,2,3,1.0\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            ["txcli", &path("in.csv"), "--manifest", &path("run.json")]
+                .iter()
+                .chain(extra)
+                .map(|arg| arg.to_string())
+                .collect()
+        };
+        run(args(&[])).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path("run.json")).unwrap()).unwrap();
+        let counts = &manifest["counts"];
+        assert_eq!(
+            (&counts["applied"], &counts["parse_errors"]),
+            (&2.into(), &2.into())
+        );
+        std::fs::remove_file(path("run.json")).unwrap();
+        let err = run(args(&["--strict"])).unwrap_err();
+        assert!(err.to_string().contains("--strict"));
+        assert!(!dir.join("run.json").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn chargeback_txid_doesnt_exist() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::ChargeBack, 1, 0, Currency::default()),
+        )
+        .unwrap_err();
+        assert_eq!(app_state.clients.len(), 1);
+        let client_state = app_state.clients.entry(ClientId(1)).or_default();
+        assert_eq!(client_state.available(), Currency::from_num(0.0));
+        assert_eq!(client_state.held(), Currency::from_num(1.0));
+        assert!(!client_state.locked);
+    }
+
+    #[test]
+    fn void_reverses_and_bars_disputes() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(5.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Withdrawal, 1, 2, Currency::from_num(2.0)),
+        )
+        .unwrap();
+        assert!(execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Void, 1, 2, Currency::default()),
+        )
+        .is_ok());
+        assert_eq!(
+            app_state.clients[&ClientId(1)].available(),
+            Currency::from_num(5.0)
+        );
+        assert!(execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Void, 1, 1, Currency::default()),
+        )
+        .is_ok());
+        assert!(execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .is_err());
+        assert!(execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Void, 1, 1, Currency::default()),
+        )
+        .is_err());
+        let client_state = &app_state.clients[&ClientId(1)];
+        assert_eq!(client_state.available(), Currency::from_num(0.0));
+        assert_eq!(client_state.held(), Currency::from_num(0.0));
+    }
+
+    #[test]
+    fn void_after_dispute() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(5.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        assert!(execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Void, 1, 1, Currency::default()),
+        )
+        .is_err());
+        // Still barred once the dispute is resolved.
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Resolve, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        assert!(execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Void, 1, 1, Currency::default()),
+        )
+        .is_err());
+        assert!(execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Void, 1, 9, Currency::default()),
+        )
+        .is_err());
+        assert_eq!(
+            app_state.clients[&ClientId(1)].available(),
+            Currency::from_num(5.0)
+        );
+    }
+
+    #[test]
+    fn deposit_into_named_accounts() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 2, Currency::from_num(2.0)).with_account("savings"),
+        )
+        .unwrap();
+        let client_state = &app_state.clients[&ClientId(1)];
+        assert!(client_state.has_named_accounts());
+        assert_eq!(client_state.available(), Currency::from_num(3.0));
+        let rows = client_state.split_output(ClientId(1), false, true);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].account, Some(AccountId::default()));
+        assert_eq!(rows[0].available, Currency::from_num(1.0));
+        assert_eq!(rows[1].account, Some(AccountId("savings".to_string())));
+        assert_eq!(rows[1].available, Currency::from_num(2.0));
+    }
+
+    #[test]
+    fn transfer_between_accounts() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(5.0)).with_account("checking"),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Transfer, 1, 2, Currency::from_num(2.0))
+                .with_account("checking")
+                .with_to_account("savings"),
+        )
+        .unwrap();
+        let accounts = &app_state.clients[&ClientId(1)].accounts;
+        assert_eq!(
+            accounts[&AccountId("checking".to_string())].available,
+            Currency::from_num(3.0)
+        );
+        assert_eq!(
+            accounts[&AccountId("savings".to_string())].available,
+            Currency::from_num(2.0)
+        );
+    }
+
+    #[test]
+    fn transfer_insufficient_funds_or_missing_target() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        assert_eq!(
+            execute_transaction(
+                &mut app_state,
+                TxRecord::new(TxType::Transfer, 1, 2, Currency::from_num(2.0))
+                    .with_to_account("savings"),
+            ),
+            Err(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(
+            execute_transaction(
+                &mut app_state,
+                TxRecord::new(TxType::Transfer, 1, 3, Currency::from_num(1.0)),
+            ),
+            Err(RejectionReason::InvalidTransfer)
+        );
+        let client_state = &app_state.clients[&ClientId(1)];
+        assert_eq!(
+            client_state.accounts[&AccountId::default()].available,
+            Currency::from_num(1.0)
+        );
+        assert_eq!(client_state.available(), Currency::from_num(1.0));
+    }
+
+    #[test]
+    fn dispute_holds_funds_in_original_account() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)).with_account("savings"),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        let savings = app_state.clients[&ClientId(1)].accounts[&AccountId("savings".to_string())];
+        assert_eq!(savings.available, Currency::from_num(0.0));
+        assert_eq!(savings.held, Currency::from_num(1.0));
+    }
+
+    #[test]
+    fn transfers_cannot_be_disputed() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Transfer, 1, 2, Currency::from_num(1.0))
+                .with_to_account("savings"),
+        )
+        .unwrap();
+        assert_eq!(
+            execute_transaction(
+                &mut app_state,
+                TxRecord::new(TxType::Dispute, 1, 2, Currency::default()),
+            ),
+            Err(RejectionReason::InternalTransfer)
+        );
+        let client_state = &app_state.clients[&ClientId(1)];
+        assert_eq!(client_state.available(), Currency::from_num(1.0));
+        assert_eq!(client_state.held(), Currency::from_num(0.0));
+    }
+
+    #[test]
+    fn joint_owners_share_balance() {
+        let mut app_state = AppState::default();
+        app_state.joint_owners.insert(ClientId(2), ClientId(1));
+        app_state.joint_owners.insert(ClientId(3), ClientId(1));
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(2.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 2, 2, Currency::from_num(1.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Withdrawal, 3, 3, Currency::from_num(2.5)),
+        )
+        .unwrap();
+        // Any owner can dispute a transaction made by another owner of the same account.
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 3, 2, Currency::default()),
+        )
+        .unwrap();
+        assert_eq!(app_state.clients.len(), 1);
+        let client_state = &app_state.clients[&ClientId(1)];
+        assert_eq!(client_state.available(), Currency::from_num(-0.5));
+        assert_eq!(client_state.held(), Currency::from_num(1.0));
+        assert_eq!(app_state.owners(ClientId(1)), "1;2;3");
+        assert_eq!(app_state.owners(ClientId(4)), "4");
+    }
+
+    #[test]
+    fn embedding_api() {
+        let mut engine = EngineBuilder::new()
+            .joint_owners(HashMap::from([(ClientId(2), ClientId(1))]))
+            .build();
+        let deposit = Tx::new(TxType::Deposit, 1, 1, Currency::from_num(3.0));
+        assert!(engine.apply(deposit.as_record()));
+        assert!(!engine.apply(TxRecord::new(
+            TxType::Withdrawal,
+            2,
+            2,
+            Currency::from_num(4.0)
+        )));
+        let balance = engine.balance(ClientId(2)).unwrap();
+        assert_eq!(
+            (balance.cid, balance.available),
+            (ClientId(1), Currency::from_num(3.0))
+        );
+        assert_eq!(engine.balance(ClientId(3)), None);
+        assert_eq!(engine.report().stats.applied, 1);
+    }
+}
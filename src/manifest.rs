@@ -0,0 +1,368 @@
+use crate::options::{Command, Options};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::time::Duration;
+use txcli_model::{Timestamp, TxType};
+
+// Bumped on any incompatible change to the layout, see schema/manifest.schema.json.
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FileEntry {
+    role: &'static str,
+    // "-" for stdout.
+    path: String,
+    bytes: u64,
+    sha256: String,
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl FileEntry {
+    pub fn of_file(role: &'static str, path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut hasher = Sha256::new();
+        let bytes = std::io::copy(&mut File::open(path)?, &mut hasher)?;
+        Ok(FileEntry {
+            role,
+            path: path.to_string(),
+            bytes,
+            sha256: hex(&hasher.finalize()),
+        })
+    }
+
+    pub fn of_bytes(role: &'static str, path: &str, content: &[u8]) -> Self {
+        FileEntry {
+            role,
+            path: path.to_string(),
+            bytes: content.len() as u64,
+            sha256: hex(&Sha256::digest(content)),
+        }
+    }
+}
+
+#[derive(Serialize, Default, Debug, PartialEq, Clone, Copy)]
+struct TypeCounts {
+    applied: u64,
+    rejected: u64,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct Counts {
+    pub rows: u64,
+    pub applied: u64,
+    pub rejected: u64,
+    // Rows skipped for failing to parse, processing stops at the first one with --strict.
+    pub parse_errors: u64,
+    pub clients: u64,
+    by_type: BTreeMap<String, TypeCounts>,
+}
+
+impl Counts {
+    pub fn record(&mut self, tx_type: TxType, applied: bool) {
+        self.rows += 1;
+        let by_type = self.by_type.entry(tx_type.to_string()).or_default();
+        if applied {
+            self.applied += 1;
+            by_type.applied += 1;
+        } else {
+            self.rejected += 1;
+            by_type.rejected += 1;
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Manifest<'a> {
+    manifest_version: u32,
+    txcli_version: &'static str,
+    command: Command,
+    started_at: Timestamp,
+    duration_seconds: f64,
+    run_id: String,
+    inputs: Vec<FileEntry>,
+    config: &'a Options,
+    counts: &'a Counts,
+    outputs: Vec<FileEntry>,
+    // Only present with --closing-balances.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reconcile_breaks: Option<u64>,
+}
+
+// Every file the options make the run read, in a fixed order.
+fn input_paths(options: &Options) -> Vec<(&'static str, &str)> {
+    [
+        ("input", Some(&options.input)),
+        ("balances", options.balances.as_ref()),
+        ("map", options.map.as_ref()),
+        ("split_txs", options.split_txs.as_ref()),
+        ("clients", options.clients.as_ref()),
+        ("assets", options.assets.as_ref()),
+        ("tx_types", options.tx_types.as_ref()),
+        ("categorize", options.categorize.as_ref()),
+        ("fixups", options.fixups.as_ref()),
+        ("closing_balances", options.closing_balances.as_ref()),
+        ("disputes_file", options.disputes_file.as_ref()),
+        ("authorizations", options.authorizations.as_ref()),
+        ("assertions", options.assertions.as_ref()),
+        ("policy", options.policy.as_ref()),
+        ("snapshot_in", options.snapshot_in.as_ref()),
+    ]
+    .into_iter()
+    .filter_map(|(role, path)| path.map(|path| (role, path.as_str())))
+    .collect()
+}
+
+// Every file the options make the run write, besides the balances.
+fn output_paths(options: &Options) -> Vec<(&'static str, &str)> {
+    [
+        ("cdc", options.cdc_out.as_ref()),
+        ("sample", options.sample_out.as_ref()),
+        ("category_report", options.category_report.as_ref()),
+        ("anomalies", options.anomalies.as_ref()),
+        ("graph", options.graph_out.as_ref()),
+        ("retry", options.retry_out.as_ref()),
+        ("errors", options.errors.as_ref()),
+        ("snapshot_out", options.snapshot_out.as_ref()),
+        ("settlement_report", options.settlement_report.as_ref()),
+        ("rollforward", options.rollforward_out.as_ref()),
+        ("source_report", options.report_by_source.as_ref()),
+        ("precision_report", options.precision_report.as_ref()),
+        ("warnings", options.warnings_out.as_ref()),
+        ("upsert", options.upsert.as_ref()),
+    ]
+    .into_iter()
+    .filter_map(|(role, path)| path.map(|path| (role, path.as_str())))
+    .collect()
+}
+
+// Identifies a run by what it read and how it was configured, so rerunning the same job gives
+// the same id whenever it runs. The first 16 hex digits of a hash over the input hashes and the
+// options.
+fn run_id_of(inputs: &[FileEntry], options: &Options) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    for input in inputs {
+        hasher.update(format!("{} {}\n", input.role, input.sha256));
+    }
+    hasher.update(serde_json::to_vec(options)?);
+    Ok(hex(&hasher.finalize())[..16].to_string())
+}
+
+fn inputs(options: &Options) -> Result<Vec<FileEntry>, Box<dyn Error>> {
+    input_paths(options)
+        .into_iter()
+        .map(|(role, path)| FileEntry::of_file(role, path))
+        .collect()
+}
+
+// The run_id the manifest of this run carries.
+pub fn run_id(options: &Options) -> Result<String, Box<dyn Error>> {
+    run_id_of(&inputs(options)?, options)
+}
+
+impl<'a> Manifest<'a> {
+    // Hashes the inputs and outputs as they are on disk now, so this is built once the run is done.
+    pub fn new(
+        options: &'a Options,
+        counts: &'a Counts,
+        started_at: Timestamp,
+        duration: Duration,
+        balances: &[u8],
+        reconcile_breaks: Option<u64>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let inputs = inputs(options)?;
+        let path = options.output.as_deref().unwrap_or("-");
+        let outputs = std::iter::once(Ok(FileEntry::of_bytes("balances", path, balances)))
+            .chain(
+                output_paths(options)
+                    .into_iter()
+                    .map(|(role, path)| FileEntry::of_file(role, path)),
+            )
+            .collect::<Result<_, _>>()?;
+        Ok(Manifest {
+            manifest_version: MANIFEST_VERSION,
+            txcli_version: env!("CARGO_PKG_VERSION"),
+            command: options.command,
+            started_at,
+            duration_seconds: duration.as_secs_f64(),
+            run_id: run_id_of(&inputs, options)?,
+            inputs,
+            config: options,
+            counts,
+            outputs,
+            reconcile_breaks,
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn hashes_and_counts() {
+        assert_eq!(
+            FileEntry::of_bytes("balances", "-", b"abc").sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let mut counts = Counts::default();
+        counts.record(TxType::Deposit, true);
+        counts.record(TxType::Withdrawal, false);
+        counts.record(TxType::Deposit, true);
+        assert_eq!((counts.rows, counts.applied, counts.rejected), (3, 2, 1));
+        assert_eq!(
+            counts.by_type["deposit"],
+            TypeCounts {
+                applied: 2,
+                rejected: 0
+            }
+        );
+    }
+
+    // Just enough of JSON Schema for the keywords the manifest schema uses.
+    fn validate(value: &Value, schema: &Value, root: &Value, path: &str) {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let target = root.pointer(reference.trim_start_matches('#')).unwrap();
+            return validate(value, target, root, path);
+        }
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(types) => types.iter().map(|t| t.as_str().unwrap()).collect(),
+            _ => vec![],
+        };
+        let matches = |t: &str| match t {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_u64() || value.is_i64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => panic!("unsupported type {}", t),
+        };
+        assert!(
+            types.is_empty() || types.iter().any(|t| matches(t)),
+            "{} is not {:?}",
+            path,
+            types
+        );
+        if let Some(required) = schema["required"].as_array() {
+            for key in required {
+                assert!(
+                    value.get(key.as_str().unwrap()).is_some(),
+                    "{} is missing {}",
+                    path,
+                    key
+                );
+            }
+        }
+        if let (Some(object), Some(properties)) =
+            (value.as_object(), schema["properties"].as_object())
+        {
+            for (key, field) in object {
+                match properties.get(key) {
+                    Some(property) => validate(field, property, root, &format!("{}.{}", path, key)),
+                    None => match &schema["additionalProperties"] {
+                        Value::Bool(false) => panic!("{} has unexpected {}", path, key),
+                        Value::Object(_) => validate(
+                            field,
+                            &schema["additionalProperties"],
+                            root,
+                            &format!("{}.{}", path, key),
+                        ),
+                        _ => {}
+                    },
+                }
+            }
+        } else if let (Some(object), Value::Object(_)) =
+            (value.as_object(), &schema["additionalProperties"])
+        {
+            for (key, field) in object {
+                validate(
+                    field,
+                    &schema["additionalProperties"],
+                    root,
+                    &format!("{}.{}", path, key),
+                );
+            }
+        }
+        if let (Some(items), Value::Object(_)) = (value.as_array(), &schema["items"]) {
+            for (index, item) in items.iter().enumerate() {
+                validate(
+                    item,
+                    &schema["items"],
+                    root,
+                    &format!("{}[{}]", path, index),
+                );
+            }
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            assert!(
+                values.contains(value),
+                "{} is not one of {:?}",
+                path,
+                values
+            );
+        }
+    }
+
+    #[test]
+    fn conforms_to_schema() {
+        let schema: Value =
+            serde_json::from_str(include_str!("../schema/manifest.schema.json")).unwrap();
+        let dir = std::env::temp_dir().join(format!("txcli-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        std::fs::write(&input, "type,client,tx,amount\ndeposit,1,1,1.0\n").unwrap();
+        let graph = dir.join("disputes.dot");
+        std::fs::write(&graph, "digraph disputes {\n}\n").unwrap();
+
+        let options = Options {
+            input: input.to_string_lossy().into_owned(),
+            graph_out: Some(graph.to_string_lossy().into_owned()),
+            ..Options::default()
+        };
+        let mut counts = Counts::default();
+        counts.record(TxType::Deposit, true);
+        counts.clients = 1;
+        for breaks in [None, Some(2)] {
+            let manifest = Manifest::new(
+                &options,
+                &counts,
+                Timestamp(1660052700),
+                Duration::from_millis(1500),
+                b"client,available,held,total,locked\n",
+                breaks,
+            )
+            .unwrap();
+            let mut out = vec![];
+            manifest.write(&mut out).unwrap();
+            let value: Value = serde_json::from_slice(&out).unwrap();
+            validate(&value, &schema, &schema, "manifest");
+            assert_eq!(value["manifest_version"], MANIFEST_VERSION);
+            assert_eq!(value["command"], "process");
+            assert_eq!(value["inputs"][0]["bytes"], 38);
+            assert_eq!(value["outputs"][1]["role"], "graph");
+            assert_eq!(value["counts"]["by_type"]["deposit"]["applied"], 1);
+            assert_eq!(value["run_id"], run_id(&options).unwrap());
+        }
+        // Same job, same id. Another input, another id.
+        let id = run_id(&options).unwrap();
+        assert_eq!(run_id(&options).unwrap(), id);
+        std::fs::write(&input, "type,client,tx,amount\ndeposit,1,1,2.0\n").unwrap();
+        assert_ne!(run_id(&options).unwrap(), id);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{AppState, Balance};
+use std::collections::HashMap;
+use std::mem::size_of;
+use txcli_model::{AccountId, Tx, TxId};
+
+#[cfg(feature = "alloc-stats")]
+pub use counting::ALLOCATOR;
+
+// Global allocator wrapper counting live and peak heap bytes, only compiled in with the
+// alloc-stats feature as every allocation pays for a couple of atomic operations.
+#[cfg(feature = "alloc-stats")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    pub struct CountingAllocator {
+        live: AtomicU64,
+        peak: AtomicU64,
+        allocations: AtomicU64,
+    }
+
+    #[global_allocator]
+    pub static ALLOCATOR: CountingAllocator = CountingAllocator {
+        live: AtomicU64::new(0),
+        peak: AtomicU64::new(0),
+        allocations: AtomicU64::new(0),
+    };
+
+    impl CountingAllocator {
+        pub fn live(&self) -> u64 {
+            self.live.load(Ordering::Relaxed)
+        }
+
+        pub fn peak(&self) -> u64 {
+            self.peak.load(Ordering::Relaxed)
+        }
+
+        pub fn allocations(&self) -> u64 {
+            self.allocations.load(Ordering::Relaxed)
+        }
+
+        fn grow(&self, bytes: u64) {
+            let live = self.live.fetch_add(bytes, Ordering::Relaxed) + bytes;
+            self.peak.fetch_max(live, Ordering::Relaxed);
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                self.allocations.fetch_add(1, Ordering::Relaxed);
+                self.grow(layout.size() as u64);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            self.live.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                self.allocations.fetch_add(1, Ordering::Relaxed);
+                self.live.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+                self.grow(new_size as u64);
+            }
+            new_ptr
+        }
+    }
+}
+
+// Peak resident set size from /proc, None where that isn't available.
+pub fn peak_rss() -> Option<u64> {
+    proc_status_bytes("VmHWM:")
+}
+
+// Current resident set size, what a soak run watches for growth.
+pub fn rss() -> Option<u64> {
+    proc_status_bytes("VmRSS:")
+}
+
+fn proc_status_bytes(field: &str) -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with(field))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
+
+// std's HashMap keeps one control byte per bucket next to the entries.
+fn hash_map_bytes<K, V>(map: &HashMap<K, V>) -> u64 {
+    (map.capacity() * (size_of::<(K, V)>() + 1)) as u64
+}
+
+fn tx_heap_bytes(tx: &Tx) -> u64 {
+    let strings = [
+        &tx.category,
+        &tx.currency,
+        &tx.counterparty,
+        &tx.source,
+        &tx.memo,
+    ];
+    (tx.account.0.capacity()
+        + tx.to_account
+            .as_ref()
+            .map_or(0, |account| account.0.capacity())
+        + strings
+            .iter()
+            .map(|value| value.as_ref().map_or(0, String::capacity))
+            .sum::<usize>()) as u64
+}
+
+// Estimated bytes held by each part of the engine state, from container capacities and string
+// lengths. Allocator overhead and fragmentation are not included, compare with peak RSS.
+#[derive(Default, Debug, PartialEq)]
+pub struct MemoryUsage {
+    pub clients: u64,
+    pub accounts: u64,
+    pub history: u64,
+    pub disputed: u64,
+    pub stored_txs: u64,
+}
+
+impl MemoryUsage {
+    pub fn of(app_state: &AppState) -> Self {
+        let mut usage = MemoryUsage {
+            clients: hash_map_bytes(&app_state.clients) + hash_map_bytes(&app_state.joint_owners),
+            ..Default::default()
+        };
+        for client in app_state.clients.values() {
+            // BTreeMap nodes hold up to 11 entries, assume they are about two thirds full.
+            usage.accounts += client
+                .balances()
+                .map(|(_, account, _)| {
+                    (size_of::<(AccountId, Balance)>() * 3 / 2 + account.0.capacity()) as u64
+                })
+                .sum::<u64>();
+            usage.history += stored_bytes(&client.history);
+            usage.disputed += stored_bytes(&client.disputed);
+            usage.stored_txs += (client.history.len() + client.disputed.len()) as u64;
+        }
+        usage
+    }
+
+    pub fn per_stored_tx(&self) -> Option<u64> {
+        (self.stored_txs > 0).then(|| (self.history + self.disputed) / self.stored_txs)
+    }
+}
+
+fn stored_bytes(txs: &HashMap<TxId, Tx>) -> u64 {
+    hash_map_bytes(txs) + txs.values().map(tx_heap_bytes).sum::<u64>()
+}
+
+pub fn report(app_state: &AppState) -> Vec<String> {
+    let usage = MemoryUsage::of(app_state);
+    let mut lines = vec![
+        format!(
+            "clients: {} bytes ({} clients)",
+            usage.clients,
+            app_state.clients.len()
+        ),
+        format!("accounts: {} bytes", usage.accounts),
+        format!("history: {} bytes", usage.history),
+        format!("disputed: {} bytes", usage.disputed),
+        format!(
+            "stored transactions: {}, {} bytes each",
+            usage.stored_txs,
+            usage.per_stored_tx().unwrap_or(0)
+        ),
+    ];
+    lines.push(match peak_rss() {
+        Some(rss) => format!("peak rss: {} bytes", rss),
+        None => "peak rss: unavailable on this platform".to_string(),
+    });
+    #[cfg(feature = "alloc-stats")]
+    lines.push(format!(
+        "heap: {} bytes live, {} bytes peak, {} allocations",
+        ALLOCATOR.live(),
+        ALLOCATOR.peak(),
+        ALLOCATOR.allocations()
+    ));
+    #[cfg(not(feature = "alloc-stats"))]
+    lines.push("heap: build with --features alloc-stats to count allocations".to_string());
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_transaction;
+    use txcli_model::{Currency, TxRecord, TxType};
+
+    #[test]
+    fn usage_grows_with_history() {
+        let mut app_state = AppState::default();
+        assert_eq!(MemoryUsage::of(&app_state).per_stored_tx(), None);
+        for tid in 0..100 {
+            execute_transaction(
+                &mut app_state,
+                TxRecord::new(TxType::Deposit, 1, tid, Currency::from_num(1)),
+            )
+            .unwrap();
+        }
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 0, Currency::default()),
+        )
+        .unwrap();
+        let usage = MemoryUsage::of(&app_state);
+        assert_eq!(usage.stored_txs, 100);
+        assert!(usage.disputed > 0);
+        // At least the entry itself plus the account name.
+        let per_tx = usage.per_stored_tx().unwrap();
+        assert!(per_tx >= (size_of::<(TxId, Tx)>() + "default".len()) as u64);
+    }
+
+    #[test]
+    fn report_lines() {
+        let lines = report(&AppState::default());
+        assert!(lines[0].starts_with("clients: "));
+        assert!(lines.iter().any(|line| line.starts_with("peak rss: ")));
+        assert!(lines.last().unwrap().starts_with("heap: "));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{AppState, BasicError};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+use txcli_model::ClientId;
+
+// One row per duplicate client id, source is folded into target.
+#[derive(Deserialize, Debug)]
+struct MergeRow {
+    source: ClientId,
+    target: ClientId,
+}
+
+pub fn read_map<R: Read>(input: R) -> Result<Vec<(ClientId, ClientId)>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(true)
+        .from_reader(input);
+
+    let mut merges = HashMap::new();
+    let mut order = vec![];
+    for row in reader.deserialize::<MergeRow>() {
+        let row = row?;
+        if row.source == row.target {
+            return Err(BasicError::new(format!(
+                "Merge map merges client[{}] into itself.",
+                row.source.0
+            )));
+        }
+        if merges.insert(row.source, row.target).is_some() {
+            return Err(BasicError::new(format!(
+                "Merge map lists client[{}] as a source more than once.",
+                row.source.0
+            )));
+        }
+        order.push((row.source, row.target));
+    }
+
+    // Same rule as joint owners, a target that is itself merged away would depend on merge order.
+    for (source, target) in &order {
+        if merges.contains_key(target) {
+            return Err(BasicError::new(format!(
+                "Client[{}] is merged into client[{}], which is itself merged into another client.",
+                source.0, target.0
+            )));
+        }
+    }
+
+    Ok(order)
+}
+
+// Folds each source client's accounts, history and disputes into its target and removes the source.
+// Balances add up per account and the merged client is locked if either side was.
+// Merges where both sides have open disputes are rejected, as resolving them would need both
+// clients' dispute cases to be reconciled by hand first.
+pub fn merge(
+    app_state: &mut AppState,
+    merges: &[(ClientId, ClientId)],
+) -> Result<(), Box<dyn Error>> {
+    for (source, target) in merges {
+        for cid in [source, target] {
+            if app_state.joint_owners.contains_key(cid) {
+                return Err(BasicError::new(format!(
+                    "Cannot merge client[{}], it is a joint owner. Merge the canonical owner instead.",
+                    cid.0
+                )));
+            }
+        }
+        let source_state = app_state.clients.remove(source).ok_or_else(|| {
+            BasicError::new(format!(
+                "Cannot merge client[{}] into client[{}], client[{}] has no transactions.",
+                source.0, target.0, source.0
+            ))
+        })?;
+        let target_state = app_state.clients.entry(*target).or_default();
+        if !source_state.disputed.is_empty() && !target_state.disputed.is_empty() {
+            return Err(BasicError::new(format!(
+                "Cannot merge client[{}] into client[{}], both have open disputes.",
+                source.0, target.0
+            )));
+        }
+        if let Some(tid) = source_state
+            .history
+            .keys()
+            .chain(source_state.disputed.keys())
+            .find(|tid| {
+                target_state.history.contains_key(tid) || target_state.disputed.contains_key(tid)
+            })
+        {
+            return Err(BasicError::new(format!(
+                "Cannot merge client[{}] into client[{}], both have a transaction tid[{}].",
+                source.0, target.0, tid.0
+            )));
+        }
+
+        for (account, balance) in source_state.accounts {
+            let merged = target_state.account(&account.0);
+            merged.available += balance.available;
+            merged.held += balance.held;
+            merged.penalties += balance.penalties;
+            merged.chargeback_fees += balance.chargeback_fees;
+            merged.written_off += balance.written_off;
+            merged.pending += balance.pending;
+        }
+        for (asset, accounts) in source_state.assets {
+            for (account, balance) in accounts {
+                let merged = target_state.balance(Some(&asset), &account.0);
+                merged.available += balance.available;
+                merged.held += balance.held;
+                merged.penalties += balance.penalties;
+                merged.chargeback_fees += balance.chargeback_fees;
+                merged.written_off += balance.written_off;
+                merged.pending += balance.pending;
+            }
+        }
+        target_state.history.extend(source_state.history);
+        target_state.disputed.extend(source_state.disputed);
+        target_state.lifecycle.extend(source_state.lifecycle);
+        target_state.shortfalls.extend(source_state.shortfalls);
+        target_state.locked |= source_state.locked;
+
+        for owner in app_state.joint_owners.values_mut() {
+            if owner == source {
+                *owner = *target;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_transaction;
+    use txcli_model::{Currency, TxRecord, TxType};
+
+    fn apply(app_state: &mut AppState, ty: TxType, cid: u16, tid: u32, amount: f64) {
+        execute_transaction(
+            app_state,
+            TxRecord::new(ty, cid, tid, Currency::from_num(amount)),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn read_merge_map() {
+        let merges = read_map("source,target\n5,1\n6,1\n".as_bytes()).unwrap();
+        assert_eq!(
+            merges,
+            vec![(ClientId(5), ClientId(1)), (ClientId(6), ClientId(1))]
+        );
+        assert!(read_map("source,target\n5,5\n".as_bytes()).is_err());
+        assert!(read_map("source,target\n5,1\n5,2\n".as_bytes()).is_err());
+        assert!(read_map("source,target\n5,1\n1,2\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn merge_balances_and_history() {
+        let mut app_state = AppState::default();
+        apply(&mut app_state, TxType::Deposit, 1, 1, 10.0);
+        apply(&mut app_state, TxType::Deposit, 5, 2, 4.0);
+        apply(&mut app_state, TxType::Dispute, 5, 2, 0.0);
+        merge(&mut app_state, &[(ClientId(5), ClientId(1))]).unwrap();
+
+        assert_eq!(app_state.clients.len(), 1);
+        let client = &app_state.clients[&ClientId(1)];
+        assert_eq!(client.available(), Currency::from_num(10.0));
+        assert_eq!(client.held(), Currency::from_num(4.0));
+
+        // The source's open dispute now resolves against the target.
+        apply(&mut app_state, TxType::Resolve, 1, 2, 0.0);
+        assert_eq!(
+            app_state.clients[&ClientId(1)].available(),
+            Currency::from_num(14.0)
+        );
+    }
+
+    #[test]
+    fn reject_conflicting_merges() {
+        let mut app_state = AppState::default();
+        apply(&mut app_state, TxType::Deposit, 1, 1, 10.0);
+        apply(&mut app_state, TxType::Deposit, 5, 2, 4.0);
+        apply(&mut app_state, TxType::Dispute, 1, 1, 0.0);
+        apply(&mut app_state, TxType::Dispute, 5, 2, 0.0);
+        assert!(merge(&mut app_state, &[(ClientId(5), ClientId(1))]).is_err());
+        assert!(merge(&mut app_state, &[(ClientId(9), ClientId(1))]).is_err());
+
+        let mut app_state = AppState::default();
+        apply(&mut app_state, TxType::Deposit, 1, 1, 10.0);
+        apply(&mut app_state, TxType::Deposit, 5, 1, 4.0);
+        assert!(merge(&mut app_state, &[(ClientId(5), ClientId(1))]).is_err());
+    }
+
+    #[test]
+    fn joint_owners_follow_the_merge() {
+        let mut app_state = AppState::default();
+        app_state.joint_owners.insert(ClientId(6), ClientId(5));
+        apply(&mut app_state, TxType::Deposit, 5, 1, 1.0);
+        merge(&mut app_state, &[(ClientId(5), ClientId(1))]).unwrap();
+        assert_eq!(app_state.canonical(ClientId(6)), ClientId(1));
+        assert!(merge(&mut app_state, &[(ClientId(6), ClientId(1))]).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::BasicError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+use txcli_model::ClientId;
+
+// One row per client. Only the columns txcli understands are read, anything else
+// (names, addresses, ...) is allowed so the same file can be shared with other tools.
+#[derive(Deserialize, Debug)]
+struct MetadataRow {
+    client: ClientId,
+    // The client id that owns the shared state when this client is a joint owner of another client's account.
+    #[serde(default)]
+    joint_owner: Option<ClientId>,
+}
+
+#[derive(Default, Debug)]
+pub struct ClientMetadata {
+    // Maps each joint owner to the canonical client holding the shared state.
+    pub joint_owners: HashMap<ClientId, ClientId>,
+}
+
+pub fn load<R: Read>(input: R) -> Result<ClientMetadata, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(true)
+        .from_reader(input);
+
+    let mut metadata = ClientMetadata::default();
+    for row in reader.deserialize::<MetadataRow>() {
+        let row = row?;
+        let owner = match row.joint_owner {
+            Some(owner) if owner != row.client => owner,
+            _ => continue,
+        };
+        if metadata.joint_owners.insert(row.client, owner).is_some() {
+            return Err(BasicError::new(format!(
+                "Client metadata lists client[{}] more than once.",
+                row.client.0
+            )));
+        }
+    }
+
+    // Chains would make the canonical owner depend on lookup order, so only one level is allowed.
+    for (client, owner) in &metadata.joint_owners {
+        if metadata.joint_owners.contains_key(owner) {
+            return Err(BasicError::new(format!(
+                "Client[{}] is a joint owner of client[{}], which is itself a joint owner of another client.",
+                client.0, owner.0
+            )));
+        }
+    }
+
+    Ok(metadata)
+}
+
+// Every column of the metadata file besides client, as text, for copying into reports.
+#[derive(Default, Debug)]
+pub struct MetadataColumns {
+    pub header: Vec<String>,
+    pub values: HashMap<ClientId, Vec<String>>,
+}
+
+impl MetadataColumns {
+    // The client's values in header order, blank for clients the file doesn't list.
+    pub fn of(&self, cid: ClientId) -> Vec<String> {
+        self.values
+            .get(&cid)
+            .cloned()
+            .unwrap_or_else(|| vec![String::new(); self.header.len()])
+    }
+}
+
+pub fn load_columns<R: Read>(input: R) -> Result<MetadataColumns, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(true)
+        .from_reader(input);
+    let headers = reader.headers()?.clone();
+    let client = headers
+        .iter()
+        .position(|header| header == "client")
+        .ok_or_else(|| BasicError::new("Client metadata is missing the client column."))?;
+    let others = |record: &csv::StringRecord| -> Vec<String> {
+        record
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != client)
+            .map(|(_, value)| value.to_string())
+            .collect()
+    };
+    let mut columns = MetadataColumns {
+        header: others(&headers),
+        values: HashMap::new(),
+    };
+    for record in reader.records() {
+        let record = record?;
+        let cid = record[client].parse::<u16>().map_err(|_| {
+            BasicError::new(format!(
+                "Invalid client {} in client metadata.",
+                &record[client]
+            ))
+        })?;
+        columns.values.insert(ClientId(cid), others(&record));
+    }
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_joint_owners() {
+        let input = "client,name,joint_owner\n1,alice,\n2,bob,1\n3,carol,3\n";
+        let metadata = load(input.as_bytes()).unwrap();
+        assert_eq!(metadata.joint_owners.len(), 1);
+        assert_eq!(metadata.joint_owners[&ClientId(2)], ClientId(1));
+    }
+
+    #[test]
+    fn joint_owner_column_is_optional() {
+        let metadata = load("client,name\n1,alice\n".as_bytes()).unwrap();
+        assert!(metadata.joint_owners.is_empty());
+    }
+
+    #[test]
+    fn reject_chains_and_duplicates() {
+        assert!(load("client,joint_owner\n2,1\n3,2\n".as_bytes()).is_err());
+        assert!(load("client,joint_owner\n2,1\n2,3\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn load_every_column() {
+        let input = "name,client,joint_owner\nalice,1,\nbob,2,1\n";
+        let columns = load_columns(input.as_bytes()).unwrap();
+        assert_eq!(columns.header, ["name", "joint_owner"]);
+        assert_eq!(columns.of(ClientId(2)), ["bob", "1"]);
+        assert_eq!(columns.of(ClientId(3)), ["", ""]);
+        assert!(load_columns("name\nalice\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::i18n::{text, Msg};
+use crate::rejection::RejectionReason;
+use crate::{soak, BasicError};
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use txcli_model::{ClientId, TxRecord, TxType};
+
+// Counters over every row the engine is handed, kept whatever the flags so a library user can
+// read them off Engine::metrics while a run goes on. --stats prints them.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    started: Instant,
+    pub rows: u64,
+    pub applied: u64,
+    // Rows per type, applied or not.
+    pub by_type: HashMap<TxType, u64>,
+    pub rejections: HashMap<RejectionReason, u64>,
+    // Clients any row named, before joint owners are mapped to their canonical client.
+    pub clients: HashSet<ClientId>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            started: Instant::now(),
+            rows: 0,
+            applied: 0,
+            by_type: HashMap::new(),
+            rejections: HashMap::new(),
+            clients: HashSet::new(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn record(&mut self, tx: &TxRecord, result: Result<(), RejectionReason>) {
+        self.rows += 1;
+        *self.by_type.entry(tx.tx_type).or_default() += 1;
+        match result {
+            Ok(()) => self.applied += 1,
+            Err(reason) => *self.rejections.entry(reason).or_default() += 1,
+        }
+        self.clients.insert(tx.cid);
+    }
+
+    // Since the engine was built.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn rows_per_sec(&self) -> f64 {
+        let seconds = self.elapsed().as_secs_f64();
+        if seconds > 0.0 {
+            self.rows as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            rows: self.rows,
+            applied: self.applied,
+            rejected: self.rows - self.applied,
+            parse_errors: 0,
+            by_type: self
+                .by_type
+                .iter()
+                .map(|(tx_type, count)| (tx_type.to_string(), *count))
+                .collect(),
+            rejections: self
+                .rejections
+                .iter()
+                .map(|(reason, count)| (reason.to_string(), *count))
+                .collect(),
+            clients_touched: self.clients.len() as u64,
+            elapsed_secs: self.elapsed().as_secs_f64(),
+            rows_per_sec: self.rows_per_sec(),
+        }
+    }
+}
+
+// The final --stats line, ordered keys so runs diff cleanly.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Summary {
+    pub rows: u64,
+    pub applied: u64,
+    pub rejected: u64,
+    // Rows that couldn't be parsed, which never reach the engine.
+    pub parse_errors: u64,
+    pub by_type: BTreeMap<String, u64>,
+    // Per reason code, the same codes as --errors.
+    pub rejections: BTreeMap<String, u64>,
+    pub clients_touched: u64,
+    pub elapsed_secs: f64,
+    pub rows_per_sec: f64,
+}
+
+// How often --progress-every prints a progress line.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ProgressEvery {
+    Rows(u64),
+    Time(Duration),
+}
+
+impl FromStr for ProgressEvery {
+    type Err = Box<dyn Error>;
+
+    // A bare number counts rows, one with a unit (30s, 5m) is a period.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let every = match s.parse::<u64>() {
+            Ok(rows) => (rows > 0).then_some(ProgressEvery::Rows(rows)),
+            Err(_) => soak::parse_duration(s).map(ProgressEvery::Time),
+        };
+        every.ok_or_else(|| {
+            BasicError::new(format!(
+                "Invalid --progress-every {}, expected a number of rows or a period like 30s or 5m.",
+                s
+            )) as Box<dyn Error>
+        })
+    }
+}
+
+impl Display for ProgressEvery {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ProgressEvery::Rows(rows) => write!(f, "{}", rows),
+            ProgressEvery::Time(period) => write!(f, "{}s", period.as_secs()),
+        }
+    }
+}
+
+impl Serialize for ProgressEvery {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+// Prints a line to stderr whenever the next one is due, checked once per row.
+pub struct Progress {
+    every: ProgressEvery,
+    rows: u64,
+    at: Instant,
+}
+
+impl Progress {
+    pub fn new(every: ProgressEvery) -> Self {
+        Progress {
+            every,
+            rows: 0,
+            at: Instant::now(),
+        }
+    }
+
+    fn due(&self, metrics: &Metrics) -> bool {
+        match self.every {
+            ProgressEvery::Rows(rows) => metrics.rows >= self.rows + rows,
+            ProgressEvery::Time(period) => self.at.elapsed() >= period,
+        }
+    }
+
+    pub fn tick(&mut self, metrics: &Metrics) {
+        if !self.due(metrics) {
+            return;
+        }
+        self.rows = metrics.rows;
+        self.at = Instant::now();
+        eprintln!(
+            "{}",
+            text(
+                Msg::Progress,
+                &[
+                    &metrics.rows,
+                    &metrics.applied,
+                    &(metrics.rows - metrics.applied),
+                    &metrics.clients.len(),
+                    &format!("{:.0}", metrics.rows_per_sec()),
+                ],
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::Currency;
+
+    #[test]
+    fn counts_per_type_and_reason() {
+        let mut metrics = Metrics::default();
+        let deposit = TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1));
+        let withdrawal = TxRecord::new(TxType::Withdrawal, 2, 2, Currency::from_num(5));
+        metrics.record(&deposit, Ok(()));
+        metrics.record(&deposit, Err(RejectionReason::ReusedId));
+        metrics.record(&withdrawal, Err(RejectionReason::InsufficientFunds));
+        let summary = metrics.summary();
+        assert_eq!((summary.rows, summary.applied, summary.rejected), (3, 1, 2));
+        assert_eq!(summary.clients_touched, 2);
+        assert_eq!(summary.by_type["deposit"], 2);
+        assert_eq!(summary.rejections["insufficient_funds"], 1);
+        assert_eq!(summary.rejections["reused_id"], 1);
+    }
+
+    #[test]
+    fn parse_progress_every() {
+        assert_eq!(
+            "50000".parse::<ProgressEvery>().unwrap(),
+            ProgressEvery::Rows(50000)
+        );
+        assert_eq!(
+            "30s".parse::<ProgressEvery>().unwrap(),
+            ProgressEvery::Time(Duration::from_secs(30))
+        );
+        assert!("0".parse::<ProgressEvery>().is_err());
+        assert!("soon".parse::<ProgressEvery>().is_err());
+    }
+}
@@ -0,0 +1,3161 @@
+use crate::control::Request;
+use crate::engine::{DisputePolicy, FeePayer, LockedPolicy, Overflow};
+use crate::explain::Format;
+use crate::filter::OutputFilter;
+use crate::i18n::Lang;
+use crate::metrics::ProgressEvery;
+use crate::ordering::OrderingPolicy;
+use crate::policy::{self, PolicyAction};
+use crate::results::{self, OutputFormat};
+use crate::retention::Retention;
+use crate::rollforward::Period;
+use crate::schedule::Cron;
+use crate::schema::Schema;
+use crate::soak;
+use crate::source::InputFormat;
+use crate::spill::HistoryBackend;
+use crate::validation::TidScope;
+use crate::BasicError;
+use serde::{Serialize, Serializer};
+use std::error::Error;
+use std::time::Duration;
+use txcli_model::{parse_amount, ClientId, Currency, TxId};
+
+// --serve and --stdin, the flags pipe and shrink take as well.
+const SERVE_USAGE: &str = "txcli --serve <addr> | serve <addr> | --stdin [--clients <metadata.csv>] [--overdraft-rate <rate> [--cutoff-every <rows>]] [--assets <assets.toml>] [--tx-types <types.toml>] [--max-open-disputes <n> [--dispute-overflow reject|queue]] [--lock-after-chargebacks <n>] [--lock-after-chargeback-amount <amount>] [--locked-policy reject-all|reject-withdrawals|allow] [--dispute-policy allow-negative|reject|partial-hold] [--chargeback-fee <amount> [--chargeback-fee-payer client|counterparty]] [--writeoffs weekly|monthly] [--settlement-delays <delays.toml>] [--unique-tids client|global] [--retain-history last-<n>|amount-bearing|until-resolved] [--lang en|fr|de] [--policy <policy.toml>] [--history-backend memory|disk [--history-cache <txs>]] [--latency-budget <ms>] [--control <socket>]";
+
+// Ends every usage.
+const EVERY_COMMAND: &str = "Every command takes --read-only and --help.";
+
+// The other commands, with the usage line each prints.
+const USAGE: [(&[Command], &str); 21] = [
+    (
+        &[Command::Pipe],
+        "txcli pipe [the flags --stdin takes] [--state-every <rows>]",
+    ),
+    (
+        &[Command::Policy],
+        "txcli policy lint|explain [<policy.toml>] [--overdraft-rate <rate> [--cutoff-every <rows>]] [--max-amount <amount>] [--max-open-disputes <n> [--dispute-overflow reject|queue]] [--lock-after-chargebacks <n>] [--lock-after-chargeback-amount <amount>] [--locked-policy reject-all|reject-withdrawals|allow] [--dispute-policy allow-negative|reject|partial-hold] [--chargeback-fee <amount> [--chargeback-fee-payer client|counterparty]] [--writeoffs weekly|monthly] [--settlement-delays <delays.toml>] [--unique-tids client|global] [--retain-history last-<n>|amount-bearing|until-resolved] [--ordering-policy priority|input] [--assets <assets.toml>] [--tx-types <types.toml>] [--categorize <rules.toml>]",
+    ),
+    (
+        &[Command::Ctl],
+        "txcli ctl pause|resume|status|snapshot <state.json> --control <socket>",
+    ),
+    (
+        &[Command::Config],
+        "txcli config effective <the command line of any run>",
+    ),
+    (
+        &[Command::Tui],
+        "txcli tui <input.csv> [--follow] [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]]",
+    ),
+    (
+        &[Command::Soak],
+        "txcli soak --duration <24h> --tps <n> [--seed <n>]",
+    ),
+    (
+        &[Command::DeterminismCheck],
+        "txcli determinism-check",
+    ),
+    (
+        &[Command::ConvergeTest],
+        "txcli converge-test [--replicas <n>] [--seed <n>]",
+    ),
+    (
+        &[Command::Schedule],
+        "txcli schedule --cron <minute hour day month weekday> --job <job.toml>",
+    ),
+    (
+        &[Command::Explain],
+        "txcli explain --tx <id> | --client <id> <input.csv> [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]] [--format text|json] [--ordering-policy priority|input]",
+    ),
+    (
+        &[Command::Screen],
+        "txcli screen <input.csv> [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]]",
+    ),
+    (
+        &[Command::Activity],
+        "txcli activity <input.csv> [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]]",
+    ),
+    (
+        &[Command::Estimate],
+        "txcli estimate <input.csv> [--schema v1|v2]",
+    ),
+    (
+        &[Command::Anonymize],
+        "txcli anonymize <input.csv> --out <anon.csv> --seed <n> [--perturb <percent>] [--sample <fraction>] [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]]",
+    ),
+    (
+        &[Command::Split],
+        "txcli split <input.csv> --by client-mod <n> -o <dir> [--clients <metadata.csv>] [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]]",
+    ),
+    (
+        &[Command::Shrink],
+        "txcli shrink <input.csv> --out <shrunk.csv> [--preserve-client <id>] [--preserve-failure] [--conservation-check] [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]] [the flags --stdin takes but --control]",
+    ),
+    (
+        &[Command::CompareVersions],
+        "txcli compare-versions --old <txcli> --new <txcli> <input.csv> [--schema v1|v2]",
+    ),
+    (
+        &[Command::Profile],
+        "txcli profile <input.csv> [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]] [--profile-out <perf.json>]",
+    ),
+    (
+        &[Command::Statements],
+        "txcli statements <input.csv> --out-dir <dir> [--clients <metadata.csv>] [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]]",
+    ),
+    (
+        &[Command::Audit],
+        "txcli audit <input.csv> --out-dir <dir> [--clients <metadata.csv>] [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]]",
+    ),
+    (
+        &[Command::Process, Command::Bootstrap, Command::MergeClients, Command::SplitClient],
+        "txcli [process | bootstrap --balances <opening.csv> | merge-clients --map <merges.csv> | split-client --client <id> --txs <tids.txt> --into <id>] <input.csv> [--schema v1|v2] [--input-format csv|jsonl|parquet|fixed-width [--layout <layout.toml>]] [--clients <metadata.csv>] [--disjoint-files <more.csv>...] [--closing-balances <expected.csv> [--tolerance <amount>]] [--overdraft-rate <rate> [--cutoff-every <rows>]] [--assets <assets.toml>] [--tx-types <types.toml>] [--categorize <rules.toml>] [--fixups <fixups.toml>] [--anomalies <anomalies.csv> [--anomaly-threshold <z>]] [--graph-out <disputes.dot|disputes.json>] [--category-report <categories.csv>] [--sample <fraction> --sample-out <sample.csv> [--seed <n>]] [--mem-stats] [--stats [--progress-every <rows|period>]] [--redis <redis://host:port/db>] [--upsert <balances.csv>] [--cdc-out <changes.jsonl>] [--manifest <run.json>] [--retry-out <retry.csv>] [--errors <rejections.csv|rejections.jsonl>] [--snapshot-in <state.json>] [--snapshot-out <state.json>] [--settlement-report <settlement.csv>] [--rollforward weekly|monthly --rollforward-out <rollforward.csv>] [--writeoff-report <writeoffs.csv>] [--report-by-source <sources.csv>] [--audit-out <dir>] [--precision-report <precision.csv>] [--strict] [--conservation-check] [--atomic-per-file] [--assert <expr>]... [--assertions <file>] [--disputes-file <cases.csv>] [--authorizations <auths.csv>] [--output-filter locked|has-open-disputes|negative]... [--include-archived] [--output <balances.csv>] [--output-format csv|json] [--columns <client,available,held,total,locked,...>] [--warnings-fd <n> | --warnings-out <warnings.jsonl>] [--max-amount <amount>] [--max-open-disputes <n> [--dispute-overflow reject|queue]] [--lock-after-chargebacks <n>] [--lock-after-chargeback-amount <amount>] [--locked-policy reject-all|reject-withdrawals|allow] [--dispute-policy allow-negative|reject|partial-hold] [--chargeback-fee <amount> [--chargeback-fee-payer client|counterparty]] [--writeoffs weekly|monthly] [--settlement-delays <delays.toml>] [--unique-tids client|global] [--ordering-policy priority|input] [--retain-history last-<n>|amount-bearing|until-resolved] [--history-backend memory|disk [--history-cache <txs>]] [--lang en|fr|de] [--policy <policy.toml>] [--emit-every <rows> --emit-dir <dir>] [--read-buffer <bytes>] [--threads <n|auto>] [--parse-threads <n|auto> [--batch-size <rows>] [--channel-capacity <batches>]]",
+    ),
+];
+
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Command {
+    #[default]
+    Process,
+    // Same as process, but starting from opening balances instead of an empty state.
+    Bootstrap,
+    // Statistical screens over the input's amounts, no balances are computed.
+    Screen,
+    // Spread of transactions over clients and amounts, no balances are computed either.
+    Activity,
+    // Shareable test data from the input, pseudonymized and perturbed, no balances are computed.
+    Anonymize,
+    // Same as process, then folds duplicate client ids into one before writing the balances.
+    MergeClients,
+    // Same as process, but the listed transactions of one client are moved to a new client id.
+    SplitClient,
+    // Plain processing with per stage timing, reports the breakdown instead of the balances.
+    Profile,
+    // Generated traffic against the engine at a fixed rate, no input file.
+    Soak,
+    // Plain processing behind a live dashboard, the balances are written once it is closed.
+    Tui,
+    // Lifecycle of one transaction or timeline of one client, reported instead of the balances.
+    Explain,
+    // Runs two txcli binaries over the same input and diffs their balances, before an upgrade.
+    CompareVersions,
+    // Plain processing, writing a statement file per client instead of the balances.
+    Statements,
+    // Checks or prints the policy flags and policy file, no input is read.
+    Policy,
+    // Partitions the input into shard files by client, no balances are computed.
+    Split,
+    // Plain processing, writing every client's audit trail instead of the balances.
+    Audit,
+    // Hashes the state a bundled workload leaves against the committed hash, no input file.
+    DeterminismCheck,
+    // Like --stdin, with length prefixed JSON frames for programs embedding txcli as a co-process.
+    Pipe,
+    // Prints how the command line of another run resolves, the run isn't started.
+    Config,
+    // Runs a job file's command line whenever a cron expression fires, until stopped.
+    Schedule,
+    // Samples the input to estimate its size and what a run over it takes, no balances computed.
+    Estimate,
+    // Pauses, resumes, checks on or snapshots a process started with --control, no input file.
+    Ctl,
+    // Cuts a failing input down to the fewest rows that still fail the same way, no balances.
+    Shrink,
+    // Applies a generated workload on engines splitting the work differently and compares their
+    // state hashes, no input file.
+    ConvergeTest,
+}
+
+impl Command {
+    // The subcommand given as the first argument, plain processing needs none.
+    pub fn named(name: &str) -> Option<Command> {
+        match name {
+            "process" => Some(Command::Process),
+            "bootstrap" => Some(Command::Bootstrap),
+            "screen" => Some(Command::Screen),
+            "activity" => Some(Command::Activity),
+            "anonymize" => Some(Command::Anonymize),
+            "merge-clients" => Some(Command::MergeClients),
+            "split-client" => Some(Command::SplitClient),
+            "profile" => Some(Command::Profile),
+            "soak" => Some(Command::Soak),
+            "explain" => Some(Command::Explain),
+            "tui" => Some(Command::Tui),
+            "compare-versions" => Some(Command::CompareVersions),
+            "statements" => Some(Command::Statements),
+            "policy" => Some(Command::Policy),
+            "split" => Some(Command::Split),
+            "audit" => Some(Command::Audit),
+            "determinism-check" => Some(Command::DeterminismCheck),
+            "pipe" => Some(Command::Pipe),
+            "config" => Some(Command::Config),
+            "schedule" => Some(Command::Schedule),
+            "estimate" => Some(Command::Estimate),
+            "ctl" => Some(Command::Ctl),
+            "shrink" => Some(Command::Shrink),
+            "converge-test" => Some(Command::ConvergeTest),
+            _ => None,
+        }
+    }
+}
+
+// Whether flag is one txcli takes, the usage lines list every one of them.
+pub fn known_flag(flag: &str) -> bool {
+    flag.starts_with("--")
+        && USAGE
+            .iter()
+            .map(|(_, line)| *line)
+            .chain([SERVE_USAGE, EVERY_COMMAND])
+            .flat_map(|line| line.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-')))
+            .any(|word| word == flag)
+}
+
+// Hand rolled rather than pulling in an argument parsing crate. Errors print the usage of the
+// command given, see Options::usage.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub input: String,
+    pub balances: Option<String>,
+    pub map: Option<String>,
+    pub client: Option<ClientId>,
+    pub split_txs: Option<String>,
+    pub split_into: Option<ClientId>,
+    pub schema: Option<Schema>,
+    pub profile_out: Option<String>,
+    pub follow: bool,
+    pub tx: Option<TxId>,
+    pub format: Option<Format>,
+    #[serde(serialize_with = "serialize_optional_seconds")]
+    pub duration: Option<Duration>,
+    pub tps: Option<u64>,
+    pub clients: Option<String>,
+    pub closing_balances: Option<String>,
+    pub tolerance: Option<Currency>,
+    pub overdraft_rate: Option<Currency>,
+    pub cutoff_every: Option<u64>,
+    pub assets: Option<String>,
+    pub categorize: Option<String>,
+    pub anomalies: Option<String>,
+    pub anomaly_threshold: Option<f64>,
+    pub graph_out: Option<String>,
+    pub category_report: Option<String>,
+    pub sample: Option<f64>,
+    pub sample_out: Option<String>,
+    pub seed: Option<u64>,
+    pub replicas: Option<usize>,
+    pub mem_stats: bool,
+    pub redis: Option<String>,
+    pub cdc_out: Option<String>,
+    pub manifest: Option<String>,
+    pub retry_out: Option<String>,
+    pub errors: Option<String>,
+    pub settlement_report: Option<String>,
+    pub rollforward: Option<Period>,
+    pub rollforward_out: Option<String>,
+    pub conservation_check: bool,
+    pub parse_threads: Option<usize>,
+    pub read_buffer: Option<usize>,
+    pub batch_size: Option<usize>,
+    pub channel_capacity: Option<usize>,
+    pub read_only: bool,
+    pub max_amount: Option<Currency>,
+    pub emit_every: Option<u64>,
+    pub emit_dir: Option<String>,
+    pub out: Option<String>,
+    pub perturb: Option<f64>,
+    pub disputes_file: Option<String>,
+    pub report_by_source: Option<String>,
+    pub assert: Vec<String>,
+    pub assertions: Option<String>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    pub warnings_fd: Option<i32>,
+    pub warnings_out: Option<String>,
+    pub max_open_disputes: Option<usize>,
+    pub dispute_overflow: Option<Overflow>,
+    pub lock_after_chargebacks: Option<u64>,
+    pub lock_after_chargeback_amount: Option<Currency>,
+    pub ordering_policy: Option<OrderingPolicy>,
+    pub lang: Option<Lang>,
+    pub tx_types: Option<String>,
+    pub retain_history: Option<Retention>,
+    pub upsert: Option<String>,
+    pub serve: Option<String>,
+    pub stdin: bool,
+    pub out_dir: Option<String>,
+    pub threads: Option<usize>,
+    pub policy: Option<String>,
+    pub policy_action: Option<PolicyAction>,
+    pub snapshot_in: Option<String>,
+    pub snapshot_out: Option<String>,
+    pub locked_policy: Option<LockedPolicy>,
+    pub dispute_policy: Option<DisputePolicy>,
+    pub chargeback_fee: Option<Currency>,
+    pub chargeback_fee_payer: Option<FeePayer>,
+    pub writeoffs: Option<Period>,
+    pub writeoff_report: Option<String>,
+    pub settlement_delays: Option<String>,
+    pub client_mod: Option<usize>,
+    pub preserve_client: Option<ClientId>,
+    pub preserve_failure: bool,
+    pub authorizations: Option<String>,
+    pub input_format: Option<InputFormat>,
+    pub layout: Option<String>,
+    pub output_filter: Vec<OutputFilter>,
+    pub unique_tids: Option<TidScope>,
+    pub atomic_per_file: bool,
+    pub disjoint_files: bool,
+    // The input files after the first, only with --disjoint-files.
+    pub more_inputs: Vec<String>,
+    pub history_backend: Option<HistoryBackend>,
+    pub history_cache: Option<usize>,
+    pub include_archived: bool,
+    pub audit_out: Option<String>,
+    pub strict: bool,
+    pub precision_report: Option<String>,
+    pub output: Option<String>,
+    pub output_format: Option<OutputFormat>,
+    pub columns: Option<Vec<&'static str>>,
+    #[serde(serialize_with = "serialize_optional_millis")]
+    pub latency_budget: Option<Duration>,
+    pub state_every: Option<u64>,
+    pub stats: bool,
+    pub progress_every: Option<ProgressEvery>,
+    pub fixups: Option<String>,
+    // The command line config effective reports on, parsed when it is reported.
+    #[serde(skip)]
+    pub config_args: Vec<String>,
+    pub cron: Option<Cron>,
+    pub job: Option<String>,
+    pub control: Option<String>,
+    pub ctl: Option<Request>,
+    // --help, the usage of the command is printed instead of running it.
+    #[serde(skip)]
+    pub help: bool,
+}
+
+// Whole seconds, the same unit --duration takes without a suffix.
+fn serialize_optional_seconds<S>(duration: &Option<Duration>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match duration {
+        Some(duration) => s.serialize_u64(duration.as_secs()),
+        None => s.serialize_none(),
+    }
+}
+
+// Milliseconds, the unit --latency-budget takes.
+fn serialize_optional_millis<S>(duration: &Option<Duration>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match duration {
+        Some(duration) => s.serialize_f64(duration.as_secs_f64() * 1000.0),
+        None => s.serialize_none(),
+    }
+}
+
+impl Options {
+    pub fn parse<I>(args: I) -> Result<Self, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Options::default();
+        let mut input = None;
+        let mut args: Vec<String> = args.into_iter().skip(1).collect();
+        // serve <addr> is --serve <addr> under another name.
+        if args.first().is_some_and(|arg| arg == "serve") {
+            args[0] = "--serve".to_string();
+        }
+        let mut start = 0;
+        if let Some(command) = args.first().and_then(|arg| Command::named(arg)) {
+            options.command = command;
+            start = 1;
+        }
+        // config effective takes the whole command line of another run.
+        if options.command == Command::Config {
+            if args.get(1).map(String::as_str) != Some("effective") {
+                return Err(BasicError::new(format!(
+                    "config requires effective. {}",
+                    options.usage()
+                )));
+            }
+            options.config_args = args.split_off(2);
+            return Ok(options);
+        }
+        if args.iter().any(|arg| arg == "--help") {
+            options.help = true;
+            options.stdin = args.iter().any(|arg| arg == "--stdin");
+            options.serve = args
+                .iter()
+                .position(|arg| arg == "--serve")
+                .map(|index| args.get(index + 1).cloned().unwrap_or_default());
+            return Ok(options);
+        }
+        // policy takes lint or explain, then the policy file if any, read as --policy.
+        if options.command == Command::Policy {
+            let action = args.get(1).ok_or_else(|| {
+                BasicError::new(format!(
+                    "policy requires lint or explain. {}",
+                    options.usage()
+                ))
+            })?;
+            options.policy_action = Some(action.parse()?);
+            start = 2;
+            if args.get(2).is_some_and(|arg| !arg.starts_with("--")) {
+                args.insert(2, "--policy".to_string());
+            }
+        }
+        // ctl takes pause, resume, status or snapshot and the path to write the snapshot to.
+        if options.command == Command::Ctl {
+            let action = args.get(1).ok_or_else(|| {
+                BasicError::new(format!(
+                    "ctl requires pause, resume, status or snapshot <state.json>. {}",
+                    options.usage()
+                ))
+            })?;
+            let argument = args.get(2).filter(|arg| !arg.starts_with("--"));
+            options.ctl = Some(Request::parse(action, argument.map(String::as_str))?);
+            start = if argument.is_some() { 3 } else { 2 };
+        }
+        // The policy file's flags go ahead of the command line's, so those override the file.
+        if let Some(index) = args.iter().position(|arg| arg == "--policy") {
+            let path = flag_value("--policy", args.get(index + 1).cloned())?;
+            args.splice(start..start, policy::flags(&path)?);
+        }
+        let mut args = args.into_iter().skip(start);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--balances" => options.balances = Some(flag_value(&arg, args.next())?),
+                "--map" => options.map = Some(flag_value(&arg, args.next())?),
+                "--client" => {
+                    options.client = Some(client_id(&arg, flag_value(&arg, args.next())?)?)
+                }
+                "--txs" => options.split_txs = Some(flag_value(&arg, args.next())?),
+                "--into" => {
+                    options.split_into = Some(client_id(&arg, flag_value(&arg, args.next())?)?)
+                }
+                "--schema" => options.schema = Some(flag_value(&arg, args.next())?.parse()?),
+                "--input-format" => {
+                    options.input_format = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--layout" => options.layout = Some(flag_value(&arg, args.next())?),
+                "--profile-out" => options.profile_out = Some(flag_value(&arg, args.next())?),
+                "--follow" => options.follow = true,
+                "--tx" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let tid = value
+                        .parse::<u32>()
+                        .map_err(|_| BasicError::new(format!("Invalid --tx id {}.", value)))?;
+                    options.tx = Some(TxId(tid));
+                }
+                "--format" => options.format = Some(flag_value(&arg, args.next())?.parse()?),
+                "--duration" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let duration = soak::parse_duration(&value).ok_or_else(|| {
+                        BasicError::new(format!(
+                            "Invalid --duration {}, expected e.g. 90s, 30m, 24h or 2d.",
+                            value
+                        ))
+                    })?;
+                    options.duration = Some(duration);
+                }
+                "--tps" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let tps = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|tps| *tps > 0)
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --tps {}, expected a positive rate.",
+                                value
+                            ))
+                        })?;
+                    options.tps = Some(tps);
+                }
+                "--clients" => options.clients = Some(flag_value(&arg, args.next())?),
+                "--closing-balances" => {
+                    options.closing_balances = Some(flag_value(&arg, args.next())?)
+                }
+                "--tolerance" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let tolerance = parse_amount(&value).map_err(|_| {
+                        BasicError::new(format!("Invalid --tolerance amount {}.", value))
+                    })?;
+                    options.tolerance = Some(tolerance);
+                }
+                "--max-amount" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let max = parse_amount(&value)
+                        .ok()
+                        .filter(|max| *max > 0)
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --max-amount {}, expected a positive amount.",
+                                value
+                            ))
+                        })?;
+                    options.max_amount = Some(max);
+                }
+                "--assets" => options.assets = Some(flag_value(&arg, args.next())?),
+                "--tx-types" => options.tx_types = Some(flag_value(&arg, args.next())?),
+                "--retain-history" => {
+                    options.retain_history = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--history-backend" => {
+                    options.history_backend = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--history-cache" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.history_cache = Some(positive(&arg, &value, "transaction count")?);
+                }
+                "--anomalies" => options.anomalies = Some(flag_value(&arg, args.next())?),
+                "--anomaly-threshold" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let threshold = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|threshold| *threshold > 0.0)
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --anomaly-threshold {}, expected a positive z-score.",
+                                value
+                            ))
+                        })?;
+                    options.anomaly_threshold = Some(threshold);
+                }
+                "--state-every" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.state_every = Some(positive(&arg, &value, "number of rows")? as u64);
+                }
+                "--latency-budget" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let budget = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ms| *ms > 0.0 && ms.is_finite())
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --latency-budget {}, expected a positive number of milliseconds.",
+                                value
+                            ))
+                        })?;
+                    options.latency_budget = Some(Duration::from_secs_f64(budget / 1000.0));
+                }
+                "--graph-out" => options.graph_out = Some(flag_value(&arg, args.next())?),
+                "--categorize" => options.categorize = Some(flag_value(&arg, args.next())?),
+                "--fixups" => options.fixups = Some(flag_value(&arg, args.next())?),
+                "--category-report" => {
+                    options.category_report = Some(flag_value(&arg, args.next())?)
+                }
+                "--overdraft-rate" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let rate = parse_amount(&value).map_err(|_| {
+                        BasicError::new(format!("Invalid --overdraft-rate {}.", value))
+                    })?;
+                    options.overdraft_rate = Some(rate);
+                }
+                "--cutoff-every" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let every = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|every| *every > 0)
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --cutoff-every {}, expected a positive row count.",
+                                value
+                            ))
+                        })?;
+                    options.cutoff_every = Some(every);
+                }
+                "--sample" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let rate = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|rate| (0.0..=1.0).contains(rate))
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --sample {}, expected a fraction between 0 and 1.",
+                                value
+                            ))
+                        })?;
+                    options.sample = Some(rate);
+                }
+                "--sample-out" => options.sample_out = Some(flag_value(&arg, args.next())?),
+                "--seed" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let seed = value
+                        .parse::<u64>()
+                        .map_err(|_| BasicError::new(format!("Invalid --seed {}.", value)))?;
+                    options.seed = Some(seed);
+                }
+                "--replicas" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let replicas = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|replicas| *replicas >= 2)
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --replicas {}, expected at least 2 to compare.",
+                                value
+                            ))
+                        })?;
+                    options.replicas = Some(replicas);
+                }
+                "--mem-stats" => options.mem_stats = true,
+                "--stats" => options.stats = true,
+                "--progress-every" => {
+                    options.progress_every = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--redis" => options.redis = Some(flag_value(&arg, args.next())?),
+                "--cdc-out" => options.cdc_out = Some(flag_value(&arg, args.next())?),
+                "--manifest" => options.manifest = Some(flag_value(&arg, args.next())?),
+                "--retry-out" => options.retry_out = Some(flag_value(&arg, args.next())?),
+                "--snapshot-in" => options.snapshot_in = Some(flag_value(&arg, args.next())?),
+                "--snapshot-out" => options.snapshot_out = Some(flag_value(&arg, args.next())?),
+                "--errors" => options.errors = Some(flag_value(&arg, args.next())?),
+                "--conservation-check" => options.conservation_check = true,
+                "--atomic-per-file" => options.atomic_per_file = true,
+                "--disjoint-files" => options.disjoint_files = true,
+                "--include-archived" => options.include_archived = true,
+                "--strict" => options.strict = true,
+                "--output" => options.output = Some(flag_value(&arg, args.next())?),
+                "--output-format" => {
+                    options.output_format = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--columns" => {
+                    options.columns = Some(results::parse_columns(&flag_value(&arg, args.next())?)?)
+                }
+                "--precision-report" => {
+                    options.precision_report = Some(flag_value(&arg, args.next())?)
+                }
+                "--audit-out" => options.audit_out = Some(flag_value(&arg, args.next())?),
+                "--lock-after-chargebacks" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.lock_after_chargebacks =
+                        Some(positive(&arg, &value, "chargeback count")? as u64);
+                }
+                "--lock-after-chargeback-amount" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let amount = parse_amount(&value)
+                        .ok()
+                        .filter(|amount| *amount > 0)
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --lock-after-chargeback-amount {}, expected a positive amount.",
+                                value
+                            ))
+                        })?;
+                    options.lock_after_chargeback_amount = Some(amount);
+                }
+                "--max-open-disputes" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.max_open_disputes = Some(positive(&arg, &value, "dispute count")?);
+                }
+                "--dispute-overflow" => {
+                    options.dispute_overflow = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--locked-policy" => {
+                    options.locked_policy = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--dispute-policy" => {
+                    options.dispute_policy = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--chargeback-fee" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let amount = parse_amount(&value)
+                        .ok()
+                        .filter(|amount| *amount > 0)
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --chargeback-fee {}, expected a positive amount.",
+                                value
+                            ))
+                        })?;
+                    options.chargeback_fee = Some(amount);
+                }
+                "--chargeback-fee-payer" => {
+                    options.chargeback_fee_payer = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--writeoffs" => options.writeoffs = Some(flag_value(&arg, args.next())?.parse()?),
+                "--settlement-delays" => {
+                    options.settlement_delays = Some(flag_value(&arg, args.next())?)
+                }
+                "--writeoff-report" => {
+                    options.writeoff_report = Some(flag_value(&arg, args.next())?)
+                }
+                "--unique-tids" => {
+                    options.unique_tids = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--lang" => options.lang = Some(flag_value(&arg, args.next())?.parse()?),
+                "--ordering-policy" => {
+                    options.ordering_policy = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--warnings-fd" => {
+                    let value = flag_value(&arg, args.next())?;
+                    // 0 to 2 are stdin, the balances and the human readable log.
+                    let fd = value
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|fd| *fd > 2)
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --warnings-fd {}, expected a file descriptor above 2.",
+                                value
+                            ))
+                        })?;
+                    options.warnings_fd = Some(fd);
+                }
+                "--warnings-out" => options.warnings_out = Some(flag_value(&arg, args.next())?),
+                "--upsert" => options.upsert = Some(flag_value(&arg, args.next())?),
+                "--serve" => options.serve = Some(flag_value(&arg, args.next())?),
+                "--stdin" => options.stdin = true,
+                "--control" => options.control = Some(flag_value(&arg, args.next())?),
+                "--policy" => options.policy = Some(flag_value(&arg, args.next())?),
+                "--cron" => options.cron = Some(flag_value(&arg, args.next())?.parse()?),
+                "--job" => options.job = Some(flag_value(&arg, args.next())?),
+                "--old" => options.old = Some(flag_value(&arg, args.next())?),
+                "--new" => options.new = Some(flag_value(&arg, args.next())?),
+                "--assert" => options.assert.push(flag_value(&arg, args.next())?),
+                "--assertions" => options.assertions = Some(flag_value(&arg, args.next())?),
+                "--report-by-source" => {
+                    options.report_by_source = Some(flag_value(&arg, args.next())?)
+                }
+                "--settlement-report" => {
+                    options.settlement_report = Some(flag_value(&arg, args.next())?)
+                }
+                "--rollforward" => {
+                    options.rollforward = Some(flag_value(&arg, args.next())?.parse()?)
+                }
+                "--rollforward-out" => {
+                    options.rollforward_out = Some(flag_value(&arg, args.next())?)
+                }
+                "--parse-threads" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.parse_threads = Some(match value.as_str() {
+                        "auto" => std::thread::available_parallelism()?.get(),
+                        _ => positive(&arg, &value, "thread count")?,
+                    });
+                }
+                "--threads" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.threads = Some(match value.as_str() {
+                        "auto" => std::thread::available_parallelism()?.get(),
+                        _ => positive(&arg, &value, "thread count")?,
+                    });
+                }
+                "--read-only" => options.read_only = true,
+                "--emit-every" => {
+                    let value = flag_value(&arg, args.next())?;
+                    // Underscores allowed, 1_000_000 reads better than 1000000.
+                    let every = positive(&arg, &value.replace('_', ""), "row count")?;
+                    options.emit_every = Some(every as u64);
+                }
+                "--disputes-file" => options.disputes_file = Some(flag_value(&arg, args.next())?),
+                "--authorizations" => options.authorizations = Some(flag_value(&arg, args.next())?),
+                "--output-filter" => options
+                    .output_filter
+                    .push(flag_value(&arg, args.next())?.parse()?),
+                "--out" | "-o" => options.out = Some(flag_value(&arg, args.next())?),
+                "--preserve-client" => {
+                    options.preserve_client = Some(client_id(&arg, flag_value(&arg, args.next())?)?)
+                }
+                "--preserve-failure" => options.preserve_failure = true,
+                // Only client-mod for now, the key comes first so others can follow.
+                "--by" => {
+                    let by = flag_value(&arg, args.next())?;
+                    if by != "client-mod" {
+                        return Err(BasicError::new(format!(
+                            "Unknown --by {}, expected client-mod <n>.",
+                            by
+                        )));
+                    }
+                    let value = flag_value("--by client-mod", args.next())?;
+                    options.client_mod = Some(positive("--by client-mod", &value, "shard count")?);
+                }
+                "--perturb" => {
+                    let value = flag_value(&arg, args.next())?;
+                    let percent = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|percent| (0.0..100.0).contains(percent))
+                        .ok_or_else(|| {
+                            BasicError::new(format!(
+                                "Invalid --perturb {}, expected a percentage from 0 up to 100.",
+                                value
+                            ))
+                        })?;
+                    options.perturb = Some(percent);
+                }
+                "--emit-dir" => options.emit_dir = Some(flag_value(&arg, args.next())?),
+                "--out-dir" => options.out_dir = Some(flag_value(&arg, args.next())?),
+                "--read-buffer" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.read_buffer = Some(positive(&arg, &value, "byte count")?);
+                }
+                "--batch-size" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.batch_size = Some(positive(&arg, &value, "row count")?);
+                }
+                "--channel-capacity" => {
+                    let value = flag_value(&arg, args.next())?;
+                    options.channel_capacity = Some(positive(&arg, &value, "batch count")?);
+                }
+                flag if flag.starts_with("--") => {
+                    return Err(BasicError::new(format!(
+                        "Unknown flag {}. {}",
+                        flag,
+                        options.usage()
+                    )));
+                }
+                _ if input.is_none() => input = Some(arg),
+                _ => options.more_inputs.push(arg),
+            }
+        }
+        if let (Some(arg), false) = (options.more_inputs.first(), options.disjoint_files) {
+            return Err(BasicError::new(format!(
+                "Unexpected argument {}. {}",
+                arg,
+                options.usage()
+            )));
+        }
+
+        let writes = options.writes();
+        if options.read_only && !writes.is_empty() {
+            return Err(BasicError::new(format!(
+                "--read-only refuses flags writing outside stdout and stderr: {}. {}",
+                writes.join(", "),
+                options.usage()
+            )));
+        }
+
+        let soak_only = Options {
+            command: Command::Soak,
+            duration: options.duration,
+            tps: options.tps,
+            seed: options.seed,
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Soak {
+            if input.is_some() || options != soak_only {
+                return Err(BasicError::new(format!(
+                    "soak takes no input file, only --duration, --tps and --seed. {}",
+                    options.usage()
+                )));
+            }
+            if options.duration.is_none() || options.tps.is_none() {
+                return Err(BasicError::new(format!(
+                    "soak requires --duration and --tps. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        let schedule_only = Options {
+            command: Command::Schedule,
+            cron: options.cron.clone(),
+            job: options.job.clone(),
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Schedule {
+            if input.is_some() || options != schedule_only {
+                return Err(BasicError::new(format!(
+                    "schedule takes no input file, only --cron and --job. {}",
+                    options.usage()
+                )));
+            }
+            if options.cron.is_none() || options.job.is_none() {
+                return Err(BasicError::new(format!(
+                    "schedule requires --cron and --job. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.cron.is_some() || options.job.is_some() {
+            return Err(BasicError::new(format!(
+                "--cron and --job are only valid with schedule. {}",
+                options.usage()
+            )));
+        }
+        let converge_only = Options {
+            command: Command::ConvergeTest,
+            replicas: options.replicas,
+            seed: options.seed,
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::ConvergeTest {
+            if input.is_some() || options != converge_only {
+                return Err(BasicError::new(format!(
+                    "converge-test takes no input file, only --replicas and --seed. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.replicas.is_some() {
+            return Err(BasicError::new(format!(
+                "--replicas is only valid with converge-test. {}",
+                options.usage()
+            )));
+        }
+        if options.command == Command::DeterminismCheck {
+            let check_only = Options {
+                command: Command::DeterminismCheck,
+                read_only: options.read_only,
+                ..Options::default()
+            };
+            if input.is_some() || options != check_only {
+                return Err(BasicError::new(format!(
+                    "determinism-check takes no input file or flags. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.duration.is_some() || options.tps.is_some() {
+            return Err(BasicError::new(format!(
+                "--duration and --tps are only valid with soak. {}",
+                options.usage()
+            )));
+        }
+
+        if options.chargeback_fee_payer.is_some() && options.chargeback_fee.is_none() {
+            return Err(BasicError::new(format!(
+                "--chargeback-fee-payer is only valid with --chargeback-fee. {}",
+                options.usage()
+            )));
+        }
+
+        if options.writeoff_report.is_some() && options.writeoffs.is_none() {
+            return Err(BasicError::new(format!(
+                "--writeoff-report is only valid with --writeoffs. {}",
+                options.usage()
+            )));
+        }
+
+        // Only the policy flags, there is no input file.
+        let policy_only = Options {
+            command: Command::Policy,
+            policy_action: options.policy_action,
+            policy: options.policy.clone(),
+            overdraft_rate: options.overdraft_rate,
+            cutoff_every: options.cutoff_every,
+            max_amount: options.max_amount,
+            max_open_disputes: options.max_open_disputes,
+            dispute_overflow: options.dispute_overflow,
+            lock_after_chargebacks: options.lock_after_chargebacks,
+            lock_after_chargeback_amount: options.lock_after_chargeback_amount,
+            locked_policy: options.locked_policy,
+            dispute_policy: options.dispute_policy,
+            chargeback_fee: options.chargeback_fee,
+            chargeback_fee_payer: options.chargeback_fee_payer,
+            writeoffs: options.writeoffs,
+            unique_tids: options.unique_tids,
+            retain_history: options.retain_history,
+            ordering_policy: options.ordering_policy,
+            assets: options.assets.clone(),
+            tx_types: options.tx_types.clone(),
+            categorize: options.categorize.clone(),
+            settlement_delays: options.settlement_delays.clone(),
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Policy {
+            if input.is_some() || options != policy_only {
+                return Err(BasicError::new(format!(
+                    "policy takes a policy file and the flags it can hold, --overdraft-rate, --cutoff-every, --max-amount, --max-open-disputes, --dispute-overflow, --lock-after-chargebacks, --lock-after-chargeback-amount, --locked-policy, --dispute-policy, --chargeback-fee, --chargeback-fee-payer, --writeoffs, --unique-tids, --retain-history, --ordering-policy, --assets, --tx-types, --categorize and --settlement-delays. {}",
+                    options.usage()
+                )));
+            }
+            if options.dispute_overflow.is_some() && options.max_open_disputes.is_none() {
+                return Err(BasicError::new(format!(
+                    "--dispute-overflow is only valid with --max-open-disputes. {}",
+                    options.usage()
+                )));
+            }
+            if options.cutoff_every.is_some() && options.overdraft_rate.is_none() {
+                return Err(BasicError::new(format!(
+                    "--cutoff-every is only valid with --overdraft-rate. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+
+        if options.history_cache.is_some() && options.history_backend != Some(HistoryBackend::Disk)
+        {
+            return Err(BasicError::new(format!(
+                "--history-cache is only valid with --history-backend disk. {}",
+                options.usage()
+            )));
+        }
+
+        // Only the flags shaping the engine, there is no input file and the run never ends.
+        let serve_only = Options {
+            serve: options.serve.clone(),
+            stdin: options.stdin,
+            clients: options.clients.clone(),
+            overdraft_rate: options.overdraft_rate,
+            cutoff_every: options.cutoff_every,
+            assets: options.assets.clone(),
+            tx_types: options.tx_types.clone(),
+            max_open_disputes: options.max_open_disputes,
+            dispute_overflow: options.dispute_overflow,
+            lock_after_chargebacks: options.lock_after_chargebacks,
+            lock_after_chargeback_amount: options.lock_after_chargeback_amount,
+            locked_policy: options.locked_policy,
+            dispute_policy: options.dispute_policy,
+            chargeback_fee: options.chargeback_fee,
+            chargeback_fee_payer: options.chargeback_fee_payer,
+            writeoffs: options.writeoffs,
+            settlement_delays: options.settlement_delays.clone(),
+            unique_tids: options.unique_tids,
+            retain_history: options.retain_history,
+            lang: options.lang,
+            read_only: options.read_only,
+            policy: options.policy.clone(),
+            history_backend: options.history_backend,
+            history_cache: options.history_cache,
+            latency_budget: options.latency_budget,
+            control: options.control.clone(),
+            ..Options::default()
+        };
+        if options.serve.is_some() || options.stdin {
+            if input.is_some() || options != serve_only {
+                return Err(BasicError::new(format!(
+                    "--serve and --stdin take no input file, only --clients, --overdraft-rate, --cutoff-every, --assets, --tx-types, --max-open-disputes, --dispute-overflow, --lock-after-chargebacks, --lock-after-chargeback-amount, --locked-policy, --dispute-policy, --chargeback-fee, --chargeback-fee-payer, --writeoffs, --settlement-delays, --unique-tids, --retain-history, --lang, --policy, --history-backend, --history-cache, --latency-budget and --control. {}",
+                    options.usage()
+                )));
+            }
+            if options.serve.is_some() && options.stdin {
+                return Err(BasicError::new(format!(
+                    "--serve and --stdin can't be given together. {}",
+                    options.usage()
+                )));
+            }
+            if options.dispute_overflow.is_some() && options.max_open_disputes.is_none() {
+                return Err(BasicError::new(format!(
+                    "--dispute-overflow is only valid with --max-open-disputes. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        let pipe_only = Options {
+            command: Command::Pipe,
+            state_every: options.state_every,
+            ..serve_only
+        };
+        if options.command == Command::Pipe {
+            if input.is_some() || options != pipe_only {
+                return Err(BasicError::new(format!(
+                    "pipe takes no input file, only the flags --stdin takes and --state-every. {}",
+                    options.usage()
+                )));
+            }
+            if options.dispute_overflow.is_some() && options.max_open_disputes.is_none() {
+                return Err(BasicError::new(format!(
+                    "--dispute-overflow is only valid with --max-open-disputes. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.state_every.is_some() {
+            return Err(BasicError::new(format!(
+                "--state-every is only valid with pipe. {}",
+                options.usage()
+            )));
+        }
+        let ctl_only = Options {
+            command: Command::Ctl,
+            ctl: options.ctl.clone(),
+            control: options.control.clone(),
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Ctl {
+            if input.is_some() || options != ctl_only {
+                return Err(BasicError::new(format!(
+                    "ctl only takes its request and --control. {}",
+                    options.usage()
+                )));
+            }
+            if options.control.is_none() {
+                return Err(BasicError::new(format!(
+                    "ctl requires --control <socket>. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.control.is_some() {
+            return Err(BasicError::new(format!(
+                "--control is only valid with --serve, --stdin, pipe or ctl. {}",
+                options.usage()
+            )));
+        }
+
+        // Rows are only timed one at a time as a server answers them.
+        if options.latency_budget.is_some() {
+            return Err(BasicError::new(format!(
+                "--latency-budget is only valid with --serve, --stdin or pipe. {}",
+                options.usage()
+            )));
+        }
+
+        options.input = input.ok_or_else(|| {
+            BasicError::new(format!(
+                "Input path is required but missing. This must specify a path to the input csv file. {}",
+                options.usage()
+            ))
+        })?;
+
+        if options.layout.is_some() != (options.input_format == Some(InputFormat::FixedWidth)) {
+            return Err(BasicError::new(format!(
+                "--input-format fixed-width and --layout go together, the layout says where each field is. {}",
+                options.usage()
+            )));
+        }
+
+        // Other formats are converted as they are read, there is no csv file to seek into or follow.
+        if InputFormat::of(&options.input, options.input_format) != InputFormat::Csv
+            && (options.parse_threads.is_some() || options.follow)
+        {
+            return Err(BasicError::new(format!(
+                "--parse-threads and --follow only read csv inputs. {}",
+                options.usage()
+            )));
+        }
+        if InputFormat::of(&options.input, options.input_format) != InputFormat::Csv
+            && options.command == Command::Estimate
+        {
+            return Err(BasicError::new(format!(
+                "estimate only samples csv inputs. {}",
+                options.usage()
+            )));
+        }
+
+        // Only flags acting on one client at a time, the others look across clients or need every
+        // row in input order, see shard::Shards.
+        let threads_only = Options {
+            command: Command::Process,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            clients: options.clients.clone(),
+            overdraft_rate: options.overdraft_rate,
+            assets: options.assets.clone(),
+            tx_types: options.tx_types.clone(),
+            max_open_disputes: options.max_open_disputes,
+            dispute_overflow: options.dispute_overflow,
+            lock_after_chargebacks: options.lock_after_chargebacks,
+            lock_after_chargeback_amount: options.lock_after_chargeback_amount,
+            locked_policy: options.locked_policy,
+            dispute_policy: options.dispute_policy,
+            chargeback_fee: options.chargeback_fee,
+            chargeback_fee_payer: options.chargeback_fee_payer,
+            writeoffs: options.writeoffs,
+            // Each engine only sees its own clients' ids.
+            unique_tids: options
+                .unique_tids
+                .filter(|scope| *scope == TidScope::Client),
+            retain_history: options.retain_history,
+            ordering_policy: options.ordering_policy,
+            lang: options.lang,
+            read_buffer: options.read_buffer,
+            read_only: options.read_only,
+            threads: options.threads,
+            policy: options.policy.clone(),
+            output_filter: options.output_filter.clone(),
+            include_archived: options.include_archived,
+            strict: options.strict,
+            output: options.output.clone(),
+            output_format: options.output_format,
+            columns: options.columns.clone(),
+            fixups: options.fixups.clone(),
+            ..Options::default()
+        };
+        if options.threads.is_some() && options != threads_only {
+            return Err(BasicError::new(format!(
+                "--threads only takes an input file, --schema, --input-format, --layout, --clients, --overdraft-rate, --assets, --tx-types, --max-open-disputes, --dispute-overflow, --lock-after-chargebacks, --lock-after-chargeback-amount, --locked-policy, --dispute-policy, --chargeback-fee, --chargeback-fee-payer, --writeoffs, --unique-tids client, --retain-history, --ordering-policy, --lang, --read-buffer, --policy, --output-filter, --include-archived, --strict, --output, --output-format, --columns and --fixups. {}",
+                options.usage()
+            )));
+        }
+        // Each file gets an engine of its own, so the same flags as --threads, besides --threads.
+        let disjoint_only = Options {
+            threads: None,
+            disjoint_files: true,
+            more_inputs: options.more_inputs.clone(),
+            ..threads_only
+        };
+        if options.disjoint_files && options != disjoint_only {
+            return Err(BasicError::new(format!(
+                "--disjoint-files takes the same flags as --threads, not --threads itself. {}",
+                options.usage()
+            )));
+        }
+
+        if options.dispute_overflow.is_some() && options.max_open_disputes.is_none() {
+            return Err(BasicError::new(format!(
+                "--dispute-overflow is only valid with --max-open-disputes. {}",
+                options.usage()
+            )));
+        }
+
+        if options.warnings_fd.is_some() && options.warnings_out.is_some() {
+            return Err(BasicError::new(format!(
+                "--warnings-fd and --warnings-out can't be given together. {}",
+                options.usage()
+            )));
+        }
+
+        if options.snapshot_in.is_some() && options.balances.is_some() {
+            return Err(BasicError::new(format!(
+                "--snapshot-in and bootstrap --balances can't be given together, both set the opening state. {}",
+                options.usage()
+            )));
+        }
+
+        // Nothing of a rejected file may be written, these write out rows as they apply.
+        if options.atomic_per_file
+            && (options.cdc_out.is_some()
+                || options.emit_every.is_some()
+                || options.sample_out.is_some()
+                || options.retry_out.is_some())
+        {
+            return Err(BasicError::new(format!(
+                "--atomic-per-file can't be given with --cdc-out, --emit-every, --sample-out or --retry-out, they write rows out as they apply. {}",
+                options.usage()
+            )));
+        }
+
+        if options.tolerance.is_some() && options.closing_balances.is_none() {
+            return Err(BasicError::new(format!(
+                "--tolerance is only valid with --closing-balances. {}",
+                options.usage()
+            )));
+        }
+
+        if options.cutoff_every.is_some() && options.overdraft_rate.is_none() {
+            return Err(BasicError::new(format!(
+                "--cutoff-every is only valid with --overdraft-rate. {}",
+                options.usage()
+            )));
+        }
+
+        let screen_only = Options {
+            command: Command::Screen,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Screen && options != screen_only {
+            return Err(BasicError::new(format!(
+                "screen only takes an input file, --schema, --input-format and --layout. {}",
+                options.usage()
+            )));
+        }
+        let activity_only = Options {
+            command: Command::Activity,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Activity && options != activity_only {
+            return Err(BasicError::new(format!(
+                "activity only takes an input file, --schema, --input-format and --layout. {}",
+                options.usage()
+            )));
+        }
+        let estimate_only = Options {
+            command: Command::Estimate,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Estimate && options != estimate_only {
+            return Err(BasicError::new(format!(
+                "estimate only takes an input file and --schema. {}",
+                options.usage()
+            )));
+        }
+
+        let compare_only = Options {
+            command: Command::CompareVersions,
+            input: options.input.clone(),
+            schema: options.schema,
+            read_only: options.read_only,
+            old: options.old.clone(),
+            new: options.new.clone(),
+            ..Options::default()
+        };
+        if options.command == Command::CompareVersions {
+            if options != compare_only || options.old.is_none() || options.new.is_none() {
+                return Err(BasicError::new(format!(
+                    "compare-versions requires --old and --new, and only takes --schema besides. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.old.is_some() || options.new.is_some() {
+            return Err(BasicError::new(format!(
+                "--old and --new are only valid with compare-versions. {}",
+                options.usage()
+            )));
+        }
+
+        let statements_only = Options {
+            command: options.command,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            read_only: options.read_only,
+            out_dir: options.out_dir.clone(),
+            clients: options.clients.clone(),
+            ..Options::default()
+        };
+        if options.command == Command::Statements {
+            if options != statements_only || options.out_dir.is_none() {
+                return Err(BasicError::new(format!(
+                    "statements requires --out-dir, and only takes --clients, --schema, --input-format and --layout besides. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        // Joint owners' rows go in their canonical client's trail, so audit takes --clients too.
+        if options.command == Command::Audit {
+            if options != statements_only || options.out_dir.is_none() {
+                return Err(BasicError::new(format!(
+                    "audit requires --out-dir, and only takes --clients, --schema, --input-format and --layout besides. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.out_dir.is_some() {
+            return Err(BasicError::new(format!(
+                "--out-dir is only valid with statements and audit. {}",
+                options.usage()
+            )));
+        }
+
+        let profile_only = Options {
+            command: Command::Profile,
+            profile_out: options.profile_out.clone(),
+            ..screen_only
+        };
+        if options.command == Command::Profile && options != profile_only {
+            return Err(BasicError::new(format!(
+                "profile only takes an input file, --schema, --input-format, --layout and --profile-out. {}",
+                options.usage()
+            )));
+        }
+        if options.profile_out.is_some() && options.command != Command::Profile {
+            return Err(BasicError::new(format!(
+                "--profile-out is only valid with profile. {}",
+                options.usage()
+            )));
+        }
+
+        let tui_only = Options {
+            command: Command::Tui,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            follow: options.follow,
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Tui && options != tui_only {
+            return Err(BasicError::new(format!(
+                "tui only takes an input file, --follow, --schema, --input-format and --layout. {}",
+                options.usage()
+            )));
+        }
+        if options.follow && options.command != Command::Tui {
+            return Err(BasicError::new(format!(
+                "--follow is only valid with tui. {}",
+                options.usage()
+            )));
+        }
+
+        let explain_only = Options {
+            command: Command::Explain,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            tx: options.tx,
+            client: options.client,
+            format: options.format,
+            ordering_policy: options.ordering_policy,
+            read_only: options.read_only,
+            ..Options::default()
+        };
+        if options.command == Command::Explain
+            && (options != explain_only || options.tx.is_some() == options.client.is_some())
+        {
+            return Err(BasicError::new(format!(
+                "explain takes an input file, one of --tx or --client, --schema, --input-format, --layout, --format and --ordering-policy. {}",
+                options.usage()
+            )));
+        }
+        if (options.tx.is_some() || options.format.is_some()) && options.command != Command::Explain
+        {
+            return Err(BasicError::new(format!(
+                "--tx and --format are only valid with explain. {}",
+                options.usage()
+            )));
+        }
+
+        if (options.batch_size.is_some() || options.channel_capacity.is_some())
+            && options.parse_threads.is_none()
+        {
+            return Err(BasicError::new(format!(
+                "--batch-size and --channel-capacity are only valid with --parse-threads. {}",
+                options.usage()
+            )));
+        }
+
+        if options.anomaly_threshold.is_some() && options.anomalies.is_none() {
+            return Err(BasicError::new(format!(
+                "--anomaly-threshold is only valid with --anomalies. {}",
+                options.usage()
+            )));
+        }
+
+        if options.conservation_check && options.tx_types.is_some() {
+            return Err(BasicError::new(format!(
+                "--conservation-check can't check the custom types of --tx-types. {}",
+                options.usage()
+            )));
+        }
+
+        // It looks up the transaction a row references before the engine would bring it back.
+        if options.conservation_check && options.history_backend == Some(HistoryBackend::Disk) {
+            return Err(BasicError::new(format!(
+                "--conservation-check needs the whole history in memory, it can't be given with --history-backend disk. {}",
+                options.usage()
+            )));
+        }
+
+        if options.progress_every.is_some() && !options.stats {
+            return Err(BasicError::new(format!(
+                "--progress-every is only valid with --stats. {}",
+                options.usage()
+            )));
+        }
+
+        if options.emit_every.is_some() != options.emit_dir.is_some() {
+            return Err(BasicError::new(format!(
+                "--emit-every and --emit-dir must be given together. {}",
+                options.usage()
+            )));
+        }
+
+        let split_only = Options {
+            command: Command::Split,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            read_only: options.read_only,
+            out: options.out.clone(),
+            client_mod: options.client_mod,
+            clients: options.clients.clone(),
+            ..Options::default()
+        };
+        if options.command == Command::Split {
+            if options != split_only || options.out.is_none() || options.client_mod.is_none() {
+                return Err(BasicError::new(format!(
+                    "split requires --by and --out, and only takes --clients, --schema, --input-format and --layout besides. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.client_mod.is_some() {
+            return Err(BasicError::new(format!(
+                "--by is only valid with split. {}",
+                options.usage()
+            )));
+        }
+
+        let shrink_only = Options {
+            command: Command::Shrink,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            read_only: options.read_only,
+            out: options.out.clone(),
+            preserve_client: options.preserve_client,
+            preserve_failure: options.preserve_failure,
+            conservation_check: options.conservation_check,
+            clients: options.clients.clone(),
+            overdraft_rate: options.overdraft_rate,
+            cutoff_every: options.cutoff_every,
+            assets: options.assets.clone(),
+            tx_types: options.tx_types.clone(),
+            max_open_disputes: options.max_open_disputes,
+            dispute_overflow: options.dispute_overflow,
+            lock_after_chargebacks: options.lock_after_chargebacks,
+            lock_after_chargeback_amount: options.lock_after_chargeback_amount,
+            locked_policy: options.locked_policy,
+            dispute_policy: options.dispute_policy,
+            chargeback_fee: options.chargeback_fee,
+            chargeback_fee_payer: options.chargeback_fee_payer,
+            writeoffs: options.writeoffs,
+            settlement_delays: options.settlement_delays.clone(),
+            unique_tids: options.unique_tids,
+            retain_history: options.retain_history,
+            lang: options.lang,
+            policy: options.policy.clone(),
+            history_backend: options.history_backend,
+            history_cache: options.history_cache,
+            latency_budget: options.latency_budget,
+            ..Options::default()
+        };
+        if options.command == Command::Shrink {
+            if options != shrink_only
+                || options.out.is_none()
+                || !(options.preserve_failure || options.preserve_client.is_some())
+            {
+                return Err(BasicError::new(format!(
+                    "shrink requires --out and --preserve-failure, --preserve-client or both, and only takes --schema, --input-format, --layout, --conservation-check, --clients, --overdraft-rate, --cutoff-every, --assets, --tx-types, --max-open-disputes, --dispute-overflow, --lock-after-chargebacks, --lock-after-chargeback-amount, --locked-policy, --dispute-policy, --chargeback-fee, --chargeback-fee-payer, --writeoffs, --settlement-delays, --unique-tids, --retain-history, --lang, --policy, --history-backend, --history-cache and --latency-budget besides. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.preserve_client.is_some() || options.preserve_failure {
+            return Err(BasicError::new(format!(
+                "--preserve-client and --preserve-failure are only valid with shrink. {}",
+                options.usage()
+            )));
+        }
+
+        let anonymize_only = Options {
+            command: Command::Anonymize,
+            input: options.input.clone(),
+            schema: options.schema,
+            input_format: options.input_format,
+            layout: options.layout.clone(),
+            read_only: options.read_only,
+            out: options.out.clone(),
+            seed: options.seed,
+            perturb: options.perturb,
+            sample: options.sample,
+            ..Options::default()
+        };
+        if options.command == Command::Anonymize {
+            if options != anonymize_only || options.out.is_none() || options.seed.is_none() {
+                return Err(BasicError::new(format!(
+                    "anonymize requires --out and --seed, and only takes --perturb, --sample, --schema, --input-format and --layout besides. {}",
+                    options.usage()
+                )));
+            }
+            return Ok(options);
+        }
+        if options.out.is_some() || options.perturb.is_some() {
+            return Err(BasicError::new(format!(
+                "--out is only valid with anonymize, split and shrink, --perturb with anonymize. {}",
+                options.usage()
+            )));
+        }
+
+        if options.sample.is_some() != options.sample_out.is_some() {
+            return Err(BasicError::new(format!(
+                "--sample and --sample-out must be given together. {}",
+                options.usage()
+            )));
+        }
+
+        if options.rollforward.is_some() != options.rollforward_out.is_some() {
+            return Err(BasicError::new(format!(
+                "--rollforward and --rollforward-out must be given together. {}",
+                options.usage()
+            )));
+        }
+
+        if options.seed.is_some() && options.sample.is_none() {
+            return Err(BasicError::new(format!(
+                "--seed is only valid with --sample, soak and converge-test. {}",
+                options.usage()
+            )));
+        }
+
+        if (options.command == Command::MergeClients) != options.map.is_some() {
+            return Err(BasicError::new(format!(
+                "merge-clients requires --map, and --map is only valid with merge-clients. {}",
+                options.usage()
+            )));
+        }
+
+        let split_flags = [
+            options.client.is_some(),
+            options.split_txs.is_some(),
+            options.split_into.is_some(),
+        ];
+        let split_valid = match options.command {
+            Command::SplitClient => split_flags.iter().all(|set| *set),
+            // explain --client was checked above.
+            Command::Explain => !split_flags[1..].iter().any(|set| *set),
+            _ => !split_flags.iter().any(|set| *set),
+        };
+        if !split_valid {
+            return Err(BasicError::new(format!(
+                "split-client requires --client, --txs and --into, which are only valid with split-client. {}",
+                options.usage()
+            )));
+        }
+
+        match (options.command, &options.balances) {
+            (Command::Bootstrap, None) => Err(BasicError::new(format!(
+                "bootstrap requires --balances. {}",
+                options.usage()
+            ))),
+            (command, Some(_)) if command != Command::Bootstrap => Err(BasicError::new(format!(
+                "--balances is only valid with bootstrap. {}",
+                options.usage()
+            ))),
+            _ => Ok(options),
+        }
+    }
+
+    // The usage of the command given, rather than that of every command.
+    pub fn usage(&self) -> String {
+        let mut lines = Vec::new();
+        if self.serve.is_some() || self.stdin {
+            lines.push(SERVE_USAGE);
+        } else {
+            lines.extend(
+                USAGE
+                    .iter()
+                    .filter(|(commands, _)| commands.contains(&self.command))
+                    .map(|(_, line)| *line),
+            );
+            if matches!(self.command, Command::Pipe | Command::Shrink) {
+                lines.push(SERVE_USAGE);
+            }
+        }
+        format!("usage: {}. {}", lines.join(" | "), EVERY_COMMAND)
+    }
+
+    // Flags making the run write anything besides stdout and stderr.
+    fn writes(&self) -> Vec<&'static str> {
+        [
+            ("--cdc-out", self.cdc_out.is_some()),
+            ("--sample-out", self.sample_out.is_some()),
+            ("--category-report", self.category_report.is_some()),
+            ("--anomalies", self.anomalies.is_some()),
+            ("--graph-out", self.graph_out.is_some()),
+            ("--retry-out", self.retry_out.is_some()),
+            ("--errors", self.errors.is_some()),
+            ("--snapshot-out", self.snapshot_out.is_some()),
+            ("--settlement-report", self.settlement_report.is_some()),
+            ("--rollforward-out", self.rollforward_out.is_some()),
+            ("--writeoff-report", self.writeoff_report.is_some()),
+            ("--report-by-source", self.report_by_source.is_some()),
+            ("--audit-out", self.audit_out.is_some()),
+            ("--precision-report", self.precision_report.is_some()),
+            ("--output", self.output.is_some()),
+            ("--manifest", self.manifest.is_some()),
+            ("--profile-out", self.profile_out.is_some()),
+            ("--emit-dir", self.emit_dir.is_some()),
+            ("--warnings-out", self.warnings_out.is_some()),
+            ("--out", self.out.is_some()),
+            ("--redis", self.redis.is_some()),
+            ("--upsert", self.upsert.is_some()),
+            ("--out-dir", self.out_dir.is_some()),
+            // Creates the socket, which ctl can then have snapshots written through.
+            (
+                "--control",
+                self.control.is_some() && self.command != Command::Ctl,
+            ),
+            (
+                "ctl snapshot",
+                matches!(self.ctl, Some(Request::Snapshot(_))),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(flag, set)| set.then_some(flag))
+        .collect()
+    }
+}
+
+fn flag_value(flag: &str, value: Option<String>) -> Result<String, Box<dyn Error>> {
+    Ok(value.ok_or_else(|| BasicError::new(format!("Flag {} requires a value.", flag)))?)
+}
+
+fn positive(flag: &str, value: &str, expected: &str) -> Result<usize, Box<dyn Error>> {
+    Ok(value
+        .parse::<usize>()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| {
+            BasicError::new(format!(
+                "Invalid {} {}, expected a positive {}.",
+                flag, value, expected
+            ))
+        })?)
+}
+
+fn client_id(flag: &str, value: String) -> Result<ClientId, Box<dyn Error>> {
+    Ok(ClientId(value.parse::<u16>().map_err(|_| {
+        BasicError::new(format!("Invalid {} client id {}.", flag, value))
+    })?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("txcli")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn input_only() {
+        let options = Options::parse(args(&["in.csv"])).unwrap();
+        assert_eq!(options.input, "in.csv");
+        assert_eq!(options.redis, None);
+    }
+
+    #[test]
+    fn redis_flag() {
+        let options = Options::parse(args(&["--redis", "redis://localhost", "in.csv"])).unwrap();
+        assert_eq!(options.input, "in.csv");
+        assert_eq!(options.redis.as_deref(), Some("redis://localhost"));
+    }
+
+    #[test]
+    fn upsert_flag() {
+        let options = Options::parse(args(&["in.csv", "--upsert", "balances.csv"])).unwrap();
+        assert_eq!(options.upsert.as_deref(), Some("balances.csv"));
+        assert!(Options::parse(args(&["in.csv", "--upsert", "b.csv", "--read-only"])).is_err());
+        assert!(Options::parse(args(&["tui", "in.csv", "--upsert", "b.csv"])).is_err());
+    }
+
+    #[test]
+    fn serve_flags() {
+        let options = Options::parse(args(&[
+            "--serve",
+            "127.0.0.1:7070",
+            "--overdraft-rate",
+            "0.01",
+        ]))
+        .unwrap();
+        assert_eq!(options.serve.as_deref(), Some("127.0.0.1:7070"));
+        assert!(
+            Options::parse(args(&["--stdin", "--retain-history", "last-10"]))
+                .unwrap()
+                .stdin
+        );
+        assert!(Options::parse(args(&["--stdin", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["--stdin", "--serve", "127.0.0.1:7070"])).is_err());
+        assert!(Options::parse(args(&["--stdin", "--manifest", "run.json"])).is_err());
+        assert!(Options::parse(args(&["--stdin", "--dispute-overflow", "queue"])).is_err());
+    }
+
+    #[test]
+    fn latency_budget_flag() {
+        let options = Options::parse(args(&[
+            "--stdin",
+            "--latency-budget",
+            "2.5",
+            "--history-backend",
+            "disk",
+        ]))
+        .unwrap();
+        assert_eq!(options.latency_budget, Some(Duration::from_micros(2500)));
+        assert_eq!(options.history_backend, Some(HistoryBackend::Disk));
+        assert!(Options::parse(args(&["--stdin", "--latency-budget", "0"])).is_err());
+        assert!(Options::parse(args(&["--stdin", "--history-cache", "10"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--latency-budget", "5"])).is_err());
+    }
+
+    #[test]
+    fn control_socket() {
+        let options = Options::parse(args(&["pipe", "--control", "/run/txcli.sock"])).unwrap();
+        assert_eq!(options.control.as_deref(), Some("/run/txcli.sock"));
+        assert!(Options::parse(args(&["--stdin", "--control", "t.sock", "--read-only"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--control", "t.sock"])).is_err());
+
+        let options = Options::parse(args(&["ctl", "pause", "--control", "t.sock"])).unwrap();
+        assert_eq!(options.command, Command::Ctl);
+        assert_eq!(options.ctl, Some(Request::Pause));
+        let options = Options::parse(args(&[
+            "ctl",
+            "snapshot",
+            "state.json",
+            "--control",
+            "t.sock",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.ctl,
+            Some(Request::Snapshot("state.json".to_string()))
+        );
+        assert!(Options::parse(args(&[
+            "ctl",
+            "status",
+            "--control",
+            "t.sock",
+            "--read-only"
+        ]))
+        .is_ok());
+        assert!(Options::parse(args(&["ctl", "pause"])).is_err());
+        assert!(Options::parse(args(&["ctl", "stop", "--control", "t.sock"])).is_err());
+        assert!(Options::parse(args(&["ctl", "snapshot", "--control", "t.sock"])).is_err());
+        assert!(
+            Options::parse(args(&["ctl", "pause", "--control", "t.sock", "--strict"])).is_err()
+        );
+    }
+
+    #[test]
+    fn statements_command() {
+        let options = Options::parse(args(&[
+            "statements",
+            "in.csv",
+            "--out-dir",
+            "statements",
+            "--clients",
+            "clients.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::Statements);
+        assert_eq!(options.out_dir.as_deref(), Some("statements"));
+        assert!(Options::parse(args(&["statements", "in.csv"])).is_err());
+        assert!(Options::parse(args(&[
+            "statements",
+            "in.csv",
+            "--out-dir",
+            "s",
+            "--mem-stats"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&["in.csv", "--out-dir", "s"])).is_err());
+        assert!(Options::parse(args(&[
+            "statements",
+            "in.csv",
+            "--out-dir",
+            "s",
+            "--read-only"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn threads_flag() {
+        let options =
+            Options::parse(args(&["in.csv", "--threads", "4", "--clients", "c.csv"])).unwrap();
+        assert_eq!(options.threads, Some(4));
+        assert!(Options::parse(args(&["in.csv", "--threads", "auto"]))
+            .unwrap()
+            .threads
+            .is_some());
+        assert!(Options::parse(args(&["in.csv", "--threads", "0"])).is_err());
+        assert!(
+            Options::parse(args(&["in.csv", "--threads", "2", "--cutoff-every", "10"])).is_err()
+        );
+        assert!(
+            Options::parse(args(&["in.csv", "--threads", "2", "--manifest", "r.json"])).is_err()
+        );
+        assert!(Options::parse(args(&["tui", "in.csv", "--threads", "2"])).is_err());
+    }
+
+    #[test]
+    fn cdc_flag() {
+        let options = Options::parse(args(&["in.csv", "--cdc-out", "cdc.jsonl"])).unwrap();
+        assert_eq!(options.cdc_out.as_deref(), Some("cdc.jsonl"));
+    }
+
+    #[test]
+    fn manifest_flag() {
+        let options = Options::parse(args(&["in.csv", "--manifest", "run.json"])).unwrap();
+        assert_eq!(options.manifest.as_deref(), Some("run.json"));
+        assert!(Options::parse(args(&["screen", "in.csv", "--manifest", "run.json"])).is_err());
+    }
+
+    #[test]
+    fn retry_flag() {
+        let options = Options::parse(args(&["in.csv", "--retry-out", "retry.csv"])).unwrap();
+        assert_eq!(options.retry_out.as_deref(), Some("retry.csv"));
+    }
+
+    #[test]
+    fn policy_command() {
+        let options =
+            Options::parse(args(&["policy", "explain", "--max-open-disputes", "2"])).unwrap();
+        assert_eq!(options.command, Command::Policy);
+        assert_eq!(options.policy_action, Some(PolicyAction::Explain));
+        assert_eq!(options.max_open_disputes, Some(2));
+        assert!(Options::parse(args(&["policy"])).is_err());
+        assert!(Options::parse(args(&["policy", "check"])).is_err());
+        assert!(Options::parse(args(&["policy", "lint", "--schema", "v1"])).is_err());
+        assert!(Options::parse(args(&["policy", "lint", "--cutoff-every", "5"])).is_err());
+    }
+
+    #[test]
+    fn snapshot_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--snapshot-in",
+            "monday.json",
+            "--snapshot-out",
+            "tuesday.json",
+        ]))
+        .unwrap();
+        assert_eq!(options.snapshot_in.as_deref(), Some("monday.json"));
+        assert_eq!(options.snapshot_out.as_deref(), Some("tuesday.json"));
+        assert!(
+            Options::parse(args(&["in.csv", "--snapshot-in", "s.json", "--read-only"])).is_ok()
+        );
+        assert!(
+            Options::parse(args(&["in.csv", "--snapshot-out", "s.json", "--read-only"])).is_err()
+        );
+        assert!(Options::parse(args(&[
+            "bootstrap",
+            "--balances",
+            "opening.csv",
+            "in.csv",
+            "--snapshot-in",
+            "s.json"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn dispute_policy_flag() {
+        let options =
+            Options::parse(args(&["in.csv", "--dispute-policy", "partial-hold"])).unwrap();
+        assert_eq!(options.dispute_policy, Some(DisputePolicy::PartialHold));
+        assert!(Options::parse(args(&["in.csv", "--dispute-policy", "debt"])).is_err());
+        assert!(Options::parse(args(&["--stdin", "--dispute-policy", "reject"])).is_ok());
+    }
+
+    #[test]
+    fn schedule_command() {
+        let options = Options::parse(args(&[
+            "schedule",
+            "--cron",
+            "0 2 * * *",
+            "--job",
+            "job.toml",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::Schedule);
+        assert_eq!(options.cron.unwrap().to_string(), "0 2 * * *");
+        assert!(Options::parse(args(&["schedule", "--cron", "0 2 * * *"])).is_err());
+        assert!(Options::parse(args(&["schedule", "--cron", "2am", "--job", "j.toml"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--job", "job.toml"])).is_err());
+        assert!(Options::parse(args(&[
+            "schedule",
+            "--cron",
+            "0 2 * * *",
+            "--job",
+            "job.toml",
+            "in.csv"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn config_effective_command() {
+        let options = Options::parse(args(&["config", "effective", "in.csv", "--strict"])).unwrap();
+        assert_eq!(options.command, Command::Config);
+        assert_eq!(options.config_args, ["in.csv", "--strict"]);
+        assert!(Options::parse(args(&["config", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["config"])).is_err());
+    }
+
+    #[test]
+    fn chargeback_fee_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--chargeback-fee",
+            "15",
+            "--chargeback-fee-payer",
+            "counterparty",
+        ]))
+        .unwrap();
+        assert_eq!(options.chargeback_fee, Some(Currency::from_num(15)));
+        assert_eq!(options.chargeback_fee_payer, Some(FeePayer::Counterparty));
+        assert!(Options::parse(args(&["in.csv", "--chargeback-fee", "0"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--chargeback-fee-payer", "client"])).is_err());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--chargeback-fee",
+            "1",
+            "--chargeback-fee-payer",
+            "merchant"
+        ]))
+        .is_err());
+        assert!(
+            Options::parse(args(&["in.csv", "--threads", "2", "--chargeback-fee", "1"])).is_ok()
+        );
+    }
+
+    #[test]
+    fn locked_policy_flag() {
+        let options =
+            Options::parse(args(&["in.csv", "--locked-policy", "reject-withdrawals"])).unwrap();
+        assert_eq!(options.locked_policy, Some(LockedPolicy::RejectWithdrawals));
+        assert!(Options::parse(args(&["in.csv", "--locked-policy", "freeze"])).is_err());
+        assert!(Options::parse(args(&["--stdin", "--locked-policy", "allow"])).is_ok());
+    }
+
+    #[test]
+    fn errors_flag() {
+        let options = Options::parse(args(&["in.csv", "--errors", "rejections.jsonl"])).unwrap();
+        assert_eq!(options.errors.as_deref(), Some("rejections.jsonl"));
+        assert!(Options::parse(args(&["in.csv", "--errors", "e.csv", "--read-only"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--errors", "e.csv", "--threads", "2"])).is_err());
+    }
+
+    #[test]
+    fn assert_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--assert",
+            "client(7).available == 100.0",
+            "--assert",
+            "locked_count <= 3",
+            "--assertions",
+            "expect.txt",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.assert,
+            ["client(7).available == 100.0", "locked_count <= 3"]
+        );
+        assert_eq!(options.assertions.as_deref(), Some("expect.txt"));
+    }
+
+    #[test]
+    fn report_by_source_flag() {
+        let options =
+            Options::parse(args(&["in.csv", "--report-by-source", "sources.csv"])).unwrap();
+        assert_eq!(options.report_by_source.as_deref(), Some("sources.csv"));
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--report-by-source",
+            "sources.csv",
+            "--read-only"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn settlement_flag() {
+        let options =
+            Options::parse(args(&["in.csv", "--settlement-report", "settlement.csv"])).unwrap();
+        assert_eq!(options.settlement_report.as_deref(), Some("settlement.csv"));
+    }
+
+    #[test]
+    fn rollforward_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--rollforward",
+            "monthly",
+            "--rollforward-out",
+            "rollforward.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.rollforward, Some(Period::Monthly));
+        assert!(Options::parse(args(&["in.csv", "--rollforward", "weekly"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--rollforward", "daily"])).is_err());
+    }
+
+    #[test]
+    fn writeoff_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--writeoffs",
+            "weekly",
+            "--writeoff-report",
+            "writeoffs.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.writeoffs, Some(Period::Weekly));
+        assert_eq!(options.writeoff_report.as_deref(), Some("writeoffs.csv"));
+        assert!(Options::parse(args(&["in.csv", "--writeoff-report", "writeoffs.csv"])).is_err());
+        assert!(Options::parse(args(&["--stdin", "--writeoffs", "monthly"])).is_ok());
+        assert!(Options::parse(args(&[
+            "--stdin",
+            "--writeoffs",
+            "monthly",
+            "--writeoff-report",
+            "writeoffs.csv"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn settlement_delays_flag() {
+        let options =
+            Options::parse(args(&["in.csv", "--settlement-delays", "delays.toml"])).unwrap();
+        assert_eq!(options.settlement_delays.as_deref(), Some("delays.toml"));
+        assert!(Options::parse(args(&["--stdin", "--settlement-delays", "delays.toml"])).is_ok());
+        // The delays count rows across every client, which shards don't see.
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--settlement-delays",
+            "delays.toml",
+            "--threads",
+            "4"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn audit_out_flag() {
+        let options = Options::parse(args(&["in.csv", "--audit-out", "audit"])).unwrap();
+        assert_eq!(options.audit_out.as_deref(), Some("audit"));
+        assert!(Options::parse(args(&["in.csv", "--audit-out", "audit", "--read-only"])).is_err());
+    }
+
+    #[test]
+    fn conservation_flag() {
+        assert!(
+            Options::parse(args(&["in.csv", "--conservation-check"]))
+                .unwrap()
+                .conservation_check
+        );
+        assert!(
+            !Options::parse(args(&["in.csv"]))
+                .unwrap()
+                .conservation_check
+        );
+    }
+
+    #[test]
+    fn disjoint_files_flag() {
+        let options = Options::parse(args(&[
+            "a.csv",
+            "b.csv",
+            "--disjoint-files",
+            "c.csv",
+            "--strict",
+        ]))
+        .unwrap();
+        assert_eq!(options.input, "a.csv");
+        assert_eq!(options.more_inputs, ["b.csv", "c.csv"]);
+        assert!(Options::parse(args(&["a.csv", "b.csv"])).is_err());
+        assert!(Options::parse(args(&["a.csv", "--disjoint-files", "--threads", "2"])).is_err());
+        assert!(
+            Options::parse(args(&["a.csv", "--disjoint-files", "--cdc-out", "c.jsonl"])).is_err()
+        );
+        assert!(Options::parse(args(&["screen", "a.csv", "b.csv", "--disjoint-files"])).is_err());
+    }
+
+    #[test]
+    fn atomic_per_file_flag() {
+        assert!(
+            Options::parse(args(&["in.csv", "--atomic-per-file", "--errors", "e.csv"]))
+                .unwrap()
+                .atomic_per_file
+        );
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--atomic-per-file",
+            "--cdc-out",
+            "changes.jsonl"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&["in.csv", "--atomic-per-file", "--threads", "2"])).is_err());
+    }
+
+    #[test]
+    fn tx_types_flag() {
+        let options = Options::parse(args(&["in.csv", "--tx-types", "types.toml"])).unwrap();
+        assert_eq!(options.tx_types.as_deref(), Some("types.toml"));
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--tx-types",
+            "types.toml",
+            "--conservation-check"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&["screen", "in.csv", "--tx-types", "types.toml"])).is_err());
+    }
+
+    #[test]
+    fn retain_history_flag() {
+        let options = Options::parse(args(&["in.csv", "--retain-history", "last-1000"])).unwrap();
+        assert_eq!(options.retain_history, Some(Retention::LastN(1000)));
+        assert!(Options::parse(args(&["in.csv", "--retain-history", "forever"])).is_err());
+    }
+
+    #[test]
+    fn determinism_check_takes_nothing() {
+        let options = Options::parse(args(&["determinism-check"])).unwrap();
+        assert_eq!(options.command, Command::DeterminismCheck);
+        assert!(Options::parse(args(&["determinism-check", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["determinism-check", "--seed", "1"])).is_err());
+    }
+
+    #[test]
+    fn subcommand_names() {
+        assert_eq!(
+            Options::parse(args(&["process", "in.csv"])).unwrap(),
+            Options::parse(args(&["in.csv"])).unwrap()
+        );
+        let options = Options::parse(args(&["serve", "127.0.0.1:7070", "--lang", "fr"])).unwrap();
+        assert_eq!(options.serve.as_deref(), Some("127.0.0.1:7070"));
+        assert!(Options::parse(args(&["serve"])).is_err());
+        let options =
+            Options::parse(args(&["pipe", "--state-every", "50", "--lang", "de"])).unwrap();
+        assert_eq!(options.command, Command::Pipe);
+        assert_eq!(options.state_every, Some(50));
+        assert!(Options::parse(args(&["pipe", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["pipe", "--stdin"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--state-every", "50"])).is_err());
+        let options = Options::parse(args(&["audit", "in.csv", "--out-dir", "audit"])).unwrap();
+        assert_eq!(options.command, Command::Audit);
+        assert!(Options::parse(args(&["audit", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["audit", "in.csv", "--out-dir", "a", "--strict"])).is_err());
+    }
+
+    #[test]
+    fn output_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--output",
+            "balances.json",
+            "--output-format",
+            "json",
+            "--threads",
+            "2",
+        ]))
+        .unwrap();
+        assert_eq!(options.output.as_deref(), Some("balances.json"));
+        assert_eq!(options.output_format, Some(OutputFormat::Json));
+        assert!(Options::parse(args(&["in.csv", "--output-format", "xml"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--output", "b.csv", "--read-only"])).is_err());
+        assert!(Options::parse(args(&["screen", "in.csv", "--output", "b.csv"])).is_err());
+    }
+
+    #[test]
+    fn columns_flag() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--columns",
+            "client,total,locked,dispute_count",
+            "--threads",
+            "2",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.columns,
+            Some(vec!["client", "total", "locked", "dispute_count"])
+        );
+        assert!(Options::parse(args(&["in.csv", "--columns", "client,balance"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--columns", "client,total,client"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--columns", ""])).is_err());
+        assert!(Options::parse(args(&["screen", "in.csv", "--columns", "client"])).is_err());
+    }
+
+    #[test]
+    fn precision_report_flag() {
+        let options =
+            Options::parse(args(&["in.csv", "--precision-report", "precision.csv"])).unwrap();
+        assert_eq!(options.precision_report.as_deref(), Some("precision.csv"));
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--precision-report",
+            "precision.csv",
+            "--threads",
+            "2"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn strict_flag() {
+        assert!(!Options::parse(args(&["in.csv"])).unwrap().strict);
+        assert!(
+            Options::parse(args(&["in.csv", "--strict", "--threads", "2"]))
+                .unwrap()
+                .strict
+        );
+    }
+
+    #[test]
+    fn stats_flags() {
+        let options =
+            Options::parse(args(&["in.csv", "--stats", "--progress-every", "5m"])).unwrap();
+        assert!(options.stats);
+        assert_eq!(
+            options.progress_every,
+            Some(ProgressEvery::Time(Duration::from_secs(300)))
+        );
+        assert!(Options::parse(args(&["in.csv", "--progress-every", "1000"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--stats", "--threads", "2"])).is_err());
+        assert!(Options::parse(args(&["tui", "in.csv", "--stats"])).is_err());
+    }
+
+    #[test]
+    fn fixups_flag() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--fixups",
+            "fixups.toml",
+            "--threads",
+            "2",
+        ]))
+        .unwrap();
+        assert_eq!(options.fixups.as_deref(), Some("fixups.toml"));
+        assert!(Options::parse(args(&["screen", "in.csv", "--fixups", "f.toml"])).is_err());
+    }
+
+    #[test]
+    fn include_archived_flag() {
+        assert!(
+            Options::parse(args(&["in.csv", "--include-archived", "--threads", "2"]))
+                .unwrap()
+                .include_archived
+        );
+    }
+
+    #[test]
+    fn history_backend_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--history-backend",
+            "disk",
+            "--history-cache",
+            "5000",
+        ]))
+        .unwrap();
+        assert_eq!(options.history_backend, Some(HistoryBackend::Disk));
+        assert_eq!(options.history_cache, Some(5000));
+        assert!(Options::parse(args(&["in.csv", "--history-cache", "5000"])).is_err());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--history-backend",
+            "disk",
+            "--conservation-check"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--history-backend",
+            "disk",
+            "--threads",
+            "2"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_threads_flag() {
+        let options = Options::parse(args(&["in.csv", "--parse-threads", "4"])).unwrap();
+        assert_eq!(options.parse_threads, Some(4));
+        assert!(Options::parse(args(&["in.csv", "--parse-threads", "0"])).is_err());
+        assert!(Options::parse(args(&["profile", "in.csv", "--parse-threads", "4"])).is_err());
+        let options = Options::parse(args(&["in.csv", "--parse-threads", "auto"])).unwrap();
+        assert!(options.parse_threads.unwrap() > 0);
+    }
+
+    #[test]
+    fn read_only_refuses_writes() {
+        for command in [
+            &["in.csv"][..],
+            &["screen", "in.csv"],
+            &["profile", "in.csv"],
+            &["tui", "in.csv"],
+            &["explain", "--tx", "1", "in.csv"],
+            &["soak", "--duration", "1", "--tps", "1"],
+        ] {
+            let mut list = command.to_vec();
+            list.push("--read-only");
+            assert!(Options::parse(args(&list)).unwrap().read_only);
+        }
+        let err = Options::parse(args(&[
+            "in.csv",
+            "--read-only",
+            "--cdc-out",
+            "changes.jsonl",
+            "--redis",
+            "localhost",
+        ]))
+        .unwrap_err();
+        assert!(err.to_string().starts_with(
+            "--read-only refuses flags writing outside stdout and stderr: --cdc-out, --redis."
+        ));
+        let err = Options::parse(args(&[
+            "profile",
+            "in.csv",
+            "--profile-out",
+            "perf.json",
+            "--read-only",
+        ]))
+        .unwrap_err();
+        assert!(err.to_string().contains("--profile-out"));
+    }
+
+    #[test]
+    fn max_amount_flag() {
+        let options = Options::parse(args(&["in.csv", "--max-amount", "1000000"])).unwrap();
+        assert_eq!(options.max_amount, Some(Currency::from_num(1_000_000)));
+        assert!(Options::parse(args(&["in.csv", "--max-amount", "0"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--max-amount", "1e6"])).is_err());
+    }
+
+    #[test]
+    fn emit_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--emit-every",
+            "1_000_000",
+            "--emit-dir",
+            "out",
+        ]))
+        .unwrap();
+        assert_eq!(options.emit_every, Some(1_000_000));
+        assert_eq!(options.emit_dir.as_deref(), Some("out"));
+        assert!(Options::parse(args(&["in.csv", "--emit-every", "10"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--emit-dir", "out"])).is_err());
+    }
+
+    #[test]
+    fn disputes_file_flag() {
+        let options = Options::parse(args(&["in.csv", "--disputes-file", "cases.csv"])).unwrap();
+        assert_eq!(options.disputes_file.as_deref(), Some("cases.csv"));
+        assert!(
+            Options::parse(args(&["screen", "in.csv", "--disputes-file", "cases.csv"])).is_err()
+        );
+    }
+
+    #[test]
+    fn input_format_flag() {
+        let options = Options::parse(args(&["in.txt", "--input-format", "jsonl"])).unwrap();
+        assert_eq!(options.input_format, Some(InputFormat::Jsonl));
+        assert!(Options::parse(args(&["in.csv", "--input-format", "xml"])).is_err());
+        assert!(
+            Options::parse(args(&["screen", "in.parquet", "--input-format", "parquet"])).is_ok()
+        );
+        // Sniffed from the extension too.
+        assert!(Options::parse(args(&["in.parquet", "--parse-threads", "2"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--parse-threads", "2"])).is_ok());
+        assert!(Options::parse(args(&["tui", "in.jsonl", "--follow"])).is_err());
+
+        let options = Options::parse(args(&[
+            "screen",
+            "in.dat",
+            "--input-format",
+            "fixed-width",
+            "--layout",
+            "layout.toml",
+        ]))
+        .unwrap();
+        assert_eq!(options.input_format, Some(InputFormat::FixedWidth));
+        assert_eq!(options.layout.as_deref(), Some("layout.toml"));
+        assert!(Options::parse(args(&["in.dat", "--input-format", "fixed-width"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--layout", "layout.toml"])).is_err());
+    }
+
+    #[test]
+    fn authorizations_flag() {
+        let options = Options::parse(args(&["in.csv", "--authorizations", "auths.csv"])).unwrap();
+        assert_eq!(options.authorizations.as_deref(), Some("auths.csv"));
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--authorizations",
+            "auths.csv",
+            "--threads",
+            "2"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn unique_tids_flag() {
+        let options = Options::parse(args(&["in.csv", "--unique-tids", "global"])).unwrap();
+        assert_eq!(options.unique_tids, Some(TidScope::Global));
+        assert!(Options::parse(args(&["in.csv", "--unique-tids", "account"])).is_err());
+        assert!(Options::parse(args(&["--stdin", "--unique-tids", "global"])).is_ok());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--unique-tids",
+            "client",
+            "--threads",
+            "2"
+        ]))
+        .is_ok());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--unique-tids",
+            "global",
+            "--threads",
+            "2"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn output_filter_flag() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--output-filter",
+            "locked",
+            "--output-filter",
+            "negative",
+            "--threads",
+            "2",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.output_filter,
+            [OutputFilter::Locked, OutputFilter::Negative]
+        );
+        assert!(Options::parse(args(&["in.csv", "--output-filter", "healthy"])).is_err());
+        assert!(Options::parse(args(&["screen", "in.csv", "--output-filter", "locked"])).is_err());
+    }
+
+    #[test]
+    fn tuning_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--read-buffer",
+            "65536",
+            "--parse-threads",
+            "2",
+            "--batch-size",
+            "1000",
+            "--channel-capacity",
+            "8",
+        ]))
+        .unwrap();
+        assert_eq!(options.read_buffer, Some(65536));
+        assert_eq!(options.batch_size, Some(1000));
+        assert_eq!(options.channel_capacity, Some(8));
+        assert!(Options::parse(args(&["in.csv", "--read-buffer", "64k"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--batch-size", "1000"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--channel-capacity", "8"])).is_err());
+    }
+
+    #[test]
+    fn bootstrap_command() {
+        let options =
+            Options::parse(args(&["bootstrap", "--balances", "open.csv", "in.csv"])).unwrap();
+        assert_eq!(options.command, Command::Bootstrap);
+        assert_eq!(options.balances.as_deref(), Some("open.csv"));
+        assert_eq!(options.input, "in.csv");
+    }
+
+    #[test]
+    fn bootstrap_requires_balances() {
+        assert!(Options::parse(args(&["bootstrap", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["--balances", "open.csv", "in.csv"])).is_err());
+    }
+
+    #[test]
+    fn schema_flag() {
+        let options = Options::parse(args(&["in.csv", "--schema", "v2"])).unwrap();
+        assert_eq!(options.schema, Some(Schema::V2));
+        assert!(Options::parse(args(&["in.csv", "--schema", "v9"])).is_err());
+    }
+
+    #[test]
+    fn clients_flag() {
+        let options = Options::parse(args(&["in.csv", "--clients", "clients.csv"])).unwrap();
+        assert_eq!(options.clients.as_deref(), Some("clients.csv"));
+    }
+
+    #[test]
+    fn closing_balances() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--closing-balances",
+            "expected.csv",
+            "--tolerance",
+            "0.01",
+        ]))
+        .unwrap();
+        assert_eq!(options.closing_balances.as_deref(), Some("expected.csv"));
+        assert_eq!(options.tolerance, Some(parse_amount("0.01").unwrap()));
+        assert!(Options::parse(args(&["in.csv", "--tolerance", "0.01"])).is_err());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--closing-balances",
+            "e.csv",
+            "--tolerance",
+            "abc"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn overdraft_penalties() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--overdraft-rate",
+            "0.5",
+            "--cutoff-every",
+            "100",
+        ]))
+        .unwrap();
+        assert_eq!(options.overdraft_rate, Some(Currency::from_num(0.5)));
+        assert_eq!(options.cutoff_every, Some(100));
+        assert!(Options::parse(args(&["in.csv", "--cutoff-every", "100"])).is_err());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--overdraft-rate",
+            "0.5",
+            "--cutoff-every",
+            "0"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn category_report_flag() {
+        let options = Options::parse(args(&["in.csv", "--category-report", "c.csv"])).unwrap();
+        assert_eq!(options.category_report.as_deref(), Some("c.csv"));
+        let options = Options::parse(args(&["in.csv", "--categorize", "rules.toml"])).unwrap();
+        assert_eq!(options.categorize.as_deref(), Some("rules.toml"));
+    }
+
+    #[test]
+    fn assets_flag() {
+        let options = Options::parse(args(&["in.csv", "--assets", "assets.toml"])).unwrap();
+        assert_eq!(options.assets.as_deref(), Some("assets.toml"));
+    }
+
+    #[test]
+    fn sample_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--sample",
+            "0.01",
+            "--sample-out",
+            "sample.csv",
+            "--seed",
+            "7",
+        ]))
+        .unwrap();
+        assert_eq!(options.sample, Some(0.01));
+        assert_eq!(options.sample_out.as_deref(), Some("sample.csv"));
+        assert_eq!(options.seed, Some(7));
+        assert!(Options::parse(args(&["in.csv", "--sample", "0.01"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--sample-out", "s.csv"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--seed", "7"])).is_err());
+        assert!(
+            Options::parse(args(&["in.csv", "--sample", "2", "--sample-out", "s.csv"])).is_err()
+        );
+    }
+
+    #[test]
+    fn anomaly_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--anomalies",
+            "a.csv",
+            "--anomaly-threshold",
+            "3.5",
+        ]))
+        .unwrap();
+        assert_eq!(options.anomalies.as_deref(), Some("a.csv"));
+        assert_eq!(options.anomaly_threshold, Some(3.5));
+        assert!(Options::parse(args(&["in.csv", "--anomaly-threshold", "3"])).is_err());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--anomalies",
+            "a.csv",
+            "--anomaly-threshold",
+            "-1"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn screen_command() {
+        let options = Options::parse(args(&["screen", "in.csv", "--schema", "v1"])).unwrap();
+        assert_eq!(options.command, Command::Screen);
+        assert_eq!(options.input, "in.csv");
+        assert!(Options::parse(args(&["screen", "in.csv", "--redis", "localhost"])).is_err());
+    }
+
+    #[test]
+    fn risk_lock_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--lock-after-chargebacks",
+            "3",
+            "--lock-after-chargeback-amount",
+            "500.5",
+        ]))
+        .unwrap();
+        assert_eq!(options.lock_after_chargebacks, Some(3));
+        assert_eq!(
+            options.lock_after_chargeback_amount,
+            Some(Currency::from_num(500.5))
+        );
+        assert!(Options::parse(args(&["in.csv", "--lock-after-chargebacks", "0"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--lock-after-chargeback-amount", "-1"])).is_err());
+    }
+
+    #[test]
+    fn dispute_cap_flags() {
+        let options = Options::parse(args(&[
+            "in.csv",
+            "--max-open-disputes",
+            "100",
+            "--dispute-overflow",
+            "queue",
+        ]))
+        .unwrap();
+        assert_eq!(options.max_open_disputes, Some(100));
+        assert_eq!(options.dispute_overflow, Some(Overflow::Queue));
+        assert!(Options::parse(args(&["in.csv", "--max-open-disputes", "0"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--dispute-overflow", "queue"])).is_err());
+        let err = Options::parse(args(&[
+            "in.csv",
+            "--max-open-disputes",
+            "1",
+            "--dispute-overflow",
+            "drop",
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown dispute overflow drop, expected reject or queue."
+        );
+    }
+
+    #[test]
+    fn ordering_policy_flag() {
+        let options = Options::parse(args(&["in.csv", "--ordering-policy", "input"])).unwrap();
+        assert_eq!(options.ordering_policy, Some(OrderingPolicy::Input));
+        let options = Options::parse(args(&[
+            "explain",
+            "--tx",
+            "7",
+            "in.csv",
+            "--ordering-policy",
+            "priority",
+        ]))
+        .unwrap();
+        assert_eq!(options.ordering_policy, Some(OrderingPolicy::Priority));
+        assert!(Options::parse(args(&["in.csv", "--ordering-policy", "arrival"])).is_err());
+        assert!(Options::parse(args(&["screen", "in.csv", "--ordering-policy", "input"])).is_err());
+    }
+
+    #[test]
+    fn lang_flag() {
+        let options = Options::parse(args(&["in.csv", "--lang", "de"])).unwrap();
+        assert_eq!(options.lang, Some(Lang::De));
+        assert!(Options::parse(args(&["in.csv", "--lang", "it"])).is_err());
+        assert!(Options::parse(args(&["activity", "in.csv", "--lang", "fr"])).is_err());
+    }
+
+    #[test]
+    fn warnings_flags() {
+        let options = Options::parse(args(&["in.csv", "--warnings-fd", "3"])).unwrap();
+        assert_eq!(options.warnings_fd, Some(3));
+        assert!(Options::parse(args(&["in.csv", "--warnings-fd", "2"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--warnings-fd", "x"])).is_err());
+        assert!(Options::parse(args(&[
+            "in.csv",
+            "--warnings-fd",
+            "3",
+            "--warnings-out",
+            "w.jsonl"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&["screen", "in.csv", "--warnings-fd", "3"])).is_err());
+    }
+
+    #[test]
+    fn compare_versions_command() {
+        let options = Options::parse(args(&[
+            "compare-versions",
+            "--old",
+            "./txcli-1.2",
+            "--new",
+            "./txcli-1.3",
+            "in.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::CompareVersions);
+        assert_eq!(options.old.as_deref(), Some("./txcli-1.2"));
+        assert_eq!(options.new.as_deref(), Some("./txcli-1.3"));
+        assert!(Options::parse(args(&["compare-versions", "--old", "a", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--old", "a", "--new", "b"])).is_err());
+        assert!(Options::parse(args(&[
+            "compare-versions",
+            "--old",
+            "a",
+            "--new",
+            "b",
+            "in.csv",
+            "--mem-stats"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn activity_command() {
+        let options = Options::parse(args(&["activity", "in.csv"])).unwrap();
+        assert_eq!(options.command, Command::Activity);
+        assert!(Options::parse(args(&["activity", "in.csv", "--max-amount", "10"])).is_err());
+    }
+
+    #[test]
+    fn estimate_command() {
+        let options = Options::parse(args(&["estimate", "in.csv", "--schema", "v2"])).unwrap();
+        assert_eq!(options.command, Command::Estimate);
+        assert!(Options::parse(args(&["estimate", "in.csv", "--threads", "2"])).is_err());
+        assert!(Options::parse(args(&["estimate", "in.jsonl"])).is_err());
+    }
+
+    #[test]
+    fn anonymize_command() {
+        let options = Options::parse(args(&[
+            "anonymize",
+            "in.csv",
+            "-o",
+            "anon.csv",
+            "--seed",
+            "7",
+            "--perturb",
+            "5",
+            "--sample",
+            "0.1",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::Anonymize);
+        assert_eq!(options.out.as_deref(), Some("anon.csv"));
+        assert_eq!(options.perturb, Some(5.0));
+        assert_eq!(options.sample, Some(0.1));
+        assert!(Options::parse(args(&["anonymize", "in.csv", "--out", "anon.csv"])).is_err());
+        assert!(Options::parse(args(&["anonymize", "in.csv", "--seed", "7"])).is_err());
+        assert!(Options::parse(args(&[
+            "anonymize",
+            "in.csv",
+            "--out",
+            "anon.csv",
+            "--seed",
+            "7",
+            "--perturb",
+            "100"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&["in.csv", "--out", "anon.csv"])).is_err());
+    }
+
+    #[test]
+    fn shrink_command() {
+        let options = Options::parse(args(&[
+            "shrink",
+            "in.csv",
+            "--out",
+            "small.csv",
+            "--preserve-client",
+            "42",
+            "--preserve-failure",
+            "--conservation-check",
+            "--locked-policy",
+            "allow",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::Shrink);
+        assert_eq!(options.preserve_client, Some(ClientId(42)));
+        assert!(options.preserve_failure);
+        assert!(Options::parse(args(&["shrink", "in.csv", "--out", "small.csv"])).is_err());
+        assert!(Options::parse(args(&["shrink", "in.csv", "--preserve-failure"])).is_err());
+        assert!(Options::parse(args(&[
+            "shrink",
+            "in.csv",
+            "--out",
+            "small.csv",
+            "--preserve-failure",
+            "--strict"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&["in.csv", "--preserve-failure"])).is_err());
+    }
+
+    #[test]
+    fn split_command() {
+        let options = Options::parse(args(&[
+            "split",
+            "in.csv",
+            "--by",
+            "client-mod",
+            "8",
+            "-o",
+            "shards",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::Split);
+        assert_eq!(options.client_mod, Some(8));
+        assert_eq!(options.out.as_deref(), Some("shards"));
+        assert!(Options::parse(args(&["split", "in.csv", "--by", "client-mod", "8"])).is_err());
+        assert!(
+            Options::parse(args(&["split", "in.csv", "--by", "tx-mod", "8", "-o", "s"])).is_err()
+        );
+        assert!(Options::parse(args(&[
+            "split",
+            "in.csv",
+            "--by",
+            "client-mod",
+            "0",
+            "-o",
+            "s"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&[
+            "split",
+            "in.csv",
+            "--by",
+            "client-mod",
+            "8",
+            "-o",
+            "shards",
+            "--threads",
+            "2"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&["in.csv", "--by", "client-mod", "8"])).is_err());
+    }
+
+    #[test]
+    fn merge_clients_command() {
+        let options =
+            Options::parse(args(&["merge-clients", "--map", "merges.csv", "in.csv"])).unwrap();
+        assert_eq!(options.command, Command::MergeClients);
+        assert_eq!(options.map.as_deref(), Some("merges.csv"));
+        assert!(Options::parse(args(&["merge-clients", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--map", "merges.csv"])).is_err());
+    }
+
+    #[test]
+    fn split_client_command() {
+        let options = Options::parse(args(&[
+            "split-client",
+            "--client",
+            "7",
+            "--txs",
+            "tids.txt",
+            "--into",
+            "9001",
+            "in.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::SplitClient);
+        assert_eq!(options.client, Some(ClientId(7)));
+        assert_eq!(options.split_txs.as_deref(), Some("tids.txt"));
+        assert_eq!(options.split_into, Some(ClientId(9001)));
+        assert!(Options::parse(args(&["split-client", "--client", "7", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--client", "7"])).is_err());
+        assert!(Options::parse(args(&[
+            "split-client",
+            "--client",
+            "x",
+            "--txs",
+            "t.txt",
+            "--into",
+            "1",
+            "in.csv"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn profile_command() {
+        let options =
+            Options::parse(args(&["profile", "in.csv", "--profile-out", "perf.json"])).unwrap();
+        assert_eq!(options.command, Command::Profile);
+        assert_eq!(options.profile_out.as_deref(), Some("perf.json"));
+        assert!(Options::parse(args(&["profile", "in.csv", "--redis", "localhost"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--profile-out", "perf.json"])).is_err());
+    }
+
+    #[test]
+    fn tui_command() {
+        let options = Options::parse(args(&["tui", "in.csv", "--follow"])).unwrap();
+        assert_eq!(options.command, Command::Tui);
+        assert!(options.follow);
+        assert!(!Options::parse(args(&["tui", "in.csv"])).unwrap().follow);
+        assert!(Options::parse(args(&["tui", "in.csv", "--redis", "localhost"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--follow"])).is_err());
+    }
+
+    #[test]
+    fn explain_command() {
+        let options = Options::parse(args(&["explain", "--tx", "1234", "in.csv"])).unwrap();
+        assert_eq!(options.command, Command::Explain);
+        assert_eq!(options.tx, Some(TxId(1234)));
+        assert_eq!(options.input, "in.csv");
+        assert!(Options::parse(args(&["explain", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["explain", "--tx", "x", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["explain", "--tx", "1", "in.csv", "--mem-stats"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--tx", "1"])).is_err());
+
+        let options = Options::parse(args(&[
+            "explain", "--client", "42", "--format", "json", "in.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.client, Some(ClientId(42)));
+        assert_eq!(options.format, Some(Format::Json));
+        assert!(
+            Options::parse(args(&["explain", "--client", "4", "--tx", "1", "in.csv"])).is_err()
+        );
+        assert!(
+            Options::parse(args(&["explain", "--client", "4", "--into", "5", "in.csv"])).is_err()
+        );
+        assert!(
+            Options::parse(args(&["explain", "--tx", "1", "--format", "xml", "in.csv"])).is_err()
+        );
+        assert!(Options::parse(args(&["in.csv", "--format", "json"])).is_err());
+    }
+
+    #[test]
+    fn converge_test_command() {
+        let options =
+            Options::parse(args(&["converge-test", "--replicas", "3", "--seed", "9"])).unwrap();
+        assert_eq!(options.command, Command::ConvergeTest);
+        assert_eq!(options.replicas, Some(3));
+        assert_eq!(options.seed, Some(9));
+        assert!(Options::parse(args(&["converge-test"])).is_ok());
+        assert!(Options::parse(args(&["converge-test", "--replicas", "1"])).is_err());
+        assert!(Options::parse(args(&["converge-test", "in.csv"])).is_err());
+        assert!(Options::parse(args(&["converge-test", "--threads", "2"])).is_err());
+        assert!(Options::parse(args(&["in.csv", "--replicas", "3"])).is_err());
+    }
+
+    #[test]
+    fn soak_command() {
+        let options = Options::parse(args(&[
+            "soak",
+            "--duration",
+            "24h",
+            "--tps",
+            "5000",
+            "--seed",
+            "1",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::Soak);
+        assert_eq!(options.duration, Some(Duration::from_secs(86400)));
+        assert_eq!(options.tps, Some(5000));
+        assert_eq!(options.seed, Some(1));
+        assert!(Options::parse(args(&["soak", "--duration", "1h"])).is_err());
+        assert!(Options::parse(args(&["soak", "--duration", "1y", "--tps", "5"])).is_err());
+        assert!(Options::parse(args(&["soak", "--duration", "1h", "--tps", "0"])).is_err());
+        assert!(
+            Options::parse(args(&["soak", "in.csv", "--duration", "1h", "--tps", "5"])).is_err()
+        );
+        assert!(Options::parse(args(&[
+            "soak",
+            "--duration",
+            "1h",
+            "--tps",
+            "5",
+            "--mem-stats"
+        ]))
+        .is_err());
+        assert!(Options::parse(args(&["in.csv", "--tps", "5"])).is_err());
+    }
+
+    #[test]
+    fn graph_out_flag() {
+        let options = Options::parse(args(&["in.csv", "--graph-out", "disputes.dot"])).unwrap();
+        assert_eq!(options.graph_out.as_deref(), Some("disputes.dot"));
+        assert!(Options::parse(args(&["screen", "in.csv", "--graph-out", "d.dot"])).is_err());
+    }
+
+    #[test]
+    fn mem_stats_flag() {
+        assert!(
+            Options::parse(args(&["in.csv", "--mem-stats"]))
+                .unwrap()
+                .mem_stats
+        );
+        assert!(!Options::parse(args(&["in.csv"])).unwrap().mem_stats);
+    }
+
+    #[test]
+    fn missing_input() {
+        assert!(Options::parse(args(&[])).is_err());
+        assert!(Options::parse(args(&["--redis"])).is_err());
+    }
+
+    #[test]
+    fn unknown_flag() {
+        assert!(Options::parse(args(&["in.csv", "--nope"])).is_err());
+    }
+
+    #[test]
+    fn errors_print_their_command_usage() {
+        let err = Options::parse(args(&["soak", "--nope"]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("usage: txcli soak --duration"));
+        assert!(!err.contains("txcli tui"));
+        let err = Options::parse(args(&["--stdin", "in.csv"]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("usage: txcli --serve <addr>"));
+        assert!(!err.contains("txcli soak"));
+        let err = Options::parse(args(&["pipe", "--nope"]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("txcli pipe [the flags --stdin takes]"));
+        assert!(err.contains("| txcli --serve <addr>"));
+        let err = Options::parse(args(&["bootstrap", "in.csv"]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("usage: txcli [process | bootstrap"));
+        assert!(err.len() < 3000);
+    }
+
+    #[test]
+    fn help_flag() {
+        let options = Options::parse(args(&["shrink", "--help"])).unwrap();
+        assert!(options.help);
+        assert!(options
+            .usage()
+            .starts_with("usage: txcli shrink <input.csv>"));
+        let options = Options::parse(args(&["--serve", "127.0.0.1:7000", "--help"])).unwrap();
+        assert!(options.usage().starts_with("usage: txcli --serve <addr>"));
+        assert!(known_flag("--help"));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::BasicError;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::error::Error;
+use std::str::FromStr;
+use txcli_model::Timestamp;
+
+// Every place txcli picks an order between rows that could go either way, kept together so the
+// balances are a function of the inputs alone: the same bits whatever --parse-threads, chunking
+// or platform. Nothing here looks at arrival time, thread ids or hash map iteration order.
+//
+// - Rows are applied in input order, parsed chunks are put back in that order first (see
+//   chunked::Sequencer).
+// - Consecutive rows sharing a timestamp go highest priority first, equal priorities in input
+//   order (tie_key). Rows never move across timestamps. Only under OrderingPolicy::Priority.
+// - A case from --disputes-file goes after the input rows stamped the same second as it
+//   (case_first), so a dispute can follow its transaction within one second.
+// - A tid is owned by the first row that uses it, a later deposit or withdrawal reusing it is
+//   rejected as a duplicate whichever client it names (see dispute_state).
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderingPolicy {
+    // Reorders rows sharing a timestamp by their priority column, when the input has one.
+    #[default]
+    Priority,
+    // Strictly the order rows appear in the input, priorities are ignored.
+    Input,
+}
+
+impl FromStr for OrderingPolicy {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "priority" => Ok(OrderingPolicy::Priority),
+            "input" => Ok(OrderingPolicy::Input),
+            _ => Err(BasicError::new(format!(
+                "Unknown ordering policy {}, expected priority or input.",
+                s
+            ))),
+        }
+    }
+}
+
+// Sort key among rows sharing a timestamp, arrival is the row's position in the input. The key
+// is total, no two rows compare equal, so it doesn't matter whether the sort is stable.
+pub fn tie_key(priority: Option<u8>, arrival: u64) -> (Reverse<u8>, u64) {
+    (Reverse(priority.unwrap_or_default()), arrival)
+}
+
+// Whether a case is applied ahead of an input row stamped at row.
+pub fn case_first(case: Timestamp, row: Timestamp) -> bool {
+    case < row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use txcli_model::{ClientId, Currency, TxRecord, TxType};
+
+    #[test]
+    fn parse_policies() {
+        assert_eq!(
+            "input".parse::<OrderingPolicy>().unwrap(),
+            OrderingPolicy::Input
+        );
+        assert_eq!(
+            "priority".parse::<OrderingPolicy>().unwrap(),
+            OrderingPolicy::Priority
+        );
+        assert!("random".parse::<OrderingPolicy>().is_err());
+    }
+
+    #[test]
+    fn ties_are_total() {
+        let mut rows = vec![
+            (Some(1), 3),
+            (None, 0),
+            (Some(1), 1),
+            (Some(9), 2),
+            (Some(0), 4),
+        ];
+        rows.sort_unstable_by_key(|(priority, arrival)| tie_key(*priority, *arrival));
+        assert_eq!(
+            rows,
+            [
+                (Some(9), 2),
+                (Some(1), 1),
+                (Some(1), 3),
+                (None, 0),
+                (Some(0), 4)
+            ]
+        );
+        assert!(!case_first(Timestamp(5), Timestamp(5)));
+        assert!(case_first(Timestamp(4), Timestamp(5)));
+    }
+
+    #[test]
+    fn first_use_of_a_tid_wins() {
+        let mut engine = EngineBuilder::new().build();
+        let deposit =
+            |cid, amount: u32| TxRecord::new(TxType::Deposit, cid, 1, Currency::from_num(amount));
+        assert!(engine.apply(deposit(1, 5)));
+        assert!(!engine.apply(deposit(1, 7)));
+        assert!(engine.apply(TxRecord::new(TxType::Dispute, 1, 1, Currency::from_num(0))));
+        let state = engine.state();
+        assert_eq!(state.clients[&ClientId(1)].held(), Currency::from_num(5));
+    }
+}
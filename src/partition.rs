@@ -0,0 +1,113 @@
+use crate::schema::RowReader;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read};
+use std::path::Path;
+use txcli_model::ClientId;
+
+// Splits an input into n shard files by client, for runs on several machines at once. A client's
+// rows, with those of its joint owners, all land in the shard of its canonical id modulo n, the
+// same shard --threads gives it, and in input order, so each shard processes exactly as its
+// clients would in the whole input. Rows are copied untouched under the input's header.
+pub struct Partition {
+    shards: usize,
+    joint_owners: HashMap<ClientId, ClientId>,
+}
+
+impl Partition {
+    pub fn new(shards: usize, joint_owners: HashMap<ClientId, ClientId>) -> Self {
+        Partition {
+            shards,
+            joint_owners,
+        }
+    }
+
+    pub fn shard(&self, cid: ClientId) -> usize {
+        let cid = self.joint_owners.get(&cid).copied().unwrap_or(cid);
+        cid.0 as usize % self.shards
+    }
+
+    // Shard files are numbered from 0, zero padded so they sort in order.
+    pub fn file_name(&self, shard: usize) -> String {
+        let width = (self.shards - 1).to_string().len();
+        format!("shard-{:0width$}.csv", shard, width = width)
+    }
+
+    // Writes every shard file into dir, even those no client falls in, and returns the rows
+    // written to each.
+    pub fn run<R: Read>(
+        &self,
+        reader: &mut csv::Reader<R>,
+        mut rows: RowReader,
+        dir: &Path,
+    ) -> Result<Vec<u64>, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        let mut writers = (0..self.shards)
+            .map(|shard| {
+                let file = File::create(dir.join(self.file_name(shard)))?;
+                let mut writer = csv::WriterBuilder::new()
+                    .flexible(true)
+                    .from_writer(BufWriter::new(file));
+                writer.write_record(reader.headers()?)?;
+                Ok(writer)
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        let mut counts = vec![0; self.shards];
+        while rows.read(reader)? {
+            let shard = self.shard(rows.parse()?.cid);
+            writers[shard].write_record(rows.record())?;
+            counts[shard] += 1;
+        }
+        for writer in &mut writers {
+            writer.flush()?;
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+
+    #[test]
+    fn clients_keep_their_rows_in_order() {
+        let input = "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            deposit,2,2,2.0\n\
+            deposit,3,3,3.0\n\
+            dispute,1,1,\n\
+            withdrawal,2,4,1.5\n\
+            resolve,1,1,\n\
+            deposit,12,5,4.0\n";
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let rows = Schema::V1.reader(reader.headers().unwrap()).unwrap();
+        // Client 12 is a joint owner of client 3.
+        let partition = Partition::new(10, HashMap::from([(ClientId(12), ClientId(3))]));
+        let dir = std::env::temp_dir().join(format!("txcli-partition-{}", std::process::id()));
+        let counts = partition.run(&mut reader, rows, &dir).unwrap();
+        assert_eq!(counts, [0, 3, 2, 2, 0, 0, 0, 0, 0, 0]);
+
+        let shard = |shard| fs::read_to_string(dir.join(partition.file_name(shard))).unwrap();
+        assert_eq!(
+            shard(1),
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,1,\nresolve,1,1,\n"
+        );
+        assert_eq!(
+            shard(3),
+            "type,client,tx,amount\ndeposit,3,3,3.0\ndeposit,12,5,4.0\n"
+        );
+        assert_eq!(shard(9), "type,client,tx,amount\n");
+        assert_eq!(partition.file_name(0), "shard-0.csv");
+        assert_eq!(
+            Partition::new(12, HashMap::new()).file_name(3),
+            "shard-03.csv"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
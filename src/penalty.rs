@@ -0,0 +1,69 @@
+use crate::AppState;
+use txcli_model::Currency;
+
+// Charges every account with a negative available balance rate * |available| at a cutoff.
+// The penalty is taken from available, so an account that stays negative compounds from one cutoff to the next.
+// Returns the total assessed at this cutoff.
+pub fn assess(app_state: &mut AppState, rate: Currency) -> Currency {
+    let mut total = Currency::from_num(0);
+    for client in app_state.clients.values_mut() {
+        for balance in client.balances_mut() {
+            if balance.available < 0 {
+                let penalty = balance.available.abs() * rate;
+                balance.available -= penalty;
+                balance.penalties += penalty;
+                total += penalty;
+            }
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_transaction;
+    use txcli_model::{AccountId, ClientId, TxRecord, TxType};
+
+    #[test]
+    fn only_negative_accounts_are_charged() {
+        let mut app_state = AppState::default();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(10.0)),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Withdrawal, 1, 2, Currency::from_num(10.0)),
+        )
+        .unwrap();
+        // Disputing the deposit after the funds left drives available negative.
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+        )
+        .unwrap();
+        execute_transaction(
+            &mut app_state,
+            TxRecord::new(TxType::Deposit, 2, 3, Currency::from_num(5.0)),
+        )
+        .unwrap();
+
+        let total = assess(&mut app_state, Currency::from_num(0.5));
+        assert_eq!(total, Currency::from_num(5.0));
+        let balance = app_state.clients[&ClientId(1)].accounts[&AccountId::default()];
+        assert_eq!(balance.available, Currency::from_num(-15.0));
+        assert_eq!(balance.penalties, Currency::from_num(5.0));
+        assert_eq!(
+            app_state.clients[&ClientId(2)].accounts[&AccountId::default()].penalties,
+            Currency::from_num(0)
+        );
+
+        // Compounds on the next cutoff.
+        assert_eq!(
+            assess(&mut app_state, Currency::from_num(0.5)),
+            Currency::from_num(7.5)
+        );
+    }
+}
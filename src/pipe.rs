@@ -0,0 +1,218 @@
+use crate::engine::Engine;
+use crate::results::Stats;
+use crate::schema::Schema;
+use crate::server::record_of_json;
+use crate::BasicError;
+use serde::Serialize;
+use std::error::Error;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Mutex, MutexGuard};
+use txcli_model::{ClientId, ClientOutputState, TxId, TxType};
+
+// txcli pipe, for programs embedding the engine as a co-process rather than over a socket. Every
+// frame either way is a 4 byte big endian length, then that many bytes of JSON.
+//
+// in   {"type":"deposit","client":1,"tx":1,"amount":"2.5"}   the same columns as --stdin takes
+// out  {"frame":"outcome","seq":1,"type":"deposit","client":1,"tx":1,"applied":true}
+//      {"frame":"outcome",...,"applied":false,"reason":"insufficient_funds"}
+//      {"frame":"error","seq":2,"message":"line 2: missing tx"}
+//      {"frame":"state","rows":1000,"stats":{...},"clients":[...]}
+//
+// seq counts the frames read. Each one is answered by one outcome or error frame, flushed
+// before the next is read, and a state frame follows every --state-every rows and the end of
+// the input. Frames are independent of each other, so the reader never has to resync.
+pub const DEFAULT_STATE_EVERY: u64 = 1000;
+
+// Anything longer is taken for a corrupt length rather than allocated.
+const MAX_FRAME: usize = 1 << 20;
+
+#[derive(Serialize)]
+#[serde(tag = "frame", rename_all = "lowercase")]
+enum Frame<'a> {
+    Outcome {
+        seq: u64,
+        #[serde(rename = "type")]
+        tx_type: TxType,
+        client: ClientId,
+        tx: TxId,
+        applied: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+    Error {
+        seq: u64,
+        message: String,
+    },
+    State {
+        rows: u64,
+        stats: Stats,
+        clients: &'a [ClientOutputState],
+    },
+}
+
+// The next frame's body, None once the input ends between frames.
+fn read_frame<R: Read>(input: &mut R) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let mut length = [0; 4];
+    let mut filled = 0;
+    while filled < length.len() {
+        match input.read(&mut length[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(BasicError::new("The input ended inside a frame's length.")),
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME {
+        return Err(BasicError::new(format!(
+            "Frame of {} bytes is over the {} byte limit, the length prefix is probably corrupt.",
+            length, MAX_FRAME
+        )));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_frame<W: Write>(output: &mut W, frame: &Frame) -> Result<(), Box<dyn Error>> {
+    let body = serde_json::to_vec(frame)?;
+    output.write_all(&(body.len() as u32).to_be_bytes())?;
+    output.write_all(&body)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn write_state<W: Write>(output: &mut W, engine: &Engine, rows: u64) -> Result<(), Box<dyn Error>> {
+    let report = engine.report();
+    write_frame(
+        output,
+        &Frame::State {
+            rows,
+            stats: report.stats,
+            clients: &report.clients,
+        },
+    )
+}
+
+fn lock(engine: &Mutex<Engine>) -> MutexGuard<'_, Engine> {
+    engine
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Applies the input's frames until it ends, then writes the final state. The engine is locked a
+// frame at a time, so txcli ctl can pause between frames, see control.rs.
+pub fn run<R: Read, W: Write>(
+    engine: &Mutex<Engine>,
+    mut input: R,
+    mut output: W,
+    state_every: u64,
+) -> Result<(), Box<dyn Error>> {
+    let mut reader = Schema::V1.reader(&csv::StringRecord::new())?;
+    let mut seq = 0;
+    let mut processed = 0;
+    while let Some(body) = read_frame(&mut input)? {
+        seq += 1;
+        let parsed = std::str::from_utf8(&body)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(record_of_json)
+            .and_then(|mut record| {
+                let mut position = csv::Position::new();
+                position.set_line(seq);
+                record.set_position(Some(position));
+                reader.set_record(record);
+                reader.parse()
+            });
+        let tx = match parsed {
+            Ok(tx) => tx,
+            Err(err) => {
+                let message = err.to_string();
+                write_frame(&mut output, &Frame::Error { seq, message })?;
+                continue;
+            }
+        };
+        let (tx_type, client, tid) = (tx.tx_type, tx.cid, tx.tid);
+        let result = lock(engine).try_apply(tx);
+        write_frame(
+            &mut output,
+            &Frame::Outcome {
+                seq,
+                tx_type,
+                client,
+                tx: tid,
+                applied: result.is_ok(),
+                reason: result.err().map(|reason| reason.to_string()),
+            },
+        )?;
+        processed += 1;
+        if processed % state_every == 0 {
+            write_state(&mut output, &lock(engine), processed)?;
+        }
+    }
+    let mut engine = lock(engine);
+    engine.finish();
+    write_state(&mut output, &engine, processed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use serde_json::Value;
+
+    fn framed(bodies: &[&str]) -> Vec<u8> {
+        let mut input = vec![];
+        for body in bodies {
+            input.extend((body.len() as u32).to_be_bytes());
+            input.extend(body.as_bytes());
+        }
+        input
+    }
+
+    fn frames(mut output: &[u8]) -> Vec<Value> {
+        let mut frames = vec![];
+        while let Some(body) = read_frame(&mut output).unwrap() {
+            frames.push(serde_json::from_slice(&body).unwrap());
+        }
+        frames
+    }
+
+    #[test]
+    fn outcomes_and_state_frames() {
+        let input = framed(&[
+            r#"{"type":"deposit","client":1,"tx":1,"amount":"2.5"}"#,
+            r#"{"type":"withdrawal","client":1,"tx":2,"amount":"5"}"#,
+            r#"{"type":"deposit","client":1}"#,
+            r#"{"type":"dispute","client":1,"tx":1}"#,
+        ]);
+        let engine = Mutex::new(EngineBuilder::new().build());
+        let mut output = vec![];
+        run(&engine, input.as_slice(), &mut output, 2).unwrap();
+        let frames = frames(&output);
+        let kinds: Vec<_> = frames.iter().map(|frame| frame["frame"].clone()).collect();
+        assert_eq!(
+            kinds,
+            ["outcome", "outcome", "state", "error", "outcome", "state"]
+        );
+        assert_eq!(frames[0]["applied"], true);
+        assert_eq!(frames[1]["reason"], "insufficient_funds");
+        assert_eq!(frames[3]["message"], "line 3: missing tx");
+        assert_eq!(frames[4]["seq"], 4);
+        let state = &frames[5];
+        assert_eq!(state["rows"], 3);
+        assert_eq!(state["stats"]["applied"], 2);
+        assert_eq!(state["clients"][0]["held"], "2.5000");
+    }
+
+    #[test]
+    fn truncated_frame_is_an_error() {
+        let mut input = framed(&[r#"{"type":"deposit","client":1,"tx":1,"amount":"1"}"#]);
+        input.truncate(input.len() - 1);
+        let engine = Mutex::new(EngineBuilder::new().build());
+        assert!(run(&engine, input.as_slice(), vec![], 10).is_err());
+        assert!(run(&engine, [0, 0].as_slice(), vec![], 10).is_err());
+        let oversized = (MAX_FRAME as u32 + 1).to_be_bytes();
+        assert!(run(&engine, oversized.as_slice(), vec![], 10).is_err());
+    }
+}
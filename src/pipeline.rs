@@ -0,0 +1,533 @@
+use crate::anomaly::{self, AnomalyDetector};
+use crate::categorize::Categorizer;
+use crate::cdc::CdcWriter;
+use crate::engine::{Engine, FeePayer};
+use crate::i18n::{text, Msg};
+use crate::options::Options;
+use crate::ordering::OrderingPolicy;
+use crate::rejection::{RejectionLog, RejectionReason};
+use crate::report::CategoryReport;
+use crate::sample::Sampler;
+use crate::schema::RowReader;
+use crate::warnings::{Kind, Warning};
+use crate::{
+    assertions, audit, cases, clearing, conservation, emit, graph, manifest, metrics, precision,
+    priority, retry, rollforward, settlement, sources, split, validation, writeoff,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use txcli_model::{ClientId, ClientOutputState, Tx, TxId, TxRecord, TxType};
+
+// Everything a plain run does with its rows besides applying them: the reports, logs and checks
+// its flags ask for. Rows go in through feed as they are read, then through the priority queue
+// and on to process once they are due. The engine stays the caller's, passed to each call.
+pub struct Pipeline<'a> {
+    options: &'a Options,
+    // Looked at as rows are read.
+    precision: Option<precision::PrecisionReport>,
+    authorizations: Option<clearing::Authorizations>,
+    cases: Option<cases::Cases<File>>,
+    // Who owns each deposit and withdrawal, only kept when the cases don't name their client.
+    owners: HashMap<TxId, ClientId>,
+    amount_column: Option<usize>,
+    // Rows held back for their priority, with the input row for the retry file.
+    pending: Option<priority::PendingQueue<(Tx, Option<csv::StringRecord>)>>,
+    // Looked at as rows are applied.
+    pub cdc: Option<CdcWriter<BufWriter<File>>>,
+    pub split: Option<split::Split>,
+    pub sampler: Option<Sampler<BufWriter<File>>>,
+    pub anomalies: Option<AnomalyDetector>,
+    pub graph: Option<graph::DisputeGraph>,
+    pub assertions: Vec<assertions::Assertion>,
+    categorizer: Option<Categorizer>,
+    categories: Option<CategoryReport>,
+    settlement: Option<settlement::SettlementReport>,
+    rollforward: Option<rollforward::RollForward>,
+    writeoffs: Option<writeoff::WriteoffReport>,
+    sources: Option<sources::SourceReport>,
+    pub counts: manifest::Counts,
+    pub retry: Option<retry::RetryWriter<BufWriter<File>>>,
+    pub errors: Option<RejectionLog>,
+    pub emitter: Option<emit::Emitter>,
+    pub conservation: Option<conservation::Conservation>,
+    // Rows rejected for what they are rather than what they meet, see validation::invalid.
+    pub invalid: u64,
+    audit: Option<audit::AuditTrail>,
+    progress: Option<metrics::Progress>,
+}
+
+impl<'a> Pipeline<'a> {
+    // After any opening balances, which come out of the world account.
+    pub fn new(
+        options: &'a Options,
+        engine: &Engine,
+        headers: &csv::StringRecord,
+        rows: &RowReader,
+    ) -> Result<Self, Box<dyn Error>> {
+        let cdc = match &options.cdc_out {
+            Some(path) => Some(CdcWriter::create(path)?),
+            None => None,
+        };
+        let split = match (&options.split_txs, options.client, options.split_into) {
+            (Some(path), Some(client), Some(into)) => {
+                Some(split::Split::load(client, into, File::open(path)?)?)
+            }
+            _ => None,
+        };
+        let sampler = match (&options.sample_out, options.sample) {
+            (Some(path), Some(rate)) => {
+                let seed = match options.seed {
+                    Some(seed) => seed,
+                    None => std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)?
+                        .as_nanos() as u64,
+                };
+                eprintln!("{}", text(Msg::SamplingSeed, &[&seed]));
+                Some(Sampler::create(path, rate, seed)?)
+            }
+            _ => None,
+        };
+        let assertions = assertions::load(&options.assert, options.assertions.as_ref())?;
+        let categorizer = match &options.categorize {
+            Some(path) => Some(Categorizer::load(path)?),
+            None => None,
+        };
+        let retry = match &options.retry_out {
+            Some(path) => Some(retry::RetryWriter::create(path, headers)?),
+            None => None,
+        };
+        let errors = match &options.errors {
+            Some(path) => Some(RejectionLog::create(path)?),
+            None => None,
+        };
+        let emitter = match (options.emit_every, &options.emit_dir) {
+            (Some(every), Some(dir)) => Some(emit::Emitter::new(every, dir)?),
+            _ => None,
+        };
+        let cases = match &options.disputes_file {
+            Some(path) => Some(cases::Cases::new(File::open(path)?)?),
+            None => None,
+        };
+        let authorizations = match &options.authorizations {
+            Some(path) => Some(clearing::Authorizations::load(File::open(path)?)?),
+            None => None,
+        };
+        Ok(Pipeline {
+            options,
+            precision: options
+                .precision_report
+                .as_ref()
+                .map(|_| precision::PrecisionReport::default()),
+            authorizations,
+            cases,
+            owners: HashMap::new(),
+            amount_column: rows.amount_column(),
+            // Only inputs with a priority column are reordered, holding rows back costs an owned
+            // copy of each. See ordering for the other tie rules.
+            pending: (rows.has_priority()
+                && options.ordering_policy != Some(OrderingPolicy::Input))
+            .then(priority::PendingQueue::default),
+            cdc,
+            split,
+            sampler,
+            anomalies: options.anomalies.as_ref().map(|_| {
+                AnomalyDetector::new(
+                    options
+                        .anomaly_threshold
+                        .unwrap_or(anomaly::DEFAULT_THRESHOLD),
+                )
+            }),
+            graph: options
+                .graph_out
+                .as_ref()
+                .map(|_| graph::DisputeGraph::default()),
+            assertions,
+            categorizer,
+            categories: options
+                .category_report
+                .as_ref()
+                .map(|_| CategoryReport::default()),
+            settlement: options.settlement_report.as_ref().map(|_| {
+                settlement::SettlementReport::new(
+                    options.chargeback_fee.is_some()
+                        && options.chargeback_fee_payer == Some(FeePayer::Counterparty),
+                )
+            }),
+            rollforward: options.rollforward.map(rollforward::RollForward::new),
+            writeoffs: options
+                .writeoff_report
+                .as_ref()
+                .and(options.writeoffs)
+                .map(writeoff::WriteoffReport::new),
+            sources: options
+                .report_by_source
+                .as_ref()
+                .map(|_| sources::SourceReport::default()),
+            counts: manifest::Counts::default(),
+            retry,
+            errors,
+            emitter,
+            conservation: options
+                .conservation_check
+                .then(|| conservation::Conservation::new(engine.state())),
+            invalid: 0,
+            audit: options.audit_out.is_some().then(audit::AuditTrail::default),
+            progress: options.progress_every.map(metrics::Progress::new),
+        })
+    }
+
+    // Input rows go through here, letting in the cases stamped before them first.
+    pub fn feed(
+        &mut self,
+        engine: &mut Engine,
+        tx: TxRecord,
+        row: &csv::StringRecord,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(precision) = &mut self.precision {
+            let amount = self.amount_column.and_then(|column| row.get(column));
+            if let Some(amount) = amount.filter(|amount| !amount.is_empty()) {
+                precision.record(tx.cid, amount);
+            }
+        }
+        if let Some(authorizations) = &mut self.authorizations {
+            if !authorizations.clear(&tx) {
+                let line = row.position().map_or(0, |position| position.line());
+                Warning::new(
+                    Kind::UnmatchedClearing,
+                    text(
+                        Msg::UnmatchedClearing,
+                        &[&line, &tx.tx_type, &tx.tid.0, &tx.cid.0],
+                    ),
+                )
+                .tx(tx.tx_type, tx.tid)
+                .client(tx.cid)
+                .line(line)
+                .emit();
+                return Ok(());
+            }
+        }
+        if let Some(cases) = &mut self.cases {
+            if !cases.has_clients() && matches!(tx.tx_type, TxType::Deposit | TxType::Withdrawal) {
+                self.owners.insert(tx.tid, tx.cid);
+            }
+            if tx.timestamp.is_some() {
+                for case in release(cases.before(tx.timestamp)?, &self.owners) {
+                    self.due(engine, case, None)?;
+                }
+            }
+        }
+        self.due(engine, tx, Some(row))
+    }
+
+    // The cases and held back rows still waiting once the input is read, and the authorizations
+    // no row cleared.
+    pub fn drain(&mut self, engine: &mut Engine) -> Result<(), Box<dyn Error>> {
+        if let Some(cases) = &mut self.cases {
+            for case in release(cases.before(None)?, &self.owners) {
+                self.due(engine, case, None)?;
+            }
+        }
+        if let Some(pending) = &mut self.pending {
+            for (tx, row) in pending.drain() {
+                self.process(engine, tx.as_record(), row.as_ref())?;
+            }
+        }
+        if let Some(authorizations) = self.authorizations.take() {
+            for authorization in authorizations.unmatched() {
+                // Like cases, the line is the authorizations file's and only goes in the message.
+                Warning::new(
+                    Kind::UnmatchedAuthorization,
+                    text(
+                        Msg::UnmatchedAuthorization,
+                        &[
+                            &authorization.tx_type,
+                            &authorization.tid.0,
+                            &authorization.cid.0,
+                            &format!("{:.4}", authorization.amount),
+                            &authorization.line,
+                        ],
+                    ),
+                )
+                .tx(authorization.tx_type, authorization.tid)
+                .client(authorization.cid)
+                .emit();
+            }
+        }
+        Ok(())
+    }
+
+    fn due(
+        &mut self,
+        engine: &mut Engine,
+        tx: TxRecord,
+        row: Option<&csv::StringRecord>,
+    ) -> Result<(), Box<dyn Error>> {
+        match &mut self.pending {
+            Some(pending) => {
+                for (tx, row) in pending.push(tx.timestamp, tx.priority, (tx.to_tx(), row.cloned()))
+                {
+                    self.process(engine, tx.as_record(), row.as_ref())?;
+                }
+                Ok(())
+            }
+            None => self.process(engine, tx, row),
+        }
+    }
+
+    // Everything done with a row once it is due, straight from the reader or out of the priority
+    // queue. row is the input row as read, for the retry file, None for a case from the disputes
+    // file.
+    fn process(
+        &mut self,
+        engine: &mut Engine,
+        tx: TxRecord,
+        row: Option<&csv::StringRecord>,
+    ) -> Result<(), Box<dyn Error>> {
+        let options = self.options;
+        if let Some(progress) = &mut self.progress {
+            progress.tick(engine.metrics());
+        }
+        let line = row
+            .and_then(|row| row.position())
+            .map(|position| position.line());
+        // Caught before anything else sees the row, fat fingered amounts have poisoned balances before.
+        if let Some(max) = options
+            .max_amount
+            .filter(|max| tx.amount > *max || tx.amount < -*max)
+        {
+            if let Some(errors) = &mut self.errors {
+                errors.record(line, &tx, RejectionReason::MaxAmount)?;
+            }
+            let line = line.unwrap_or(0);
+            Warning::new(
+                Kind::MaxAmount,
+                text(
+                    Msg::MaxAmount,
+                    &[
+                        &line,
+                        &format!("{:.4}", tx.amount),
+                        &tx.tx_type,
+                        &tx.tid.0,
+                        &format!("{:.4}", max),
+                    ],
+                ),
+            )
+            .tx(tx.tx_type, tx.tid)
+            .client(tx.cid)
+            .line(line)
+            .emit();
+            self.invalid += 1;
+            self.counts.record(tx.tx_type, false);
+            engine
+                .metrics_mut()
+                .record(&tx, Err(RejectionReason::MaxAmount));
+            if let Some(audit) = &mut self.audit {
+                audit.record(engine, Some(line), &tx, Err(RejectionReason::MaxAmount));
+            }
+            if let Some(sources) = &mut self.sources {
+                sources.record(source_of(&tx, row, options), tx.tx_type, tx.amount, false);
+            }
+            return Ok(());
+        }
+        // Rebound so the categorizer can lend tx a category for just this call.
+        let mut tx: TxRecord = tx;
+        if let Some(split) = &mut self.split {
+            split.apply(&mut tx)?;
+        }
+        if let Some(categorizer) = &self.categorizer {
+            categorizer.apply(&mut tx);
+        }
+        let cid = engine.state().canonical(tx.cid);
+        if let Some(anomalies) = &mut self.anomalies {
+            anomalies.observe(cid, tx.tid, tx.tx_type, tx.amount);
+        }
+        let before = if self.cdc.is_some() || self.sampler.is_some() {
+            engine.state().asset_states(cid)
+        } else {
+            vec![]
+        };
+
+        let retriable = self.retry.is_some() && retry::retriable(engine.state(), &tx);
+        let settles = self
+            .settlement
+            .is_some()
+            .then(|| settlement::Settles::of(engine.state(), cid, &tx))
+            .flatten();
+        let flow = self
+            .conservation
+            .is_some()
+            .then(|| conservation::world_flow(engine.state(), cid, &tx));
+        let penalties = engine.penalties_assessed();
+        if let Some(rollforward) = &mut self.rollforward {
+            rollforward.begin(engine.state(), tx.timestamp)?;
+        }
+        let rolled = self
+            .rollforward
+            .is_some()
+            .then(|| rollforward::RollForward::before(engine.state(), cid));
+        if let Some(writeoffs) = &mut self.writeoffs {
+            writeoffs.begin(tx.timestamp);
+        }
+        let writes_off = self
+            .writeoffs
+            .is_some()
+            .then(|| writeoff::WritesOff::of(engine.state(), cid, &tx))
+            .flatten();
+
+        let result = engine.try_apply(tx);
+        let applied = result.is_ok();
+        if let (Some(errors), Err(reason)) = (&mut self.errors, result) {
+            errors.record(line, &tx, reason)?;
+        }
+        if result.is_err_and(validation::invalid) {
+            self.invalid += 1;
+        }
+        if let Some(audit) = &mut self.audit {
+            audit.record(engine, line, &tx, result);
+        }
+        self.counts.record(tx.tx_type, applied);
+        if let Some(emitter) = &mut self.emitter {
+            emitter.record(engine)?;
+        }
+        if let Some(conservation) = &mut self.conservation {
+            let charged = engine.penalties_assessed() - penalties;
+            conservation.check(engine.state(), Some(&tx), flow.filter(|_| applied), charged)?;
+        }
+        if let (Some(retry), false, true, Some(row)) = (&mut self.retry, applied, retriable, row) {
+            retry.record(row)?;
+        }
+
+        // Balances are followed per asset, each row against the same asset's before.
+        let after = if self.cdc.is_some() || self.sampler.is_some() {
+            engine.state().asset_states(cid)
+        } else {
+            vec![]
+        };
+        let before_of = |after: &ClientOutputState| {
+            before
+                .iter()
+                .find(|before| before.currency == after.currency)
+        };
+        if let Some(cdc) = &mut self.cdc {
+            for after in &after {
+                cdc.record(tx.tid, tx.tx_type, tx.timestamp, before_of(after), after)?;
+            }
+        }
+        if let (Some(sampler), true) = (&mut self.sampler, applied) {
+            // The asset the transaction changed, or its currency's when it changed none.
+            let sampled = after
+                .iter()
+                .find(|after| before_of(after) != Some(*after))
+                .or_else(|| {
+                    after
+                        .iter()
+                        .find(|after| after.currency.as_deref() == tx.currency)
+                })
+                .or(after.first());
+            if let Some(after) = sampled {
+                sampler.record(&tx, before_of(after), after)?;
+            }
+        }
+        if let Some(graph) = &mut self.graph {
+            graph.record(cid, tx.tid, tx.tx_type, applied);
+        }
+        if let (Some(categories), true) = (&mut self.categories, applied) {
+            categories.record(cid, tx.tx_type, tx.amount, tx.category);
+        }
+        if let (Some(settlement), Some(settles), true) = (&mut self.settlement, settles, applied) {
+            settlement.record(settles);
+        }
+        if let (Some(rollforward), Some(before), true) = (&mut self.rollforward, rolled, applied) {
+            rollforward.record(engine.state(), cid, tx.tx_type, before);
+        }
+        if let (Some(writeoffs), Some(writes_off), true) =
+            (&mut self.writeoffs, writes_off, applied)
+        {
+            writeoffs.record(writes_off);
+        }
+        if let Some(sources) = &mut self.sources {
+            sources.record(source_of(&tx, row, options), tx.tx_type, tx.amount, applied);
+        }
+        Ok(())
+    }
+
+    // The reports gathered from the rows, once the balances are out.
+    pub fn write_reports(self, engine: &Engine) -> Result<(), Box<dyn Error>> {
+        let options = self.options;
+        if let (Some(path), Some(categories)) = (&options.category_report, &self.categories) {
+            categories.write(File::create(path)?)?;
+        }
+        if let (Some(path), Some(settlement)) = (&options.settlement_report, &self.settlement) {
+            settlement.write(File::create(path)?)?;
+        }
+        if let (Some(path), Some(rollforward)) = (&options.rollforward_out, self.rollforward) {
+            rollforward.write(engine.state(), File::create(path)?)?;
+        }
+        if let (Some(path), Some(writeoffs)) = (&options.writeoff_report, &self.writeoffs) {
+            writeoffs.write(File::create(path)?)?;
+        }
+        if let (Some(path), Some(sources)) = (&options.report_by_source, &self.sources) {
+            sources.write(File::create(path)?)?;
+        }
+        if let (Some(path), Some(precision)) = (&options.precision_report, &self.precision) {
+            precision.write(File::create(path)?)?;
+            let totals = precision.totals();
+            eprintln!(
+                "{}",
+                text(
+                    Msg::PrecisionLoss,
+                    &[
+                        &totals.inexact,
+                        &totals.amounts,
+                        &txcli_model::CURRENCY_FORMAT,
+                        &format!("{:e}", totals.net),
+                        &format!("{:e}", totals.max)
+                    ]
+                )
+            );
+        }
+        if let (Some(dir), Some(audit)) = (&options.audit_out, &self.audit) {
+            let written = audit.write(dir)?;
+            eprintln!("{}", text(Msg::AuditWritten, &[&written, dir]));
+        }
+        Ok(())
+    }
+}
+
+// Rows without a source column are put down to the file they were read from.
+fn source_of<'a>(
+    tx: &TxRecord<'a>,
+    row: Option<&csv::StringRecord>,
+    options: &'a Options,
+) -> &'a str {
+    match (tx.source, row, &options.disputes_file) {
+        (Some(source), _, _) => source,
+        (None, None, Some(path)) => path,
+        _ => &options.input,
+    }
+}
+
+// The rows for cases that are due, dropping those whose client can't be found.
+fn release(cases: Vec<cases::Case>, owners: &HashMap<TxId, ClientId>) -> Vec<TxRecord<'static>> {
+    cases
+        .into_iter()
+        .filter_map(|case| {
+            let tx = case.record(owners);
+            if tx.is_none() {
+                // line would read as a line of the input, the message has the disputes file's.
+                let warning = Warning::new(
+                    Kind::UnmatchedCase,
+                    text(Msg::UnmatchedCase, &[&case.tid.0, &case.line]),
+                )
+                .tx(case.tx_type, case.tid);
+                match case.cid {
+                    Some(cid) => warning.client(cid).emit(),
+                    None => warning.emit(),
+                }
+            }
+            tx
+        })
+        .collect()
+}
@@ -0,0 +1,404 @@
+use crate::asset::AssetRegistry;
+use crate::availability::SettlementDelays;
+use crate::categorize::Categorizer;
+use crate::engine::{DisputePolicy, EngineBuilder, FeePayer, LockedPolicy, Overflow};
+use crate::options::Options;
+use crate::ordering::OrderingPolicy;
+use crate::retention::Retention;
+use crate::rollforward::Period;
+use crate::validation::TidScope;
+use crate::{custom, BasicError};
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+// Policy file format, the policy flags of a run in one place, keys named after their flags:
+//
+// overdraft-rate = "0.01"
+// cutoff-every = 1000
+// max-amount = "10000"
+// max-open-disputes = 3
+// dispute-overflow = "queue"
+// lock-after-chargebacks = 2
+// lock-after-chargeback-amount = "500"
+// locked-policy = "reject-withdrawals"
+// dispute-policy = "partial-hold"
+// chargeback-fee = "15"
+// chargeback-fee-payer = "counterparty"
+// writeoffs = "monthly"
+// unique-tids = "global"
+// retain-history = "last-1000"
+// ordering-policy = "input"
+// assets = "assets.toml"       # paths are relative to the policy file
+// tx-types = "types.toml"
+// categorize = "rules.toml"
+// settlement-delays = "delays.toml"
+//
+// --policy reads it as if its flags were given ahead of the others, so the same values are
+// accepted and a flag on the command line overrides the file.
+const KEYS: [&str; 19] = [
+    "overdraft-rate",
+    "cutoff-every",
+    "max-amount",
+    "max-open-disputes",
+    "dispute-overflow",
+    "lock-after-chargebacks",
+    "lock-after-chargeback-amount",
+    "locked-policy",
+    "dispute-policy",
+    "chargeback-fee",
+    "chargeback-fee-payer",
+    "writeoffs",
+    "unique-tids",
+    "retain-history",
+    "ordering-policy",
+    "assets",
+    "tx-types",
+    "categorize",
+    "settlement-delays",
+];
+
+// What txcli policy does with the policy.
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    // Loads every file the policy names and reports settings working against each other.
+    Lint,
+    // Prints the policy a run would apply, file and flags resolved, as a policy file.
+    Explain,
+}
+
+impl FromStr for PolicyAction {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lint" => Ok(PolicyAction::Lint),
+            "explain" => Ok(PolicyAction::Explain),
+            _ => Err(BasicError::new(format!(
+                "Unknown policy action {}, expected lint or explain.",
+                s
+            ))),
+        }
+    }
+}
+
+// Keys naming another file.
+const PATHS: [&str; 4] = ["assets", "tx-types", "categorize", "settlement-delays"];
+
+// The flags a policy file stands for, in key order.
+pub fn flags(path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let table: toml::Table = fs::read_to_string(path)?
+        .parse()
+        .map_err(|err| BasicError::new(format!("Invalid policy file {}: {}", path, err)))?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut flags = vec![];
+    for (key, value) in table {
+        if !KEYS.contains(&key.as_str()) {
+            return Err(BasicError::new(format!(
+                "Unknown key {} in policy file {}, expected one of {}.",
+                key,
+                path,
+                KEYS.join(", ")
+            )));
+        }
+        let value = match value {
+            toml::Value::String(value) if PATHS.contains(&key.as_str()) => {
+                dir.join(value).to_string_lossy().into_owned()
+            }
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            _ => {
+                return Err(BasicError::new(format!(
+                    "Invalid {} in policy file {}, expected a string or a number.",
+                    key, path
+                )))
+            }
+        };
+        flags.push(format!("--{}", key));
+        flags.push(value);
+    }
+    Ok(flags)
+}
+
+// Every key with its value in a policy file, None when unset, and what happens without it.
+pub fn settings(options: &Options) -> [(&'static str, Option<String>, &'static str); 19] {
+    let quoted = |value: String| format!("{:?}", value);
+    let amount = |amount: txcli_model::Currency| quoted(format!("{:.4}", amount));
+    [
+        (
+            "overdraft-rate",
+            options.overdraft_rate.map(amount),
+            "no overdraft penalties",
+        ),
+        (
+            "cutoff-every",
+            options.cutoff_every.map(|every| every.to_string()),
+            "penalties assessed once, at the end of the run",
+        ),
+        (
+            "max-amount",
+            options.max_amount.map(amount),
+            "any amount is accepted",
+        ),
+        (
+            "max-open-disputes",
+            options.max_open_disputes.map(|max| max.to_string()),
+            "no limit on open disputes",
+        ),
+        (
+            "dispute-overflow",
+            options.dispute_overflow.map(|overflow| {
+                quoted(match overflow {
+                    Overflow::Reject => "reject".to_string(),
+                    Overflow::Queue => "queue".to_string(),
+                })
+            }),
+            "reject",
+        ),
+        (
+            "lock-after-chargebacks",
+            options
+                .lock_after_chargebacks
+                .map(|count| count.to_string()),
+            "every chargeback locks its client",
+        ),
+        (
+            "lock-after-chargeback-amount",
+            options.lock_after_chargeback_amount.map(amount),
+            "every chargeback locks its client",
+        ),
+        (
+            "locked-policy",
+            options.locked_policy.map(|policy| {
+                quoted(match policy {
+                    LockedPolicy::RejectAll => "reject-all".to_string(),
+                    LockedPolicy::RejectWithdrawals => "reject-withdrawals".to_string(),
+                    LockedPolicy::Allow => "allow".to_string(),
+                })
+            }),
+            "reject-all",
+        ),
+        (
+            "dispute-policy",
+            options.dispute_policy.map(|policy| {
+                quoted(match policy {
+                    DisputePolicy::AllowNegative => "allow-negative".to_string(),
+                    DisputePolicy::Reject => "reject".to_string(),
+                    DisputePolicy::PartialHold => "partial-hold".to_string(),
+                })
+            }),
+            "allow-negative",
+        ),
+        (
+            "chargeback-fee",
+            options.chargeback_fee.map(amount),
+            "chargebacks carry no fee",
+        ),
+        (
+            "chargeback-fee-payer",
+            options.chargeback_fee_payer.map(|payer| {
+                quoted(match payer {
+                    FeePayer::Client => "client".to_string(),
+                    FeePayer::Counterparty => "counterparty".to_string(),
+                })
+            }),
+            "client",
+        ),
+        (
+            "writeoffs",
+            options.writeoffs.map(|period| {
+                quoted(match period {
+                    Period::Weekly => "weekly".to_string(),
+                    Period::Monthly => "monthly".to_string(),
+                })
+            }),
+            "chargebacks leave no writeoff trace",
+        ),
+        (
+            "unique-tids",
+            options.unique_tids.map(|scope| {
+                quoted(match scope {
+                    TidScope::Client => "client".to_string(),
+                    TidScope::Global => "global".to_string(),
+                })
+            }),
+            "client",
+        ),
+        (
+            "retain-history",
+            options
+                .retain_history
+                .map(|retention| quoted(retention.to_string())),
+            "every transaction is kept",
+        ),
+        (
+            "ordering-policy",
+            options.ordering_policy.map(|policy| {
+                quoted(match policy {
+                    OrderingPolicy::Priority => "priority".to_string(),
+                    OrderingPolicy::Input => "input".to_string(),
+                })
+            }),
+            "priority",
+        ),
+        (
+            "assets",
+            options.assets.clone().map(quoted),
+            "the built in assets, BTC and ETH",
+        ),
+        (
+            "tx-types",
+            options.tx_types.clone().map(quoted),
+            "no custom transaction types",
+        ),
+        (
+            "categorize",
+            options.categorize.clone().map(quoted),
+            "rows keep the category they came with",
+        ),
+        (
+            "settlement-delays",
+            options.settlement_delays.clone().map(quoted),
+            "every deposit is available at once",
+        ),
+    ]
+}
+
+// The policy a run with these options applies, as a policy file. Unset keys are listed as
+// comments saying what happens without them.
+pub fn explain(options: &Options) -> String {
+    settings(options)
+        .into_iter()
+        .map(|(key, value, unset)| match value {
+            Some(value) => format!("{} = {}\n", key, value),
+            None => format!("# {}: {}\n", key, unset),
+        })
+        .collect()
+}
+
+// Loads every file the policy names, failing on the first that doesn't, and returns the
+// settings that work against each other. Those the flags can't express at all, like
+// --dispute-overflow without --max-open-disputes, were already refused by Options::parse.
+pub fn lint(options: &Options) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut conflicts = vec![];
+    let assets = match &options.assets {
+        Some(path) => AssetRegistry::load(path)?,
+        None => AssetRegistry::default(),
+    };
+    if let Some(path) = &options.tx_types {
+        let mut builder = EngineBuilder::new();
+        for (name, handler) in custom::load(path)? {
+            builder = builder.custom_type(&name, handler)?;
+        }
+    }
+    if let Some(path) = &options.categorize {
+        Categorizer::load(path)?;
+    }
+    if let Some(path) = &options.settlement_delays {
+        SettlementDelays::load(path)?;
+    }
+
+    if options.overdraft_rate.is_some_and(|rate| rate < 0) {
+        conflicts.push(
+            "overdraft-rate is negative, clients in overdraft would be paid rather than charged."
+                .to_string(),
+        );
+    }
+    if let Some(max) = options.max_amount {
+        let mut codes = assets.codes();
+        codes.sort_unstable();
+        for code in codes {
+            if let Some(dust) = assets.get(code).and_then(|asset| asset.dust) {
+                if max < dust {
+                    conflicts.push(format!(
+                        "max-amount {:.4} is below the {} dust threshold, every {} deposit and withdrawal is rejected.",
+                        max, code, code
+                    ));
+                }
+            }
+        }
+    }
+    if options.locked_policy == Some(LockedPolicy::Allow)
+        && (options.lock_after_chargebacks.is_some()
+            || options.lock_after_chargeback_amount.is_some())
+    {
+        conflicts.push(
+            "lock-after-chargebacks and lock-after-chargeback-amount decide which clients lock, but locked-policy allow lets locked clients do everything anyway."
+                .to_string(),
+        );
+    }
+    if let (Some(Overflow::Queue), Some(Retention::LastN(n))) =
+        (options.dispute_overflow, options.retain_history)
+    {
+        conflicts.push(format!(
+            "dispute-overflow queue with retain-history last-{}: a queued dispute whose transaction leaves the last {} before there is room is rejected as expired.",
+            n, n
+        ));
+    }
+    Ok(conflicts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Result<Options, Box<dyn Error>> {
+        Options::parse(
+            std::iter::once("txcli")
+                .chain(args.iter().copied())
+                .map(str::to_string),
+        )
+    }
+
+    fn write(dir: &Path, name: &str, contents: &str) -> String {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn file_flags_and_overrides() {
+        let dir = std::env::temp_dir().join(format!("txcli-policy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write(
+            &dir,
+            "assets.toml",
+            "[[asset]]\ncode = \"SAT\"\ndecimals = 0\ndust = \"100\"\n",
+        );
+        let policy = write(
+            &dir,
+            "policy.toml",
+            "overdraft-rate = 0.01\nmax-amount = \"50\"\nmax-open-disputes = 2\n\
+            dispute-overflow = \"queue\"\nretain-history = \"last-10\"\nassets = \"assets.toml\"\n",
+        );
+
+        let options =
+            options(&["in.csv", "--policy", &policy, "--max-open-disputes", "3"]).unwrap();
+        assert_eq!(options.overdraft_rate, Some("0.01".parse().unwrap()));
+        assert_eq!(options.max_open_disputes, Some(3));
+        assert_eq!(
+            options.assets,
+            Some(dir.join("assets.toml").to_str().unwrap().to_string())
+        );
+        let explained = explain(&options);
+        assert!(explained.contains("max-open-disputes = 3\n"));
+        assert!(explained.contains("dispute-overflow = \"queue\"\n"));
+        assert!(explained.contains("# cutoff-every: "));
+        assert_eq!(lint(&options).unwrap().len(), 2);
+
+        // What explain prints reads back as the same policy.
+        let exported = write(&dir, "exported.toml", &explained);
+        let reread = self::options(&["in.csv", "--policy", &exported]).unwrap();
+        assert_eq!(explain(&reread), explained);
+
+        let unknown = write(&dir, "unknown.toml", "overdraft = 0.01\n");
+        assert!(self::options(&["in.csv", "--policy", &unknown]).is_err());
+        let invalid = write(&dir, "invalid.toml", "retain-history = \"last-0\"\n");
+        assert!(self::options(&["in.csv", "--policy", &invalid]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{parse_amount_with_error, ClientId};
+
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub struct Totals {
+    pub amounts: u64,
+    // Amounts the currency couldn't hold exactly.
+    pub inexact: u64,
+    // Input minus parsed amounts, so positive means the ledger holds less than the feed said.
+    pub net: f64,
+    pub absolute: f64,
+    pub max: f64,
+}
+
+impl Totals {
+    fn add(&mut self, error: f64) {
+        self.amounts += 1;
+        if error != 0.0 {
+            self.inexact += 1;
+        }
+        self.net += error;
+        self.absolute += error.abs();
+        self.max = self.max.max(error.abs());
+    }
+}
+
+#[derive(Serialize)]
+struct PrecisionRow {
+    client: String,
+    amounts: u64,
+    inexact: u64,
+    net_error: f64,
+    abs_error: f64,
+    max_abs_error: f64,
+}
+
+// What rounding the input's decimal amounts into the fixed point currency lost, per client and
+// across all of them, to tell whether the currency format is precise enough for a feed. Each
+// amount is rounded once when parsed and never again, see txcli_model::parse_amount, so the
+// errors here are all there is, and the rest of the arithmetic is exact.
+#[derive(Default)]
+pub struct PrecisionReport {
+    all: Totals,
+    clients: BTreeMap<ClientId, Totals>,
+}
+
+impl PrecisionReport {
+    // amount is the row's amount as written in the input, rows without one count for nothing.
+    pub fn record(&mut self, cid: ClientId, amount: &str) {
+        let Ok((_, error)) = parse_amount_with_error(amount) else {
+            return;
+        };
+        self.all.add(error);
+        self.clients.entry(cid).or_default().add(error);
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.all
+    }
+
+    // The totals across all clients first, then each client by id.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        let clients = self
+            .clients
+            .iter()
+            .map(|(cid, totals)| (cid.0.to_string(), totals));
+        for (client, totals) in [("all".to_string(), &self.all)].into_iter().chain(clients) {
+            writer.serialize(PrecisionRow {
+                client,
+                amounts: totals.amounts,
+                inexact: totals.inexact,
+                net_error: totals.net,
+                abs_error: totals.absolute,
+                max_abs_error: totals.max,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// The errors are those of the default width.
+#[cfg(all(
+    test,
+    not(any(feature = "currency-i64f64", feature = "currency-i34f30"))
+))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_per_client() {
+        let mut report = PrecisionReport::default();
+        report.record(ClientId(2), "0.5");
+        report.record(ClientId(1), "0.000030517578125");
+        report.record(ClientId(1), "-0.000030517578125");
+        report.record(ClientId(1), "0.000152587890625");
+        report.record(ClientId(2), "garbage");
+        let half = 2f64.powi(-15);
+        assert_eq!(
+            report.totals(),
+            Totals {
+                amounts: 4,
+                inexact: 3,
+                net: half,
+                absolute: 3.0 * half,
+                max: half,
+            }
+        );
+        let mut out = vec![];
+        report.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,amounts,inexact,net_error,abs_error,max_abs_error\n\
+             all,4,3,0.000030517578125,0.000091552734375,0.000030517578125\n\
+             1,3,3,0.000030517578125,0.000091552734375,0.000030517578125\n\
+             2,1,0,0.0,0.0,0.0\n"
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use crate::ordering::tie_key;
+use txcli_model::Timestamp;
+
+// Holds back consecutive rows sharing a timestamp until the timestamp moves on, then releases
+// them highest priority first, e.g. so a network mandated chargeback lands before a withdrawal
+// stamped the same second. Ties keep input order, see ordering::tie_key.
+// Rows without a timestamp have nothing to be ordered against and pass straight through.
+pub struct PendingQueue<T> {
+    timestamp: Option<Timestamp>,
+    group: Vec<(Option<u8>, u64, T)>,
+    // Rows pushed so far, the arrival tie_key falls back on.
+    arrivals: u64,
+}
+
+impl<T> Default for PendingQueue<T> {
+    fn default() -> Self {
+        PendingQueue {
+            timestamp: None,
+            group: vec![],
+            arrivals: 0,
+        }
+    }
+}
+
+impl<T> PendingQueue<T> {
+    // Returns the rows ready to be applied, in the order to apply them.
+    pub fn push(&mut self, timestamp: Option<Timestamp>, priority: Option<u8>, row: T) -> Vec<T> {
+        let mut ready = match timestamp {
+            Some(_) if timestamp == self.timestamp => vec![],
+            _ => self.drain(),
+        };
+        self.timestamp = timestamp;
+        self.group.push((priority, self.arrivals, row));
+        self.arrivals += 1;
+        if timestamp.is_none() {
+            ready.extend(self.drain());
+        }
+        ready
+    }
+
+    // Releases whatever is held, at the end of the input.
+    pub fn drain(&mut self) -> Vec<T> {
+        self.group
+            .sort_unstable_by_key(|(priority, arrival, _)| tie_key(*priority, *arrival));
+        self.group.drain(..).map(|(_, _, row)| row).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reorders_within_a_timestamp() {
+        let mut queue = PendingQueue::default();
+        let at = |seconds| Some(Timestamp(seconds));
+        assert!(queue.push(at(1), None, "withdrawal").is_empty());
+        assert!(queue.push(at(1), Some(5), "chargeback").is_empty());
+        assert!(queue.push(at(1), Some(0), "deposit").is_empty());
+        assert!(queue.push(at(1), Some(5), "dispute").is_empty());
+        assert_eq!(
+            queue.push(at(2), Some(9), "resolve"),
+            ["chargeback", "dispute", "withdrawal", "deposit"]
+        );
+        // A later row with a higher priority can't jump an earlier timestamp.
+        assert_eq!(queue.push(None, Some(9), "untimed"), ["resolve", "untimed"]);
+        assert!(queue.push(at(1), None, "late").is_empty());
+        assert_eq!(queue.drain(), ["late"]);
+        assert!(queue.drain().is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+use crate::bootstrap::BalanceRow;
+use crate::AppState;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{ClientId, Currency};
+
+// Output is only printed to 4 decimal places, so an expected file produced by a previous run
+// is rounded. Allow one unit in the last printed place by default.
+pub const DEFAULT_TOLERANCE: &str = "0.0001";
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Break {
+    client: ClientId,
+    // Blank for the unnamed asset.
+    currency: Option<String>,
+    field: &'static str,
+    expected: String,
+    actual: String,
+}
+
+impl Break {
+    fn new(client: ClientId, field: &'static str, expected: String, actual: String) -> Self {
+        Break {
+            client,
+            currency: None,
+            field,
+            expected,
+            actual,
+        }
+    }
+
+    fn asset(self, currency: Option<&str>) -> Self {
+        Break {
+            currency: currency.map(String::from),
+            ..self
+        }
+    }
+
+    fn currency(
+        client: ClientId,
+        field: &'static str,
+        expected: Currency,
+        actual: Currency,
+    ) -> Self {
+        Break::new(
+            client,
+            field,
+            format!("{:.4}", expected),
+            format!("{:.4}", actual),
+        )
+    }
+}
+
+// Compares computed closing balances per client and asset, summed across the client's accounts,
+// against the expected ones. Clients or assets missing from either side are breaks as well, a
+// client silently appearing or disappearing is exactly what this should catch.
+pub fn reconcile(app_state: &AppState, expected: &[BalanceRow], tolerance: Currency) -> Vec<Break> {
+    let mut breaks = vec![];
+    let within = |a: Currency, b: Currency| a.abs_diff(b) <= tolerance.unsigned_abs();
+
+    let mut expected_clients = HashSet::new();
+    let mut expected_assets = HashSet::new();
+    for row in expected {
+        expected_clients.insert(row.client);
+        let client = match app_state.clients.get(&row.client) {
+            Some(client) => client,
+            None => {
+                breaks.push(Break::new(
+                    row.client,
+                    "client",
+                    "present".to_string(),
+                    "missing".to_string(),
+                ));
+                continue;
+            }
+        };
+
+        // A row without a currency is the unnamed asset, or the client's only asset when it holds
+        // just one, as files written before the currency column was read.
+        let assets: Vec<Option<&str>> = client.asset_names().collect();
+        let asset = match row.currency.as_deref() {
+            None if assets.len() == 1 => assets[0],
+            currency => currency,
+        };
+        expected_assets.insert((row.client, asset));
+        if !assets.is_empty() && !assets.contains(&asset) {
+            breaks.push(
+                Break::new(
+                    row.client,
+                    "currency",
+                    "present".to_string(),
+                    "missing".to_string(),
+                )
+                .asset(asset),
+            );
+            continue;
+        }
+
+        let (mut available, mut held) = (Currency::from_num(0), Currency::from_num(0));
+        for (_, _, balance) in client.balances().filter(|(name, _, _)| *name == asset) {
+            available += balance.available;
+            held += balance.held;
+        }
+        if !within(row.available, available) {
+            breaks.push(
+                Break::currency(row.client, "available", row.available, available).asset(asset),
+            );
+        }
+        if !within(row.held, held) {
+            breaks.push(Break::currency(row.client, "held", row.held, held).asset(asset));
+        }
+        if row.locked != client.locked {
+            breaks.push(Break::new(
+                row.client,
+                "locked",
+                row.locked.to_string(),
+                client.locked.to_string(),
+            ));
+        }
+    }
+
+    for (cid, client) in &app_state.clients {
+        if !expected_clients.contains(cid) {
+            breaks.push(Break::new(
+                *cid,
+                "client",
+                "missing".to_string(),
+                "present".to_string(),
+            ));
+            continue;
+        }
+        for asset in client.asset_names() {
+            if !expected_assets.contains(&(*cid, asset)) {
+                breaks.push(
+                    Break::new(
+                        *cid,
+                        "currency",
+                        "missing".to_string(),
+                        "present".to_string(),
+                    )
+                    .asset(asset),
+                );
+            }
+        }
+    }
+
+    breaks.sort_by(|a, b| (a.client.0, &a.currency).cmp(&(b.client.0, &b.currency)));
+    breaks
+}
+
+pub fn write_report<W: Write>(breaks: &[Break], writer: W) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for b in breaks {
+        writer.serialize(b)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::read_balances;
+    use crate::{Balance, ClientState};
+    use txcli_model::parse_amount;
+    use txcli_model::{AccountId, DEFAULT_ACCOUNT};
+
+    fn app_state(clients: &[(u16, f64, f64, bool)]) -> AppState {
+        let mut app_state = AppState::default();
+        for &(cid, available, held, locked) in clients {
+            app_state.clients.insert(
+                ClientId(cid),
+                ClientState {
+                    accounts: [(
+                        AccountId::default(),
+                        Balance {
+                            available: Currency::from_num(available),
+                            held: Currency::from_num(held),
+                            ..Default::default()
+                        },
+                    )]
+                    .into(),
+                    locked,
+                    ..Default::default()
+                },
+            );
+        }
+        app_state
+    }
+
+    fn tolerance() -> Currency {
+        parse_amount(DEFAULT_TOLERANCE).unwrap()
+    }
+
+    #[test]
+    fn matching_balances_within_tolerance() {
+        let app_state = app_state(&[(1, 1.11116, 0.0, false)]);
+        let expected = read_balances(
+            "client,available,held,total,locked\n1,1.1112,0,1.1112,false\n".as_bytes(),
+        )
+        .unwrap();
+        assert!(reconcile(&app_state, &expected, tolerance()).is_empty());
+    }
+
+    #[test]
+    fn mismatched_fields() {
+        let app_state = app_state(&[(1, 1.0, 0.5, true)]);
+        let expected =
+            read_balances("client,available,held,locked\n1,1.0,0.25,false\n".as_bytes()).unwrap();
+        let breaks = reconcile(&app_state, &expected, tolerance());
+        assert_eq!(
+            breaks,
+            vec![
+                Break::new(ClientId(1), "held", "0.2500".into(), "0.5000".into()),
+                Break::new(ClientId(1), "locked", "false".into(), "true".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_and_unexpected_clients() {
+        let app_state = app_state(&[(2, 1.0, 0.0, false)]);
+        let expected = read_balances("client,available\n1,1.0\n".as_bytes()).unwrap();
+        let breaks = reconcile(&app_state, &expected, tolerance());
+        assert_eq!(breaks.len(), 2);
+        assert_eq!(breaks[0].client, ClientId(1));
+        assert_eq!(breaks[0].actual, "missing");
+        assert_eq!(breaks[1].client, ClientId(2));
+        assert_eq!(breaks[1].expected, "missing");
+    }
+
+    #[test]
+    fn report_format() {
+        let mut out = vec![];
+        write_report(
+            &[Break::currency(
+                ClientId(3),
+                "available",
+                Currency::from_num(1),
+                Currency::from_num(2),
+            )],
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,currency,field,expected,actual\n3,,available,1.0000,2.0000\n"
+        );
+    }
+
+    #[test]
+    fn reconciles_each_asset() {
+        let mut app_state = app_state(&[]);
+        let client = app_state.clients.entry(ClientId(1)).or_default();
+        client.balance(Some("BTC"), DEFAULT_ACCOUNT).available = Currency::from_num(2);
+        client.balance(Some("USD"), DEFAULT_ACCOUNT).available = Currency::from_num(100);
+        let expected = read_balances(
+            "client,currency,available,held,total,locked\n1,BTC,2,0,2,false\n1,USD,90,0,90,false\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let breaks = reconcile(&app_state, &expected, tolerance());
+        assert_eq!(
+            breaks,
+            vec![Break::new(
+                ClientId(1),
+                "available",
+                "90.0000".into(),
+                "100.0000".into()
+            )
+            .asset(Some("USD"))]
+        );
+
+        // Every asset needs its row, and a row without a currency can't stand for two of them.
+        let expected = read_balances("client,available\n1,102\n".as_bytes()).unwrap();
+        let breaks = reconcile(&app_state, &expected, tolerance());
+        assert_eq!(breaks.len(), 3);
+        assert!(breaks.iter().all(|b| b.field == "currency"));
+    }
+}
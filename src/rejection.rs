@@ -0,0 +1,181 @@
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use txcli_model::{ClientId, TxId, TxRecord, TxType};
+
+// Why a row wasn't applied, returned by execute_transaction and TransactionEngine::try_apply so
+// callers can act on it without reading messages. The codes are stable, unlike the messages,
+// which follow --lang.
+#[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionReason {
+    // Not allowed against the referenced transaction's state, see dispute_state::rejection.
+    UnknownTx,
+    ReusedId,
+    InternalTransfer,
+    NoteReferenced,
+    AlreadyDisputed,
+    VoidDisputed,
+    NotDisputed,
+    ChargedBack,
+    Voided,
+    Expired,
+    UnderDispute,
+    // A reference row naming another currency than the transaction it references.
+    AssetMismatch,
+    InsufficientFunds,
+    // A transfer without a to_account, or to its own account.
+    InvalidTransfer,
+    // The client is locked and --locked-policy rejects the type.
+    Locked,
+    // Below the asset's dust threshold, ignored or swept.
+    Dust,
+    // The client already has --max-open-disputes open.
+    TooManyDisputes,
+    // A dispute, or a chargeback of one, still queued behind --max-open-disputes.
+    DisputeQueued,
+    // A custom type without a handler, or one its handler refused.
+    NoHandler,
+    CustomRefused,
+    // Over --max-amount, never reached the engine.
+    MaxAmount,
+    // Malformed whatever the state, see validation::check: a deposit, withdrawal or transfer of
+    // zero or less, an amount on a row referencing another transaction, or a note without a memo.
+    InvalidAmount,
+    UnexpectedAmount,
+    MissingMemo,
+    // The client is archived, or an archive_client or reactivate_client row that can't apply.
+    Archived,
+    NotArchived,
+    // Only clients without funds or disputes can be archived.
+    NotDormant,
+    UnknownClient,
+    // The history spill file failed, every row after it is rejected, see Engine::history_error.
+    HistoryUnavailable,
+}
+
+impl Display for RejectionReason {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        self.serialize(f)
+    }
+}
+
+impl Error for RejectionReason {}
+
+// One rejected row of the --errors log.
+#[derive(Serialize)]
+struct Rejection {
+    // Blank for cases from the disputes file, which aren't lines of the input.
+    line: Option<u64>,
+    client: ClientId,
+    tx: TxId,
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    reason: RejectionReason,
+}
+
+enum Format {
+    Csv(Box<csv::Writer<BufWriter<File>>>),
+    JsonLines(BufWriter<File>),
+}
+
+// Every rejected row with its reason code, csv when the path ends in .csv, JSON lines otherwise.
+pub struct RejectionLog {
+    format: Format,
+}
+
+impl RejectionLog {
+    pub fn create(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = BufWriter::new(File::create(path)?);
+        let format = if path.ends_with(".csv") {
+            Format::Csv(Box::new(csv::Writer::from_writer(file)))
+        } else {
+            Format::JsonLines(file)
+        };
+        Ok(RejectionLog { format })
+    }
+
+    pub fn record(
+        &mut self,
+        line: Option<u64>,
+        tx: &TxRecord,
+        reason: RejectionReason,
+    ) -> Result<(), Box<dyn Error>> {
+        let rejection = Rejection {
+            line,
+            client: tx.cid,
+            tx: tx.tid,
+            tx_type: tx.tx_type,
+            reason,
+        };
+        match &mut self.format {
+            Format::Csv(writer) => writer.serialize(rejection)?,
+            Format::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, &rejection)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            Format::Csv(mut writer) => writer.flush()?,
+            Format::JsonLines(mut writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use std::fs;
+    use txcli_model::Currency;
+
+    #[test]
+    fn csv_and_json_lines() {
+        let mut engine = EngineBuilder::new().build();
+        let deposit = TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1));
+        let withdrawal = TxRecord::new(TxType::Withdrawal, 1, 2, Currency::from_num(2));
+        let dispute = TxRecord::new(TxType::Dispute, 2, 9, Currency::from_num(0));
+        assert_eq!(engine.try_apply(deposit), Ok(()));
+        assert_eq!(
+            engine.try_apply(withdrawal),
+            Err(RejectionReason::InsufficientFunds)
+        );
+        assert_eq!(engine.try_apply(dispute), Err(RejectionReason::UnknownTx));
+        assert_eq!(
+            RejectionReason::TooManyDisputes.to_string(),
+            "too_many_disputes"
+        );
+
+        let dir = std::env::temp_dir().join(format!("txcli-rejections-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let contents = |name: &str| {
+            let path = dir.join(name);
+            let mut log = RejectionLog::create(path.to_str().unwrap()).unwrap();
+            log.record(Some(3), &withdrawal, RejectionReason::InsufficientFunds)
+                .unwrap();
+            log.record(None, &dispute, RejectionReason::UnknownTx)
+                .unwrap();
+            log.finish().unwrap();
+            fs::read_to_string(path).unwrap()
+        };
+        assert_eq!(
+            contents("rejections.csv"),
+            "line,client,tx,type,reason\n\
+            3,1,2,withdrawal,insufficient_funds\n\
+            ,2,9,dispute,unknown_tx\n"
+        );
+        assert_eq!(
+            contents("rejections.jsonl"),
+            "{\"line\":3,\"client\":1,\"tx\":2,\"type\":\"withdrawal\",\"reason\":\"insufficient_funds\"}\n\
+            {\"line\":null,\"client\":2,\"tx\":9,\"type\":\"dispute\",\"reason\":\"unknown_tx\"}\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{ClientId, Currency, TxType};
+
+const UNCATEGORIZED: &str = "uncategorized";
+
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+struct Flows {
+    earned: Currency,
+    spent: Currency,
+}
+
+impl Flows {
+    fn add(&mut self, tx_type: TxType, amount: Currency) {
+        match tx_type {
+            TxType::Deposit => self.earned += amount,
+            TxType::Withdrawal => self.spent += amount,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CategoryRow<'a> {
+    client: String,
+    category: &'a str,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    earned: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    spent: Currency,
+}
+
+// Earned/spent breakdown by category, per client and across all clients.
+// Only applied deposits and withdrawals count, transfers and the dispute lifecycle are not income or spending.
+#[derive(Default)]
+pub struct CategoryReport {
+    global: BTreeMap<String, Flows>,
+    per_client: BTreeMap<(ClientId, String), Flows>,
+}
+
+impl CategoryReport {
+    pub fn record(
+        &mut self,
+        cid: ClientId,
+        tx_type: TxType,
+        amount: Currency,
+        category: Option<&str>,
+    ) {
+        if !matches!(tx_type, TxType::Deposit | TxType::Withdrawal) {
+            return;
+        }
+        let category = category.unwrap_or(UNCATEGORIZED);
+        self.global
+            .entry(category.to_string())
+            .or_default()
+            .add(tx_type, amount);
+        self.per_client
+            .entry((cid, category.to_string()))
+            .or_default()
+            .add(tx_type, amount);
+    }
+
+    // Global rows come first with client "all", then each client ordered by id and category.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        let global = self
+            .global
+            .iter()
+            .map(|(category, flows)| ("all".to_string(), category, flows));
+        let per_client = self
+            .per_client
+            .iter()
+            .map(|((cid, category), flows)| (cid.0.to_string(), category, flows));
+        for (client, category, flows) in global.chain(per_client) {
+            writer.serialize(CategoryRow {
+                client,
+                category,
+                earned: flows.earned,
+                spent: flows.spent,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breakdown_by_category() {
+        let mut report = CategoryReport::default();
+        report.record(
+            ClientId(2),
+            TxType::Deposit,
+            Currency::from_num(10),
+            Some("salary"),
+        );
+        report.record(
+            ClientId(1),
+            TxType::Withdrawal,
+            Currency::from_num(3),
+            Some("groceries"),
+        );
+        report.record(
+            ClientId(2),
+            TxType::Withdrawal,
+            Currency::from_num(1.5),
+            Some("groceries"),
+        );
+        report.record(ClientId(1), TxType::Deposit, Currency::from_num(4), None);
+        report.record(
+            ClientId(1),
+            TxType::Dispute,
+            Currency::from_num(4),
+            Some("salary"),
+        );
+
+        let mut out = vec![];
+        report.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,category,earned,spent\n\
+             all,groceries,0.0000,4.5000\n\
+             all,salary,10.0000,0.0000\n\
+             all,uncategorized,4.0000,0.0000\n\
+             1,groceries,0.0000,3.0000\n\
+             1,uncategorized,4.0000,0.0000\n\
+             2,groceries,0.0000,1.5000\n\
+             2,salary,10.0000,0.0000\n"
+        );
+    }
+}
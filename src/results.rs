@@ -0,0 +1,307 @@
+use crate::BasicError;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::str::FromStr;
+use txcli_model::{ClientOutputState, Currency};
+
+// How the balances are written, see Report::balances_where.
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    // An array of client rows as --cdc-out writes them, amounts as 4 decimal strings.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(BasicError::new(format!(
+                "Unknown output format {}, expected csv or json.",
+                s
+            ))),
+        }
+    }
+}
+
+// Every column a balances row can have, in the order they come by default. Most only come when
+// the run produces them, dispute_count only when --columns selects it.
+pub const COLUMNS: [&str; 13] = [
+    "client",
+    "currency",
+    "account",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "owners",
+    "penalties",
+    "chargeback_fees",
+    "written_off",
+    "pending",
+    "dispute_count",
+];
+
+// --columns, a comma separated list of known columns, each at most once.
+pub fn parse_columns(list: &str) -> Result<Vec<&'static str>, Box<dyn Error>> {
+    let mut columns = vec![];
+    for name in list.split(',').map(str::trim) {
+        let Some(column) = COLUMNS.iter().find(|column| **column == name) else {
+            return Err(BasicError::new(format!(
+                "Unknown column {}, expected some of {}.",
+                name,
+                COLUMNS.join(", ")
+            )));
+        };
+        if columns.contains(column) {
+            return Err(BasicError::new(format!("Column {} is listed twice.", name)));
+        }
+        columns.push(*column);
+    }
+    Ok(columns)
+}
+
+// A column of client as the csv has it, blank when the row has no value for it.
+fn cell(client: &ClientOutputState, column: &str) -> String {
+    let amount = |amount: Option<Currency>| {
+        amount.map_or_else(String::new, |amount| format!("{:.4}", amount))
+    };
+    match column {
+        "client" => client.cid.0.to_string(),
+        "currency" => client.currency.clone().unwrap_or_default(),
+        "account" => client
+            .account
+            .as_ref()
+            .map(|account| account.0.clone())
+            .unwrap_or_default(),
+        "available" => amount(Some(client.available)),
+        "held" => amount(Some(client.held)),
+        "total" => amount(Some(client.total)),
+        "locked" => client.locked.to_string(),
+        "owners" => client.owners.clone().unwrap_or_default(),
+        "penalties" => amount(client.penalties),
+        "chargeback_fees" => amount(client.chargeback_fees),
+        "written_off" => amount(client.written_off),
+        "dispute_count" => client
+            .dispute_count
+            .map(|count| count.to_string())
+            .unwrap_or_default(),
+        "pending" => amount(client.pending),
+        _ => unreachable!("columns come from COLUMNS"),
+    }
+}
+
+// A client's JSON object with the report's columns as fields, in their order. Fields are named
+// and typed as ClientOutputState serializes them, so client is cid and a column the row has no
+// value for is null.
+struct JsonRow<'a> {
+    columns: &'a [&'static str],
+    client: &'a ClientOutputState,
+}
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let serde_json::Value::Object(mut fields) =
+            serde_json::to_value(self.client).map_err(serde::ser::Error::custom)?
+        else {
+            unreachable!("client rows serialize to an object");
+        };
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for column in self.columns {
+            let field = match *column {
+                "client" => "cid",
+                column => column,
+            };
+            let value = fields.remove(field).unwrap_or_default();
+            map.serialize_entry(field, &value)?;
+        }
+        map.end()
+    }
+}
+
+// Sums across every client row in the report.
+#[derive(Serialize, Debug, Default, PartialEq, Clone)]
+pub struct Totals {
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    pub available: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    pub held: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    pub total: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    pub penalties_assessed: Currency,
+    // Assessed on chargebacks, charged to clients or counterparties.
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    pub chargeback_fees: Currency,
+    // Held funds chargebacks took from clients, other than charged back withdrawals.
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    pub written_off: Currency,
+    // Deposits still waiting out a settlement delay when the run closed, counted in total.
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    pub pending: Currency,
+    // What disputes couldn't hold under the partial-hold dispute policy.
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    pub shortfall: Currency,
+    // Dust collected under the sweep policy, per asset code.
+    pub swept: BTreeMap<String, Currency>,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, Clone)]
+pub struct Stats {
+    // Rows handed to the engine, applied or not.
+    pub processed: u64,
+    pub applied: u64,
+    pub clients: u64,
+    pub locked: u64,
+    // Disputes still waiting behind --max-open-disputes, never opened.
+    pub queued_disputes: u64,
+    // Rows over --latency-budget and the slowest row timed, both 0 without a budget.
+    pub slow_rows: u64,
+    pub slowest_row_us: u64,
+}
+
+// Everything the engine can say about a run, built by Engine::report. The csv on stdout, the
+// interim reports and the redis hashes are all renderings of it, other callers can render their
+// own without going through csv. Clients are ordered by id, then account, so runs diff cleanly.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Report {
+    // The columns of the balances in output order, the optional ones the run produces or
+    // those --columns selected.
+    pub columns: Vec<&'static str>,
+    pub clients: Vec<ClientOutputState>,
+    pub totals: Totals,
+    // Rows the engine didn't apply, per transaction type.
+    pub rejects: BTreeMap<String, u64>,
+    pub stats: Stats,
+}
+
+impl Report {
+    // One client's row of the balances csv.
+    pub fn record(&self, client: &ClientOutputState) -> csv::StringRecord {
+        self.columns
+            .iter()
+            .map(|column| cell(client, column))
+            .collect()
+    }
+
+    // The balances as txcli prints them, header included.
+    pub fn balances_csv(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.balances_csv_where(|_| true)
+    }
+
+    // The balances with only the rows keep is true of, the header whatever they are.
+    pub fn balances_csv_where(
+        &self,
+        keep: impl Fn(&ClientOutputState) -> bool,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(&self.columns)?;
+        for client in self.clients.iter().filter(|client| keep(client)) {
+            writer.write_record(&self.record(client))?;
+        }
+        Ok(writer.into_inner()?)
+    }
+
+    // The balances in either format with only the rows keep is true of.
+    pub fn balances_where(
+        &self,
+        format: OutputFormat,
+        keep: impl Fn(&ClientOutputState) -> bool,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        match format {
+            OutputFormat::Csv => self.balances_csv_where(keep),
+            OutputFormat::Json => {
+                let clients: Vec<_> = self
+                    .clients
+                    .iter()
+                    .filter(|client| keep(client))
+                    .map(|client| JsonRow {
+                        columns: &self.columns,
+                        client,
+                    })
+                    .collect();
+                let mut out = serde_json::to_vec(&clients)?;
+                out.push(b'\n');
+                Ok(out)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use txcli_model::{TxRecord, TxType};
+
+    #[test]
+    fn typed_report() {
+        let mut engine = EngineBuilder::new().build();
+        let row = |tx_type, cid, tid, amount: u32| {
+            TxRecord::new(tx_type, cid, tid, Currency::from_num(amount))
+        };
+        engine.apply(row(TxType::Deposit, 2, 1, 10));
+        engine.apply(row(TxType::Deposit, 1, 2, 5));
+        engine.apply(row(TxType::Withdrawal, 1, 3, 50));
+        engine.apply(row(TxType::Dispute, 2, 1, 0));
+        engine.apply(row(TxType::Dispute, 2, 9, 0));
+        engine.finish();
+        let report = engine.report();
+        assert_eq!(report.clients.len(), 2);
+        assert_eq!(report.totals.available, Currency::from_num(5));
+        assert_eq!(report.totals.held, Currency::from_num(10));
+        assert_eq!(report.totals.total, Currency::from_num(15));
+        assert_eq!(report.rejects.len(), 2);
+        assert_eq!(report.rejects["withdrawal"], 1);
+        assert_eq!(report.rejects["dispute"], 1);
+        assert_eq!(
+            (
+                report.stats.processed,
+                report.stats.applied,
+                report.stats.clients
+            ),
+            (5, 3, 2)
+        );
+        assert_eq!(
+            String::from_utf8(report.balances_csv().unwrap()).unwrap(),
+            "client,available,held,total,locked\n\
+             1,5.0000,0.0000,5.0000,false\n\
+             2,0.0000,10.0000,10.0000,false\n"
+        );
+        let json = report
+            .balances_where(OutputFormat::Json, |client| client.cid.0 == 2)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[{\"cid\":2,\"available\":\"0.0000\",\"held\":\"10.0000\",\"total\":\"10.0000\",\"locked\":false}]\n"
+        );
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn selected_columns() {
+        let columns = parse_columns("total,client,dispute_count,penalties").unwrap();
+        let mut engine = EngineBuilder::new().columns(columns).build();
+        engine.apply(TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(3)));
+        engine.apply(TxRecord::new(TxType::Dispute, 1, 1, Currency::ZERO));
+        let report = engine.report();
+        assert_eq!(
+            String::from_utf8(report.balances_csv().unwrap()).unwrap(),
+            "total,client,dispute_count,penalties\n3.0000,1,1,0.0000\n"
+        );
+        assert_eq!(
+            String::from_utf8(report.balances_where(OutputFormat::Json, |_| true).unwrap())
+                .unwrap(),
+            "[{\"total\":\"3.0000\",\"cid\":1,\"dispute_count\":1,\"penalties\":\"0.0000\"}]\n"
+        );
+        assert!(parse_columns("client,balance").is_err());
+        assert!(parse_columns("held,held").is_err());
+    }
+}
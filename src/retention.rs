@@ -0,0 +1,235 @@
+use crate::dispute_state::DisputeState;
+use crate::{BasicError, ClientState};
+use serde::{Serialize, Serializer};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use txcli_model::{TxId, TxRecord, TxType};
+
+// How much of each client's history the engine keeps. History is what disputes, resolves,
+// chargebacks and voids find their transaction in, and the bulk of the memory on long inputs.
+// Whatever is pruned leaves its DisputeState behind in the client's lifecycle, so a later row
+// reusing the tid or referencing it is rejected with the right reason rather than taken for new.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Retention {
+    // Each client keeps its N most recent deposits, withdrawals and transfers, older ones expire
+    // and can't be disputed or voided from then on. This is a dispute window, counted in rows
+    // rather than time. A transaction under dispute when it would expire is kept.
+    LastN(usize),
+    // Drops what no row can move again: charged back transactions and transfers, which can't
+    // be disputed. Same results as keeping everything.
+    AmountBearing,
+    // Drops charged back transactions, their dispute is over for good. Same results as keeping
+    // everything. Resolved ones are kept, they can be disputed again.
+    UntilResolved,
+}
+
+impl FromStr for Retention {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "amount-bearing" => Ok(Retention::AmountBearing),
+            "until-resolved" => Ok(Retention::UntilResolved),
+            _ => match s.strip_prefix("last-").map(str::parse) {
+                Some(Ok(n)) if n > 0 => Ok(Retention::LastN(n)),
+                _ => Err(BasicError::new(format!(
+                    "Unknown history retention {}, expected last-<n>, amount-bearing or until-resolved.",
+                    s
+                ))),
+            },
+        }
+    }
+}
+
+impl Display for Retention {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Retention::LastN(n) => write!(f, "last-{}", n),
+            Retention::AmountBearing => write!(f, "amount-bearing"),
+            Retention::UntilResolved => write!(f, "until-resolved"),
+        }
+    }
+}
+
+impl Serialize for Retention {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+// Drops tid from history, from then on it only exists as state.
+fn forget(client: &mut ClientState, tid: TxId, state: DisputeState) {
+    if client.history.remove(&tid).is_some() {
+        client.lifecycle.insert(tid, state);
+    }
+}
+
+// Takes tid out of the dispute window, unless it is under dispute right now.
+pub fn expire(client: &mut ClientState, tid: TxId) {
+    forget(client, tid, DisputeState::Expired);
+}
+
+// Prunes after tx was applied to client. recent is the client's window under LastN, oldest first.
+pub fn retain(
+    retention: Retention,
+    client: &mut ClientState,
+    recent: &mut VecDeque<TxId>,
+    tx: &TxRecord,
+) {
+    match (retention, tx.tx_type) {
+        (_, TxType::ChargeBack) => forget(client, tx.tid, DisputeState::ChargedBack),
+        (Retention::AmountBearing, TxType::Transfer) => {
+            forget(client, tx.tid, DisputeState::Internal)
+        }
+        (Retention::LastN(n), TxType::Deposit | TxType::Withdrawal | TxType::Transfer) => {
+            recent.push_back(tx.tid);
+            while recent.len() > n {
+                if let Some(oldest) = recent.pop_front() {
+                    expire(client, oldest);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Engine, EngineBuilder};
+    use crate::sample::SplitMix64;
+    use std::collections::HashMap;
+    use txcli_model::{ClientId, Currency, TxType};
+
+    #[test]
+    fn parse_policies() {
+        for text in ["last-100", "amount-bearing", "until-resolved"] {
+            assert_eq!(text.parse::<Retention>().unwrap().to_string(), text);
+        }
+        for text in ["last-0", "last-", "last-x", "all"] {
+            assert!(text.parse::<Retention>().is_err(), "{}", text);
+        }
+    }
+
+    // Deposits, withdrawals, transfers and references to earlier ones for a handful of clients,
+    // with tids reused now and then.
+    fn rows(seed: u64) -> Vec<TxRecord<'static>> {
+        let mut rng = SplitMix64(seed);
+        let mut rows = vec![];
+        for tid in 1..=400u32 {
+            let cid = (rng.next() % 4) as u16;
+            let earlier = 1 + (rng.next() % tid as u64) as u32;
+            let amount = Currency::from_num(1 + rng.next() % 50);
+            rows.push(match rng.next() % 10 {
+                0..=2 => TxRecord::new(TxType::Deposit, cid, tid, amount),
+                3 => TxRecord::new(TxType::Withdrawal, cid, tid, amount),
+                4 => TxRecord::new(TxType::Transfer, cid, tid, amount).with_to_account("savings"),
+                // Rows referencing another transaction take no amount, see validation::check.
+                5 => TxRecord::new(TxType::Dispute, cid, earlier, Currency::ZERO),
+                6 => TxRecord::new(TxType::Resolve, cid, earlier, Currency::ZERO),
+                7 => TxRecord::new(TxType::ChargeBack, cid, earlier, Currency::ZERO),
+                8 => TxRecord::new(TxType::Void, cid, earlier, Currency::ZERO),
+                _ => TxRecord::new(TxType::Deposit, cid, earlier, amount),
+            });
+        }
+        rows
+    }
+
+    fn run(retention: Option<Retention>, rows: &[TxRecord]) -> (Engine, Vec<bool>) {
+        let mut builder = EngineBuilder::new();
+        if let Some(retention) = retention {
+            builder = builder.retention(retention);
+        }
+        let mut engine = builder.build();
+        let applied = rows.iter().map(|tx| engine.apply(*tx)).collect();
+        (engine, applied)
+    }
+
+    fn history_len(engine: &Engine) -> usize {
+        engine
+            .state()
+            .clients
+            .values()
+            .map(|client| client.history.len())
+            .sum()
+    }
+
+    #[test]
+    fn pruning_without_a_window_changes_nothing() {
+        for seed in 0..20 {
+            let rows = rows(seed);
+            let (kept, applied) = run(None, &rows);
+            for retention in [Retention::UntilResolved, Retention::AmountBearing] {
+                let (pruned, pruned_applied) = run(Some(retention), &rows);
+                assert_eq!(pruned_applied, applied, "seed {} {}", seed, retention);
+                assert_eq!(pruned.report().clients.len(), kept.report().clients.len());
+                for cid in kept.state().clients.keys() {
+                    assert_eq!(
+                        pruned.state().output_state(*cid),
+                        kept.state().output_state(*cid)
+                    );
+                }
+                assert!(history_len(&pruned) <= history_len(&kept));
+            }
+        }
+    }
+
+    // Under last-N, nothing among a client's N latest transactions ever expires, so every row
+    // referencing one of them is decided as it would be with the whole history.
+    #[test]
+    fn window_keeps_every_recent_transaction() {
+        let n = 5;
+        for seed in 0..20 {
+            let rows = rows(seed);
+            let (kept, _) = run(None, &rows);
+            let mut engine = EngineBuilder::new().retention(Retention::LastN(n)).build();
+            let mut windows: HashMap<ClientId, VecDeque<TxId>> = HashMap::new();
+            for tx in &rows {
+                let applied = engine.apply(*tx);
+                let window = windows.entry(tx.cid).or_default();
+                if applied
+                    && matches!(
+                        tx.tx_type,
+                        TxType::Deposit | TxType::Withdrawal | TxType::Transfer
+                    )
+                {
+                    window.push_back(tx.tid);
+                    if window.len() > n {
+                        window.pop_front();
+                    }
+                }
+                let client = &engine.state().clients[&tx.cid];
+                for tid in window.iter() {
+                    assert_ne!(
+                        client.dispute_state(*tid),
+                        DisputeState::Expired,
+                        "seed {} tid {}",
+                        seed,
+                        tid.0
+                    );
+                }
+            }
+            assert!(history_len(&engine) < history_len(&kept));
+        }
+    }
+
+    #[test]
+    fn expired_transactions_are_rejected() {
+        let mut engine = EngineBuilder::new().retention(Retention::LastN(1)).build();
+        let deposit = |tid| TxRecord::new(TxType::Deposit, 1, tid, Currency::from_num(10));
+        let dispute = |tid| TxRecord::new(TxType::Dispute, 1, tid, Currency::from_num(0));
+        assert!(engine.apply(deposit(1)));
+        assert!(engine.apply(dispute(1)));
+        // Under dispute, so it survives falling out of the window.
+        assert!(engine.apply(deposit(2)));
+        assert!(engine.apply(TxRecord::new(TxType::Resolve, 1, 1, Currency::from_num(0))));
+        assert!(engine.apply(deposit(3)));
+        assert!(!engine.apply(dispute(2)));
+        assert!(!engine.apply(deposit(2)));
+        let client = &engine.state().clients[&ClientId(1)];
+        assert_eq!(client.dispute_state(TxId(2)), DisputeState::Expired);
+        assert_eq!(client.dispute_state(TxId(1)), DisputeState::Resolved);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::dispute_state::DisputeState;
+use crate::validation;
+use crate::AppState;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use txcli_model::{Currency, TxRecord, TxType};
+
+// Whether a rejection of tx against the state before it could be accepted by a later run:
+// a withdrawal or transfer waiting on funds, or a dispute, resolve or chargeback of a
+// transaction the client doesn't have yet. Anything else would be rejected again.
+pub fn retriable(state: &AppState, tx: &TxRecord) -> bool {
+    if validation::malformed(tx).is_some() {
+        return false;
+    }
+    let client = state.clients.get(&state.canonical(tx.cid));
+    let available = client
+        .and_then(|client| client.balance_of(tx.currency, tx.account))
+        .map_or(Currency::from_num(0), |balance| balance.available);
+    let known = client.is_some_and(|client| client.dispute_state(tx.tid) != DisputeState::Unknown);
+    match tx.tx_type {
+        TxType::Deposit => false,
+        TxType::Withdrawal => available < tx.amount,
+        TxType::Transfer => {
+            tx.to_account.is_some_and(|to| to != tx.account) && available < tx.amount
+        }
+        TxType::Dispute | TxType::Resolve | TxType::ChargeBack | TxType::Void => !known,
+        // Admin rows and notes, whoever sent them decides whether to send them again.
+        TxType::ArchiveClient | TxType::ReactivateClient | TxType::Note => false,
+        // Refused by its handler, which may well refuse it again.
+        TxType::Custom(_) => false,
+    }
+}
+
+// Copies retriable rejected rows verbatim under the input's header, so the file can be
+// appended to or processed ahead of the next input.
+pub struct RetryWriter<W: Write> {
+    writer: csv::Writer<W>,
+    written: usize,
+}
+
+impl RetryWriter<BufWriter<File>> {
+    pub fn create(path: &str, headers: &csv::StringRecord) -> Result<Self, Box<dyn Error>> {
+        RetryWriter::new(BufWriter::new(File::create(path)?), headers)
+    }
+}
+
+impl<W: Write> RetryWriter<W> {
+    pub fn new(writer: W, headers: &csv::StringRecord) -> Result<Self, Box<dyn Error>> {
+        // v1 rows may leave out the optional trailing columns.
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(writer);
+        writer.write_record(headers)?;
+        Ok(RetryWriter { writer, written: 0 })
+    }
+
+    pub fn record(&mut self, row: &csv::StringRecord) -> Result<(), Box<dyn Error>> {
+        self.writer.write_record(row)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.written
+    }
+
+    pub fn finish(self) -> Result<W, Box<dyn Error>> {
+        Ok(self.writer.into_inner().map_err(|err| err.into_error())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_transaction;
+
+    #[test]
+    fn retriable_rejections() {
+        let mut state = AppState::default();
+        let tx =
+            |tx_type, tid, amount: u32| TxRecord::new(tx_type, 1, tid, Currency::from_num(amount));
+        execute_transaction(&mut state, tx(TxType::Deposit, 1, 5)).unwrap();
+        execute_transaction(&mut state, tx(TxType::Deposit, 2, 5)).unwrap();
+        execute_transaction(&mut state, tx(TxType::Dispute, 2, 0)).unwrap();
+
+        assert!(retriable(&state, &tx(TxType::Withdrawal, 3, 6)));
+        assert!(!retriable(&state, &tx(TxType::Withdrawal, 3, 5)));
+        let mut transfer = tx(TxType::Transfer, 4, 6);
+        assert!(!retriable(&state, &transfer));
+        transfer.to_account = Some("savings");
+        assert!(retriable(&state, &transfer));
+        assert!(retriable(&state, &tx(TxType::Dispute, 9, 0)));
+        assert!(retriable(&state, &tx(TxType::ChargeBack, 9, 0)));
+        // Already disputed, or never disputed, won't change by waiting for more input.
+        assert!(!retriable(&state, &tx(TxType::Dispute, 2, 0)));
+        assert!(!retriable(&state, &tx(TxType::Resolve, 1, 0)));
+        assert!(!retriable(&state, &tx(TxType::Deposit, 10, 1)));
+        // Malformed rows are rejected however much input follows.
+        assert!(!retriable(&state, &tx(TxType::Dispute, 9, 1)));
+        assert!(retriable(
+            &state,
+            &TxRecord::new(TxType::Withdrawal, 2, 11, Currency::from_num(1))
+        ));
+    }
+
+    #[test]
+    fn rows_copied_verbatim() {
+        let headers = csv::StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        let mut retry = RetryWriter::new(vec![], &headers).unwrap();
+        retry
+            .record(&csv::StringRecord::from(vec![
+                "withdrawal",
+                "1",
+                "3",
+                "1.50",
+            ]))
+            .unwrap();
+        retry
+            .record(&csv::StringRecord::from(vec!["dispute", "1", "9"]))
+            .unwrap();
+        assert_eq!(retry.len(), 2);
+        assert_eq!(
+            String::from_utf8(retry.finish().unwrap()).unwrap(),
+            "type,client,tx,amount\nwithdrawal,1,3,1.50\ndispute,1,9\n"
+        );
+    }
+}
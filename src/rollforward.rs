@@ -0,0 +1,274 @@
+use crate::{AppState, BasicError};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::io::Write;
+use std::str::FromStr;
+use txcli_model::timestamp::civil_from_days;
+use txcli_model::{ClientId, Currency, Timestamp, TxType};
+
+// How --rollforward cuts the input into periods.
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    // Monday to Sunday, named after the Monday, e.g. 2024-03-04.
+    Weekly,
+    // Calendar months, e.g. 2024-03.
+    Monthly,
+}
+
+impl FromStr for Period {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "weekly" => Ok(Period::Weekly),
+            "monthly" => Ok(Period::Monthly),
+            _ => Err(BasicError::new(format!(
+                "Unknown roll-forward period {}, expected weekly or monthly.",
+                s
+            ))),
+        }
+    }
+}
+
+impl Period {
+    // The name of the period timestamp falls in, UTC.
+    pub fn of(self, timestamp: Timestamp) -> String {
+        let days = timestamp.days();
+        match self {
+            Period::Weekly => {
+                // The epoch was a Thursday.
+                let monday = days - (days + 3).rem_euclid(7);
+                let (year, month, day) = civil_from_days(monday);
+                format!("{:04}-{:02}-{:02}", year, month, day)
+            }
+            Period::Monthly => {
+                let (year, month, _) = civil_from_days(days);
+                format!("{:04}-{:02}", year, month)
+            }
+        }
+    }
+}
+
+// What moved a client's total within one period.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+struct Movements {
+    deposits: Currency,
+    withdrawals: Currency,
+    // Put on hold by disputes, which doesn't change the total.
+    disputes_held: Currency,
+    // Taken back by chargebacks, negative when charged back withdrawals returned more.
+    chargebacks: Currency,
+}
+
+#[derive(Serialize)]
+struct RollForwardRow<'a> {
+    period: &'a str,
+    client: ClientId,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    opening: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    deposits: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    withdrawals: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    disputes_held: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    chargebacks: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    other: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    closing: Currency,
+}
+
+// Per period and client, the opening total (available plus held), what deposits, withdrawals and
+// chargebacks moved, what disputes put on hold, and the closing total. other is whatever else
+// moved the total, voids, overdraft penalties and custom rows among them, so that opening +
+// deposits - withdrawals - chargebacks + other is always closing. Each period closes on the
+// state the engine had when the first row of the next one came, so periods follow the input's
+// order: a row without a timestamp, or stamped before the current period, counts in the current
+// one, rows before the first timestamp in the first. An input without timestamps is one period,
+// named all. Clients holding several assets get their assets summed.
+pub struct RollForward {
+    period: Period,
+    current: Option<String>,
+    // Every client's total when the current period opened.
+    opening: BTreeMap<ClientId, Currency>,
+    movements: BTreeMap<ClientId, Movements>,
+    rows: Vec<u8>,
+}
+
+fn totals(state: &AppState) -> BTreeMap<ClientId, Currency> {
+    state
+        .clients
+        .iter()
+        .map(|(cid, client)| (*cid, client.available() + client.held()))
+        .collect()
+}
+
+impl RollForward {
+    pub fn new(period: Period) -> Self {
+        RollForward {
+            period,
+            current: None,
+            opening: BTreeMap::new(),
+            movements: BTreeMap::new(),
+            rows: vec![],
+        }
+    }
+
+    // Before each row is applied, closes the current period when timestamp starts the next.
+    pub fn begin(
+        &mut self,
+        state: &AppState,
+        timestamp: Option<Timestamp>,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(period) = timestamp.map(|timestamp| self.period.of(timestamp)) else {
+            return Ok(());
+        };
+        match &self.current {
+            Some(current) if *current >= period => {}
+            Some(_) => {
+                self.close(state)?;
+                self.current = Some(period);
+            }
+            None => self.current = Some(period),
+        }
+        Ok(())
+    }
+
+    // After a row is applied, with its client's canonical id and held balance before it.
+    pub fn record(
+        &mut self,
+        state: &AppState,
+        cid: ClientId,
+        tx_type: TxType,
+        before: (Currency, Currency),
+    ) {
+        let (total, held) = RollForward::before(state, cid);
+        let moved = total - before.0;
+        let movements = self.movements.entry(cid).or_default();
+        match tx_type {
+            TxType::Deposit => movements.deposits += moved,
+            TxType::Withdrawal => movements.withdrawals -= moved,
+            TxType::ChargeBack => movements.chargebacks -= moved,
+            TxType::Dispute => movements.disputes_held += held - before.1,
+            _ => {}
+        }
+    }
+
+    // A client's total and held balance, to pass back to record once its row is applied.
+    pub fn before(state: &AppState, cid: ClientId) -> (Currency, Currency) {
+        state
+            .clients
+            .get(&cid)
+            .map_or((Currency::ZERO, Currency::ZERO), |client| {
+                (client.available() + client.held(), client.held())
+            })
+    }
+
+    fn close(&mut self, state: &AppState) -> Result<(), Box<dyn Error>> {
+        let closing = totals(state);
+        let period = self.current.as_deref().unwrap_or("all");
+        let clients: BTreeSet<ClientId> = self
+            .opening
+            .keys()
+            .chain(closing.keys())
+            .chain(self.movements.keys())
+            .copied()
+            .collect();
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(self.rows.is_empty())
+            .from_writer(&mut self.rows);
+        for client in clients {
+            let opening = self.opening.get(&client).copied().unwrap_or_default();
+            let closed = closing.get(&client).copied().unwrap_or_default();
+            let moved = self.movements.get(&client).copied().unwrap_or_default();
+            if opening == 0 && closed == 0 && moved == Movements::default() {
+                continue;
+            }
+            writer.serialize(RollForwardRow {
+                period,
+                client,
+                opening,
+                deposits: moved.deposits,
+                withdrawals: moved.withdrawals,
+                disputes_held: moved.disputes_held,
+                chargebacks: moved.chargebacks,
+                other: closed - opening - moved.deposits + moved.withdrawals + moved.chargebacks,
+                closing: closed,
+            })?;
+        }
+        writer.flush()?;
+        drop(writer);
+        self.opening = closing;
+        self.movements.clear();
+        Ok(())
+    }
+
+    // Closes the last period on the final state and writes every period, oldest first.
+    pub fn write<W: Write>(
+        mut self,
+        state: &AppState,
+        mut writer: W,
+    ) -> Result<(), Box<dyn Error>> {
+        self.close(state)?;
+        writer.write_all(&self.rows)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use txcli_model::TxRecord;
+
+    #[test]
+    fn period_names() {
+        // 2024-03-06 was a Wednesday.
+        let wednesday: Timestamp = "2024-03-06T10:00:00Z".parse().unwrap();
+        assert_eq!(Period::Weekly.of(wednesday), "2024-03-04");
+        assert_eq!(Period::Monthly.of(wednesday), "2024-03");
+        let sunday: Timestamp = "2024-03-10T23:59:59Z".parse().unwrap();
+        assert_eq!(Period::Weekly.of(sunday), "2024-03-04");
+        assert!("daily".parse::<Period>().is_err());
+    }
+
+    #[test]
+    fn rolls_forward_each_month() {
+        let mut engine = EngineBuilder::new().build();
+        let mut rollforward = RollForward::new(Period::Monthly);
+        let stamped = |tx_type, cid, tid, amount: f64, date: &str| {
+            let mut tx = TxRecord::new(tx_type, cid, tid, Currency::from_num(amount));
+            tx.timestamp = Some(date.parse().unwrap());
+            tx
+        };
+        for tx in [
+            stamped(TxType::Deposit, 1, 1, 10.0, "2024-01-05"),
+            stamped(TxType::Deposit, 1, 2, 5.0, "2024-01-20"),
+            stamped(TxType::Withdrawal, 1, 3, 3.0, "2024-01-31"),
+            stamped(TxType::Dispute, 1, 2, 0.0, "2024-02-02"),
+            stamped(TxType::ChargeBack, 1, 2, 0.0, "2024-02-10"),
+            stamped(TxType::Deposit, 2, 4, 1.0, "2024-03-01"),
+        ] {
+            rollforward.begin(engine.state(), tx.timestamp).unwrap();
+            let cid = engine.state().canonical(tx.cid);
+            let before = RollForward::before(engine.state(), cid);
+            let tx_type = tx.tx_type;
+            engine.apply(tx);
+            rollforward.record(engine.state(), cid, tx_type, before);
+        }
+        let mut out = vec![];
+        rollforward.write(engine.state(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "period,client,opening,deposits,withdrawals,disputes_held,chargebacks,other,closing\n\
+             2024-01,1,0.0000,15.0000,3.0000,0.0000,0.0000,0.0000,12.0000\n\
+             2024-02,1,12.0000,0.0000,0.0000,5.0000,5.0000,0.0000,7.0000\n\
+             2024-03,1,7.0000,0.0000,0.0000,0.0000,0.0000,0.0000,7.0000\n\
+             2024-03,2,0.0000,1.0000,0.0000,0.0000,0.0000,0.0000,1.0000\n"
+        );
+    }
+}
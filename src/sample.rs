@@ -0,0 +1,166 @@
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use txcli_model::{
+    precision4_serialize_currency, ClientId, ClientOutputState, Currency, TxRecord, TxType,
+};
+
+// splitmix64, plenty for picking QA rows and keeps a run reproducible from its seed alone.
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[derive(Serialize)]
+struct SampleRow<'a> {
+    tx: u32,
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    client: ClientId,
+    account: &'a str,
+    currency: Option<&'a str>,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    amount: Currency,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    available_before: Currency,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    held_before: Currency,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    available_after: Currency,
+    #[serde(serialize_with = "precision4_serialize_currency")]
+    held_after: Currency,
+    locked: bool,
+}
+
+// Picks each applied transaction with probability rate while processing, so sampling a huge
+// input needs no second pass. Balances are the client's totals in the asset the transaction
+// changed, like the cdc stream's.
+pub struct Sampler<W: Write> {
+    rng: SplitMix64,
+    threshold: u64,
+    writer: csv::Writer<W>,
+}
+
+impl Sampler<BufWriter<File>> {
+    pub fn create(path: &str, rate: f64, seed: u64) -> Result<Self, Box<dyn Error>> {
+        Ok(Sampler::new(
+            BufWriter::new(File::create(path)?),
+            rate,
+            seed,
+        ))
+    }
+}
+
+impl<W: Write> Sampler<W> {
+    pub fn new(writer: W, rate: f64, seed: u64) -> Self {
+        // A draw at or below the threshold is sampled, so u64::MAX samples everything.
+        let threshold = if rate >= 1.0 {
+            u64::MAX
+        } else {
+            (rate * u64::MAX as f64) as u64
+        };
+        Sampler {
+            rng: SplitMix64(seed),
+            threshold,
+            writer: csv::Writer::from_writer(writer),
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        tx: &TxRecord,
+        before: Option<&ClientOutputState>,
+        after: &ClientOutputState,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.rng.next() > self.threshold {
+            return Ok(());
+        }
+        let zero = Currency::from_num(0);
+        self.writer.serialize(SampleRow {
+            tx: tx.tid.0,
+            tx_type: tx.tx_type,
+            client: after.cid,
+            account: tx.account,
+            currency: after.currency.as_deref(),
+            amount: tx.amount,
+            available_before: before.map_or(zero, |before| before.available),
+            held_before: before.map_or(zero, |before| before.held),
+            available_after: after.available,
+            held_after: after.held,
+            locked: after.locked,
+        })?;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<W, Box<dyn Error>> {
+        Ok(self.writer.into_inner().map_err(|err| err.into_error())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(available: f64) -> ClientOutputState {
+        ClientOutputState {
+            cid: ClientId(1),
+            currency: None,
+            account: None,
+            available: Currency::from_num(available),
+            held: Currency::from_num(0),
+            total: Currency::from_num(available),
+            locked: false,
+            owners: None,
+            penalties: None,
+            chargeback_fees: None,
+            written_off: None,
+            dispute_count: None,
+            pending: None,
+        }
+    }
+
+    fn run(rate: f64, seed: u64) -> String {
+        let mut sampler = Sampler::new(vec![], rate, seed);
+        for tid in 1..=1000 {
+            let tx = TxRecord::new(TxType::Deposit, 1, tid, Currency::from_num(1));
+            let before = (tid > 1).then(|| state((tid - 1) as f64));
+            sampler
+                .record(&tx, before.as_ref(), &state(tid as f64))
+                .unwrap();
+        }
+        String::from_utf8(sampler.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn sample_rate_and_format() {
+        let all = run(1.0, 7);
+        let mut lines = all.lines();
+        assert_eq!(
+            lines.next(),
+            Some("tx,type,client,account,currency,amount,available_before,held_before,available_after,held_after,locked")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("1,deposit,1,default,,1.0000,0.0000,0.0000,1.0000,0.0000,false")
+        );
+        assert_eq!(all.lines().count(), 1001);
+
+        let rows = run(0.1, 7).lines().count() - 1;
+        assert!((50..150).contains(&rows), "{}", rows);
+        assert_eq!(run(0.0, 7), "");
+    }
+
+    #[test]
+    fn same_seed_same_sample() {
+        assert_eq!(run(0.05, 42), run(0.05, 42));
+        assert_ne!(run(0.05, 42), run(0.05, 43));
+    }
+}
@@ -0,0 +1,382 @@
+use crate::i18n::{text, Msg};
+use crate::options::{Command, Options};
+use crate::BasicError;
+use serde::{Deserialize, Serialize, Serializer};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::process::{self, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use txcli_model::timestamp::civil_from_days;
+use txcli_model::Timestamp;
+
+// A five field cron expression, minute hour day-of-month month day-of-week, read in UTC. Each
+// field is *, a number, a range a-b, a step */n or a-b/n, or a comma separated list of those.
+// Day of week runs 0-7, both 0 and 7 being Sunday. As in cron, when both day fields are
+// restricted a day matching either one fires.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Whether the day fields were *, which decides how they combine.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+// The bits of the values field allows, between min and max.
+fn cron_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((from, to)) => (from.parse().ok()?, to.parse().ok()?),
+                // A single value with a step runs to the end, as 5/15 does in cron.
+                None if part.contains('/') => (range.parse().ok()?, max),
+                None => {
+                    let value = range.parse().ok()?;
+                    (value, value)
+                }
+            },
+        };
+        if from < min || to > max || from > to {
+            return None;
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Some(bits)
+}
+
+impl FromStr for Cron {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            BasicError::new(format!(
+                "Invalid --cron {}, expected five fields, minute hour day-of-month month day-of-week, e.g. \"0 2 * * *\".",
+                s
+            ))
+        };
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(invalid());
+        };
+        let parse = |field, min, max| cron_field(field, min, max).ok_or_else(invalid);
+        let any_weekday = weekdays == "*";
+        let weekdays = parse(weekdays, 0, 7)?;
+        Ok(Cron {
+            expression: fields.join(" "),
+            minutes: parse(minutes, 0, 59)?,
+            hours: parse(hours, 0, 23)?,
+            days: parse(days, 1, 31)?,
+            months: parse(months, 1, 12)?,
+            // Sunday is 0, 7 is only another name for it.
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: days == "*",
+            any_weekday,
+        })
+    }
+}
+
+impl Display for Cron {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl Serialize for Cron {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl Cron {
+    fn matches_day(&self, days: i64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        // The epoch was a Thursday.
+        let weekday = (days + 4).rem_euclid(7);
+        let by_day = self.days & 1 << day != 0;
+        let by_weekday = self.weekdays & 1 << weekday != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => by_day || by_weekday,
+            _ => by_day && by_weekday,
+        };
+        self.months & 1 << month != 0 && day_matches
+    }
+
+    // The first time after the given one the expression fires, None if it never does, e.g. on
+    // the 31st of February.
+    pub fn next_after(&self, after: Timestamp) -> Option<Timestamp> {
+        let mut minute = after.0 / 60 + 1;
+        // Every day of the week falls on every date within this many years.
+        let limit = minute + 28 * 366 * 24 * 60;
+        while minute < limit {
+            if !self.matches_day((minute / 1440) as i64) {
+                minute = (minute / 1440 + 1) * 1440;
+            } else if self.hours & 1 << (minute / 60 % 24) == 0 {
+                minute = (minute / 60 + 1) * 60;
+            } else if self.minutes & 1 << (minute % 60) == 0 {
+                minute += 1;
+            } else {
+                return Some(Timestamp(minute * 60));
+            }
+        }
+        None
+    }
+}
+
+// Job file format:
+//
+// args = ["process", "in.csv", "--output", "out/{date}.csv"]   # the txcli command line to run
+// manifest = "runs/{date}-{time}.json"                         # optional, --manifest of each run
+// on_failure = ["notify", "txcli job failed at {date} {time}: {error}"]  # optional
+//
+// {date} and {time} are the scheduled time in UTC, 2024-03-04 and 0200, in args, manifest and
+// on_failure. on_failure is a command run when a run fails, {error} being the last line it
+// printed to stderr.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    args: Vec<String>,
+    manifest: Option<String>,
+    #[serde(default)]
+    on_failure: Vec<String>,
+}
+
+fn placeholders(value: &str, at: Timestamp, error: &str) -> String {
+    let (year, month, day) = civil_from_days(at.days());
+    let minutes = at.0 % 86400 / 60;
+    value
+        .replace("{date}", &format!("{:04}-{:02}-{:02}", year, month, day))
+        .replace("{time}", &format!("{:02}{:02}", minutes / 60, minutes % 60))
+        .replace("{error}", error)
+}
+
+impl Job {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let job = Job::parse(&fs::read_to_string(path)?)
+            .map_err(|err| BasicError::new(format!("Invalid job file {}: {}", path, err)))?;
+        Ok(job)
+    }
+
+    // args are checked as txcli would check them, placeholders filled in, so a broken job fails
+    // when the scheduler starts rather than at two in the morning.
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let job: Job = toml::from_str(input)?;
+        let args = job.args(Timestamp::default(), false);
+        let options = Options::parse(std::iter::once("txcli".to_string()).chain(args))?;
+        if matches!(
+            options.command,
+            Command::Schedule | Command::Pipe | Command::Tui
+        ) || options.serve.is_some()
+            || options.stdin
+        {
+            return Err(BasicError::new(
+                "args must be a run that ends on its own, not schedule, --serve, --stdin, pipe or tui.",
+            ));
+        }
+        if job.manifest.is_some() && job.args.iter().any(|arg| arg == "--manifest") {
+            return Err(BasicError::new(
+                "--manifest is given both in args and as manifest, give it once.",
+            ));
+        }
+        Ok(job)
+    }
+
+    fn args(&self, at: Timestamp, read_only: bool) -> Vec<String> {
+        let mut args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| placeholders(arg, at, ""))
+            .collect();
+        if let Some(manifest) = &self.manifest {
+            args.push("--manifest".to_string());
+            args.push(placeholders(manifest, at, ""));
+        }
+        if read_only {
+            args.push("--read-only".to_string());
+        }
+        args
+    }
+
+    // Runs the job as a child txcli, so runs share nothing but the files they name. What it
+    // prints goes through, stderr once it ends.
+    fn fire(&self, at: Timestamp, read_only: bool) -> Result<(), String> {
+        let exe = std::env::current_exe().map_err(|err| err.to_string())?;
+        let output = process::Command::new(exe)
+            .args(self.args(at, read_only))
+            .stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|err| err.to_string())?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        eprint!("{}", stderr);
+        if output.status.success() {
+            return Ok(());
+        }
+        Err(stderr
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .map_or_else(|| output.status.to_string(), String::from))
+    }
+
+    fn notify(&self, at: Timestamp, error: &str) {
+        let Some((program, args)) = self.on_failure.split_first() else {
+            return;
+        };
+        let status = process::Command::new(placeholders(program, at, error))
+            .args(args.iter().map(|arg| placeholders(arg, at, error)))
+            .stdin(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!("{}", text(Msg::NotifyFailed, &[&program, &status])),
+            Err(err) => eprintln!("{}", text(Msg::NotifyFailed, &[&program, &err])),
+        }
+    }
+}
+
+fn now() -> Result<Timestamp, Box<dyn Error>> {
+    Ok(Timestamp(
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    ))
+}
+
+// Runs the job every time cron fires, for as long as the process lives. Runs never overlap: one
+// still going when the next time comes delays it to the first time after it ends, the times it
+// missed are skipped. A failed run is reported and notified, and the schedule goes on.
+pub fn run(cron: &Cron, job: &Job, read_only: bool) -> Result<(), Box<dyn Error>> {
+    loop {
+        let at = cron
+            .next_after(now()?)
+            .ok_or_else(|| BasicError::new(format!("--cron {} never fires.", cron)))?;
+        eprintln!("{}", text(Msg::NextRun, &[&at]));
+        // Slept in steps, so a clock set forward doesn't leave the run waiting.
+        loop {
+            let current = now()?;
+            if current >= at {
+                break;
+            }
+            std::thread::sleep(Duration::from_secs((at.0 - current.0).min(60)));
+        }
+        let started = std::time::Instant::now();
+        match job.fire(at, read_only) {
+            Ok(()) => eprintln!(
+                "{}",
+                text(
+                    Msg::RunFinished,
+                    &[&at, &format!("{:.1}", started.elapsed().as_secs_f64())]
+                )
+            ),
+            Err(error) => {
+                eprintln!("{}", text(Msg::RunFailed, &[&at, &error]));
+                job.notify(at, &error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> Timestamp {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn next_times() {
+        let nightly: Cron = "0 2 * * *".parse().unwrap();
+        assert_eq!(
+            nightly.next_after(at("2024-03-04T01:59:59Z")),
+            Some(at("2024-03-04T02:00:00Z"))
+        );
+        assert_eq!(
+            nightly.next_after(at("2024-03-04T02:00:00Z")),
+            Some(at("2024-03-05T02:00:00Z"))
+        );
+        // Every 15 minutes of working hours on weekdays, 2024-03-09 was a Saturday.
+        let working: Cron = "*/15 9-17 * * 1-5".parse().unwrap();
+        assert_eq!(
+            working.next_after(at("2024-03-08T17:50:00Z")),
+            Some(at("2024-03-11T09:00:00Z"))
+        );
+        // Either day field matches when both are restricted, 7 is Sunday.
+        let either: Cron = "30 6 1 * 7".parse().unwrap();
+        assert_eq!(
+            either.next_after(at("2024-03-02T00:00:00Z")),
+            Some(at("2024-03-03T06:30:00Z"))
+        );
+        assert_eq!(
+            either.next_after(at("2024-03-31T07:00:00Z")),
+            Some(at("2024-04-01T06:30:00Z"))
+        );
+        let leap: Cron = "0 0 29 2 *".parse().unwrap();
+        assert_eq!(
+            leap.next_after(at("2024-03-01T00:00:00Z")),
+            Some(at("2028-02-29T00:00:00Z"))
+        );
+        let never: Cron = "0 0 31 2 *".parse().unwrap();
+        assert_eq!(never.next_after(at("2024-01-01T00:00:00Z")), None);
+
+        for invalid in [
+            "0 2 * *",
+            "60 * * * *",
+            "0 2 * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(invalid.parse::<Cron>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn job_files() {
+        let job = Job::parse(
+            "args = [\"in.csv\", \"--output\", \"out/{date}.csv\"]\n\
+             manifest = \"runs/{date}-{time}.json\"\n\
+             on_failure = [\"notify\", \"{date} {time}: {error}\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            job.args(at("2024-03-04T02:00:00Z"), true),
+            [
+                "in.csv",
+                "--output",
+                "out/2024-03-04.csv",
+                "--manifest",
+                "runs/2024-03-04-0200.json",
+                "--read-only"
+            ]
+        );
+        assert_eq!(
+            placeholders(
+                &job.on_failure[1],
+                at("2024-03-04T02:00:00Z"),
+                "no such file"
+            ),
+            "2024-03-04 0200: no such file"
+        );
+
+        assert!(Job::parse("args = [\"--stdin\"]\n").is_err());
+        assert!(Job::parse("args = [\"in.csv\", \"--strict\", \"--nope\"]\n").is_err());
+        assert!(Job::parse(
+            "args = [\"in.csv\", \"--manifest\", \"m.json\"]\nmanifest = \"n.json\"\n"
+        )
+        .is_err());
+        assert!(Job::parse("args = [\"in.csv\"]\nretries = 3\n").is_err());
+    }
+}
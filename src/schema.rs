@@ -0,0 +1,443 @@
+use crate::fixups::{FixupColumns, Fixups};
+use crate::BasicError;
+use serde::Serialize;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use txcli_model::{parse_amount, ClientId, Currency, TxId, TxRecord, DEFAULT_ACCOUNT};
+
+// Columns only v2 knows about, their presence in the header selects v2 when no --schema is given.
+const V2_COLUMNS: [&str; 5] = [
+    "timestamp",
+    "currency",
+    "counterparty",
+    "priority",
+    "source",
+];
+const V2_REQUIRED: [&str; 3] = ["type", "client", "tx"];
+
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Schema {
+    // type,client,tx,amount[,account,to_account,category,memo], read by position so headers are
+    // free form.
+    V1,
+    // Read by header name in any order, adding timestamp, currency, counterparty, priority and
+    // source.
+    // Unknown columns are ignored so newer producers don't break older readers.
+    V2,
+}
+
+impl FromStr for Schema {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" | "v1" => Ok(Schema::V1),
+            "2" | "v2" => Ok(Schema::V2),
+            _ => Err(BasicError::new(format!(
+                "Unknown schema version {}. Supported versions are v1 and v2.",
+                s
+            ))),
+        }
+    }
+}
+
+impl Schema {
+    pub fn detect(headers: &csv::StringRecord) -> Schema {
+        if headers.iter().any(|header| V2_COLUMNS.contains(&header)) {
+            Schema::V2
+        } else {
+            Schema::V1
+        }
+    }
+
+    fn check_headers(&self, headers: &csv::StringRecord) -> Result<(), Box<dyn Error>> {
+        if *self == Schema::V2 {
+            for required in V2_REQUIRED {
+                if !headers.iter().any(|header| header == required) {
+                    return Err(BasicError::new(format!(
+                        "Schema v2 input is missing the {} column.",
+                        required
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reader(&self, headers: &csv::StringRecord) -> Result<RowReader, Box<dyn Error>> {
+        self.check_headers(headers)?;
+        let columns = match self {
+            Schema::V1 => Columns {
+                tx_type: Some(0),
+                client: Some(1),
+                tx: Some(2),
+                amount: Some(3),
+                account: Some(4),
+                to_account: Some(5),
+                category: Some(6),
+                memo: Some(7),
+                ..Columns::default()
+            },
+            Schema::V2 => {
+                let position = |name: &str| headers.iter().position(|header| header == name);
+                Columns {
+                    tx_type: position("type"),
+                    client: position("client"),
+                    tx: position("tx"),
+                    amount: position("amount"),
+                    account: position("account"),
+                    to_account: position("to_account"),
+                    category: position("category"),
+                    timestamp: position("timestamp"),
+                    currency: position("currency"),
+                    counterparty: position("counterparty"),
+                    priority: position("priority"),
+                    source: position("source"),
+                    memo: position("memo"),
+                }
+            }
+        };
+        Ok(RowReader {
+            columns,
+            record: csv::StringRecord::new(),
+            fixups: None,
+        })
+    }
+}
+
+// Column index of every field, None when the input doesn't have that column.
+#[derive(Debug, Default, Clone)]
+struct Columns {
+    tx_type: Option<usize>,
+    client: Option<usize>,
+    tx: Option<usize>,
+    amount: Option<usize>,
+    account: Option<usize>,
+    to_account: Option<usize>,
+    category: Option<usize>,
+    timestamp: Option<usize>,
+    currency: Option<usize>,
+    counterparty: Option<usize>,
+    priority: Option<usize>,
+    source: Option<usize>,
+    memo: Option<usize>,
+}
+
+// Whether err came from reading the input rather than from a row in it, past which nothing read
+// can be trusted to line up with the input.
+pub fn unreadable(err: &(dyn Error + 'static)) -> bool {
+    err.downcast_ref::<csv::Error>()
+        .is_some_and(|err| err.is_io_error())
+}
+
+// Reads rows into a single reused buffer and hands out records borrowing from it, so the
+// hot path neither allocates per row nor goes through serde.
+// Cloned for each chunk when parsing in parallel, see chunked::ChunkedRows.
+#[derive(Clone)]
+pub struct RowReader {
+    columns: Columns,
+    record: csv::StringRecord,
+    // Applied to every row as it is read, see --fixups.
+    fixups: Option<Arc<Fixups>>,
+}
+
+impl RowReader {
+    pub fn next<R: std::io::Read>(
+        &mut self,
+        reader: &mut csv::Reader<R>,
+    ) -> Option<Result<TxRecord<'_>, Box<dyn Error>>> {
+        match self.read(reader) {
+            Ok(true) => Some(self.parse()),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    // next() in two steps, for callers timing reading and parsing separately.
+    // Rows the fixups drop are skipped as if they weren't in the input.
+    pub fn read<R: std::io::Read>(
+        &mut self,
+        reader: &mut csv::Reader<R>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let columns = FixupColumns {
+            tx_type: self.columns.tx_type,
+            client: self.columns.client,
+            tx: self.columns.tx,
+            amount: self.columns.amount,
+        };
+        while reader.read_record(&mut self.record)? {
+            match &self.fixups {
+                Some(fixups) if !fixups.fix(&mut self.record, columns) => continue,
+                _ => return Ok(true),
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn with_fixups(mut self, fixups: Arc<Fixups>) -> Self {
+        self.fixups = Some(fixups);
+        self
+    }
+
+    pub fn fixups(&self) -> Option<&Fixups> {
+        self.fixups.as_deref()
+    }
+
+    // Takes a row that didn't come through a csv::Reader, e.g. one sent to the server or one
+    // shrink replays.
+    pub fn set_record(&mut self, record: csv::StringRecord) {
+        self.record = record;
+    }
+
+    // Line of the row last read, for pointing back into the input.
+    pub fn line(&self) -> u64 {
+        self.record.position().map_or(0, |position| position.line())
+    }
+
+    // Where rows have their amount, for reading it as written.
+    pub fn amount_column(&self) -> Option<usize> {
+        self.columns.amount
+    }
+
+    // Whether rows carry a priority, only then is it worth buffering them to reorder.
+    pub fn has_priority(&self) -> bool {
+        self.columns.priority.is_some()
+    }
+
+    // The row last read as it appears in the input, for copying it elsewhere untouched.
+    pub fn record(&self) -> &csv::StringRecord {
+        &self.record
+    }
+
+    // The row last read with its client, tx and amount replaced and its memo redacted, every
+    // other column untouched. The amount and memo are only replaced where the row has one.
+    pub fn replaced(&self, cid: ClientId, tid: TxId, amount: Currency) -> csv::StringRecord {
+        let columns = &self.columns;
+        self.record
+            .iter()
+            .enumerate()
+            .map(|(column, value)| match Some(column) {
+                index if index == columns.client => cid.0.to_string(),
+                index if index == columns.tx => tid.0.to_string(),
+                index if index == columns.amount && !value.is_empty() => format!("{:.4}", amount),
+                index if index == columns.memo && !value.is_empty() => "redacted".to_string(),
+                _ => value.to_string(),
+            })
+            .collect()
+    }
+
+    pub fn parse(&self) -> Result<TxRecord<'_>, Box<dyn Error>> {
+        // Empty and missing columns are treated the same, like the optional trailing v1 columns.
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| self.record.get(column))
+                .filter(|value| !value.is_empty())
+        };
+        let line = self.line();
+        let required = |column: Option<usize>, name: &str| {
+            field(column).ok_or_else(|| BasicError::new(format!("line {}: missing {}", line, name)))
+        };
+        fn parsed<T: FromStr>(value: &str, name: &str, line: u64) -> Result<T, Box<dyn Error>> {
+            Ok(value.parse().map_err(|_| {
+                BasicError::new(format!("line {}: invalid {} {}", line, name, value))
+            })?)
+        }
+
+        let columns = &self.columns;
+        Ok(TxRecord {
+            tx_type: parsed(required(columns.tx_type, "type")?, "type", line)?,
+            cid: ClientId(parsed(required(columns.client, "client")?, "client", line)?),
+            tid: TxId(parsed(required(columns.tx, "tx")?, "tx", line)?),
+            amount: match field(columns.amount) {
+                Some(amount) => parse_amount(amount)
+                    .map_err(|err| BasicError::new(format!("line {}: {}", line, err)))?,
+                None => Currency::from_num(0),
+            },
+            account: field(columns.account).unwrap_or(DEFAULT_ACCOUNT),
+            to_account: field(columns.to_account),
+            category: field(columns.category),
+            timestamp: field(columns.timestamp)
+                .map(|timestamp| parsed(timestamp, "timestamp", line))
+                .transpose()?,
+            currency: field(columns.currency),
+            counterparty: field(columns.counterparty),
+            priority: field(columns.priority)
+                .map(|priority| parsed(priority, "priority", line))
+                .transpose()?,
+            source: field(columns.source),
+            memo: field(columns.memo),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::{AccountId, Timestamp, Tx, TxType};
+
+    fn read(input: &str, schema: Option<Schema>) -> Result<Vec<Tx>, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let schema = match schema {
+            Some(schema) => schema,
+            None => Schema::detect(reader.headers()?),
+        };
+        let mut rows = schema.reader(reader.headers()?)?;
+        let mut txs = vec![];
+        while let Some(row) = rows.next(&mut reader) {
+            txs.push(row?.to_tx());
+        }
+        Ok(txs)
+    }
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!("v1".parse::<Schema>().unwrap(), Schema::V1);
+        assert_eq!("2".parse::<Schema>().unwrap(), Schema::V2);
+        let err = "v3".parse::<Schema>().unwrap_err();
+        assert!(err.to_string().contains("Unknown schema version v3"));
+    }
+
+    #[test]
+    fn detect_by_header() {
+        let v1 = csv::StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        let v2 = csv::StringRecord::from(vec!["type", "client", "tx", "amount", "counterparty"]);
+        assert_eq!(Schema::detect(&v1), Schema::V1);
+        assert_eq!(Schema::detect(&v2), Schema::V2);
+    }
+
+    #[test]
+    fn v1_is_positional() {
+        let txs = read("kind, who, id, value\ndeposit, 1, 1, 1.0\n", None).unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].cid, ClientId(1));
+        assert_eq!(txs[0].timestamp, None);
+    }
+
+    #[test]
+    fn v1_optional_account_columns() {
+        let input = "type,client,tx,amount,account,to_account,category\ndeposit,1,1,1.0\ndeposit,1,2,1.0,savings,,salary\ntransfer,1,3,1.0,savings,checking\ndispute,1,1,\n";
+        let txs = read(input, None).unwrap();
+        assert_eq!(txs.len(), 4);
+        assert_eq!(txs[0].account, AccountId::default());
+        assert_eq!(txs[1].account, AccountId("savings".to_string()));
+        assert_eq!(txs[1].to_account, None);
+        assert_eq!(txs[1].category.as_deref(), Some("salary"));
+        assert_eq!(txs[2].tx_type, TxType::Transfer);
+        assert_eq!(txs[2].to_account, Some(AccountId("checking".to_string())));
+        assert_eq!(txs[3].amount, Currency::from_num(0));
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let err = read("type,client,tx,amount\nrefund,1,1,1.0\n", None).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid type refund");
+        let err = read(
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,abc\n",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "line 3: Invalid amount abc");
+        let err = read("type,client,tx,amount\ndeposit,,1,1.0\n", None).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: missing client");
+        assert!(read("type,client,tx,amount\ndeposit,70000,1,1.0\n", None).is_err());
+    }
+
+    #[test]
+    fn records_borrow_the_row_buffer() {
+        let input =
+            "type,client,tx,amount,account\ndeposit,1,1,1.0,savings\ndeposit,1,2,2.0,checking\n";
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let mut rows = Schema::V1.reader(reader.headers().unwrap()).unwrap();
+        assert_eq!(rows.next(&mut reader).unwrap().unwrap().account, "savings");
+        let record = rows.next(&mut reader).unwrap().unwrap();
+        assert_eq!(
+            record,
+            TxRecord::new(TxType::Deposit, 1, 2, Currency::from_num(2.0)).with_account("checking")
+        );
+        assert!(rows.next(&mut reader).is_none());
+    }
+
+    #[test]
+    fn v2_by_name_with_unknown_columns() {
+        let input = "timestamp,client,type,tx,amount,currency,counterparty,future_column\n\
+                     2022-08-09T13:45:00Z,1,deposit,7,1.5,USD,acme,whatever\n\
+                     1660052800,1,dispute,7,,,,\n";
+        let txs = read(input, None).unwrap();
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].tx_type, TxType::Deposit);
+        assert_eq!(txs[0].tid, TxId(7));
+        assert_eq!(txs[0].amount, Currency::from_num(1.5));
+        assert_eq!(txs[0].timestamp, Some(Timestamp(1660052700)));
+        assert_eq!(txs[0].currency.as_deref(), Some("USD"));
+        assert_eq!(txs[0].counterparty.as_deref(), Some("acme"));
+        assert_eq!(txs[1].amount, Currency::from_num(0));
+        assert_eq!(txs[1].currency, None);
+    }
+
+    #[test]
+    fn v2_priority() {
+        let input = "type,client,tx,amount,priority
+deposit,1,1,1.0,
+chargeback,1,1,,9
+";
+        let txs = read(input, None).unwrap();
+        assert_eq!(txs[0].priority, None);
+        assert_eq!(txs[1].priority, Some(9));
+        let err = read(
+            "type,client,tx,priority
+deposit,1,1,high
+",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid priority high");
+    }
+
+    #[test]
+    fn v2_source() {
+        let txs = read(
+            "type,client,tx,amount,source
+deposit,1,1,1.0,ledger
+deposit,1,2,1.0,
+",
+            None,
+        )
+        .unwrap();
+        assert_eq!(txs[0].source.as_deref(), Some("ledger"));
+        assert_eq!(txs[1].source, None);
+    }
+
+    #[test]
+    fn memo_column() {
+        let v1 = read(
+            "type,client,tx,amount,account,to_account,category,memo\nnote,1,5,,,,,\"called, asked for a statement\"\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(v1[0].tx_type, TxType::Note);
+        assert_eq!(v1[0].memo.as_deref(), Some("called, asked for a statement"));
+        let v2 = read(
+            "memo,type,client,tx,timestamp\ncard replaced,note,1,6,1660052700\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(v2[0].memo.as_deref(), Some("card replaced"));
+        assert_eq!(v2[0].amount, Currency::from_num(0));
+    }
+
+    #[test]
+    fn v2_requires_core_columns() {
+        assert!(read("timestamp,client,tx\n1,1,1\n", None).is_err());
+        assert!(read("a,b,c,d\ndeposit,1,1,1.0\n", Some(Schema::V2)).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{Currency, TxType};
+
+// Amounts that show up at least this often are listed as duplicate clusters, largest first.
+const DUPLICATE_MIN_COUNT: u64 = 3;
+const DUPLICATE_MAX_ROWS: usize = 20;
+const ROUND_MULTIPLES: [u32; 3] = [10, 100, 1000];
+
+#[derive(Serialize)]
+struct ScreenRow {
+    screen: &'static str,
+    bucket: String,
+    count: u64,
+    share: String,
+    expected: String,
+}
+
+// Nigrini's first digit mean absolute deviation cutoffs.
+fn benford_conformity(mad: f64) -> &'static str {
+    if mad < 0.006 {
+        "close"
+    } else if mad < 0.012 {
+        "acceptable"
+    } else if mad < 0.015 {
+        "marginal"
+    } else {
+        "nonconforming"
+    }
+}
+
+// Auditor style screens over the amounts of deposits and withdrawals. Balances are never computed,
+// this only looks at the shape of the numbers to decide which files deserve a closer look.
+#[derive(Default)]
+pub struct Screen {
+    amounts: u64,
+    first_digits: [u64; 9],
+    whole: u64,
+    round: [u64; ROUND_MULTIPLES.len()],
+    counts: HashMap<Currency, u64>,
+}
+
+impl Screen {
+    pub fn observe(&mut self, tx_type: TxType, amount: Currency) {
+        if !matches!(tx_type, TxType::Deposit | TxType::Withdrawal) || amount <= 0 {
+            return;
+        }
+        self.amounts += 1;
+        if let Some(digit) = amount
+            .to_string()
+            .bytes()
+            .find(|b| (b'1'..=b'9').contains(b))
+        {
+            self.first_digits[(digit - b'1') as usize] += 1;
+        }
+        if amount.frac() == 0 {
+            self.whole += 1;
+            for (i, multiple) in ROUND_MULTIPLES.iter().enumerate() {
+                if (amount % Currency::from_num(*multiple)) == 0 {
+                    self.round[i] += 1;
+                }
+            }
+        }
+        *self.counts.entry(amount).or_default() += 1;
+    }
+
+    fn share(&self, count: u64) -> f64 {
+        if self.amounts == 0 {
+            0.0
+        } else {
+            count as f64 / self.amounts as f64
+        }
+    }
+
+    // Mean absolute deviation of the first digit shares from Benford's expected shares.
+    pub fn benford_mad(&self) -> f64 {
+        (1..=9)
+            .map(|digit| {
+                let expected = (1.0 + 1.0 / digit as f64).log10();
+                (self.share(self.first_digits[digit - 1]) - expected).abs()
+            })
+            .sum::<f64>()
+            / 9.0
+    }
+
+    pub fn summary(&self) -> String {
+        let mad = self.benford_mad();
+        format!(
+            "Screened {} amounts, Benford first digit MAD {:.4} ({}).",
+            self.amounts,
+            mad,
+            benford_conformity(mad)
+        )
+    }
+
+    // One long form csv: benford rows per first digit, round rows per multiple, then duplicate clusters.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for digit in 1..=9 {
+            let count = self.first_digits[digit - 1];
+            writer.serialize(ScreenRow {
+                screen: "benford",
+                bucket: digit.to_string(),
+                count,
+                share: format!("{:.4}", self.share(count)),
+                expected: format!("{:.4}", (1.0 + 1.0 / digit as f64).log10()),
+            })?;
+        }
+
+        let round = std::iter::once(("whole".to_string(), self.whole)).chain(
+            ROUND_MULTIPLES
+                .iter()
+                .zip(self.round)
+                .map(|(multiple, count)| (format!("multiple_of_{}", multiple), count)),
+        );
+        for (bucket, count) in round {
+            writer.serialize(ScreenRow {
+                screen: "round",
+                bucket,
+                count,
+                share: format!("{:.4}", self.share(count)),
+                expected: String::new(),
+            })?;
+        }
+
+        let mut duplicates: Vec<_> = self
+            .counts
+            .iter()
+            .filter(|(_, count)| **count >= DUPLICATE_MIN_COUNT)
+            .collect();
+        // Ties are broken by amount so the report is stable across runs.
+        duplicates.sort_unstable_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (amount, count) in duplicates.into_iter().take(DUPLICATE_MAX_ROWS) {
+            writer.serialize(ScreenRow {
+                screen: "duplicate",
+                bucket: format!("{:.4}", amount),
+                count: *count,
+                share: format!("{:.4}", self.share(*count)),
+                expected: String::new(),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Amounts the currency is too narrow for are left out, as a row holding one wouldn't parse.
+    fn screen(amounts: &[f64]) -> Screen {
+        let mut screen = Screen::default();
+        for amount in amounts
+            .iter()
+            .filter_map(|amount| Currency::checked_from_num(*amount))
+        {
+            screen.observe(TxType::Deposit, amount);
+        }
+        screen
+    }
+
+    #[test]
+    fn first_digits_round_numbers_and_duplicates() {
+        let mut screen = screen(&[1.5, 0.25, 19.0, 300.0, 1000.0, 7.75, 7.75, 7.75, 0.0]);
+        screen.observe(TxType::Dispute, Currency::from_num(5));
+        assert_eq!(screen.amounts, 8);
+        assert_eq!(screen.first_digits, [3, 1, 1, 0, 0, 0, 3, 0, 0]);
+        assert_eq!(screen.whole, 3);
+        assert_eq!(screen.round, [2, 2, 1]);
+
+        let mut out = vec![];
+        screen.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "screen,bucket,count,share,expected");
+        assert_eq!(lines[1], "benford,1,3,0.3750,0.3010");
+        assert_eq!(lines[10], "round,whole,3,0.3750,");
+        assert_eq!(lines[13], "round,multiple_of_1000,1,0.1250,");
+        assert_eq!(lines[14], "duplicate,7.7500,3,0.3750,");
+        assert_eq!(lines.len(), 15);
+    }
+
+    #[test]
+    fn benford_conforming_data() {
+        // Powers of 1.1 follow Benford's law closely.
+        let amounts: Vec<f64> = (0..2000).map(|i| 1.1f64.powi(i % 300)).collect();
+        let screen = screen(&amounts);
+        assert!(screen.benford_mad() < 0.012, "{}", screen.benford_mad());
+        assert!(screen
+            .summary()
+            .contains(&format!("{} amounts", screen.amounts)));
+
+        let fives = screen_of_leading(5);
+        assert_eq!(benford_conformity(fives.benford_mad()), "nonconforming");
+    }
+
+    fn screen_of_leading(digit: u32) -> Screen {
+        let amounts: Vec<f64> = (0..100).map(|i| digit as f64 + i as f64 / 1000.0).collect();
+        screen(&amounts)
+    }
+}
@@ -0,0 +1,294 @@
+use crate::engine::Engine;
+use crate::i18n::{text, Msg};
+use crate::schema::{RowReader, Schema};
+use crate::BasicError;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use txcli_model::{ClientId, Timestamp, TxId};
+
+// Live feeds instead of batch files: one engine kept in memory for as long as the process runs,
+// fed a request per line and answering each with one line, except balances.
+//
+// deposit,1,1,2.5                       a v1 row, type,client,tx,amount[,account,to_account,category,memo]
+// {"type":"dispute","client":1,"tx":1}  the same columns as a JSON object
+// balance 1                             the client's row as txcli prints it, or unknown client
+// notes 1                               the client's notes as one JSON array in tid order, or
+//                                       unknown client
+// balances                              every row with the header, then an empty line
+// stats                                 the counts so far as one JSON object, rows over
+//                                       --latency-budget among them
+//
+// A row is answered applied or rejected, with the reason on stderr like any batch run, and a
+// request that can't be read is answered error and the reason. Connections are served on their
+// own threads and each one's rows are applied in the order sent, rows of different connections
+// in whatever order they take the engine.
+pub type Shared = Arc<Mutex<Engine>>;
+
+// The v1 columns, in position order, for JSON requests.
+const COLUMNS: [&str; 8] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "account",
+    "to_account",
+    "category",
+    "memo",
+];
+
+// One note of a notes reply.
+#[derive(Serialize)]
+struct Note<'a> {
+    tx: TxId,
+    account: &'a str,
+    memo: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<Timestamp>,
+}
+
+// Also the frames of txcli pipe, see pipe.rs.
+pub fn record_of_json(line: &str) -> Result<csv::StringRecord, Box<dyn Error>> {
+    let object = match serde_json::from_str(line)? {
+        Value::Object(object) => object,
+        _ => return Err(BasicError::new("expected a JSON object")),
+    };
+    if let Some(key) = object.keys().find(|key| !COLUMNS.contains(&key.as_str())) {
+        return Err(BasicError::new(format!("unknown field {}", key)));
+    }
+    Ok(COLUMNS
+        .iter()
+        .map(|column| match object.get(*column) {
+            Some(Value::String(value)) => value.clone(),
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        })
+        .collect())
+}
+
+fn record_of_csv(line: &str) -> Result<csv::StringRecord, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(line.as_bytes());
+    let mut record = csv::StringRecord::new();
+    reader.read_record(&mut record)?;
+    Ok(record)
+}
+
+fn client_row(engine: &Engine, cid: ClientId) -> Result<Option<String>, Box<dyn Error>> {
+    let Some(balance) = engine.balance(cid) else {
+        return Ok(None);
+    };
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.serialize(balance)?;
+    Ok(Some(String::from_utf8(writer.into_inner()?)?))
+}
+
+fn client_notes(engine: &Engine, cid: ClientId) -> Result<Option<String>, Box<dyn Error>> {
+    let Some(notes) = engine.notes(cid) else {
+        return Ok(None);
+    };
+    let notes: Vec<Note> = notes
+        .iter()
+        .map(|tx| Note {
+            tx: tx.tid,
+            account: &tx.account.0,
+            memo: tx.memo.as_deref().unwrap_or_default(),
+            timestamp: tx.timestamp,
+        })
+        .collect();
+    Ok(Some(format!("{}\n", serde_json::to_string(&notes)?)))
+}
+
+fn client_of(request: &str) -> Result<ClientId, Box<dyn Error>> {
+    let cid = request.trim();
+    Ok(ClientId(cid.parse::<u16>().map_err(|_| {
+        BasicError::new(format!("invalid client {}", cid))
+    })?))
+}
+
+// Answers one request. line is the request's number on its connection, for error messages.
+fn answer(
+    engine: &Mutex<Engine>,
+    rows: &mut RowReader,
+    line: u64,
+    request: &str,
+) -> Result<String, Box<dyn Error>> {
+    let engine = &mut *engine
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if request == "balances" {
+        return Ok(format!(
+            "{}\n",
+            String::from_utf8(engine.report().balances_csv()?)?
+        ));
+    }
+    if request == "stats" {
+        return Ok(format!(
+            "{}\n",
+            serde_json::to_string(&engine.report().stats)?
+        ));
+    }
+    if let Some(cid) = request.strip_prefix("balance ") {
+        let cid = client_of(cid)?;
+        return Ok(
+            client_row(engine, cid)?.unwrap_or_else(|| format!("unknown client {}\n", cid.0))
+        );
+    }
+    if let Some(cid) = request.strip_prefix("notes ") {
+        let cid = client_of(cid)?;
+        return Ok(
+            client_notes(engine, cid)?.unwrap_or_else(|| format!("unknown client {}\n", cid.0))
+        );
+    }
+    let mut record = if request.starts_with('{') {
+        record_of_json(request)?
+    } else {
+        record_of_csv(request)?
+    };
+    let mut position = csv::Position::new();
+    position.set_line(line);
+    record.set_position(Some(position));
+    rows.set_record(record);
+    Ok(if engine.apply(rows.parse()?) {
+        "applied\n".to_string()
+    } else {
+        "rejected\n".to_string()
+    })
+}
+
+// Serves one connection, or stdin and stdout, until the input ends.
+pub fn serve<R: BufRead, W: Write>(
+    engine: &Mutex<Engine>,
+    input: R,
+    mut output: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut rows = Schema::V1.reader(&csv::StringRecord::new())?;
+    for (index, request) in input.lines().enumerate() {
+        let request = request?;
+        let request = request.trim();
+        if request.is_empty() {
+            continue;
+        }
+        match answer(engine, &mut rows, index as u64 + 1, request) {
+            Ok(reply) => output.write_all(reply.as_bytes())?,
+            Err(err) => writeln!(output, "error {}", err)?,
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// Accepts connections until the process is stopped, a failed connection only ends itself.
+pub fn listen(engine: Shared, listener: TcpListener) -> Result<(), Box<dyn Error>> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let engine = Arc::clone(&engine);
+        thread::spawn(move || {
+            let peer = stream.peer_addr();
+            let result = stream
+                .try_clone()
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|reader| serve(&engine, BufReader::new(reader), stream));
+            if let (Err(err), Ok(peer)) = (result, peer) {
+                eprintln!("{}", text(Msg::ConnectionFailed, &[&peer, &err]));
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    fn replies(engine: &Mutex<Engine>, input: &str) -> String {
+        let mut out = vec![];
+        serve(engine, input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_and_json_requests() {
+        let engine = Mutex::new(EngineBuilder::new().build());
+        let input = "deposit,1,1,2.5\n\n\
+            {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"5\"}\n\
+            {\"type\":\"dispute\",\"client\":1,\"tx\":1}\n\
+            balance 1\nbalance 2\n\
+            deposit,1\n{\"type\":\"deposit\",\"cleint\":1}\n";
+        assert_eq!(
+            replies(&engine, input),
+            "applied\nrejected\napplied\n\
+            1,0.0000,2.5000,2.5000,false\nunknown client 2\n\
+            error line 7: missing tx\nerror unknown field cleint\n"
+        );
+        // State outlives the connection.
+        assert_eq!(
+            replies(&engine, "resolve,1,1\nbalances\n"),
+            "applied\nclient,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n\n"
+        );
+    }
+
+    #[test]
+    fn notes_by_client() {
+        let engine = Mutex::new(EngineBuilder::new().build());
+        let input = "deposit,1,1,2.5\n\
+            note,1,9,,,,,\"asked for a limit increase, declined\"\n\
+            {\"type\":\"note\",\"client\":1,\"tx\":3,\"account\":\"savings\",\"memo\":\"opened savings\"}\n\
+            note,1,4\n\
+            notes 1\nnotes 2\nnotes x\nbalance 1\n";
+        assert_eq!(
+            replies(&engine, input),
+            "applied\napplied\napplied\nrejected\n\
+            [{\"tx\":3,\"account\":\"savings\",\"memo\":\"opened savings\"},\
+            {\"tx\":9,\"account\":\"default\",\"memo\":\"asked for a limit increase, declined\"}]\n\
+            unknown client 2\nerror invalid client x\n\
+            1,2.5000,0.0000,2.5000,false\n"
+        );
+    }
+
+    #[test]
+    fn rows_over_budget_are_counted() {
+        let engine = Mutex::new(
+            EngineBuilder::new()
+                .latency_budget(Duration::from_nanos(1))
+                .build(),
+        );
+        let stats = replies(&engine, "deposit,1,1,2\nwithdrawal,1,2,1\nstats\n");
+        let stats: Value = serde_json::from_str(stats.lines().last().unwrap()).unwrap();
+        assert_eq!(stats["processed"], 2);
+        assert_eq!(stats["slow_rows"], 2);
+        assert!(stats["slowest_row_us"].is_u64());
+    }
+
+    #[test]
+    fn tcp_connections_share_the_engine() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let engine: Shared = Arc::new(Mutex::new(EngineBuilder::new().build()));
+        thread::spawn(move || {
+            let _ = listen(engine, listener);
+        });
+        let request = |line: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(line.as_bytes()).unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut reply = String::new();
+            reader.read_line(&mut reply).unwrap();
+            reply
+        };
+        assert_eq!(request("deposit,3,1,4\n"), "applied\n");
+        assert_eq!(request("balance 3\n"), "3,4.0000,0.0000,4.0000,false\n");
+    }
+}
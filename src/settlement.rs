@@ -0,0 +1,231 @@
+use crate::engine::FeePayer;
+use crate::AppState;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{ClientId, Currency, TxRecord, TxType};
+
+const NO_COUNTERPARTY: &str = "unknown";
+
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+struct Totals {
+    deposits: Currency,
+    withdrawals: Currency,
+    chargebacks: Currency,
+    chargeback_fees: Currency,
+}
+
+#[derive(Serialize)]
+struct SettlementRow<'a> {
+    counterparty: &'a str,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    deposits: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    withdrawals: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    chargebacks: Currency,
+    // Only when chargeback fees are charged to counterparties.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "txcli_model::precision4_serialize_optional_currency"
+    )]
+    chargeback_fees: Option<Currency>,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    net: Currency,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Column {
+    Deposits,
+    Withdrawals,
+    Chargebacks,
+}
+
+// What one row would change in the report if it is applied. Chargebacks and voids carry no
+// counterparty or amount of their own, so they are resolved against the referenced transaction
+// before the row is applied, a void removes it for good.
+#[derive(Debug, PartialEq)]
+pub struct Settles {
+    counterparty: String,
+    column: Column,
+    amount: Currency,
+    // The chargeback fee, when the counterparty pays it.
+    fee: Currency,
+}
+
+impl Settles {
+    pub fn of(state: &AppState, cid: ClientId, tx: &TxRecord) -> Option<Self> {
+        let client = state.clients.get(&cid);
+        let mut fee = Currency::from_num(0);
+        let (counterparty, column, amount) = match tx.tx_type {
+            TxType::Deposit => (tx.counterparty, Column::Deposits, tx.amount),
+            TxType::Withdrawal => (tx.counterparty, Column::Withdrawals, tx.amount),
+            // A charged back withdrawal comes back from the counterparty.
+            TxType::ChargeBack => {
+                let disputed = client?.disputed.get(&tx.tid)?;
+                let amount = match disputed.tx_type {
+                    TxType::Withdrawal => -disputed.amount,
+                    _ => disputed.amount,
+                };
+                if let Some(charged) = state
+                    .chargeback_fee
+                    .filter(|charged| charged.payer == FeePayer::Counterparty)
+                {
+                    fee = charged.amount;
+                }
+                (
+                    disputed.counterparty.as_deref(),
+                    Column::Chargebacks,
+                    amount,
+                )
+            }
+            // Voided before settlement, so it takes back its own column.
+            TxType::Void => {
+                let voided = client?.history.get(&tx.tid)?;
+                let column = match voided.tx_type {
+                    TxType::Deposit => Column::Deposits,
+                    _ => Column::Withdrawals,
+                };
+                (voided.counterparty.as_deref(), column, -voided.amount)
+            }
+            _ => return None,
+        };
+        Some(Settles {
+            counterparty: counterparty.unwrap_or(NO_COUNTERPARTY).to_string(),
+            column,
+            amount,
+            fee,
+        })
+    }
+}
+
+// Net amount moved with each counterparty across all clients, to check against the settlement
+// files from the networks. net is deposits minus withdrawals minus chargebacks, where a charged
+// back withdrawal counts negative. Chargeback fees charged to counterparties are netted off the
+// same way as chargebacks.
+#[derive(Default)]
+pub struct SettlementReport {
+    counterparties: BTreeMap<String, Totals>,
+    // Whether there is a chargeback_fees column.
+    fees: bool,
+}
+
+impl SettlementReport {
+    pub fn new(fees: bool) -> Self {
+        SettlementReport {
+            fees,
+            ..SettlementReport::default()
+        }
+    }
+
+    // Only for applied rows.
+    pub fn record(&mut self, settles: Settles) {
+        let totals = self.counterparties.entry(settles.counterparty).or_default();
+        match settles.column {
+            Column::Deposits => totals.deposits += settles.amount,
+            Column::Withdrawals => totals.withdrawals += settles.amount,
+            Column::Chargebacks => totals.chargebacks += settles.amount,
+        }
+        totals.chargeback_fees += settles.fee;
+    }
+
+    // Ordered by counterparty.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for (counterparty, totals) in &self.counterparties {
+            writer.serialize(SettlementRow {
+                counterparty,
+                deposits: totals.deposits,
+                withdrawals: totals.withdrawals,
+                chargebacks: totals.chargebacks,
+                chargeback_fees: self.fees.then_some(totals.chargeback_fees),
+                net: totals.deposits
+                    - totals.withdrawals
+                    - totals.chargebacks
+                    - totals.chargeback_fees,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ChargebackFee;
+    use crate::execute_transaction;
+
+    #[test]
+    fn net_per_counterparty() {
+        let mut state = AppState::default();
+        let mut report = SettlementReport::default();
+        let with = |tx_type, cid, tid, amount: u32, counterparty| TxRecord {
+            counterparty,
+            ..TxRecord::new(tx_type, cid, tid, Currency::from_num(amount))
+        };
+        for tx in [
+            with(TxType::Deposit, 1, 1, 10, Some("visa")),
+            with(TxType::Deposit, 2, 2, 20, Some("visa")),
+            with(TxType::Withdrawal, 1, 3, 4, Some("visa")),
+            with(TxType::Deposit, 1, 4, 7, Some("amex")),
+            with(TxType::Deposit, 2, 5, 1, None),
+            with(TxType::Dispute, 2, 2, 0, None),
+            with(TxType::ChargeBack, 2, 2, 0, None),
+            with(TxType::Void, 1, 4, 0, None),
+            with(TxType::Deposit, 3, 6, 5, None),
+            with(TxType::Withdrawal, 3, 7, 2, Some("amex")),
+            with(TxType::Dispute, 3, 7, 0, None),
+            with(TxType::ChargeBack, 3, 7, 0, None),
+            // Rejected, there is nothing to charge back.
+            with(TxType::ChargeBack, 1, 1, 0, None),
+        ] {
+            let settles = Settles::of(&state, tx.cid, &tx);
+            if let (Ok(()), Some(settles)) = (execute_transaction(&mut state, tx), settles) {
+                report.record(settles);
+            }
+        }
+        let mut out = vec![];
+        report.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "counterparty,deposits,withdrawals,chargebacks,net\n\
+             amex,0.0000,2.0000,-2.0000,0.0000\n\
+             unknown,6.0000,0.0000,0.0000,6.0000\n\
+             visa,30.0000,4.0000,20.0000,6.0000\n"
+        );
+    }
+
+    #[test]
+    fn fees_charged_to_counterparties() {
+        let mut state = AppState {
+            chargeback_fee: Some(ChargebackFee {
+                amount: Currency::from_num(15),
+                payer: FeePayer::Counterparty,
+            }),
+            ..Default::default()
+        };
+        let mut report = SettlementReport::new(true);
+        for tx in [
+            TxRecord {
+                counterparty: Some("visa"),
+                ..TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(100))
+            },
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::default()),
+            TxRecord::new(TxType::ChargeBack, 1, 1, Currency::default()),
+        ] {
+            let settles = Settles::of(&state, tx.cid, &tx);
+            if let (Ok(()), Some(settles)) = (execute_transaction(&mut state, tx), settles) {
+                report.record(settles);
+            }
+        }
+        let mut out = vec![];
+        report.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "counterparty,deposits,withdrawals,chargebacks,chargeback_fees,net\n\
+             visa,100.0000,0.0000,100.0000,15.0000,-15.0000\n"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::engine::{Engine, EngineBuilder};
+use crate::BasicError;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+use txcli_model::{ClientId, Tx};
+
+// Rows sent to a shard at a time, and batches in flight per shard. Enough to keep every shard
+// busy without the channels costing more than applying.
+const BATCH: usize = 4096;
+const IN_FLIGHT: usize = 4;
+
+// Applies rows on a pool of engines, each owning the clients whose canonical id falls in its
+// shard (cid % shards). A client's rows all go to the same shard in the order they are pushed,
+// and nothing an engine does to one client depends on another, so every client ends up exactly
+// as on a single engine whatever the shard count. Rejections are still warned about as they
+// happen, lines from different shards interleave in no particular order.
+pub struct Shards {
+    senders: Vec<SyncSender<Vec<Tx>>>,
+    batches: Vec<Vec<Tx>>,
+    workers: Vec<JoinHandle<Engine>>,
+    joint_owners: HashMap<ClientId, ClientId>,
+}
+
+impl Shards {
+    pub fn new(builder: &EngineBuilder, shards: usize) -> Self {
+        let mut engines: Vec<Engine> = (0..shards).map(|_| builder.clone().build()).collect();
+        let joint_owners = engines[0].config().joint_owners.clone();
+        let mut senders = vec![];
+        let mut workers = vec![];
+        for mut engine in engines.drain(..) {
+            let (sender, receiver) = mpsc::sync_channel::<Vec<Tx>>(IN_FLIGHT);
+            senders.push(sender);
+            workers.push(thread::spawn(move || {
+                for batch in receiver {
+                    for tx in &batch {
+                        engine.apply(tx.as_record());
+                    }
+                }
+                engine.finish();
+                engine
+            }));
+        }
+        Shards {
+            batches: vec![vec![]; shards],
+            senders,
+            workers,
+            joint_owners,
+        }
+    }
+
+    // Queues tx for its client's shard, in order after the client's earlier rows.
+    pub fn push(&mut self, tx: Tx) -> Result<(), Box<dyn Error>> {
+        let cid = self.joint_owners.get(&tx.cid).copied().unwrap_or(tx.cid);
+        let shard = cid.0 as usize % self.senders.len();
+        self.batches[shard].push(tx);
+        if self.batches[shard].len() >= BATCH {
+            self.send(shard)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, shard: usize) -> Result<(), Box<dyn Error>> {
+        let batch = std::mem::take(&mut self.batches[shard]);
+        self.senders[shard]
+            .send(batch)
+            .map_err(|_| BasicError::new(format!("Shard {} stopped early.", shard)))?;
+        Ok(())
+    }
+
+    // Waits for every shard to apply its rows and close its run, then gathers the clients into
+    // one engine.
+    pub fn finish(mut self) -> Result<Engine, Box<dyn Error>> {
+        for shard in 0..self.senders.len() {
+            self.send(shard)?;
+        }
+        self.senders.clear();
+        let mut engines = self.workers.into_iter().enumerate().map(|(shard, worker)| {
+            worker
+                .join()
+                .map_err(|_| BasicError::new(format!("Shard {} panicked.", shard)))
+        });
+        let mut engine = engines.next().expect("at least one shard")?;
+        for other in engines {
+            engine.absorb(other?);
+        }
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SplitMix64;
+    use txcli_model::{Currency, TxType};
+
+    // A mix of every row type over a dozen clients, two of them joint owners of others.
+    fn rows(seed: u64) -> Vec<Tx> {
+        let mut rng = SplitMix64(seed);
+        (1..=2000u32)
+            .map(|tid| {
+                let cid = (rng.next() % 12) as u16;
+                let earlier = 1 + (rng.next() % tid as u64) as u32;
+                let amount = Currency::from_num(1 + rng.next() % 40);
+                match rng.next() % 8 {
+                    0..=2 => Tx::new(TxType::Deposit, cid, tid, amount),
+                    3 => Tx::new(TxType::Withdrawal, cid, tid, amount),
+                    4 => Tx::new(TxType::Dispute, cid, earlier, amount),
+                    5 => Tx::new(TxType::Resolve, cid, earlier, amount),
+                    6 => Tx::new(TxType::ChargeBack, cid, earlier, amount),
+                    _ => Tx::new(TxType::Void, cid, earlier, amount),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn any_shard_count_matches_one_engine() {
+        let builder = EngineBuilder::new()
+            .joint_owners(HashMap::from([
+                (ClientId(5), ClientId(2)),
+                (ClientId(7), ClientId(4)),
+            ]))
+            .overdraft_rate(Currency::from_num(0.01));
+        for seed in 0..5 {
+            let rows = rows(seed);
+            let mut single = builder.clone().build();
+            for tx in &rows {
+                single.apply(tx.as_record());
+            }
+            single.finish();
+            let mut expected = single.report();
+            expected.clients.sort_unstable_by_key(|client| client.cid);
+            for shards in 1..=5 {
+                let mut sharded = Shards::new(&builder, shards);
+                for tx in &rows {
+                    sharded.push(tx.clone()).unwrap();
+                }
+                let mut report = sharded.finish().unwrap().report();
+                report.clients.sort_unstable_by_key(|client| client.cid);
+                assert_eq!(report, expected, "seed {} shards {}", seed, shards);
+            }
+        }
+    }
+}
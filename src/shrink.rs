@@ -0,0 +1,321 @@
+use crate::conservation::{self, Conservation};
+use crate::engine::{Engine, EngineBuilder};
+use crate::rejection::RejectionReason;
+use crate::schema::RowReader;
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+use txcli_model::{ClientId, ClientOutputState};
+
+// How a row went wrong.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Failure {
+    Rejected(RejectionReason),
+    // The clients and the world no longer sum to zero after it, see --conservation-check.
+    Unconserved,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Rejected(reason) => write!(f, "rejected as {}", reason),
+            Failure::Unconserved => write!(f, "failing the conservation check"),
+        }
+    }
+}
+
+// What the shrunk input has to keep doing.
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    // The row at this index fails this way.
+    Failure(usize, Failure),
+    // The client ends on this balance row, None if it never appears. Boxed, as the row is as large
+    // as the widest currency makes it.
+    Balance(ClientId, Option<Box<ClientOutputState>>),
+}
+
+// Delta debugging (Zeller's ddmin) over the rows of an input: drops ever smaller runs of rows for
+// as long as what remains still reproduces the target, until no single row can go. The result is
+// minimal in that sense, not necessarily the smallest subset there is. Every attempt replays the
+// remaining rows on a fresh engine in input order, as --ordering-policy input does.
+pub struct Shrinker {
+    builder: EngineBuilder,
+    rows: RowReader,
+    headers: csv::StringRecord,
+    records: Vec<(csv::StringRecord, ClientId)>,
+    conservation: bool,
+    replays: u64,
+    // Rows that don't parse, left out as a run skips them.
+    pub malformed: u64,
+}
+
+impl Shrinker {
+    pub fn read<R: Read>(
+        reader: &mut csv::Reader<R>,
+        mut rows: RowReader,
+        builder: EngineBuilder,
+        conservation: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut records = vec![];
+        let mut malformed = 0;
+        while rows.read(reader)? {
+            match rows.parse() {
+                Ok(tx) => records.push((rows.record().clone(), tx.cid)),
+                Err(_) => malformed += 1,
+            }
+        }
+        Ok(Shrinker {
+            builder,
+            rows,
+            headers: reader.headers()?.clone(),
+            records,
+            conservation,
+            replays: 0,
+            malformed,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn replays(&self) -> u64 {
+        self.replays
+    }
+
+    // Line of the row at index in the input.
+    pub fn line(&self, index: usize) -> u64 {
+        self.records[index]
+            .0
+            .position()
+            .map_or(0, |position| position.line())
+    }
+
+    // Applies the rows at keep on a fresh engine, passing each row's index, client and failure to
+    // until, and stops early once it returns true.
+    fn replay(
+        &mut self,
+        keep: &[usize],
+        mut until: impl FnMut(usize, ClientId, Option<Failure>) -> bool,
+    ) -> Engine {
+        self.replays += 1;
+        let mut engine = self.builder.clone().build();
+        let mut world = self.conservation.then(|| Conservation::new(engine.state()));
+        for &index in keep {
+            let (record, cid) = &self.records[index];
+            self.rows.set_record(record.clone());
+            // Only rows that parsed were kept.
+            let Ok(tx) = self.rows.parse() else { continue };
+            let flow = world
+                .is_some()
+                .then(|| conservation::world_flow(engine.state(), *cid, &tx));
+            let penalties = engine.penalties_assessed();
+            let result = engine.try_apply(tx);
+            let mut failure = result.err().map(Failure::Rejected);
+            if let Some(conservation) = &mut world {
+                let charged = engine.penalties_assessed() - penalties;
+                let flow = flow.filter(|_| result.is_ok());
+                if conservation
+                    .check(engine.state(), Some(&tx), flow, charged)
+                    .is_err()
+                {
+                    failure = Some(Failure::Unconserved);
+                    // Later rows are checked against the state as it is now.
+                    *conservation = Conservation::new(engine.state());
+                }
+            }
+            if until(index, *cid, failure) {
+                break;
+            }
+        }
+        engine
+    }
+
+    // The first row of client, or of any client, to fail with failure, otherwise the balance row
+    // client ends on. None when there is nothing to preserve.
+    pub fn target(&mut self, client: Option<ClientId>, failure: bool) -> Option<Target> {
+        let all: Vec<usize> = (0..self.records.len()).collect();
+        if failure {
+            let mut first = None;
+            self.replay(&all, |index, cid, failure| {
+                if client.is_none_or(|client| client == cid) {
+                    first = failure.map(|failure| Target::Failure(index, failure));
+                }
+                first.is_some()
+            });
+            return first;
+        }
+        let cid = client?;
+        let mut engine = self.replay(&all, |_, _, _| false);
+        engine.finish();
+        Some(Target::Balance(
+            cid,
+            engine.state().output_state(cid).map(Box::new),
+        ))
+    }
+
+    fn reproduces(&mut self, keep: &[usize], target: &Target) -> bool {
+        match target {
+            Target::Failure(pinned, expected) => {
+                let mut reproduced = false;
+                self.replay(keep, |index, _, failure| {
+                    reproduced = index == *pinned && failure == Some(*expected);
+                    index == *pinned
+                });
+                reproduced
+            }
+            Target::Balance(cid, expected) => {
+                let mut engine = self.replay(keep, |_, _, _| false);
+                engine.finish();
+                engine.state().output_state(*cid).map(Box::new) == *expected
+            }
+        }
+    }
+
+    // Indices of the rows kept, in input order.
+    pub fn shrink(&mut self, target: &Target) -> Vec<usize> {
+        // A failing row is always kept and the rows after it can't matter.
+        let (pinned, cid, mut candidates): (Option<usize>, ClientId, Vec<usize>) = match target {
+            Target::Failure(index, _) => {
+                (Some(*index), self.records[*index].1, (0..*index).collect())
+            }
+            Target::Balance(cid, _) => (None, *cid, (0..self.records.len()).collect()),
+        };
+        let with = |candidates: &[usize]| -> Vec<usize> {
+            candidates.iter().copied().chain(pinned).collect()
+        };
+
+        // Other clients' rows rarely matter, one replay rules them all out at once.
+        let own: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|index| self.records[*index].1 == cid)
+            .collect();
+        if own.len() < candidates.len() && self.reproduces(&with(&own), target) {
+            candidates = own;
+        }
+
+        let mut granularity = 2;
+        while candidates.len() >= 2 {
+            let chunk = candidates.len().div_ceil(granularity);
+            let mut reduced = false;
+            for start in (0..candidates.len()).step_by(chunk) {
+                let end = (start + chunk).min(candidates.len());
+                let subset = candidates[start..end].to_vec();
+                if self.reproduces(&with(&subset), target) {
+                    candidates = subset;
+                    granularity = 2;
+                    reduced = true;
+                    break;
+                }
+                // With two chunks each one's complement is the other.
+                if granularity == 2 {
+                    continue;
+                }
+                let complement: Vec<usize> = candidates[..start]
+                    .iter()
+                    .chain(&candidates[end..])
+                    .copied()
+                    .collect();
+                if self.reproduces(&with(&complement), target) {
+                    candidates = complement;
+                    granularity = (granularity - 1).max(2);
+                    reduced = true;
+                    break;
+                }
+            }
+            if !reduced {
+                if granularity >= candidates.len() {
+                    break;
+                }
+                granularity = (granularity * 2).min(candidates.len());
+            }
+        }
+        if candidates.len() == 1 && self.reproduces(&with(&[]), target) {
+            candidates.clear();
+        }
+        with(&candidates)
+    }
+
+    // The rows at keep, as they were in the input, under its header.
+    pub fn write<W: Write>(&self, keep: &[usize], writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(writer);
+        writer.write_record(&self.headers)?;
+        for &index in keep {
+            writer.write_record(&self.records[index].0)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+
+    fn shrinker(input: &str, builder: EngineBuilder) -> Shrinker {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let rows = Schema::V1.reader(reader.headers().unwrap()).unwrap();
+        Shrinker::read(&mut reader, rows, builder, true).unwrap()
+    }
+
+    fn shrunk(shrinker: &mut Shrinker, target: &Target) -> String {
+        let keep = shrinker.shrink(target);
+        let mut out = vec![];
+        shrinker.write(&keep, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn keeps_what_a_rejection_needs() {
+        let mut input = String::from("type,client,tx,amount\n");
+        for tid in 1..=200 {
+            input += &format!("deposit,{},{},1.0\n", tid % 7 + 1, tid);
+        }
+        // Client 3 locks, so its withdrawal is rejected.
+        input += "dispute,3,2,\nchargeback,3,2,\ndeposit,three,9,1\nwithdrawal,3,300,0.5\ndeposit,5,301,1\n";
+        let mut shrinker = shrinker(&input, EngineBuilder::new());
+        assert_eq!(shrinker.malformed, 1);
+        let target = shrinker.target(None, true).unwrap();
+        assert_eq!(
+            target,
+            Target::Failure(202, Failure::Rejected(RejectionReason::Locked))
+        );
+        assert_eq!(shrinker.line(202), 205);
+        assert_eq!(
+            shrunk(&mut shrinker, &target),
+            "type,client,tx,amount\n\
+             deposit,3,2,1.0\n\
+             dispute,3,2,\n\
+             chargeback,3,2,\n\
+             withdrawal,3,300,0.5\n"
+        );
+        // No rows of client 6 fail.
+        assert_eq!(shrinker.target(Some(ClientId(6)), true), None);
+    }
+
+    #[test]
+    fn keeps_a_balance() {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,5\n\
+                     deposit,2,2,3\n\
+                     withdrawal,1,3,9\n\
+                     deposit,1,4,1\n\
+                     withdrawal,1,5,2\n";
+        let mut shrinker = shrinker(input, EngineBuilder::new());
+        let target = shrinker.target(Some(ClientId(1)), false).unwrap();
+        // The rejected withdrawal changes nothing, and client 2 is someone else.
+        assert_eq!(
+            shrunk(&mut shrinker, &target),
+            "type,client,tx,amount\n\
+             deposit,1,1,5\n\
+             deposit,1,4,1\n\
+             withdrawal,1,5,2\n"
+        );
+    }
+}
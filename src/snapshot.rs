@@ -0,0 +1,243 @@
+use crate::availability::Hold;
+use crate::dispute_state::DisputeState;
+use crate::{AppState, Balance, BasicError, ClientState};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use txcli_model::{AccountId, ClientId, Currency, Tx, TxId, CURRENCY_FORMAT};
+
+// Bumped whenever a field changes meaning, older snapshots are refused rather than misread.
+const VERSION: u32 = 1;
+
+// Everything the engine carries from one run into the next: every client with its history, open
+// disputes and lifecycle, plus the queued disputes, risk lock counts and dispute windows. Restored
+// with --snapshot-in, the next day's file applies exactly as if it followed the history in one
+// input. Maps are ordered and amounts exact, so the same state always writes the same bytes.
+// The run's counts and totals (rejects, penalties assessed, swept dust) aren't kept, each run
+// reports on its own rows.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Snapshot {
+    version: u32,
+    // Amounts only restore exactly into the same fixed point format.
+    currency_format: String,
+    clients: BTreeMap<ClientId, ClientSnapshot>,
+    // Snapshots from before archive_client have none.
+    #[serde(default)]
+    archived: BTreeMap<ClientId, ClientSnapshot>,
+    pub queued: BTreeMap<ClientId, Vec<Tx>>,
+    pub charged_back: BTreeMap<ClientId, (u64, Currency)>,
+    pub recent: BTreeMap<ClientId, Vec<TxId>>,
+    // Deposits pending settlement, left out when there are none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub holds: Vec<Hold>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct ClientSnapshot {
+    accounts: BTreeMap<AccountId, Balance>,
+    // Left out when empty, so snapshots of files without currencies read the same as before.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    assets: BTreeMap<String, BTreeMap<AccountId, Balance>>,
+    locked: bool,
+    history: BTreeMap<TxId, Tx>,
+    disputed: BTreeMap<TxId, Tx>,
+    lifecycle: BTreeMap<TxId, DisputeState>,
+    // Only partial-hold disputes leave any, left out when empty like assets.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    shortfalls: BTreeMap<TxId, Currency>,
+}
+
+fn ordered<K: Ord + Clone, V: Clone>(map: &HashMap<K, V>) -> BTreeMap<K, V> {
+    map.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+}
+
+fn snapshots(clients: &HashMap<ClientId, ClientState>) -> BTreeMap<ClientId, ClientSnapshot> {
+    clients
+        .iter()
+        .map(|(cid, client)| {
+            let snapshot = ClientSnapshot {
+                accounts: client.accounts.clone(),
+                assets: client.assets.clone(),
+                locked: client.locked,
+                history: ordered(&client.history),
+                disputed: ordered(&client.disputed),
+                lifecycle: ordered(&client.lifecycle),
+                shortfalls: ordered(&client.shortfalls),
+            };
+            (*cid, snapshot)
+        })
+        .collect()
+}
+
+fn states(clients: BTreeMap<ClientId, ClientSnapshot>) -> HashMap<ClientId, ClientState> {
+    clients
+        .into_iter()
+        .map(|(cid, client)| {
+            let state = ClientState {
+                accounts: client.accounts,
+                assets: client.assets,
+                locked: client.locked,
+                history: client.history.into_iter().collect(),
+                disputed: client.disputed.into_iter().collect(),
+                lifecycle: client.lifecycle.into_iter().collect(),
+                shortfalls: client.shortfalls.into_iter().collect(),
+            };
+            (cid, state)
+        })
+        .collect()
+}
+
+impl Snapshot {
+    pub fn new(
+        state: &AppState,
+        queued: &HashMap<ClientId, VecDeque<Tx>>,
+        charged_back: &HashMap<ClientId, (u64, Currency)>,
+        recent: &HashMap<ClientId, VecDeque<TxId>>,
+    ) -> Self {
+        Snapshot {
+            version: VERSION,
+            currency_format: CURRENCY_FORMAT.to_string(),
+            clients: snapshots(&state.clients),
+            archived: snapshots(&state.archived),
+            queued: queued
+                .iter()
+                .filter(|(_, queue)| !queue.is_empty())
+                .map(|(cid, queue)| (*cid, queue.iter().cloned().collect()))
+                .collect(),
+            charged_back: ordered(charged_back),
+            recent: recent
+                .iter()
+                .map(|(cid, window)| (*cid, window.iter().copied().collect()))
+                .collect(),
+            holds: vec![],
+        }
+    }
+
+    pub fn read(path: &str) -> Result<Self, Box<dyn Error>> {
+        let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|err| BasicError::new(format!("Invalid snapshot {}: {}", path, err)))?;
+        if snapshot.version != VERSION {
+            return Err(BasicError::new(format!(
+                "Snapshot {} is version {}, this txcli reads version {}.",
+                path, snapshot.version, VERSION
+            )));
+        }
+        if snapshot.currency_format != CURRENCY_FORMAT {
+            return Err(BasicError::new(format!(
+                "Snapshot {} holds {} amounts, this txcli is built for {}.",
+                path, snapshot.currency_format, CURRENCY_FORMAT
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    // Under a temporary name then renamed, a run failing halfway never leaves half a snapshot.
+    // A write that fails, e.g. on a full disk, takes its partial file with it and leaves the
+    // snapshot already at path as it was.
+    pub fn write(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let partial = format!("{}.partial", path);
+        let bytes = serde_json::to_vec(self)?;
+        let written = File::create(&partial).and_then(|file| {
+            #[cfg(feature = "faults")]
+            let file = crate::faults::snapshot(file);
+            let mut file = file;
+            file.write_all(&bytes)?;
+            file.flush()
+        });
+        if let Err(err) = written {
+            let _ = fs::remove_file(&partial);
+            return Err(BasicError::new(format!(
+                "Can't write snapshot {}: {}",
+                path, err
+            )));
+        }
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    // Replaces the clients in state, archived ones included, keeping its joint owners, which
+    // come from --clients.
+    pub fn restore_clients(&mut self, state: &mut AppState) {
+        state.clients = states(std::mem::take(&mut self.clients));
+        state.archived = states(std::mem::take(&mut self.archived));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineBuilder, Overflow};
+    use crate::retention::Retention;
+    use txcli_model::{TxRecord, TxType};
+
+    fn rows() -> Vec<Tx> {
+        let amount = |amount: f64| Currency::from_num(amount);
+        vec![
+            Tx::new(TxType::Deposit, 1, 1, amount(10.0)),
+            Tx::new(TxType::Deposit, 1, 2, amount(0.0001)),
+            Tx::new(TxType::Deposit, 2, 3, amount(5.0)).with_account("savings"),
+            Tx::new(TxType::Dispute, 1, 1, amount(0.0)),
+            Tx::new(TxType::Dispute, 1, 2, amount(0.0)),
+            Tx::new(TxType::Withdrawal, 2, 4, amount(1.5)).with_account("savings"),
+            Tx::new(TxType::Deposit, 2, 5, amount(3.0)),
+            Tx::new(TxType::Dispute, 2, 3, amount(0.0)),
+            Tx::new(TxType::ChargeBack, 2, 3, amount(0.0)),
+            Tx::new(TxType::Resolve, 1, 1, amount(0.0)),
+            Tx::new(TxType::Deposit, 1, 6, amount(2.0)),
+            Tx::new(TxType::Void, 1, 6, amount(0.0)),
+            Tx::new(TxType::Resolve, 1, 2, amount(0.0)),
+            Tx::new(TxType::Dispute, 2, 5, amount(0.0)),
+            Tx::new(TxType::Deposit, 3, 7, amount(1.0)),
+            Tx::new(TxType::Withdrawal, 3, 8, amount(1.0)),
+            Tx::new(TxType::ArchiveClient, 3, 9, amount(0.0)),
+        ]
+    }
+
+    // Any split of the rows into two runs, carried over by a snapshot, ends where one run does.
+    #[test]
+    fn resuming_matches_one_run() {
+        let builder = EngineBuilder::new()
+            .dispute_cap(1, Overflow::Queue)
+            .retention(Retention::LastN(2));
+        let rows = rows();
+        let mut whole = builder.clone().build();
+        for tx in &rows {
+            whole.apply(tx.as_record());
+        }
+        let path = std::env::temp_dir().join(format!("txcli-snapshot-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        for split in 0..=rows.len() {
+            let mut first = builder.clone().build();
+            for tx in &rows[..split] {
+                first.apply(tx.as_record());
+            }
+            first.snapshot().write(path).unwrap();
+            let mut second = builder.clone().build();
+            second.restore(Snapshot::read(path).unwrap());
+            for tx in &rows[split..] {
+                second.apply(tx.as_record());
+            }
+            assert_eq!(second.snapshot(), whole.snapshot(), "split at {}", split);
+        }
+
+        // The same state writes the same bytes.
+        whole.snapshot().write(path).unwrap();
+        let written = fs::read(path).unwrap();
+        let mut restored = builder.clone().build();
+        restored.restore(Snapshot::read(path).unwrap());
+        restored.snapshot().write(path).unwrap();
+        assert_eq!(fs::read(path).unwrap(), written);
+
+        let older = String::from_utf8(written)
+            .unwrap()
+            .replace("\"version\":1", "\"version\":0");
+        fs::write(path, older).unwrap();
+        assert!(Snapshot::read(path).is_err());
+        fs::remove_file(path).unwrap();
+        assert!(restored
+            .try_apply(TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(1)))
+            .is_err());
+    }
+}
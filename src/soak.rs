@@ -0,0 +1,479 @@
+use crate::engine::{Engine, EngineBuilder, LockedPolicy};
+use crate::sample::SplitMix64;
+use crate::{memory, BasicError};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use txcli_model::{ClientId, Currency, TxRecord, TxType};
+
+const CLIENTS: u16 = 1000;
+// Bounds what the generator remembers per client, so the soak itself doesn't grow with the run.
+const RECENT_DEPOSITS: usize = 32;
+const MAX_OPEN_DISPUTES: usize = 8;
+const REPORT_EVERY: Duration = Duration::from_secs(10);
+
+// Accepts plain seconds or a number with an s, m, h or d suffix, e.g. 90s, 30m, 24h.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|number| *number > 0)
+        .map(|number| Duration::from_secs(number * seconds))
+}
+
+// Log linear buckets, 16 per power of two, so memory stays fixed however long the soak runs.
+// Percentiles are reported as the bucket's upper bound, at most 1/16 above the real value.
+const SUB_BUCKETS: u64 = 16;
+const BUCKETS: usize = 976;
+
+#[derive(Clone)]
+struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            total: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    fn bucket(value: u64) -> usize {
+        if value < SUB_BUCKETS {
+            return value as usize;
+        }
+        let magnitude = 63 - value.leading_zeros() as u64;
+        let sub = (value >> (magnitude - 4)) & (SUB_BUCKETS - 1);
+        ((magnitude - 3) * SUB_BUCKETS + sub) as usize
+    }
+
+    fn upper_bound(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < SUB_BUCKETS {
+            return bucket;
+        }
+        let shift = bucket / SUB_BUCKETS - 1;
+        let lower = (SUB_BUCKETS + bucket % SUB_BUCKETS) << shift;
+        lower + ((1u64 << shift) - 1)
+    }
+
+    fn record(&mut self, value: u64) {
+        self.counts[Self::bucket(value)] += 1;
+        self.total += 1;
+        self.max = self.max.max(value);
+    }
+
+    fn percentile(&self, quantile: f64) -> u64 {
+        let rank = ((quantile * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::upper_bound(bucket).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+}
+
+#[derive(Default)]
+struct ShadowClient {
+    available: Currency,
+    held: Currency,
+    recent_deposits: VecDeque<(u32, Currency)>,
+    disputed: Vec<(u32, Currency)>,
+}
+
+impl ShadowClient {
+    fn push_deposit(&mut self, tid: u32, amount: Currency) {
+        if self.recent_deposits.len() == RECENT_DEPOSITS {
+            self.recent_deposits.pop_front();
+        }
+        self.recent_deposits.push_back((tid, amount));
+    }
+}
+
+// Generates traffic the engine should accept in full and tracks what the balances must be
+// afterwards. Withdrawals never exceed the shadow balance and disputes only reference known
+// deposits, so a rejection or a mismatch against the shadow is drift in the engine.
+struct Traffic {
+    rng: SplitMix64,
+    next_tid: u32,
+    clients: Vec<ShadowClient>,
+    // Deposits and withdrawals the engine should be holding in history or disputes.
+    stored: u64,
+    open_disputes: u64,
+}
+
+impl Traffic {
+    fn new(seed: u64) -> Self {
+        Traffic {
+            rng: SplitMix64(seed),
+            next_tid: 0,
+            clients: (0..CLIENTS).map(|_| ShadowClient::default()).collect(),
+            stored: 0,
+            open_disputes: 0,
+        }
+    }
+
+    // Whole cents between 0.01 and max.
+    fn amount(&mut self, max: Currency) -> Currency {
+        let max_cents = (max * Currency::from_num(100)).to_num::<u64>().max(1);
+        Currency::from_num(self.rng.next() % max_cents + 1) / Currency::from_num(100)
+    }
+
+    fn next(&mut self) -> Result<TxRecord<'static>, Box<dyn Error>> {
+        let cid = (self.rng.next() % CLIENTS as u64) as u16;
+        let roll = self.rng.next() % 1000;
+        let client = &self.clients[cid as usize];
+        let (available, deposits, disputes) = (
+            client.available,
+            client.recent_deposits.len(),
+            client.disputed.len(),
+        );
+        let tx_type = match roll {
+            0..=449 => TxType::Deposit,
+            450..=899 if available >= Currency::from_num(0.01) => TxType::Withdrawal,
+            900..=949 if deposits > 0 && disputes < MAX_OPEN_DISPUTES => TxType::Dispute,
+            950..=994 if disputes > 0 => TxType::Resolve,
+            995..=999 if disputes > 0 => TxType::ChargeBack,
+            _ => TxType::Deposit,
+        };
+
+        let tx = match tx_type {
+            TxType::Deposit | TxType::Withdrawal => {
+                let tid = self.next_tid;
+                self.next_tid = self.next_tid.checked_add(1).ok_or_else(|| {
+                    BasicError::new("Soak ran out of transaction ids, use a shorter --duration.")
+                })?;
+                let amount = match tx_type {
+                    TxType::Deposit => self.amount(Currency::from_num(1000)),
+                    // Cents don't divide exactly in binary, never round past the balance.
+                    _ => self
+                        .amount(available.min(Currency::from_num(1000)))
+                        .min(available),
+                };
+                let client = &mut self.clients[cid as usize];
+                if tx_type == TxType::Deposit {
+                    client.available += amount;
+                    client.push_deposit(tid, amount);
+                } else {
+                    client.available -= amount;
+                }
+                self.stored += 1;
+                TxRecord::new(tx_type, cid, tid, amount)
+            }
+            TxType::Dispute => {
+                let pick = self.rng.next() as usize % deposits;
+                let client = &mut self.clients[cid as usize];
+                let (tid, amount) = client.recent_deposits.remove(pick).unwrap();
+                client.available -= amount;
+                client.held += amount;
+                client.disputed.push((tid, amount));
+                self.open_disputes += 1;
+                TxRecord::new(tx_type, cid, tid, Currency::default())
+            }
+            _ => {
+                let pick = self.rng.next() as usize % disputes;
+                let client = &mut self.clients[cid as usize];
+                let (tid, amount) = client.disputed.swap_remove(pick);
+                client.held -= amount;
+                if tx_type == TxType::Resolve {
+                    client.available += amount;
+                    client.push_deposit(tid, amount);
+                }
+                self.open_disputes -= 1;
+                TxRecord::new(tx_type, cid, tid, Currency::default())
+            }
+        };
+        Ok(tx)
+    }
+}
+
+#[derive(Serialize)]
+struct ReportRow {
+    elapsed_seconds: u64,
+    transactions: u64,
+    tps: u64,
+    p50_us: f64,
+    p99_us: f64,
+    p999_us: f64,
+    max_us: f64,
+    rss_bytes: Option<u64>,
+    stored_txs: u64,
+}
+
+fn micros(nanos: u64) -> f64 {
+    nanos as f64 / 1000.0
+}
+
+// Drives the engine with generated traffic at a fixed rate, checking the invariants and writing
+// a latency/memory row every REPORT_EVERY. The first failed invariant stops the run.
+pub struct Soak {
+    engine: Engine,
+    traffic: Traffic,
+    transactions: u64,
+    window: Histogram,
+    overall: Histogram,
+}
+
+impl Soak {
+    pub fn new(seed: u64) -> Self {
+        Soak {
+            // The shadow doesn't model locks, chargebacks would shut clients out of the traffic.
+            engine: EngineBuilder::new()
+                .locked_policy(LockedPolicy::Allow)
+                .build(),
+            traffic: Traffic::new(seed),
+            transactions: 0,
+            window: Histogram::default(),
+            overall: Histogram::default(),
+        }
+    }
+
+    fn step(&mut self) -> Result<(), Box<dyn Error>> {
+        let tx = self.traffic.next()?;
+        let start = Instant::now();
+        let applied = self.engine.apply(tx);
+        self.window.record(start.elapsed().as_nanos() as u64);
+        self.transactions += 1;
+        if !applied {
+            return Err(BasicError::new(format!(
+                "Soak invariant failed: the engine rejected {:?} tid[{}] for client {}, which the generator expected to apply.",
+                tx.tx_type, tx.tid.0, tx.cid.0
+            )));
+        }
+        Ok(())
+    }
+
+    // Every client's balances match the shadow, nothing is held without an open dispute, and
+    // history plus open disputes hold exactly the generated deposits and withdrawals.
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        let state = self.engine.state();
+        let fail = |desc: String| -> Result<(), Box<dyn Error>> {
+            Err(BasicError::new(format!("Soak invariant failed: {}", desc)))
+        };
+        for (cid, shadow) in self.traffic.clients.iter().enumerate() {
+            let (available, held) = match state.clients.get(&ClientId(cid as u16)) {
+                Some(client) => (client.available(), client.held()),
+                None => Default::default(),
+            };
+            if (available, held) != (shadow.available, shadow.held) {
+                return fail(format!(
+                    "client {} has available {} held {}, expected available {} held {}.",
+                    cid, available, held, shadow.available, shadow.held
+                ));
+            }
+        }
+        let held: Currency = state.clients.values().map(|client| client.held()).sum();
+        let (stored, disputed) =
+            state
+                .clients
+                .values()
+                .fold((0, 0), |(stored, disputed), client| {
+                    (
+                        stored + client.history.len() as u64,
+                        disputed + client.disputed.len() as u64,
+                    )
+                });
+        if held < Currency::default() {
+            return fail(format!("total held funds are negative ({}).", held));
+        }
+        if disputed != self.traffic.open_disputes {
+            return fail(format!(
+                "{} open disputes, expected {}.",
+                disputed, self.traffic.open_disputes
+            ));
+        }
+        if stored + disputed != self.traffic.stored {
+            return fail(format!(
+                "{} stored transactions, expected {}.",
+                stored + disputed,
+                self.traffic.stored
+            ));
+        }
+        Ok(())
+    }
+
+    fn report<W: Write>(
+        &mut self,
+        writer: &mut csv::Writer<W>,
+        elapsed: Duration,
+        window: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check()?;
+        let row = ReportRow {
+            elapsed_seconds: elapsed.as_secs(),
+            transactions: self.window.total,
+            tps: (self.window.total as f64 / window.as_secs_f64().max(f64::EPSILON)) as u64,
+            p50_us: micros(self.window.percentile(0.5)),
+            p99_us: micros(self.window.percentile(0.99)),
+            p999_us: micros(self.window.percentile(0.999)),
+            max_us: micros(self.window.max),
+            rss_bytes: memory::rss(),
+            stored_txs: self.traffic.stored,
+        };
+        writer.serialize(&row)?;
+        writer.flush()?;
+        self.overall.merge(&self.window);
+        self.window = Histogram::default();
+        Ok(())
+    }
+
+    // Rows are paced against the wall clock. When the engine can't keep up it works through the
+    // backlog in slices, so reports still come out on time and show the shortfall in their tps.
+    pub fn run<W: Write>(
+        &mut self,
+        duration: Duration,
+        tps: u64,
+        writer: W,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        let slice = (tps / 100).max(1);
+        let start = Instant::now();
+        let mut last_report = Duration::ZERO;
+        loop {
+            let elapsed = start.elapsed().min(duration);
+            let due = (elapsed.as_secs_f64() * tps as f64) as u64;
+            if self.transactions >= due {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            let behind = due.saturating_sub(self.transactions);
+            let batch = if elapsed == duration {
+                behind
+            } else {
+                behind.min(slice)
+            };
+            for _ in 0..batch {
+                self.step()?;
+            }
+            if elapsed == duration || elapsed - last_report >= REPORT_EVERY {
+                self.report(&mut writer, elapsed, elapsed - last_report)?;
+                last_report = elapsed;
+            }
+            if elapsed == duration {
+                break;
+            }
+        }
+
+        eprintln!(
+            "Soak passed: {} transactions over {}s, p50 {}us p99 {}us p99.9 {}us max {}us, peak rss {}.",
+            self.overall.total,
+            duration.as_secs(),
+            micros(self.overall.percentile(0.5)),
+            micros(self.overall.percentile(0.99)),
+            micros(self.overall.percentile(0.999)),
+            micros(self.overall.max),
+            memory::peak_rss().map_or("unavailable".to_string(), |rss| format!("{} bytes", rss))
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("24h"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("2d"), Some(Duration::from_secs(172800)));
+        for bad in ["", "0h", "h", "1w", "1.5h", "-1s"] {
+            assert_eq!(parse_duration(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn histogram_percentiles() {
+        let mut histogram = Histogram::default();
+        for value in 1..=1000 {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.percentile(0.0), 1);
+        assert_eq!(histogram.max, 1000);
+        assert_eq!(histogram.percentile(1.0), 1000);
+        // Within the 1/16 bucket resolution, never below the true value.
+        let p50 = histogram.percentile(0.5);
+        assert!((500..=500 + 500 / 16).contains(&p50), "{}", p50);
+        for value in [0, 15, 16, 17, 31, 32, 1 << 40, u64::MAX] {
+            let bucket = Histogram::bucket(value);
+            assert!(bucket < BUCKETS);
+            assert!(Histogram::upper_bound(bucket) >= value);
+        }
+    }
+
+    #[test]
+    fn generated_traffic_holds_invariants() {
+        let mut soak = Soak::new(1);
+        for _ in 0..50 {
+            for _ in 0..1000 {
+                soak.step().unwrap();
+            }
+            soak.check().unwrap();
+        }
+        assert!(soak.traffic.open_disputes > 0);
+        assert!(soak
+            .engine
+            .state()
+            .clients
+            .values()
+            .any(|client| client.locked));
+    }
+
+    #[test]
+    fn check_catches_drift() {
+        let mut soak = Soak::new(7);
+        for _ in 0..1000 {
+            soak.step().unwrap();
+        }
+        let client = soak.engine.state_mut().clients.values_mut().next().unwrap();
+        client.account("default").available += Currency::from_num(1);
+        assert!(soak.check().is_err());
+    }
+
+    #[test]
+    fn short_run_writes_reports() {
+        let mut out = vec![];
+        Soak::new(3)
+            .run(Duration::from_secs(1), 2000, &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "elapsed_seconds,transactions,tps,p50_us,p99_us,p999_us,max_us,rss_bytes,stored_txs"
+        );
+        assert!(lines.next().unwrap().starts_with("1,2000,2000,"));
+        assert_eq!(lines.next(), None);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::fixed_width::Layout;
+use crate::options::Options;
+use crate::BasicError;
+#[cfg(feature = "parquet")]
+use parquet::file::reader::SerializedFileReader;
+#[cfg(feature = "parquet")]
+use parquet::record::Field;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::str::FromStr;
+
+// The columns JSON lines, Parquet and fixed-width rows are read into: the v1 columns in their
+// positions, then the rest of v2, so the rows read the same under either --schema.
+pub(crate) const COLUMNS: [&str; 13] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "account",
+    "to_account",
+    "category",
+    "memo",
+    "timestamp",
+    "currency",
+    "counterparty",
+    "priority",
+    "source",
+];
+
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    Csv,
+    // One JSON object per line, keyed by column name.
+    Jsonl,
+    // Columns named like the csv header's.
+    Parquet,
+    // Records with each field at fixed byte positions, as a --layout file gives them.
+    #[serde(rename = "fixed-width")]
+    FixedWidth,
+}
+
+impl FromStr for InputFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "jsonl" => Ok(InputFormat::Jsonl),
+            "parquet" => Ok(InputFormat::Parquet),
+            "fixed-width" => Ok(InputFormat::FixedWidth),
+            _ => Err(BasicError::new(format!(
+                "Unknown --input-format {}, expected csv, jsonl, parquet or fixed-width.",
+                s
+            ))),
+        }
+    }
+}
+
+impl InputFormat {
+    // The format given, or else the one the path's extension names, csv for anything else. Fixed
+    // width is never guessed, its files have no extension of their own.
+    pub fn of(path: &str, format: Option<InputFormat>) -> Self {
+        format.unwrap_or_else(
+            || match path.rsplit_once('.').map(|(_, extension)| extension) {
+                Some("jsonl" | "ndjson") => InputFormat::Jsonl,
+                Some("parquet") => InputFormat::Parquet,
+                _ => InputFormat::Csv,
+            },
+        )
+    }
+}
+
+// Errors are io errors, the csv reader hands them on as they are.
+type Rows = Box<dyn Iterator<Item = io::Result<csv::StringRecord>> + Send>;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// An input in any format, read as csv. Csv is passed through untouched, other formats are
+// converted a row at a time under a header naming every column, so every command reads them
+// through the same csv reader and schema as always, and nothing downstream knows the difference.
+// Converted rows are numbered as csv lines, the first row is line 2.
+pub struct TxSource {
+    inner: Inner,
+}
+
+enum Inner {
+    Csv(File),
+    Converted {
+        rows: Rows,
+        // The row being handed out as csv, and how much of it already was.
+        pending: Vec<u8>,
+        offset: usize,
+    },
+}
+
+impl TxSource {
+    // Fixed-width inputs need their layout, see TxSource::fixed_width.
+    pub fn open(path: &str, format: InputFormat) -> Result<Self, Box<dyn Error>> {
+        TxSource::open_with(path, format, None)
+    }
+
+    // The input path of a run in the format its options give, with the --layout file loaded for a
+    // fixed-width one. Records failing to convert stop the read with their number, the byte they
+    // start at and the field that failed.
+    pub(crate) fn for_run(path: &str, options: &Options) -> Result<Self, Box<dyn Error>> {
+        let layout = match &options.layout {
+            Some(layout) => Some(Layout::load(layout)?),
+            None => None,
+        };
+        TxSource::open_with(
+            path,
+            InputFormat::of(path, options.input_format),
+            layout.as_ref(),
+        )
+    }
+
+    fn open_with(
+        path: &str,
+        format: InputFormat,
+        layout: Option<&Layout>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let name = path.to_string();
+        let rows: Rows = match format {
+            InputFormat::Csv => {
+                return Ok(TxSource {
+                    inner: Inner::Csv(file),
+                })
+            }
+            InputFormat::Jsonl => Box::new(
+                BufReader::new(file)
+                    .lines()
+                    .enumerate()
+                    .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+                    .map(move |(index, line)| {
+                        record_of_json(&line?).map_err(|err| {
+                            invalid(format!("line {} of {}: {}", index + 1, name, err))
+                        })
+                    }),
+            ),
+            InputFormat::FixedWidth => {
+                let Some(layout) = layout.cloned() else {
+                    return Err(BasicError::new(format!(
+                        "Can't read {}, --input-format fixed-width needs a --layout.",
+                        path
+                    )));
+                };
+                let records = layout.records(BufReader::new(file)).enumerate();
+                Box::new(records.map(move |(index, record)| {
+                    let (start, record) =
+                        record.map_err(|err| invalid(format!("{}: {}", name, err)))?;
+                    layout.row(&record).map_err(|err| {
+                        invalid(format!(
+                            "record {} of {}, at byte {}: {}",
+                            index + 1,
+                            name,
+                            start,
+                            err
+                        ))
+                    })
+                }))
+            }
+            #[cfg(not(feature = "parquet"))]
+            InputFormat::Parquet => {
+                return Err(BasicError::new(format!(
+                "Can't read {}, --input-format parquet needs txcli built with the parquet feature.",
+                path
+            )))
+            }
+            #[cfg(feature = "parquet")]
+            InputFormat::Parquet => {
+                let reader = SerializedFileReader::new(file)
+                    .map_err(|err| BasicError::new(format!("Invalid Parquet {}: {}", path, err)))?;
+                Box::new(reader.into_iter().enumerate().map(move |(index, row)| {
+                    let row = row.map_err(|err| {
+                        invalid(format!("row {} of {}: {}", index + 1, name, err))
+                    })?;
+                    Ok(COLUMNS
+                        .iter()
+                        .map(|column| {
+                            row.get_column_iter()
+                                .find(|(name, _)| name == column)
+                                .map_or(String::new(), |(_, field)| text_of_field(field))
+                        })
+                        .collect())
+                }))
+            }
+        };
+        let header = std::iter::once(Ok(COLUMNS.iter().collect()));
+        Ok(TxSource {
+            inner: Inner::Converted {
+                rows: Box::new(header.chain(rows)),
+                pending: vec![],
+                offset: 0,
+            },
+        })
+    }
+}
+
+impl Read for TxSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read_rows(buf);
+        #[cfg(feature = "faults")]
+        let read = crate::faults::input(read);
+        read
+    }
+}
+
+impl TxSource {
+    fn read_rows(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (rows, pending, offset) = match &mut self.inner {
+            Inner::Csv(file) => return file.read(buf),
+            Inner::Converted {
+                rows,
+                pending,
+                offset,
+            } => (rows, pending, offset),
+        };
+        while *offset == pending.len() {
+            let Some(row) = rows.next() else {
+                return Ok(0);
+            };
+            let row = row?;
+            pending.clear();
+            *offset = 0;
+            let mut writer = csv::Writer::from_writer(&mut *pending);
+            writer.write_record(&row)?;
+            writer.flush()?;
+        }
+        let read = buf.len().min(pending.len() - *offset);
+        buf[..read].copy_from_slice(&pending[*offset..*offset + read]);
+        *offset += read;
+        Ok(read)
+    }
+}
+
+// Unknown keys are ignored like unknown v2 columns, numbers are taken as written.
+fn record_of_json(line: &str) -> Result<csv::StringRecord, Box<dyn Error>> {
+    let object = match serde_json::from_str(line)? {
+        Value::Object(object) => object,
+        _ => return Err(BasicError::new("expected a JSON object")),
+    };
+    Ok(COLUMNS
+        .iter()
+        .map(|column| match object.get(*column) {
+            Some(Value::String(value)) => value.clone(),
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        })
+        .collect())
+}
+
+// Parquet timestamps become the unix seconds txcli reads, decimals keep their exact digits.
+#[cfg(feature = "parquet")]
+fn text_of_field(field: &Field) -> String {
+    match field {
+        Field::Null => String::new(),
+        Field::Str(value) => value.clone(),
+        Field::TimestampMillis(millis) => (millis / 1000).to_string(),
+        Field::TimestampMicros(micros) => (micros / 1_000_000).to_string(),
+        field => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+    use txcli_model::{Currency, Timestamp, Tx, TxType};
+
+    fn read(path: &str) -> Vec<Tx> {
+        let source = TxSource::open(path, InputFormat::of(path, None)).unwrap();
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(source);
+        let mut rows = Schema::detect(reader.headers().unwrap())
+            .reader(reader.headers().unwrap())
+            .unwrap();
+        let mut txs = vec![];
+        while let Some(row) = rows.next(&mut reader) {
+            txs.push(row.unwrap().to_tx());
+        }
+        txs
+    }
+
+    fn expected() -> Vec<Tx> {
+        let mut deposit = Tx::new(TxType::Deposit, 1, 1, Currency::from_num(2.5));
+        deposit.timestamp = Some(Timestamp(1660052700));
+        deposit.counterparty = Some("visa".to_string());
+        vec![
+            deposit,
+            Tx::new(TxType::Withdrawal, 1, 2, Currency::from_num(1)).with_account("savings"),
+            Tx::new(TxType::Dispute, 1, 1, Currency::from_num(0)),
+        ]
+    }
+
+    #[test]
+    fn jsonl_and_parquet_read_like_csv() {
+        let dir = std::env::temp_dir().join(format!("txcli-source-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let jsonl = dir.join("in.jsonl");
+        std::fs::write(
+            &jsonl,
+            "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.5\",\"timestamp\":1660052700,\"counterparty\":\"visa\",\"note\":\"ignored\"}\n\
+            \n\
+            {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"1\",\"account\":\"savings\"}\n\
+            {\"type\":\"dispute\",\"client\":1,\"tx\":1,\"amount\":null}\n",
+        )
+        .unwrap();
+        assert_eq!(read(jsonl.to_str().unwrap()), expected());
+
+        #[cfg(feature = "parquet")]
+        {
+            use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+            use parquet::file::properties::WriterProperties;
+            use parquet::file::writer::SerializedFileWriter;
+            use parquet::schema::parser::parse_message_type;
+            use std::sync::Arc;
+
+            let parquet = dir.join("in.parquet");
+            let schema = Arc::new(
+                parse_message_type(
+                    "message tx {
+                        REQUIRED BINARY type (UTF8);
+                        REQUIRED INT32 client;
+                        REQUIRED INT64 tx;
+                        OPTIONAL BINARY amount (UTF8);
+                        OPTIONAL BINARY account (UTF8);
+                        OPTIONAL INT64 timestamp (TIMESTAMP_MILLIS);
+                        OPTIONAL BINARY counterparty (UTF8);
+                    }",
+                )
+                .unwrap(),
+            );
+            let mut writer = SerializedFileWriter::new(
+                File::create(&parquet).unwrap(),
+                schema,
+                Arc::new(WriterProperties::builder().build()),
+            )
+            .unwrap();
+            let mut group = writer.next_row_group().unwrap();
+            let strings = |values: &[&str]| -> Vec<ByteArray> {
+                values.iter().map(|value| ByteArray::from(*value)).collect()
+            };
+            // Optional columns are given their present values and a definition level per row.
+            let mut column = 0;
+            while let Some(mut writer) = group.next_column().unwrap() {
+                match column {
+                    0 => writer.typed::<ByteArrayType>().write_batch(
+                        &strings(&["deposit", "withdrawal", "dispute"]),
+                        None,
+                        None,
+                    ),
+                    1 => writer
+                        .typed::<Int32Type>()
+                        .write_batch(&[1, 1, 1], None, None),
+                    2 => writer
+                        .typed::<Int64Type>()
+                        .write_batch(&[1, 2, 1], None, None),
+                    3 => writer.typed::<ByteArrayType>().write_batch(
+                        &strings(&["2.5", "1"]),
+                        Some(&[1, 1, 0]),
+                        None,
+                    ),
+                    4 => writer.typed::<ByteArrayType>().write_batch(
+                        &strings(&["savings"]),
+                        Some(&[0, 1, 0]),
+                        None,
+                    ),
+                    5 => writer.typed::<Int64Type>().write_batch(
+                        &[1660052700123],
+                        Some(&[1, 0, 0]),
+                        None,
+                    ),
+                    _ => writer.typed::<ByteArrayType>().write_batch(
+                        &strings(&["visa"]),
+                        Some(&[1, 0, 0]),
+                        None,
+                    ),
+                }
+                .unwrap();
+                writer.close().unwrap();
+                column += 1;
+            }
+            group.close().unwrap();
+            writer.close().unwrap();
+            assert_eq!(read(parquet.to_str().unwrap()), expected());
+        }
+
+        assert_eq!(InputFormat::of("in.csv", None), InputFormat::Csv);
+        assert_eq!(
+            InputFormat::of("in.txt", Some(InputFormat::Jsonl)),
+            InputFormat::Jsonl
+        );
+        let invalid = dir.join("invalid.jsonl");
+        std::fs::write(&invalid, "[1, 2]\n").unwrap();
+        let mut source = TxSource::open(invalid.to_str().unwrap(), InputFormat::Jsonl).unwrap();
+        let mut out = String::new();
+        assert!(source.read_to_string(&mut out).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
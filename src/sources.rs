@@ -0,0 +1,91 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{Currency, TxType};
+
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+struct Totals {
+    rows: u64,
+    rejected: u64,
+    deposits: Currency,
+    withdrawals: Currency,
+}
+
+#[derive(Serialize)]
+struct SourceRow<'a> {
+    source: &'a str,
+    rows: u64,
+    applied: u64,
+    rejected: u64,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    deposits: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    withdrawals: Currency,
+}
+
+// Volumes and rejects per upstream source, so when several systems feed one run a data quality
+// problem can be put down to the one sending it. Volumes only count applied rows.
+#[derive(Default)]
+pub struct SourceReport {
+    sources: BTreeMap<String, Totals>,
+}
+
+impl SourceReport {
+    pub fn record(&mut self, source: &str, tx_type: TxType, amount: Currency, applied: bool) {
+        if !self.sources.contains_key(source) {
+            self.sources.insert(source.to_string(), Totals::default());
+        }
+        let totals = self.sources.get_mut(source).unwrap();
+        totals.rows += 1;
+        match (applied, tx_type) {
+            (false, _) => totals.rejected += 1,
+            (true, TxType::Deposit) => totals.deposits += amount,
+            (true, TxType::Withdrawal) => totals.withdrawals += amount,
+            _ => {}
+        }
+    }
+
+    // Ordered by source.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for (source, totals) in &self.sources {
+            writer.serialize(SourceRow {
+                source,
+                rows: totals.rows,
+                applied: totals.rows - totals.rejected,
+                rejected: totals.rejected,
+                deposits: totals.deposits,
+                withdrawals: totals.withdrawals,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_per_source() {
+        let mut report = SourceReport::default();
+        let amount = Currency::from_num;
+        report.record("ledger", TxType::Deposit, amount(10), true);
+        report.record("ledger", TxType::Withdrawal, amount(4), true);
+        report.record("cards", TxType::Deposit, amount(7), true);
+        report.record("cards", TxType::Withdrawal, amount(50), false);
+        report.record("cards", TxType::Dispute, amount(0), false);
+        report.record("in.csv", TxType::Dispute, amount(0), true);
+        let mut out = vec![];
+        report.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "source,rows,applied,rejected,deposits,withdrawals\n\
+             cards,3,1,2,7.0000,0.0000\n\
+             in.csv,1,1,0,0.0000,0.0000\n\
+             ledger,2,2,0,10.0000,4.0000\n"
+        );
+    }
+}
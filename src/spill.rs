@@ -0,0 +1,389 @@
+use crate::{AppState, BasicError};
+use serde::{Serialize, Serializer};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use txcli_model::{ClientId, Tx, TxId};
+
+// Transactions kept in memory under --history-backend disk unless --history-cache says otherwise.
+pub const DEFAULT_CACHE: usize = 1_000_000;
+
+// Evicted transactions are written out in batches of about this many bytes.
+const WRITE_BUFFER: usize = 64 * 1024;
+
+// Where ClientState::history lives.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum HistoryBackend {
+    #[default]
+    Memory,
+    // The most recently referenced transactions stay in memory, the rest go to a file, see Spill.
+    Disk,
+}
+
+impl FromStr for HistoryBackend {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(HistoryBackend::Memory),
+            "disk" => Ok(HistoryBackend::Disk),
+            _ => Err(BasicError::new(format!(
+                "Unknown history backend {}, expected memory or disk.",
+                s
+            ))),
+        }
+    }
+}
+
+impl Display for HistoryBackend {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            HistoryBackend::Memory => write!(f, "memory"),
+            HistoryBackend::Disk => write!(f, "disk"),
+        }
+    }
+}
+
+impl Serialize for HistoryBackend {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+// Engines of one process each get their own file.
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+// Keeps at most cache transactions in the clients' histories, across all clients. Past that the
+// ones brought into memory longest ago are appended to a file in the temp directory as JSON
+// lines, and only their offset stays in memory, a few bytes against the whole Tx. The engine
+// loads a transaction back before any row that could reference it, so everything reading history
+// still finds it there. The file is only ever appended to, a transaction evicted again after
+// being loaded is written again, and it is removed when the engine is dropped.
+#[derive(Debug)]
+pub struct Spill {
+    cache: usize,
+    // Created on the first eviction, runs that fit in the cache never touch the disk.
+    file: Option<File>,
+    path: PathBuf,
+    // Bytes in the file plus those still buffered.
+    end: u64,
+    buffer: Vec<u8>,
+    index: HashMap<(ClientId, TxId), (u64, usize)>,
+    // Transactions in the order they were brought into memory, oldest first, with the
+    // generation they were brought in under. One loaded or resolved again is pushed again under
+    // a new generation, its earlier entries are skipped rather than evicting it.
+    recent: VecDeque<(ClientId, TxId, u64)>,
+    // The generation of every transaction counted against the cache.
+    live: HashMap<(ClientId, TxId), u64>,
+    generation: u64,
+}
+
+impl Spill {
+    pub fn new(cache: usize) -> Self {
+        let name = format!(
+            "txcli-history-{}-{}",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        );
+        Spill {
+            cache: cache.max(1),
+            file: None,
+            path: std::env::temp_dir().join(name),
+            end: 0,
+            buffer: vec![],
+            index: HashMap::new(),
+            recent: VecDeque::new(),
+            live: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    // A row can't be rejected for a full disk, and carrying on without the file would decide
+    // disputes against a history missing transactions, so failures end the run.
+    fn failed(&self, err: impl Display) -> Box<dyn Error> {
+        BasicError::new(format!(
+            "History spill file {}: {}",
+            self.path.display(),
+            err
+        ))
+    }
+
+    fn file(&mut self) -> Result<&mut File, Box<dyn Error>> {
+        if self.file.is_none() {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&self.path)
+                .map_err(|err| self.failed(err))?;
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let buffer = std::mem::take(&mut self.buffer);
+        let start = self.end - buffer.len() as u64;
+        let file = self.file()?;
+        let written = file
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| file.write_all(&buffer));
+        written.map_err(|err| self.failed(err))
+    }
+
+    fn evict(&mut self, cid: ClientId, tid: TxId, tx: &Tx) -> Result<(), Box<dyn Error>> {
+        let mut line = serde_json::to_vec(tx).map_err(|err| self.failed(err))?;
+        line.push(b'\n');
+        self.index.insert((cid, tid), (self.end, line.len()));
+        self.end += line.len() as u64;
+        self.buffer.extend(line);
+        if self.buffer.len() >= WRITE_BUFFER {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // Records that tid was just put in cid's history, evicting the oldest ones over the cache.
+    pub fn touch(
+        &mut self,
+        state: &mut AppState,
+        cid: ClientId,
+        tid: TxId,
+    ) -> Result<(), Box<dyn Error>> {
+        self.generation += 1;
+        self.live.insert((cid, tid), self.generation);
+        self.recent.push_back((cid, tid, self.generation));
+        while self.live.len() > self.cache {
+            let Some((cid, tid, generation)) = self.recent.pop_front() else {
+                break;
+            };
+            if self.live.get(&(cid, tid)) != Some(&generation) {
+                continue;
+            }
+            self.live.remove(&(cid, tid));
+            let evicted = state
+                .client_mut(cid)
+                .and_then(|client| client.history.remove(&tid));
+            if let Some(tx) = evicted {
+                self.evict(cid, tid, &tx)?;
+            }
+        }
+        // Transactions touched over and over leave entries behind that nothing evicts.
+        if self.recent.len() > 2 * self.live.len() + 64 {
+            let live = &self.live;
+            self.recent
+                .retain(|(cid, tid, generation)| live.get(&(*cid, *tid)) == Some(generation));
+        }
+        Ok(())
+    }
+
+    // Puts tid back in cid's history if it was evicted.
+    pub fn load(
+        &mut self,
+        state: &mut AppState,
+        cid: ClientId,
+        tid: TxId,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some((offset, len)) = self.index.remove(&(cid, tid)) else {
+            return Ok(());
+        };
+        self.flush()?;
+        let mut line = vec![0; len];
+        let file = self.file()?;
+        let read = file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut line));
+        read.map_err(|err| self.failed(err))?;
+        let tx: Tx = serde_json::from_slice(&line).map_err(|err| self.failed(err))?;
+        if let Some(client) = state.client_mut(cid) {
+            client.history.insert(tid, tx);
+            self.touch(state, cid, tid)?;
+        }
+        Ok(())
+    }
+
+    // Puts every evicted transaction back, for what needs the whole history at once.
+    pub fn load_all(&mut self, state: &mut AppState) -> Result<(), Box<dyn Error>> {
+        self.cache = usize::MAX;
+        let evicted: Vec<_> = self.index.keys().copied().collect();
+        for (cid, tid) in evicted {
+            self.load(state, cid, tid)?;
+        }
+        Ok(())
+    }
+
+    // Transactions on disk and the bytes written for them so far, including dead copies.
+    pub fn usage(&self) -> (usize, u64) {
+        (self.index.len(), self.end)
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Engine, EngineBuilder, LockedPolicy};
+    use crate::retention::Retention;
+    use crate::sample::SplitMix64;
+    use txcli_model::{Currency, TxRecord, TxType};
+
+    #[test]
+    fn parse_backends() {
+        for text in ["memory", "disk"] {
+            assert_eq!(text.parse::<HistoryBackend>().unwrap().to_string(), text);
+        }
+        assert!("sled".parse::<HistoryBackend>().is_err());
+    }
+
+    // Mostly deposits and withdrawals across a few clients, with rows referencing any earlier
+    // tid, so most references reach transactions long evicted.
+    fn rows(seed: u64) -> Vec<TxRecord<'static>> {
+        let mut rng = SplitMix64(seed);
+        (1..=600u32)
+            .map(|tid| {
+                let cid = (rng.next() % 5) as u16;
+                let earlier = 1 + (rng.next() % tid as u64) as u32;
+                let amount = Currency::from_num(1 + rng.next() % 50);
+                match rng.next() % 10 {
+                    0..=3 => TxRecord::new(TxType::Deposit, cid, tid, amount),
+                    4 => TxRecord::new(TxType::Withdrawal, cid, tid, amount),
+                    5 => TxRecord::new(TxType::Dispute, cid, earlier, Currency::ZERO),
+                    6 => TxRecord::new(TxType::Resolve, cid, earlier, Currency::ZERO),
+                    7 => TxRecord::new(TxType::ChargeBack, cid, earlier, Currency::ZERO),
+                    8 => TxRecord::new(TxType::Void, cid, earlier, Currency::ZERO),
+                    _ => TxRecord::new(TxType::Deposit, cid, earlier, amount),
+                }
+            })
+            .collect()
+    }
+
+    fn history_len(engine: &Engine) -> usize {
+        engine
+            .state()
+            .clients
+            .values()
+            .map(|client| client.history.len())
+            .sum()
+    }
+
+    #[test]
+    fn spilling_changes_nothing() {
+        for seed in 0..10 {
+            let rows = rows(seed);
+            for retention in [None, Some(Retention::LastN(20))] {
+                let build = |cache: Option<usize>| {
+                    let mut builder = EngineBuilder::new().locked_policy(LockedPolicy::Allow);
+                    if let Some(retention) = retention {
+                        builder = builder.retention(retention);
+                    }
+                    if let Some(cache) = cache {
+                        builder = builder.history_cache(cache);
+                    }
+                    builder.build()
+                };
+                let mut kept = build(None);
+                let mut spilled = build(Some(8));
+                for tx in &rows {
+                    assert_eq!(
+                        spilled.try_apply(*tx),
+                        kept.try_apply(*tx),
+                        "seed {} {:?}",
+                        seed,
+                        tx
+                    );
+                    assert!(history_len(&spilled) <= 8);
+                }
+                assert!(spilled.spilled().unwrap().0 > 0);
+                spilled.load_history().unwrap();
+                assert_eq!(spilled.snapshot(), kept.snapshot());
+            }
+        }
+    }
+
+    // The dispute after the resolve loads tx 1 back while an entry of its from before the resolve
+    // is still the oldest, which must not evict it again.
+    #[test]
+    fn reloaded_transaction_stays() {
+        let mut engine = EngineBuilder::new().history_cache(2).build();
+        for tx in [
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(10)),
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::ZERO),
+            TxRecord::new(TxType::Resolve, 1, 1, Currency::ZERO),
+            TxRecord::new(TxType::Deposit, 1, 2, Currency::from_num(5)),
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::ZERO),
+            TxRecord::new(TxType::Resolve, 1, 1, Currency::ZERO),
+            TxRecord::new(TxType::Dispute, 1, 2, Currency::ZERO),
+        ] {
+            assert_eq!(engine.try_apply(tx), Ok(()), "{:?}", tx);
+        }
+        let reference =
+            std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/reference.csv"))
+                .unwrap();
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(reference.as_bytes());
+        let mut rows = crate::schema::Schema::V1
+            .reader(reader.headers().unwrap())
+            .unwrap();
+        let mut kept = EngineBuilder::new().build();
+        let mut spilled = EngineBuilder::new().history_cache(5).build();
+        while let Some(tx) = rows.next(&mut reader) {
+            let tx = tx.unwrap();
+            assert_eq!(spilled.try_apply(tx), kept.try_apply(tx), "{:?}", tx);
+        }
+        assert_eq!(spilled.report().clients, kept.report().clients);
+    }
+
+    #[test]
+    fn failures_end_the_run() {
+        let mut state = AppState::default();
+        let mut spill = Spill::new(1);
+        spill.path = std::env::temp_dir()
+            .join("txcli-missing-dir")
+            .join("history");
+        for tid in 1..=2 {
+            let tx = Tx::new(TxType::Deposit, 1, tid, Currency::from_num(1));
+            let client = state.clients.entry(ClientId(1)).or_default();
+            client.history.insert(TxId(tid), tx);
+            spill.touch(&mut state, ClientId(1), TxId(tid)).unwrap();
+        }
+        // Evicted into the buffer, the file is only needed to read it back.
+        let err = spill.load(&mut state, ClientId(1), TxId(1)).unwrap_err();
+        assert!(err.to_string().starts_with("History spill file"), "{}", err);
+    }
+
+    #[test]
+    fn file_removed_on_drop() {
+        let mut state = AppState::default();
+        let mut spill = Spill::new(1);
+        for tid in 1..=3 {
+            let tx = Tx::new(TxType::Deposit, 1, tid, Currency::from_num(1));
+            let client = state.clients.entry(ClientId(1)).or_default();
+            client.history.insert(TxId(tid), tx);
+            spill.touch(&mut state, ClientId(1), TxId(tid)).unwrap();
+        }
+        assert_eq!(spill.usage().0, 2);
+        spill.load(&mut state, ClientId(1), TxId(1)).unwrap();
+        assert!(state.clients[&ClientId(1)].history.contains_key(&TxId(1)));
+        let path = spill.path.clone();
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::BasicError;
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read};
+use txcli_model::{ClientId, TxId, TxRecord};
+
+// Moves the listed transactions of one client to a new client id while the input is read, so
+// both clients' balances are recomputed from their own transactions. Disputes, resolves and
+// chargebacks share the tid of the transaction they reference, so they move along with it.
+pub struct Split {
+    client: ClientId,
+    into: ClientId,
+    tids: HashSet<TxId>,
+    moved: HashSet<TxId>,
+}
+
+impl Split {
+    // One tid per line, blank lines and lines starting with # are skipped.
+    pub fn load<R: Read>(
+        client: ClientId,
+        into: ClientId,
+        tids: R,
+    ) -> Result<Self, Box<dyn Error>> {
+        if client == into {
+            return Err(BasicError::new(format!(
+                "Cannot split client[{}] into itself.",
+                client.0
+            )));
+        }
+        let mut parsed = HashSet::new();
+        for line in BufReader::new(tids).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let tid = line.parse::<u32>().map_err(|_| {
+                BasicError::new(format!("Invalid transaction id {} in split list.", line))
+            })?;
+            parsed.insert(TxId(tid));
+        }
+        Ok(Split {
+            client,
+            into,
+            tids: parsed,
+            moved: HashSet::new(),
+        })
+    }
+
+    pub fn apply(&mut self, tx: &mut TxRecord) -> Result<(), Box<dyn Error>> {
+        if tx.cid == self.into {
+            return Err(BasicError::new(format!(
+                "Cannot split into client[{}], the input already has transactions for it.",
+                self.into.0
+            )));
+        }
+        if tx.cid == self.client && self.tids.contains(&tx.tid) {
+            tx.cid = self.into;
+            self.moved.insert(tx.tid);
+        }
+        Ok(())
+    }
+
+    // Listed tids that never showed up for the client, sorted.
+    pub fn missing(&self) -> Vec<TxId> {
+        let mut missing: Vec<TxId> = self.tids.difference(&self.moved).copied().collect();
+        missing.sort_unstable_by_key(|tid| tid.0);
+        missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::{Currency, TxType};
+
+    #[test]
+    fn moves_listed_transactions_and_their_lifecycle() {
+        let mut split =
+            Split::load(ClientId(7), ClientId(9001), "# moved\n2\n\n3\n".as_bytes()).unwrap();
+        let mut rows = [
+            TxRecord::new(TxType::Deposit, 7, 1, Currency::from_num(1)),
+            TxRecord::new(TxType::Deposit, 7, 2, Currency::from_num(2)),
+            TxRecord::new(TxType::Dispute, 7, 2, Currency::default()),
+            TxRecord::new(TxType::Deposit, 8, 3, Currency::from_num(3)),
+        ];
+        for row in &mut rows {
+            split.apply(row).unwrap();
+        }
+        let cids: Vec<u16> = rows.iter().map(|row| row.cid.0).collect();
+        assert_eq!(cids, vec![7, 9001, 9001, 8]);
+        assert_eq!(split.missing(), vec![TxId(3)]);
+    }
+
+    #[test]
+    fn reject_bad_splits() {
+        assert!(Split::load(ClientId(7), ClientId(7), "1\n".as_bytes()).is_err());
+        assert!(Split::load(ClientId(7), ClientId(8), "abc\n".as_bytes()).is_err());
+        let mut split = Split::load(ClientId(7), ClientId(8), "1\n".as_bytes()).unwrap();
+        let mut row = TxRecord::new(TxType::Deposit, 8, 5, Currency::from_num(1));
+        assert!(split.apply(&mut row).is_err());
+    }
+}
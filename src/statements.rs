@@ -0,0 +1,161 @@
+use crate::engine::Engine;
+use crate::metadata::MetadataColumns;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use txcli_model::{ClientId, ClientOutputState, TxRecord, TxType};
+
+// Statement columns after client and the metadata columns.
+const COLUMNS: [&str; 11] = [
+    "entry",
+    "type",
+    "tx",
+    "account",
+    "amount",
+    "status",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "memo",
+];
+
+// One file per client for the servicing team's mail merge: each of the client's rows in input
+// order with the client's balances right after it, then the closing balances. Every line starts
+// with the client and its metadata columns, so a merge field is the same on every line. Rows of
+// joint owners appear on the canonical client's statement.
+#[derive(Default)]
+pub struct Statements {
+    lines: BTreeMap<ClientId, Vec<Vec<String>>>,
+}
+
+fn balances(balance: Option<&ClientOutputState>) -> [String; 4] {
+    match balance {
+        Some(balance) => [
+            format!("{:.4}", balance.available),
+            format!("{:.4}", balance.held),
+            format!("{:.4}", balance.total),
+            balance.locked.to_string(),
+        ],
+        None => Default::default(),
+    }
+}
+
+impl Statements {
+    // Called once per row, after the engine applied or rejected it.
+    pub fn record(&mut self, engine: &Engine, tx: &TxRecord, applied: bool) {
+        let cid = engine.state().canonical(tx.cid);
+        // Rows referencing an earlier one, admin rows and notes carry no amount of their own.
+        let amount = match tx.tx_type {
+            TxType::Dispute
+            | TxType::Resolve
+            | TxType::ChargeBack
+            | TxType::Void
+            | TxType::ArchiveClient
+            | TxType::ReactivateClient
+            | TxType::Note => String::new(),
+            _ => format!("{:.4}", tx.amount),
+        };
+        let mut line = vec![
+            "transaction".to_string(),
+            tx.tx_type.to_string(),
+            tx.tid.0.to_string(),
+            tx.account.to_string(),
+            amount,
+            if applied { "applied" } else { "rejected" }.to_string(),
+        ];
+        line.extend(balances(engine.balance(cid).as_ref()));
+        line.push(tx.memo.unwrap_or_default().to_string());
+        self.lines.entry(cid).or_default().push(line);
+    }
+
+    // Writes dir/client-<id>.csv for every client with a row or a balance, returns how many.
+    pub fn write(
+        mut self,
+        dir: &str,
+        engine: &Engine,
+        metadata: Option<&MetadataColumns>,
+    ) -> Result<usize, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        let mut closing = engine.report().clients;
+        closing.sort_unstable_by(|a, b| (a.cid, &a.account).cmp(&(b.cid, &b.account)));
+        for balance in &closing {
+            let mut line = vec!["closing".to_string(), String::new(), String::new()];
+            line.push(
+                balance
+                    .account
+                    .as_ref()
+                    .map_or(String::new(), |a| a.0.clone()),
+            );
+            line.extend([String::new(), String::new()]);
+            line.extend(balances(Some(balance)));
+            line.push(String::new());
+            self.lines.entry(balance.cid).or_default().push(line);
+        }
+        let empty = MetadataColumns::default();
+        let metadata = metadata.unwrap_or(&empty);
+        for (cid, lines) in &self.lines {
+            let path = Path::new(dir).join(format!("client-{}.csv", cid.0));
+            let mut writer = csv::Writer::from_path(path)?;
+            let header = std::iter::once("client".to_string())
+                .chain(metadata.header.iter().cloned())
+                .chain(COLUMNS.iter().map(|column| column.to_string()));
+            writer.write_record(header)?;
+            let prefix: Vec<String> = std::iter::once(cid.0.to_string())
+                .chain(metadata.of(*cid))
+                .collect();
+            for line in lines {
+                writer.write_record(prefix.iter().chain(line))?;
+            }
+            writer.flush()?;
+        }
+        Ok(self.lines.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use crate::metadata;
+    use std::collections::HashMap;
+    use txcli_model::Currency;
+
+    #[test]
+    fn one_statement_per_client() {
+        let mut engine = EngineBuilder::new()
+            .joint_owners(HashMap::from([(ClientId(3), ClientId(1))]))
+            .build();
+        let mut statements = Statements::default();
+        for tx in [
+            TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(10)),
+            TxRecord::new(TxType::Withdrawal, 2, 2, Currency::from_num(1)),
+            TxRecord::new(TxType::Withdrawal, 3, 3, Currency::from_num(4)),
+            TxRecord::new(TxType::Dispute, 1, 1, Currency::from_num(0)),
+            TxRecord {
+                memo: Some("disputed by phone"),
+                ..TxRecord::new(TxType::Note, 1, 4, Currency::from_num(0))
+            },
+        ] {
+            let applied = engine.apply(tx);
+            statements.record(&engine, &tx, applied);
+        }
+        let metadata = metadata::load_columns("client,name\n1,alice\n".as_bytes()).unwrap();
+        let dir = std::env::temp_dir().join(format!("txcli-statements-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        assert_eq!(statements.write(dir, &engine, Some(&metadata)).unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(Path::new(dir).join("client-1.csv")).unwrap(),
+            "client,name,entry,type,tx,account,amount,status,available,held,total,locked,memo\n\
+            1,alice,transaction,deposit,1,default,10.0000,applied,10.0000,0.0000,10.0000,false,\n\
+            1,alice,transaction,withdrawal,3,default,4.0000,applied,6.0000,0.0000,6.0000,false,\n\
+            1,alice,transaction,dispute,1,default,,applied,-4.0000,10.0000,6.0000,false,\n\
+            1,alice,transaction,note,4,default,,applied,-4.0000,10.0000,6.0000,false,disputed by phone\n\
+            1,alice,closing,,,,,,-4.0000,10.0000,6.0000,false,\n"
+        );
+        let other = fs::read_to_string(Path::new(dir).join("client-2.csv")).unwrap();
+        assert!(other.contains("2,,transaction,withdrawal,2,default,1.0000,rejected"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
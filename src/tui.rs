@@ -0,0 +1,537 @@
+use crate::engine::EngineBuilder;
+use crate::options::Options;
+use crate::schema::Schema;
+use crate::source::TxSource;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use txcli_model::{ClientOutputState, Tx, TxType};
+
+const FRAME: Duration = Duration::from_millis(100);
+const BATCH: usize = 1024;
+const RECENT: usize = 100;
+// Every built in type, rows of custom types are counted together after them as other.
+const TYPES: [TxType; 10] = [
+    TxType::Deposit,
+    TxType::Withdrawal,
+    TxType::Transfer,
+    TxType::Dispute,
+    TxType::Resolve,
+    TxType::ChargeBack,
+    TxType::Void,
+    TxType::ArchiveClient,
+    TxType::ReactivateClient,
+    TxType::Note,
+];
+
+// Reads like tail -f, waiting for the file to grow instead of reporting the end.
+struct Follow<R> {
+    inner: R,
+}
+
+impl<R: Read> Read for Follow<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.inner.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+// Rows are read and parsed on their own thread, owned, so the ui thread only applies and draws.
+// The thread stops at the end of the input, or never with --follow.
+fn spawn_reader(
+    input: Box<dyn Read + Send>,
+    schema: Option<Schema>,
+) -> Receiver<Result<Vec<(u64, Tx)>, String>> {
+    let (sender, receiver) = mpsc::sync_channel(16);
+    thread::spawn(move || {
+        let result = (|| -> Result<(), Box<dyn Error>> {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .has_headers(true)
+                .flexible(true)
+                .from_reader(input);
+            let schema = match schema {
+                Some(schema) => schema,
+                None => Schema::detect(reader.headers()?),
+            };
+            let mut rows = schema.reader(reader.headers()?)?;
+            let mut batch = vec![];
+            while rows.read(&mut reader)? {
+                batch.push((rows.line(), rows.parse()?.to_tx()));
+                if batch.len() == BATCH && sender.send(Ok(std::mem::take(&mut batch))).is_err() {
+                    return Ok(());
+                }
+            }
+            let _ = sender.send(Ok(batch));
+            Ok(())
+        })();
+        if let Err(err) = result {
+            let _ = sender.send(Err(err.to_string()));
+        }
+    });
+    receiver
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counter {
+    applied: u64,
+    rejected: u64,
+}
+
+// Everything the dashboard shows besides the balances, kept apart from the terminal so it can
+// be rendered into a test backend.
+#[derive(Default)]
+struct Dashboard {
+    rows: u64,
+    counters: [Counter; TYPES.len() + 1],
+    // (when, rows) once per frame, the last second of them gives the throughput.
+    samples: VecDeque<(Instant, u64)>,
+    rejects: VecDeque<String>,
+    log: VecDeque<String>,
+    search: String,
+    searching: bool,
+    offset: usize,
+    status: String,
+}
+
+fn push_recent(list: &mut VecDeque<String>, line: String) {
+    if list.len() == RECENT {
+        list.pop_front();
+    }
+    list.push_back(line);
+}
+
+impl Dashboard {
+    fn record(&mut self, line: u64, tx: &Tx, applied: bool) {
+        self.rows += 1;
+        let index = TYPES
+            .iter()
+            .position(|t| *t == tx.tx_type)
+            .unwrap_or(TYPES.len());
+        let counter = &mut self.counters[index];
+        if applied {
+            counter.applied += 1;
+        } else {
+            counter.rejected += 1;
+            push_recent(
+                &mut self.rejects,
+                format!(
+                    "line {}: {} client {} tx {}",
+                    line, tx.tx_type, tx.cid.0, tx.tid.0
+                ),
+            );
+        }
+    }
+
+    fn sample(&mut self, now: Instant) {
+        self.samples.push_back((now, self.rows));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(when, _)| now.duration_since(*when) > Duration::from_secs(1))
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    fn throughput(&self) -> u64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((first, first_rows)), Some((last, last_rows))) if last > first => {
+                ((last_rows - first_rows) as f64 / last.duration_since(*first).as_secs_f64()) as u64
+            }
+            _ => 0,
+        }
+    }
+
+    // Returns false once the user asked to quit.
+    fn key(&mut self, code: KeyCode) -> bool {
+        match (self.searching, code) {
+            (true, KeyCode::Char(c)) if c.is_ascii_digit() => self.search.push(c),
+            (true, KeyCode::Backspace) => {
+                self.search.pop();
+            }
+            (true, KeyCode::Enter) => self.searching = false,
+            (true, KeyCode::Esc) => {
+                self.search.clear();
+                self.searching = false;
+            }
+            (false, KeyCode::Char('q')) | (false, KeyCode::Esc) => return false,
+            (false, KeyCode::Char('/')) => self.searching = true,
+            (_, KeyCode::Down) => self.offset += 1,
+            (_, KeyCode::Up) => self.offset = self.offset.saturating_sub(1),
+            (_, KeyCode::PageDown) => self.offset += 20,
+            (_, KeyCode::PageUp) => self.offset = self.offset.saturating_sub(20),
+            _ => {}
+        }
+        if self.searching {
+            self.offset = 0;
+        }
+        true
+    }
+
+    fn render(&self, frame: &mut Frame, clients: &[ClientOutputState]) {
+        let [header, middle, table] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(TYPES.len() as u16 + 4),
+            Constraint::Min(4),
+        ])
+        .areas(frame.area());
+        let [counters, rejects, log] = Layout::horizontal([
+            Constraint::Length(44),
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ])
+        .areas(middle);
+
+        frame.render_widget(
+            Paragraph::new(format!(
+                "{} rows, {} rows/s, {} clients. {}",
+                self.rows,
+                self.throughput(),
+                clients.len(),
+                self.status
+            ))
+            .block(Block::bordered().title(" txcli  q quit  / search client  ↑↓ scroll ")),
+            header,
+        );
+
+        let names = TYPES
+            .iter()
+            .map(TxType::to_string)
+            .chain(["other".to_string()]);
+        let counter_rows = names.zip(&self.counters).map(|(name, counter)| {
+            Row::new(vec![
+                name,
+                counter.applied.to_string(),
+                counter.rejected.to_string(),
+            ])
+        });
+        frame.render_widget(
+            Table::new(
+                counter_rows,
+                [
+                    Constraint::Length(18),
+                    Constraint::Length(10),
+                    Constraint::Length(10),
+                ],
+            )
+            .header(Row::new(vec!["type", "applied", "rejected"]).style(bold()))
+            .block(Block::bordered().title(" Counters ")),
+            counters,
+        );
+
+        let recent = |list: &VecDeque<String>, height: u16| {
+            let skip = list.len().saturating_sub(height.saturating_sub(2) as usize);
+            list.iter()
+                .skip(skip)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        frame.render_widget(
+            Paragraph::new(recent(&self.rejects, rejects.height))
+                .block(Block::bordered().title(" Recent rejects ")),
+            rejects,
+        );
+        frame.render_widget(
+            Paragraph::new(recent(&self.log, log.height)).block(Block::bordered().title(" Log ")),
+            log,
+        );
+
+        let title = match (self.searching, self.search.is_empty()) {
+            (true, _) => format!(" Clients, search: {}_ ", self.search),
+            (false, false) => format!(" Clients, search: {} ", self.search),
+            (false, true) => " Clients ".to_string(),
+        };
+        let matching: Vec<&ClientOutputState> = clients
+            .iter()
+            .filter(|client| client.cid.0.to_string().starts_with(&self.search))
+            .collect();
+        let offset = self.offset.min(matching.len().saturating_sub(1));
+        let client_rows = matching.iter().skip(offset).map(|client| {
+            Row::new(vec![
+                client.cid.0.to_string(),
+                format!("{:.4}", client.available),
+                format!("{:.4}", client.held),
+                format!("{:.4}", client.total),
+                client.locked.to_string(),
+            ])
+        });
+        frame.render_widget(
+            Table::new(client_rows, [Constraint::Length(16); 5])
+                .header(
+                    Row::new(vec!["client", "available", "held", "total", "locked"]).style(bold()),
+                )
+                .block(Block::bordered().title(title)),
+            table,
+        );
+    }
+}
+
+fn bold() -> Style {
+    Style::default().add_modifier(Modifier::BOLD)
+}
+
+// Points stderr at a pipe for as long as it lives, so the engine's rejection messages land in
+// the log panel instead of on top of the dashboard.
+#[cfg(unix)]
+struct StderrCapture {
+    saved: i32,
+}
+
+#[cfg(unix)]
+impl StderrCapture {
+    fn start() -> std::io::Result<(Self, Receiver<String>)> {
+        use std::io::BufRead;
+        use std::os::unix::io::FromRawFd;
+
+        let mut fds = [0; 2];
+        // SAFETY: plain fd calls, every returned fd is checked and owned by exactly one side.
+        unsafe {
+            let saved = libc::dup(libc::STDERR_FILENO);
+            if saved < 0 || libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if libc::dup2(fds[1], libc::STDERR_FILENO) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            libc::close(fds[1]);
+            let read = File::from_raw_fd(fds[0]);
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for line in std::io::BufReader::new(read).lines() {
+                    let Ok(line) = line else { break };
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+            Ok((StderrCapture { saved }, receiver))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for StderrCapture {
+    fn drop(&mut self) {
+        // SAFETY: saved is the original stderr from dup, restored once and then closed.
+        unsafe {
+            libc::dup2(self.saved, libc::STDERR_FILENO);
+            libc::close(self.saved);
+        }
+    }
+}
+
+// Processes the input while drawing the dashboard. Once the user quits, the balances processed
+// so far are written to stdout as usual.
+pub fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    // Following only works on csv, Options::parse refuses --follow for other formats.
+    let input: Box<dyn Read + Send> = if options.follow {
+        Box::new(Follow {
+            inner: File::open(&options.input)?,
+        })
+    } else {
+        Box::new(TxSource::for_run(&options.input, options)?)
+    };
+    let batches = spawn_reader(input, options.schema);
+    let mut engine = EngineBuilder::new().build();
+    let mut dashboard = Dashboard::default();
+
+    #[cfg(unix)]
+    let (capture, messages) = StderrCapture::start()?;
+    #[cfg(not(unix))]
+    let (_, messages) = mpsc::channel::<String>();
+
+    let mut terminal = ratatui::try_init()?;
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        let mut reading = true;
+        loop {
+            let frame_end = Instant::now() + FRAME;
+            while reading && Instant::now() < frame_end {
+                match batches.try_recv() {
+                    Ok(Ok(batch)) => {
+                        for (line, tx) in &batch {
+                            let applied = engine.apply(tx.as_record());
+                            dashboard.record(*line, tx, applied);
+                        }
+                    }
+                    Ok(Err(err)) => {
+                        dashboard.status = format!("Stopped: {}", err);
+                        reading = false;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        dashboard.status = "Done.".to_string();
+                        reading = false;
+                    }
+                }
+            }
+            while let Ok(message) = messages.try_recv() {
+                push_recent(&mut dashboard.log, message);
+            }
+            dashboard.sample(Instant::now());
+
+            let report = engine.report();
+            terminal.draw(|frame| dashboard.render(frame, &report.clients))?;
+
+            let wait = frame_end.saturating_duration_since(Instant::now());
+            if event::poll(wait)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !dashboard.key(key.code) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    })();
+    ratatui::try_restore()?;
+    #[cfg(unix)]
+    drop(capture);
+    result?;
+
+    engine.finish();
+    crate::print_balances(options, &engine.report(), |_| true)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use txcli_model::{ClientId, Currency, TxRecord};
+
+    #[test]
+    fn counters_and_rejects() {
+        let mut dashboard = Dashboard::default();
+        let deposit = TxRecord::new(TxType::Deposit, 1, 1, Currency::from_num(5)).to_tx();
+        let withdrawal = TxRecord::new(TxType::Withdrawal, 2, 2, Currency::from_num(5)).to_tx();
+        dashboard.record(2, &deposit, true);
+        dashboard.record(3, &withdrawal, false);
+        assert_eq!(dashboard.rows, 2);
+        assert_eq!(dashboard.counters[0].applied, 1);
+        assert_eq!(dashboard.counters[1].rejected, 1);
+        assert_eq!(
+            dashboard.rejects.back().unwrap(),
+            "line 3: withdrawal client 2 tx 2"
+        );
+
+        for line in 0..RECENT + 5 {
+            push_recent(&mut dashboard.log, line.to_string());
+        }
+        assert_eq!(dashboard.log.len(), RECENT);
+        assert_eq!(dashboard.log.front().unwrap(), "5");
+    }
+
+    #[test]
+    fn counts_every_type() {
+        let mut dashboard = Dashboard::default();
+        let custom = txcli_model::register_custom_type("tui_cashback").unwrap();
+        for (tid, tx_type) in TYPES.iter().chain([&custom]).enumerate() {
+            let tx = TxRecord::new(*tx_type, 1, tid as u32, Currency::from_num(1)).to_tx();
+            dashboard.record(tid as u64 + 2, &tx, true);
+        }
+        assert!(dashboard
+            .counters
+            .iter()
+            .all(|counter| counter.applied == 1));
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame, &[])).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("reactivate_client"));
+        assert!(screen.contains("other"));
+    }
+
+    #[test]
+    fn throughput_over_last_second() {
+        let mut dashboard = Dashboard::default();
+        let start = Instant::now();
+        dashboard.sample(start);
+        dashboard.rows = 500;
+        dashboard.sample(start + Duration::from_millis(500));
+        assert_eq!(dashboard.throughput(), 1000);
+        // The first sample drops out of the window.
+        dashboard.rows = 600;
+        dashboard.sample(start + Duration::from_millis(1400));
+        assert_eq!(dashboard.throughput(), 111);
+    }
+
+    #[test]
+    fn search_keys() {
+        let mut dashboard = Dashboard::default();
+        assert!(dashboard.key(KeyCode::Char('/')));
+        assert!(dashboard.key(KeyCode::Char('4')));
+        assert!(dashboard.key(KeyCode::Char('x')));
+        assert!(dashboard.key(KeyCode::Char('2')));
+        // q is typed into the search, not a quit.
+        assert!(dashboard.key(KeyCode::Char('q')));
+        assert_eq!(dashboard.search, "42");
+        assert!(dashboard.key(KeyCode::Enter));
+        assert!(!dashboard.searching);
+        assert!(!dashboard.key(KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn render_filters_clients() {
+        let dashboard = Dashboard {
+            search: "4".to_string(),
+            status: "Done.".to_string(),
+            ..Default::default()
+        };
+        let clients: Vec<ClientOutputState> = [4, 42, 7]
+            .into_iter()
+            .map(|cid| ClientOutputState {
+                cid: ClientId(cid),
+                currency: None,
+                account: None,
+                available: Currency::from_num(cid),
+                held: Currency::default(),
+                total: Currency::from_num(cid),
+                locked: cid == 42,
+                owners: None,
+                penalties: None,
+                chargeback_fees: None,
+                written_off: None,
+                dispute_count: None,
+                pending: None,
+            })
+            .collect();
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        terminal
+            .draw(|frame| dashboard.render(frame, &clients))
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("0 rows, 0 rows/s, 3 clients. Done."));
+        assert!(screen.contains("Clients, search: 4"));
+        assert!(screen.contains("42.0000"));
+        assert!(screen.contains("true"));
+        assert!(!screen.contains("7.0000"));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::results::Report;
+use crate::BasicError;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Columns identifying a row of the upsert file, besides run_date.
+const KEY_COLUMNS: [&str; 2] = ["client", "account"];
+
+// The balances merged into a file kept across runs, one row per client (and account) and run
+// date. A row of this run replaces the one with the same key, so rerunning a failed job leaves
+// one row per client rather than two. Other rows keep their place and this run's rows follow.
+// The file is written under a temporary name and renamed into place, like the interim reports.
+pub fn upsert(
+    path: &str,
+    report: &Report,
+    run_date: &str,
+    run_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let header: Vec<&str> = report
+        .columns
+        .iter()
+        .copied()
+        .chain(["run_date", "run_id"])
+        .collect();
+    let key: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| KEY_COLUMNS.contains(column) || **column == "run_date")
+        .map(|(index, _)| index)
+        .collect();
+    let key_of = |record: &csv::StringRecord| -> Vec<String> {
+        key.iter().map(|index| record[*index].to_string()).collect()
+    };
+
+    let mut fresh = vec![];
+    for client in &report.clients {
+        let mut record = report.record(client);
+        record.push_field(run_date);
+        record.push_field(run_id);
+        fresh.push(record);
+    }
+    let replaced: HashSet<Vec<String>> = fresh.iter().map(key_of).collect();
+
+    let mut kept = vec![];
+    if Path::new(path).exists() {
+        let mut reader = csv::Reader::from_path(path)?;
+        if reader.headers()? != header {
+            return Err(BasicError::new(format!(
+                "Cannot upsert into {}, it has the columns {} while this run writes {}.",
+                path,
+                reader.headers()?.iter().collect::<Vec<_>>().join(","),
+                header.join(",")
+            )));
+        }
+        for record in reader.records() {
+            let record = record?;
+            if !replaced.contains(&key_of(&record)) {
+                kept.push(record);
+            }
+        }
+    }
+
+    let partial = format!("{}.partial", path);
+    let mut writer = csv::Writer::from_path(&partial)?;
+    writer.write_record(&header)?;
+    for record in kept.iter().chain(&fresh) {
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+    use txcli_model::{Currency, TxRecord, TxType};
+
+    fn report(deposits: &[(u16, u32)]) -> Report {
+        let mut engine = EngineBuilder::new().build();
+        for (tid, (cid, amount)) in deposits.iter().enumerate() {
+            engine.apply(TxRecord::new(
+                TxType::Deposit,
+                *cid,
+                tid as u32 + 1,
+                Currency::from_num(*amount),
+            ));
+        }
+        engine.report()
+    }
+
+    #[test]
+    fn reruns_replace_their_rows() {
+        let dir = std::env::temp_dir().join(format!("txcli-upsert-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("balances.csv");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        upsert(path, &report(&[(1, 5)]), "2026-10-15", "a").unwrap();
+        // A failed run of the next day, then its rerun with one more client.
+        upsert(path, &report(&[(1, 6)]), "2026-10-16", "b").unwrap();
+        upsert(path, &report(&[(1, 7), (2, 1)]), "2026-10-16", "b").unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        let mut lines: Vec<&str> = contents.lines().collect();
+        lines[2..].sort_unstable();
+        assert_eq!(
+            lines,
+            [
+                "client,available,held,total,locked,run_date,run_id",
+                "1,5.0000,0.0000,5.0000,false,2026-10-15,a",
+                "1,7.0000,0.0000,7.0000,false,2026-10-16,b",
+                "2,1.0000,0.0000,1.0000,false,2026-10-16,b",
+            ]
+        );
+
+        fs::write(path, "client,available\n1,5\n").unwrap();
+        assert!(upsert(path, &report(&[(1, 5)]), "2026-10-16", "b").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use crate::i18n::{text, Msg};
+use crate::rejection::RejectionReason;
+use crate::warnings::{Kind, Warning};
+use crate::BasicError;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::error::Error;
+use std::str::FromStr;
+use txcli_model::{TxId, TxRecord, TxType};
+
+// How far apart two deposits, withdrawals or transfers must be to share a tid.
+#[derive(Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TidScope {
+    // Only within a client, checked against its history, so ids --retain-history forgot can be
+    // used again.
+    #[default]
+    Client,
+    // Never, whichever client. Every id is kept for the rest of the run.
+    Global,
+}
+
+impl FromStr for TidScope {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(TidScope::Client),
+            "global" => Ok(TidScope::Global),
+            _ => Err(BasicError::new(format!(
+                "Unknown tid scope {}, expected client or global.",
+                s
+            ))),
+        }
+    }
+}
+
+fn moves_funds(tx_type: TxType) -> bool {
+    matches!(
+        tx_type,
+        TxType::Deposit | TxType::Withdrawal | TxType::Transfer
+    )
+}
+
+// Rows bringing a new transaction, whose tid no other may share.
+fn new_id(tx_type: TxType) -> bool {
+    moves_funds(tx_type) || tx_type == TxType::Note
+}
+
+// Rows malformed whatever state they meet: a negative amount deposited would withdraw, and an
+// amount on a dispute was never what it held. Custom rows are left to their handlers.
+pub fn malformed(tx: &TxRecord) -> Option<(Msg, RejectionReason)> {
+    match tx.tx_type {
+        _ if moves_funds(tx.tx_type) && tx.amount <= 0 => {
+            Some((Msg::InvalidAmount, RejectionReason::InvalidAmount))
+        }
+        TxType::Dispute
+        | TxType::Resolve
+        | TxType::ChargeBack
+        | TxType::Void
+        | TxType::ArchiveClient
+        | TxType::ReactivateClient
+        | TxType::Note
+            if tx.amount != 0 =>
+        {
+            Some((Msg::UnexpectedAmount, RejectionReason::UnexpectedAmount))
+        }
+        TxType::Note if tx.memo.is_none() => Some((Msg::MissingMemo, RejectionReason::MissingMemo)),
+        _ => None,
+    }
+}
+
+// Caught before the engine applies anything. tids are the ids seen so far under
+// TidScope::Global.
+pub fn check(tx: &TxRecord, tids: Option<&HashSet<TxId>>) -> Result<(), RejectionReason> {
+    let (msg, reason) = match malformed(tx) {
+        Some(malformed) => malformed,
+        None if new_id(tx.tx_type) && tids.is_some_and(|tids| tids.contains(&tx.tid)) => {
+            let message = text(
+                Msg::Rejected,
+                &[&tx.tx_type, &tx.tid.0, &text(Msg::ReusedId, &[])],
+            );
+            Warning::new(Kind::Invalid, message)
+                .tx(tx.tx_type, tx.tid)
+                .client(tx.cid)
+                .emit();
+            return Err(RejectionReason::ReusedId);
+        }
+        None => return Ok(()),
+    };
+    Warning::new(Kind::Invalid, text(msg, &[&tx.tx_type, &tx.tid.0]))
+        .tx(tx.tx_type, tx.tid)
+        .client(tx.cid)
+        .emit();
+    Err(reason)
+}
+
+// Whether a rejection is of the row itself rather than of what it meets, failing the whole file
+// under --atomic-per-file. Insufficient funds and the like are rejected as usual.
+pub fn invalid(reason: RejectionReason) -> bool {
+    matches!(
+        reason,
+        RejectionReason::InvalidAmount
+            | RejectionReason::UnexpectedAmount
+            | RejectionReason::MissingMemo
+            | RejectionReason::ReusedId
+            | RejectionReason::MaxAmount
+    )
+}
+
+// Records an applied row's id under TidScope::Global.
+pub fn record(tx: &TxRecord, tids: &mut HashSet<TxId>) {
+    if new_id(tx.tx_type) {
+        tids.insert(tx.tid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txcli_model::Currency;
+
+    #[test]
+    fn malformed_rows() {
+        let tx =
+            |tx_type, tid, amount: f64| TxRecord::new(tx_type, 1, tid, Currency::from_num(amount));
+        assert_eq!(check(&tx(TxType::Deposit, 1, 1.5), None), Ok(()));
+        for tx_type in [TxType::Deposit, TxType::Withdrawal, TxType::Transfer] {
+            assert_eq!(
+                check(&tx(tx_type, 1, 0.0), None),
+                Err(RejectionReason::InvalidAmount)
+            );
+            assert_eq!(
+                check(&tx(tx_type, 1, -2.0), None),
+                Err(RejectionReason::InvalidAmount)
+            );
+        }
+        assert_eq!(check(&tx(TxType::Dispute, 1, 0.0), None), Ok(()));
+        for tx_type in [
+            TxType::Dispute,
+            TxType::Resolve,
+            TxType::ChargeBack,
+            TxType::Void,
+        ] {
+            assert_eq!(
+                check(&tx(tx_type, 1, 3.0), None),
+                Err(RejectionReason::UnexpectedAmount)
+            );
+        }
+
+        let note = tx(TxType::Note, 3, 0.0);
+        assert_eq!(check(&note, None), Err(RejectionReason::MissingMemo));
+        let note = TxRecord {
+            memo: Some("asked to close the account"),
+            ..note
+        };
+        assert_eq!(check(&note, None), Ok(()));
+        assert_eq!(
+            check(
+                &TxRecord {
+                    amount: Currency::from_num(1),
+                    ..note
+                },
+                None
+            ),
+            Err(RejectionReason::UnexpectedAmount)
+        );
+
+        assert!(invalid(RejectionReason::MaxAmount));
+        assert!(!invalid(RejectionReason::InsufficientFunds));
+
+        let mut tids = HashSet::new();
+        record(&tx(TxType::Deposit, 1, 1.5), &mut tids);
+        record(&tx(TxType::Dispute, 2, 0.0), &mut tids);
+        let other_client = TxRecord::new(TxType::Withdrawal, 2, 1, Currency::from_num(1));
+        assert_eq!(
+            check(&other_client, Some(&tids)),
+            Err(RejectionReason::ReusedId)
+        );
+        assert_eq!(check(&tx(TxType::Deposit, 2, 1.0), Some(&tids)), Ok(()));
+        assert_eq!(check(&tx(TxType::Dispute, 1, 0.0), Some(&tids)), Ok(()));
+    }
+}
@@ -0,0 +1,196 @@
+// Exhaustive verification over a tiny domain: every sequence of rows up to DEPTH long, drawn from
+// 2 clients, 3 tids and 2 amounts, is applied and the engine's invariants are checked after each
+// row. Small domains catch most lifecycle bugs since they are about ordering, not magnitudes.
+// The default depth keeps cargo test quick, the verify feature goes deeper:
+// cargo test --profile verify --features verify verify::
+use crate::conservation::{world_flow, Conservation};
+use crate::dispute_state::{self, DisputeState};
+use crate::{execute_transaction, AppState, Balance, ClientState};
+use txcli_model::{ClientId, Currency, TxId, TxRecord, TxType};
+
+#[cfg(not(feature = "verify"))]
+const DEPTH: usize = 3;
+#[cfg(feature = "verify")]
+const DEPTH: usize = 4;
+
+const CLIENTS: [u16; 2] = [1, 2];
+const TIDS: [u32; 3] = [1, 2, 3];
+const AMOUNTS: [u32; 2] = [1, 2];
+
+fn alphabet() -> Vec<TxRecord<'static>> {
+    let mut rows = vec![];
+    for cid in CLIENTS {
+        for tid in TIDS {
+            for amount in AMOUNTS {
+                let amount = Currency::from_num(amount);
+                rows.push(TxRecord::new(TxType::Deposit, cid, tid, amount));
+                rows.push(TxRecord::new(TxType::Withdrawal, cid, tid, amount));
+                rows.push(
+                    TxRecord::new(TxType::Transfer, cid, tid, amount).with_to_account("savings"),
+                );
+            }
+            for tx_type in [
+                TxType::Dispute,
+                TxType::Resolve,
+                TxType::ChargeBack,
+                TxType::Void,
+            ] {
+                rows.push(TxRecord::new(tx_type, cid, tid, Currency::from_num(0)));
+            }
+        }
+    }
+    rows
+}
+
+fn dispute_state_of(state: &AppState, cid: ClientId, tid: TxId) -> DisputeState {
+    state
+        .clients
+        .get(&cid)
+        .map_or(DisputeState::Unknown, |client| client.dispute_state(tid))
+}
+
+// Rejected rows may still leave an empty client or account behind, which shows up as a zero
+// row in the output, so states are compared without those.
+fn pruned(state: &AppState) -> AppState {
+    let mut state = state.clone();
+    for client in state.clients.values_mut() {
+        client
+            .accounts
+            .retain(|_, balance| *balance != Balance::default());
+        for accounts in client.assets.values_mut() {
+            accounts.retain(|_, balance| *balance != Balance::default());
+        }
+        client.assets.retain(|_, accounts| !accounts.is_empty());
+    }
+    state
+        .clients
+        .retain(|_, client| *client != ClientState::default());
+    state
+}
+
+// Invariants of one row, given the state before and after it. Returns what is broken.
+fn check(before: &AppState, tx: &TxRecord, applied: bool, after: &AppState) -> Result<(), String> {
+    if !applied && pruned(after) != pruned(before) {
+        return Err("rejected row changed the state".to_string());
+    }
+    let from = dispute_state_of(before, tx.cid, tx.tid);
+    let to = dispute_state_of(after, tx.cid, tx.tid);
+    let expected = match (applied, dispute_state::next(from, tx.tx_type)) {
+        (true, Some(next)) => next,
+        (true, None) => return Err(format!("applied without a transition from {:?}", from)),
+        (false, _) => from,
+    };
+    if to != expected {
+        return Err(format!(
+            "went from {:?} to {:?} instead of {:?}",
+            from, to, expected
+        ));
+    }
+    if applied && tx.tx_type == TxType::Withdrawal {
+        let available = before.clients[&tx.cid]
+            .balance_of(tx.currency, tx.account)
+            .map_or(Currency::ZERO, |balance| balance.available);
+        if available < tx.amount {
+            return Err(format!("withdrew {} from {}", tx.amount, available));
+        }
+    }
+    for (cid, client) in &after.clients {
+        // Held funds are exactly the open disputes, per asset and account.
+        for (asset, account, balance) in client.balances() {
+            let disputed: Currency = client
+                .disputed
+                .iter()
+                .filter(|(_, disputed)| {
+                    disputed.currency.as_deref() == asset && disputed.account == *account
+                })
+                .map(|(tid, disputed)| disputed.amount - client.shortfall(*tid))
+                .sum();
+            if balance.held != disputed {
+                return Err(format!(
+                    "client {} account {} holds {} for {} disputed",
+                    cid.0, account.0, balance.held, disputed
+                ));
+            }
+        }
+        let charged_back = client
+            .lifecycle
+            .values()
+            .any(|state| *state == DisputeState::ChargedBack);
+        if client.locked != charged_back {
+            return Err(format!(
+                "client {} locked is {} with charged back transactions {}",
+                cid.0, client.locked, charged_back
+            ));
+        }
+        for (tid, state) in &client.lifecycle {
+            let stored = client.history.contains_key(tid) || client.disputed.contains_key(tid);
+            if (*state == DisputeState::Disputed) != client.disputed.contains_key(tid)
+                || (*state == DisputeState::Voided) == stored
+            {
+                return Err(format!(
+                    "client {} tid {} is {:?} but stored wrong",
+                    cid.0, tid.0, state
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn explore(
+    state: &AppState,
+    conservation: &Conservation,
+    alphabet: &[TxRecord<'static>],
+    path: &mut Vec<TxRecord<'static>>,
+    explored: &mut u64,
+) {
+    if path.len() == DEPTH {
+        return;
+    }
+    for tx in alphabet {
+        let mut after = state.clone();
+        let mut conservation = conservation.clone();
+        let flow = world_flow(&after, tx.cid, tx);
+        let applied = execute_transaction(&mut after, *tx).is_ok();
+        path.push(*tx);
+        *explored += 1;
+        let result = check(state, tx, applied, &after).and_then(|_| {
+            conservation
+                .check(
+                    &after,
+                    Some(tx),
+                    applied.then_some(flow),
+                    Currency::from_num(0),
+                )
+                .map_err(|err| err.to_string())
+        });
+        if let Err(err) = result {
+            let rows: Vec<String> = path
+                .iter()
+                .map(|tx| format!("{} {} {} {}", tx.tx_type, tx.cid.0, tx.tid.0, tx.amount))
+                .collect();
+            panic!("{} after [{}]", err, rows.join(", "));
+        }
+        explore(&after, &conservation, alphabet, path, explored);
+        path.pop();
+    }
+}
+
+#[test]
+fn every_sequence_keeps_the_invariants() {
+    let alphabet = alphabet();
+    let state = AppState::default();
+    let mut explored = 0;
+    explore(
+        &state,
+        &Conservation::new(&state),
+        &alphabet,
+        &mut vec![],
+        &mut explored,
+    );
+    let rows = alphabet.len() as u64;
+    assert_eq!(
+        explored,
+        (1..=DEPTH as u32).map(|depth| rows.pow(depth)).sum::<u64>()
+    );
+}
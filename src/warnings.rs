@@ -0,0 +1,179 @@
+use crate::BasicError;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use txcli_model::{ClientId, TxId, TxType};
+
+// Where warnings go as JSON lines besides stderr, set once at startup. A global rather than
+// threaded through, warnings come from deep inside the engine.
+static CHANNEL: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+// Set for the rest of the process once warnings would only repeat, see mute.
+static MUTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    // Not allowed against the referenced transaction's dispute state, see dispute_state.
+    Rejected,
+    InsufficientFunds,
+    InvalidTransfer,
+    // The client is locked, see engine::LockedPolicy.
+    Locked,
+    // Over --max-open-disputes, or referencing a dispute still queued behind it.
+    DisputeCap,
+    Dust,
+    // Not a rejection, a chargeback took the client over --lock-after-chargebacks or
+    // --lock-after-chargeback-amount.
+    AutoLock,
+    MaxAmount,
+    // The client is archived, or an archive_client or reactivate_client row didn't apply.
+    Archival,
+    // Malformed whatever the state, see validation::check.
+    Invalid,
+    UnmatchedCase,
+    // A deposit or withdrawal of the input clearing no --authorizations row, or an authorization
+    // nothing in the input cleared.
+    UnmatchedClearing,
+    UnmatchedAuthorization,
+    // The row couldn't be parsed and was skipped, see --strict.
+    ParseError,
+    // Not a rejection, the row took longer than --latency-budget.
+    Slow,
+    // Not a rejection, a dispute held less than it disputed, see --dispute-policy partial-hold.
+    PartialHold,
+}
+
+// Something a wrapping script may want to act on, e.g. an ignored dispute. The message is the
+// same line a person reads on stderr, the other fields are there so nobody has to parse it.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Warning {
+    kind: Kind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client: Option<ClientId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx: Option<TxId>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    tx_type: Option<TxType>,
+    message: String,
+}
+
+impl Warning {
+    pub fn new(kind: Kind, message: String) -> Self {
+        Warning {
+            kind,
+            line: None,
+            client: None,
+            tx: None,
+            tx_type: None,
+            message,
+        }
+    }
+
+    pub fn tx(mut self, tx_type: TxType, tid: TxId) -> Self {
+        self.tx_type = Some(tx_type);
+        self.tx = Some(tid);
+        self
+    }
+
+    pub fn client(mut self, cid: ClientId) -> Self {
+        self.client = Some(cid);
+        self
+    }
+
+    pub fn line(mut self, line: u64) -> Self {
+        self.line = Some(line).filter(|line| *line > 0);
+        self
+    }
+
+    fn write<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Prints the message to stderr as always, and the whole warning to the channel if there is one.
+    pub fn emit(self) {
+        if MUTED.load(Ordering::Relaxed) {
+            return;
+        }
+        eprintln!("{}", self.message);
+        let mut channel = CHANNEL
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(writer) = channel.as_mut() {
+            // Losing the channel mid run shouldn't lose the run, the message is on stderr anyway.
+            if self.write(writer).is_err() {
+                eprintln!("Could not write to the warnings channel, closing it.");
+                *channel = None;
+            }
+        }
+    }
+}
+
+// Sends warnings to a file descriptor the caller opened, e.g. 3>warnings.jsonl.
+#[cfg(unix)]
+pub fn open_fd(fd: i32) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::io::FromRawFd;
+
+    // SAFETY: fcntl only checks fd is open, the File then owns it for the rest of the process.
+    unsafe {
+        if libc::fcntl(fd, libc::F_GETFD) < 0 {
+            return Err(BasicError::new(format!(
+                "--warnings-fd {} is not an open file descriptor.",
+                fd
+            )));
+        }
+        *CHANNEL.lock().unwrap() = Some(Box::new(File::from_raw_fd(fd)));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn open_fd(_fd: i32) -> Result<(), Box<dyn Error>> {
+    Err(BasicError::new(
+        "--warnings-fd is only supported on unix, use --warnings-out instead.",
+    ))
+}
+
+// Drops every warning from now on, for shrink replaying the same rows over and over.
+pub fn mute() {
+    MUTED.store(true, Ordering::Relaxed);
+}
+
+pub fn open_file(path: &str) -> Result<(), Box<dyn Error>> {
+    *CHANNEL.lock().unwrap() = Some(Box::new(File::create(path)?));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_json_object_per_line() {
+        let mut out = vec![];
+        Warning::new(
+            Kind::Rejected,
+            "Rejected dispute tid[7], it is already disputed. Ignoring.".to_string(),
+        )
+        .tx(TxType::Dispute, TxId(7))
+        .client(ClientId(3))
+        .write(&mut out)
+        .unwrap();
+        Warning::new(Kind::ParseError, "line 9: missing tx".to_string())
+            .line(9)
+            .write(&mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"kind\":\"rejected\",\"client\":3,\"tx\":7,\"type\":\"dispute\",\"message\":\"Rejected dispute tid[7], it is already disputed. Ignoring.\"}\n\
+             {\"kind\":\"parse_error\",\"line\":9,\"message\":\"line 9: missing tx\"}\n"
+        );
+    }
+}
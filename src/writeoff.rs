@@ -0,0 +1,223 @@
+use crate::rollforward::Period;
+use crate::AppState;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use txcli_model::{ClientId, Currency, Timestamp, TxRecord, TxType};
+
+const NO_COUNTERPARTY: &str = "unknown";
+
+// What one chargeback would write off if it is applied: the held funds it takes, under the
+// disputed transaction's counterparty. Resolved before the row is applied, as the dispute is gone
+// after. Charged back withdrawals come back to the client and write nothing off.
+#[derive(Debug, PartialEq)]
+pub struct WritesOff {
+    counterparty: String,
+    amount: Currency,
+}
+
+impl WritesOff {
+    pub fn of(state: &AppState, cid: ClientId, tx: &TxRecord) -> Option<Self> {
+        if tx.tx_type != TxType::ChargeBack {
+            return None;
+        }
+        let client = state.clients.get(&cid)?;
+        let disputed = client.disputed.get(&tx.tid)?;
+        if disputed.tx_type == TxType::Withdrawal {
+            return None;
+        }
+        Some(WritesOff {
+            counterparty: disputed
+                .counterparty
+                .as_deref()
+                .unwrap_or(NO_COUNTERPARTY)
+                .to_string(),
+            amount: disputed.amount - client.shortfall(tx.tid),
+        })
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+struct Written {
+    chargebacks: u64,
+    amount: Currency,
+}
+
+#[derive(Serialize)]
+struct WriteoffRow<'a> {
+    period: &'a str,
+    counterparty: &'a str,
+    chargebacks: u64,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    written_off: Currency,
+    #[serde(serialize_with = "txcli_model::precision4_serialize_currency")]
+    cumulative: Currency,
+}
+
+// Writeoffs per period and counterparty, with each counterparty's running total since the start
+// of the input. Periods follow the input's order as in rollforward::RollForward: a chargeback
+// without a timestamp, or stamped before the current period, counts in the current one, and
+// those before the first timestamp in the first. An input without timestamps is one period,
+// named all.
+pub struct WriteoffReport {
+    period: Period,
+    current: Option<String>,
+    undated: BTreeMap<String, Written>,
+    written: BTreeMap<(String, String), Written>,
+}
+
+impl WriteoffReport {
+    pub fn new(period: Period) -> Self {
+        WriteoffReport {
+            period,
+            current: None,
+            undated: BTreeMap::new(),
+            written: BTreeMap::new(),
+        }
+    }
+
+    // Before each row is applied, moves on to the period timestamp falls in if it is later.
+    pub fn begin(&mut self, timestamp: Option<Timestamp>) {
+        let Some(period) = timestamp.map(|timestamp| self.period.of(timestamp)) else {
+            return;
+        };
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|current| *current >= period)
+        {
+            return;
+        }
+        for (counterparty, undated) in std::mem::take(&mut self.undated) {
+            self.written.insert((period.clone(), counterparty), undated);
+        }
+        self.current = Some(period);
+    }
+
+    // Only for applied rows.
+    pub fn record(&mut self, writes_off: WritesOff) {
+        let written = match &self.current {
+            Some(period) => self
+                .written
+                .entry((period.clone(), writes_off.counterparty))
+                .or_default(),
+            None => self.undated.entry(writes_off.counterparty).or_default(),
+        };
+        written.chargebacks += 1;
+        written.amount += writes_off.amount;
+    }
+
+    // Ordered by period, then counterparty.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        let undated = self
+            .undated
+            .iter()
+            .map(|(counterparty, written)| (("all", counterparty.as_str()), written));
+        let dated = self
+            .written
+            .iter()
+            .map(|((period, counterparty), written)| {
+                ((period.as_str(), counterparty.as_str()), written)
+            });
+        let mut cumulative: BTreeMap<&str, Currency> = BTreeMap::new();
+        for ((period, counterparty), written) in undated.chain(dated) {
+            let total = cumulative.entry(counterparty).or_default();
+            *total += written.amount;
+            writer.serialize(WriteoffRow {
+                period,
+                counterparty,
+                chargebacks: written.chargebacks,
+                written_off: written.amount,
+                cumulative: *total,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineBuilder;
+
+    #[test]
+    fn writeoffs_by_month_and_counterparty() {
+        let mut engine = EngineBuilder::new().track_writeoffs(true).build();
+        let mut report = WriteoffReport::new(Period::Monthly);
+        let row = |tx_type, cid, tid, amount: f64, date: &str, counterparty| {
+            let mut tx = TxRecord::new(tx_type, cid, tid, Currency::from_num(amount));
+            tx.timestamp = Some(date.parse().unwrap());
+            tx.counterparty = counterparty;
+            tx
+        };
+        for tx in [
+            row(TxType::Deposit, 1, 1, 10.0, "2024-01-05", Some("visa")),
+            row(TxType::Deposit, 2, 2, 4.0, "2024-01-06", None),
+            row(TxType::Deposit, 1, 3, 1.0, "2024-01-07", Some("visa")),
+            row(TxType::Deposit, 3, 4, 5.0, "2024-01-08", Some("visa")),
+            row(TxType::Withdrawal, 3, 5, 1.0, "2024-01-08", Some("visa")),
+            row(TxType::Dispute, 1, 1, 0.0, "2024-01-20", None),
+            row(TxType::ChargeBack, 1, 1, 0.0, "2024-01-31", None),
+            row(TxType::Dispute, 3, 5, 0.0, "2024-02-01", None),
+            row(TxType::ChargeBack, 3, 5, 0.0, "2024-02-01", None),
+            row(TxType::Dispute, 2, 2, 0.0, "2024-02-01", None),
+            row(TxType::ChargeBack, 2, 2, 0.0, "2024-02-02", None),
+            // Locked by now, so these are rejected and write nothing off.
+            row(TxType::Dispute, 1, 3, 0.0, "2024-02-03", None),
+            row(TxType::ChargeBack, 1, 3, 0.0, "2024-02-04", None),
+        ] {
+            report.begin(tx.timestamp);
+            let writes_off = WritesOff::of(engine.state(), tx.cid, &tx);
+            if engine.apply(tx) {
+                if let Some(writes_off) = writes_off {
+                    report.record(writes_off);
+                }
+            }
+        }
+        let mut out = vec![];
+        report.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "period,counterparty,chargebacks,written_off,cumulative\n\
+             2024-01,visa,1,10.0000,10.0000\n\
+             2024-02,unknown,1,4.0000,4.0000\n"
+        );
+        let report = engine.report();
+        assert_eq!(report.columns.last(), Some(&"written_off"));
+        assert_eq!(report.clients[0].written_off, Some(Currency::from_num(10)));
+        assert_eq!(report.clients[2].written_off, Some(Currency::ZERO));
+        assert_eq!(report.totals.written_off, Currency::from_num(14));
+    }
+
+    #[test]
+    fn undated_rows_count_in_the_first_period() {
+        let mut report = WriteoffReport::new(Period::Weekly);
+        let writes_off = |amount: u32| WritesOff {
+            counterparty: "visa".to_string(),
+            amount: Currency::from_num(amount),
+        };
+        report.record(writes_off(1));
+        report.begin(Some("2024-03-06T10:00:00Z".parse().unwrap()));
+        report.record(writes_off(2));
+        report.begin(None);
+        report.record(writes_off(3));
+        let mut out = vec![];
+        report.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "period,counterparty,chargebacks,written_off,cumulative\n\
+             2024-03-04,visa,3,6.0000,6.0000\n"
+        );
+
+        let mut undated = WriteoffReport::new(Period::Monthly);
+        undated.record(writes_off(5));
+        let mut out = vec![];
+        undated.write(&mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("all,visa,1,5.0000,5.0000\n"));
+    }
+}
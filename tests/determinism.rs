@@ -0,0 +1,17 @@
+// The determinism promised in the Currency comment, as the binary checks it: the bundled
+// reference workload must hash to the state committed for this architecture and currency format.
+// An architecture without one fails too, with the hash to commit once it's been checked.
+#[test]
+fn reference_workload_state() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_txcli"))
+        .arg("determinism-check")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(std::env::consts::ARCH), "{}", stdout);
+}
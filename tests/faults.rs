@@ -0,0 +1,150 @@
+// The binary under the faults of src/faults.rs: whatever fails, the run exits with an error and
+// the snapshot from the day before is still there to carry on from. Needs the faults feature:
+// cargo test --features faults --test faults
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("txcli-faults-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Deposits over 20 clients, long enough that a fault at a few kilobytes lands mid-file.
+fn input(dir: &Path, first: u32, rows: u32) -> String {
+    let mut csv = String::from("type,client,tx,amount\n");
+    for tid in first..first + rows {
+        csv += &format!("deposit,{},{},1.5\n", tid % 20, tid);
+    }
+    let path = dir.join(format!("in-{}.csv", first));
+    fs::write(&path, csv).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn txcli(dir: &Path, args: &[&str], faults: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_txcli"))
+        .args(args)
+        .current_dir(dir)
+        .env("TXCLI_FAULTS", faults)
+        .output()
+        .unwrap()
+}
+
+// Monday's run, clean, leaves the snapshot the faulty runs start from and must not damage.
+fn monday(dir: &Path) -> Vec<u8> {
+    let input = input(dir, 1, 100);
+    let output = txcli(
+        dir,
+        &[
+            &input,
+            "--output",
+            "monday.csv",
+            "--snapshot-out",
+            "state.json",
+        ],
+        "",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    fs::read(dir.join("state.json")).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn read_error_mid_file() {
+    let dir = dir("read");
+    let snapshot = monday(&dir);
+    let input = input(&dir, 1000, 2000);
+    let args = [
+        &input,
+        "--snapshot-in",
+        "state.json",
+        "--output",
+        "tuesday.csv",
+        "--snapshot-out",
+        "state.json",
+    ];
+    let output = txcli(&dir, &args, "read-error@4096");
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("failed partway"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!dir.join("tuesday.csv").exists());
+    assert_eq!(fs::read(dir.join("state.json")).unwrap(), snapshot);
+
+    // The same under --threads, which reads on its own path.
+    let output = txcli(&dir, &[&input, "--threads", "2"], "read-error@4096");
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(output.stdout.is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn disk_full() {
+    let dir = dir("full");
+    let snapshot = monday(&dir);
+    let input = input(&dir, 1000, 2000);
+
+    // Balances first, the snapshot is never reached.
+    let args = [
+        &input,
+        "--snapshot-in",
+        "state.json",
+        "--output",
+        "tuesday.csv",
+        "--snapshot-out",
+        "state.json",
+    ];
+    let output = txcli(&dir, &args, "disk-full@64");
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert_eq!(fs::read(dir.join("state.json")).unwrap(), snapshot);
+
+    // Balances on stdout, the snapshot fills the disk.
+    let args = [
+        &input,
+        "--snapshot-in",
+        "state.json",
+        "--snapshot-out",
+        "state.json",
+    ];
+    let output = txcli(&dir, &args, "disk-full@64");
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("Can't write snapshot"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(fs::read(dir.join("state.json")).unwrap(), snapshot);
+    assert!(!dir.join("state.json.partial").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn interrupted_snapshot() {
+    let dir = dir("interrupt");
+    let snapshot = monday(&dir);
+    let input = input(&dir, 1000, 2000);
+    let args = [
+        &input,
+        "--snapshot-in",
+        "state.json",
+        "--snapshot-out",
+        "state.json",
+    ];
+    let output = txcli(&dir, &args, "snapshot-interrupt@50");
+    assert!(!output.status.success());
+    assert_eq!(fs::read(dir.join("state.json")).unwrap(), snapshot);
+    assert_eq!(fs::read(dir.join("state.json.partial")).unwrap().len(), 50);
+
+    // The rerun picks up from Monday's snapshot, over the partial file left behind.
+    let output = txcli(&dir, &args, "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_ne!(fs::read(dir.join("state.json")).unwrap(), snapshot);
+    assert!(!dir.join("state.json.partial").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
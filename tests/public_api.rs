@@ -0,0 +1,112 @@
+// The library as a downstream crate sees it: only txcli:: paths, every name lib.rs exports. A
+// rename, a removed export or a changed signature breaks this build first.
+use std::io::Read;
+use std::sync::Arc;
+use txcli::{
+    AssetRegistry, ChargebackFee, ClientId, ClientOutputState, Currency, CustomTxHandler,
+    DisputePolicy, Effect, EngineBuilder, FeePayer, HistoryBackend, InputFormat, LockedPolicy,
+    Overflow, RejectionLog, RejectionReason, Report, Retention, RiskLock, SettlementDelays, Stats,
+    TidScope, Totals, TransactionEngine, Tx, TxId, TxRecord, TxSource, TxType,
+};
+
+#[derive(Debug)]
+struct Cashback;
+
+impl CustomTxHandler for Cashback {
+    fn effect(&self, tx: &TxRecord, _: &ClientOutputState) -> Option<Effect> {
+        Some(Effect {
+            available: tx.amount,
+            held: Currency::from_num(0),
+            lock: false,
+        })
+    }
+}
+
+#[test]
+fn embedding() {
+    let mut engine: TransactionEngine = EngineBuilder::new()
+        .overdraft_rate(Currency::from_num(0.01))
+        .cutoff_every(1000)
+        .assets(AssetRegistry::default())
+        .dispute_cap(3, Overflow::Queue)
+        .risk_lock(RiskLock {
+            chargebacks: Some(2),
+            amount: None,
+        })
+        .locked_policy(LockedPolicy::RejectWithdrawals)
+        .dispute_policy(DisputePolicy::PartialHold)
+        .chargeback_fee(Currency::from_num(15), FeePayer::Counterparty)
+        .tid_scope(TidScope::Global)
+        .retention(Retention::LastN(100))
+        .history_cache(1_000_000)
+        .history_backend(HistoryBackend::Disk)
+        .include_archived()
+        .settlement_delays(SettlementDelays::default())
+        .custom_type("public_api_cashback", Arc::new(Cashback))
+        .unwrap()
+        .build();
+
+    let deposit = Tx::new(TxType::Deposit, 1, 1, Currency::from_num(10));
+    assert!(engine.apply(deposit.as_record()));
+    assert_eq!(
+        engine.try_apply(TxRecord::new(
+            TxType::Withdrawal,
+            2,
+            1,
+            Currency::from_num(1)
+        )),
+        Err(RejectionReason::ReusedId)
+    );
+    let cashback = "public_api_cashback".parse::<TxType>().unwrap();
+    assert!(engine.apply(TxRecord::new(cashback, 1, 2, Currency::from_num(1))));
+
+    let balance: ClientOutputState = engine.balance(ClientId(1)).unwrap();
+    assert_eq!(balance.available, Currency::from_num(11));
+    let report: Report = engine.report();
+    let stats: &Stats = &report.stats;
+    let totals: &Totals = &report.totals;
+    assert_eq!((stats.processed, stats.applied), (3, 2));
+    assert_eq!(totals.total, Currency::from_num(11));
+    assert!(report.balances_csv().is_ok());
+    assert_eq!(TxId(1), deposit.tid);
+    assert_eq!(
+        engine.config().chargeback_fee,
+        Some(ChargebackFee {
+            amount: Currency::from_num(15),
+            payer: FeePayer::Counterparty,
+        })
+    );
+}
+
+#[test]
+fn sources_and_sinks() {
+    let dir = std::env::temp_dir().join(format!("txcli-public-api-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.jsonl");
+    std::fs::write(
+        &input,
+        "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1\"}\n",
+    )
+    .unwrap();
+    let input = input.to_str().unwrap();
+    let format = InputFormat::of(input, None);
+    assert_eq!(format, InputFormat::Jsonl);
+    let mut csv = String::new();
+    TxSource::open(input, format)
+        .unwrap()
+        .read_to_string(&mut csv)
+        .unwrap();
+    assert!(csv.ends_with("deposit,1,1,1,,,,,,,,,\n"));
+
+    let errors = dir.join("errors.csv");
+    let mut log = RejectionLog::create(errors.to_str().unwrap()).unwrap();
+    let tx = TxRecord::new(TxType::Dispute, 1, 9, Currency::from_num(0));
+    log.record(Some(2), &tx, RejectionReason::UnknownTx)
+        .unwrap();
+    log.finish().unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&errors).unwrap(),
+        "line,client,tx,type,reason\n2,1,9,dispute,unknown_tx\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
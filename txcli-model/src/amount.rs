@@ -0,0 +1,329 @@
+use crate::{Currency, CurrencyBits, CURRENCY_FORMAT};
+use serde::de::{Error as DeError, Visitor};
+use serde::Deserializer;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+// Amounts are parsed digit by digit straight into the fixed point bits, no float is ever
+// involved so there is exactly one rounding step, to the nearest representable value with
+// ties to even. Accepts an optional sign, integer digits and an optional fraction, e.g.
+// "12", "-0.5", "+.25" or "3.".
+pub fn parse_amount(s: &str) -> Result<Currency, ParseAmountError> {
+    parse(s, false).map(|(amount, _)| amount)
+}
+
+// parse_amount along with what that one rounding step lost, the decimal value of s minus the
+// amount, as a float since it is meant for adding up and reporting. Exact to well within its
+// own magnitude, the digits past the rounded bits are only read to float precision.
+pub fn parse_amount_with_error(s: &str) -> Result<(Currency, f64), ParseAmountError> {
+    parse(s, true)
+}
+
+fn parse(s: &str, with_error: bool) -> Result<(Currency, f64), ParseAmountError> {
+    let invalid = || ParseAmountError::Invalid(s.to_string());
+    let out_of_range = || ParseAmountError::OutOfRange(s.to_string());
+    let (negative, unsigned) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (int_digits, frac_digits) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if int_digits.is_empty() && frac_digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut int_part: u128 = 0;
+    let mut overflow = false;
+    for b in int_digits.bytes() {
+        if !b.is_ascii_digit() {
+            return Err(invalid());
+        }
+        match int_part
+            .checked_mul(10)
+            .and_then(|n| n.checked_add((b - b'0') as u128))
+        {
+            Some(n) => int_part = n,
+            None => overflow = true,
+        }
+    }
+
+    // Every rounding midpoint has at most FRAC_NBITS + 1 decimal places, so digits past this
+    // can only matter as a sticky "above the midpoint" flag.
+    const MAX_FRAC_DIGITS: usize = 80;
+    let mut digits = [0u8; MAX_FRAC_DIGITS];
+    let mut len = 0;
+    let mut sticky = false;
+    for b in frac_digits.bytes() {
+        if !b.is_ascii_digit() {
+            return Err(invalid());
+        }
+        if len < MAX_FRAC_DIGITS {
+            digits[len] = b - b'0';
+            len += 1;
+        } else if b != b'0' {
+            sticky = true;
+        }
+    }
+    if overflow {
+        return Err(out_of_range());
+    }
+
+    // Doubling the decimal fraction shifts its next binary digit out as the carry.
+    let digits = &mut digits[..len];
+    let mut next_bit = || {
+        let mut carry = 0;
+        for digit in digits.iter_mut().rev() {
+            let doubled = *digit * 2 + carry;
+            *digit = doubled % 10;
+            carry = doubled / 10;
+        }
+        carry as u128
+    };
+    let mut frac_bits: u128 = 0;
+    for _ in 0..Currency::FRAC_NBITS {
+        frac_bits = frac_bits << 1 | next_bit();
+    }
+    let above_half = next_bit() == 1;
+    let rounded_up =
+        above_half && (sticky || digits.iter().any(|digit| *digit != 0) || frac_bits & 1 == 1);
+    if rounded_up {
+        frac_bits += 1;
+    }
+    // What the digits left after the half bit are worth, in units of the last bit kept.
+    let mut error = 0.0;
+    if with_error {
+        let rest = digits
+            .iter()
+            .take(20)
+            .rev()
+            .fold(0.0, |rest, digit| (rest + *digit as f64) / 10.0);
+        error = (above_half as u8 as f64 + rest) / 2.0;
+        if rounded_up {
+            error -= 1.0;
+        }
+        error *= 2f64.powi(-(Currency::FRAC_NBITS as i32));
+        if negative {
+            error = -error;
+        }
+    }
+
+    let magnitude = int_part
+        .checked_shl(Currency::FRAC_NBITS)
+        .filter(|shifted| shifted >> Currency::FRAC_NBITS == int_part)
+        .and_then(|shifted| shifted.checked_add(frac_bits))
+        .ok_or_else(out_of_range)?;
+    let bits = if negative {
+        0i128.checked_sub_unsigned(magnitude)
+    } else {
+        i128::try_from(magnitude).ok()
+    }
+    .ok_or_else(out_of_range)?;
+    let amount = Currency::from_bits(CurrencyBits::try_from(bits).map_err(|_| out_of_range())?);
+    Ok((amount, error))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseAmountError {
+    Invalid(String),
+    OutOfRange(String),
+}
+
+impl Display for ParseAmountError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ParseAmountError::Invalid(value) => write!(f, "Invalid amount {}", value),
+            ParseAmountError::OutOfRange(value) => write!(
+                f,
+                "Amount {} is outside the {} currency range of {} to {}",
+                value,
+                CURRENCY_FORMAT,
+                Currency::MIN,
+                Currency::MAX
+            ),
+        }
+    }
+}
+
+impl Error for ParseAmountError {}
+
+struct AmountVisitor;
+
+impl Visitor<'_> for AmountVisitor {
+    type Value = Currency;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "a decimal amount string")
+    }
+
+    fn visit_str<E: DeError>(self, value: &str) -> Result<Currency, E> {
+        parse_amount(value).map_err(E::custom)
+    }
+}
+
+// For #[serde(deserialize_with)], goes through parse_amount instead of fixed's own parser.
+// Only strings are accepted, json numbers are rejected rather than passing through an f64.
+pub fn deserialize_amount<'de, D>(d: D) -> Result<Currency, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    d.deserialize_str(AmountVisitor)
+}
+
+pub fn deserialize_optional_amount<'de, D>(d: D) -> Result<Option<Currency>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct OptionalVisitor;
+
+    impl<'de> Visitor<'de> for OptionalVisitor {
+        type Value = Option<Currency>;
+
+        fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+            AmountVisitor.expecting(f)
+        }
+
+        fn visit_none<E: DeError>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: DeError>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+            deserialize_amount(d).map(Some)
+        }
+    }
+
+    d.deserialize_option(OptionalVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[test]
+    fn exact_values() {
+        assert_eq!(parse_amount("0").unwrap(), Currency::from_num(0));
+        assert_eq!(parse_amount("1.5").unwrap(), Currency::from_num(1.5));
+        assert_eq!(parse_amount("-2.25").unwrap(), Currency::from_num(-2.25));
+        assert_eq!(parse_amount("+.5").unwrap(), Currency::from_num(0.5));
+        assert_eq!(parse_amount("3.").unwrap(), Currency::from_num(3));
+        assert_eq!(
+            parse_amount("1234567890").unwrap(),
+            Currency::from_num(1234567890)
+        );
+    }
+
+    #[cfg(not(any(feature = "currency-i64f64", feature = "currency-i34f30")))]
+    #[test]
+    fn rounds_to_nearest() {
+        // 0.1 * 2^14 = 1638.4, so the nearest bits are 1638.
+        assert_eq!(parse_amount("0.1").unwrap().to_bits(), 1638);
+        // 0.0001 * 2^14 = 1.6384
+        assert_eq!(parse_amount("0.0001").unwrap().to_bits(), 2);
+        assert_eq!(parse_amount("-0.0001").unwrap().to_bits(), -2);
+        // Exactly half way between 0 and 1 bit rounds to even, anything above rounds up.
+        let half = "0.000030517578125";
+        assert_eq!(parse_amount(half).unwrap().to_bits(), 0);
+        let above = format!("{}{}1", half, "0".repeat(100));
+        assert_eq!(parse_amount(&above).unwrap().to_bits(), 1);
+        // 2.5 bits rounds down to the even 2.
+        assert_eq!(parse_amount("0.000152587890625").unwrap().to_bits(), 2);
+        assert_eq!(
+            parse_amount("12345678901234").unwrap(),
+            Currency::from_num(12345678901234_i64)
+        );
+    }
+
+    #[cfg(not(any(feature = "currency-i64f64", feature = "currency-i34f30")))]
+    #[test]
+    fn rounding_error() {
+        let ulp = 2f64.powi(-14);
+        let error = |s| parse_amount_with_error(s).unwrap().1;
+        assert_eq!(error("12.5"), 0.0);
+        assert_eq!(error("0.000030517578125"), ulp / 2.0);
+        assert_eq!(error("0.000152587890625"), ulp / 2.0);
+        // 1638.4 bits rounds down to 1638 and 1.6384 up to 2.
+        assert!((error("0.1") - 0.4 * ulp).abs() < 1e-18);
+        assert!((error("0.0001") + 0.3616 * ulp).abs() < 1e-18);
+        assert!((error("-0.0001") - 0.3616 * ulp).abs() < 1e-18);
+        for value in ["0.1", "-7.77", "1.23456789", "99999999999999.9999"] {
+            let (amount, error) = parse_amount_with_error(value).unwrap();
+            assert_eq!(amount, parse_amount(value).unwrap());
+            assert!(error.abs() <= ulp / 2.0, "{}", value);
+        }
+    }
+
+    #[test]
+    fn matches_fixed_parser() {
+        for value in [
+            "0.1",
+            "0.2",
+            "0.3",
+            "0.0001",
+            "0.00005",
+            "1.23456789",
+            "-7.77",
+            "1000000.0001",
+            "0.000000000000000000012345",
+            "8589934591.99",
+            "562949953421311.99",
+            "99999999999999.9999",
+        ] {
+            match value.parse::<Currency>() {
+                Ok(expected) => assert_eq!(parse_amount(value).unwrap(), expected, "{}", value),
+                Err(_) => assert!(parse_amount(value).is_err(), "{}", value),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_garbage_and_overflow() {
+        for value in ["", "-", ".", "1e5", "1.2.3", "abc", " 1", "1,000", "--1"] {
+            assert_eq!(
+                parse_amount(value),
+                Err(ParseAmountError::Invalid(value.to_string())),
+                "{}",
+                value
+            );
+        }
+        let max = Currency::MAX.int().to_string();
+        let above_max = (Currency::MAX.int().to_num::<i128>() + 1).to_string();
+        assert!(parse_amount(&max).is_ok());
+        assert_eq!(
+            parse_amount(&above_max),
+            Err(ParseAmountError::OutOfRange(above_max.clone()))
+        );
+        assert!(parse_amount(&format!("-{}", above_max)).is_ok());
+        assert!(parse_amount("99999999999999999999999999999999999999999").is_err());
+        let err = parse_amount(&above_max).unwrap_err().to_string();
+        assert!(err.contains(CURRENCY_FORMAT), "{}", err);
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Row {
+        #[serde(deserialize_with = "deserialize_amount")]
+        amount: Currency,
+        #[serde(default, deserialize_with = "deserialize_optional_amount")]
+        fee: Option<Currency>,
+    }
+
+    #[test]
+    fn serde_helpers() {
+        let row: Row = serde_json::from_str(r#"{"amount":"0.1","fee":null}"#).unwrap();
+        assert_eq!(row.amount, "0.1".parse::<Currency>().unwrap());
+        assert_eq!(row.fee, None);
+        let row: Row = serde_json::from_str(r#"{"amount":"3","fee":"0.5"}"#).unwrap();
+        assert_eq!(row.amount, Currency::from_num(3));
+        assert_eq!(row.fee, Some(Currency::from_num(0.5)));
+        assert!(serde_json::from_str::<Row>(r#"{"amount":0.1}"#).is_err());
+        assert!(serde_json::from_str::<Row>(r#"{"amount":"1e3"}"#).is_err());
+
+        let mut reader = csv::Reader::from_reader("amount,fee\n1.5,\n".as_bytes());
+        let row: Row = reader.deserialize().next().unwrap().unwrap();
+        assert_eq!(row.amount, Currency::from_num(1.5));
+        assert_eq!(row.fee, None);
+    }
+}
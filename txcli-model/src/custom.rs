@@ -0,0 +1,109 @@
+use crate::TxType;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::RwLock;
+
+// Names of the transaction types registered at runtime, indexed by CustomType. Global so that
+// parsing and printing a TxType keep working without a registry passed around, types are only
+// ever added.
+static NAMES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+// A transaction type an experimental product added without a variant of its own, see
+// register_custom_type. The number only means something in the process that registered it,
+// files always carry the name.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct CustomType(pub u8);
+
+#[derive(Debug, PartialEq)]
+pub struct RegisterTypeError(String);
+
+impl Display for RegisterTypeError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for RegisterTypeError {}
+
+// Makes name parse as a TxType from now on. Registering a name twice returns the same type, a
+// built in name can't be taken over.
+pub fn register_custom_type(name: &str) -> Result<TxType, RegisterTypeError> {
+    if name
+        .parse::<TxType>()
+        .is_ok_and(|tx_type| !matches!(tx_type, TxType::Custom(_)))
+    {
+        return Err(RegisterTypeError(format!(
+            "Transaction type {} is built in and can't be registered.",
+            name
+        )));
+    }
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+        return Err(RegisterTypeError(format!(
+            "Invalid transaction type name {}, expected lowercase letters and underscores.",
+            name
+        )));
+    }
+    let mut names = NAMES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(index) = names.iter().position(|registered| registered == name) {
+        return Ok(TxType::Custom(CustomType(index as u8)));
+    }
+    let index = u8::try_from(names.len()).map_err(|_| {
+        RegisterTypeError(format!(
+            "Cannot register transaction type {}, at most 256 custom types are supported.",
+            name
+        ))
+    })?;
+    names.push(name.to_string());
+    Ok(TxType::Custom(CustomType(index)))
+}
+
+pub(crate) fn lookup(name: &str) -> Option<CustomType> {
+    let names = NAMES
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    names
+        .iter()
+        .position(|registered| registered == name)
+        .map(|index| CustomType(index as u8))
+}
+
+impl Display for CustomType {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let names = NAMES
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match names.get(self.0 as usize) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "custom_{}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_types_parse_and_print() {
+        assert!("cashback".parse::<TxType>().is_err());
+        let cashback = register_custom_type("cashback").unwrap();
+        assert_eq!(register_custom_type("cashback").unwrap(), cashback);
+        assert_eq!("cashback".parse::<TxType>().unwrap(), cashback);
+        assert_eq!(cashback.to_string(), "cashback");
+        assert_eq!(serde_json::to_string(&cashback).unwrap(), "\"cashback\"");
+        assert_eq!(
+            serde_json::from_str::<TxType>("\"cashback\"").unwrap(),
+            cashback
+        );
+        assert_ne!(register_custom_type("loyalty_fee").unwrap(), cashback);
+    }
+
+    #[test]
+    fn built_in_and_invalid_names() {
+        assert!(register_custom_type("deposit").is_err());
+        assert!(register_custom_type("Fee").is_err());
+        assert!(register_custom_type("").is_err());
+    }
+}
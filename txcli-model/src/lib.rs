@@ -0,0 +1,449 @@
+// Record types shared by txcli and any service producing or consuming txcli compatible data.
+// Everything here serializes and deserializes, and the tests at the bottom pin the wire format.
+
+pub mod amount;
+pub mod custom;
+pub mod record;
+pub mod timestamp;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+pub use amount::{
+    deserialize_amount, deserialize_optional_amount, parse_amount, parse_amount_with_error,
+    ParseAmountError,
+};
+pub use custom::{register_custom_type, CustomType, RegisterTypeError};
+pub use record::TxRecord;
+pub use timestamp::Timestamp;
+
+#[cfg(all(feature = "currency-i64f64", feature = "currency-i34f30"))]
+compile_error!("Enable at most one of the currency-i64f64 and currency-i34f30 features.");
+
+// You wanted precision to 0.0001,
+// but you'll get precision to 0.000061.
+// Fixed point chosen so that operations are deterministic across
+// all architectures, and to retain associativity/commutativity.
+// txcli determinism-check holds every format to that.
+#[cfg(not(any(feature = "currency-i64f64", feature = "currency-i34f30")))]
+pub type Currency = fixed::types::I50F14;
+#[cfg(not(any(feature = "currency-i64f64", feature = "currency-i34f30")))]
+pub const CURRENCY_FORMAT: &str = "I50F14";
+
+// Precision to about 5e-20 and the range of an i64, for very small amounts alongside large totals.
+#[cfg(feature = "currency-i64f64")]
+pub type Currency = fixed::types::I64F64;
+#[cfg(feature = "currency-i64f64")]
+pub const CURRENCY_FORMAT: &str = "I64F64";
+
+// Precision to about 1e-9 but totals only up to about 8.5 billion.
+#[cfg(all(feature = "currency-i34f30", not(feature = "currency-i64f64")))]
+pub type Currency = fixed::types::I34F30;
+#[cfg(all(feature = "currency-i34f30", not(feature = "currency-i64f64")))]
+pub const CURRENCY_FORMAT: &str = "I34F30";
+
+pub type CurrencyBits = <Currency as fixed::traits::Fixed>::Bits;
+
+#[derive(
+    Serialize, Deserialize, Debug, PartialEq, Hash, Eq, Clone, Copy, Default, PartialOrd, Ord,
+)]
+#[serde(transparent)]
+pub struct ClientId(pub u16);
+
+#[derive(
+    Serialize, Deserialize, Debug, PartialEq, Hash, Eq, Clone, Copy, Default, PartialOrd, Ord,
+)]
+#[serde(transparent)]
+pub struct TxId(pub u32);
+
+// Named accounts held by a single client, e.g. checking/savings. Rows without an account
+// column use the default account, so single account files behave exactly as before.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Hash, Eq, Clone, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct AccountId(pub String);
+
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+impl Default for AccountId {
+    fn default() -> Self {
+        AccountId(DEFAULT_ACCOUNT.to_string())
+    }
+}
+
+// Lets account maps be looked up by a borrowed &str without building an AccountId.
+impl Borrow<str> for AccountId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum TxType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    ChargeBack,
+    // Moves available funds between two accounts of the same client.
+    Transfer,
+    // Cancels an undisputed deposit or withdrawal before settlement, as if it never happened.
+    Void,
+    // Admin rows moving a dormant client out of the active clients and back, the tid is only the
+    // row's own id.
+    ArchiveClient,
+    ReactivateClient,
+    // A free-text memo on the client, carried in the memo column. Moves no funds and can't be
+    // referenced by later rows.
+    Note,
+    // Registered at runtime, applied by whatever handler the engine was given for it.
+    Custom(CustomType),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseTxTypeError(String);
+
+impl Display for ParseTxTypeError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Unknown transaction type {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseTxTypeError {}
+
+// Same names as the serde representation.
+impl FromStr for TxType {
+    type Err = ParseTxTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(TxType::Deposit),
+            "withdrawal" => Ok(TxType::Withdrawal),
+            "dispute" => Ok(TxType::Dispute),
+            "resolve" => Ok(TxType::Resolve),
+            "chargeback" => Ok(TxType::ChargeBack),
+            "transfer" => Ok(TxType::Transfer),
+            "void" => Ok(TxType::Void),
+            "archive_client" => Ok(TxType::ArchiveClient),
+            "reactivate_client" => Ok(TxType::ReactivateClient),
+            "note" => Ok(TxType::Note),
+            _ => custom::lookup(s)
+                .map(TxType::Custom)
+                .ok_or_else(|| ParseTxTypeError(s.to_string())),
+        }
+    }
+}
+
+// The input spelling, same as FromStr accepts.
+impl Display for TxType {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let name = match self {
+            TxType::Deposit => "deposit",
+            TxType::Withdrawal => "withdrawal",
+            TxType::Dispute => "dispute",
+            TxType::Resolve => "resolve",
+            TxType::ChargeBack => "chargeback",
+            TxType::Transfer => "transfer",
+            TxType::Void => "void",
+            TxType::ArchiveClient => "archive_client",
+            TxType::ReactivateClient => "reactivate_client",
+            TxType::Note => "note",
+            TxType::Custom(custom) => return write!(f, "{}", custom),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// By name, the same spelling as Display and FromStr, so custom types read like built in ones.
+impl Serialize for TxType {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TxType {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(d)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Tx {
+    pub tx_type: TxType,
+    pub cid: ClientId,
+    pub tid: TxId,
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub amount: Currency,
+    #[serde(default)]
+    pub account: AccountId,
+    #[serde(default)]
+    pub to_account: Option<AccountId>,
+    // Free form tag, only used for reporting.
+    #[serde(default)]
+    pub category: Option<String>,
+    // Schema v2 only.
+    #[serde(default)]
+    pub timestamp: Option<Timestamp>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub counterparty: Option<String>,
+    // Orders transactions sharing a timestamp, higher first. Missing is the same as 0.
+    #[serde(default)]
+    pub priority: Option<u8>,
+    // The upstream system the row came from, for attributing volumes and rejects.
+    #[serde(default)]
+    pub source: Option<String>,
+    // Free text, only read from note rows.
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+impl Tx {
+    pub fn new(ty: TxType, cid: u16, tid: u32, amount: Currency) -> Self {
+        Tx {
+            tx_type: ty,
+            cid: ClientId(cid),
+            tid: TxId(tid),
+            amount,
+            account: AccountId::default(),
+            to_account: None,
+            category: None,
+            timestamp: None,
+            currency: None,
+            counterparty: None,
+            priority: None,
+            source: None,
+            memo: None,
+        }
+    }
+
+    pub fn with_account(mut self, account: &str) -> Self {
+        self.account = AccountId(account.to_string());
+        self
+    }
+
+    pub fn with_to_account(mut self, account: &str) -> Self {
+        self.to_account = Some(AccountId(account.to_string()));
+        self
+    }
+}
+
+// bit hacky as this is limiting to only string output, but good enough for a demo cli tool.
+pub fn precision4_serialize_currency<S>(currency: &Currency, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_str(&format!("{:.4}", currency))
+}
+
+pub fn precision4_serialize_optional_currency<S>(
+    currency: &Option<Currency>,
+    s: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match currency {
+        Some(currency) => precision4_serialize_currency(currency, s),
+        None => s.serialize_none(),
+    }
+}
+
+// Amounts are written with 4 decimal places, so a round trip is only exact for values
+// that already sit on the fixed point value nearest to a 4 decimal number.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ClientOutputState {
+    pub cid: ClientId,
+    // Only present when the balances hold more than one asset, blank for rows without a currency.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    // Only present for per account rows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<AccountId>,
+    #[serde(
+        serialize_with = "precision4_serialize_currency",
+        deserialize_with = "deserialize_amount"
+    )]
+    pub available: Currency,
+    #[serde(
+        serialize_with = "precision4_serialize_currency",
+        deserialize_with = "deserialize_amount"
+    )]
+    pub held: Currency,
+    #[serde(
+        serialize_with = "precision4_serialize_currency",
+        deserialize_with = "deserialize_amount"
+    )]
+    pub total: Currency,
+    pub locked: bool,
+    // Only present when joint ownership is configured, the canonical owner followed by every joint owner.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owners: Option<String>,
+    // Only present when overdraft penalties are enabled.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "precision4_serialize_optional_currency",
+        deserialize_with = "deserialize_optional_amount"
+    )]
+    pub penalties: Option<Currency>,
+    // Only present when chargeback fees are charged to clients.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "precision4_serialize_optional_currency",
+        deserialize_with = "deserialize_optional_amount"
+    )]
+    pub chargeback_fees: Option<Currency>,
+    // Only present when writeoffs are tracked, what chargebacks took from the client's held funds.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "precision4_serialize_optional_currency",
+        deserialize_with = "deserialize_optional_amount"
+    )]
+    pub written_off: Option<Currency>,
+    // Only present with settlement delays, deposits not yet available, counted in the total.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "precision4_serialize_optional_currency",
+        deserialize_with = "deserialize_optional_amount"
+    )]
+    pub pending: Option<Currency>,
+    // Only present when selected with --columns, the client's open disputes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispute_count: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_tx() -> Tx {
+        let mut tx = Tx::new(TxType::Transfer, 7, 42, Currency::from_num(12.5))
+            .with_account("checking")
+            .with_to_account("savings");
+        tx.category = Some("rent".to_string());
+        tx.timestamp = Some(Timestamp(1660052700));
+        tx.currency = Some("USD".to_string());
+        tx.counterparty = Some("acme".to_string());
+        tx.priority = Some(2);
+        tx.memo = Some("paid late, called twice".to_string());
+        tx
+    }
+
+    fn output_state() -> ClientOutputState {
+        ClientOutputState {
+            cid: ClientId(3),
+            currency: Some("EUR".to_string()),
+            account: Some(AccountId("savings".to_string())),
+            available: Currency::from_num(1.5),
+            held: Currency::from_num(0.25),
+            total: Currency::from_num(1.75),
+            locked: true,
+            owners: Some("3;4".to_string()),
+            penalties: Some(Currency::from_num(0.5)),
+            chargeback_fees: Some(Currency::from_num(15)),
+            written_off: Some(Currency::from_num(40)),
+            dispute_count: None,
+            pending: None,
+        }
+    }
+
+    #[test]
+    fn tx_json_round_trip() {
+        for tx in [
+            full_tx(),
+            Tx::new(TxType::Dispute, 1, 2, Currency::default()),
+        ] {
+            let json = serde_json::to_string(&tx).unwrap();
+            assert_eq!(serde_json::from_str::<Tx>(&json).unwrap(), tx);
+        }
+    }
+
+    #[test]
+    fn tx_minimal_json_uses_defaults() {
+        let tx: Tx =
+            serde_json::from_str(r#"{"tx_type":"deposit","cid":1,"tid":2,"amount":"1.5"}"#)
+                .unwrap();
+        assert_eq!(tx, Tx::new(TxType::Deposit, 1, 2, Currency::from_num(1.5)));
+    }
+
+    #[test]
+    fn tx_type_names() {
+        for (tx_type, name) in [
+            (TxType::Deposit, "\"deposit\""),
+            (TxType::Withdrawal, "\"withdrawal\""),
+            (TxType::Dispute, "\"dispute\""),
+            (TxType::Resolve, "\"resolve\""),
+            (TxType::ChargeBack, "\"chargeback\""),
+            (TxType::Transfer, "\"transfer\""),
+            (TxType::Void, "\"void\""),
+            (TxType::ArchiveClient, "\"archive_client\""),
+            (TxType::ReactivateClient, "\"reactivate_client\""),
+            (TxType::Note, "\"note\""),
+        ] {
+            assert_eq!(serde_json::to_string(&tx_type).unwrap(), name);
+            assert_eq!(serde_json::from_str::<TxType>(name).unwrap(), tx_type);
+            assert_eq!(name.trim_matches('"').parse::<TxType>().unwrap(), tx_type);
+            assert_eq!(tx_type.to_string(), name.trim_matches('"'));
+        }
+    }
+
+    #[test]
+    fn unknown_tx_type() {
+        assert!("refund".parse::<TxType>().is_err());
+    }
+
+    #[test]
+    fn ids_are_transparent() {
+        assert_eq!(serde_json::to_string(&ClientId(5)).unwrap(), "5");
+        assert_eq!(serde_json::to_string(&TxId(6)).unwrap(), "6");
+        assert_eq!(
+            serde_json::from_str::<AccountId>("\"savings\"").unwrap(),
+            AccountId("savings".to_string())
+        );
+    }
+
+    #[test]
+    fn output_state_json_round_trip() {
+        let state = output_state();
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(
+            json,
+            r#"{"cid":3,"currency":"EUR","account":"savings","available":"1.5000","held":"0.2500","total":"1.7500","locked":true,"owners":"3;4","penalties":"0.5000","chargeback_fees":"15.0000","written_off":"40.0000"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ClientOutputState>(&json).unwrap(),
+            state
+        );
+    }
+
+    #[test]
+    fn output_state_csv_round_trip() {
+        let mut state = output_state();
+        state.currency = None;
+        state.account = None;
+        state.owners = None;
+        state.penalties = None;
+        state.chargeback_fees = None;
+        state.written_off = None;
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(&state).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "cid,available,held,total,locked\n3,1.5000,0.2500,1.7500,true\n"
+        );
+
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let read: ClientOutputState = reader.deserialize().next().unwrap().unwrap();
+        assert_eq!(read, state);
+    }
+}
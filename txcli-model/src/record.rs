@@ -0,0 +1,122 @@
+use crate::{AccountId, ClientId, Currency, Timestamp, Tx, TxId, TxType, DEFAULT_ACCOUNT};
+
+// Borrowed view of a transaction, string fields point into the reader's row buffer so
+// parsing and applying a row doesn't allocate. Convert to an owned Tx only when it has to
+// outlive the row, e.g. to keep it in history for later disputes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TxRecord<'a> {
+    pub tx_type: TxType,
+    pub cid: ClientId,
+    pub tid: TxId,
+    pub amount: Currency,
+    pub account: &'a str,
+    pub to_account: Option<&'a str>,
+    pub category: Option<&'a str>,
+    pub timestamp: Option<Timestamp>,
+    pub currency: Option<&'a str>,
+    pub counterparty: Option<&'a str>,
+    pub priority: Option<u8>,
+    pub source: Option<&'a str>,
+    pub memo: Option<&'a str>,
+}
+
+impl<'a> TxRecord<'a> {
+    pub fn new(ty: TxType, cid: u16, tid: u32, amount: Currency) -> Self {
+        TxRecord {
+            tx_type: ty,
+            cid: ClientId(cid),
+            tid: TxId(tid),
+            amount,
+            account: DEFAULT_ACCOUNT,
+            to_account: None,
+            category: None,
+            timestamp: None,
+            currency: None,
+            counterparty: None,
+            priority: None,
+            source: None,
+            memo: None,
+        }
+    }
+
+    pub fn with_account(mut self, account: &'a str) -> Self {
+        self.account = account;
+        self
+    }
+
+    pub fn with_to_account(mut self, account: &'a str) -> Self {
+        self.to_account = Some(account);
+        self
+    }
+
+    pub fn to_tx(&self) -> Tx {
+        Tx {
+            tx_type: self.tx_type,
+            cid: self.cid,
+            tid: self.tid,
+            amount: self.amount,
+            account: AccountId(self.account.to_string()),
+            to_account: self
+                .to_account
+                .map(|account| AccountId(account.to_string())),
+            category: self.category.map(String::from),
+            timestamp: self.timestamp,
+            currency: self.currency.map(String::from),
+            counterparty: self.counterparty.map(String::from),
+            priority: self.priority,
+            source: self.source.map(String::from),
+            memo: self.memo.map(String::from),
+        }
+    }
+}
+
+impl Tx {
+    pub fn as_record(&self) -> TxRecord<'_> {
+        TxRecord {
+            tx_type: self.tx_type,
+            cid: self.cid,
+            tid: self.tid,
+            amount: self.amount,
+            account: &self.account.0,
+            to_account: self.to_account.as_ref().map(|account| account.0.as_str()),
+            category: self.category.as_deref(),
+            timestamp: self.timestamp,
+            currency: self.currency.as_deref(),
+            counterparty: self.counterparty.as_deref(),
+            priority: self.priority,
+            source: self.source.as_deref(),
+            memo: self.memo.as_deref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owned_round_trip() {
+        let record = TxRecord {
+            category: Some("rent"),
+            timestamp: Some(Timestamp(1660052700)),
+            currency: Some("USD"),
+            counterparty: Some("acme"),
+            priority: Some(3),
+            source: Some("bank-a"),
+            memo: Some("card replaced"),
+            ..TxRecord::new(TxType::Transfer, 7, 42, Currency::from_num(12.5))
+                .with_account("checking")
+                .with_to_account("savings")
+        };
+        let tx = record.to_tx();
+        assert_eq!(tx.account, AccountId("checking".to_string()));
+        assert_eq!(tx.counterparty.as_deref(), Some("acme"));
+        assert_eq!(tx.as_record(), record);
+    }
+
+    #[test]
+    fn default_account() {
+        let tx = TxRecord::new(TxType::Deposit, 1, 2, Currency::from_num(1.5)).to_tx();
+        assert_eq!(tx, Tx::new(TxType::Deposit, 1, 2, Currency::from_num(1.5)));
+    }
+}
@@ -0,0 +1,230 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// Seconds since the unix epoch, always UTC. Kept as a plain integer so ordering and
+// arithmetic stay trivial and deterministic, the calendar is only needed at the edges.
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
+#[serde(transparent)]
+pub struct Timestamp(pub u64);
+
+// Howard Hinnant's days_from_civil, proleptic gregorian calendar.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// Inverse of days_from_civil, returns (year, month, day).
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseTimestampError(String);
+
+impl Display for ParseTimestampError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Invalid timestamp {}", self.0)
+    }
+}
+
+impl Error for ParseTimestampError {}
+
+impl Timestamp {
+    pub fn days(&self) -> i64 {
+        (self.0 / 86400) as i64
+    }
+}
+
+fn parse_number(s: &str, range: std::ops::RangeInclusive<u32>) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse::<u32>().ok().filter(|n| range.contains(n))
+}
+
+impl FromStr for Timestamp {
+    type Err = ParseTimestampError;
+
+    // Accepts unix seconds ("1660000000") or an ISO 8601 UTC date/datetime
+    // ("2022-08-09", "2022-08-09T13:45:00Z"). Offsets other than Z are rejected rather than guessed at.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseTimestampError(s.to_string());
+        if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(Timestamp(s.parse().map_err(|_| invalid())?));
+        }
+
+        let (date, time) = match s.split_once(['T', ' ']) {
+            Some((date, time)) => (date, Some(time.strip_suffix('Z').unwrap_or(time))),
+            None => (s, None),
+        };
+        let mut date_parts = date.split('-');
+        let (year, month, day) = match (
+            date_parts.next(),
+            date_parts.next(),
+            date_parts.next(),
+            date_parts.next(),
+        ) {
+            (Some(year), Some(month), Some(day), None) if year.len() == 4 => (
+                parse_number(year, 1970..=9999).ok_or_else(invalid)?,
+                parse_number(month, 1..=12).ok_or_else(invalid)?,
+                parse_number(day, 1..=31).ok_or_else(invalid)?,
+            ),
+            _ => return Err(invalid()),
+        };
+        let days = days_from_civil(year as i64, month, day);
+        if civil_from_days(days) != (year as i64, month, day) {
+            return Err(invalid());
+        }
+
+        let seconds = match time {
+            Some(time) => {
+                let mut time_parts = time.split(':');
+                match (
+                    time_parts.next(),
+                    time_parts.next(),
+                    time_parts.next(),
+                    time_parts.next(),
+                ) {
+                    (Some(h), Some(m), sec, None) => {
+                        let h = parse_number(h, 0..=23).ok_or_else(invalid)?;
+                        let m = parse_number(m, 0..=59).ok_or_else(invalid)?;
+                        let sec = match sec {
+                            Some(sec) => parse_number(sec, 0..=59).ok_or_else(invalid)?,
+                            None => 0,
+                        };
+                        (h * 3600 + m * 60 + sec) as u64
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            None => 0,
+        };
+
+        Ok(Timestamp(days as u64 * 86400 + seconds))
+    }
+}
+
+struct TimestampVisitor;
+
+impl Visitor<'_> for TimestampVisitor {
+    type Value = Timestamp;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "unix seconds or an ISO 8601 UTC date/datetime")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Timestamp, E> {
+        Ok(Timestamp(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Timestamp, E> {
+        value.parse().map_err(E::custom)
+    }
+}
+
+// Serialized as plain seconds, but read from either seconds or an ISO string since
+// that is what most ledgers export.
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let (year, month, day) = civil_from_days(self.days());
+        let seconds = self.0 % 86400;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_formats() {
+        assert_eq!("0".parse::<Timestamp>().unwrap(), Timestamp(0));
+        assert_eq!(
+            "1660052700".parse::<Timestamp>().unwrap(),
+            Timestamp(1660052700)
+        );
+        assert_eq!(
+            "2022-08-09T13:45:00Z".parse::<Timestamp>().unwrap(),
+            Timestamp(1660052700)
+        );
+        assert_eq!(
+            "2022-08-09 13:45".parse::<Timestamp>().unwrap(),
+            Timestamp(1660052700)
+        );
+        assert_eq!(
+            "2022-08-09".parse::<Timestamp>().unwrap(),
+            Timestamp(1660003200)
+        );
+    }
+
+    #[test]
+    fn reject_invalid() {
+        for bad in [
+            "",
+            "2022-02-30",
+            "2022-13-01",
+            "22-08-09",
+            "2022-08-09T25:00:00Z",
+            "2022-08-09T13:45:00+02:00",
+            "yesterday",
+        ] {
+            assert!(bad.parse::<Timestamp>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let ts = Timestamp(1660052700);
+        assert_eq!(ts.to_string(), "2022-08-09T13:45:00Z");
+        assert_eq!(ts.to_string().parse::<Timestamp>().unwrap(), ts);
+        assert_eq!(Timestamp(951782400).to_string(), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn deserialize_number_or_string() {
+        assert_eq!(
+            serde_json::from_str::<Timestamp>("1660052700").unwrap(),
+            Timestamp(1660052700)
+        );
+        assert_eq!(
+            serde_json::from_str::<Timestamp>("\"2022-08-09T13:45:00Z\"").unwrap(),
+            Timestamp(1660052700)
+        );
+        assert_eq!(serde_json::to_string(&Timestamp(5)).unwrap(), "5");
+    }
+}